@@ -10,6 +10,15 @@ fn panic_handler(_info: &core::panic::PanicInfo) -> ! {
     loop {}
 }
 
+/* Tell the loader which Bootinfo this kernel expects and what it needs
+ * at entry, see `bootinfo::abi` */
+bootinfo::sovos_notes! {
+    /* Nothing beyond Bootinfo itself yet */
+    required: bootinfo::KernelFeatures::new(),
+    kernel_base: bootinfo::KERNEL_BASE,
+    requirements: bootinfo::KernelRequirements::DEFAULT,
+}
+
 static STR: &[u8] = b"ayyyyyyyyyyyy";
 static mut MUT: [u8; 1 << 18] = [b'a'; 1 << 18];
 static mut ZEROED: [u8; 1 << 18] = [0u8; 1 << 18];
@@ -0,0 +1,278 @@
+//! Kernel ABI negotiation. A kernel declares what it expects from the
+//! loader in an ELF note owned by `SOVOS`, the loader compares that with
+//! what it can do and either enables the requested subsystems or refuses
+//! to boot with a list of what's missing. A kernel without the note gets
+//! the legacy contract, that is everything the loader did before notes.
+//! Kernels written in Rust emit their notes with `sovos_notes!`.
+
+use crate::{KernelRequirements, DEFAULT_BUF_SIZE, KERNEL_BASE};
+use crate::{REQUIREMENTS_DESC_SIZE, REQUIREMENTS_NOTE_TYPE};
+use impl_bits::bytes::Reader;
+use impl_bits::impl_bits;
+
+/// Bumped on every incompatible change of `Bootinfo` or the handoff
+pub const BOOTINFO_VERSION: u32 = 1;
+/// Owner of the note, the kernel writes it with a terminating null
+pub const ABI_NOTE_NAME: &str = "SOVOS";
+pub const ABI_NOTE_TYPE: u32 = 1;
+/// `bootinfo_version`, `required` and `kernel_base`, little-endian
+pub const ABI_NOTE_DESC_SIZE: usize = 16;
+/// Followed by `buf_size`, notes from before it have the shorter descriptor
+pub const ABI_NOTE_BUF_DESC_SIZE: usize = 20;
+/// Size of `Bootinfo::buf` kernels with the shorter descriptor were built with
+pub const LEGACY_BUF_SIZE: u32 = 8192;
+
+#[derive(PartialEq, Eq)]
+#[repr(transparent)]
+pub struct KernelFeatures(u32);
+
+impl_bits! {
+    KernelFeatures = {
+        direct_map = 0,
+        symbols = 1,
+        framebuffer = 2,
+        percpu = 3,
+    }
+}
+
+impl KernelFeatures {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_u32(x: u32) -> Self {
+        Self(x)
+    }
+
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    /// Features in `self` that `other` lacks
+    pub const fn missing_from(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// What this loader can provide
+    pub const fn supported() -> Self {
+        Self::new().set_percpu()
+    }
+
+    /// What kernels without the note always got
+    pub const fn legacy() -> Self {
+        Self::new().set_percpu()
+    }
+}
+
+/// Contents of the `SOVOS` note
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AbiNote {
+    pub bootinfo_version: u32,
+    pub required: KernelFeatures,
+    /// Where the kernel would like to be mapped, 0 for no preference
+    pub kernel_base: u64,
+    /// Size of `Bootinfo::buf` the kernel was built with
+    pub buf_size: u32,
+}
+
+impl AbiNote {
+    /// Note of a kernel built against this crate
+    pub const fn new(required: KernelFeatures, kernel_base: u64) -> Self {
+        Self {
+            bootinfo_version: BOOTINFO_VERSION,
+            required,
+            kernel_base,
+            buf_size: DEFAULT_BUF_SIZE as u32,
+        }
+    }
+
+    /// Parses the note's descriptor. Longer descriptors are accepted,
+    /// future fields are ignored.
+    pub fn from_desc(desc: &[u8]) -> Result<Self, AbiError> {
+        let desc = Reader::new(desc);
+        let malformed = |_| AbiError::Malformed;
+        return Ok(Self {
+            bootinfo_version: desc.u32_le_at(0).map_err(malformed)?,
+            required: KernelFeatures(desc.u32_le_at(4).map_err(malformed)?),
+            kernel_base: desc.u64_le_at(8).map_err(malformed)?,
+            /* Not there before `ABI_NOTE_BUF_DESC_SIZE` */
+            buf_size: desc.u32_le_at(16).unwrap_or(LEGACY_BUF_SIZE),
+        });
+    }
+
+    pub const fn to_desc(&self) -> [u8; ABI_NOTE_BUF_DESC_SIZE] {
+        let mut desc = [0u8; ABI_NOTE_BUF_DESC_SIZE];
+        let version = self.bootinfo_version.to_le_bytes();
+        let required = self.required.0.to_le_bytes();
+        let base = self.kernel_base.to_le_bytes();
+        let buf_size = self.buf_size.to_le_bytes();
+        let mut i = 0;
+        while i < 4 {
+            desc[i] = version[i];
+            desc[4 + i] = required[i];
+            desc[16 + i] = buf_size[i];
+            i += 1;
+        }
+        let mut i = 0;
+        while i < 8 {
+            desc[8 + i] = base[i];
+            i += 1;
+        }
+        return desc;
+    }
+}
+
+/// ELF note owned by `SOVOS` as it's laid out in a note segment, `DESC`
+/// must be a multiple of 4
+#[repr(C, align(4))]
+pub struct ElfNote<const DESC: usize> {
+    pub namesz: u32,
+    pub descsz: u32,
+    pub n_type: u32,
+    /// `ABI_NOTE_NAME`, null terminated and padded
+    pub name: [u8; 8],
+    pub desc: [u8; DESC],
+}
+
+impl<const DESC: usize> ElfNote<DESC> {
+    pub const fn sovos(n_type: u32, desc: [u8; DESC]) -> Self {
+        let src = ABI_NOTE_NAME.as_bytes();
+        let mut name = [0u8; 8];
+        let mut i = 0;
+        while i < src.len() {
+            name[i] = src[i];
+            i += 1;
+        }
+        Self {
+            namesz: ABI_NOTE_NAME.len() as u32 + 1,
+            descsz: DESC as u32,
+            n_type,
+            name,
+            desc,
+        }
+    }
+}
+
+/// The ABI note of a kernel that requires `required` and would like to
+/// be mapped at `kernel_base`
+pub const fn abi_note(
+    required: KernelFeatures,
+    kernel_base: u64,
+) -> ElfNote<ABI_NOTE_BUF_DESC_SIZE> {
+    ElfNote::sovos(ABI_NOTE_TYPE, AbiNote::new(required, kernel_base).to_desc())
+}
+
+pub const fn requirements_note(
+    requirements: KernelRequirements,
+) -> ElfNote<REQUIREMENTS_DESC_SIZE> {
+    ElfNote::sovos(REQUIREMENTS_NOTE_TYPE, requirements.to_desc())
+}
+
+/// Emits both `SOVOS` notes into `.note.sovos`, for the kernel's crate root
+///
+/// ```ignore
+/// bootinfo::sovos_notes! {
+///     required: KernelFeatures::new(),
+///     kernel_base: KERNEL_BASE,
+///     requirements: KernelRequirements::DEFAULT,
+/// }
+/// ```
+#[macro_export]
+macro_rules! sovos_notes {
+    (
+        required: $required:expr,
+        kernel_base: $kernel_base:expr,
+        requirements: $requirements:expr $(,)?
+    ) => {
+        #[used]
+        #[link_section = ".note.sovos"]
+        static SOVOS_ABI_NOTE: $crate::ElfNote<{ $crate::ABI_NOTE_BUF_DESC_SIZE }> =
+            $crate::abi_note($required, $kernel_base);
+
+        #[used]
+        #[link_section = ".note.sovos"]
+        static SOVOS_REQUIREMENTS_NOTE: $crate::ElfNote<{ $crate::REQUIREMENTS_DESC_SIZE }> =
+            $crate::requirements_note($requirements);
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbiError {
+    /// Descriptor is shorter than `ABI_NOTE_DESC_SIZE`
+    Malformed,
+    VersionMismatch {
+        kernel: u32,
+        loader: u32,
+    },
+    /// Required features this loader can't provide
+    MissingFeatures(KernelFeatures),
+    /// `Bootinfo::buf` sizes differ, everything after it would be misread
+    BufSizeMismatch {
+        kernel: u32,
+        loader: u32,
+    },
+}
+
+/// What the loader will provide to the kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AbiContract {
+    /// `BOOTINFO_VERSION` if the kernel has the note, 0 for legacy
+    pub bootinfo_version: u32,
+    pub features: KernelFeatures,
+    pub kernel_base: u64,
+    /// Kernel asked for a base other than `kernel_base`
+    pub base_ignored: bool,
+}
+
+impl AbiContract {
+    pub const fn legacy() -> Self {
+        Self {
+            bootinfo_version: 0,
+            features: KernelFeatures::legacy(),
+            kernel_base: KERNEL_BASE,
+            base_ignored: false,
+        }
+    }
+}
+
+/// Compares the kernel's note, `None` if it has none, with features the
+/// loader `supports` and the size of its `Bootinfo::buf`. The kernel base
+/// is only a preference, this loader always maps at `KERNEL_BASE`.
+pub fn negotiate(
+    note: Option<&AbiNote>,
+    supports: KernelFeatures,
+    buf_size: usize,
+) -> Result<AbiContract, AbiError> {
+    let note = match note {
+        Some(x) => x,
+        None => return Ok(AbiContract::legacy()),
+    };
+
+    if note.bootinfo_version != BOOTINFO_VERSION {
+        return Err(AbiError::VersionMismatch {
+            kernel: note.bootinfo_version,
+            loader: BOOTINFO_VERSION,
+        });
+    }
+    if note.buf_size as usize != buf_size {
+        return Err(AbiError::BufSizeMismatch {
+            kernel: note.buf_size,
+            loader: buf_size as u32,
+        });
+    }
+    let missing = note.required.missing_from(supports);
+    if !missing.is_empty() {
+        return Err(AbiError::MissingFeatures(missing));
+    }
+
+    return Ok(AbiContract {
+        bootinfo_version: BOOTINFO_VERSION,
+        features: note.required,
+        kernel_base: KERNEL_BASE,
+        base_ignored: note.kernel_base != 0 && note.kernel_base != KERNEL_BASE,
+    });
+}
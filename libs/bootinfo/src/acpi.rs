@@ -0,0 +1,135 @@
+//! What the loader takes from ACPI for the kernel. Every table is optional,
+//! firmware like coreboot may have none or corrupt ones: without a MADT
+//! only the BSP is known and there's no SMP bring-up, without an HPET
+//! timing is TSC only and without a FADT reset register the kernel resets
+//! through UEFI. What was found is recorded in the capabilities.
+//!
+//! The FPDT is only read for the report: firmware's own timestamps go in
+//! front of the boot timeline, so seconds spent before the loader ran
+//! show up as such.
+
+use crate::{BootCapabilities, Bootinfo, TimelineEvent};
+use arrayvec::ArrayVec;
+use cpu::acpi::{self, AcpiContext, FirmwareBootPerformance, ResetRegister};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct AcpiInfo {
+    /// CPUs the MADT lists, 0 without a usable one
+    pub cpus: u32,
+    /// Physical address of the HPET registers, 0 without a usable HPET
+    pub hpet: u64,
+    pub reset: Option<ResetRegister>,
+    /// Tables left out as corrupt
+    pub skipped: u32,
+    /// Physical address of the FBPT, 0 without a FPDT. Its exit boot
+    /// services timestamps are only filled in after the loader read it.
+    pub fbpt: u64,
+    /// As read from the FBPT, nanoseconds since reset
+    pub firmware_boot: Option<FirmwareBootPerformance>,
+}
+
+impl AcpiInfo {
+    pub const fn new() -> Self {
+        Self {
+            cpus: 0,
+            hpet: 0,
+            reset: None,
+            skipped: 0,
+            fbpt: 0,
+            firmware_boot: None,
+        }
+    }
+
+    /// Asks every consumer, a missing or corrupt table leaves its field empty
+    pub fn from_context(acpi: &AcpiContext) -> Self {
+        Self {
+            cpus: acpi::madt_cpu_count(acpi).unwrap_or(0) as u32,
+            hpet: acpi::hpet_address(acpi).unwrap_or(0),
+            reset: acpi::reset_register(acpi),
+            skipped: (acpi.skipped().len() + acpi.dropped()) as u32,
+            fbpt: acpi::fbpt_address(acpi).unwrap_or(0),
+            firmware_boot: acpi::firmware_boot_performance(acpi),
+        }
+    }
+
+    /// CPUs to reserve per-CPU areas for, `None` without a MADT
+    pub fn madt_cpus(&self) -> Option<usize> {
+        Some(self.cpus as usize).filter(|&x| x != 0)
+    }
+}
+
+/// Firmware's timestamps up to the loader's StartImage as timeline events.
+/// FPDT times are nanoseconds since reset, they are counted back from
+/// `entry`, the TSC when the loader started, so a TSC that didn't start
+/// at reset doesn't matter. Nothing without a StartImage timestamp, and
+/// timestamps that are missing or later than it are left out.
+pub fn firmware_events(
+    perf: &FirmwareBootPerformance,
+    ticks_per_us: u64,
+    entry: u64,
+) -> ArrayVec<TimelineEvent, 3> {
+    let start = perf.loader_start_image;
+    let mut events = ArrayVec::new();
+    if start == 0 {
+        return events;
+    }
+
+    /* Reset end may really be at 0, load image not */
+    let times = [
+        ("firmware reset end", perf.reset_end, true),
+        ("firmware load image", perf.loader_load_image, false),
+        ("firmware start image", start, false),
+    ];
+    for &(name, ns, zero_ok) in times.iter() {
+        if ns > start || (ns == 0 && !zero_ok) {
+            continue;
+        }
+        let ticks = (start - ns) as u128 * ticks_per_us as u128 / 1000;
+        let tsc = entry.saturating_sub(core::cmp::min(ticks, u64::MAX as u128) as u64);
+        events.push(TimelineEvent::new(name, tsc));
+    }
+    return events;
+}
+
+impl Default for AcpiInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Fills `acpi`, `None` if there were no usable tables at all, and
+    /// records which features the kernel can use
+    pub fn read_acpi(&mut self, acpi: Option<&AcpiContext>) {
+        self.acpi = acpi.map_or(AcpiInfo::new(), AcpiInfo::from_context);
+        if acpi.is_some() {
+            self.record(BootCapabilities::set_acpi);
+        }
+        if self.acpi.cpus != 0 {
+            self.record(BootCapabilities::set_madt);
+        }
+        if self.acpi.hpet != 0 {
+            self.record(BootCapabilities::set_hpet);
+        }
+        if self.acpi.reset.is_some() {
+            self.record(BootCapabilities::set_acpi_reset);
+        }
+        if self.acpi.firmware_boot.is_some() {
+            self.record(BootCapabilities::set_fpdt);
+        }
+    }
+
+    /// Puts the `firmware_events` of `acpi.firmware_boot` in front of the
+    /// timeline, `entry` as there. Events that don't fit are dropped.
+    pub fn prepend_firmware_boot(&mut self, ticks_per_us: u64, entry: u64) {
+        let perf = match &self.acpi.firmware_boot {
+            Some(x) => x,
+            None => return,
+        };
+        let events = firmware_events(perf, ticks_per_us, entry);
+        for (i, event) in events.into_iter().enumerate() {
+            let _ = self.timeline.try_insert(i, event);
+        }
+    }
+}
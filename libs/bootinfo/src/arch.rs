@@ -0,0 +1,151 @@
+//! Architecture specific pieces of `Bootinfo`: the console it mirrors the
+//! boot log to, the type of the root page table and how it is walked and
+//! loaded. The layout of `Bootinfo` is per-architecture, a kernel is only
+//! ever handed one built for its own target.
+//!
+//! x86_64 is the only real implementation. aarch64 has stubs, so code
+//! above this module can be kept architecture neutral until a PL011 driver
+//! and translation tables exist. It has no `activate` though, switching to
+//! tables that map nothing fails to build instead of at boot.
+
+use cpu::{PhysAddr, VirtAddr};
+
+/// Byte-oriented console, written to by polling
+pub trait ConsoleDevice {
+    /// Initialized and usable, `send` is a no-op otherwise
+    fn is_ready(&self) -> bool;
+    fn send(&mut self, byte: u8);
+    /// A received byte, if there is one
+    fn try_recv(&mut self) -> Option<u8>;
+
+    fn send_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.send(byte);
+        }
+    }
+}
+
+/// Root page table and the entry points `Bootinfo` needs on it
+pub trait ArchPaging {
+    type Root;
+    /// Root table that maps nothing
+    const EMPTY_ROOT: Self::Root;
+    const PAGE_SIZE: u64;
+
+    /// Physical address `virt` is mapped to, `None` if it isn't
+    ///
+    /// # Safety
+    /// Every table reachable from `root` must be identity-mapped.
+    unsafe fn translate(root: &Self::Root, virt: VirtAddr) -> Option<PhysAddr>;
+
+    /// Switches to the tables rooted at physical address `root`
+    ///
+    /// # Safety
+    /// The tables must map the currently running code and stack.
+    #[cfg(all(feature = "ringzero", target_arch = "x86_64"))]
+    unsafe fn activate(root: PhysAddr<Self::Root>);
+}
+
+/// Root page table of the current architecture
+pub type RootTable = <Paging as ArchPaging>::Root;
+
+#[cfg(target_arch = "x86_64")]
+pub use self::x86_64::*;
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64 {
+    use super::{ArchPaging, ConsoleDevice};
+    use crate::Uart;
+    use cpu::paging::{self, PML4Entry, Table};
+    use cpu::{PhysAddr, VirtAddr};
+
+    /// 4-level paging, the existing code in `cpu::paging`
+    pub struct X86Paging;
+    pub type Paging = X86Paging;
+    pub type Console = Uart;
+
+    impl ArchPaging for X86Paging {
+        type Root = Table<PML4Entry>;
+        const EMPTY_ROOT: Self::Root = Table::new();
+        const PAGE_SIZE: u64 = paging::PAGE_SIZE;
+
+        unsafe fn translate(root: &Self::Root, virt: VirtAddr) -> Option<PhysAddr> {
+            paging::translate(root, virt)
+        }
+
+        #[cfg(feature = "ringzero")]
+        unsafe fn activate(root: PhysAddr<Self::Root>) {
+            cpu::Cr3::set(cpu::Cr3::from_addr(root.cast()));
+        }
+    }
+
+    impl ConsoleDevice for Uart {
+        fn is_ready(&self) -> bool {
+            Uart::is_ready(self)
+        }
+
+        fn send(&mut self, byte: u8) {
+            Uart::send(self, byte)
+        }
+
+        fn try_recv(&mut self) -> Option<u8> {
+            Uart::try_recv(self)
+        }
+    }
+
+    /// Timestamp for the boot timeline, in TSC ticks
+    pub fn timestamp() -> u64 {
+        cpu::rdtsc()
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use self::aarch64::*;
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use super::{ArchPaging, ConsoleDevice};
+    use cpu::{PhysAddr, VirtAddr};
+
+    /// Level 0 translation table with a 4K granule
+    #[repr(C, align(4096))]
+    pub struct TranslationTable(pub [u64; 512]);
+
+    /// Stub, nothing is ever mapped
+    pub struct Aarch64Paging;
+    pub type Paging = Aarch64Paging;
+    pub type Console = Pl011;
+
+    impl ArchPaging for Aarch64Paging {
+        type Root = TranslationTable;
+        const EMPTY_ROOT: Self::Root = TranslationTable([0; 512]);
+        const PAGE_SIZE: u64 = 4096;
+
+        unsafe fn translate(_root: &Self::Root, _virt: VirtAddr) -> Option<PhysAddr> {
+            None
+        }
+    }
+
+    /// Stub of the Raspberry Pi UART, never ready
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Pl011 {
+        pub base: u64,
+    }
+
+    impl ConsoleDevice for Pl011 {
+        fn is_ready(&self) -> bool {
+            false
+        }
+
+        fn send(&mut self, _byte: u8) {}
+
+        fn try_recv(&mut self) -> Option<u8> {
+            None
+        }
+    }
+
+    /// No counter is read yet, every event is at 0
+    pub fn timestamp() -> u64 {
+        0
+    }
+}
@@ -0,0 +1,241 @@
+use arrayvec::ArrayVec;
+use cpu::VirtRange;
+
+/// What a region of the arena is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocPurpose {
+    Idt,
+    Gdt,
+    Cmdline,
+    Symbols,
+    RingBuffer,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArenaError {
+    OutOfMemory,
+    /// Handoff to the kernel has begun, the arena can't change anymore
+    Frozen,
+    /// Reset would reclaim a pinned region
+    Pinned,
+    TooManyReservations,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reservation {
+    pub purpose: AllocPurpose,
+    /// Offset from the start of the arena
+    pub offset: usize,
+    pub size: usize,
+    /// Pinned regions are never reclaimed, e.g. the IDT after `lidt`
+    pub pinned: bool,
+}
+
+/// Room planned for one consumer of `Bootinfo::buf`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufBudget {
+    pub purpose: AllocPurpose,
+    pub size: usize,
+    pub align: usize,
+}
+
+impl BufBudget {
+    pub const fn new(purpose: AllocPurpose, size: usize, align: usize) -> Self {
+        Self {
+            purpose,
+            size,
+            align,
+        }
+    }
+
+    /// Size including the worst-case padding in front of it
+    pub const fn worst_case(&self) -> usize {
+        self.size + self.align - 1
+    }
+}
+
+/// Everything planned to stay pinned in `Bootinfo::buf` for the life of
+/// the kernel. A new consumer gets an entry here first, `Bootinfo::with_buf`
+/// doesn't compile for buffers that can't hold all of them.
+pub const BUF_BUDGET: [BufBudget; 4] = [
+    /* 256 gates of 16 bytes */
+    BufBudget::new(AllocPurpose::Idt, 4096, 16),
+    /* Null, code and data, with room for a TSS descriptor */
+    BufBudget::new(AllocPurpose::Gdt, 64, 8),
+    BufBudget::new(AllocPurpose::Cmdline, 1024, 1),
+    BufBudget::new(AllocPurpose::RingBuffer, 4096, 8),
+];
+
+/// Bytes all of `budget` takes in the worst case
+pub const fn budget_size(budget: &[BufBudget]) -> usize {
+    let mut total = 0;
+    let mut i = 0;
+    while i < budget.len() {
+        total += budget[i].worst_case();
+        i += 1;
+    }
+    return total;
+}
+
+/// Size planned for `purpose` in `BUF_BUDGET`, 0 if it has no entry
+pub const fn budgeted(purpose: AllocPurpose) -> usize {
+    let mut i = 0;
+    while i < BUF_BUDGET.len() {
+        if BUF_BUDGET[i].purpose as u8 == purpose as u8 {
+            return BUF_BUDGET[i].size;
+        }
+        i += 1;
+    }
+    return 0;
+}
+
+/// Bump allocator carving regions out of a static buffer,
+/// usually `Bootinfo::buf`
+pub struct BootArena {
+    base: *mut u8,
+    size: usize,
+    next: usize,
+    frozen: bool,
+    reservations: ArrayVec<Reservation, 16>,
+}
+
+impl BootArena {
+    pub fn new(buf: &'static mut [u8]) -> Self {
+        unsafe { Self::from_raw(buf.as_mut_ptr(), buf.len()) }
+    }
+
+    /// # Safety
+    /// * `base..base+size` must be valid for writes for the rest of the
+    /// program and not accessed other than through this arena.
+    pub unsafe fn from_raw(base: *mut u8, size: usize) -> Self {
+        Self {
+            base,
+            size,
+            next: 0,
+            frozen: false,
+            reservations: ArrayVec::new_const(),
+        }
+    }
+
+    fn carve(
+        &mut self,
+        purpose: AllocPurpose,
+        size: usize,
+        align: usize,
+        pinned: bool,
+    ) -> Result<*mut u8, ArenaError> {
+        if self.frozen {
+            return Err(ArenaError::Frozen);
+        }
+        if self.reservations.is_full() {
+            return Err(ArenaError::TooManyReservations);
+        }
+
+        let addr = self.base as usize + self.next;
+        let padding = addr.wrapping_neg() % align;
+        let offset = self.next + padding;
+        let end = match offset.checked_add(size) {
+            Some(end) if end <= self.size => end,
+            _ => return Err(ArenaError::OutOfMemory),
+        };
+
+        self.reservations.push(Reservation {
+            purpose,
+            offset,
+            size,
+            pinned,
+        });
+        self.next = end;
+
+        return Ok(unsafe { self.base.add(offset) });
+    }
+
+    /// Allocates a region that is given back on `reset`
+    pub fn alloc_bytes(
+        &mut self,
+        purpose: AllocPurpose,
+        size: usize,
+        align: usize,
+    ) -> Result<&'static mut [u8], ArenaError> {
+        let ptr = self.carve(purpose, size, align, false)?;
+        unsafe {
+            ptr.write_bytes(0, size);
+            return Ok(core::slice::from_raw_parts_mut(ptr, size));
+        }
+    }
+
+    /// Moves `value` into a region that is never reclaimed,
+    /// for structures the CPU keeps pointing to, like the IDT and GDT
+    pub fn reserve_pinned<T>(
+        &mut self,
+        purpose: AllocPurpose,
+        value: T,
+    ) -> Result<&'static mut T, ArenaError> {
+        let size = core::mem::size_of::<T>();
+        let align = core::mem::align_of::<T>();
+        let ptr = self.carve(purpose, size, align, true)? as *mut T;
+        unsafe {
+            ptr.write(value);
+            return Ok(&mut *ptr);
+        }
+    }
+
+    /// Gives back the whole arena. Fails if anything is pinned,
+    /// as new allocations would overlap it.
+    ///
+    /// # Safety
+    /// * Regions returned by `alloc_bytes` must not be used anymore.
+    pub unsafe fn reset(&mut self) -> Result<(), ArenaError> {
+        if self.frozen {
+            return Err(ArenaError::Frozen);
+        }
+        if self.reservations.iter().any(|r| r.pinned) {
+            return Err(ArenaError::Pinned);
+        }
+
+        self.reservations.clear();
+        self.next = 0;
+        return Ok(());
+    }
+
+    /// Makes all further allocations and resets fail,
+    /// called once the handoff to the kernel begins
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Checks whether `target` lies entirely within a pinned reservation
+    pub fn is_pinned<T: ?Sized>(&self, target: &T) -> bool {
+        let start = target as *const T as *const u8 as u64;
+        let target = match VirtRange::new(start, core::mem::size_of_val(target) as u64) {
+            Some(x) => x,
+            None => return false,
+        };
+
+        return self
+            .reservations
+            .iter()
+            .filter(|r| r.pinned)
+            .any(|r| self.range_of(r).contains_range(&target));
+    }
+
+    /// Addresses covered by `reservation`
+    pub fn range_of(&self, reservation: &Reservation) -> VirtRange {
+        let start = self.base as u64 + reservation.offset as u64;
+        VirtRange::new(start, reservation.size as u64).expect("reservation past the address space")
+    }
+
+    pub fn reservations(&self) -> &[Reservation] {
+        &self.reservations
+    }
+
+    /// Bytes left, not counting padding needed for alignment
+    pub fn remaining(&self) -> usize {
+        self.size - self.next
+    }
+}
@@ -0,0 +1,10 @@
+//! Prints the C header for `Bootinfo`, see `bootinfo::cheader`
+
+fn main() {
+    let mut header = String::new();
+    if let Err(e) = bootinfo::cheader::write_c_header(&mut header) {
+        eprintln!("Can't generate bootinfo.h: {:?}", e);
+        std::process::exit(1);
+    }
+    print!("{}", header);
+}
@@ -0,0 +1,210 @@
+//! Typestate builder for the handoff. A `Bootinfo` without its memory map,
+//! kernel or console isn't an error anywhere, the kernel just gets a
+//! subtly broken handoff. `BootinfoBuilder::finish` only exists once all
+//! of them were set, so forgetting one doesn't compile:
+//!
+//! ```compile_fail
+//! use bootinfo::{BootinfoBuilder, Missing, SerialSinks};
+//! use cpu::PhysSlice;
+//!
+//! fn no_memory_map(builder: BootinfoBuilder<Missing, Missing, Missing>) {
+//!     let _ = builder
+//!         .kernel(PhysSlice::null())
+//!         .console(SerialSinks::new())
+//!         .finish();
+//! }
+//! ```
+//!
+//! Optional parts are set through the `PinnedBootinfo` the builder derefs
+//! to, like before.
+
+use crate::sha256::Sha256;
+use crate::{
+    BootArena, BootCapabilities, Bootinfo, InterruptFlag, InterruptSources, PinnedBootinfo,
+    SerialSinks, DEFAULT_BUF_SIZE,
+};
+use core::ops::{Deref, DerefMut};
+use cpu::PhysSlice;
+use uefi::memory::Descriptor;
+
+/// A required part that wasn't set yet
+pub struct Missing;
+
+/// The memory map is final and boot services are gone, which frees `buf`
+/// for the arena
+pub struct WithMem {
+    arena: BootArena,
+}
+
+pub struct WithKernel;
+
+pub struct WithConsole;
+
+pub struct BootinfoBuilder<M, K, C, const BUF: usize = DEFAULT_BUF_SIZE> {
+    pinned: PinnedBootinfo<BUF>,
+    mem: M,
+    kernel: K,
+    console: C,
+}
+
+impl<const BUF: usize> BootinfoBuilder<Missing, Missing, Missing, BUF> {
+    /// Pins `bootinfo` where it is, which also records `this`
+    ///
+    /// # Safety
+    /// See `PinnedBootinfo::new`.
+    pub unsafe fn new(bootinfo: &'static mut Bootinfo<BUF>) -> Self {
+        Self {
+            pinned: PinnedBootinfo::new(bootinfo),
+            mem: Missing,
+            kernel: Missing,
+            console: Missing,
+        }
+    }
+}
+
+impl<K, C, const BUF: usize> BootinfoBuilder<Missing, K, C, BUF> {
+    /// Uses `map` as the final memory map, descriptors that don't fit are
+    /// dropped. For memory maps that didn't come from `exit_boot_services`.
+    pub fn memory_map(
+        mut self,
+        map: impl IntoIterator<Item = Descriptor>,
+    ) -> BootinfoBuilder<WithMem, K, C, BUF> {
+        /* SAFETY: plain fields */
+        let bootinfo = unsafe { self.pinned.get_mut() };
+        bootinfo.uefi_meminfo.clear();
+        for descriptor in map {
+            if bootinfo.uefi_meminfo.try_push(descriptor).is_err() {
+                bootinfo.record(BootCapabilities::set_memory_map_truncated);
+                break;
+            }
+        }
+        bootinfo.summarize_memory();
+        return self.with_mem();
+    }
+
+    /// Takes the final memory map and exits boot services, see
+    /// `Bootinfo::retrieve_and_exit`
+    ///
+    /// # Safety
+    /// See `Bootinfo::retrieve_and_exit`.
+    pub unsafe fn exit_boot_services(
+        mut self,
+        st: &uefi::SystemTable,
+        image: &uefi::ImageHandle,
+        clock: &impl uefi::Clock,
+        memmap: &mut uefi::MemoryMapBuffer,
+        sources: &mut InterruptSources,
+        flag: &mut impl InterruptFlag,
+    ) -> Result<BootinfoBuilder<WithMem, K, C, BUF>, uefi::Error> {
+        self.pinned
+            .get_mut()
+            .retrieve_and_exit(st, image, clock, memmap, sources, flag)?;
+        return Ok(self.with_mem());
+    }
+
+    fn with_mem(mut self) -> BootinfoBuilder<WithMem, K, C, BUF> {
+        /* SAFETY: nothing used `buf` before */
+        let arena = unsafe { self.pinned.as_mut().arena() };
+        BootinfoBuilder {
+            pinned: self.pinned,
+            mem: WithMem { arena },
+            kernel: self.kernel,
+            console: self.console,
+        }
+    }
+}
+
+impl<M, C, const BUF: usize> BootinfoBuilder<M, Missing, C, BUF> {
+    /// Where the kernel's segments were loaded
+    pub fn kernel(mut self, pslice: PhysSlice<u8>) -> BootinfoBuilder<M, WithKernel, C, BUF> {
+        /* SAFETY: a plain field */
+        unsafe { self.pinned.get_mut() }.kernel_pslice = pslice;
+        BootinfoBuilder {
+            pinned: self.pinned,
+            mem: self.mem,
+            kernel: WithKernel,
+            console: self.console,
+        }
+    }
+}
+
+impl<M, K, const BUF: usize> BootinfoBuilder<M, K, Missing, BUF> {
+    /// Serial ports the kernel keeps logging to
+    pub fn console(mut self, sinks: SerialSinks) -> BootinfoBuilder<M, K, WithConsole, BUF> {
+        /* SAFETY: a plain field */
+        unsafe { self.pinned.get_mut() }.serial_sinks = sinks;
+        BootinfoBuilder {
+            pinned: self.pinned,
+            mem: self.mem,
+            kernel: self.kernel,
+            console: WithConsole,
+        }
+    }
+}
+
+impl<K, C, const BUF: usize> BootinfoBuilder<WithMem, K, C, BUF> {
+    /// Allocations for the kernel, e.g. the GDT and IDT
+    pub fn arena(&mut self) -> &mut BootArena {
+        &mut self.mem.arena
+    }
+}
+
+impl<const BUF: usize> BootinfoBuilder<WithMem, WithKernel, WithConsole, BUF> {
+    /// Freezes the arena, merges `reserved` and seals the required parts
+    /// with `checksum`
+    pub fn finish(mut self) -> PinnedBootinfo<BUF> {
+        self.mem.arena.freeze();
+        /* SAFETY: plain fields */
+        let bootinfo = unsafe { self.pinned.get_mut() };
+        bootinfo.merge_reserved();
+        bootinfo.checksum = bootinfo.compute_checksum();
+        return self.pinned;
+    }
+}
+
+impl<M, K, C, const BUF: usize> Deref for BootinfoBuilder<M, K, C, BUF> {
+    type Target = PinnedBootinfo<BUF>;
+    fn deref(&self) -> &PinnedBootinfo<BUF> {
+        &self.pinned
+    }
+}
+
+impl<M, K, C, const BUF: usize> DerefMut for BootinfoBuilder<M, K, C, BUF> {
+    fn deref_mut(&mut self) -> &mut PinnedBootinfo<BUF> {
+        &mut self.pinned
+    }
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// SHA-256 of what `BootinfoBuilder` requires: `this`, the kernel, the
+    /// memory map and the modules. The size of `buf` goes in first, a
+    /// kernel reading `Bootinfo` with another layout doesn't get a match.
+    pub fn compute_checksum(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(&(BUF as u64).to_le_bytes());
+        hasher.update(&self.this.as_u64().to_le_bytes());
+        hasher.update(&self.kernel_pslice.addr().as_u64().to_le_bytes());
+        hasher.update(&(self.kernel_pslice.len() as u64).to_le_bytes());
+
+        for x in &self.uefi_meminfo {
+            hasher.update(&x.typ.to_le_bytes());
+            hasher.update(&x.phys_start.to_le_bytes());
+            hasher.update(&x.virt_start.to_le_bytes());
+            hasher.update(&x.pages.to_le_bytes());
+            hasher.update(&x.attributes.bits().to_le_bytes());
+        }
+        for x in &self.modules {
+            hasher.update(&x.name);
+            hasher.update(&(x.kind as u32).to_le_bytes());
+            hasher.update(&x.data.addr().as_u64().to_le_bytes());
+            hasher.update(&(x.data.len() as u64).to_le_bytes());
+        }
+
+        return hasher.finish();
+    }
+
+    /// The required parts are as `BootinfoBuilder::finish` left them
+    pub fn checksum_ok(&self) -> bool {
+        self.checksum == self.compute_checksum()
+    }
+}
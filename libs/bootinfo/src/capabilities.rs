@@ -0,0 +1,89 @@
+//! One word summarizing which boot features were actually exercised, for
+//! tooling that only wants to know how a machine booted. A bit is set at
+//! the point its subsystem succeeded, so a clear bit means the feature
+//! wasn't used, whether it's missing or failed.
+//!
+//! Bits are never reassigned, new ones are only appended.
+
+use crate::Bootinfo;
+use impl_bits::impl_bits;
+
+#[derive(PartialEq, Eq)]
+#[repr(transparent)]
+pub struct BootCapabilities(u64);
+
+impl_bits! {
+    BootCapabilities = {
+        /// TSC frequency was measured against Stall, not guessed
+        tsc_calibrated = 0,
+        /// A microcode update was loaded on the BSP
+        microcode_applied = 1,
+        /// Descriptors didn't fit into `uefi_meminfo` and were dropped
+        memory_map_truncated = 2,
+        /// Usable memory above MAXPHYADDR was cut
+        physical_memory_clamped = 3,
+        /// A verifier accepted the kernel's signature
+        kernel_verified = 4,
+        /// SecureBoot was on and SetupMode off
+        secure_boot = 5,
+        /// The kernel has an ABI note, otherwise it got the legacy contract
+        abi_note = 6,
+        /// Per-CPU areas were reserved
+        percpu = 7,
+        /// The kernel is entered through the handoff trampoline
+        handoff_trampoline = 8,
+        /// At least one entropy source passed health checks
+        entropy_seeded = 9,
+        /// Firmware resources were read from the ESRT
+        esrt = 10,
+        /// The console is a legacy UART, not EFI Serial I/O
+        legacy_serial = 11,
+        /// Page tables were snapshotted and found unmodified at entry
+        tables_verified = 12,
+        avx = 13,
+        /// A kernel slot ran out of tries, the other one was booted
+        slot_fallback = 14,
+        /// Mappings don't use NX, the processor lacks it or `nx=off`
+        nx_unavailable = 15,
+        /// Booted by a previous kernel through `kexec_prepare`, not firmware
+        kexec = 16,
+        /// `strict=1`, tolerated conditions fail the boot
+        strict = 17,
+        /// The RSDP and XSDT or RSDT were valid
+        acpi = 18,
+        /// CPUs were counted from the MADT, otherwise there's no SMP bring-up
+        madt = 19,
+        /// An HPET is there, otherwise timing is TSC only
+        hpet = 20,
+        /// The FADT has a reset register, otherwise reset is UEFI only
+        acpi_reset = 21,
+        /// A 16550 passed the scratch and loopback tests, otherwise any
+        /// serial output is through EFI or nowhere
+        uart = 22,
+        /// Firmware's boot timestamps were read from the FPDT
+        fpdt = 23,
+        /// The crash-dump region was reserved
+        crash_dump = 24,
+    }
+}
+
+impl BootCapabilities {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_u64(x: u64) -> Self {
+        Self(x)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Sets the capabilities `f` adds
+    pub fn record(&mut self, f: impl FnOnce(BootCapabilities) -> BootCapabilities) {
+        self.capabilities = f(self.capabilities);
+    }
+}
@@ -0,0 +1,339 @@
+//! C header for kernels not written in Rust, generated from the Rust
+//! definitions by `cargo run --bin bootinfo-h`. Fields of `Bootinfo` are
+//! laid out with explicit padding at the offsets measured here, fields
+//! of Rust types are opaque bytes of the right size. Constants, enum
+//! values and capability bits come from `ALL` arrays and `impl_bits`.
+//!
+//! `bootinfo_fields` lists every field by hand, `check_layout` fails if
+//! the list doesn't cover `Bootinfo` exactly, so a field added without
+//! being listed breaks the generator instead of shifting the header.
+//! Only the default `buf` size is described.
+
+use crate::REQUIREMENTS_NOTE_TYPE;
+use crate::{BootCapabilities, BootStage, Bootinfo, Condition, ReservedKind};
+use crate::{ABI_NOTE_NAME, ABI_NOTE_TYPE, BOOTINFO_VERSION, DEFAULT_BUF_SIZE, KERNEL_BASE};
+use core::fmt::{self, Write};
+use core::mem::{align_of, size_of, MaybeUninit};
+use core::ptr::addr_of;
+
+/// How a field is declared in C
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CType {
+    /// `uint8_t name[size]`, for anything with a Rust layout
+    Bytes,
+    U8,
+    U32,
+    U64,
+    Ptr,
+}
+
+impl CType {
+    /// Size of the C type, `None` for `Bytes`
+    const fn size(self) -> Option<usize> {
+        match self {
+            Self::Bytes => None,
+            Self::U8 => Some(1),
+            Self::U32 => Some(4),
+            Self::U64 | Self::Ptr => Some(8),
+        }
+    }
+
+    /// Declaration up to the field name
+    const fn prefix(self) -> &'static str {
+        match self {
+            Self::Bytes | Self::U8 => "uint8_t ",
+            Self::U32 => "uint32_t ",
+            Self::U64 => "uint64_t ",
+            Self::Ptr => "void *",
+        }
+    }
+}
+
+/// Field of `Bootinfo` as measured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    pub align: usize,
+    pub ctype: CType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// `field` isn't where the fields before it end, one isn't listed
+    Gap {
+        field: &'static str,
+        offset: usize,
+        expected: usize,
+    },
+    /// The C type of `field` has a different size than the Rust one
+    CTypeSize(&'static str),
+    /// Listed fields don't add up to the size of `Bootinfo`
+    Size { size: usize, expected: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    Layout(LayoutError),
+    Fmt,
+}
+
+impl From<fmt::Error> for HeaderError {
+    fn from(_: fmt::Error) -> Self {
+        Self::Fmt
+    }
+}
+
+fn size_of_pointee<T>(_: *const T) -> usize {
+    size_of::<T>()
+}
+
+fn align_of_pointee<T>(_: *const T) -> usize {
+    align_of::<T>()
+}
+
+macro_rules! fields {
+    ($($field:ident: $ctype:ident,)*) => {
+        /// Fields of `Bootinfo`
+        pub const BOOTINFO_FIELD_COUNT: usize = [$(stringify!($field)),*].len();
+
+        /// Every field of `Bootinfo` in declaration order, `_pinned` last
+        pub fn bootinfo_fields() -> [CField; BOOTINFO_FIELD_COUNT] {
+            let uninit = MaybeUninit::<Bootinfo>::uninit();
+            let base = uninit.as_ptr();
+            [$({
+                /* SAFETY: no reference to the uninitialized memory is made */
+                let field = unsafe { addr_of!((*base).$field) };
+                CField {
+                    name: stringify!($field),
+                    offset: field as usize - base as usize,
+                    size: size_of_pointee(field),
+                    align: align_of_pointee(field),
+                    ctype: CType::$ctype,
+                }
+            },)*]
+        }
+    };
+}
+
+fields! {
+    paging_root: Bytes,
+    pdp: Bytes,
+    pd: Bytes,
+    page_table: Bytes,
+    this: U64,
+    kernel_pslice: Bytes,
+    buf: Bytes,
+    uefi_meminfo: Bytes,
+    physical_memory_clamped: U8,
+    memory: Bytes,
+    reserved: Bytes,
+    reserve_requests: Bytes,
+    modules: Bytes,
+    timeline: Bytes,
+    stages: Bytes,
+    capabilities: U64,
+    conditions: U32,
+    last_error: Bytes,
+    percpu: Bytes,
+    kernel_stack: Bytes,
+    acpi: Bytes,
+    abi: Bytes,
+    microcode: Bytes,
+    mitigations: Bytes,
+    page_flags: Bytes,
+    pre_exit: Bytes,
+    seed: Bytes,
+    entropy: Bytes,
+    lineage: Bytes,
+    esrt: Bytes,
+    esrt_dropped: U32,
+    dma_ceiling: U64,
+    dma_regions: Bytes,
+    crash_dump: Bytes,
+    uefi_systable: Ptr,
+    uefi_revision: Bytes,
+    serial: Bytes,
+    serial_sinks: Bytes,
+    checksum: Bytes,
+    _pinned: Bytes,
+}
+
+/// `align` is a power of two
+const fn align_up(x: usize, align: usize) -> usize {
+    (x + align - 1) & !(align - 1)
+}
+
+/// Each field has to start where the previous one ends, up to its own
+/// alignment, as in `repr(C)`. The zero sized `_pinned` marks the end.
+pub fn check_layout(fields: &[CField]) -> Result<(), LayoutError> {
+    let mut end = 0;
+    for field in fields {
+        let expected = align_up(end, field.align);
+        if field.offset != expected {
+            return Err(LayoutError::Gap {
+                field: field.name,
+                offset: field.offset,
+                expected,
+            });
+        }
+        if matches!(field.ctype.size(), Some(size) if size != field.size) {
+            return Err(LayoutError::CTypeSize(field.name));
+        }
+        end = field.offset + field.size;
+    }
+
+    let expected = align_up(end, align_of::<Bootinfo>());
+    if size_of::<Bootinfo>() != expected {
+        return Err(LayoutError::Size {
+            size: size_of::<Bootinfo>(),
+            expected,
+        });
+    }
+    return Ok(());
+}
+
+/// Writes `CamelCase` as `CAMEL_CASE`, and `snake_case` as `SNAKE_CASE`
+struct UpperSnake<'a, W: Write> {
+    out: &'a mut W,
+    first: bool,
+}
+
+impl<W: Write> Write for UpperSnake<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c.is_ascii_uppercase() && !self.first {
+                self.out.write_char('_')?;
+            }
+            self.out.write_char(c.to_ascii_uppercase())?;
+            self.first = false;
+        }
+        return Ok(());
+    }
+}
+
+fn write_enum<T: fmt::Debug + Copy>(
+    out: &mut impl Write,
+    name: &str,
+    prefix: &str,
+    all: &[T],
+    value: impl Fn(T) -> u8,
+) -> fmt::Result {
+    writeln!(out, "enum {} {{", name)?;
+    for &x in all {
+        write!(out, "    {}", prefix)?;
+        write!(
+            UpperSnake {
+                out: &mut *out,
+                first: true
+            },
+            "{:?}",
+            x
+        )?;
+        writeln!(out, " = {},", value(x))?;
+    }
+    writeln!(out, "}};\n")
+}
+
+/// Writes `bootinfo.h`, after `check_layout`
+pub fn write_c_header(out: &mut impl Write) -> Result<(), HeaderError> {
+    let fields = bootinfo_fields();
+    check_layout(&fields).map_err(HeaderError::Layout)?;
+
+    writeln!(
+        out,
+        "/* Generated by `cargo run --bin bootinfo-h` in libs/bootinfo, don't edit */"
+    )?;
+    writeln!(out, "#ifndef SOVOS_BOOTINFO_H")?;
+    writeln!(out, "#define SOVOS_BOOTINFO_H\n")?;
+    writeln!(out, "#include <stdint.h>\n")?;
+
+    writeln!(out, "#define SOVOS_BOOTINFO_VERSION {}u", BOOTINFO_VERSION)?;
+    writeln!(out, "#define SOVOS_BOOTINFO_BUF_SIZE {}u", DEFAULT_BUF_SIZE)?;
+    writeln!(
+        out,
+        "#define SOVOS_BOOTINFO_SIZE {:#x}u",
+        size_of::<Bootinfo>()
+    )?;
+    writeln!(
+        out,
+        "#define SOVOS_BOOTINFO_ALIGN {}u",
+        align_of::<Bootinfo>()
+    )?;
+    writeln!(out, "#define SOVOS_ABI_NOTE_NAME \"{}\"", ABI_NOTE_NAME)?;
+    writeln!(out, "#define SOVOS_ABI_NOTE_TYPE {}u", ABI_NOTE_TYPE)?;
+    writeln!(
+        out,
+        "#define SOVOS_REQUIREMENTS_NOTE_TYPE {}u",
+        REQUIREMENTS_NOTE_TYPE
+    )?;
+    writeln!(out, "#define SOVOS_KERNEL_BASE {:#x}ull\n", KERNEL_BASE)?;
+
+    writeln!(out, "/* Bits of `capabilities` */")?;
+    for &(name, bit) in BootCapabilities::FLAGS {
+        write!(out, "#define SOVOS_CAP_")?;
+        write!(
+            UpperSnake {
+                out: &mut *out,
+                first: true
+            },
+            "{}",
+            name
+        )?;
+        writeln!(out, " (1ull << {})", bit)?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "/* Bits of `conditions` */")?;
+    write_enum(
+        out,
+        "sovos_condition",
+        "SOVOS_CONDITION_",
+        &Condition::ALL,
+        |x| x as u8,
+    )?;
+    writeln!(out, "/* Stored as uint8_t */")?;
+    write_enum(
+        out,
+        "sovos_boot_stage",
+        "SOVOS_STAGE_",
+        &BootStage::ALL,
+        |x| x as u8,
+    )?;
+    write_enum(
+        out,
+        "sovos_reserved_kind",
+        "SOVOS_RESERVED_",
+        &ReservedKind::ALL,
+        |x| x as u8,
+    )?;
+
+    writeln!(out, "struct sovos_bootinfo {{")?;
+    let mut end = 0;
+    for (i, field) in fields.iter().enumerate() {
+        if field.offset > end {
+            writeln!(out, "    uint8_t _pad{}[{}];", i, field.offset - end)?;
+        }
+        end = field.offset + field.size;
+        let align = if i == 0 {
+            "_Alignas(SOVOS_BOOTINFO_ALIGN) "
+        } else {
+            ""
+        };
+        match field.ctype {
+            /* Zero sized, C has no such thing */
+            _ if field.size == 0 => {}
+            CType::Bytes => writeln!(out, "    {}uint8_t {}[{}];", align, field.name, field.size)?,
+            ctype => writeln!(out, "    {}{}{};", align, ctype.prefix(), field.name)?,
+        }
+    }
+    writeln!(out, "}};\n")?;
+
+    writeln!(
+        out,
+        "_Static_assert(sizeof(struct sovos_bootinfo) == SOVOS_BOOTINFO_SIZE, \"sovos_bootinfo\");\n"
+    )?;
+    writeln!(out, "#endif")?;
+    return Ok(());
+}
@@ -0,0 +1,83 @@
+/// Contents of `sovos.cfg`: `key=value` lines, `#` starts a comment.
+/// When a key is repeated, the last value wins.
+#[derive(Clone, Copy)]
+pub struct Config<'a> {
+    text: &'a [u8],
+}
+
+impl<'a> Config<'a> {
+    pub const fn new(text: &'a [u8]) -> Self {
+        Self { text }
+    }
+
+    pub const fn empty() -> Self {
+        Self::new(&[])
+    }
+
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        let mut value = None;
+
+        for line in self.text.split(|&c| c == b'\n') {
+            let line = match line.iter().position(|&c| c == b'#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            };
+            let line = match core::str::from_utf8(line) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let (k, v) = match line.find('=') {
+                Some(eq) => (&line[..eq], &line[eq + 1..]),
+                None => continue,
+            };
+            if k.trim() == key {
+                value = Some(v.trim());
+            }
+        }
+
+        return value;
+    }
+
+    /// `1`, `true`, `yes` and `on` enable a flag, anything else doesn't
+    pub fn flag(&self, key: &str) -> bool {
+        match self.get(key) {
+            Some("1") | Some("true") | Some("yes") | Some("on") => true,
+            _ => false,
+        }
+    }
+}
+
+/// Decimal, or hex with `0x` prefix. Underscores are ignored,
+/// so addresses can be copied from the logs.
+pub fn parse_u64(s: &str) -> Option<u64> {
+    let (digits, radix) = match s.strip_prefix("0x") {
+        Some(hex) => (hex, 16),
+        None => (s, 10),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+
+    let mut value: u64 = 0;
+    for c in digits.chars().filter(|&c| c != '_') {
+        let digit = c.to_digit(radix)? as u64;
+        value = value.checked_mul(radix as u64)?.checked_add(digit)?;
+    }
+    return Some(value);
+}
+
+/// A number of bytes like `parse_u64` reads it, with an optional `K`, `M`
+/// or `G` suffix
+pub fn parse_size(s: &str) -> Option<u64> {
+    let (digits, shift) = match s.as_bytes().last() {
+        Some(b'K') | Some(b'k') => (&s[..s.len() - 1], 10),
+        Some(b'M') | Some(b'm') => (&s[..s.len() - 1], 20),
+        Some(b'G') | Some(b'g') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let number = parse_u64(digits)?;
+    if number.leading_zeros() < shift {
+        return None;
+    }
+    return Some(number << shift);
+}
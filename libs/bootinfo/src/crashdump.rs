@@ -0,0 +1,289 @@
+//! Region at a fixed physical address the kernel writes a crash dump to,
+//! which survives a warm reset like the trace buffer does, so the next
+//! boot can save or report it. Configured in `sovos.cfg`:
+//!
+//! ```text
+//! crashdump=0x10000000
+//! crashdump_size=64M
+//! ```
+//!
+//! `crashdump` is page aligned, `crashdump_size` is a whole number of
+//! pages, `DEFAULT_CRASHDUMP_SIZE` without it. The region is reserved
+//! every boot, so the kernel never hands it out and `poison_low_memory`,
+//! which only fills conventional memory, never reaches it. The loader only
+//! ever writes the header: a dump too large to checksum at boot is trusted
+//! as long as the header is.
+//!
+//! The region starts with a `CrashDumpHeader`, the dump follows it.
+//! Everything is little-endian.
+//!
+//! | offset | field      |                                             |
+//! |--------|------------|---------------------------------------------|
+//! | 0      | `magic`    | `CRASHDUMP_MAGIC`, "SOVOSDMP"               |
+//! | 8      | `version`  | `CRASHDUMP_VERSION`                         |
+//! | 12     | `reserved` | 0                                           |
+//! | 16     | `capacity` | bytes after the header                      |
+//! | 24     | `length`   | bytes of dump after the header, 0 for none  |
+//! | 32     | `boot_id`  | `BootLineage::boot_id` of the writer        |
+//! | 48     | `checksum` | `CrashDumpHeader::checksum` of the above    |
+//!
+//! The loader writes a fresh header, with its own boot ID and no dump,
+//! unless there's a valid one with a dump in it. The kernel writes the
+//! dump, then the header with `CrashDumpHeader::with_dump`.
+
+use crate::trace::fnv1a;
+use crate::{parse_size, parse_u64, Config};
+use core::convert::TryInto;
+use core::mem::{align_of, size_of};
+use cpu::PhysRange;
+
+pub const CRASHDUMP_MAGIC: u64 = u64::from_le_bytes(*b"SOVOSDMP");
+pub const CRASHDUMP_VERSION: u32 = 1;
+pub const CRASHDUMP_HEADER_SIZE: usize = size_of::<CrashDumpHeader>();
+pub const DEFAULT_CRASHDUMP_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct CrashDumpHeader {
+    pub magic: u64,
+    pub version: u32,
+    pub reserved: u32,
+    pub capacity: u64,
+    pub length: u64,
+    pub boot_id: [u8; 16],
+    pub checksum: u64,
+}
+
+impl CrashDumpHeader {
+    /// All zero, what `Bootinfo::new` starts with
+    pub const fn empty() -> Self {
+        Self {
+            magic: 0,
+            version: 0,
+            reserved: 0,
+            capacity: 0,
+            length: 0,
+            boot_id: [0u8; 16],
+            checksum: 0,
+        }
+    }
+
+    /// Header of a region with room for `capacity` bytes of dump and none
+    /// in it yet
+    pub fn fresh(capacity: u64, boot_id: [u8; 16]) -> Self {
+        let header = Self {
+            magic: CRASHDUMP_MAGIC,
+            version: CRASHDUMP_VERSION,
+            reserved: 0,
+            capacity,
+            length: 0,
+            boot_id,
+            checksum: 0,
+        };
+        return header.with_checksum();
+    }
+
+    /// This header once `length` bytes of dump were written by `boot_id`,
+    /// the kernel writes it after the dump itself
+    pub fn with_dump(self, length: u64, boot_id: [u8; 16]) -> Self {
+        let header = Self {
+            length: core::cmp::min(length, self.capacity),
+            boot_id,
+            ..self
+        };
+        return header.with_checksum();
+    }
+
+    fn with_checksum(self) -> Self {
+        Self {
+            checksum: self.checksum(),
+            ..self
+        }
+    }
+
+    /// FNV-1a over every field before `checksum`, like
+    /// `TraceHeader::checksum`
+    pub fn checksum(&self) -> u64 {
+        let words = [
+            self.magic,
+            self.version as u64 | (self.reserved as u64) << 32,
+            self.capacity,
+            self.length,
+            u64::from_le_bytes(self.boot_id[..8].try_into().unwrap()),
+            u64::from_le_bytes(self.boot_id[8..].try_into().unwrap()),
+        ];
+        return fnv1a(&words);
+    }
+
+    pub fn holds_dump(&self) -> bool {
+        self.length != 0
+    }
+
+    /// Validates the header at the start of `buf`, the whole region
+    pub fn find(buf: &[u8]) -> Result<Self, CrashDumpError> {
+        let capacity = capacity(buf)?;
+        /* SAFETY: aligned and large enough, any bit pattern is a valid header */
+        let header = unsafe { *(buf.as_ptr() as *const CrashDumpHeader) };
+
+        if header.magic != CRASHDUMP_MAGIC {
+            return Err(CrashDumpError::BadMagic);
+        }
+        if header.version != CRASHDUMP_VERSION {
+            return Err(CrashDumpError::BadVersion(header.version));
+        }
+        /* A different `crashdump_size` makes it a different region */
+        if header.capacity != capacity || header.length > header.capacity {
+            return Err(CrashDumpError::BadGeometry);
+        }
+        if header.checksum != header.checksum() {
+            return Err(CrashDumpError::BadChecksum);
+        }
+        return Ok(header);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashDumpError {
+    /// The region isn't aligned for the header
+    Misaligned,
+    /// Not even the header fits
+    TooSmall,
+    BadMagic,
+    BadVersion(u32),
+    /// Capacity doesn't match the region, or the dump doesn't fit it
+    BadGeometry,
+    BadChecksum,
+    /// `crashdump` isn't a page aligned address
+    BadAddress,
+    /// `crashdump_size` isn't a whole number of pages
+    BadSize,
+}
+
+fn capacity(buf: &[u8]) -> Result<u64, CrashDumpError> {
+    if buf.as_ptr() as usize % align_of::<CrashDumpHeader>() != 0 {
+        return Err(CrashDumpError::Misaligned);
+    }
+    match buf.len().checked_sub(CRASHDUMP_HEADER_SIZE) {
+        Some(x) => Ok(x as u64),
+        None => Err(CrashDumpError::TooSmall),
+    }
+}
+
+fn write_header(buf: &mut [u8], header: CrashDumpHeader) {
+    /* SAFETY: `capacity` checked the alignment and size */
+    unsafe { *(buf.as_mut_ptr() as *mut CrashDumpHeader) = header };
+}
+
+/// Where the region goes, from the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CrashDumpConfig {
+    pub addr: u64,
+    pub size: u64,
+}
+
+impl CrashDumpConfig {
+    /// `None` if there is no `crashdump` key
+    pub fn from_config(config: &Config) -> Result<Option<Self>, CrashDumpError> {
+        let addr = match config.get("crashdump") {
+            Some(x) => parse_u64(x)
+                .filter(|x| x % 4096 == 0)
+                .ok_or(CrashDumpError::BadAddress)?,
+            None => return Ok(None),
+        };
+        let size = match config.get("crashdump_size") {
+            Some(x) => parse_size(x).ok_or(CrashDumpError::BadSize)?,
+            None => DEFAULT_CRASHDUMP_SIZE,
+        };
+        if size == 0 || size % 4096 != 0 {
+            return Err(CrashDumpError::BadSize);
+        }
+
+        return Ok(Some(Self { addr, size }));
+    }
+
+    /// 4K pages the region spans
+    pub fn pages(&self) -> usize {
+        (self.size / 4096) as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CrashDumpState {
+    /// No `crashdump=`, or the region couldn't be reserved
+    Absent = 0,
+    /// The loader wrote a fresh header, there's no dump
+    Fresh,
+    /// An earlier boot left a dump, `header` says whose
+    Dump,
+}
+
+/// The crash-dump region as handed to the kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct CrashDumpRegion {
+    /// Empty unless reserved
+    pub range: PhysRange,
+    pub state: CrashDumpState,
+    /// As last written to the region
+    pub header: CrashDumpHeader,
+}
+
+impl CrashDumpRegion {
+    pub const fn new() -> Self {
+        Self {
+            range: PhysRange::empty(),
+            state: CrashDumpState::Absent,
+            header: CrashDumpHeader::empty(),
+        }
+    }
+
+    /// Decides between the dump already in `buf`, the region at `range`,
+    /// and a fresh header for `boot_id`. A valid header with a dump is
+    /// kept as it is, anything else is overwritten. Only the header is
+    /// ever written, the rest of the region is left alone.
+    pub fn open(range: PhysRange, buf: &mut [u8], boot_id: [u8; 16]) -> Result<Self, CrashDumpError> {
+        let header = match CrashDumpHeader::find(buf) {
+            Ok(header) if header.holds_dump() => {
+                return Ok(Self {
+                    range,
+                    state: CrashDumpState::Dump,
+                    header,
+                });
+            }
+            _ => CrashDumpHeader::fresh(capacity(buf)?, boot_id),
+        };
+        write_header(buf, header);
+        return Ok(Self {
+            range,
+            state: CrashDumpState::Fresh,
+            header,
+        });
+    }
+
+    /// Gives up the dump, once it was saved or if nobody wants it, by
+    /// writing a fresh header for `boot_id`. `buf` is the region.
+    pub fn clear(&mut self, buf: &mut [u8], boot_id: [u8; 16]) -> Result<(), CrashDumpError> {
+        let header = CrashDumpHeader::fresh(capacity(buf)?, boot_id);
+        write_header(buf, header);
+        self.state = CrashDumpState::Fresh;
+        self.header = header;
+        return Ok(());
+    }
+
+    /// The header and the dump after it, what gets saved, `None` without a
+    /// dump. `buf` is the region.
+    pub fn dump<'a>(&self, buf: &'a [u8]) -> Option<&'a [u8]> {
+        if self.state != CrashDumpState::Dump {
+            return None;
+        }
+        let end = CRASHDUMP_HEADER_SIZE as u64 + self.header.length;
+        return buf.get(..end as usize);
+    }
+}
+
+impl Default for CrashDumpRegion {
+    fn default() -> Self {
+        Self::new()
+    }
+}
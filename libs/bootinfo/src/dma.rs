@@ -0,0 +1,83 @@
+//! `dma_ceiling=` in `sovos.cfg`: devices the kernel brings up before its
+//! IOMMU, like a disk for crash dumps or a NIC for a network console, may
+//! not reach high memory. Structures they could DMA from are placed below
+//! the ceiling while there's room, and tagged in `Bootinfo::dma_regions`
+//! with whether they are, so the kernel knows what it can use directly.
+
+use crate::{parse_size, Bootinfo, Config, FrameAllocator, ReservedKind};
+use cpu::PhysRange;
+
+/// Entries of `Bootinfo::dma_regions`
+pub const MAX_DMA_REGIONS: usize = 8;
+
+/// A region the kernel may DMA from, see `Bootinfo::tag_dma`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct DmaRegion {
+    pub range: PhysRange,
+    pub kind: ReservedKind,
+    /// Ends at or below `Bootinfo::dma_ceiling`, always set without one
+    pub below_ceiling: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaCeilingError {
+    BadNumber,
+    /// Below the first page, nothing could go there
+    TooLow,
+}
+
+/// `dma_ceiling=` as a number of bytes, see `parse_size`. `None` without
+/// the key.
+pub fn dma_ceiling(config: &Config) -> Result<Option<u64>, DmaCeilingError> {
+    let value = match config.get("dma_ceiling") {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let ceiling = parse_size(value).ok_or(DmaCeilingError::BadNumber)?;
+    if ceiling < 0x1000 {
+        return Err(DmaCeilingError::TooLow);
+    }
+    return Ok(Some(ceiling));
+}
+
+/// Frames for something early devices may DMA from: below `ceiling` while
+/// `frames` has room there, anywhere otherwise. Whether the ceiling held
+/// is up to `Bootinfo::tag_dma`.
+pub fn alloc_dma(
+    frames: &mut impl FrameAllocator,
+    len: u64,
+    align: u64,
+    ceiling: Option<u64>,
+) -> Option<PhysRange> {
+    if let Some(ceiling) = ceiling {
+        if let Some(range) = frames.alloc_frames_below(len, align, ceiling) {
+            return Some(range);
+        }
+    }
+    return frames.alloc_frames(len, align);
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// `dma_ceiling` if there is one
+    pub fn dma_ceiling(&self) -> Option<u64> {
+        match self.dma_ceiling {
+            0 => None,
+            x => Some(x),
+        }
+    }
+
+    /// Records `range` in `dma_regions`. Returns whether it honors the
+    /// ceiling, the caller warns if it doesn't. A full table drops the tag,
+    /// the kernel then treats the region as out of reach.
+    pub fn tag_dma(&mut self, range: PhysRange, kind: ReservedKind) -> bool {
+        let below_ceiling = self.dma_ceiling().map_or(true, |c| range.end() <= c);
+        let _ = self.dma_regions.try_push(DmaRegion {
+            range,
+            kind,
+            below_ceiling,
+        });
+        return below_ceiling;
+    }
+}
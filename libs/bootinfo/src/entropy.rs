@@ -0,0 +1,189 @@
+use crate::sha256::Sha256;
+use impl_bits::impl_bits;
+
+/// Longest run of identical bytes a healthy sample may contain
+const REPETITION_CUTOFF: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntropySource {
+    EfiRng = 0,
+    Rdseed = 1,
+    Rdrand = 2,
+    /// Low bits of TSC deltas between repeated samples
+    TscJitter = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthError {
+    Empty,
+    AllZero,
+    AllOnes,
+    /// Same byte repeated too many times or a 64-bit word repeated
+    /// back to back, like a generator stuck at one value
+    Repetition,
+}
+
+/// Which entropy sources were present at boot and which of them passed
+/// health checks, so the kernel knows the quality of `Bootinfo::seed`
+#[repr(transparent)]
+pub struct EntropyStatus(u32);
+
+impl_bits! {
+    EntropyStatus = {
+        efi_rng_available = 0,
+        efi_rng_healthy = 1,
+        rdseed_available = 2,
+        rdseed_healthy = 3,
+        rdrand_available = 4,
+        rdrand_healthy = 5,
+        tsc_jitter_available = 6,
+        tsc_jitter_healthy = 7,
+    }
+}
+
+impl EntropyStatus {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub const fn is_available(&self, source: EntropySource) -> bool {
+        (self.0 >> (2 * source as u32)) & 1 == 1
+    }
+
+    pub const fn is_healthy(&self, source: EntropySource) -> bool {
+        (self.0 >> (2 * source as u32 + 1)) & 1 == 1
+    }
+
+    /// Number of sources that contributed to the seed
+    pub const fn healthy_sources(&self) -> u32 {
+        (self.0 & 0xAA).count_ones()
+    }
+
+    fn mark(self, source: EntropySource, healthy: bool) -> Self {
+        let bit = 2 * source as u32;
+        let healthy = (healthy as u32) << (bit + 1);
+        Self(self.0 | 1 << bit | healthy)
+    }
+}
+
+/// Rejects samples that are obviously broken, like an RNG that returns
+/// all zeroes. It can't tell whether a sample is actually random.
+pub fn health_check(sample: &[u8]) -> Result<(), HealthError> {
+    if sample.is_empty() {
+        return Err(HealthError::Empty);
+    }
+    if sample.iter().all(|&x| x == 0x00) {
+        return Err(HealthError::AllZero);
+    }
+    if sample.iter().all(|&x| x == 0xFF) {
+        return Err(HealthError::AllOnes);
+    }
+
+    let mut run = 1;
+    for pair in sample.windows(2) {
+        run = if pair[0] == pair[1] { run + 1 } else { 1 };
+        if run >= REPETITION_CUTOFF {
+            return Err(HealthError::Repetition);
+        }
+    }
+
+    let words = sample.chunks_exact(8);
+    if words.clone().zip(words.skip(1)).any(|(a, b)| a == b) {
+        return Err(HealthError::Repetition);
+    }
+
+    return Ok(());
+}
+
+/// Mixes samples of every entropy source through SHA-256.
+/// A sample that fails `health_check` contributes nothing.
+pub struct EntropyPool {
+    hasher: Sha256,
+    status: EntropyStatus,
+}
+
+impl EntropyPool {
+    pub const fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+            status: EntropyStatus::new(),
+        }
+    }
+
+    pub fn add(&mut self, source: EntropySource, sample: &[u8]) -> Result<(), HealthError> {
+        let result = health_check(sample);
+        self.status = self.status.mark(source, result.is_ok());
+
+        if result.is_ok() {
+            /* Domain separation, so that sources can't imitate each other */
+            self.hasher.update(&[source as u8]);
+            self.hasher.update(&(sample.len() as u64).to_le_bytes());
+            self.hasher.update(sample);
+        }
+
+        return result;
+    }
+
+    pub fn status(&self) -> EntropyStatus {
+        self.status
+    }
+
+    pub fn finish(self) -> ([u8; 32], EntropyStatus) {
+        (self.hasher.finish(), self.status)
+    }
+}
+
+/// IDs linking a boot to the firmware boot it descends from through kexec,
+/// so logs of every generation can be told apart and still grouped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootLineage {
+    pub boot_id: [u8; 16],
+    /// `boot_id` of the boot started by firmware
+    pub root_id: [u8; 16],
+    /// Kexecs since then, 0 for the firmware boot
+    pub generation: u32,
+}
+
+impl BootLineage {
+    pub const fn new() -> Self {
+        Self {
+            boot_id: [0u8; 16],
+            root_id: [0u8; 16],
+            generation: 0,
+        }
+    }
+
+    /// Lineage of a boot started by firmware, the ID comes from `seed`
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"sovos boot id");
+        hasher.update(seed);
+
+        let mut boot_id = [0u8; 16];
+        boot_id.copy_from_slice(&hasher.finish()[..16]);
+        return Self {
+            boot_id,
+            root_id: boot_id,
+            generation: 0,
+        };
+    }
+
+    /// Lineage of a kernel kexec'd from this boot. The ID is derived from
+    /// this one, the seed may be carried forward unchanged.
+    pub fn next(&self) -> Self {
+        let generation = self.generation.wrapping_add(1);
+        let mut hasher = Sha256::new();
+        hasher.update(b"sovos kexec");
+        hasher.update(&self.boot_id);
+        hasher.update(&generation.to_le_bytes());
+
+        let mut boot_id = [0u8; 16];
+        boot_id.copy_from_slice(&hasher.finish()[..16]);
+        return Self {
+            boot_id,
+            root_id: self.root_id,
+            generation,
+        };
+    }
+}
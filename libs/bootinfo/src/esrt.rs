@@ -0,0 +1,59 @@
+//! Copy of the EFI System Resource Table, so the kernel knows which
+//! firmware components are updatable and at what versions without a
+//! separate UEFI tool. The ESRT usually lives in boot services data,
+//! which the kernel reclaims, so only the copy in `Bootinfo` survives.
+
+use crate::Bootinfo;
+use uefi::memory::Descriptor;
+use uefi::{Esrt, Guid};
+
+/// Entries over this are counted in `esrt_dropped`
+pub const MAX_FIRMWARE_RESOURCES: usize = 16;
+
+/// The part of an ESRT entry the kernel cares about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct FirmwareResource {
+    pub fw_class: Guid,
+    pub version: u32,
+    pub lowest_supported_version: u32,
+    pub last_attempt_status: u32,
+}
+
+/// Memory from the table at `addr` to the end of the `meminfo` region
+/// containing it, which bounds what the table may claim
+///
+/// # Safety
+/// Memory in `meminfo` must be identity-mapped and readable.
+pub unsafe fn table_memory(meminfo: &[Descriptor], addr: u64) -> Option<&'static [u8]> {
+    let range = meminfo
+        .iter()
+        .filter_map(|x| x.phys_range())
+        .find(|x| x.start() <= addr && addr < x.end())?;
+
+    let len = (range.end() - addr) as usize;
+    return Some(core::slice::from_raw_parts(addr as *const u8, len));
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Replaces `esrt` with the entries of `table`. Returns how many
+    /// didn't fit, which is also kept in `esrt_dropped`.
+    pub fn record_esrt(&mut self, table: &Esrt) -> u32 {
+        self.esrt.clear();
+        self.esrt_dropped = 0;
+
+        for entry in table.entries() {
+            let resource = FirmwareResource {
+                fw_class: entry.fw_class,
+                version: entry.fw_version,
+                lowest_supported_version: entry.lowest_supported_fw_version,
+                last_attempt_status: entry.last_attempt_status,
+            };
+            if self.esrt.try_push(resource).is_err() {
+                self.esrt_dropped += 1;
+            }
+        }
+
+        return self.esrt_dropped;
+    }
+}
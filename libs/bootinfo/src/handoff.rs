@@ -0,0 +1,757 @@
+//! Entering a kernel, whether the loader starts it or a running kernel
+//! soft-reboots into another one (kexec). The pipeline is the same: copy
+//! the kernel's segments into frames from a `FrameAllocator`, build fresh
+//! page tables and a fresh `Bootinfo`, and switch to them through
+//! `TRAMPOLINE`. Only where the frames and the memory map come from differs,
+//! the loader asks boot services, `kexec_prepare` takes both as arguments
+//! and never calls firmware.
+//!
+//! Like the rest of `Bootinfo`, everything here assumes memory is
+//! identity-mapped.
+
+use crate::{
+    is_usable, negotiate, AbiError, AbiNote, BootCapabilities, Bootinfo, BootinfoBuilder,
+    CrashDumpState, KernelFeatures, KernelPermPolicy, KernelRequirements, MapGranularity,
+    MapKernelError, OwnedTable, PinnedBootinfo, ReservedKind, SegmentPerms, UnmetRequirement,
+    ABI_NOTE_NAME, ABI_NOTE_TYPE, DEFAULT_BUF_SIZE, KERNEL_BASE, LOW_MEMORY_END,
+    REQUIREMENTS_NOTE_TYPE,
+};
+use arrayvec::ArrayVec;
+use cpu::mapper::{MapError, Mapper, TableAlloc};
+use cpu::paging::{self, Megapage, PML4Entry, Table, MEGAPAGE_SIZE, PAGE_SIZE};
+use cpu::phys::{IdentityMapping, PhysMapping, PhysWrite, PhysWriter};
+use cpu::{PhysAddr, PhysRange, PhysSlice, VirtAddr};
+use elf::{Amd64, Elf, HeaderTable, ProgramHeader};
+use uefi::memory::{Descriptor, Type};
+
+/// Position independent code that switches page tables and stacks and jumps
+/// to the kernel, for kernels that build their own tables from scratch.
+/// Called as `extern "sysv64" fn(cr3, stack, entry, arg) -> !`,
+/// the kernel gets `arg` as its first argument.
+pub const TRAMPOLINE: [u8; 11] = [
+    0x0f, 0x22, 0xdf, /* mov cr3, rdi */
+    0x48, 0x89, 0xf4, /* mov rsp, rsi */
+    0x48, 0x89, 0xcf, /* mov rdi, rcx */
+    0xff, 0xe2, /* jmp rdx */
+];
+
+/// Kernel section the trampoline is copied into
+pub const HANDOFF_SECTION: &str = ".handoff";
+
+/// Page used when the kernel has no `HANDOFF_SECTION`,
+/// the kernel's initial tables must map it 1:1 like firmware does
+pub const HANDOFF_FIXED_VIRT: u64 = 0x8000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandoffError {
+    /// Trampoline would cross a page boundary
+    CrossesPage,
+    NotMappedOld,
+    NotMappedNew,
+    /// Both tables map the page, but to different physical memory
+    Mismatch {
+        old: u64,
+        new: u64,
+    },
+}
+
+/// Checks that `virt` is backed by the same physical page in both tables,
+/// so that code there keeps running across the CR3 switch
+///
+/// # Safety
+/// Every table reachable from either root must be identity-mapped.
+pub unsafe fn check_dual_mapping(
+    old_root: &Table<PML4Entry>,
+    new_root: &Table<PML4Entry>,
+    virt: u64,
+) -> Result<PhysAddr, HandoffError> {
+    if virt % PAGE_SIZE + TRAMPOLINE.len() as u64 > PAGE_SIZE {
+        return Err(HandoffError::CrossesPage);
+    }
+
+    let old = paging::translate(old_root, VirtAddr::new(virt)).ok_or(HandoffError::NotMappedOld)?;
+    let new = paging::translate(new_root, VirtAddr::new(virt)).ok_or(HandoffError::NotMappedNew)?;
+    if old.as_u64() != new.as_u64() {
+        return Err(HandoffError::Mismatch {
+            old: old.as_u64(),
+            new: new.as_u64(),
+        });
+    }
+
+    return Ok(old);
+}
+
+/// `TRAMPOLINE` installed at an address mapped in both page tables
+#[derive(Clone, Copy, Debug)]
+pub struct Trampoline {
+    virt: u64,
+}
+
+impl Trampoline {
+    /// Verifies the dual mapping, then copies `TRAMPOLINE` to where `virt`
+    /// is backed in physical memory and flushes it from the caches
+    ///
+    /// # Safety
+    /// * Every table reachable from either root must be identity-mapped.
+    /// * `writer` must allow the target, and nothing else may live at
+    ///   `virt..virt+TRAMPOLINE.len()`.
+    pub unsafe fn install(
+        old_root: &Table<PML4Entry>,
+        new_root: &Table<PML4Entry>,
+        virt: u64,
+        writer: &mut PhysWriter<impl PhysMapping>,
+    ) -> Result<Self, HandoffError> {
+        let phys = check_dual_mapping(old_root, new_root, virt)?;
+        writer.copy_from_slice("handoff trampoline", phys.as_u64(), &TRAMPOLINE);
+        /* Executed through `virt`, not the mapping it was written through */
+        if let Some(range) = PhysRange::new(phys.as_u64(), TRAMPOLINE.len() as u64) {
+            writer.flush(range);
+        }
+
+        return Ok(Self { virt });
+    }
+
+    pub const fn virt(&self) -> u64 {
+        self.virt
+    }
+
+    /// Loads `cr3`, switches to `stack` and jumps to `entry` with `arg`
+    /// as the first argument
+    ///
+    /// # Safety
+    /// * The page tables given to `install` must still be the same.
+    /// * `cr3` must be the physical address of the new root.
+    /// * `stack` and `entry` must be mapped in the new tables.
+    pub unsafe fn jump(&self, cr3: u64, stack: u64, entry: u64, arg: u64) -> ! {
+        let trampoline: extern "sysv64" fn(u64, u64, u64, u64) -> ! =
+            core::mem::transmute(self.virt as usize);
+        trampoline(cr3, stack, entry, arg)
+    }
+}
+
+/// Source of physical memory for a kernel being entered
+pub trait FrameAllocator {
+    /// `len` bytes, rounded up to whole pages, starting at a multiple of
+    /// `align`. The memory isn't zeroed.
+    fn alloc_frames(&mut self, len: u64, align: u64) -> Option<PhysRange>;
+
+    /// `alloc_frames` ending at or below `end`
+    fn alloc_frames_below(&mut self, len: u64, align: u64, end: u64) -> Option<PhysRange>;
+}
+
+/// `align` must be a power of two
+const fn align_up(x: u64, align: u64) -> Option<u64> {
+    match x.checked_add(align - 1) {
+        Some(x) => Some(x & !(align - 1)),
+        None => None,
+    }
+}
+
+/// Frames from AllocatePages, typed `LoaderData` like the rest of what the
+/// loader hands over
+pub struct BootServicesFrames<'a> {
+    boot_services: &'a uefi::BootServices,
+}
+
+impl<'a> BootServicesFrames<'a> {
+    pub fn new(boot_services: &'a uefi::BootServices) -> Self {
+        Self { boot_services }
+    }
+}
+
+impl BootServicesFrames<'_> {
+    fn allocate(&mut self, len: u64, align: u64, typ: uefi::AllocateType, addr: u64) -> Option<PhysRange> {
+        let align = align.max(PAGE_SIZE).next_power_of_two();
+        let len = align_up(len, PAGE_SIZE)?;
+
+        /* UEFI only aligns to pages, so allocate enough to align the start */
+        let pages = (len.checked_add(align - PAGE_SIZE)? / PAGE_SIZE) as usize;
+        let base = self
+            .boot_services
+            .allocate_pages(typ, Type::LoaderData, pages, addr)
+            .ok()?;
+        return PhysRange::new(align_up(base, align)?, len);
+    }
+}
+
+impl FrameAllocator for BootServicesFrames<'_> {
+    fn alloc_frames(&mut self, len: u64, align: u64) -> Option<PhysRange> {
+        self.allocate(len, align, uefi::AllocateType::AnyPages, 0)
+    }
+
+    fn alloc_frames_below(&mut self, len: u64, align: u64, end: u64) -> Option<PhysRange> {
+        /* MaxAddress is the last byte the allocation may use */
+        self.allocate(len, align, uefi::AllocateType::MaxAddress, end.checked_sub(1)?)
+    }
+}
+
+/// Bump allocator over `Conventional` memory of a memory map, for when boot
+/// services are gone. Frames are handed out lowest address first, never
+/// below `LOW_MEMORY_END`, where AP startup code goes, and never overlapping
+/// `reserved`, which has to cover conventional memory the running kernel
+/// took for itself.
+pub struct MapFrameAllocator<'a> {
+    map: &'a [Descriptor],
+    reserved: &'a [PhysRange],
+    next: u64,
+}
+
+impl<'a> MapFrameAllocator<'a> {
+    pub fn new(map: &'a [Descriptor], reserved: &'a [PhysRange]) -> Self {
+        Self {
+            map,
+            reserved,
+            next: LOW_MEMORY_END,
+        }
+    }
+
+    /// Lowest aligned start of `len` bytes inside `region`
+    fn first_fit(&self, region: PhysRange, len: u64, align: u64) -> Option<u64> {
+        let mut start = align_up(region.start().max(self.next), align)?;
+        loop {
+            let candidate = PhysRange::new(start, len)?;
+            if !region.contains_range(&candidate) {
+                return None;
+            }
+            match self.reserved.iter().find(|x| x.overlaps(&candidate)) {
+                Some(reserved) => start = align_up(reserved.end(), align)?,
+                None => return Some(start),
+            }
+        }
+    }
+}
+
+impl FrameAllocator for MapFrameAllocator<'_> {
+    fn alloc_frames(&mut self, len: u64, align: u64) -> Option<PhysRange> {
+        self.alloc_frames_below(len, align, u64::MAX)
+    }
+
+    fn alloc_frames_below(&mut self, len: u64, align: u64, end: u64) -> Option<PhysRange> {
+        let align = align.max(PAGE_SIZE).next_power_of_two();
+        let len = align_up(len, PAGE_SIZE)?;
+        if len == 0 {
+            return None;
+        }
+
+        let start = self
+            .map
+            .iter()
+            .filter(|x| x.memory_type() == Some(Type::Conventional))
+            .filter_map(|x| x.phys_range())
+            .filter_map(|region| region.split_at(end.max(region.start()).min(region.end())))
+            .filter_map(|(below, _)| self.first_fit(below, len, align))
+            .min()?;
+        self.next = start + len;
+        return PhysRange::new(start, len);
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum KernelImageError {
+    Elf(elf::ParseError),
+    Headers(elf::MemoryError),
+    /// Fewer than three program headers
+    MissingSegment,
+    /// Segment `index` has the wrong permissions, isn't 2M aligned or
+    /// doesn't directly follow the previous one
+    Layout {
+        index: usize,
+    },
+}
+
+/// Text, rodata and data/bss, in the order `KernelImage` expects them
+const KERNEL_SEGMENT_PERMS: [SegmentPerms; 3] =
+    [SegmentPerms::RX, SegmentPerms::R, SegmentPerms::RW];
+
+/// A kernel ELF laid out the way `Bootinfo::map_kernel` maps it: text,
+/// rodata and data/bss as the first three program headers, 2M aligned,
+/// one after another from `KERNEL_BASE`
+pub struct KernelImage<'a> {
+    elf: Elf<'a, Amd64>,
+    segments: [ProgramHeader; 3],
+    rest: HeaderTable<'a, ProgramHeader>,
+}
+
+/// Where one kernel segment goes in physical memory
+#[derive(Clone, Copy, Debug)]
+pub struct SegmentLoad<'a> {
+    pub vaddr: u64,
+    /// Bytes from the file
+    pub data: &'a [u8],
+    /// Where `data` goes
+    pub filled: PhysRange,
+    /// Rest of the segment up to a megapage boundary, zeroed
+    pub bss: PhysRange,
+}
+
+fn megapages(ph: &ProgramHeader) -> Option<u64> {
+    Some(ph.p_memsz.checked_add(MEGAPAGE_SIZE - 1)? / MEGAPAGE_SIZE)
+}
+
+impl<'a> KernelImage<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, KernelImageError> {
+        let elf: Elf<Amd64> = Elf::parse(bytes).map_err(KernelImageError::Elf)?;
+        /* Also the PT_LOAD segments after the three, none may overlap */
+        elf.load_bounds().map_err(KernelImageError::Elf)?;
+        let mut rest = elf.program_headers().map_err(KernelImageError::Headers)?;
+
+        let mut segments = [ProgramHeader::new_load(0, 0, 0, 0, 0, 0); 3];
+        let mut vaddr = Some(KERNEL_BASE);
+        for (index, (segment, perms)) in segments.iter_mut().zip(&KERNEL_SEGMENT_PERMS).enumerate()
+        {
+            let (ph, tail) = rest.split_first().ok_or(KernelImageError::MissingSegment)?;
+            let layout_ok = ph.is_executable() == perms.executable
+                && ph.is_writable() == perms.writable
+                && ph.p_align == MEGAPAGE_SIZE
+                && Some(ph.p_vaddr) == vaddr
+                && ph.p_filesz <= ph.p_memsz;
+            if !layout_ok {
+                return Err(KernelImageError::Layout { index });
+            }
+            elf.segment_data(&ph).map_err(KernelImageError::Headers)?;
+
+            let size = megapages(&ph).ok_or(KernelImageError::Layout { index })? * MEGAPAGE_SIZE;
+            vaddr = ph.p_vaddr.checked_add(size);
+            *segment = ph;
+            rest = tail;
+        }
+
+        return Ok(Self {
+            elf,
+            segments,
+            rest,
+        });
+    }
+
+    pub fn elf(&self) -> &Elf<'a, Amd64> {
+        &self.elf
+    }
+
+    /// Text, rodata and data/bss
+    pub fn segments(&self) -> &[ProgramHeader; 3] {
+        &self.segments
+    }
+
+    /// Program headers after the three segments
+    pub fn other_headers(&self) -> HeaderTable<'a, ProgramHeader> {
+        self.rest
+    }
+
+    /// Bytes the segments take in memory, whole megapages each
+    pub fn size(&self) -> u64 {
+        /* Sizes were checked in `parse` */
+        self.segments
+            .iter()
+            .map(|ph| megapages(ph).unwrap_or(0) * MEGAPAGE_SIZE)
+            .sum()
+    }
+
+    /// `e_entry`, if it is inside text
+    pub fn entry(&self) -> Option<u64> {
+        let entry = self.elf.header().e_entry?.get();
+        let text = &self.segments[0];
+        if entry < text.p_vaddr || entry - text.p_vaddr >= text.p_memsz {
+            return None;
+        }
+        return Some(entry);
+    }
+
+    /// The kernel's `SOVOS` note, `None` if it has none
+    pub fn abi_note(&self) -> Result<Option<AbiNote>, KexecError> {
+        let note = self
+            .elf
+            .header()
+            .find_note(self.elf.data, ABI_NOTE_NAME, ABI_NOTE_TYPE)
+            .map_err(KexecError::Notes)?;
+        return match note {
+            Some(note) => AbiNote::from_desc(note.desc)
+                .map(Some)
+                .map_err(KexecError::Abi),
+            None => Ok(None),
+        };
+    }
+
+    /// The kernel's requirements note, `KernelRequirements::DEFAULT` if
+    /// it has none
+    pub fn requirements(&self) -> Result<KernelRequirements, KexecError> {
+        let note = self
+            .elf
+            .header()
+            .find_note(self.elf.data, ABI_NOTE_NAME, REQUIREMENTS_NOTE_TYPE)
+            .map_err(KexecError::Notes)?;
+        return match note {
+            Some(note) => KernelRequirements::from_desc(note.desc).map_err(KexecError::Abi),
+            None => Ok(KernelRequirements::DEFAULT),
+        };
+    }
+
+    /// Where the segments go when loaded from `base` on, `None` if `base`
+    /// isn't 2M aligned or the image doesn't fit below it
+    pub fn loads(&self, base: u64) -> Option<[SegmentLoad<'a>; 3]> {
+        if base % MEGAPAGE_SIZE != 0 {
+            return None;
+        }
+
+        let mut loads = [SegmentLoad {
+            vaddr: 0,
+            data: &[],
+            filled: PhysRange::empty(),
+            bss: PhysRange::empty(),
+        }; 3];
+        let mut offset = 0;
+        for (load, ph) in loads.iter_mut().zip(&self.segments) {
+            let size = megapages(ph)? * MEGAPAGE_SIZE;
+            let data = self.elf.segment_data(ph).ok()?;
+            let segment = PhysRange::new(base.checked_add(offset)?, size)?;
+            let (filled, bss) = segment.split_at(segment.start() + data.len() as u64)?;
+
+            *load = SegmentLoad {
+                vaddr: ph.p_vaddr,
+                data,
+                filled,
+                bss,
+            };
+            offset += size;
+        }
+
+        return Some(loads);
+    }
+}
+
+impl SegmentLoad<'_> {
+    /// The whole segment, in megapages as `Bootinfo::map_kernel` takes it
+    pub fn megapages(&self) -> PhysSlice<Megapage> {
+        let dst: PhysSlice<u8> = self.filled.into();
+        let len = self.filled.len() + self.bss.len();
+        PhysSlice::new(dst.addr().cast(), len / MEGAPAGE_SIZE)
+    }
+
+    /// Copies `data` to `filled`
+    ///
+    /// # Safety
+    /// `writer` must allow `filled`.
+    pub unsafe fn copy(&self, writer: &mut PhysWriter<impl PhysMapping>) {
+        writer.copy_from_slice("kernel segment", self.filled.start(), self.data);
+    }
+
+    /// Zeroes `bss`
+    ///
+    /// # Safety
+    /// `writer` must allow `bss`.
+    pub unsafe fn zero(&self, writer: &mut PhysWriter<impl PhysMapping>) {
+        writer.write_bytes("kernel bss", self.bss.start(), 0, self.bss.len());
+    }
+}
+
+/// Stack a kexec'd kernel starts on
+pub const KEXEC_STACK_SIZE: u64 = 0x1_0000;
+
+/* The block spans at most two megapages, each may need its own PDP and PD */
+const KEXEC_TABLE_PAGES: u64 = 4;
+
+/// Frames a kexec'd kernel is entered through, one after another and
+/// identity-mapped in its tables: its `Bootinfo`, the trampoline page,
+/// its initial stack and the tables of the identity mapping
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KexecBlock {
+    pub bootinfo: PhysRange,
+    pub trampoline: PhysRange,
+    pub stack: PhysRange,
+    pub tables: PhysRange,
+}
+
+impl KexecBlock {
+    /// Size of a block holding a `Bootinfo<BUF>`
+    pub const fn size<const BUF: usize>() -> u64 {
+        let bootinfo = core::mem::size_of::<Bootinfo<BUF>>() as u64;
+        bootinfo + PAGE_SIZE + KEXEC_STACK_SIZE + KEXEC_TABLE_PAGES * PAGE_SIZE
+    }
+
+    /// `None` if `start` isn't page aligned
+    pub fn at<const BUF: usize>(start: u64) -> Option<Self> {
+        if start % PAGE_SIZE != 0 {
+            return None;
+        }
+
+        let size = core::mem::size_of::<Bootinfo<BUF>>() as u64;
+        let bootinfo = PhysRange::new(start, size)?;
+        let trampoline = PhysRange::new(bootinfo.end(), PAGE_SIZE)?;
+        let stack = PhysRange::new(trampoline.end(), KEXEC_STACK_SIZE)?;
+        let tables = PhysRange::new(stack.end(), KEXEC_TABLE_PAGES * PAGE_SIZE)?;
+        return Some(Self {
+            bootinfo,
+            trampoline,
+            stack,
+            tables,
+        });
+    }
+
+    pub fn range(&self) -> PhysRange {
+        PhysRange::from_start_end(self.bootinfo.start(), self.tables.end()).unwrap_or(self.bootinfo)
+    }
+}
+
+/* Hands out the pre-zeroed pages of `KexecBlock::tables` */
+struct BlockTables {
+    next: u64,
+    end: u64,
+}
+
+impl TableAlloc for BlockTables {
+    fn alloc_table(&mut self) -> Option<PhysAddr> {
+        if self.next >= self.end {
+            return None;
+        }
+        let table = self.next;
+        self.next += PAGE_SIZE;
+        return PhysAddr::new(table);
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum KexecError {
+    /// `e_entry` is missing or outside text
+    NoEntry,
+    Notes(elf::MemoryError),
+    Abi(AbiError),
+    OutOfMemory,
+    Map(MapKernelError),
+    IdentityMap(MapError),
+    /// The kernel asked for more than a `KexecBlock` gives it
+    Requirement(UnmetRequirement),
+}
+
+/// A kernel ready to be entered, see `kexec_prepare`
+pub struct KexecImage<const BUF: usize = DEFAULT_BUF_SIZE> {
+    pub bootinfo: PinnedBootinfo<BUF>,
+    /// Physical address of the new `paging_root`
+    pub cr3: u64,
+    pub entry: u64,
+    pub kernel: PhysRange,
+    pub block: KexecBlock,
+}
+
+impl<const BUF: usize> KexecImage<BUF> {
+    /// Copies `TRAMPOLINE` to `block.trampoline`
+    ///
+    /// # Safety
+    /// * `old_root` must be the running page tables, identity-mapped.
+    /// * `writer` must allow `block.trampoline`.
+    pub unsafe fn install_trampoline(
+        &self,
+        old_root: &Table<PML4Entry>,
+        writer: &mut PhysWriter<impl PhysMapping>,
+    ) -> Result<Trampoline, HandoffError> {
+        let virt = self.block.trampoline.start();
+        Trampoline::install(old_root, &self.bootinfo.paging_root, virt, writer)
+    }
+
+    /// Where `rsp` starts: 8 below the top of the stack, as if `entry`
+    /// was called and a return address pushed
+    pub fn stack_pointer(&self) -> u64 {
+        self.block.stack.end() - 8
+    }
+
+    /// Enters the kernel with the physical address of its `Bootinfo`
+    /// as the first argument
+    ///
+    /// # Safety
+    /// * `trampoline` must come from `install_trampoline` on the running tables.
+    /// * Interrupts must be disabled, nothing the running kernel set up
+    ///   survives.
+    pub unsafe fn jump(&self, trampoline: &Trampoline) -> ! {
+        let arg = self.bootinfo.this.as_u64();
+        trampoline.jump(self.cr3, self.stack_pointer(), self.entry, arg)
+    }
+}
+
+/// Copies `map`, with `taken` cut out of usable memory as `LoaderData`.
+/// `taken` must be sorted and hold at most two ranges.
+fn carve_memory_map<'a>(
+    map: &'a [Descriptor],
+    taken: &'a [PhysRange],
+) -> impl Iterator<Item = Descriptor> + 'a {
+    /* SAFETY: plain data */
+    let copy = |x: &Descriptor| unsafe { core::ptr::read(x) };
+    let piece = move |x: &Descriptor, typ: u32, range: PhysRange| {
+        let mut piece = copy(x);
+        piece.typ = typ;
+        piece.phys_start = range.start();
+        piece.virt_start = range.start();
+        piece.pages = range.len() / PAGE_SIZE;
+        return piece;
+    };
+
+    map.iter().flat_map(move |x| {
+        /* Each taken range adds itself and what follows it */
+        let mut pieces = ArrayVec::<Descriptor, 5>::new();
+        let region = match x.phys_range() {
+            Some(region) if is_usable(x.memory_type()) => region,
+            _ => {
+                pieces.push(copy(x));
+                return pieces;
+            }
+        };
+
+        let mut cursor = region.start();
+        for range in taken.iter().filter_map(|t| t.intersection(&region)) {
+            if range.start() > cursor {
+                let before = PhysRange::from_start_end(cursor, range.start()).unwrap();
+                pieces.push(piece(x, x.typ, before));
+            }
+            pieces.push(piece(x, Type::LoaderData as u32, range));
+            cursor = range.end();
+        }
+        if cursor < region.end() {
+            let after = PhysRange::from_start_end(cursor, region.end()).unwrap();
+            pieces.push(piece(x, x.typ, after));
+        }
+        return pieces;
+    })
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Copies from `previous` what describes the machine rather than the
+    /// boot: the system table (and through it ACPI and SMBIOS), ESRT,
+    /// page flags, microcode, mitigations, entropy and the crash-dump
+    /// region, with the capabilities backed by them. The lineage moves one
+    /// generation on.
+    pub fn carry_forward<const PREV: usize>(&mut self, previous: &Bootinfo<PREV>) {
+        self.uefi_systable = previous.uefi_systable;
+        self.uefi_revision = previous.uefi_revision;
+        self.esrt = previous.esrt.clone();
+        self.esrt_dropped = previous.esrt_dropped;
+        self.page_flags = previous.page_flags;
+        self.microcode = previous.microcode;
+        self.mitigations = previous.mitigations;
+        self.seed = previous.seed;
+        self.entropy = previous.entropy;
+        self.lineage = previous.lineage.next();
+        self.acpi = previous.acpi;
+        self.crash_dump = previous.crash_dump;
+        if self.crash_dump.state != CrashDumpState::Absent {
+            self.reserve_region(self.crash_dump.range, ReservedKind::CrashDump);
+        }
+
+        let carried = BootCapabilities::new()
+            .set_microcode_applied()
+            .set_entropy_seeded()
+            .set_esrt()
+            .set_nx_unavailable()
+            .set_acpi()
+            .set_madt()
+            .set_hpet()
+            .set_acpi_reset()
+            .set_crash_dump();
+        let capabilities = previous.capabilities.as_u64() & carried.as_u64();
+        self.capabilities = BootCapabilities::from_u64(capabilities);
+    }
+}
+
+/// Prepares `kernel` to be entered from a running kernel, without firmware:
+/// * segments are copied into frames from `frames`,
+/// * a fresh `Bootinfo` in a `KexecBlock` gets `memory_map` with the new
+///   frames cut out as `LoaderData`, the console of `previous` and
+///   whatever `Bootinfo::carry_forward` takes from it,
+/// * its tables map the kernel at `KERNEL_BASE` and the block 1:1,
+///   with megapages that are writable and executable, like the 1:1 mapping
+///   firmware leaves behind. The kernel should drop it once it has its own.
+///
+/// Modules aren't carried over, nothing keeps their memory intact. The ABI
+/// note is negotiated with no features, there are no boot services left to
+/// provide them. The requirements note is checked against the block's
+/// `KEXEC_STACK_SIZE` stack. Writes go through a `PhysWriter` limited to the new frames
+/// and are reported to `log`, except for the `Bootinfo` itself.
+///
+/// # Safety
+/// * Memory must be identity-mapped.
+/// * `frames` must hand out only memory nothing else uses.
+pub unsafe fn kexec_prepare<const BUF: usize>(
+    kernel: &KernelImage,
+    memory_map: &[Descriptor],
+    previous: &Bootinfo<BUF>,
+    frames: &mut impl FrameAllocator,
+    log: &mut dyn FnMut(&PhysWrite),
+) -> Result<KexecImage<BUF>, KexecError> {
+    let entry = kernel.entry().ok_or(KexecError::NoEntry)?;
+    let note = kernel.abi_note()?;
+    let abi = negotiate(note.as_ref(), KernelFeatures::new(), BUF).map_err(KexecError::Abi)?;
+    let requirements = kernel.requirements()?;
+
+    let image = frames
+        .alloc_frames(kernel.size(), MEGAPAGE_SIZE)
+        .ok_or(KexecError::OutOfMemory)?;
+    let block = frames
+        .alloc_frames(KexecBlock::size::<BUF>(), PAGE_SIZE)
+        .and_then(|x| KexecBlock::at::<BUF>(x.start()))
+        .ok_or(KexecError::OutOfMemory)?;
+    let loads = kernel
+        .loads(image.start())
+        .ok_or(KexecError::Map(MapKernelError::Misaligned))?;
+
+    let allowed = [image, block.tables];
+    let mut writer = PhysWriter::new(&allowed, IdentityMapping, log);
+    for load in &loads {
+        load.copy(&mut writer);
+        load.zero(&mut writer);
+    }
+    writer.write_bytes("kexec tables", block.tables.start(), 0, block.tables.len());
+
+    let mut taken = [image, block.range()];
+    taken.sort_unstable_by_key(PhysRange::start);
+
+    let bootinfo = block.bootinfo.start() as *mut Bootinfo<BUF>;
+    bootinfo.write(Bootinfo::with_buf());
+    let bootinfo = &mut *bootinfo;
+    bootinfo.carry_forward(previous);
+    bootinfo.abi = abi;
+    bootinfo.kernel_stack = block.stack;
+    bootinfo.record(BootCapabilities::set_kexec);
+    bootinfo.mark("kexec");
+
+    let mut builder = BootinfoBuilder::new(bootinfo)
+        .memory_map(carve_memory_map(memory_map, &taken))
+        .kernel(image.into())
+        .console(previous.serial_sinks);
+    let [text, rodata, data] = loads;
+    builder
+        .as_mut()
+        .map_kernel(
+            text.megapages(),
+            rodata.megapages(),
+            data.megapages(),
+            &KernelPermPolicy::new(),
+            MapGranularity::Megapage,
+        )
+        .map_err(KexecError::Map)?;
+
+    let flags = builder.page_flags.leaf(SegmentPerms::RWX).pd();
+    let range = block.range();
+    let first = range.start() & !(MEGAPAGE_SIZE - 1);
+    let count = (range.end() - first + MEGAPAGE_SIZE - 1) / MEGAPAGE_SIZE;
+    let tables = BlockTables {
+        next: block.tables.start(),
+        end: block.tables.end(),
+    };
+    let root = &mut builder.get_mut().paging_root;
+    Mapper::new(root, tables)
+        .map_range_2m(first, first, count, flags)
+        .map_err(KexecError::IdentityMap)?;
+    builder
+        .get_mut()
+        .check_requirements(&requirements)
+        .map_err(KexecError::Requirement)?;
+
+    let bootinfo = builder.finish();
+    let cr3 = bootinfo
+        .as_ref()
+        .table_phys(OwnedTable::Pml4)
+        .ok_or(KexecError::Map(MapKernelError::Unpinned))?;
+    return Ok(KexecImage {
+        bootinfo,
+        cr3: cr3.as_u64(),
+        entry,
+        kernel: image,
+        block,
+    });
+}
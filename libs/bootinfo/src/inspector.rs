@@ -0,0 +1,426 @@
+#![cfg(feature = "inspector")]
+
+//! Memory inspector on the serial console, for boot failures that would
+//! otherwise need a loader rebuilt with one-off dump code.
+//!
+//! | command            | does                                           |
+//! |--------------------|------------------------------------------------|
+//! | `r <addr> <len>`   | hexdump of up to `MAX_READ` bytes              |
+//! | `w <addr> <b>...`  | writes up to `MAX_WRITE` bytes, after a prompt |
+//! | `pt <virt>`        | page table entries translating `virt`          |
+//! | `mm`               | UEFI memory map                                |
+//! | `bi`               | `Bootinfo` summary                             |
+//! | `q`                | leaves the inspector                           |
+//!
+//! Addresses are physical, or virtual with a `v:` prefix. Virtual addresses
+//! are translated through `Bootinfo`'s page tables and refused if any byte
+//! is unmapped. Numbers are parsed by `parse_u64`.
+
+use crate::{parse_u64, Bootinfo, CrashDumpState, SerialSinks};
+use arrayvec::{ArrayString, ArrayVec};
+use core::fmt::{self, Write};
+use cpu::paging::ENTRIES_PER_TABLE;
+use cpu::paging::{Bits, Entry, PDEntry, PDPEntry, PML4Entry, PTEntry, Table};
+use cpu::phys::{IdentityMapping, PhysWrite, PhysWriter};
+use cpu::PhysRange;
+use impl_bits::fmt::{Addr, HexDump, Size};
+use uefi::{poll_until, Clock, PolledInput, TimedOut};
+
+pub const MAX_LINE: usize = 80;
+pub const MAX_READ: u64 = 4096;
+pub const MAX_WRITE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Address {
+    Phys(u64),
+    Virt(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Read {
+        addr: Address,
+        len: u64,
+    },
+    Write {
+        addr: Address,
+        bytes: ArrayVec<u8, MAX_WRITE>,
+    },
+    PageTable(u64),
+    MemoryMap,
+    Bootinfo,
+    Help,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    UnknownCommand,
+    MissingArgument,
+    BadNumber,
+    /// Over `MAX_READ` or `MAX_WRITE` bytes
+    TooLong,
+    TrailingArgument,
+}
+
+const HELP: &str = "r <addr> <len>, w <addr> <byte>..., pt <virt>, mm, bi, q\n\
+                    addresses are physical, v:<addr> for virtual\n";
+
+fn number(word: Option<&str>) -> Result<u64, ParseError> {
+    let word = word.ok_or(ParseError::MissingArgument)?;
+    return parse_u64(word).ok_or(ParseError::BadNumber);
+}
+
+fn address(word: Option<&str>) -> Result<Address, ParseError> {
+    let word = word.ok_or(ParseError::MissingArgument)?;
+    return match word.strip_prefix("v:") {
+        Some(virt) => Ok(Address::Virt(number(Some(virt))?)),
+        None => Ok(Address::Phys(number(Some(word))?)),
+    };
+}
+
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let mut words = line.split_ascii_whitespace();
+
+    let command = match words.next().ok_or(ParseError::Empty)? {
+        "r" => {
+            let addr = address(words.next())?;
+            let len = number(words.next())?;
+            if len > MAX_READ {
+                return Err(ParseError::TooLong);
+            }
+            Command::Read { addr, len }
+        }
+        "w" => {
+            let addr = address(words.next())?;
+            let mut bytes = ArrayVec::new();
+            for word in &mut words {
+                let byte = number(Some(word))?;
+                if byte > 0xff {
+                    return Err(ParseError::BadNumber);
+                }
+                bytes
+                    .try_push(byte as u8)
+                    .map_err(|_| ParseError::TooLong)?;
+            }
+            if bytes.is_empty() {
+                return Err(ParseError::MissingArgument);
+            }
+            Command::Write { addr, bytes }
+        }
+        "pt" => Command::PageTable(number(words.next())?),
+        "mm" => Command::MemoryMap,
+        "bi" => Command::Bootinfo,
+        "help" | "?" => Command::Help,
+        "q" => Command::Quit,
+        _ => return Err(ParseError::UnknownCommand),
+    };
+
+    if words.next().is_some() {
+        return Err(ParseError::TrailingArgument);
+    }
+    return Ok(command);
+}
+
+/// Physical address of every byte of `addr..addr+len`,
+/// `Err` with the first address that isn't mapped
+///
+/// # Safety
+/// Memory must be identity-mapped, see `Bootinfo::translate`.
+unsafe fn resolve<const BUF: usize>(
+    bootinfo: &Bootinfo<BUF>,
+    addr: Address,
+    len: u64,
+    mut f: impl FnMut(u64, u64),
+) -> Result<(), u64> {
+    for offset in 0..len {
+        let phys = match addr {
+            Address::Phys(phys) => phys.checked_add(offset).ok_or(phys)?,
+            Address::Virt(virt) => {
+                let virt = virt.checked_add(offset).ok_or(virt)?;
+                bootinfo.translate(virt).ok_or(virt)?.as_u64()
+            }
+        };
+        f(offset, phys);
+    }
+    return Ok(());
+}
+
+const fn index(virt: u64, level: u32) -> usize {
+    ((virt >> (12 + 9 * level)) as usize) % ENTRIES_PER_TABLE
+}
+
+/// Prints every entry on the way to `virt`, like `paging::translate` walks them
+unsafe fn print_walk(out: &mut impl Write, root: &Table<PML4Entry>, virt: u64) -> fmt::Result {
+    const LEAF: u64 = 1 << 7;
+
+    let pml4e = &root[index(virt, 3)];
+    writeln!(
+        out,
+        "PML4[{:3}] {:#018x} {:?}",
+        index(virt, 3),
+        pml4e.as_u64(),
+        pml4e.flags()
+    )?;
+    if !pml4e.is_present() {
+        return writeln!(out, "not present");
+    }
+
+    let pdp = &*(pml4e.raw_addr().as_u64() as usize as *const Table<PDPEntry>);
+    let pdpe = &pdp[index(virt, 2)];
+    writeln!(
+        out,
+        "PDP [{:3}] {:#018x} {:?}",
+        index(virt, 2),
+        pdpe.as_u64(),
+        pdpe.flags()
+    )?;
+    if !pdpe.is_present() {
+        return writeln!(out, "not present");
+    }
+    if pdpe.as_u64() & LEAF != 0 {
+        writeln!(out, "1G page")?;
+    } else {
+        let pd = &*(pdpe.raw_addr().as_u64() as usize as *const Table<PDEntry>);
+        let pde = &pd[index(virt, 1)];
+        writeln!(
+            out,
+            "PD  [{:3}] {:#018x} {:?}",
+            index(virt, 1),
+            pde.as_u64(),
+            pde.flags()
+        )?;
+        if !pde.is_present() {
+            return writeln!(out, "not present");
+        }
+        if pde.flags().leaf() {
+            writeln!(out, "2M page")?;
+        } else {
+            let pt = &*(pde.raw_addr().as_u64() as usize as *const Table<PTEntry>);
+            let pte = &pt[index(virt, 0)];
+            writeln!(
+                out,
+                "PT  [{:3}] {:#018x} {:?}",
+                index(virt, 0),
+                pte.as_u64(),
+                pte.flags()
+            )?;
+            if !pte.is_present() {
+                return writeln!(out, "not present");
+            }
+        }
+    }
+
+    let phys = cpu::paging::translate(root, cpu::VirtAddr::new(virt));
+    return match phys {
+        Some(phys) => writeln!(out, "{} -> {}", Addr(virt), Addr(phys.as_u64())),
+        None => writeln!(out, "{} is not mapped", Addr(virt)),
+    };
+}
+
+fn print_bootinfo<const BUF: usize>(out: &mut impl Write, bootinfo: &Bootinfo<BUF>) -> fmt::Result {
+    writeln!(out, "this: {}", Addr(bootinfo.this.as_u64()))?;
+    writeln!(out, "buf: {} bytes", BUF)?;
+    writeln!(out, "kernel: {:?}", bootinfo.kernel_pslice)?;
+    for module in &bootinfo.modules {
+        writeln!(out, "{:?}", module)?;
+    }
+    for event in &bootinfo.timeline {
+        writeln!(out, "{:?}", event)?;
+    }
+    if let Some(perf) = &bootinfo.acpi.firmware_boot {
+        writeln!(out, "firmware boot: {:?}", perf)?;
+    }
+    writeln!(out, "memory map: {} entries", bootinfo.uefi_meminfo.len())?;
+    let memory = &bootinfo.memory;
+    writeln!(
+        out,
+        "free: {} in {} regions, {} below 1M, {} below 4G, {} above 4G, largest {:?}",
+        Size(memory.total()),
+        memory.regions,
+        Size(memory.below_1m),
+        Size(memory.below_4g),
+        Size(memory.above_4g),
+        memory.largest
+    )?;
+    writeln!(out, "entropy: {:?}", bootinfo.entropy)?;
+    for resource in &bootinfo.esrt {
+        writeln!(out, "{:?}", resource)?;
+    }
+    if bootinfo.esrt_dropped != 0 {
+        writeln!(out, "esrt: {} more entries dropped", bootinfo.esrt_dropped)?;
+    }
+    let crash_dump = &bootinfo.crash_dump;
+    if crash_dump.state != CrashDumpState::Absent {
+        writeln!(
+            out,
+            "crash dump: {:?} at {:?}, {} from boot {:02x?}",
+            crash_dump.state,
+            crash_dump.range,
+            Size(crash_dump.header.length),
+            crash_dump.header.boot_id
+        )?;
+    }
+    let microcode = &bootinfo.microcode;
+    if microcode.applied {
+        writeln!(
+            out,
+            "microcode: {:#x} -> {:#x}",
+            microcode.old_revision, microcode.new_revision
+        )?;
+    }
+    writeln!(out, "serial: {:?}", bootinfo.serial_sinks)?;
+    return Ok(());
+}
+
+/// Runs a single command, `confirm` is asked before writing memory
+///
+/// # Safety
+/// Memory must be identity-mapped, `r` and `w` access any physical address.
+pub unsafe fn execute<W: Write, const BUF: usize>(
+    out: &mut W,
+    bootinfo: &Bootinfo<BUF>,
+    command: &Command,
+    confirm: &mut dyn FnMut(&mut W) -> bool,
+) -> fmt::Result {
+    match command {
+        Command::Read { addr, len } => {
+            let mut buf = [0u8; MAX_READ as usize];
+            let resolved = resolve(bootinfo, *addr, *len, |offset, phys| {
+                buf[offset as usize] = core::ptr::read_volatile(phys as usize as *const u8);
+            });
+            if let Err(unmapped) = resolved {
+                return writeln!(out, "{} is not mapped", Addr(unmapped));
+            }
+
+            let start = match *addr {
+                Address::Phys(x) | Address::Virt(x) => x,
+            };
+            let dump = HexDump {
+                addr: start,
+                bytes: &buf[..*len as usize],
+            };
+            write!(out, "{}", dump)?;
+        }
+        Command::Write { addr, bytes } => {
+            let mut targets = [0u64; MAX_WRITE];
+            let len = bytes.len() as u64;
+            let resolved = resolve(bootinfo, *addr, len, |i, phys| targets[i as usize] = phys);
+            if let Err(unmapped) = resolved {
+                return writeln!(out, "{} is not mapped", Addr(unmapped));
+            }
+
+            write!(out, "write {} bytes at {}? [y/N] ", len, Addr(targets[0]))?;
+            if !confirm(out) {
+                return writeln!(out, "not written");
+            }
+
+            /* Confirmed is allowed, the writer keeps it on the record */
+            let mut runs = ArrayVec::<(PhysRange, usize), MAX_WRITE>::new();
+            for (i, &phys) in targets[..bytes.len()].iter().enumerate() {
+                match runs.last_mut() {
+                    Some((run, _)) if run.end() == phys => {
+                        *run = PhysRange::new(run.start(), run.len() + 1).unwrap();
+                    }
+                    _ => runs.push((PhysRange::new(phys, 1).ok_or(fmt::Error)?, i)),
+                }
+            }
+            let allowed: ArrayVec<PhysRange, MAX_WRITE> = runs.iter().map(|x| x.0).collect();
+            let mut writes = ArrayVec::<PhysWrite, MAX_WRITE>::new();
+            let mut log = |w: &PhysWrite| writes.push(*w);
+            let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+            for &(run, i) in &runs {
+                writer.copy_from_slice("inspector", run.start(), &bytes[i..i + run.len() as usize]);
+            }
+            for w in &writes {
+                writeln!(out, "written {:?} ({})", w.range, w.purpose)?;
+            }
+        }
+        Command::PageTable(virt) => print_walk(out, &bootinfo.paging_root, *virt)?,
+        Command::MemoryMap => {
+            for descriptor in &bootinfo.uefi_meminfo {
+                writeln!(out, "{:?}", descriptor)?;
+            }
+        }
+        Command::Bootinfo => print_bootinfo(out, bootinfo)?,
+        Command::Help => write!(out, "{}", HELP)?,
+        Command::Quit => {}
+    }
+    return Ok(());
+}
+
+/// Line from `console` with echo and backspace, `TimedOut` if it isn't
+/// finished by `deadline_us` of `clock`
+pub fn read_line<C: PolledInput + Write>(
+    console: &mut C,
+    clock: &dyn Clock,
+    deadline_us: u64,
+) -> Result<ArrayString<MAX_LINE>, TimedOut> {
+    let mut line = ArrayString::new();
+    loop {
+        match poll_until(console, clock, deadline_us)? {
+            b'\r' | b'\n' => {
+                let _ = console.write_str("\n");
+                return Ok(line);
+            }
+            0x08 | 0x7f => {
+                if line.pop().is_some() {
+                    let _ = console.write_str("\x08 \x08");
+                }
+            }
+            byte @ 0x20..=0x7e => {
+                if line.try_push(byte as char).is_ok() {
+                    let _ = console.write_char(byte as char);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Takes commands from the console until `q`, or until the console is
+/// idle for `idle_us` of `clock`, so a glitch on an unattended line
+/// doesn't hold up the boot. `u64::MAX` waits forever.
+/// Returns immediately if the console isn't working.
+///
+/// # Safety
+/// See `execute`.
+pub unsafe fn run<const BUF: usize>(
+    sinks: &mut SerialSinks,
+    bootinfo: &Bootinfo<BUF>,
+    clock: &dyn Clock,
+    idle_us: u64,
+) {
+    if !sinks.console().is_ready() {
+        return;
+    }
+
+    let deadline = || clock.now_us().saturating_add(idle_us);
+    let mut confirm = |sinks: &mut SerialSinks| {
+        let answer = poll_until(sinks, clock, deadline()).unwrap_or(b'n');
+        let _ = writeln!(sinks, "{}", answer as char);
+        return answer == b'y';
+    };
+
+    loop {
+        let _ = sinks.write_str("> ");
+        let line = match read_line(sinks, clock, deadline()) {
+            Ok(x) => x,
+            Err(TimedOut) => {
+                let _ = writeln!(sinks, "\nNo input, leaving the inspector");
+                return;
+            }
+        };
+        let command = match parse(&line) {
+            Ok(Command::Quit) => return,
+            Ok(x) => x,
+            Err(ParseError::Empty) => continue,
+            Err(e) => {
+                let _ = writeln!(sinks, "{:?}, try help", e);
+                continue;
+            }
+        };
+        let _ = execute(sinks, bootinfo, &command, &mut confirm);
+    }
+}
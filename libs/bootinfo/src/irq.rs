@@ -0,0 +1,162 @@
+//! Interrupt sources the loader armed, which must be quiet before
+//! ExitBootServices. An interrupt arriving after exit runs whatever
+//! handler is installed, and firmware handlers use boot services, which
+//! are gone by then. Every subsystem that arms something able to interrupt
+//! registers it here with a function that disarms it, and
+//! `Bootinfo::retrieve_and_exit` disarms everything before exiting.
+
+use arrayvec::ArrayVec;
+
+pub const MAX_INTERRUPT_SOURCES: usize = 8;
+
+/// Silences a source, the argument is whatever was registered with it,
+/// e.g. an I/O port or an MMIO base
+pub type DisarmFn = unsafe fn(u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrqError {
+    TooManySources,
+    /// Id wasn't returned by `register`
+    UnknownSource,
+}
+
+#[derive(Clone, Copy)]
+struct InterruptSource {
+    name: &'static str,
+    disarm: DisarmFn,
+    arg: u64,
+    armed: bool,
+}
+
+/// Registry of armed interrupt sources
+#[derive(Clone, Default)]
+pub struct InterruptSources {
+    sources: ArrayVec<InterruptSource, MAX_INTERRUPT_SOURCES>,
+}
+
+impl InterruptSources {
+    pub const fn new() -> Self {
+        Self {
+            sources: ArrayVec::new_const(),
+        }
+    }
+
+    /// Records an armed source, returns its id
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        disarm: DisarmFn,
+        arg: u64,
+    ) -> Result<usize, IrqError> {
+        let source = InterruptSource {
+            name,
+            disarm,
+            arg,
+            armed: true,
+        };
+        self.sources
+            .try_push(source)
+            .map_err(|_| IrqError::TooManySources)?;
+        return Ok(self.sources.len() - 1);
+    }
+
+    pub fn is_armed(&self, id: usize) -> bool {
+        self.sources.get(id).map_or(false, |x| x.armed)
+    }
+
+    /// Names of sources that are still armed
+    pub fn armed(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.sources.iter().filter(|x| x.armed).map(|x| x.name)
+    }
+
+    /// Disarms one source, a disarmed one is left alone
+    ///
+    /// # Safety
+    /// The device behind the source must still be there.
+    pub unsafe fn disarm(&mut self, id: usize) -> Result<(), IrqError> {
+        let source = self.sources.get_mut(id).ok_or(IrqError::UnknownSource)?;
+        if source.armed {
+            (source.disarm)(source.arg);
+            source.armed = false;
+        }
+        return Ok(());
+    }
+
+    /// Disarms every armed source, returns how many there were
+    ///
+    /// # Safety
+    /// See `disarm`.
+    pub unsafe fn disarm_all(&mut self) -> u8 {
+        let mut count = 0;
+        for source in self.sources.iter_mut().filter(|x| x.armed) {
+            (source.disarm)(source.arg);
+            source.armed = false;
+            count += 1;
+        }
+        return count;
+    }
+}
+
+impl core::fmt::Debug for InterruptSources {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.armed()).finish()
+    }
+}
+
+/// The processor's interrupt flag
+pub trait InterruptFlag {
+    fn is_enabled(&self) -> bool;
+    fn disable(&mut self);
+}
+
+/// RFLAGS.IF of the current processor
+#[cfg(feature = "ringzero")]
+pub struct CpuInterruptFlag;
+
+#[cfg(feature = "ringzero")]
+impl InterruptFlag for CpuInterruptFlag {
+    fn is_enabled(&self) -> bool {
+        cpu::Eflags::get().interrupt_enabled()
+    }
+
+    fn disable(&mut self) {
+        cpu::disable_interrupts();
+    }
+}
+
+/// What had to be done before ExitBootServices
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct PreExitReport {
+    /// Interrupts were enabled and got disabled
+    pub interrupts_disabled: bool,
+    /// Sources that were still armed
+    pub sources_disarmed: u8,
+}
+
+impl PreExitReport {
+    pub const fn new() -> Self {
+        Self {
+            interrupts_disabled: false,
+            sources_disarmed: 0,
+        }
+    }
+}
+
+/// Disables interrupts and disarms every source, in that order, so that
+/// nothing fires between the two
+///
+/// # Safety
+/// See `InterruptSources::disarm`.
+pub unsafe fn quiesce_interrupts(
+    sources: &mut InterruptSources,
+    flag: &mut impl InterruptFlag,
+) -> PreExitReport {
+    let mut report = PreExitReport::new();
+    if flag.is_enabled() {
+        flag.disable();
+        report.interrupts_disabled = true;
+    }
+    report.sources_disarmed = sources.disarm_all();
+    return report;
+}
@@ -1,71 +1,706 @@
 #![no_std]
 
 use arrayvec::ArrayVec;
-use cpu::paging::{self, PDEntry, PDPEntry, PML4Entry, PTEntry};
-use cpu::{interrupt, paging::Megapage, segmentation::GlobalDescriptorTable, PhysAddr, PhysSlice};
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use cpu::paging::{self, PDEntry, PDPEntry, PTEntry};
+use cpu::paging::{Megapage, Page};
+use cpu::{PhysAddr, PhysRange, PhysSlice, VirtAddr, VirtRange};
+#[cfg(target_arch = "x86_64")]
 use uart_16550::SerialPort;
 use uefi;
 
+mod abi;
+pub use abi::*;
+mod acpi;
+pub use acpi::*;
+mod arch;
+pub use arch::*;
+mod arena;
+pub use arena::*;
+mod builder;
+pub use builder::*;
+mod capabilities;
+pub use capabilities::*;
+#[cfg(target_arch = "x86_64")]
+pub mod cheader;
+mod config;
+pub use config::*;
+mod crashdump;
+pub use crashdump::*;
+mod dma;
+pub use dma::*;
+mod entropy;
+pub use entropy::*;
+mod esrt;
+pub use esrt::*;
+mod handoff;
+pub use handoff::*;
+#[cfg(feature = "inspector")]
+pub mod inspector;
+mod irq;
+pub use irq::*;
+#[cfg(feature = "limine-compat")]
+pub mod limine;
+mod lowmem;
+pub use lowmem::*;
+mod map;
+pub use map::*;
+mod microcode;
+pub use microcode::*;
+mod mitigations;
+pub use mitigations::*;
+mod pageflags;
+pub use pageflags::*;
+mod percpu;
+pub use percpu::*;
+mod physmem;
+pub use physmem::*;
+mod pinned;
+pub use pinned::*;
+mod requirements;
+pub use requirements::*;
+mod reserved;
+pub use reserved::*;
+mod serial;
+pub mod sha256;
+pub use serial::*;
+mod slots;
+pub use slots::*;
+mod snapshot;
+pub use snapshot::*;
+mod stage;
+pub use stage::*;
+mod strict;
+pub use strict::*;
+mod trace;
+pub use trace::*;
+mod txring;
+pub use txring::*;
+mod verify;
+pub use verify::*;
+
+/// What a module is for, known from its name in the archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ModuleKind {
+    Other = 0,
+    Kernel,
+    Config,
+    Initrd,
+    /// Intel microcode updates, already loaded on the BSP, see
+    /// `Bootinfo::microcode`, the kernel loads them on the APs
+    Microcode,
+}
+
+impl ModuleKind {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "kernel.elf" => Self::Kernel,
+            "sovos.cfg" => Self::Config,
+            "initrd.img" => Self::Initrd,
+            "microcode.bin" => Self::Microcode,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A file passed to the kernel alongside it, e.g. initrd
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Module {
+    /// Null-padded name of the file
+    pub name: [u8; 32],
+    pub kind: ModuleKind,
+    pub data: PhysSlice<u8>,
+}
+
+impl Module {
+    /// Names longer than 32 bytes are truncated, the kind is guessed
+    /// from the full name
+    pub fn new(name: &str, data: PhysSlice<u8>) -> Self {
+        let mut module = Self {
+            name: [0u8; 32],
+            kind: ModuleKind::from_name(name),
+            data,
+        };
+
+        let len = core::cmp::min(name.len(), module.name.len());
+        module.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        return module;
+    }
+
+    pub fn name(&self) -> &[u8] {
+        let len = self
+            .name
+            .iter()
+            .position(|&x| x == 0)
+            .unwrap_or(self.name.len());
+        &self.name[..len]
+    }
+}
+
+impl core::fmt::Debug for Module {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = core::str::from_utf8(self.name()).unwrap_or("<invalid utf-8>");
+        f.debug_struct("Module")
+            .field("name", &name)
+            .field("kind", &self.kind)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+/// Virtual address the kernel is linked at
+pub const KERNEL_BASE: u64 = 0xffff_ffff_c000_0000;
+
+/// Page permissions of a single kernel segment. Everything mapped is readable.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SegmentPerms {
+    pub writable: bool,
+    pub executable: bool,
+}
+
+impl SegmentPerms {
+    pub const R: Self = Self::new(false, false);
+    pub const RW: Self = Self::new(true, false);
+    pub const RX: Self = Self::new(false, true);
+    pub const RWX: Self = Self::new(true, true);
+
+    pub const fn new(writable: bool, executable: bool) -> Self {
+        Self {
+            writable,
+            executable,
+        }
+    }
+
+    pub const fn is_rwx(&self) -> bool {
+        self.writable && self.executable
+    }
+}
+
+/// Same as ELF segment flags, e.g. `R-X`
+impl core::fmt::Debug for SegmentPerms {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let w = if self.writable { 'W' } else { '-' };
+        let x = if self.executable { 'X' } else { '-' };
+        write!(f, "R{}{}", w, x)
+    }
+}
+
+/// Permissions `Bootinfo::map_kernel` uses for each kernel segment.
+///
+/// The default is W^X: text is R-X, rodata is R-- and data is RW-.
+/// A page that is both writable and executable turns any memory corruption
+/// bug into arbitrary code execution, so such segments are refused unless
+/// `allow_rwx` is set. Kernels that patch or relocate themselves should
+/// remap such segments as soon as they are done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KernelPermPolicy {
+    pub text: SegmentPerms,
+    pub rodata: SegmentPerms,
+    pub data: SegmentPerms,
+    pub allow_rwx: bool,
+}
+
+impl KernelPermPolicy {
+    pub const fn new() -> Self {
+        Self {
+            text: SegmentPerms::RX,
+            rodata: SegmentPerms::R,
+            data: SegmentPerms::RW,
+            allow_rwx: false,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), MapKernelError> {
+        let has_rwx = self.text.is_rwx() || self.rodata.is_rwx() || self.data.is_rwx();
+        if has_rwx && !self.allow_rwx {
+            return Err(MapKernelError::RwxNotAllowed);
+        }
+        return Ok(());
+    }
+}
+
+impl Default for KernelPermPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapKernelError {
+    /// Policy maps a segment writable and executable without `allow_rwx`
+    RwxNotAllowed,
+    /// Kernel doesn't fit in 1G
+    TooLarge,
+    /// Segment is not aligned to 2M
+    Misaligned,
+    /// `Bootinfo::init_this` wasn't called
+    Unpinned,
+    /// Something else is already mapped there
+    Conflict,
+}
+
+/// How long `Bootinfo::retrieve_and_exit` keeps retrying
+pub const EXIT_DEADLINE_US: u64 = 100_000;
+
+/// A point in time during boot, measured with the TSC
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct TimelineEvent {
+    /// Null-padded name of the event
+    pub name: [u8; 32],
+    pub tsc: u64,
+}
+
+impl TimelineEvent {
+    /// Names longer than 32 bytes are truncated
+    pub fn new(name: &str, tsc: u64) -> Self {
+        let mut event = Self {
+            name: [0u8; 32],
+            tsc,
+        };
+
+        let len = core::cmp::min(name.len(), event.name.len());
+        event.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        return event;
+    }
+
+    /// `what` with a byte count and the TSC cycles it took, like
+    /// `copy 2097152B 81234c`, truncated like `new`
+    pub fn counted(what: &str, bytes: u64, cycles: u64, tsc: u64) -> Self {
+        struct Name {
+            buf: [u8; 32],
+            len: usize,
+        }
+        impl core::fmt::Write for Name {
+            fn write_str(&mut self, s: &str) -> core::fmt::Result {
+                let len = core::cmp::min(s.len(), self.buf.len() - self.len);
+                self.buf[self.len..][..len].copy_from_slice(&s.as_bytes()[..len]);
+                self.len += len;
+                Ok(())
+            }
+        }
+
+        let mut name = Name {
+            buf: [0u8; 32],
+            len: 0,
+        };
+        let _ = core::fmt::Write::write_fmt(
+            &mut name,
+            format_args!("{} {}B {}c", what, bytes, cycles),
+        );
+        return Self { name: name.buf, tsc };
+    }
+
+    pub fn name(&self) -> &[u8] {
+        let len = self
+            .name
+            .iter()
+            .position(|&x| x == 0)
+            .unwrap_or(self.name.len());
+        &self.name[..len]
+    }
+}
+
+impl core::fmt::Debug for TimelineEvent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = core::str::from_utf8(self.name()).unwrap_or("<invalid utf-8>");
+        f.debug_struct("TimelineEvent")
+            .field("name", &name)
+            .field("tsc", &self.tsc)
+            .finish()
+    }
+}
+
+/// Size of `Bootinfo::buf` unless the loader and kernel agree on another,
+/// see `BUF_BUDGET` for what it has to hold
+pub const DEFAULT_BUF_SIZE: usize = 16384;
+
+/// Everything the loader hands to the kernel. `BUF` is the size of `buf`,
+/// the only part of the layout that can be configured. It is part of the
+/// ABI note, so a kernel built with a different size is refused, see
+/// `negotiate`.
 #[repr(C, align(4096))]
-pub struct Bootinfo {
-    pub paging_root: paging::Table<PML4Entry>,
+pub struct Bootinfo<const BUF: usize = DEFAULT_BUF_SIZE> {
+    pub paging_root: RootTable,
     pub pdp: paging::Table<PDPEntry>,
     pub pd: paging::Table<PDEntry>,
     pub page_table: paging::Table<PTEntry>,
 
-    pub idt: interrupt::Table,
-    pub gdt: GlobalDescriptorTable,
-
-    pub this: PhysAddr<Bootinfo>,
+    pub this: PhysAddr<Bootinfo<BUF>>,
     pub kernel_pslice: PhysSlice<u8>,
 
-    pub buf: [u8; 8192],
+    /// Backing memory of the `BootArena`, holds the IDT and GDT, see `BUF_BUDGET`
+    pub buf: [u8; BUF],
     pub uefi_meminfo: ArrayVec<uefi::memory::Descriptor, 192>,
+    /// Usable memory above MAXPHYADDR was marked unusable in `uefi_meminfo`
+    pub physical_memory_clamped: bool,
+    /// Free memory in `uefi_meminfo`, kept up to date with it
+    pub memory: MemorySummary,
+    /// Everything that isn't free, see `Bootinfo::merge_reserved`
+    pub reserved: ReservedTable<MAX_RESERVED>,
+    /// Ranges from `Bootinfo::reserve_region`, only merged into `reserved`
+    pub reserve_requests: ReservedTable<MAX_RESERVE_REQUESTS>,
+    pub modules: ArrayVec<Module, 8>,
+    pub timeline: ArrayVec<TimelineEvent, 32>,
+    /// How far the loader got, see `Bootinfo::advance`
+    pub stages: BootStages,
+    /// Which boot features were actually used
+    pub capabilities: BootCapabilities,
+    /// Tolerated conditions that came up, see `Bootinfo::tolerate`
+    pub conditions: Conditions,
+    /// Why the loader refused to boot, only set on the way to a panic
+    pub last_error: Option<BootError>,
+    /// Per-CPU areas for SMP bring-up, `PerCpuArea::null` if not reserved
+    pub percpu: PerCpuArea,
+    /// Stack the kernel is entered on, sized by `KernelRequirements`
+    pub kernel_stack: PhysRange,
+    /// What was found in ACPI, see `Bootinfo::read_acpi`
+    pub acpi: AcpiInfo,
+    /// What was agreed on with the kernel's ABI note
+    pub abi: AbiContract,
+    /// Microcode update of the BSP
+    pub microcode: MicrocodeStatus,
+    /// What the loader did to CR4, EFER and IA32_MISC_ENABLE
+    pub mitigations: MitigationRecord,
+    /// Flags the kernel was mapped with, without NX if it's unavailable
+    pub page_flags: PageFlags,
+    /// Interrupt cleanup done right before ExitBootServices
+    pub pre_exit: PreExitReport,
+    /// Mixed from every healthy entropy source, for KASLR and the boot ID
+    pub seed: [u8; 32],
+    pub entropy: EntropyStatus,
+    /// Boot ID, and where it came from if this kernel was kexec'd
+    pub lineage: BootLineage,
+    /// Firmware components updatable by capsule, from the ESRT
+    pub esrt: ArrayVec<FirmwareResource, MAX_FIRMWARE_RESOURCES>,
+    /// ESRT entries that didn't fit into `esrt`
+    pub esrt_dropped: u32,
+    /// `dma_ceiling=` from the config, 0 without one
+    pub dma_ceiling: u64,
+    /// What early devices may DMA from, see `Bootinfo::tag_dma`
+    pub dma_regions: ArrayVec<DmaRegion, MAX_DMA_REGIONS>,
+    /// Where the kernel writes a crash dump and whether it holds one
+    pub crash_dump: CrashDumpRegion,
     pub uefi_systable: *mut uefi::SystemTable,
+    pub uefi_revision: uefi::Revision,
+    /// Kept for kernels that predate `serial_sinks`
+    #[cfg(target_arch = "x86_64")]
     pub serial: Option<SerialPort>,
+    /// Ports the boot log was mirrored to and which one is the console
+    pub serial_sinks: SerialSinks,
+    /// Set by `BootinfoBuilder::finish`, see `Bootinfo::compute_checksum`
+    pub checksum: [u8; 32],
+    /// Page tables and `buf` are referenced by physical address
+    _pinned: PhantomPinned,
 }
 
 impl Bootinfo {
     pub const fn new() -> Self {
-        const IDT_ENTRY: interrupt::Entry = interrupt::Entry::new();
+        Self::with_buf()
+    }
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /* Evaluated when `with_buf` is instantiated, fails the build */
+    const BUDGET_FITS: () = assert!(
+        budget_size(&BUF_BUDGET) <= BUF,
+        "Bootinfo::buf can't hold everything in BUF_BUDGET"
+    );
+
+    /// Size of `buf`
+    pub const BUF_SIZE: usize = BUF;
+
+    /// `Bootinfo::new` with a non-default `buf`
+    pub const fn with_buf() -> Self {
+        let () = Self::BUDGET_FITS;
         Self {
-            paging_root: paging::Table::new(),
+            paging_root: Paging::EMPTY_ROOT,
             pdp: paging::Table::new(),
             pd: paging::Table::new(),
             page_table: paging::Table::new(),
 
-            idt: [IDT_ENTRY; 256],
-            gdt: GlobalDescriptorTable::new(),
-
             this: PhysAddr::null(),
             kernel_pslice: PhysSlice::null(),
 
-            buf: [0u8; 8192],
+            buf: [0u8; BUF],
             uefi_meminfo: ArrayVec::new_const(),
+            physical_memory_clamped: false,
+            memory: MemorySummary::new(),
+            reserved: ReservedTable::new(),
+            reserve_requests: ReservedTable::new(),
+            modules: ArrayVec::new_const(),
+            timeline: ArrayVec::new_const(),
+            stages: BootStages::new(),
+            capabilities: BootCapabilities::new(),
+            conditions: Conditions::new(),
+            last_error: None,
+            percpu: PerCpuArea::null(),
+            kernel_stack: PhysRange::empty(),
+            acpi: AcpiInfo::new(),
+            abi: AbiContract::legacy(),
+            microcode: MicrocodeStatus::new(),
+            mitigations: MitigationRecord::new(),
+            page_flags: PageFlags::WITH_NX,
+            pre_exit: PreExitReport::new(),
+            seed: [0u8; 32],
+            entropy: EntropyStatus::new(),
+            lineage: BootLineage::new(),
+            esrt: ArrayVec::new_const(),
+            esrt_dropped: 0,
+            dma_ceiling: 0,
+            dma_regions: ArrayVec::new_const(),
+            crash_dump: CrashDumpRegion::new(),
             uefi_systable: core::ptr::null_mut(),
+            uefi_revision: uefi::Revision::new(0, 0),
+            #[cfg(target_arch = "x86_64")]
             serial: None,
+            serial_sinks: SerialSinks::new(),
+            checksum: [0u8; 32],
+            _pinned: PhantomPinned,
         }
     }
 
-    /*
+    /// Retrieves the memory map into `memmap` and exits boot services.
+    ///
+    /// GetMemoryMap returns a key of the current map and ExitBootServices
+    /// fails with `Error::InvalidParameter` if the map has changed since then,
+    /// which firmware may do on its own at any time (e.g. in timer events).
+    /// After a failed ExitBootServices only GetMemoryMap may be called,
+    /// so the map is retrieved again into the same buffer and exit retried
+    /// until `EXIT_DEADLINE_US` passes. Every retry and every GetMemoryMap
+    /// call, with the cycles it took, is recorded in `timeline`.
+    ///
+    /// Before the first attempt interrupts are disabled and every source
+    /// in `sources` disarmed, see `quiesce_interrupts`. What that took is
+    /// kept in `pre_exit` and marked in `timeline`.
+    ///
+    /// On success the final map is copied into `uefi_meminfo` (descriptors
+    /// that don't fit are dropped and `memory_map_truncated` recorded) and
+    /// summarized in `memory`, `uefi_systable` is set and the EFI serial
+    /// fallback is dropped from `serial_sinks`.
+    ///
     /// # Safety
-    /// * Technically this struct is self-referential,
-    /// so we should use Pin, but for simplicity sake we don't.
-    /// * Memory must be identity-mapped.
-    /// * Kernel base must be 0xffff_ffff_c000_0000.
-    pub unsafe fn map_kernel(
+    /// * `st` and `image` must be the ones given to `efi_main`.
+    /// * Boot services must not be used afterwards.
+    /// * Devices behind `sources` must still be there.
+    pub unsafe fn retrieve_and_exit(
         &mut self,
+        st: &uefi::SystemTable,
+        image: &uefi::ImageHandle,
+        clock: &impl uefi::Clock,
+        memmap: &mut uefi::MemoryMapBuffer,
+        sources: &mut InterruptSources,
+        flag: &mut impl InterruptFlag,
+    ) -> Result<(), uefi::Error> {
+        self.pre_exit = quiesce_interrupts(sources, flag);
+        if self.pre_exit.interrupts_disabled {
+            self.mark("interrupts disabled before exit");
+        }
+        if self.pre_exit.sources_disarmed != 0 {
+            self.mark("interrupt sources disarmed");
+        }
+
+        let policy = uefi::RetryPolicy {
+            deadline_us: EXIT_DEADLINE_US,
+            backoff: uefi::Backoff::None,
+        };
+
+        let boot_services = &*st.boot_services.get();
+        let timeline = &mut self.timeline;
+        let mut first = true;
+
+        let result = uefi::retry_with(
+            clock,
+            policy,
+            |e| e == uefi::Error::InvalidParameter,
+            /* Marked by the attempt itself, which also owns `timeline` */
+            |_, _| {},
+            || {
+                let retry = !first;
+                first = false;
+                if retry {
+                    let _ =
+                        timeline.try_push(TimelineEvent::new("exit boot services retry", timestamp()));
+                }
+
+                let on_call = |call: uefi::MapCall| {
+                    let what = if call.too_small { "memory map probe" } else { "memory map" };
+                    let event = TimelineEvent::counted(what, call.bytes as u64, call.cycles, timestamp());
+                    let _ = timeline.try_push(event);
+                };
+                let key = if retry {
+                    memmap.refetch(boot_services, on_call)?
+                } else {
+                    memmap.fetch(boot_services, on_call)?
+                };
+
+                /* Nothing is copied until the map is final */
+                return boot_services.exit_boot_services(image, key);
+            },
+        );
+
+        if let Err(e) = result {
+            return Err(e.status());
+        }
+
+        self.uefi_meminfo.clear();
+        let mut truncated = false;
+        for descriptor in memmap.descriptors() {
+            let descriptor = core::ptr::read(descriptor as *const _);
+            truncated |= self.uefi_meminfo.try_push(descriptor).is_err();
+        }
+        if truncated {
+            self.record(BootCapabilities::set_memory_map_truncated);
+        }
+        self.summarize_memory();
+        self.uefi_systable = st as *const _ as *mut _;
+        self.serial_sinks.exit_boot_services();
+
+        return Ok(());
+    }
+
+    /// # Safety
+    /// * Must be called only once and `buf` must not be accessed
+    /// directly afterwards.
+    /// * `self` must live for the rest of the program, e.g. be a static.
+    pub unsafe fn arena(self: Pin<&mut Self>) -> BootArena {
+        let buf = &mut self.get_unchecked_mut().buf;
+        BootArena::from_raw(buf.as_mut_ptr(), buf.len())
+    }
+
+    /// Appends an event to the boot timeline, silently dropped when it is full
+    pub fn mark(&mut self, name: &str) {
+        let _ = self
+            .timeline
+            .try_push(TimelineEvent::new(name, timestamp()));
+    }
+
+    /// Appends what `cpu::phys` copied and zeroed so far, with the cycles
+    /// spent, as two events of the boot timeline
+    pub fn mark_phys_counters(&mut self) {
+        use core::sync::atomic::Ordering::Relaxed;
+        let counters = &cpu::phys::COUNTERS;
+        let now = timestamp();
+        let events = [
+            TimelineEvent::counted(
+                "copy",
+                counters.bytes_copied.load(Relaxed),
+                counters.copy_cycles.load(Relaxed),
+                now,
+            ),
+            TimelineEvent::counted(
+                "zero",
+                counters.bytes_zeroed.load(Relaxed),
+                counters.zero_cycles.load(Relaxed),
+                now,
+            ),
+        ];
+        for event in events.iter() {
+            let _ = self.timeline.try_push(*event);
+        }
+    }
+
+    /// Wall clock time read from the firmware through `uefi_systable`,
+    /// usable as initial time source before the kernel has an RTC driver
+    pub fn boot_time(&self) -> Option<uefi::Time> {
+        if self.uefi_systable.is_null() {
+            return None;
+        }
+
+        unsafe { (*self.uefi_systable).get_time() }
+    }
+
+    /// Translates `virt` using the page tables rooted at `paging_root`.
+    ///
+    /// # Safety
+    /// Memory must be identity-mapped, entries are followed as physical
+    /// addresses and read through them.
+    pub unsafe fn translate(&self, virt: u64) -> Option<PhysAddr> {
+        Paging::translate(&self.paging_root, VirtAddr::new(virt))
+    }
+
+    /// Returns physical address of `virt` if the whole `virt..virt+len` range
+    /// is backed by physically contiguous memory, e.g. before handing the
+    /// region to a device. Returns `None` if any page is unmapped or if
+    /// there is a gap.
+    ///
+    /// # Safety
+    /// Same as `translate`.
+    pub unsafe fn is_phys_contiguous(&self, virt: u64, len: u64) -> Option<PhysAddr<u8>> {
+        let base = self.translate(virt)?;
+        let range = VirtRange::new(virt, len)?;
+
+        let first_page = virt & !(paging::PAGE_SIZE - 1);
+        let first_phys = base.as_u64() & !(paging::PAGE_SIZE - 1);
+
+        for page in range.pages::<Page>() {
+            let phys = self.translate(page.as_u64())?;
+            if phys.as_u64() != first_phys + (page.as_u64() - first_page) {
+                return None;
+            }
+        }
+
+        return Some(base.cast());
+    }
+
+    /// Maps kernel segments one after another at `KERNEL_BASE`,
+    /// with permissions taken from `policy`. Lengths of the slices are
+    /// in megapages. Segments use `granularity` if their alignment allows
+    /// it, see `map_range`.
+    ///
+    /// # Safety
+    /// Memory must be identity-mapped.
+    pub unsafe fn map_kernel(
+        mut self: Pin<&mut Self>,
         text: PhysSlice<Megapage>,
         rodata: PhysSlice<Megapage>,
         data: PhysSlice<Megapage>,
-    ) {
-        let base = 0xffff_ffff_c000_0000u64;
-        /* What we want to do here is to map kernel with 2M pages and bootinfo
-         * with normal 4K pages.
-         * It is assumed that by this time memory is identity mapped (so that
-         * remapping `self` is possible */
+        policy: &KernelPermPolicy,
+        granularity: MapGranularity,
+    ) -> Result<[MappedRegion; 3], MapKernelError> {
+        policy.validate()?;
+
+        let segments = [
+            (text, policy.text),
+            (rodata, policy.rodata),
+            (data, policy.data),
+        ];
+
+        let total = text.len() + rodata.len() + data.len();
+        if total > paging::ENTRIES_PER_TABLE {
+            return Err(MapKernelError::TooLarge);
+        }
+        let mut ranges = [PhysRange::empty(); 3];
+        for (range, &(segment, _)) in ranges.iter_mut().zip(&segments) {
+            let len = segment.len() as u64 * paging::MEGAPAGE_SIZE;
+            *range =
+                PhysRange::new(segment.addr().as_u64(), len).ok_or(MapKernelError::TooLarge)?;
+            if !range.is_aligned::<Megapage>() {
+                return Err(MapKernelError::Misaligned);
+            }
+        }
+        if self.as_ref().table_phys(OwnedTable::Pml4).is_none() {
+            return Err(MapKernelError::Unpinned);
+        }
+
+        let mut regions = [MappedRegion {
+            virt: VirtRange::empty(),
+            phys: PhysRange::empty(),
+            granularity,
+        }; 3];
+        let mut virt = KERNEL_BASE;
+        for (i, (range, &(_, perms))) in ranges.iter().zip(&segments).enumerate() {
+            regions[i] = self.as_mut().map_range(virt, *range, granularity, perms)?;
+            virt += range.len();
+        }
+
+        return Ok(regions);
     }
 
+    /*
     /// # Safety
     /// * `entry` must be a valid function pointer, that can be
     /// called as page fault handler.
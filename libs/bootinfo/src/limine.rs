@@ -0,0 +1,279 @@
+//! Answers to the most common Limine boot protocol requests, so kernels
+//! written for Limine boot without a port, as long as they fit the rest
+//! of this loader: linked at `KERNEL_BASE` and fine with memory being
+//! identity mapped, the HHDM offset is always 0.
+//!
+//! A request is two common magic words, two words telling which request
+//! it is, a revision and a pointer the loader points at its response. The
+//! kernel can put them anywhere, so the loaded image is scanned at 8 byte
+//! boundaries. Responses are written to `Responses`, memory the loader
+//! allocated and leaves as bootloader reclaimable.
+
+use crate::Module;
+use cpu::PhysRange;
+use uefi::memory::{Descriptor, Type};
+
+pub const COMMON_MAGIC: [u64; 2] = [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b];
+
+/// Requests before the response pointer, in bytes
+const RESPONSE_OFFSET: usize = 5 * 8;
+const REQUEST_SIZE: usize = RESPONSE_OFFSET + 8;
+
+/// Requests this loader knows the ID of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestKind {
+    Memmap,
+    /// Known, but without GOP support it's never answered
+    Framebuffer,
+    Rsdp,
+    Module,
+    Hhdm,
+    BootTime,
+}
+
+impl RequestKind {
+    pub const ALL: [Self; 6] = [
+        Self::Memmap,
+        Self::Framebuffer,
+        Self::Rsdp,
+        Self::Module,
+        Self::Hhdm,
+        Self::BootTime,
+    ];
+
+    /// The two words after `COMMON_MAGIC`
+    pub const fn id(self) -> [u64; 2] {
+        match self {
+            Self::Memmap => [0x67cf3d9d378a806f, 0xe304acdfc50c3c62],
+            Self::Framebuffer => [0x9d5827dcd881dd75, 0xa3148604f6fab11b],
+            Self::Rsdp => [0xc5e77b6b397e7b43, 0x27637845accdcf3c],
+            Self::Module => [0x3e7e279702be32af, 0xca1c4f3bd1280cee],
+            Self::Hhdm => [0x48dcf1cb8ad2b852, 0x63984e959a98244b],
+            Self::BootTime => [0x502746e184c088aa, 0xfbc5ec83e6327893],
+        }
+    }
+
+    pub fn from_id(id: [u64; 2]) -> Option<Self> {
+        Self::ALL.iter().copied().find(|x| x.id() == id)
+    }
+}
+
+/// A request found in the kernel image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    /// From the start of the image
+    pub offset: usize,
+    pub id: [u64; 2],
+    pub revision: u64,
+}
+
+impl Request {
+    /// `None` for requests this loader doesn't know
+    pub fn kind(&self) -> Option<RequestKind> {
+        RequestKind::from_id(self.id)
+    }
+
+    /// Points the request at `response`, a kernel address
+    pub fn set_response(&self, image: &mut [u8], response: u64) {
+        let at = self.offset + RESPONSE_OFFSET;
+        image[at..at + 8].copy_from_slice(&response.to_le_bytes());
+    }
+}
+
+fn word(bytes: &[u8], i: usize) -> u64 {
+    let mut x = [0u8; 8];
+    x.copy_from_slice(&bytes[8 * i..8 * i + 8]);
+    return u64::from_le_bytes(x);
+}
+
+fn request_at(image: &[u8], offset: usize) -> Option<Request> {
+    let bytes = image.get(offset..offset.checked_add(REQUEST_SIZE)?)?;
+    if [word(bytes, 0), word(bytes, 1)] != COMMON_MAGIC {
+        return None;
+    }
+
+    return Some(Request {
+        offset,
+        id: [word(bytes, 2), word(bytes, 3)],
+        revision: word(bytes, 4),
+    });
+}
+
+/// Requests in the loaded kernel image, in the order they appear
+pub fn requests(image: &[u8]) -> impl Iterator<Item = Request> + '_ {
+    (0..image.len() / 8).filter_map(move |i| request_at(image, 8 * i))
+}
+
+/// Points every request in `image` `respond` has a response for at it,
+/// returns how many were answered
+pub fn answer_requests(image: &mut [u8], mut respond: impl FnMut(&Request) -> Option<u64>) -> usize {
+    let mut answered = 0;
+    for i in 0..image.len() / 8 {
+        let request = match request_at(image, 8 * i) {
+            Some(x) => x,
+            None => continue,
+        };
+        if let Some(response) = respond(&request) {
+            request.set_response(image, response);
+            answered += 1;
+        }
+    }
+    return answered;
+}
+
+/// LIMINE_MEMMAP_*
+pub mod memmap {
+    pub const USABLE: u64 = 0;
+    pub const RESERVED: u64 = 1;
+    pub const ACPI_RECLAIMABLE: u64 = 2;
+    pub const ACPI_NVS: u64 = 3;
+    pub const BAD_MEMORY: u64 = 4;
+    pub const BOOTLOADER_RECLAIMABLE: u64 = 5;
+    pub const KERNEL_AND_MODULES: u64 = 6;
+}
+
+/// Limine memory map type of `descriptor`, the kernel's own memory is
+/// the descriptors inside `kernel`
+pub fn memmap_type(descriptor: &Descriptor, kernel: PhysRange) -> u64 {
+    let inside = descriptor
+        .phys_range()
+        .map_or(false, |r| kernel.start() <= r.start() && r.end() <= kernel.end());
+    if inside {
+        return memmap::KERNEL_AND_MODULES;
+    }
+
+    return match Type::from_int(descriptor.typ) {
+        /* Boot services are gone by the time the kernel runs */
+        Some(Type::Conventional | Type::BootServicesCode | Type::BootServicesData) => memmap::USABLE,
+        Some(Type::LoaderCode | Type::LoaderData) => memmap::BOOTLOADER_RECLAIMABLE,
+        Some(Type::AcpiReclaim) => memmap::ACPI_RECLAIMABLE,
+        Some(Type::AcpiNVS) => memmap::ACPI_NVS,
+        Some(Type::Unusable) => memmap::BAD_MEMORY,
+        _ => memmap::RESERVED,
+    };
+}
+
+/// Seconds since 1970 in UTC of a firmware timestamp, one with an
+/// unspecified time zone is taken as UTC
+pub fn unix_time(time: &uefi::Time) -> i64 {
+    /* Days from civil, counting years from March so leap days come last */
+    let year = time.year as i64 - (time.month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = (time.month as i64 + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + time.day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let mut seconds = days * 86400 + time.hour as i64 * 3600 + time.minute as i64 * 60 + time.second as i64;
+    if time.time_zone != uefi::Time::UNSPECIFIED_TIMEZONE {
+        /* Local time is UTC plus the offset, like in ISO 8601 */
+        seconds -= time.time_zone as i64 * 60;
+    }
+    return seconds;
+}
+
+/// Bytes of a `struct limine_file` with everything but the location and
+/// path zeroed, which says the file wasn't loaded from a partition
+const FILE_SIZE: usize = 112;
+
+/// Memory responses are written to, `buf` is what the kernel sees at
+/// `base`. Every response is 8 byte aligned and the functions return
+/// the kernel address of it, `None` if `buf` is full.
+pub struct Responses<'a> {
+    buf: &'a mut [u8],
+    base: u64,
+    used: usize,
+}
+
+impl<'a> Responses<'a> {
+    pub fn new(buf: &'a mut [u8], base: u64) -> Self {
+        Self { buf, base, used: 0 }
+    }
+
+    /// Bytes taken so far
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Reserves `len` bytes, returns where they start in `buf`
+    fn reserve(&mut self, len: usize) -> Option<usize> {
+        let start = self.used.checked_add(7)? & !7;
+        let end = start.checked_add(len)?;
+        if end > self.buf.len() {
+            return None;
+        }
+
+        self.buf[start..end].fill(0);
+        self.used = end;
+        return Some(start);
+    }
+
+    fn addr(&self, at: usize) -> u64 {
+        self.base + at as u64
+    }
+
+    fn set(&mut self, at: usize, words: &[u64]) {
+        for (i, w) in words.iter().enumerate() {
+            self.buf[at + 8 * i..at + 8 * i + 8].copy_from_slice(&w.to_le_bytes());
+        }
+    }
+
+    /// A response made of `words`, starting with the revision
+    fn words(&mut self, words: &[u64]) -> Option<u64> {
+        let at = self.reserve(8 * words.len())?;
+        self.set(at, words);
+        return Some(self.addr(at));
+    }
+
+    /// A NUL terminated copy of `s`
+    fn string(&mut self, s: &[u8]) -> Option<u64> {
+        let at = self.reserve(s.len() + 1)?;
+        self.buf[at..at + s.len()].copy_from_slice(s);
+        return Some(self.addr(at));
+    }
+
+    pub fn hhdm(&mut self, offset: u64) -> Option<u64> {
+        self.words(&[0, offset])
+    }
+
+    pub fn rsdp(&mut self, addr: u64) -> Option<u64> {
+        self.words(&[0, addr])
+    }
+
+    pub fn boot_time(&mut self, unix_time: i64) -> Option<u64> {
+        self.words(&[0, unix_time as u64])
+    }
+
+    /// One entry per descriptor, typed by `memmap_type`
+    pub fn memmap(&mut self, meminfo: &[Descriptor], kernel: PhysRange) -> Option<u64> {
+        let entries = self.reserve(24 * meminfo.len())?;
+        let pointers = self.reserve(8 * meminfo.len())?;
+        for (i, d) in meminfo.iter().enumerate() {
+            let entry = entries + 24 * i;
+            self.set(entry, &[d.phys_start, d.pages * 4096, memmap_type(d, kernel)]);
+            let addr = self.addr(entry);
+            self.set(pointers + 8 * i, &[addr]);
+        }
+
+        let pointers = self.addr(pointers);
+        return self.words(&[0, meminfo.len() as u64, pointers]);
+    }
+
+    /// Modules with their names as paths and empty command lines
+    pub fn modules(&mut self, modules: &[Module]) -> Option<u64> {
+        let pointers = self.reserve(8 * modules.len())?;
+        let empty = self.string(b"")?;
+        for (i, module) in modules.iter().enumerate() {
+            let path = self.string(module.name())?;
+            let file = self.reserve(FILE_SIZE)?;
+            let data = &module.data;
+            self.set(file, &[0, data.addr().as_u64(), data.len() as u64, path, empty]);
+            let addr = self.addr(file);
+            self.set(pointers + 8 * i, &[addr]);
+        }
+
+        let pointers = self.addr(pointers);
+        return self.words(&[0, modules.len() as u64, pointers]);
+    }
+}
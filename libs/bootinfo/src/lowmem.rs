@@ -0,0 +1,297 @@
+//! Low memory hygiene before handoff. Firmware identity-maps page 0, so
+//! a null pointer dereference in the kernel reads BIOS data area garbage
+//! instead of faulting. The tables the kernel inherits get the null guard
+//! unmapped, and free memory below 1MiB is filled with a poison pattern
+//! so stale low pointers stand out in dumps.
+//!
+//! Pages that have to stay, like the real-mode AP trampoline, are put on
+//! a `LowMemWhitelist` and neither unmapped nor poisoned.
+
+use crate::{Bootinfo, OwnedTable};
+use arrayvec::ArrayVec;
+use core::pin::Pin;
+use cpu::paging::{Bits, Entry, PTEntry, PAGE_SIZE};
+use cpu::phys::{PhysMapping, PhysWriter};
+use cpu::{PhysAddr, PhysRange};
+use uefi::memory::{Descriptor, Type};
+
+/// Null page only
+pub const NULL_GUARD_SIZE: u64 = 0x1000;
+/// Also catches large offsets from null, e.g. arrays behind a null pointer
+pub const WIDE_NULL_GUARD_SIZE: u64 = 0x1_0000;
+/// Everything below is real-mode memory
+pub const LOW_MEMORY_END: u64 = 0x10_0000;
+pub const DEFAULT_POISON: u16 = 0xDEAD;
+
+/* Bit 7 in PDP and PD entries, gigapage or megapage */
+const LEAF: u64 = 1 << 7;
+const PRESENT: u64 = 1 << 0;
+const ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+/* What a table entry pointing to a split page keeps: P, RW, US and NX */
+const TABLE_ATTRS: u64 = 0b111 | (1 << 63);
+/* Leaf attributes in the same place at every level: P, RW, US, PWT, PCD,
+ * A, D, G, protection key and NX */
+const LEAF_ATTRS: u64 = 0x17F | (0xF << 59) | (1 << 63);
+/* PAT is bit 12 in large pages and bit 7 in 4K ones */
+const LARGE_PAT: u64 = 1 << 12;
+const PAGE_PAT: u64 = 1 << 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LowMemError {
+    /// Whitelisted page is at or above `LOW_MEMORY_END`
+    NotLow,
+    TooManyPages,
+    /// Guard isn't a multiple of the page size or reaches `LOW_MEMORY_END`
+    BadGuard,
+    /// Null guard is mapped by a megapage or gigapage, which can't be split
+    /// with the single page table `Bootinfo` has
+    LargePage,
+    /// Tables covering low memory aren't `Bootinfo`'s own
+    ForeignTable,
+    Unpinned,
+    /// Spare tables for `unmap_live_null_guard` are missing or misaligned
+    NoSpareTables,
+}
+
+/// Low pages that must stay mapped and untouched
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LowMemWhitelist {
+    pages: ArrayVec<u64, 4>,
+}
+
+impl LowMemWhitelist {
+    pub const fn new() -> Self {
+        Self {
+            pages: ArrayVec::new_const(),
+        }
+    }
+
+    /// Keeps the page containing `addr`
+    pub fn keep(&mut self, addr: u64) -> Result<(), LowMemError> {
+        if addr >= LOW_MEMORY_END {
+            return Err(LowMemError::NotLow);
+        }
+        let page = addr & !(PAGE_SIZE - 1);
+        if self.contains(page) {
+            return Ok(());
+        }
+        return self
+            .pages
+            .try_push(page)
+            .map_err(|_| LowMemError::TooManyPages);
+    }
+
+    /// Whether the page containing `addr` is kept
+    pub fn contains(&self, addr: u64) -> bool {
+        let page = addr & !(PAGE_SIZE - 1);
+        self.pages.contains(&page)
+    }
+
+    pub fn pages(&self) -> &[u64] {
+        &self.pages
+    }
+}
+
+/// Fills every page of conventional memory in `guard..LOW_MEMORY_END` with
+/// `pattern`, except whitelisted ones. The null guard itself is left alone,
+/// it's unmapped anyway and the BDA there helps with debugging. Runs of
+/// pages between whitelisted ones are filled with one write each.
+/// Returns the number of bytes poisoned.
+///
+/// # Safety
+/// Conventional memory in `meminfo` must not be in use and must be
+/// allowed by `writer`.
+pub unsafe fn poison_low_memory(
+    meminfo: &[Descriptor],
+    guard: u64,
+    whitelist: &LowMemWhitelist,
+    pattern: u16,
+    writer: &mut PhysWriter<impl PhysMapping>,
+) -> u64 {
+    let mut poisoned = 0;
+    let mut fill = |start: u64, end: u64| {
+        if start < end {
+            let words = ((end - start) / 2) as usize;
+            writer.fill("low memory poison", start, pattern, words);
+            poisoned += end - start;
+        }
+    };
+
+    for descriptor in meminfo {
+        if descriptor.memory_type() != Some(Type::Conventional) {
+            continue;
+        }
+
+        let start = descriptor.phys_start.max(guard);
+        let end = descriptor
+            .phys_start
+            .saturating_add(descriptor.pages.saturating_mul(PAGE_SIZE))
+            .min(LOW_MEMORY_END);
+
+        let mut run = start;
+        let mut page = start;
+        while page < end {
+            if whitelist.contains(page) {
+                fill(run, page);
+                run = page + PAGE_SIZE;
+            }
+            page += PAGE_SIZE;
+        }
+        fill(run, end.max(run));
+    }
+
+    return poisoned;
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Unmaps every page in `0..guard` from the tables the kernel inherits,
+    /// except whitelisted ones. Returns the number of pages unmapped,
+    /// an unmapped guard is left as is.
+    ///
+    /// # Safety
+    /// Memory must be identity-mapped.
+    pub unsafe fn unmap_null_guard(
+        self: Pin<&mut Self>,
+        guard: u64,
+        whitelist: &LowMemWhitelist,
+    ) -> Result<usize, LowMemError> {
+        if guard % PAGE_SIZE != 0 || guard >= LOW_MEMORY_END {
+            return Err(LowMemError::BadGuard);
+        }
+
+        let tables = [OwnedTable::Pdp, OwnedTable::Pd, OwnedTable::Pt];
+        let mut phys_of = [PhysAddr::null(); 3];
+        for (addr, &table) in phys_of.iter_mut().zip(&tables) {
+            *addr = self
+                .as_ref()
+                .table_phys(table)
+                .ok_or(LowMemError::Unpinned)?;
+        }
+        let [pdp, pd, pt] = phys_of;
+        let this = self.get_unchecked_mut();
+
+        /* Low memory is covered by the first entry of every level */
+        if !this.paging_root[0].is_present() {
+            return Ok(0);
+        }
+        if this.paging_root[0].raw_addr().as_u64() != pdp.as_u64() {
+            return Err(LowMemError::ForeignTable);
+        }
+        let pdpe = &this.pdp[0];
+        if !pdpe.is_present() {
+            return Ok(0);
+        }
+        if pdpe.as_u64() & LEAF != 0 {
+            return Err(LowMemError::LargePage);
+        }
+        if pdpe.raw_addr().as_u64() != pd.as_u64() {
+            return Err(LowMemError::ForeignTable);
+        }
+        let pde = &this.pd[0];
+        if !pde.is_present() {
+            return Ok(0);
+        }
+        if pde.as_u64() & LEAF != 0 {
+            return Err(LowMemError::LargePage);
+        }
+        if pde.raw_addr().as_u64() != pt.as_u64() {
+            return Err(LowMemError::ForeignTable);
+        }
+
+        let mut unmapped = 0;
+        for index in 0..(guard / PAGE_SIZE) as usize {
+            let page = index as u64 * PAGE_SIZE;
+            if whitelist.contains(page) || !this.page_table[index].is_present() {
+                continue;
+            }
+            this.page_table[index] = PTEntry::from_u64_unchecked(0);
+            unmapped += 1;
+        }
+
+        return Ok(unmapped);
+    }
+}
+
+/// Replaces the large page `entry` maps with the table at `table`, of
+/// 512 pages `size / 512` bytes each with the same attributes
+unsafe fn split(
+    entry: &mut u64,
+    table: u64,
+    size: u64,
+    leaf: u64,
+    pat: u64,
+    mapping: &impl PhysMapping,
+) {
+    let base = *entry & ADDR_MASK & !(size - 1);
+    let mut attrs = (*entry & LEAF_ATTRS) | leaf;
+    if *entry & LARGE_PAT != 0 {
+        attrs |= pat;
+    }
+    let step = size / 512;
+    for (i, x) in table_at(table, mapping).iter_mut().enumerate() {
+        *x = (base + i as u64 * step) | attrs;
+    }
+    *entry = table | (*entry & TABLE_ATTRS);
+}
+
+unsafe fn table_at<'a>(entry: u64, mapping: &impl PhysMapping) -> &'a mut [u64; 512] {
+    let addr = PhysAddr::<[u64; 512]>::new_unchecked(entry & ADDR_MASK);
+    &mut *mapping.phys_to_virt(addr).as_ptr_mut()
+}
+
+/// `Bootinfo::unmap_null_guard` for the tables at `root`, the ones that
+/// are live, like firmware's after ExitBootServices. A megapage or gigapage
+/// covering the guard is split using `spare`, two pages for a page
+/// directory and a page table, identity-mapped like the tables. Returns
+/// the number of pages unmapped, the caller has to flush the TLB.
+///
+/// # Safety
+/// `root` must be the top of a valid 4-level hierarchy, accessible and
+/// writable through `mapping`, and `spare` unused memory.
+pub unsafe fn unmap_live_null_guard(
+    root: PhysAddr<u64>,
+    guard: u64,
+    whitelist: &LowMemWhitelist,
+    spare: PhysRange,
+    mapping: &impl PhysMapping,
+) -> Result<usize, LowMemError> {
+    if guard % PAGE_SIZE != 0 || guard >= LOW_MEMORY_END {
+        return Err(LowMemError::BadGuard);
+    }
+    if spare.start() % PAGE_SIZE != 0 || spare.len() < 2 * PAGE_SIZE {
+        return Err(LowMemError::NoSpareTables);
+    }
+
+    /* Low memory is covered by the first entry of every level */
+    let pml4 = table_at(root.as_u64(), mapping);
+    if pml4[0] & PRESENT == 0 {
+        return Ok(0);
+    }
+    let pdp = table_at(pml4[0], mapping);
+    if pdp[0] & PRESENT == 0 {
+        return Ok(0);
+    }
+    if pdp[0] & LEAF != 0 {
+        split(&mut pdp[0], spare.start(), 1 << 30, LEAF, LARGE_PAT, mapping);
+    }
+    let pd = table_at(pdp[0], mapping);
+    if pd[0] & PRESENT == 0 {
+        return Ok(0);
+    }
+    if pd[0] & LEAF != 0 {
+        split(&mut pd[0], spare.start() + PAGE_SIZE, 1 << 21, 0, PAGE_PAT, mapping);
+    }
+    let pt = table_at(pd[0], mapping);
+
+    let mut unmapped = 0;
+    for index in 0..(guard / PAGE_SIZE) as usize {
+        let page = index as u64 * PAGE_SIZE;
+        if whitelist.contains(page) || pt[index] & PRESENT == 0 {
+            continue;
+        }
+        pt[index] = 0;
+        unmapped += 1;
+    }
+
+    return Ok(unmapped);
+}
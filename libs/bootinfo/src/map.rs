@@ -0,0 +1,189 @@
+use crate::{Bootinfo, MapKernelError, OwnedTable, SegmentPerms};
+use core::pin::Pin;
+use cpu::paging::{self, Bits, Entry, PDEntry, PDFlags, PDPEntry, PDPFlags, PML4Entry};
+use cpu::paging::{PML4Flags, PTEntry};
+use cpu::{PhysAddr, PhysRange, VirtRange};
+
+/// Page size a region is mapped with, trading TLB footprint for table space.
+///
+/// Virtual start, physical start and length must all be multiples of the
+/// page size. As `Bootinfo` has a single table of each level, the whole
+/// region must also be covered by a single table:
+/// * `Page` (4KiB) - one 2MiB aligned region
+/// * `Megapage` (2MiB) - one 1GiB aligned region
+/// * `Gigapage` (1GiB) - one 512GiB aligned region
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MapGranularity {
+    Page,
+    Megapage,
+    Gigapage,
+}
+
+impl MapGranularity {
+    pub const fn size(&self) -> u64 {
+        match self {
+            MapGranularity::Page => paging::PAGE_SIZE,
+            MapGranularity::Megapage => paging::MEGAPAGE_SIZE,
+            MapGranularity::Gigapage => paging::GIGAPAGE_SIZE,
+        }
+    }
+
+    const fn smaller(&self) -> Option<Self> {
+        match self {
+            MapGranularity::Page => None,
+            MapGranularity::Megapage => Some(MapGranularity::Page),
+            MapGranularity::Gigapage => Some(MapGranularity::Megapage),
+        }
+    }
+
+    /// Level of the table holding leaf entries, 0 is the page table
+    const fn leaf_level(&self) -> u32 {
+        match self {
+            MapGranularity::Page => 0,
+            MapGranularity::Megapage => 1,
+            MapGranularity::Gigapage => 2,
+        }
+    }
+
+    /// The largest granularity, up to `self`, that `virt`, `phys`
+    /// and `len` are all aligned to
+    pub fn fit(self, virt: u64, phys: u64, len: u64) -> Option<Self> {
+        let mut granularity = self;
+        loop {
+            let size = granularity.size();
+            if virt % size == 0 && phys % size == 0 && len % size == 0 {
+                return Some(granularity);
+            }
+            granularity = granularity.smaller()?;
+        }
+    }
+}
+
+/// What `Bootinfo::map_range` actually did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MappedRegion {
+    pub virt: VirtRange,
+    pub phys: PhysRange,
+    pub granularity: MapGranularity,
+}
+
+const fn table_index(virt: u64, level: u32) -> usize {
+    ((virt >> (12 + 9 * level)) as usize) % paging::ENTRIES_PER_TABLE
+}
+
+/* Bit 7 in PDP and PD entries, gigapage or megapage */
+const LEAF: u64 = 1 << 7;
+
+/// Whether a non-leaf entry is free, or already points to `table`
+fn links_to<E: Entry>(entry: &E, table: PhysAddr) -> Result<bool, MapKernelError> {
+    if !entry.is_present() {
+        return Ok(false);
+    }
+    if entry.as_u64() & LEAF != 0 || entry.raw_addr().as_u64() != table.as_u64() {
+        return Err(MapKernelError::Conflict);
+    }
+    return Ok(true);
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Maps `phys` at `virt` with `perms`, using `preferred` granularity
+    /// or a smaller one if alignment doesn't permit it.
+    /// Nothing is mapped on error.
+    ///
+    /// # Safety
+    /// Memory must be identity-mapped.
+    pub unsafe fn map_range(
+        self: Pin<&mut Self>,
+        virt: u64,
+        phys: PhysRange,
+        preferred: MapGranularity,
+        perms: SegmentPerms,
+    ) -> Result<MappedRegion, MapKernelError> {
+        let range = VirtRange::new(virt, phys.len()).ok_or(MapKernelError::TooLarge)?;
+        let granularity = preferred
+            .fit(virt, phys.start(), phys.len())
+            .ok_or(MapKernelError::Misaligned)?;
+        let region = MappedRegion {
+            virt: range,
+            phys,
+            granularity,
+        };
+        if range.is_empty() {
+            return Ok(region);
+        }
+
+        /* Only one table per level, so the region must fit in one */
+        let shift = 12 + 9 * (granularity.leaf_level() + 1);
+        if virt >> shift != (range.end() - 1) >> shift {
+            return Err(MapKernelError::TooLarge);
+        }
+
+        let tables = [OwnedTable::Pdp, OwnedTable::Pd, OwnedTable::Pt];
+        let mut phys_of = [PhysAddr::null(); 3];
+        for (addr, &table) in phys_of.iter_mut().zip(&tables) {
+            *addr = self
+                .as_ref()
+                .table_phys(table)
+                .ok_or(MapKernelError::Unpinned)?;
+        }
+        let [pdp, pd, pt] = phys_of;
+        let this = self.get_unchecked_mut();
+
+        /* Check everything first, so that a conflict leaves no partial mapping */
+        let i4 = table_index(virt, 3);
+        let i3 = table_index(virt, 2);
+        let i2 = table_index(virt, 1);
+        let has_pdp = links_to(&this.paging_root[i4], pdp)?;
+        let has_pd = granularity < MapGranularity::Gigapage && links_to(&this.pdp[i3], pd)?;
+        let has_pt = granularity < MapGranularity::Megapage && links_to(&this.pd[i2], pt)?;
+
+        let step = granularity.size();
+        let level = granularity.leaf_level();
+        let count = range.len() / step;
+        let leaf_present = |index: usize| match granularity {
+            MapGranularity::Page => this.page_table[index].is_present(),
+            MapGranularity::Megapage => this.pd[index].is_present(),
+            MapGranularity::Gigapage => this.pdp[index].is_present(),
+        };
+        if (0..count).any(|i| leaf_present(table_index(virt + i * step, level))) {
+            return Err(MapKernelError::Conflict);
+        }
+
+        /* Permissions are enforced at the leaf level only */
+        if !has_pdp {
+            let flags = PML4Flags::new().set_present().set_writable();
+            this.paging_root[i4] = PML4Entry::new(pdp.cast(), flags);
+        }
+        if granularity < MapGranularity::Gigapage && !has_pd {
+            let flags = PDPFlags::new().set_present().set_writable();
+            this.pdp[i3] = PDPEntry::new(pd.cast(), flags);
+        }
+        if granularity < MapGranularity::Megapage && !has_pt {
+            let flags = PDFlags::new().set_present().set_writable();
+            this.pd[i2] = PDEntry::new(pt.cast(), flags);
+        }
+
+        let flags = this.page_flags.leaf(perms);
+        for i in 0..count {
+            let index = table_index(virt + i * step, level);
+            let addr = phys.start() + i * step;
+            match granularity {
+                MapGranularity::Page => {
+                    let addr = PhysAddr::new_unchecked(addr);
+                    this.page_table[index] = PTEntry::new(addr, flags.pt());
+                }
+                MapGranularity::Megapage => {
+                    let addr = PhysAddr::new_unchecked(addr);
+                    this.pd[index] = PDEntry::new(addr, flags.pd());
+                }
+                MapGranularity::Gigapage => {
+                    /* PDFlags have the same layout, including the leaf bit */
+                    let raw = addr | flags.pd().as_u64();
+                    this.pdp[index] = PDPEntry::from_u64_unchecked(raw);
+                }
+            }
+        }
+
+        return Ok(region);
+    }
+}
@@ -0,0 +1,105 @@
+//! Microcode update of the bootstrap processor. The loader picks the
+//! newest update of the `microcode.bin` module that fits the processor and
+//! loads it before the kernel runs. Only the BSP is updated here, the
+//! module is passed on as `ModuleKind::Microcode` and the kernel loads it
+//! on every AP it starts.
+
+use crate::sha256::Sha256;
+use crate::{Bootinfo, Module, ModuleKind};
+use cpu::microcode::{CpuSignature, IntelMicrocode, MicrocodeError, MicrocodeFile};
+
+/// What happened to the BSP's microcode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct MicrocodeStatus {
+    /// Revision firmware left
+    pub old_revision: u32,
+    /// Revision read back after the update, `old_revision` if none was
+    /// applied
+    pub new_revision: u32,
+    pub applied: bool,
+    /// Hash of the applied update, header included
+    pub sha256: [u8; 32],
+}
+
+impl MicrocodeStatus {
+    pub const fn new() -> Self {
+        Self {
+            old_revision: 0,
+            new_revision: 0,
+            applied: false,
+            sha256: [0; 32],
+        }
+    }
+
+    /// Nothing applied, the processor runs `revision`
+    pub const fn unchanged(revision: u32) -> Self {
+        Self {
+            old_revision: revision,
+            new_revision: revision,
+            applied: false,
+            sha256: [0; 32],
+        }
+    }
+
+    pub fn applied(old_revision: u32, update: &IntelMicrocode) -> Self {
+        Self {
+            old_revision,
+            new_revision: update.header.revision,
+            applied: true,
+            sha256: Sha256::digest(update.as_bytes()),
+        }
+    }
+}
+
+/// The newest update of `file` for `cpu` that is newer than `current`.
+/// `skipped` is called with the offset of every update that isn't picked
+/// and why, corrupt ones included.
+pub fn select_microcode<'a>(
+    file: &'a [u8],
+    cpu: CpuSignature,
+    current: u32,
+    mut skipped: impl FnMut(usize, MicrocodeError),
+) -> Option<IntelMicrocode<'a>> {
+    let mut best: Option<(usize, IntelMicrocode)> = None;
+
+    for (offset, update) in MicrocodeFile::new(file) {
+        let update = match update.and_then(|x| x.check(cpu, current).map(|_| x)) {
+            Ok(x) => x,
+            Err(e) => {
+                skipped(offset, e);
+                continue;
+            }
+        };
+        /* Whichever loses to the other is skipped as not newer */
+        let (winner, loser) = match best {
+            Some((at, x)) if (x.header.revision as i32) >= (update.header.revision as i32) => {
+                ((at, x), (offset, update))
+            }
+            Some(x) => ((offset, update), x),
+            None => {
+                best = Some((offset, update));
+                continue;
+            }
+        };
+        skipped(
+            loser.0,
+            MicrocodeError::NotNewer {
+                current: winner.1.header.revision,
+                update: loser.1.header.revision,
+            },
+        );
+        best = Some(winner);
+    }
+
+    return best.map(|(_, x)| x);
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// The microcode module, for the APs
+    pub fn microcode_module(&self) -> Option<&Module> {
+        self.modules
+            .iter()
+            .find(|x| x.kind == ModuleKind::Microcode)
+    }
+}
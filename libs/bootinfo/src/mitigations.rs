@@ -0,0 +1,333 @@
+//! Hardening toggles applied right before kernel entry, so a deployment
+//! can force protections on and bring-up can turn them off without code
+//! edits. Configured by one line of `sovos.cfg`:
+//!
+//! ```text
+//! mitigations=smep=on smap=on umip=auto nx=require
+//! ```
+//!
+//! | item           | controls                                       |
+//! |----------------|------------------------------------------------|
+//! | `smep`         | CR4.SMEP                                       |
+//! | `smap`         | CR4.SMAP                                       |
+//! | `umip`         | CR4.UMIP                                       |
+//! | `nx`           | EFER.NXE, W^X mappings depend on it            |
+//! | `tsd`          | CR4.TSD, `rdtsc` only in ring 0                |
+//! | `fast_strings` | IA32_MISC_ENABLE fast strings, Intel only      |
+//!
+//! Settings are `on`, `off`, `auto` and `require`. Items that aren't
+//! listed keep whatever firmware left. The outcome of every item is
+//! recorded in `Bootinfo::mitigations`, so the kernel doesn't have to
+//! re-derive the machine state.
+//!
+//! `nx` is the exception, the kernel's mappings depend on it, so it's
+//! decided at startup by `enable_nx` and only recorded at the end.
+//! Processors without NX boot with `PageFlags::WITHOUT_NX` unless it's
+//! `nx=require`.
+
+use crate::Config;
+use cpu::CpuInfo;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Mitigation {
+    Smep = 0,
+    Smap,
+    Umip,
+    Nx,
+    Tsd,
+    FastStrings,
+}
+
+pub const MITIGATION_COUNT: usize = 6;
+
+impl Mitigation {
+    pub const ALL: [Mitigation; MITIGATION_COUNT] = [
+        Mitigation::Smep,
+        Mitigation::Smap,
+        Mitigation::Umip,
+        Mitigation::Nx,
+        Mitigation::Tsd,
+        Mitigation::FastStrings,
+    ];
+
+    /// Name in the config
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Smep => "smep",
+            Self::Smap => "smap",
+            Self::Umip => "umip",
+            Self::Nx => "nx",
+            Self::Tsd => "tsd",
+            Self::FastStrings => "fast_strings",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|x| x.name() == name)
+    }
+
+    pub fn is_supported(self, info: &CpuInfo) -> bool {
+        match self {
+            Self::Smep => info.smep(),
+            Self::Smap => info.smap(),
+            Self::Umip => info.umip(),
+            Self::Nx => info.nx(),
+            /* Every processor in long mode has a TSC */
+            Self::Tsd => true,
+            Self::FastStrings => info.misc_enable(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    /// Left as firmware set it
+    Keep,
+    /// Enabled if supported, silently skipped otherwise
+    Auto,
+    /// Enabled, a warning if not supported
+    On,
+    Off,
+    /// Enabled, the boot is aborted if not supported
+    Require,
+}
+
+impl Setting {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Self::Auto),
+            "on" => Some(Self::On),
+            "off" => Some(Self::Off),
+            "require" => Some(Self::Require),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Outcome {
+    /// Not configured, or the loader never got to apply it
+    Skipped = 0,
+    Applied,
+    NotSupported,
+}
+
+/// What has to be done to a register bit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Nothing,
+    Enable,
+    Disable,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MitigationError {
+    /// An item isn't `name=setting`
+    Malformed,
+    UnknownItem,
+    UnknownSetting(Mitigation),
+    /// Configured `require`, but the processor lacks it
+    Required(Mitigation),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mitigations {
+    settings: [Setting; MITIGATION_COUNT],
+}
+
+impl Mitigations {
+    /// Everything kept
+    pub const fn new() -> Self {
+        Self {
+            settings: [Setting::Keep; MITIGATION_COUNT],
+        }
+    }
+
+    /// Parses space separated `name=setting` items, a repeated item
+    /// overrides the earlier one
+    pub fn parse(line: &str) -> Result<Self, MitigationError> {
+        let mut this = Self::new();
+
+        for item in line.split_ascii_whitespace() {
+            let (name, setting) = match item.find('=') {
+                Some(eq) => (&item[..eq], &item[eq + 1..]),
+                None => return Err(MitigationError::Malformed),
+            };
+            let mitigation = Mitigation::from_name(name).ok_or(MitigationError::UnknownItem)?;
+            let setting =
+                Setting::from_name(setting).ok_or(MitigationError::UnknownSetting(mitigation))?;
+            this.set(mitigation, setting);
+        }
+
+        return Ok(this);
+    }
+
+    /// The `mitigations` key, everything kept if there is none
+    pub fn from_config(config: &Config) -> Result<Self, MitigationError> {
+        match config.get("mitigations") {
+            Some(line) => Self::parse(line),
+            None => Ok(Self::new()),
+        }
+    }
+
+    pub fn get(&self, mitigation: Mitigation) -> Setting {
+        self.settings[mitigation as usize]
+    }
+
+    pub fn set(&mut self, mitigation: Mitigation, setting: Setting) {
+        self.settings[mitigation as usize] = setting;
+    }
+
+    /// Whether mappings may use the NX bit on a processor with `info`,
+    /// `nxe` is whether firmware left EFER.NXE on. Fails if NX is required
+    /// but not supported.
+    pub fn use_nx(&self, info: &CpuInfo, nxe: bool) -> Result<bool, MitigationError> {
+        return match (self.get(Mitigation::Nx), info.nx()) {
+            (Setting::Require, false) => Err(MitigationError::Required(Mitigation::Nx)),
+            (_, false) | (Setting::Off, true) => Ok(false),
+            (Setting::Keep, true) => Ok(nxe),
+            (_, true) => Ok(true),
+        };
+    }
+
+    /// Decides what to do with every item on a processor with `info`,
+    /// without touching any register. Fails if a required item isn't
+    /// supported.
+    pub fn plan(&self, info: &CpuInfo) -> Result<MitigationPlan, MitigationError> {
+        let mut plan = MitigationPlan {
+            actions: [Action::Nothing; MITIGATION_COUNT],
+            record: MitigationRecord::new(),
+        };
+
+        for &mitigation in &Mitigation::ALL {
+            let supported = mitigation.is_supported(info);
+            let (action, outcome) = match (self.get(mitigation), supported) {
+                (Setting::Keep, _) => (Action::Nothing, Outcome::Skipped),
+                (Setting::Require, false) => return Err(MitigationError::Required(mitigation)),
+                (_, false) => (Action::Nothing, Outcome::NotSupported),
+                (Setting::Off, true) => (Action::Disable, Outcome::Applied),
+                (_, true) => (Action::Enable, Outcome::Applied),
+            };
+            plan.actions[mitigation as usize] = action;
+            plan.record.outcomes[mitigation as usize] = outcome;
+        }
+
+        return Ok(plan);
+    }
+}
+
+impl Default for Mitigations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Register changes decided by `Mitigations::plan`, and the record they
+/// result in once applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MitigationPlan {
+    actions: [Action; MITIGATION_COUNT],
+    pub record: MitigationRecord,
+}
+
+impl MitigationPlan {
+    pub fn action(&self, mitigation: Mitigation) -> Action {
+        self.actions[mitigation as usize]
+    }
+}
+
+/// Outcome of every item, as the kernel gets it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct MitigationRecord {
+    outcomes: [Outcome; MITIGATION_COUNT],
+}
+
+impl MitigationRecord {
+    pub const fn new() -> Self {
+        Self {
+            outcomes: [Outcome::Skipped; MITIGATION_COUNT],
+        }
+    }
+
+    pub fn get(&self, mitigation: Mitigation) -> Outcome {
+        self.outcomes[mitigation as usize]
+    }
+}
+
+impl Default for MitigationRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns EFER.NXE on if the kernel's mappings are going to use NX, see
+/// `Mitigations::use_nx`, and returns the flags to map it with. Has to
+/// run before anything is mapped.
+///
+/// # Safety
+/// * Ring 0 only
+#[cfg(feature = "ringzero")]
+pub unsafe fn enable_nx(
+    mitigations: &Mitigations,
+    info: &CpuInfo,
+) -> Result<crate::PageFlags, MitigationError> {
+    use cpu::Efer;
+
+    let efer = Efer::get();
+    let nx = mitigations.use_nx(info, efer.nx_enable())?;
+    if nx && !efer.nx_enable() {
+        Efer::set(efer.set_nx_enable());
+    }
+    return Ok(crate::PageFlags::select(nx));
+}
+
+/// Plans `mitigations` and writes CR4, EFER and IA32_MISC_ENABLE
+/// accordingly. Nothing is written if a required item isn't supported.
+///
+/// # Safety
+/// * Ring 0 only. Enabling SMEP or SMAP faults on the next access to
+/// user pages, so it has to happen right before leaving the loader.
+#[cfg(feature = "ringzero")]
+pub unsafe fn apply_mitigations(
+    mitigations: &Mitigations,
+    info: &CpuInfo,
+) -> Result<MitigationRecord, MitigationError> {
+    use cpu::{Cr4, Efer, MiscEnable};
+
+    let plan = mitigations.plan(info)?;
+
+    let mut cr4 = Cr4::get();
+    let mut efer = Efer::get();
+    for &mitigation in &Mitigation::ALL {
+        let enable = match plan.action(mitigation) {
+            Action::Nothing => continue,
+            Action::Enable => true,
+            Action::Disable => false,
+        };
+        match (mitigation, enable) {
+            (Mitigation::Smep, true) => cr4 = cr4.set_supervisormode_exec_prot(),
+            (Mitigation::Smep, false) => cr4 = cr4.clear_supervisormode_exec_prot(),
+            (Mitigation::Smap, true) => cr4 = cr4.set_supervisormode_access_prot(),
+            (Mitigation::Smap, false) => cr4 = cr4.clear_supervisormode_access_prot(),
+            (Mitigation::Umip, true) => cr4 = cr4.set_usermode_instruction_prevention(),
+            (Mitigation::Umip, false) => cr4 = cr4.clear_usermode_instruction_prevention(),
+            (Mitigation::Nx, true) => efer = efer.set_nx_enable(),
+            (Mitigation::Nx, false) => efer = efer.clear_nx_enable(),
+            (Mitigation::Tsd, true) => cr4 = cr4.set_time_stamp_disable(),
+            (Mitigation::Tsd, false) => cr4 = cr4.clear_time_stamp_disable(),
+            (Mitigation::FastStrings, true) => {
+                MiscEnable::set(MiscEnable::get().set_fast_strings())
+            }
+            (Mitigation::FastStrings, false) => {
+                MiscEnable::set(MiscEnable::get().clear_fast_strings())
+            }
+        }
+    }
+    Efer::set(efer);
+    Cr4::set(cr4);
+
+    return Ok(plan.record);
+}
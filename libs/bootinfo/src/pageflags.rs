@@ -0,0 +1,117 @@
+//! Leaf flags of the kernel's mappings. Without EFER.NXE bit 63 of an
+//! entry is reserved, so the NX bit makes every access through it fault
+//! with RSVD set. Both variants of the flags are here, `PageFlags::select`
+//! picks one set at startup and every mapping takes its flags from it,
+//! see `Bootinfo::page_flags`.
+//!
+//! Without NX everything mapped is executable, rodata and data included.
+
+use crate::{KernelPermPolicy, SegmentPerms};
+use cpu::paging::{Bits, PDFlags, PTFlags};
+use impl_bits::impl_bits;
+
+/// Flags of a leaf entry, at the same bits in page tables and directories
+#[derive(PartialEq, Eq)]
+#[repr(transparent)]
+pub struct LeafFlags(u64);
+
+impl_bits! {
+    LeafFlags = {
+        present = 0,
+        writable = 1,
+        nx = 63,
+    }
+}
+
+impl LeafFlags {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// As a 4K page table entry
+    pub fn pt(self) -> PTFlags {
+        unsafe { PTFlags::from_u64_unchecked(self.0) }
+    }
+
+    /// As a 2M or 1G leaf, with the page size bit
+    pub fn pd(self) -> PDFlags {
+        unsafe { PDFlags::from_u64_unchecked(self.0) }.set_leaf()
+    }
+}
+
+pub const KERNEL_TEXT: LeafFlags = LeafFlags::new().set_present();
+pub const KERNEL_RODATA: LeafFlags = KERNEL_TEXT.set_nx();
+pub const KERNEL_DATA: LeafFlags = KERNEL_RODATA.set_writable();
+pub const KERNEL_RWX: LeafFlags = KERNEL_TEXT.set_writable();
+pub const KERNEL_RODATA_NO_NX: LeafFlags = KERNEL_TEXT;
+pub const KERNEL_DATA_NO_NX: LeafFlags = KERNEL_RWX;
+
+/// Flags for each kind of kernel mapping
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct PageFlags {
+    pub text: LeafFlags,
+    pub rodata: LeafFlags,
+    pub data: LeafFlags,
+    pub rwx: LeafFlags,
+    /// The NX bit is used, EFER.NXE must stay on
+    pub nx: bool,
+}
+
+impl PageFlags {
+    pub const WITH_NX: Self = Self {
+        text: KERNEL_TEXT,
+        rodata: KERNEL_RODATA,
+        data: KERNEL_DATA,
+        rwx: KERNEL_RWX,
+        nx: true,
+    };
+
+    pub const WITHOUT_NX: Self = Self {
+        text: KERNEL_TEXT,
+        rodata: KERNEL_RODATA_NO_NX,
+        data: KERNEL_DATA_NO_NX,
+        rwx: KERNEL_RWX,
+        nx: false,
+    };
+
+    pub const fn select(nx: bool) -> Self {
+        if nx {
+            Self::WITH_NX
+        } else {
+            Self::WITHOUT_NX
+        }
+    }
+
+    pub const fn leaf(&self, perms: SegmentPerms) -> LeafFlags {
+        match (perms.writable, perms.executable) {
+            (false, true) => self.text,
+            (false, false) => self.rodata,
+            (true, false) => self.data,
+            (true, true) => self.rwx,
+        }
+    }
+
+    /// What `perms` end up as
+    pub const fn effective(&self, perms: SegmentPerms) -> SegmentPerms {
+        SegmentPerms::new(perms.writable, perms.executable || !self.nx)
+    }
+}
+
+impl KernelPermPolicy {
+    /// Permissions the segments actually get with `flags`. Without NX data
+    /// is writable and executable even if the policy doesn't allow it,
+    /// which `validate` can't refuse, the loader only warns about it.
+    pub const fn effective(&self, flags: &PageFlags) -> Self {
+        Self {
+            text: flags.effective(self.text),
+            rodata: flags.effective(self.rodata),
+            data: flags.effective(self.data),
+            allow_rwx: self.allow_rwx,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{Bootinfo, MapGranularity, MapKernelError, SegmentPerms, KERNEL_BASE};
+use core::pin::Pin;
+use cpu::paging::PAGE_SIZE;
+use cpu::{PhysRange, PhysSlice};
+
+/// Where per-CPU areas are mapped, in the same 1GiB as the kernel,
+/// which leaves the kernel 768MiB below it
+pub const PERCPU_BASE: u64 = KERNEL_BASE + 0x3000_0000;
+
+/// Per-CPU data laid out by the loader, for the kernel to fill in.
+///
+/// CPU `n`'s area is `stride` bytes at `virt + n * stride`, physically at
+/// `phys.addr() + n * stride`. The stride is the requested size rounded up
+/// to whole pages, so areas never share a page. CPUs are numbered in MADT
+/// order, which isn't necessarily APIC ID order.
+#[derive(Clone, Copy, Debug)]
+pub struct PerCpuArea {
+    pub phys: PhysSlice<u8>,
+    pub virt: u64,
+    pub stride: u64,
+    pub cpus: u32,
+}
+
+impl PerCpuArea {
+    pub const fn null() -> Self {
+        Self {
+            phys: PhysSlice::null(),
+            virt: 0,
+            stride: 0,
+            cpus: 0,
+        }
+    }
+
+    /// Virtual address of `cpu`'s area
+    pub fn cpu_virt(&self, cpu: u32) -> Option<u64> {
+        if cpu >= self.cpus {
+            return None;
+        }
+        return Some(self.virt + cpu as u64 * self.stride);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PerCpuError {
+    NoCpus,
+    TooLarge,
+    Alloc(uefi::Error),
+    Map(MapKernelError),
+}
+
+/// `size_per_cpu` rounded up to whole pages, `None` if it is 0 or overflows
+pub const fn percpu_stride(size_per_cpu: u64) -> Option<u64> {
+    if size_per_cpu == 0 {
+        return None;
+    }
+    return match size_per_cpu.checked_add(PAGE_SIZE - 1) {
+        Some(x) => Some(x & !(PAGE_SIZE - 1)),
+        None => None,
+    };
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Allocates per-CPU areas as `LoaderData`, so they stay reserved in the
+    /// memory map, below `dma_ceiling` if possible, maps them read-write at
+    /// `PERCPU_BASE` and records them in `percpu`. `cpus` is usually
+    /// `AcpiInfo::madt_cpus`.
+    ///
+    /// # Safety
+    /// * Boot services must still be available.
+    /// * Memory must be identity-mapped.
+    pub unsafe fn reserve_percpu(
+        self: Pin<&mut Self>,
+        boot_services: &uefi::BootServices,
+        cpus: usize,
+        size_per_cpu: u64,
+    ) -> Result<PhysSlice<u8>, PerCpuError> {
+        let stride = percpu_stride(size_per_cpu).ok_or(PerCpuError::TooLarge)?;
+        let len = stride
+            .checked_mul(cpus as u64)
+            .ok_or(PerCpuError::TooLarge)?;
+        if cpus == 0 {
+            return Err(PerCpuError::NoCpus);
+        }
+
+        let pages = (len / PAGE_SIZE) as usize;
+        let typ = uefi::memory::Type::LoaderData;
+        /* Below `dma_ceiling` while there's room, the caller tags them */
+        let below = self.dma_ceiling().and_then(|ceiling| {
+            boot_services
+                .allocate_pages(uefi::AllocateType::MaxAddress, typ, pages, ceiling - 1)
+                .ok()
+        });
+        let base = match below {
+            Some(x) => x,
+            None => boot_services
+                .allocate_pages(uefi::AllocateType::AnyPages, typ, pages, 0)
+                .map_err(PerCpuError::Alloc)?,
+        };
+
+        let phys = PhysRange::new(base, len).ok_or(PerCpuError::TooLarge)?;
+        return self.map_percpu(phys, cpus, stride);
+    }
+
+    /// Maps already reserved per-CPU areas at `PERCPU_BASE`,
+    /// `phys` must be `cpus * stride` bytes
+    ///
+    /// # Safety
+    /// Memory must be identity-mapped.
+    pub unsafe fn map_percpu(
+        mut self: Pin<&mut Self>,
+        phys: PhysRange,
+        cpus: usize,
+        stride: u64,
+    ) -> Result<PhysSlice<u8>, PerCpuError> {
+        if cpus == 0 {
+            return Err(PerCpuError::NoCpus);
+        }
+        if cpus > u32::MAX as usize || Some(phys.len()) != stride.checked_mul(cpus as u64) {
+            return Err(PerCpuError::TooLarge);
+        }
+
+        self.as_mut()
+            .map_range(
+                PERCPU_BASE,
+                phys,
+                MapGranularity::Megapage,
+                SegmentPerms::RW,
+            )
+            .map_err(PerCpuError::Map)?;
+
+        let slice = PhysSlice::from(phys);
+        self.get_unchecked_mut().percpu = PerCpuArea {
+            phys: slice,
+            virt: PERCPU_BASE,
+            stride,
+            cpus: cpus as u32,
+        };
+        return Ok(slice);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{BootCapabilities, Bootinfo, LOW_MEMORY_END};
+use arrayvec::ArrayVec;
+use cpu::PhysRange;
+use uefi::memory::{Descriptor, Type};
+
+/// What `clamp_memory_map` did to the map
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryClamp {
+    /// Usable bytes at or above the limit
+    pub lost_bytes: u64,
+    /// Usable descriptors that were cut or retyped
+    pub regions: usize,
+    /// End of the highest usable region left, what a direct map has to cover
+    pub usable_end: u64,
+}
+
+/// Memory the kernel may use once the loader is gone
+pub fn is_usable(typ: Option<Type>) -> bool {
+    matches!(
+        typ,
+        Some(Type::Conventional)
+            | Some(Type::LoaderCode)
+            | Some(Type::LoaderData)
+            | Some(Type::BootServicesCode)
+            | Some(Type::BootServicesData)
+            | Some(Type::Persistent)
+    )
+}
+
+/// Usable memory nothing was handed over in. `LoaderCode` and `LoaderData`
+/// hold the kernel, its modules and `Bootinfo`, `Persistent` memory is
+/// kept for its contents.
+pub fn is_free(typ: Option<Type>) -> bool {
+    matches!(
+        typ,
+        Some(Type::Conventional) | Some(Type::BootServicesCode) | Some(Type::BootServicesData)
+    )
+}
+
+const FOUR_GIB: u64 = 1 << 32;
+
+/// What an early kernel allocator wants to know about free memory,
+/// see `is_free`. Adjacent descriptors count as one region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct MemorySummary {
+    /// Largest contiguous free region, empty if there is none
+    pub largest: PhysRange,
+    /// Free bytes below `LOW_MEMORY_END`
+    pub below_1m: u64,
+    /// Free bytes below 4 GiB, including `below_1m`, for 32-bit DMA
+    pub below_4g: u64,
+    pub above_4g: u64,
+    /// Number of discontiguous free regions
+    pub regions: u32,
+}
+
+impl MemorySummary {
+    pub const fn new() -> Self {
+        Self {
+            largest: PhysRange::empty(),
+            below_1m: 0,
+            below_4g: 0,
+            above_4g: 0,
+            regions: 0,
+        }
+    }
+
+    /// Works on unsorted maps. Overlapping descriptors are counted twice.
+    pub fn from_map(map: &[Descriptor]) -> Self {
+        let free = || {
+            map.iter()
+                .filter(|x| is_free(x.memory_type()))
+                .filter_map(|x| x.phys_range())
+                .filter(|x| !x.is_empty())
+        };
+        let mut summary = Self::new();
+
+        for range in free() {
+            let below = |limit: u64| limit.clamp(range.start(), range.end()) - range.start();
+            let below_4g = below(FOUR_GIB);
+            summary.below_1m += below(LOW_MEMORY_END);
+            summary.below_4g += below_4g;
+            summary.above_4g += range.len() - below_4g;
+        }
+
+        /* A region starts where no other free range ends, it is followed
+         * through the ranges that start where it ends */
+        for head in free() {
+            if free().any(|x| x.end() == head.start()) {
+                continue;
+            }
+            summary.regions += 1;
+
+            let mut end = head.end();
+            while let Some(next) = free().find(|x| x.start() == end) {
+                end = next.end();
+            }
+            let region = PhysRange::from_start_end(head.start(), end).unwrap_or(head);
+            if region.len() > summary.largest.len() {
+                summary.largest = region;
+            }
+        }
+
+        return summary;
+    }
+
+    /// Free bytes in total
+    pub fn total(&self) -> u64 {
+        self.below_4g + self.above_4g
+    }
+}
+
+/// Descriptors overlapping an earlier one in `map`. Firmware shouldn't
+/// report any, the kernel would hand out memory of one type as another.
+pub fn overlapping_descriptors(map: &[Descriptor]) -> usize {
+    let ranges = || map.iter().filter_map(|x| x.phys_range());
+    return ranges()
+        .enumerate()
+        .filter(|&(i, range)| ranges().take(i).any(|x| x.overlaps(&range)))
+        .count();
+}
+
+/// Makes usable memory at or above `1 << phys_bits` `Unusable`. A region
+/// crossing the limit is cut at it and the part above is appended as
+/// a separate `Unusable` descriptor, or dropped if `map` is full.
+/// Firmware has been seen reporting such memory on CPUs that can't address it.
+pub fn clamp_memory_map<const N: usize>(
+    map: &mut ArrayVec<Descriptor, N>,
+    phys_bits: u8,
+) -> MemoryClamp {
+    let limit = 1u64.checked_shl(phys_bits as u32).unwrap_or(0);
+    let mut clamp = MemoryClamp::default();
+
+    /* Appended descriptors are Unusable, so they are never revisited */
+    for i in 0..map.len() {
+        let descriptor = &mut map[i];
+        if !is_usable(descriptor.memory_type()) {
+            continue;
+        }
+
+        let start = descriptor.phys_start;
+        let len = descriptor.pages.saturating_mul(4096);
+        let end = start.saturating_add(len);
+        if limit == 0 || end <= limit {
+            clamp.usable_end = clamp.usable_end.max(end);
+            continue;
+        }
+
+        clamp.regions += 1;
+        if start >= limit {
+            clamp.lost_bytes += len;
+            descriptor.typ = Type::Unusable as u32;
+            continue;
+        }
+
+        clamp.lost_bytes += end - limit;
+        clamp.usable_end = clamp.usable_end.max(limit);
+        descriptor.pages = (limit - start) / 4096;
+        let excess = PhysRange::new(limit, end - limit).and_then(|range| {
+            Descriptor::new(Type::Unusable, range, descriptor.attributes.clone())
+        });
+        if let Some(excess) = excess {
+            let _ = map.try_push(excess);
+        }
+    }
+
+    return clamp;
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// `clamp_memory_map` on `uefi_meminfo`, sets `physical_memory_clamped`
+    /// and records it if anything was cut
+    pub fn clamp_physical_memory(&mut self, phys_bits: u8) -> MemoryClamp {
+        let clamp = clamp_memory_map(&mut self.uefi_meminfo, phys_bits);
+        self.physical_memory_clamped = clamp.regions != 0;
+        if self.physical_memory_clamped {
+            self.record(BootCapabilities::set_physical_memory_clamped);
+        }
+        self.summarize_memory();
+        return clamp;
+    }
+
+    /// Recomputes `memory` from `uefi_meminfo`, called wherever the map changes
+    pub fn summarize_memory(&mut self) {
+        self.memory = MemorySummary::from_map(&self.uefi_meminfo);
+    }
+}
@@ -0,0 +1,102 @@
+//! `Bootinfo` is self-referential: `paging_root` points to `pdp`, which
+//! points to `pd` and `page_table`, `this` holds its own physical address
+//! and `BootArena` hands out references into `buf`. Moving it after any of
+//! these are set up leaves dangling physical addresses behind, so methods
+//! that set them up take `Pin<&mut Bootinfo>`.
+//!
+//! Migrating from the unpinned API:
+//! * A `&'static mut Bootinfo` (usually a `static mut`) becomes
+//! `PinnedBootinfo::new(..)`, which also records `this`. The loader goes
+//! through `BootinfoBuilder::new(..)`, which derefs to it.
+//! * `map_kernel` and `arena` are called through `PinnedBootinfo::as_mut`.
+//! * Everything else still takes `&Bootinfo` or `&mut Bootinfo`, the latter
+//! is reachable through the unsafe `PinnedBootinfo::get_mut` until those
+//! methods are ported.
+//! * Tests that own a `Bootinfo` use `Box::pin` and `Bootinfo::init_this`.
+
+use crate::{Bootinfo, OwnedTable, DEFAULT_BUF_SIZE};
+use core::ops::Deref;
+use core::pin::Pin;
+use cpu::PhysAddr;
+
+/// `Bootinfo` at its final location, with `this` set
+pub struct PinnedBootinfo<const BUF: usize = DEFAULT_BUF_SIZE>(Pin<&'static mut Bootinfo<BUF>>);
+
+impl<const BUF: usize> PinnedBootinfo<BUF> {
+    /// # Safety
+    /// Memory must be identity-mapped, `bootinfo`'s address is taken
+    /// as its physical address.
+    pub unsafe fn new(bootinfo: &'static mut Bootinfo<BUF>) -> Self {
+        /* SAFETY: the only reference is consumed, it can't be moved out of anymore */
+        let mut bootinfo = Pin::new_unchecked(bootinfo);
+        bootinfo.as_mut().init_this();
+        return Self(bootinfo);
+    }
+
+    pub fn as_ref(&self) -> Pin<&Bootinfo<BUF>> {
+        self.0.as_ref()
+    }
+
+    pub fn as_mut(&mut self) -> Pin<&mut Bootinfo<BUF>> {
+        self.0.as_mut()
+    }
+
+    /// Unpinned access for methods that haven't been ported yet
+    ///
+    /// # Safety
+    /// `Bootinfo` must not be moved out of, e.g. by `core::mem::replace`.
+    pub unsafe fn get_mut(&mut self) -> &mut Bootinfo<BUF> {
+        self.0.as_mut().get_unchecked_mut()
+    }
+}
+
+impl<const BUF: usize> Deref for PinnedBootinfo<BUF> {
+    type Target = Bootinfo<BUF>;
+    fn deref(&self) -> &Bootinfo<BUF> {
+        &*self.0
+    }
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Sets `this` to the current address of `self`
+    ///
+    /// # Safety
+    /// Memory must be identity-mapped.
+    pub unsafe fn init_this(self: Pin<&mut Self>) {
+        let this = self.get_unchecked_mut();
+        this.this = PhysAddr::new(this as *const Self as u64).unwrap();
+    }
+
+    /// Physical address of one of the page tables, derived from `this`.
+    /// `None` until `init_this` is called.
+    pub fn table_phys(self: Pin<&Self>, table: OwnedTable) -> Option<PhysAddr> {
+        if self.this.as_u64() == 0 {
+            return None;
+        }
+
+        let base = &*self as *const Self as u64;
+        let offset = match table {
+            OwnedTable::Pml4 => &self.paging_root as *const _ as u64,
+            OwnedTable::Pdp => &self.pdp as *const _ as u64,
+            OwnedTable::Pd => &self.pd as *const _ as u64,
+            OwnedTable::Pt => &self.page_table as *const _ as u64,
+        } - base;
+
+        return PhysAddr::new(self.this.as_u64() + offset);
+    }
+
+    /// Switches to the page tables rooted at `paging_root`
+    ///
+    /// # Safety
+    /// The tables must map the currently running code and stack.
+    ///
+    /// # Panics
+    /// If `init_this` wasn't called.
+    #[cfg(all(feature = "ringzero", target_arch = "x86_64"))]
+    pub unsafe fn activate_paging(self: Pin<&Self>) {
+        let root = self
+            .table_phys(OwnedTable::Pml4)
+            .expect("Bootinfo::this is not set");
+        <crate::Paging as crate::ArchPaging>::activate(root.cast());
+    }
+}
@@ -0,0 +1,205 @@
+//! What a kernel needs at entry beyond the features of `abi`, carried in
+//! a second `SOVOS` note of type `REQUIREMENTS_NOTE_TYPE`. Every field is
+//! a number, 0 means the kernel has no requirement and the defaults of a
+//! kernel without the note apply. The loader lays everything out first
+//! and then checks the result, see `KernelRequirements::check`.
+
+use crate::{AbiError, BootError, Bootinfo, FrameAllocator};
+use cpu::paging::PAGE_SIZE;
+use cpu::PhysRange;
+use impl_bits::bytes::Reader;
+
+pub const REQUIREMENTS_NOTE_TYPE: u32 = 2;
+/// `min_stack`, `stack_align`, `min_free_contiguous` and
+/// `max_bootinfo_distance`, little-endian
+pub const REQUIREMENTS_DESC_SIZE: usize = 32;
+/// Stack kernels get if they don't ask for more
+pub const KERNEL_STACK_SIZE: u64 = 0x1_0000;
+/// Alignment of the stack top without a requirement, what SysV expects
+pub const DEFAULT_STACK_ALIGN: u64 = 16;
+
+/// Contents of the requirements note
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KernelRequirements {
+    /// Bytes of stack the kernel is entered with
+    pub min_stack: u64,
+    /// Alignment of the stack top, a power of two
+    pub stack_align: u64,
+    /// Largest free region left after layout
+    pub min_free_contiguous: u64,
+    /// Span of physical memory covering the kernel image and `Bootinfo`,
+    /// for kernels that reach `Bootinfo` relative to their image
+    pub max_bootinfo_distance: u64,
+}
+
+impl KernelRequirements {
+    /// Kernels without the note
+    pub const DEFAULT: Self = Self {
+        min_stack: 0,
+        stack_align: 0,
+        min_free_contiguous: 0,
+        max_bootinfo_distance: 0,
+    };
+
+    /// Parses the note's descriptor. Longer descriptors are accepted,
+    /// future fields are ignored.
+    pub fn from_desc(desc: &[u8]) -> Result<Self, AbiError> {
+        let desc = Reader::new(desc);
+        let u64_at = |at| desc.u64_le_at(at).map_err(|_| AbiError::Malformed);
+
+        let requirements = Self {
+            min_stack: u64_at(0)?,
+            stack_align: u64_at(8)?,
+            min_free_contiguous: u64_at(16)?,
+            max_bootinfo_distance: u64_at(24)?,
+        };
+        if requirements.stack_align != 0 && !requirements.stack_align.is_power_of_two() {
+            return Err(AbiError::Malformed);
+        }
+        return Ok(requirements);
+    }
+
+    pub const fn to_desc(&self) -> [u8; REQUIREMENTS_DESC_SIZE] {
+        let fields = [
+            self.min_stack,
+            self.stack_align,
+            self.min_free_contiguous,
+            self.max_bootinfo_distance,
+        ];
+        let mut desc = [0u8; REQUIREMENTS_DESC_SIZE];
+        let mut i = 0;
+        while i < desc.len() {
+            desc[i] = fields[i / 8].to_le_bytes()[i % 8];
+            i += 1;
+        }
+        return desc;
+    }
+
+    /// Bytes of stack to give the kernel, a multiple of pages and of
+    /// `stack_align`
+    pub const fn stack_size(&self) -> u64 {
+        let size = if self.min_stack > KERNEL_STACK_SIZE {
+            self.min_stack
+        } else {
+            KERNEL_STACK_SIZE
+        };
+        let align = if self.stack_align() > PAGE_SIZE {
+            self.stack_align()
+        } else {
+            PAGE_SIZE
+        };
+        return (size + align - 1) & !(align - 1);
+    }
+
+    pub const fn stack_align(&self) -> u64 {
+        if self.stack_align == 0 {
+            DEFAULT_STACK_ALIGN
+        } else {
+            self.stack_align
+        }
+    }
+
+    /// The kernel's stack from `frames`, its top aligned
+    pub fn alloc_stack(&self, frames: &mut impl FrameAllocator) -> Option<PhysRange> {
+        frames.alloc_frames(self.stack_size(), self.stack_align())
+    }
+
+    /// The first requirement `layout` doesn't meet
+    pub fn check(&self, layout: &EntryLayout) -> Result<(), UnmetRequirement> {
+        let stack = layout.stack.len();
+        if stack < self.min_stack {
+            return Err(UnmetRequirement::StackSize {
+                need: self.min_stack,
+                have: stack,
+            });
+        }
+
+        let top = layout.stack.end();
+        let align = top & top.wrapping_neg();
+        if align < self.stack_align() {
+            return Err(UnmetRequirement::StackAlign {
+                need: self.stack_align(),
+                have: align,
+            });
+        }
+
+        let free = layout.largest_free;
+        if free < self.min_free_contiguous {
+            return Err(UnmetRequirement::FreeMemory {
+                need: self.min_free_contiguous,
+                have: free,
+            });
+        }
+
+        let distance = layout.bootinfo_distance();
+        if self.max_bootinfo_distance != 0 && distance > self.max_bootinfo_distance {
+            return Err(UnmetRequirement::BootinfoDistance {
+                allowed: self.max_bootinfo_distance,
+                have: distance,
+            });
+        }
+        return Ok(());
+    }
+}
+
+impl Default for KernelRequirements {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Where things ended up, what `KernelRequirements::check` looks at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryLayout {
+    /// The kernel's initial stack, `rsp` starts at its end
+    pub stack: PhysRange,
+    pub kernel: PhysRange,
+    pub bootinfo: PhysRange,
+    /// Length of the largest free region
+    pub largest_free: u64,
+}
+
+impl EntryLayout {
+    /// Length of the span covering both the kernel image and `Bootinfo`
+    pub fn bootinfo_distance(&self) -> u64 {
+        let start = core::cmp::min(self.kernel.start(), self.bootinfo.start());
+        let end = core::cmp::max(self.kernel.end(), self.bootinfo.end());
+        return end - start;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub enum UnmetRequirement {
+    StackSize { need: u64, have: u64 },
+    StackAlign { need: u64, have: u64 },
+    FreeMemory { need: u64, have: u64 },
+    BootinfoDistance { allowed: u64, have: u64 },
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Where the kernel's stack, the kernel and `self` are, with the
+    /// largest free region of `memory`
+    pub fn entry_layout(&self) -> EntryLayout {
+        let size = core::mem::size_of::<Self>() as u64;
+        EntryLayout {
+            stack: self.kernel_stack,
+            kernel: self.kernel_pslice.into(),
+            bootinfo: PhysRange::new(self.this.as_u64(), size).unwrap_or(PhysRange::empty()),
+            largest_free: self.memory.largest.len(),
+        }
+    }
+
+    /// Checks `requirements` against `entry_layout`, once nothing is
+    /// allocated anymore. An unmet one is kept in `last_error`.
+    pub fn check_requirements(
+        &mut self,
+        requirements: &KernelRequirements,
+    ) -> Result<(), UnmetRequirement> {
+        let result = requirements.check(&self.entry_layout());
+        if let Err(e) = result {
+            self.last_error = Some(BootError::Requirement(e));
+        }
+        return result;
+    }
+}
@@ -0,0 +1,245 @@
+//! Physical memory the kernel must never hand out, as one sorted table.
+//! It is merged at handoff from the memory map, explicit `reserve_region`
+//! calls, the modules and the loader's own allocations, so the kernel can
+//! seed its frame allocator from it without merging anything itself.
+
+use crate::{is_free, Bootinfo};
+use arrayvec::ArrayVec;
+use cpu::PhysRange;
+use uefi::memory::{Descriptor, Type};
+
+/// Entries of `Bootinfo::reserved`
+pub const MAX_RESERVED: usize = 64;
+/// Entries of `Bootinfo::reserve_requests`
+pub const MAX_RESERVE_REQUESTS: usize = 16;
+
+/// What a range is reserved for. Later kinds are more specific, merged
+/// entries keep the most specific one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum ReservedKind {
+    /// Not usable according to the memory map, or of an unknown type
+    Firmware = 0,
+    RuntimeServices,
+    /// ACPI tables and NVS
+    Acpi,
+    /// Kept for its contents
+    Persistent,
+    /// LoaderCode and LoaderData nothing more specific covers
+    Loader,
+    Trace,
+    /// Handoff trampoline outside the kernel
+    Handoff,
+    PerCpu,
+    Module,
+    Kernel,
+    Bootinfo,
+    /// Crash dump region, kept for the next boot
+    CrashDump,
+}
+
+impl ReservedKind {
+    pub const ALL: [ReservedKind; 12] = [
+        Self::Firmware,
+        Self::RuntimeServices,
+        Self::Acpi,
+        Self::Persistent,
+        Self::Loader,
+        Self::Trace,
+        Self::Handoff,
+        Self::PerCpu,
+        Self::Module,
+        Self::Kernel,
+        Self::Bootinfo,
+        Self::CrashDump,
+    ];
+
+    pub fn from_u8(x: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|&k| k as u8 == x)
+    }
+
+    /// Kind of a memory map descriptor, `None` if it's free, see `is_free`
+    pub fn of_descriptor(descriptor: &Descriptor) -> Option<Self> {
+        if is_free(descriptor.memory_type()) {
+            return None;
+        }
+        let kind = match descriptor.memory_type() {
+            Some(Type::RuntimeServicesCode) | Some(Type::RuntimeServicesData) => {
+                Self::RuntimeServices
+            }
+            Some(Type::AcpiReclaim) | Some(Type::AcpiNVS) => Self::Acpi,
+            Some(Type::Persistent) => Self::Persistent,
+            Some(Type::LoaderCode) | Some(Type::LoaderData) => Self::Loader,
+            _ => Self::Firmware,
+        };
+        return Some(kind);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct ReservedRange {
+    pub range: PhysRange,
+    pub kind: ReservedKind,
+}
+
+impl ReservedRange {
+    /// Overlapping, or adjacent and of the same kind
+    fn touches(&self, other: &Self) -> bool {
+        let adjacent =
+            self.range.end() == other.range.start() || other.range.end() == self.range.start();
+        return self.range.overlaps(&other.range) || (adjacent && self.kind == other.kind);
+    }
+
+    /// Covers both and whatever is between them
+    fn merge(&self, other: &Self) -> Self {
+        let start = self.range.start().min(other.range.start());
+        let end = self.range.end().max(other.range.end());
+        Self {
+            range: PhysRange::from_start_end(start, end).unwrap(),
+            kind: self.kind.max(other.kind),
+        }
+    }
+}
+
+/// Bytes between `a` and a later `b`
+fn gap(a: &ReservedRange, b: &ReservedRange) -> u64 {
+    b.range.start().saturating_sub(a.range.end())
+}
+
+/// Sorted by start, with no overlapping entries. When it's full, the
+/// entries closest to each other are merged over the gap between them,
+/// reserving too much is safe, dropping a range isn't. `N` can't be 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct ReservedTable<const N: usize> {
+    entries: ArrayVec<ReservedRange, N>,
+    widened: bool,
+}
+
+impl<const N: usize> ReservedTable<N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: ArrayVec::new_const(),
+            widened: false,
+        }
+    }
+
+    /// Adds `range`, merging it with entries it overlaps and with adjacent
+    /// ones of the same kind. Empty ranges are ignored.
+    pub fn insert(&mut self, range: PhysRange, kind: ReservedKind) {
+        if range.is_empty() {
+            return;
+        }
+
+        let mut new = ReservedRange { range, kind };
+        while let Some(i) = self.entries.iter().position(|x| new.touches(x)) {
+            new = new.merge(&self.entries.remove(i));
+        }
+        if self.entries.is_full() {
+            self.make_room(&mut new);
+        }
+
+        let at = self
+            .entries
+            .iter()
+            .position(|x| x.range.start() > new.range.start())
+            .unwrap_or(self.entries.len());
+        self.entries.insert(at, new);
+    }
+
+    /// Merges the two closest entries, counting `new` as one of them
+    fn make_room(&mut self, new: &mut ReservedRange) {
+        self.widened = true;
+        let at = self
+            .entries
+            .iter()
+            .position(|x| x.range.start() > new.range.start())
+            .unwrap_or(self.entries.len());
+
+        let pair = (1..self.entries.len())
+            .map(|i| (gap(&self.entries[i - 1], &self.entries[i]), i))
+            .min_by_key(|&(gap, _)| gap);
+        let prev = at.checked_sub(1).map(|i| (gap(&self.entries[i], new), i));
+        let next = self.entries.get(at).map(|x| (gap(new, x), at));
+        let (own_gap, neighbour) = match (prev, next) {
+            (Some(prev), Some(next)) if next.0 < prev.0 => next,
+            (prev, next) => prev
+                .or(next)
+                .expect("ReservedTable can't have a capacity of 0"),
+        };
+
+        match pair {
+            Some((pair_gap, i)) if pair_gap < own_gap => {
+                let next = self.entries.remove(i);
+                self.entries[i - 1] = self.entries[i - 1].merge(&next);
+            }
+            _ => *new = new.merge(&self.entries.remove(neighbour)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (PhysRange, ReservedKind)> + '_ {
+        self.entries.iter().map(|x| (x.range, x.kind))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries were merged over gaps to fit, more is reserved than asked for
+    pub fn widened(&self) -> bool {
+        self.widened
+    }
+}
+
+impl<const N: usize> Default for ReservedTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Keeps the kernel away from `range`, merged into `reserved` at handoff
+    pub fn reserve_region(&mut self, range: PhysRange, kind: ReservedKind) {
+        self.reserve_requests.insert(range, kind);
+    }
+
+    /// Rebuilds `reserved` from everything that isn't free: the memory map,
+    /// `reserve_region` calls, the modules, the kernel and its stack,
+    /// per-CPU areas and `Bootinfo` itself. Called by `BootinfoBuilder::finish`.
+    pub fn merge_reserved(&mut self) {
+        let mut table = ReservedTable::new();
+        for descriptor in &self.uefi_meminfo {
+            let kind = ReservedKind::of_descriptor(descriptor);
+            if let (Some(kind), Some(range)) = (kind, descriptor.phys_range()) {
+                table.insert(range, kind);
+            }
+        }
+        for (range, kind) in self.reserve_requests.iter() {
+            table.insert(range, kind);
+        }
+        for module in &self.modules {
+            table.insert(module.data.into(), ReservedKind::Module);
+        }
+        table.insert(self.kernel_pslice.into(), ReservedKind::Kernel);
+        table.insert(self.percpu.phys.into(), ReservedKind::PerCpu);
+        table.insert(self.kernel_stack, ReservedKind::Kernel);
+        if self.this.as_u64() != 0 {
+            let this = PhysRange::new(self.this.as_u64(), core::mem::size_of::<Self>() as u64);
+            table.insert(this.unwrap_or(PhysRange::empty()), ReservedKind::Bootinfo);
+        }
+
+        table.widened |= self.reserve_requests.widened;
+        self.reserved = table;
+    }
+
+    /// Sorted, non-overlapping ranges the kernel must never allocate from,
+    /// see `merge_reserved`
+    pub fn reserved_ranges(&self) -> impl Iterator<Item = (PhysRange, ReservedKind)> + '_ {
+        self.reserved.iter()
+    }
+}
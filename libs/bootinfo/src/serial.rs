@@ -0,0 +1,647 @@
+use crate::{timestamp, Config, ConsoleDevice, TxRing, TX_RING_SIZE};
+#[cfg(target_arch = "x86_64")]
+use uart_16550::SerialPort;
+use uefi::PolledInput;
+
+/// I/O ports of `ttyS0` and `ttyS1` in the config
+pub const COM_PORTS: [u16; 2] = [0x3F8, 0x2F8];
+const TTY_NAMES: [&str; 2] = ["ttyS0", "ttyS1"];
+const CLOCK_KEYS: [&str; 2] = ["ttyS0.clock", "ttyS1.clock"];
+
+/// Input clock of a standard 16550, baud rate is this divided by 16 * divisor
+pub const UART_CLOCK_HZ: u32 = 1_843_200;
+pub const DEFAULT_BAUD: u32 = 115_200;
+
+/// Line status polls a byte may wait for THR to empty
+/// before the port is considered wedged
+const THR_EMPTY_SPINS: u32 = 100_000;
+/// Line status polls of each wait in the loopback test, a byte takes
+/// about 100 polls at 115200 baud, so this is far from any real UART
+pub const PROBE_SPINS: u32 = 10_000;
+/// Sent to itself by the loopback test
+const LOOPBACK_BYTE: u8 = 0xAE;
+
+/* Register offsets */
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+/* Same offset as FIFO_CONTROL, when read */
+const INTERRUPT_ID: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const SCRATCH: u16 = 7;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_THR_EMPTY: u8 = 1 << 5;
+/* DTR, RTS and OUT2 */
+const MODEM_CONTROL_NORMAL: u8 = 0x0B;
+/* Loopback with RTS, OUT1 and OUT2, so every modem status line reads back */
+const MODEM_CONTROL_LOOPBACK: u8 = 0x1E;
+/// Nothing drives the bus, every port reads as this
+const FLOATING_BUS: u8 = 0xFF;
+/// Both bits set in the interrupt ID register once FIFOs are enabled,
+/// a 16450 has none
+const INTERRUPT_ID_FIFO: u8 = 0xC0;
+/// Bytes a 16550 takes at once when THR is empty
+const FIFO_DEPTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SerialError {
+    /// Baud rate can't be derived from the UART clock within 2%
+    UnsupportedBaud,
+    /// Value in the config isn't a number, `off` or a port name
+    Malformed,
+    /// Interactive console points to a disabled port
+    ConsoleDisabled,
+    /// `probe_uart` or the loopback test found no UART
+    NotPresent(UartProbe),
+}
+
+/// What probing a port found, in the order of the checks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UartProbe {
+    /// Disabled, or not initialized yet
+    Untested,
+    /// Passed every check, including the loopback test
+    Present,
+    /// The line status register reads 0xFF
+    FloatingBus,
+    /// The scratch register doesn't keep what was written to it
+    NoScratch,
+    /// A byte sent in loopback mode didn't come back in time
+    NoLoopback,
+}
+
+/// Port I/O, so that probing can be tested against a fake UART
+pub trait PortIo {
+    fn inb(&mut self, port: u16) -> u8;
+    fn outb(&mut self, port: u16, x: u8);
+}
+
+/// Real port I/O with `cpu::inb` and `cpu::outb`
+pub struct CpuPorts(());
+
+impl CpuPorts {
+    /// # Safety
+    /// Ports written through it must belong to a UART or to nothing.
+    pub unsafe fn new() -> Self {
+        Self(())
+    }
+}
+
+impl PortIo for CpuPorts {
+    fn inb(&mut self, port: u16) -> u8 {
+        /* SAFETY: see `new` */
+        unsafe { cpu::inb(port) }
+    }
+
+    fn outb(&mut self, port: u16, x: u8) {
+        /* SAFETY: see `new` */
+        unsafe { cpu::outb(port, x) }
+    }
+}
+
+/// Whether something that behaves like a 16550 is at `base`, without
+/// the loopback test, which needs the UART programmed. Only the scratch
+/// register is written and it's restored, so it's fine to call before
+/// any serial output. `Present` doesn't mean that a terminal is connected.
+pub fn probe_uart(io: &mut impl PortIo, base: u16) -> UartProbe {
+    if io.inb(base + LINE_STATUS) == FLOATING_BUS {
+        return UartProbe::FloatingBus;
+    }
+
+    let saved = io.inb(base + SCRATCH);
+    let ok = [0x55, 0xAA].iter().all(|&pattern| {
+        io.outb(base + SCRATCH, pattern);
+        io.inb(base + SCRATCH) == pattern
+    });
+    io.outb(base + SCRATCH, saved);
+    if !ok {
+        return UartProbe::NoScratch;
+    }
+    return UartProbe::Present;
+}
+
+/// Polls the line status of the UART at `base` until `bit` is set,
+/// at most `PROBE_SPINS` times
+fn wait_for_status(io: &mut impl PortIo, base: u16, bit: u8) -> bool {
+    (0..PROBE_SPINS).any(|_| io.inb(base + LINE_STATUS) & bit != 0)
+}
+
+/// Sends a byte to the programmed UART at `base` in loopback mode and
+/// expects it back, every wait bounded by `PROBE_SPINS`. The modem
+/// control register is restored afterwards.
+pub fn loopback_echo(io: &mut impl PortIo, base: u16) -> bool {
+    let saved = io.inb(base + MODEM_CONTROL);
+    io.outb(base + MODEM_CONTROL, MODEM_CONTROL_LOOPBACK);
+
+    /* Stale input would be taken for the echo, reading DATA drains it */
+    let drained = (0..PROBE_SPINS).any(|_| {
+        let empty = io.inb(base + LINE_STATUS) & LINE_STATUS_DATA_READY == 0;
+        if !empty {
+            io.inb(base + DATA);
+        }
+        empty
+    });
+    let mut ok = drained && wait_for_status(io, base, LINE_STATUS_THR_EMPTY);
+    if ok {
+        io.outb(base + DATA, LOOPBACK_BYTE);
+        ok = wait_for_status(io, base, LINE_STATUS_DATA_READY)
+            && io.inb(base + DATA) == LOOPBACK_BYTE;
+    }
+
+    io.outb(base + MODEM_CONTROL, saved);
+    return ok;
+}
+
+/// Masks every interrupt of the UART at `base`, leaving it in polling
+/// mode. Has the signature of an `InterruptSources` disarm function.
+///
+/// # Safety
+/// There must be a 16550 at `base`.
+pub unsafe fn mask_uart_interrupts(base: u64) {
+    cpu::outb(base as u16 + INTERRUPT_ENABLE, 0x00);
+}
+
+/// 16550 compatible UART, written to by polling
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Uart {
+    base: u16,
+    baud: u32,
+    clock_hz: u32,
+    enabled: bool,
+    /// Programmed by `init`, nothing is sent before that
+    ready: bool,
+    /// THR stayed full for too long, the port is skipped from then on
+    wedged: bool,
+    /// What `init` found
+    probe: UartProbe,
+    /// `FIFO_DEPTH` bytes can be written at once, one otherwise
+    fifo: bool,
+}
+
+impl Uart {
+    pub const fn new(base: u16, baud: u32) -> Self {
+        Self {
+            base,
+            baud,
+            clock_hz: UART_CLOCK_HZ,
+            enabled: true,
+            ready: false,
+            wedged: false,
+            probe: UartProbe::Untested,
+            fifo: false,
+        }
+    }
+
+    pub const fn disabled(base: u16) -> Self {
+        Self {
+            enabled: false,
+            ..Self::new(base, DEFAULT_BAUD)
+        }
+    }
+
+    /// For UARTs with a non-standard input clock, e.g. for rates over 115200
+    pub const fn with_clock(self, clock_hz: u32) -> Self {
+        Self { clock_hz, ..self }
+    }
+
+    pub const fn base(&self) -> u16 {
+        self.base
+    }
+
+    pub const fn baud(&self) -> u32 {
+        self.baud
+    }
+
+    pub const fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub const fn is_wedged(&self) -> bool {
+        self.wedged
+    }
+
+    pub const fn probe(&self) -> UartProbe {
+        self.probe
+    }
+
+    pub const fn has_fifo(&self) -> bool {
+        self.fifo
+    }
+
+    /// Initialized and not disabled since
+    pub const fn is_ready(&self) -> bool {
+        self.enabled && self.ready
+    }
+
+    /// Divisor latch value for `baud`, `None` if the actual rate would be
+    /// off by more than 2%
+    pub fn divisor(&self) -> Option<u16> {
+        let rate = (self.baud as u64).checked_mul(16).filter(|&x| x != 0)?;
+        let clock = self.clock_hz as u64;
+        let divisor = (clock + rate / 2) / rate;
+        if divisor == 0 || divisor > u16::MAX as u64 {
+            return None;
+        }
+
+        let actual = clock / (16 * divisor);
+        let error = if actual > self.baud as u64 {
+            actual - self.baud as u64
+        } else {
+            self.baud as u64 - actual
+        };
+        if error * 50 > self.baud as u64 {
+            return None;
+        }
+
+        return Some(divisor as u16);
+    }
+
+    /// `program` with real port I/O
+    ///
+    /// # Safety
+    /// Nothing other than a UART may be at `base`.
+    pub unsafe fn init(&mut self) -> Result<(), SerialError> {
+        return self.program(&mut CpuPorts::new());
+    }
+
+    /// Programs baud rate, 8N1 and FIFOs, with interrupts off, if
+    /// `probe_uart` and then the loopback test find a UART. Otherwise
+    /// nothing is ever sent to it.
+    pub fn program(&mut self, io: &mut impl PortIo) -> Result<(), SerialError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let divisor = self.divisor().ok_or(SerialError::UnsupportedBaud)?;
+        self.ready = false;
+        self.probe = probe_uart(io, self.base);
+        if self.probe != UartProbe::Present {
+            return Err(SerialError::NotPresent(self.probe));
+        }
+
+        io.outb(self.base + INTERRUPT_ENABLE, 0x00);
+        /* DLAB, so the next two registers are the divisor latch */
+        io.outb(self.base + LINE_CONTROL, 0x80);
+        io.outb(self.base + DATA, divisor as u8);
+        io.outb(self.base + INTERRUPT_ENABLE, (divisor >> 8) as u8);
+        io.outb(self.base + LINE_CONTROL, 0x03);
+        io.outb(self.base + FIFO_CONTROL, 0xC7);
+        self.fifo = io.inb(self.base + INTERRUPT_ID) & INTERRUPT_ID_FIFO == INTERRUPT_ID_FIFO;
+        if !loopback_echo(io, self.base) {
+            self.probe = UartProbe::NoLoopback;
+            return Err(SerialError::NotPresent(self.probe));
+        }
+        io.outb(self.base + MODEM_CONTROL, MODEM_CONTROL_NORMAL);
+
+        self.ready = true;
+        self.wedged = false;
+        return Ok(());
+    }
+
+    /// Gives up, and marks the port wedged, if THR doesn't empty in time
+    pub fn send(&mut self, byte: u8) {
+        if !self.enabled || !self.ready || self.wedged {
+            return;
+        }
+
+        /* SAFETY: `init` checked there's a UART */
+        unsafe {
+            for _ in 0..THR_EMPTY_SPINS {
+                if cpu::inb(self.base + LINE_STATUS) & LINE_STATUS_THR_EMPTY != 0 {
+                    cpu::outb(self.base + DATA, byte);
+                    return;
+                }
+                core::hint::spin_loop();
+            }
+        }
+
+        self.wedged = true;
+    }
+
+    /// Moves bytes from `ring` into the transmitter if it's empty, as many
+    /// as the FIFO takes, without waiting. Returns how many were moved.
+    /// What a THR-empty interrupt handler would call.
+    ///
+    /// # Safety
+    /// Nothing else may pop from `ring` at the same time.
+    pub unsafe fn fill_fifo<const N: usize>(
+        &mut self,
+        io: &mut impl PortIo,
+        ring: &TxRing<N>,
+    ) -> usize {
+        if !self.is_ready() || io.inb(self.base + LINE_STATUS) & LINE_STATUS_THR_EMPTY == 0 {
+            return 0;
+        }
+
+        let depth = if self.fifo { FIFO_DEPTH } else { 1 };
+        let mut moved = 0;
+        while moved < depth {
+            match ring.pop() {
+                Some(byte) => io.outb(self.base + DATA, byte),
+                None => break,
+            }
+            moved += 1;
+        }
+        return moved;
+    }
+
+    /// A received byte, if there is one
+    pub fn try_recv(&mut self) -> Option<u8> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        /* SAFETY: `init` checked there's a UART */
+        unsafe {
+            if cpu::inb(self.base + LINE_STATUS) & LINE_STATUS_DATA_READY == 0 {
+                return None;
+            }
+            return Some(cpu::inb(self.base + DATA));
+        }
+    }
+}
+
+/// EFI_SERIAL_IO_PROTOCOL as a console, for machines without a legacy
+/// UART. Firmware owns the device, so it stops working at ExitBootServices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EfiSerial {
+    protocol: *const uefi::SerialIoProtocol,
+}
+
+impl EfiSerial {
+    /// Programs the baud rate of `uart` and 8N1, like `Uart::init` does
+    ///
+    /// # Safety
+    /// `protocol` must stay valid until `SerialSinks::exit_boot_services`.
+    pub unsafe fn init(
+        protocol: &uefi::SerialIoProtocol,
+        uart: &Uart,
+    ) -> Result<Self, uefi::Error> {
+        let (parity, stop_bits) = (uefi::Parity::No, uefi::StopBits::One);
+        protocol.set_attributes(uart.baud as u64, parity, 8, stop_bits)?;
+        return Ok(Self { protocol });
+    }
+}
+
+impl ConsoleDevice for EfiSerial {
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.send_bytes(&[byte]);
+    }
+
+    fn send_bytes(&mut self, bytes: &[u8]) {
+        /* SAFETY: see `init` */
+        let _ = unsafe { (*self.protocol).write(bytes) };
+    }
+
+    fn try_recv(&mut self) -> Option<u8> {
+        let mut byte = [0u8];
+        /* SAFETY: see `init` */
+        match unsafe { (*self.protocol).read(&mut byte) } {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+}
+
+/// UARTs every boot log line is mirrored to. One of them is the interactive
+/// console for the boot menu and GDB stub, the kernel learns which one
+/// through `Bootinfo::serial_sinks`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SerialSinks {
+    pub ports: [Uart; 2],
+    console: u8,
+    /// Fallback console while boot services are up
+    efi: Option<EfiSerial>,
+    /// Output to the console is queued here if not null, see `use_tx_ring`
+    tx: *const TxRing<TX_RING_SIZE>,
+}
+
+impl SerialSinks {
+    /// `ttyS0` at 115200 as the console, `ttyS1` disabled
+    pub const fn new() -> Self {
+        Self {
+            ports: [
+                Uart::new(COM_PORTS[0], DEFAULT_BAUD),
+                Uart::disabled(COM_PORTS[1]),
+            ],
+            console: 0,
+            efi: None,
+            tx: core::ptr::null(),
+        }
+    }
+
+    /// Reads `ttyS0=<baud>|off`, `ttyS1=<baud>|off`, `ttySN.clock=<hz>`
+    /// and `console=ttySN`. Missing keys keep the defaults of `new`.
+    pub fn from_config(config: &Config) -> Result<Self, SerialError> {
+        let mut sinks = Self::new();
+
+        for (i, port) in sinks.ports.iter_mut().enumerate() {
+            match config.get(TTY_NAMES[i]) {
+                None => {}
+                Some("off") => *port = Uart::disabled(COM_PORTS[i]),
+                Some(baud) => {
+                    let baud = baud.parse().map_err(|_| SerialError::Malformed)?;
+                    *port = Uart::new(COM_PORTS[i], baud);
+                }
+            }
+            if let Some(clock) = config.get(CLOCK_KEYS[i]) {
+                let clock = clock.parse().map_err(|_| SerialError::Malformed)?;
+                *port = port.with_clock(clock);
+            }
+            if port.enabled && port.divisor().is_none() {
+                return Err(SerialError::UnsupportedBaud);
+            }
+        }
+
+        if let Some(console) = config.get("console") {
+            let index = TTY_NAMES.iter().position(|&name| name == console);
+            sinks.console = index.ok_or(SerialError::Malformed)? as u8;
+        }
+        if !sinks.console().enabled {
+            return Err(SerialError::ConsoleDisabled);
+        }
+
+        return Ok(sinks);
+    }
+
+    /// The port for interactive use
+    pub fn console(&self) -> &Uart {
+        &self.ports[self.console as usize]
+    }
+
+    pub fn console_index(&self) -> usize {
+        self.console as usize
+    }
+
+    /// A port passed the probe, even if it isn't the console
+    pub fn uart_found(&self) -> bool {
+        self.ports
+            .iter()
+            .any(|port| port.probe == UartProbe::Present)
+    }
+
+    /// The console for `Bootinfo::serial`, `None` unless it passed the probe
+    #[cfg(target_arch = "x86_64")]
+    pub fn legacy_port(&self) -> Option<SerialPort> {
+        if !self.console().is_ready() {
+            return None;
+        }
+        /* SAFETY: the probe found a UART there */
+        return Some(unsafe { SerialPort::new(self.console().base) });
+    }
+
+    /// Input is only taken from the console, or from EFI serial
+    /// if the console isn't usable. Queued output makes progress while
+    /// input is polled, so a prompt is out before its answer is awaited.
+    pub fn try_recv(&mut self) -> Option<u8> {
+        self.top_up();
+        let console = &mut self.ports[self.console as usize];
+        match &mut self.efi {
+            Some(efi) if !console.is_ready() => efi.try_recv(),
+            _ => console.try_recv(),
+        }
+    }
+
+    /// Whether a legacy console is missing, so that output is lost unless
+    /// `use_efi_serial` gets a fallback. Meaningful after `init`.
+    pub fn needs_fallback(&self) -> bool {
+        !self.console().is_ready()
+    }
+
+    /// Mirrors output to `efi` too, until `exit_boot_services`
+    pub fn use_efi_serial(&mut self, efi: EfiSerial) {
+        self.efi = Some(efi);
+    }
+
+    pub fn has_efi_serial(&self) -> bool {
+        self.efi.is_some()
+    }
+
+    /// Queues output to the console in `ring` instead of waiting for the
+    /// UART, mirrors stay synchronous. `flush` sends what's queued.
+    ///
+    /// # Safety
+    /// `ring` may be used by nothing else, including copies of `self`
+    /// made before `unbuffered` at the same time as `self`.
+    pub unsafe fn use_tx_ring(&mut self, ring: &'static TxRing<TX_RING_SIZE>) {
+        self.tx = ring;
+    }
+
+    /// Without the transmit ring, for whoever outlives its owner, like
+    /// the kernel. Anything still queued has to be flushed first.
+    pub fn unbuffered(self) -> Self {
+        Self {
+            tx: core::ptr::null(),
+            ..self
+        }
+    }
+
+    pub fn tx_ring(&self) -> Option<&TxRing<TX_RING_SIZE>> {
+        /* SAFETY: set from a `&'static` by `use_tx_ring` */
+        unsafe { self.tx.as_ref() }
+    }
+
+    /// Waits until everything queued is out, e.g. at stage boundaries
+    /// and before the kernel gets control
+    pub fn flush(&mut self) {
+        /* SAFETY: set from a `&'static` by `use_tx_ring` */
+        let ring = match unsafe { self.tx.as_ref() } {
+            Some(ring) => ring,
+            None => return,
+        };
+        let start = timestamp();
+        let console = &mut self.ports[self.console as usize];
+        /* SAFETY: see `use_tx_ring` */
+        while let Some(byte) = unsafe { ring.pop() } {
+            console.send(byte);
+        }
+        ring.add_blocked(timestamp().wrapping_sub(start));
+    }
+
+    /// Sends queued bytes as far as the console takes them without waiting
+    fn top_up(&mut self) {
+        /* SAFETY: set from a `&'static` by `use_tx_ring` */
+        if let Some(ring) = unsafe { self.tx.as_ref() } {
+            let console = &mut self.ports[self.console as usize];
+            /* SAFETY: `init` found a UART at the console's ports if it's
+             * ready, and see `use_tx_ring` for the ring */
+            unsafe { console.fill_fifo(&mut CpuPorts::new(), ring) };
+        }
+    }
+
+    /// Queues `byte` for the console, sending the oldest queued byte
+    /// synchronously if the ring is full
+    fn queue(&mut self, ring: &TxRing<TX_RING_SIZE>, byte: u8) {
+        let console = &mut self.ports[self.console as usize];
+        /* SAFETY: see `use_tx_ring` */
+        unsafe {
+            if let Err(byte) = ring.push(byte) {
+                let start = timestamp();
+                if let Some(oldest) = ring.pop() {
+                    console.send(oldest);
+                }
+                let _ = ring.push(byte);
+                ring.add_blocked(timestamp().wrapping_sub(start));
+            }
+        }
+    }
+
+    /// Drops the EFI serial fallback, its device belongs to firmware
+    pub fn exit_boot_services(&mut self) {
+        self.efi = None;
+    }
+
+    /// Initializes every enabled port, ports that fail or aren't there
+    /// are disabled and the first error is returned
+    ///
+    /// # Safety
+    /// See `Uart::init`.
+    pub unsafe fn init(&mut self) -> Result<(), SerialError> {
+        let mut result = Ok(());
+        for port in self.ports.iter_mut() {
+            if let Err(e) = port.init() {
+                port.enabled = false;
+                result = result.and(Err(e));
+            }
+        }
+        return result;
+    }
+}
+
+/// Lets the console take part in `uefi::wait_key_or_timeout`
+impl PolledInput for SerialSinks {
+    fn poll(&mut self) -> Option<u8> {
+        self.try_recv()
+    }
+}
+
+/// Mirrors to every port, a wedged port doesn't stall the others.
+/// Output to the console is only queued if there's a transmit ring.
+impl core::fmt::Write for SerialSinks {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        /* SAFETY: set from a `&'static` by `use_tx_ring` */
+        let ring = unsafe { self.tx.as_ref() }.filter(|_| self.console().is_ready());
+        let console = self.console as usize;
+        for byte in s.bytes() {
+            for (i, port) in self.ports.iter_mut().enumerate() {
+                if i != console || ring.is_none() {
+                    port.send(byte);
+                }
+            }
+            if let Some(ring) = ring {
+                self.queue(ring, byte);
+            }
+        }
+        self.top_up();
+        if let Some(efi) = &mut self.efi {
+            efi.send_bytes(s.as_bytes());
+        }
+        return Ok(());
+    }
+}
@@ -0,0 +1,228 @@
+//! A/B kernel selection for unattended updates. An updater writes the new
+//! kernel into the other slot and hands it a few tries, the loader counts
+//! them down and goes back to the known good slot once they run out
+//! without the kernel confirming the boot. Configured in `sovos.cfg` by
+//! naming the kernel of each slot in the archive:
+//!
+//! ```text
+//! kernel_a=kernel.elf
+//! kernel_b=kernel-b.elf
+//! ```
+//!
+//! The state is the `SovosBootSlot` variable of `Guid::SOVOS_VARIABLE`,
+//! non-volatile with boot service and runtime access, 4 bytes:
+//!
+//! | byte | field             |                                         |
+//! |------|-------------------|-----------------------------------------|
+//! | 0    | version           | `SLOT_STATE_VERSION`                    |
+//! | 1    | `slot`            | 0 for A, 1 for B                        |
+//! | 2    | `tries_remaining` | boots left before falling back          |
+//! | 3    | `last_result`     | 0 pending, 1 success, 2 fell back       |
+//!
+//! To try a new kernel, an updater writes `slot` = the new slot,
+//! `tries_remaining` = N and `last_result` = pending. After a boot it
+//! considers successful the kernel writes the variable back with
+//! `last_result` = success and the rest unchanged, see
+//! `SlotState::mark_success`. A missing variable boots slot A.
+
+use uefi::{Guid, RuntimeServices, VariableAttributes};
+
+pub const SLOT_STATE_VERSION: u8 = 1;
+pub const SLOT_STATE_SIZE: usize = 4;
+pub const SLOT_VARIABLE: [u16; 14] = uefi::ucs2("SovosBootSlot");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Slot {
+    A = 0,
+    B,
+}
+
+impl Slot {
+    pub const fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+
+    /// Key of the slot's kernel in the config
+    pub const fn config_key(self) -> &'static str {
+        match self {
+            Self::A => "kernel_a",
+            Self::B => "kernel_b",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BootResult {
+    /// `slot` is being tried and wasn't confirmed yet
+    Pending = 0,
+    /// The kernel of `slot` confirmed a boot
+    Success,
+    /// The tried slot ran out of tries, `slot` is the one fallen back to
+    FellBack,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotState {
+    pub slot: Slot,
+    pub tries_remaining: u8,
+    pub last_result: BootResult,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotError {
+    /// Wrong size or version
+    Malformed,
+    BadSlot,
+    BadResult,
+    Variable(uefi::Error),
+}
+
+impl SlotState {
+    /// What a missing variable means
+    pub const fn initial() -> Self {
+        Self {
+            slot: Slot::A,
+            tries_remaining: 0,
+            last_result: BootResult::Success,
+        }
+    }
+
+    /// `tries` boots of `slot` before going back to the other one
+    pub const fn trial(slot: Slot, tries: u8) -> Self {
+        Self {
+            slot,
+            tries_remaining: tries,
+            last_result: BootResult::Pending,
+        }
+    }
+
+    /// What the kernel writes once it booted fine
+    pub const fn mark_success(self) -> Self {
+        Self {
+            last_result: BootResult::Success,
+            ..self
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SlotError> {
+        let bytes = match bytes {
+            &[SLOT_STATE_VERSION, slot, tries, result] => (slot, tries, result),
+            _ => return Err(SlotError::Malformed),
+        };
+        let slot = match bytes.0 {
+            0 => Slot::A,
+            1 => Slot::B,
+            _ => return Err(SlotError::BadSlot),
+        };
+        let last_result = match bytes.2 {
+            0 => BootResult::Pending,
+            1 => BootResult::Success,
+            2 => BootResult::FellBack,
+            _ => return Err(SlotError::BadResult),
+        };
+
+        return Ok(Self {
+            slot,
+            tries_remaining: bytes.1,
+            last_result,
+        });
+    }
+
+    pub const fn to_bytes(self) -> [u8; SLOT_STATE_SIZE] {
+        [
+            SLOT_STATE_VERSION,
+            self.slot as u8,
+            self.tries_remaining,
+            self.last_result as u8,
+        ]
+    }
+
+    /// Which slot to boot and the state to write before anything of it is
+    /// loaded, `None` if the state stays as it is
+    pub fn plan(self) -> SlotPlan {
+        let (boot, write) = match self.last_result {
+            BootResult::Success | BootResult::FellBack => (self.slot, None),
+            BootResult::Pending if self.tries_remaining != 0 => {
+                let next = Self {
+                    tries_remaining: self.tries_remaining - 1,
+                    ..self
+                };
+                (self.slot, Some(next))
+            }
+            BootResult::Pending => {
+                let next = Self {
+                    slot: self.slot.other(),
+                    tries_remaining: 0,
+                    last_result: BootResult::FellBack,
+                };
+                (next.slot, Some(next))
+            }
+        };
+
+        return SlotPlan {
+            state: self,
+            boot,
+            write,
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotPlan {
+    /// State found in the variable
+    pub state: SlotState,
+    pub boot: Slot,
+    pub write: Option<SlotState>,
+}
+
+impl SlotPlan {
+    /// A pending slot ran out of tries on this boot
+    pub fn falls_back(&self) -> bool {
+        self.boot != self.state.slot
+    }
+}
+
+fn attributes() -> VariableAttributes {
+    VariableAttributes::new()
+        .set_non_volatile()
+        .set_bootservice_access()
+        .set_runtime_access()
+}
+
+/// Reads the state, `SlotState::initial` if there is none
+pub fn read_slot_state(rt: &RuntimeServices) -> Result<SlotState, SlotError> {
+    /* One more byte, so a longer variable isn't silently cut */
+    let mut buf = [0u8; SLOT_STATE_SIZE + 1];
+    match rt.get_variable(&SLOT_VARIABLE, &Guid::SOVOS_VARIABLE, &mut buf) {
+        Ok((_, bytes)) => SlotState::from_bytes(bytes),
+        Err(uefi::Error::NotFound) => Ok(SlotState::initial()),
+        Err(uefi::Error::BufferTooSmall) => Err(SlotError::Malformed),
+        Err(e) => Err(SlotError::Variable(e)),
+    }
+}
+
+pub fn write_slot_state(rt: &RuntimeServices, state: SlotState) -> Result<(), SlotError> {
+    rt.set_variable(
+        &SLOT_VARIABLE,
+        &Guid::SOVOS_VARIABLE,
+        attributes(),
+        &state.to_bytes(),
+    )
+    .map_err(SlotError::Variable)
+}
+
+/// Reads the state, decides which slot boots and writes the new state
+/// right away. A power loss after this costs a try instead of giving
+/// one back, so it must run before the kernel is loaded.
+pub fn begin_slot_boot(rt: &RuntimeServices) -> Result<SlotPlan, SlotError> {
+    let plan = read_slot_state(rt)?.plan();
+    if let Some(state) = plan.write {
+        write_slot_state(rt, state)?;
+    }
+    return Ok(plan);
+}
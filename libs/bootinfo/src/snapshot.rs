@@ -0,0 +1,112 @@
+use crate::Bootinfo;
+use cpu::paging::{Entry, Table, ENTRIES_PER_TABLE};
+
+/// Page tables embedded in `Bootinfo`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OwnedTable {
+    Pml4 = 0,
+    Pdp = 1,
+    Pd = 2,
+    Pt = 3,
+}
+
+const OWNED_TABLES: [OwnedTable; 4] = [
+    OwnedTable::Pml4,
+    OwnedTable::Pdp,
+    OwnedTable::Pd,
+    OwnedTable::Pt,
+];
+
+/// An entry that changed since `Bootinfo::snapshot_tables`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TableMismatch {
+    pub table: OwnedTable,
+    pub index: usize,
+    pub expected: u64,
+    pub found: u64,
+}
+
+/// Hashes and copies of page tables `Bootinfo` owns. Hashes make the common
+/// case cheap, copies tell which entry changed. It's 16KiB, so it's kept
+/// outside of `Bootinfo`.
+pub struct TableSnapshot {
+    hashes: [u64; 4],
+    entries: [[u64; ENTRIES_PER_TABLE]; 4],
+}
+
+impl TableSnapshot {
+    pub const fn new() -> Self {
+        Self {
+            hashes: [0; 4],
+            entries: [[0; ENTRIES_PER_TABLE]; 4],
+        }
+    }
+}
+
+/* FNV-1a, this only has to catch accidents */
+fn hash(entries: &[u64; ENTRIES_PER_TABLE]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for entry in entries.iter() {
+        for &byte in entry.to_le_bytes().iter() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    return hash;
+}
+
+fn read_entries<E: Entry>(table: &Table<E>) -> [u64; ENTRIES_PER_TABLE] {
+    let mut entries = [0u64; ENTRIES_PER_TABLE];
+    for (raw, entry) in entries.iter_mut().zip(table.0.iter()) {
+        *raw = entry.as_u64();
+    }
+    return entries;
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    fn table_entries(&self, table: OwnedTable) -> [u64; ENTRIES_PER_TABLE] {
+        match table {
+            OwnedTable::Pml4 => read_entries(&self.paging_root),
+            OwnedTable::Pdp => read_entries(&self.pdp),
+            OwnedTable::Pd => read_entries(&self.pd),
+            OwnedTable::Pt => read_entries(&self.page_table),
+        }
+    }
+
+    /// Records current contents of every owned page table,
+    /// meant to be called after the last mapping pass
+    pub fn snapshot_tables(&self, snapshot: &mut TableSnapshot) {
+        for &table in &OWNED_TABLES {
+            let entries = self.table_entries(table);
+            snapshot.hashes[table as usize] = hash(&entries);
+            snapshot.entries[table as usize] = entries;
+        }
+    }
+
+    /// Checks that no owned page table changed since `snapshot_tables`,
+    /// returns the first entry that did
+    pub fn verify_tables(&self, snapshot: &TableSnapshot) -> Result<(), TableMismatch> {
+        for &table in &OWNED_TABLES {
+            let entries = self.table_entries(table);
+            if hash(&entries) == snapshot.hashes[table as usize] {
+                continue;
+            }
+
+            let expected = &snapshot.entries[table as usize];
+            let index = match (0..ENTRIES_PER_TABLE).find(|&i| entries[i] != expected[i]) {
+                Some(x) => x,
+                /* Only the stored hash got corrupted */
+                None => continue,
+            };
+
+            return Err(TableMismatch {
+                table,
+                index,
+                expected: expected[index],
+                found: entries[index],
+            });
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,198 @@
+//! Boot stages of the loader as a state machine. Every stage is entered
+//! through `Bootinfo::advance`, which checks it against `TRANSITIONS`,
+//! records it in the timeline and in `Bootinfo::stages`, so the kernel and
+//! crash dumps see how far the loader got. Code that needs a stage to have
+//! happened, like the kernel entry, asks `BootStages::missing_before`.
+
+use crate::Bootinfo;
+
+/// Ordered, a later stage compares greater
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum BootStage {
+    /// `efi_main` entered
+    Entry = 0,
+    /// Serial sinks configured from the config
+    ConsoleReady,
+    /// Kernel segments copied and mapped
+    KernelLoaded,
+    /// Per-CPU areas, handoff trampoline and entropy
+    HandoffPrepared,
+    ExitedBootServices,
+    /// GDT and IDT loaded, arena frozen
+    TablesReady,
+    JumpingToKernel,
+}
+
+impl BootStage {
+    pub const ALL: [BootStage; 7] = [
+        Self::Entry,
+        Self::ConsoleReady,
+        Self::KernelLoaded,
+        Self::HandoffPrepared,
+        Self::ExitedBootServices,
+        Self::TablesReady,
+        Self::JumpingToKernel,
+    ];
+
+    pub fn from_u8(x: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|&s| s as u8 == x)
+    }
+
+    /// Name in the timeline
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Entry => "stage: entry",
+            Self::ConsoleReady => "stage: console ready",
+            Self::KernelLoaded => "stage: kernel loaded",
+            Self::HandoffPrepared => "stage: handoff prepared",
+            Self::ExitedBootServices => "stage: exited boot services",
+            Self::TablesReady => "stage: tables ready",
+            Self::JumpingToKernel => "stage: jumping to kernel",
+        }
+    }
+
+    /// The firmware watchdog runs until ExitBootServices, so stages before
+    /// it re-arm it
+    pub const fn pets_watchdog(self) -> bool {
+        (self as u8) < (Self::ExitedBootServices as u8)
+    }
+
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Seconds the watchdog is re-armed for on every stage. Firmware arms it
+/// for 5 minutes, no stage should take anywhere near that.
+pub const STAGE_WATCHDOG_S: usize = 60;
+
+/// Every allowed `(from, to)` pair
+pub const TRANSITIONS: &[(BootStage, BootStage)] = {
+    use BootStage::*;
+    &[
+        (Entry, ConsoleReady),
+        (ConsoleReady, KernelLoaded),
+        (KernelLoaded, HandoffPrepared),
+        /* Nothing to prepare for a kernel without the extras */
+        (KernelLoaded, ExitedBootServices),
+        (HandoffPrepared, ExitedBootServices),
+        (ExitedBootServices, TablesReady),
+        (TablesReady, JumpingToKernel),
+    ]
+};
+
+pub fn is_allowed(from: BootStage, to: BootStage) -> bool {
+    TRANSITIONS.contains(&(from, to))
+}
+
+/// Stages that must have happened before `stage`, whatever the path
+pub const fn required_before(stage: BootStage) -> &'static [BootStage] {
+    use BootStage::*;
+    match stage {
+        Entry => &[],
+        ConsoleReady => &[Entry],
+        KernelLoaded => &[Entry, ConsoleReady],
+        HandoffPrepared => &[Entry, ConsoleReady, KernelLoaded],
+        ExitedBootServices => &[Entry, ConsoleReady, KernelLoaded],
+        TablesReady => &[Entry, ConsoleReady, KernelLoaded, ExitedBootServices],
+        JumpingToKernel => &[
+            Entry,
+            ConsoleReady,
+            KernelLoaded,
+            ExitedBootServices,
+            TablesReady,
+        ],
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IllegalTransition {
+    pub from: BootStage,
+    pub to: BootStage,
+}
+
+/// Current stage and every stage reached so far
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootStages {
+    current: BootStage,
+    reached: u8,
+    /// First illegal transition, later ones are only counted
+    illegal: Option<IllegalTransition>,
+    illegal_count: u8,
+}
+
+impl BootStages {
+    pub const fn new() -> Self {
+        Self {
+            current: BootStage::Entry,
+            reached: BootStage::Entry.bit(),
+            illegal: None,
+            illegal_count: 0,
+        }
+    }
+
+    pub const fn current(&self) -> BootStage {
+        self.current
+    }
+
+    pub const fn has_reached(&self, stage: BootStage) -> bool {
+        self.reached & stage.bit() != 0
+    }
+
+    /// Moves to `to`. An illegal transition is recorded, but the machine
+    /// still moves, it has to reflect where the loader really is.
+    pub fn try_advance(&mut self, to: BootStage) -> Result<(), IllegalTransition> {
+        let from = self.current;
+        self.current = to;
+        self.reached |= to.bit();
+
+        if is_allowed(from, to) {
+            return Ok(());
+        }
+
+        let illegal = IllegalTransition { from, to };
+        self.illegal.get_or_insert(illegal);
+        self.illegal_count = self.illegal_count.saturating_add(1);
+        return Err(illegal);
+    }
+
+    /// First stage of `required_before(stage)` that wasn't reached
+    pub fn missing_before(&self, stage: BootStage) -> Option<BootStage> {
+        required_before(stage)
+            .iter()
+            .copied()
+            .find(|&x| !self.has_reached(x))
+    }
+
+    pub const fn illegal(&self) -> Option<IllegalTransition> {
+        self.illegal
+    }
+
+    pub const fn illegal_count(&self) -> u8 {
+        self.illegal_count
+    }
+}
+
+impl Default for BootStages {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Enters `stage`, marking it in the timeline. An illegal transition
+    /// panics in debug builds, release builds record it in `stages` and
+    /// return it for the caller to log.
+    pub fn advance(&mut self, stage: BootStage) -> Result<(), IllegalTransition> {
+        self.mark(stage.name());
+        let result = self.stages.try_advance(stage);
+
+        if let (true, Err(e)) = (cfg!(debug_assertions), result) {
+            panic!("illegal boot stage transition {:?}", e);
+        }
+
+        return result;
+    }
+}
@@ -0,0 +1,124 @@
+//! Conditions the loader tolerates in the field but that mean something is
+//! off with the firmware, the machine or the kernel build. They are logged
+//! and recorded in `Bootinfo::conditions`, with `strict=1` the first one
+//! fails the boot instead, so CI doesn't get used to them.
+
+use crate::{Bootinfo, UnmetRequirement};
+use core::fmt;
+
+/// Bits are never reassigned, new conditions are only appended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Condition {
+    /// Descriptors didn't fit into `uefi_meminfo` and were dropped
+    MemMapTruncated = 0,
+    /// Descriptors in `uefi_meminfo` overlap each other
+    MemMapOverlap,
+    /// Kernel sections aren't within the segment they belong to
+    SectionConflict,
+    /// A kernel segment isn't mapped with the permissions W^X asks for
+    WxAudit,
+    /// Usable memory above MAXPHYADDR was cut
+    PhysMemClamped,
+    /// The kernel is mapped without NX
+    NxUnavailable,
+    /// The kernel's signature wasn't checked while it should have been
+    Unverified,
+    /// ACPI tables with a bad checksum or length were left out
+    AcpiCorrupt,
+    /// Something early devices may DMA from is above `dma_ceiling=`
+    DmaCeiling,
+}
+
+impl Condition {
+    pub const ALL: [Condition; 9] = [
+        Self::MemMapTruncated,
+        Self::MemMapOverlap,
+        Self::SectionConflict,
+        Self::WxAudit,
+        Self::PhysMemClamped,
+        Self::NxUnavailable,
+        Self::Unverified,
+        Self::AcpiCorrupt,
+        Self::DmaCeiling,
+    ];
+
+    pub fn from_u8(x: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|&c| c as u8 == x)
+    }
+
+    const fn bit(self) -> u32 {
+        1 << self as u8
+    }
+}
+
+/// Every condition that came up during the boot
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Conditions(u32);
+
+impl Conditions {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_u32(x: u32) -> Self {
+        Self(x)
+    }
+
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, condition: Condition) -> bool {
+        self.0 & condition.bit() != 0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn insert(&mut self, condition: Condition) {
+        self.0 |= condition.bit();
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Condition> {
+        Condition::ALL
+            .iter()
+            .copied()
+            .filter(move |&c| self.contains(c))
+    }
+}
+
+impl fmt::Debug for Conditions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Why the loader refused to boot, kept in `Bootinfo::last_error` for
+/// crash dumps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub enum BootError {
+    /// `condition` came up with `strict=1`
+    StrictViolation(Condition),
+    /// The kernel's requirements note asked for more, see
+    /// `Bootinfo::check_requirements`
+    Requirement(UnmetRequirement),
+}
+
+impl<const BUF: usize> Bootinfo<BUF> {
+    /// Records `condition`. In strict mode it is an error, which is also
+    /// kept in `last_error`, the caller is expected to fail the boot with it.
+    pub fn tolerate(&mut self, condition: Condition) -> Result<(), BootError> {
+        self.conditions.insert(condition);
+        if !self.capabilities.strict() {
+            return Ok(());
+        }
+
+        let e = BootError::StrictViolation(condition);
+        self.last_error = Some(e);
+        return Err(e);
+    }
+}
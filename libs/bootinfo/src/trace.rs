@@ -0,0 +1,392 @@
+//! Binary trace of loader events for machines without serial or display.
+//! Records go to a buffer at a fixed physical address, which most chipsets
+//! leave alone across a warm reset, so a memory dump or the next boot can
+//! tell how far the last one got. Configured in `sovos.cfg`:
+//!
+//! ```text
+//! trace=0x2000000
+//! trace_size=0x10000
+//! trace_alloc_threshold=0x200000
+//! ```
+//!
+//! `trace` is page aligned, `trace_size` defaults to `DEFAULT_TRACE_SIZE`,
+//! allocations smaller than `trace_alloc_threshold` aren't recorded.
+//!
+//! The buffer starts with a `TraceHeader`, records follow it. Once the
+//! buffer is full the oldest records are overwritten, `written` keeps
+//! counting so `written % capacity` is the next slot. Everything is
+//! little-endian.
+//!
+//! | offset | field         |                                          |
+//! |--------|---------------|------------------------------------------|
+//! | 0      | `magic`       | `TRACE_MAGIC`, "SOVOSTRC"                |
+//! | 8      | `version`     | `TRACE_VERSION`                          |
+//! | 12     | `record_size` | `TRACE_RECORD_SIZE`                      |
+//! | 16     | `capacity`    | records after the header                 |
+//! | 20     | `boot`        | one more than the previous trace's       |
+//! | 24     | `written`     | records ever appended                    |
+//! | 32     | `checksum`    | `TraceHeader::checksum` of the above     |
+//!
+//! A record is `tsc: u64, event: u32, reserved: u32, args: [u64; 2]`,
+//! with the meaning of `args` given by `TraceEvent`.
+
+use crate::{parse_u64, timestamp, BootStage, Config};
+use core::convert::TryInto;
+use core::mem::{align_of, size_of};
+
+pub const TRACE_MAGIC: u64 = u64::from_le_bytes(*b"SOVOSTRC");
+pub const TRACE_VERSION: u32 = 1;
+pub const TRACE_HEADER_SIZE: usize = size_of::<TraceHeader>();
+pub const TRACE_RECORD_SIZE: usize = size_of::<TraceRecord>();
+pub const DEFAULT_TRACE_SIZE: u64 = 64 * 1024;
+pub const DEFAULT_TRACE_ALLOC_THRESHOLD: u64 = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TraceEvent {
+    /// Trace started, `[boot, 0]`
+    Start = 1,
+    /// `[BootStage as u64, 0]`
+    Stage,
+    /// Something failed, `[source line, error code or 0]`
+    Error,
+    /// `[source line, 0]`, the last record of a boot that panicked
+    Panic,
+    /// `[physical address, bytes]`, only above the threshold
+    Alloc,
+    /// `[virtual address, physical address]` of a mapped region
+    Map,
+}
+
+impl TraceEvent {
+    pub const ALL: [TraceEvent; 6] = [
+        Self::Start,
+        Self::Stage,
+        Self::Error,
+        Self::Panic,
+        Self::Alloc,
+        Self::Map,
+    ];
+
+    pub fn from_u32(x: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|&e| e as u32 == x)
+    }
+}
+
+/// FNV-1a over the little-endian bytes of `words`
+pub(crate) fn fnv1a(words: &[u64]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for word in words {
+        for &byte in &word.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    return hash;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct TraceHeader {
+    pub magic: u64,
+    pub version: u32,
+    pub record_size: u32,
+    pub capacity: u32,
+    pub boot: u32,
+    pub written: u64,
+    pub checksum: u64,
+}
+
+impl TraceHeader {
+    /// FNV-1a over every field before `checksum`, so a random pattern left
+    /// in RAM isn't taken for a trace
+    pub fn checksum(&self) -> u64 {
+        let words = [
+            self.magic,
+            self.version as u64 | (self.record_size as u64) << 32,
+            self.capacity as u64 | (self.boot as u64) << 32,
+            self.written,
+        ];
+        return fnv1a(&words);
+    }
+
+    /// Number of records still in the buffer
+    pub fn len(&self) -> usize {
+        core::cmp::min(self.written, self.capacity as u64) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+
+    /// Records that were overwritten by newer ones
+    pub fn dropped(&self) -> u64 {
+        self.written - self.len() as u64
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct TraceRecord {
+    /// TSC ticks, see `timestamp`
+    pub tsc: u64,
+    pub event: u32,
+    pub reserved: u32,
+    pub args: [u64; 2],
+}
+
+impl TraceRecord {
+    pub const fn new(tsc: u64, event: TraceEvent, args: [u64; 2]) -> Self {
+        Self {
+            tsc,
+            event: event as u32,
+            reserved: 0,
+            args,
+        }
+    }
+
+    /// `None` for an id this loader doesn't know
+    pub fn event(&self) -> Option<TraceEvent> {
+        TraceEvent::from_u32(self.event)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceError {
+    /// The buffer isn't aligned for the header
+    Misaligned,
+    /// Not even the header and one record fit
+    TooSmall,
+    BadMagic,
+    BadVersion(u32),
+    /// Record size or capacity don't match the buffer
+    BadGeometry,
+    BadChecksum,
+    /// `trace` isn't a page aligned address
+    BadAddress,
+    BadSize,
+    BadThreshold,
+}
+
+/// Where the trace goes, from the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceConfig {
+    pub addr: u64,
+    pub size: u64,
+    pub alloc_threshold: u64,
+}
+
+impl TraceConfig {
+    /// `None` if there is no `trace` key
+    pub fn from_config(config: &Config) -> Result<Option<Self>, TraceError> {
+        let addr = match config.get("trace") {
+            Some(x) => parse_u64(x)
+                .filter(|x| x % 4096 == 0)
+                .ok_or(TraceError::BadAddress)?,
+            None => return Ok(None),
+        };
+        let size = match config.get("trace_size") {
+            Some(x) => parse_u64(x).ok_or(TraceError::BadSize)?,
+            None => DEFAULT_TRACE_SIZE,
+        };
+        if size < (TRACE_HEADER_SIZE + TRACE_RECORD_SIZE) as u64 {
+            return Err(TraceError::BadSize);
+        }
+        let alloc_threshold = match config.get("trace_alloc_threshold") {
+            Some(x) => parse_u64(x).ok_or(TraceError::BadThreshold)?,
+            None => DEFAULT_TRACE_ALLOC_THRESHOLD,
+        };
+
+        return Ok(Some(Self {
+            addr,
+            size,
+            alloc_threshold,
+        }));
+    }
+
+    /// 4K pages the buffer spans
+    pub fn pages(&self) -> usize {
+        ((self.size + 4095) / 4096) as usize
+    }
+}
+
+fn capacity(len: usize) -> Result<usize, TraceError> {
+    match len.checked_sub(TRACE_HEADER_SIZE) {
+        Some(x) if x >= TRACE_RECORD_SIZE => Ok(x / TRACE_RECORD_SIZE),
+        _ => Err(TraceError::TooSmall),
+    }
+}
+
+fn check_alignment(buf: &[u8]) -> Result<(), TraceError> {
+    if buf.as_ptr() as usize % align_of::<TraceHeader>() != 0 {
+        return Err(TraceError::Misaligned);
+    }
+    return Ok(());
+}
+
+/// A trace left in the buffer by an earlier boot
+#[derive(Clone, Copy, Debug)]
+pub struct PreviousTrace<'a> {
+    pub header: TraceHeader,
+    records: &'a [TraceRecord],
+}
+
+impl<'a> PreviousTrace<'a> {
+    /// Validates the header in `buf`
+    pub fn find(buf: &'a [u8]) -> Result<Self, TraceError> {
+        check_alignment(buf)?;
+        let fits = capacity(buf.len())?;
+        /* SAFETY: aligned and large enough, any bit pattern is a valid header */
+        let header = unsafe { *(buf.as_ptr() as *const TraceHeader) };
+
+        if header.magic != TRACE_MAGIC {
+            return Err(TraceError::BadMagic);
+        }
+        if header.version != TRACE_VERSION {
+            return Err(TraceError::BadVersion(header.version));
+        }
+        if header.record_size as usize != TRACE_RECORD_SIZE
+            || header.capacity == 0
+            || header.capacity as usize > fits
+        {
+            return Err(TraceError::BadGeometry);
+        }
+        if header.checksum != header.checksum() {
+            return Err(TraceError::BadChecksum);
+        }
+
+        /* SAFETY: `capacity` records fit after the header, which keeps them aligned */
+        let records = unsafe {
+            let first = buf.as_ptr().add(TRACE_HEADER_SIZE) as *const TraceRecord;
+            core::slice::from_raw_parts(first, header.capacity as usize)
+        };
+        return Ok(Self { header, records });
+    }
+
+    /// Records still in the buffer, oldest first
+    pub fn records(&self) -> impl Iterator<Item = &'a TraceRecord> + Clone {
+        let len = self.header.len();
+        let start = if self.header.written > len as u64 {
+            (self.header.written % self.header.capacity as u64) as usize
+        } else {
+            0
+        };
+        let records = self.records;
+        (0..len).map(move |i| &records[(start + i) % records.len()])
+    }
+
+    pub fn summary(&self) -> TraceSummary {
+        let mut summary = TraceSummary {
+            boot: self.header.boot,
+            records: self.header.len(),
+            dropped: self.header.dropped(),
+            last_stage: None,
+            errors: 0,
+            panicked: false,
+            last: None,
+        };
+        for record in self.records() {
+            match record.event() {
+                Some(TraceEvent::Stage) => {
+                    let stage = record.args[0].try_into().ok().and_then(BootStage::from_u8);
+                    summary.last_stage = core::cmp::max(summary.last_stage, stage);
+                }
+                Some(TraceEvent::Error) => summary.errors += 1,
+                Some(TraceEvent::Panic) => summary.panicked = true,
+                _ => (),
+            }
+            summary.last = Some(*record);
+        }
+        return summary;
+    }
+}
+
+/// What the loader prints about a previous trace
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceSummary {
+    pub boot: u32,
+    pub records: usize,
+    pub dropped: u64,
+    /// Furthest stage that boot reached, among the records left
+    pub last_stage: Option<BootStage>,
+    pub errors: usize,
+    pub panicked: bool,
+    pub last: Option<TraceRecord>,
+}
+
+/// Appends to a trace buffer
+pub struct TraceWriter<'a> {
+    header: &'a mut TraceHeader,
+    records: &'a mut [TraceRecord],
+    alloc_threshold: u64,
+}
+
+impl<'a> TraceWriter<'a> {
+    /// Starts an empty trace in `buf`, overwriting whatever was there.
+    /// `boot` numbers this trace, usually the previous one's plus one.
+    pub fn new(buf: &'a mut [u8], boot: u32) -> Result<Self, TraceError> {
+        check_alignment(buf)?;
+        let capacity = capacity(buf.len())?;
+        let capacity = core::cmp::min(capacity, u32::MAX as usize);
+
+        let (header, records) = buf.split_at_mut(TRACE_HEADER_SIZE);
+        /* SAFETY: aligned and sized above, both are plain integers */
+        let (header, records) = unsafe {
+            let header = &mut *(header.as_mut_ptr() as *mut TraceHeader);
+            let records =
+                core::slice::from_raw_parts_mut(records.as_mut_ptr() as *mut TraceRecord, capacity);
+            (header, records)
+        };
+        *header = TraceHeader {
+            magic: TRACE_MAGIC,
+            version: TRACE_VERSION,
+            record_size: TRACE_RECORD_SIZE as u32,
+            capacity: capacity as u32,
+            boot,
+            written: 0,
+            checksum: 0,
+        };
+        header.checksum = header.checksum();
+
+        return Ok(Self {
+            header,
+            records,
+            alloc_threshold: 0,
+        });
+    }
+
+    /// Allocations smaller than `bytes` aren't recorded by `alloc`
+    pub fn with_alloc_threshold(self, bytes: u64) -> Self {
+        Self {
+            alloc_threshold: bytes,
+            ..self
+        }
+    }
+
+    pub fn header(&self) -> &TraceHeader {
+        self.header
+    }
+
+    pub fn append(&mut self, record: TraceRecord) {
+        let slot = (self.header.written % self.header.capacity as u64) as usize;
+        self.records[slot] = record;
+        /* Record first, a reset before the header is updated only loses it */
+        self.header.written += 1;
+        self.header.checksum = self.header.checksum();
+    }
+
+    /// Appends `event` at the current timestamp
+    pub fn push(&mut self, event: TraceEvent, args: [u64; 2]) {
+        self.append(TraceRecord::new(timestamp(), event, args));
+    }
+
+    /// Records an allocation if it's at least the threshold, returns
+    /// whether it did
+    pub fn alloc(&mut self, addr: u64, bytes: u64) -> bool {
+        if bytes < self.alloc_threshold {
+            return false;
+        }
+        self.push(TraceEvent::Alloc, [addr, bytes]);
+        return true;
+    }
+}
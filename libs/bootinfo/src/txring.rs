@@ -0,0 +1,104 @@
+//! Transmit ring of the console, so a log line doesn't wait for the UART.
+//! Writes only queue bytes and top up the UART's FIFO if it's already
+//! empty, the rest goes out while the loader does something else and is
+//! flushed at stage boundaries. The loader never gets the THR-empty
+//! interrupt, firmware owns interrupts while boot services are up and
+//! they stay off after ExitBootServices, so `Uart::fill_fifo` is polled.
+//!
+//! One producer and one consumer, each index is only stored by its own
+//! side, so a consumer in an interrupt handler needs no lock.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Bytes of log the console can be behind
+pub const TX_RING_SIZE: usize = 4096;
+
+pub struct TxRing<const N: usize> {
+    buf: UnsafeCell<[u8; N]>,
+    /// Next byte to write, only stored by `push`
+    head: AtomicUsize,
+    /// Next byte to read, only stored by `pop`
+    tail: AtomicUsize,
+    /// Bytes that went through the ring
+    queued: AtomicU64,
+    /// Timestamp ticks spent waiting for the UART, see `add_blocked`
+    blocked: AtomicU64,
+}
+
+impl<const N: usize> TxRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: UnsafeCell::new([0u8; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            queued: AtomicU64::new(0),
+            blocked: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues `byte`, giving it back if the ring is full
+    ///
+    /// # Safety
+    /// Nothing else may push at the same time.
+    pub unsafe fn push(&self, byte: u8) -> Result<(), u8> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) == N {
+            return Err(byte);
+        }
+
+        (*self.buf.get())[head % N] = byte;
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        return Ok(());
+    }
+
+    /// Oldest queued byte
+    ///
+    /// # Safety
+    /// Nothing else may pop at the same time.
+    pub unsafe fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let byte = (*self.buf.get())[tail % N];
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        return Some(byte);
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        head.wrapping_sub(self.tail.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn queued(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Counts `ticks` of waiting for the UART, on a full ring or a flush
+    pub fn add_blocked(&self, ticks: u64) {
+        self.blocked.fetch_add(ticks, Ordering::Relaxed);
+    }
+
+    /// Timestamp ticks writers spent waiting, what is left of the time
+    /// synchronous output took
+    pub fn blocked(&self) -> u64 {
+        self.blocked.load(Ordering::Relaxed)
+    }
+}
+
+impl<const N: usize> Default for TxRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
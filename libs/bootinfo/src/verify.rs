@@ -0,0 +1,113 @@
+//! Signature check of the kernel before it's loaded. The loader is
+//! verified by firmware, the kernel it loads isn't unless we ask.
+//! Configured by one line of `sovos.cfg`:
+//!
+//! ```text
+//! verify=firmware
+//! ```
+//!
+//! | policy     | verifier                                             |
+//! |------------|------------------------------------------------------|
+//! | `off`      | none, the default                                    |
+//! | `firmware` | EFI_SECURITY2_ARCH_PROTOCOL, db/dbx as for LoadImage |
+//! | `shim`     | SHIM_LOCK_PROTOCOL, shim's keys and MOK              |
+//!
+//! A rejection fails the boot. Without a verifier the boot only fails if
+//! Secure Boot is enforcing, otherwise there is nothing to verify against.
+
+use crate::Config;
+use uefi::{DevicePath, SecureBootState, Security2Protocol, ShimLock};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyPolicy {
+    Off,
+    Firmware,
+    Shim,
+}
+
+impl VerifyPolicy {
+    pub const ALL: [VerifyPolicy; 3] = [Self::Off, Self::Firmware, Self::Shim];
+
+    /// Name in the config
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Firmware => "firmware",
+            Self::Shim => "shim",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|x| x.name() == name)
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, VerifyError> {
+        match config.get("verify") {
+            Some(name) => Self::from_name(name).ok_or(VerifyError::UnknownPolicy),
+            None => Ok(Self::Off),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    UnknownPolicy,
+    /// The verifier said no, with this status
+    Rejected(uefi::Error),
+    /// No verifier while Secure Boot is enforcing
+    Unavailable(uefi::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    /// `VerifyPolicy::Off`
+    Skipped,
+    Accepted,
+    /// No verifier, but Secure Boot isn't enforcing either
+    NoVerifier(uefi::Error),
+}
+
+/// A located verification protocol
+#[derive(Clone, Copy)]
+pub enum Verifier<'a> {
+    /// `path` is what the file would have been loaded from, which
+    /// firmware may use in its policy
+    Firmware {
+        protocol: &'a Security2Protocol,
+        path: Option<&'a DevicePath>,
+    },
+    Shim(&'a ShimLock),
+}
+
+impl Verifier<'_> {
+    pub fn verify(&self, file: &[u8]) -> Result<(), uefi::Error> {
+        match self {
+            Self::Firmware { protocol, path } => protocol.file_authentication(*path, file, true),
+            Self::Shim(shim) => shim.verify(file),
+        }
+    }
+}
+
+/// Checks `kernel` as `policy` says. `verifier` is the result of looking
+/// up the protocol the policy asks for, it's ignored for `Off`.
+pub fn verify_kernel(
+    policy: VerifyPolicy,
+    secure_boot: SecureBootState,
+    verifier: Result<Verifier, uefi::Error>,
+    kernel: &[u8],
+) -> Result<VerifyOutcome, VerifyError> {
+    if policy == VerifyPolicy::Off {
+        return Ok(VerifyOutcome::Skipped);
+    }
+
+    let verifier = match verifier {
+        Ok(x) => x,
+        Err(e) if secure_boot.is_enforcing() => return Err(VerifyError::Unavailable(e)),
+        Err(e) => return Ok(VerifyOutcome::NoVerifier(e)),
+    };
+
+    return match verifier.verify(kernel) {
+        Ok(()) => Ok(VerifyOutcome::Accepted),
+        Err(e) => Err(VerifyError::Rejected(e)),
+    };
+}
@@ -0,0 +1,149 @@
+use bootinfo::*;
+
+fn desc(version: u32, required: KernelFeatures, base: u64) -> Vec<u8> {
+    let mut desc = Vec::new();
+    desc.extend_from_slice(&version.to_le_bytes());
+    desc.extend_from_slice(&required.as_u32().to_le_bytes());
+    desc.extend_from_slice(&base.to_le_bytes());
+    desc.extend_from_slice(&(DEFAULT_BUF_SIZE as u32).to_le_bytes());
+    return desc;
+}
+
+fn note(required: KernelFeatures, base: u64) -> AbiNote {
+    AbiNote::from_desc(&desc(BOOTINFO_VERSION, required, base)).unwrap()
+}
+
+#[test]
+fn parse() {
+    let required = KernelFeatures::new().set_percpu().set_symbols();
+    let mut bytes = desc(BOOTINFO_VERSION, required, KERNEL_BASE);
+    let note = AbiNote::from_desc(&bytes).unwrap();
+    assert_eq!(note.bootinfo_version, BOOTINFO_VERSION);
+    assert_eq!(note.required, required);
+    assert_eq!(note.kernel_base, KERNEL_BASE);
+    assert_eq!(note.buf_size as usize, DEFAULT_BUF_SIZE);
+
+    /* Fields added later don't bother older loaders */
+    bytes.extend_from_slice(&[0xFF; 8]);
+    assert_eq!(AbiNote::from_desc(&bytes), Ok(note));
+
+    assert_eq!(AbiNote::from_desc(&bytes[..15]), Err(AbiError::Malformed));
+    assert_eq!(AbiNote::from_desc(&[]), Err(AbiError::Malformed));
+}
+
+#[test]
+fn legacy_without_note() {
+    let contract = negotiate(None, KernelFeatures::supported(), DEFAULT_BUF_SIZE).unwrap();
+    assert_eq!(contract, AbiContract::legacy());
+    assert_eq!(contract.bootinfo_version, 0);
+    assert!(contract.features.percpu());
+}
+
+#[test]
+fn matrix() {
+    let none = KernelFeatures::new();
+    let percpu = KernelFeatures::new().set_percpu();
+    let direct_map = KernelFeatures::new().set_direct_map();
+    let all = KernelFeatures::new()
+        .set_direct_map()
+        .set_symbols()
+        .set_framebuffer()
+        .set_percpu();
+
+    /* (required, supported, missing) */
+    let cases = [
+        (none, none, none),
+        (none, all, none),
+        (percpu, percpu, none),
+        (percpu, all, none),
+        (percpu, none, percpu),
+        (direct_map, percpu, direct_map),
+        (all, percpu, all.clear_percpu()),
+        (all, all, none),
+    ];
+    for &(required, supported, missing) in &cases {
+        let result = negotiate(Some(&note(required, 0)), supported, DEFAULT_BUF_SIZE);
+        if missing.is_empty() {
+            let contract = result.unwrap();
+            assert_eq!(contract.features, required);
+            assert_eq!(contract.bootinfo_version, BOOTINFO_VERSION);
+        } else {
+            assert_eq!(result, Err(AbiError::MissingFeatures(missing)));
+        }
+    }
+}
+
+#[test]
+fn version_mismatch() {
+    for &version in &[0, BOOTINFO_VERSION + 1] {
+        let note = AbiNote::from_desc(&desc(version, KernelFeatures::new(), 0)).unwrap();
+        assert_eq!(
+            negotiate(Some(&note), KernelFeatures::supported(), DEFAULT_BUF_SIZE),
+            Err(AbiError::VersionMismatch {
+                kernel: version,
+                loader: BOOTINFO_VERSION,
+            })
+        );
+    }
+}
+
+#[test]
+fn kernel_base_is_a_preference() {
+    let supported = KernelFeatures::supported();
+    let contract = negotiate(
+        Some(&note(KernelFeatures::new(), 0)),
+        supported,
+        DEFAULT_BUF_SIZE,
+    )
+    .unwrap();
+    assert_eq!(contract.kernel_base, KERNEL_BASE);
+    assert!(!contract.base_ignored);
+
+    let contract = negotiate(
+        Some(&note(KernelFeatures::new(), KERNEL_BASE)),
+        supported,
+        DEFAULT_BUF_SIZE,
+    )
+    .unwrap();
+    assert!(!contract.base_ignored);
+
+    let elsewhere = note(KernelFeatures::new(), 0xffff_ffff_8000_0000);
+    let contract = negotiate(Some(&elsewhere), supported, DEFAULT_BUF_SIZE).unwrap();
+    assert_eq!(contract.kernel_base, KERNEL_BASE);
+    assert!(contract.base_ignored);
+}
+
+#[test]
+fn buf_size_mismatch() {
+    let supported = KernelFeatures::supported();
+    let note = note(KernelFeatures::new(), 0);
+    assert_eq!(
+        negotiate(Some(&note), supported, 2 * DEFAULT_BUF_SIZE),
+        Err(AbiError::BufSizeMismatch {
+            kernel: DEFAULT_BUF_SIZE as u32,
+            loader: 2 * DEFAULT_BUF_SIZE as u32,
+        })
+    );
+
+    /* Notes from before the field was added meant the old buffer */
+    let bytes = desc(BOOTINFO_VERSION, KernelFeatures::new(), 0);
+    let short = AbiNote::from_desc(&bytes[..ABI_NOTE_DESC_SIZE]).unwrap();
+    assert_eq!(short.buf_size, LEGACY_BUF_SIZE);
+    assert_eq!(
+        negotiate(Some(&short), supported, DEFAULT_BUF_SIZE),
+        Err(AbiError::BufSizeMismatch {
+            kernel: LEGACY_BUF_SIZE,
+            loader: DEFAULT_BUF_SIZE as u32,
+        })
+    );
+    assert!(negotiate(Some(&short), supported, LEGACY_BUF_SIZE as usize).is_ok());
+}
+
+#[test]
+fn missing_features_are_listed() {
+    let missing = KernelFeatures::new().set_direct_map().set_framebuffer();
+    let text = format!("{:?}", AbiError::MissingFeatures(missing));
+    assert!(text.contains("direct_map"));
+    assert!(text.contains("framebuffer"));
+    assert!(!text.contains("percpu"));
+}
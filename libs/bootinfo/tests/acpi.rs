@@ -0,0 +1,224 @@
+use bootinfo::{firmware_events, AcpiInfo, BootCapabilities, Bootinfo, TimelineEvent};
+use cpu::acpi::{AcpiContext, FirmwareBootPerformance, OldRsdp, ResetRegister, Rsdp, SdtHeader};
+use std::mem::size_of;
+
+const HEADER_SIZE: usize = size_of::<SdtHeader>();
+
+fn fix_checksum(bytes: &mut [u8], at: usize) {
+    bytes[at] = 0;
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes[at] = sum.wrapping_neg();
+}
+
+fn table(signature: &[u8; 4], len: usize, fields: &[(usize, &[u8])]) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    bytes[0..4].copy_from_slice(signature);
+    bytes[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+    for &(at, field) in fields {
+        bytes[at..at + field.len()].copy_from_slice(field);
+    }
+    fix_checksum(&mut bytes, 9);
+    return bytes;
+}
+
+/* Two enabled local APICs */
+fn madt() -> Vec<u8> {
+    let entries = [0, 8, 0, 0, 1, 0, 0, 0, 0, 8, 1, 1, 1, 0, 0, 0];
+    return table(
+        b"APIC",
+        HEADER_SIZE + 8 + 16,
+        &[(HEADER_SIZE + 8, &entries)],
+    );
+}
+
+fn hpet() -> Vec<u8> {
+    return table(b"HPET", 56, &[(44, &0xfed0_0000u64.to_le_bytes())]);
+}
+
+/* Reset through port 0xcf9 */
+fn fadt() -> Vec<u8> {
+    let flags = (1u32 << 10).to_le_bytes();
+    let reg = 0xcf9u64.to_le_bytes();
+    return table(
+        b"FACP",
+        0x114,
+        &[(112, &flags), (116, &[1]), (120, &reg), (128, &[6])],
+    );
+}
+
+fn negated_sum(rsdp: &Rsdp, len: usize) -> u8 {
+    let bytes = unsafe { std::slice::from_raw_parts(rsdp as *const Rsdp as *const u8, len) };
+    return bytes.iter().fold(0u8, |sum, &b| sum.wrapping_sub(b));
+}
+
+/* XSDT pointing to `tables`, and the RSDP pointing to it */
+fn context(tables: &[&[u8]]) -> (Vec<u8>, Rsdp) {
+    let mut xsdt = table(b"XSDT", HEADER_SIZE + tables.len() * 8, &[]);
+    for (i, table) in tables.iter().enumerate() {
+        let at = HEADER_SIZE + i * 8;
+        xsdt[at..at + 8].copy_from_slice(&(table.as_ptr() as u64).to_le_bytes());
+    }
+    fix_checksum(&mut xsdt, 9);
+
+    let mut rsdp = Rsdp {
+        old: OldRsdp {
+            signature: *b"RSD PTR ",
+            checksum: 0,
+            oem_id: *b"SOVOS ",
+            revision: 2,
+            rsdt_address: 0,
+        },
+        length: size_of::<Rsdp>() as u32,
+        xsdt: xsdt.as_ptr() as *const SdtHeader,
+        ext_checksum: 0,
+        _reserved: [0; 3],
+    };
+    rsdp.old.checksum = negated_sum(&rsdp, 20);
+    rsdp.ext_checksum = negated_sum(&rsdp, 36);
+    return (xsdt, rsdp);
+}
+
+#[test]
+fn everything_there() {
+    let (madt, hpet, fadt) = (madt(), hpet(), fadt());
+    let (_xsdt, rsdp) = context(&[&madt, &hpet, &fadt]);
+    let acpi = unsafe { AcpiContext::discover(&rsdp, 0) }.unwrap();
+
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.read_acpi(Some(&acpi));
+    let reset = ResetRegister {
+        space: 1,
+        value: 6,
+        address: 0xcf9,
+    };
+    assert_eq!(
+        bootinfo.acpi,
+        AcpiInfo {
+            cpus: 2,
+            hpet: 0xfed0_0000,
+            reset: Some(reset),
+            skipped: 0,
+            fbpt: 0,
+            firmware_boot: None,
+        }
+    );
+    let caps = bootinfo.capabilities;
+    assert!(caps.acpi() && caps.madt() && caps.hpet() && caps.acpi_reset());
+}
+
+#[test]
+fn missing_and_corrupt() {
+    /* No HPET and a FADT that doesn't sum up to zero */
+    let madt = madt();
+    let mut fadt = fadt();
+    fadt[128] = 0xe;
+    let (_xsdt, rsdp) = context(&[&madt, &fadt]);
+    let acpi = unsafe { AcpiContext::discover(&rsdp, 0) }.unwrap();
+    assert_eq!(acpi.skipped().len(), 1);
+
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.read_acpi(Some(&acpi));
+    assert_eq!(bootinfo.acpi.madt_cpus(), Some(2));
+    /* TSC only timing and UEFI only reset */
+    assert_eq!(bootinfo.acpi.hpet, 0);
+    assert_eq!(bootinfo.acpi.reset, None);
+    assert_eq!(bootinfo.acpi.skipped, 1);
+    let caps = bootinfo.capabilities;
+    assert!(caps.acpi() && caps.madt());
+    assert!(!caps.hpet() && !caps.acpi_reset());
+}
+
+#[test]
+fn no_acpi() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.read_acpi(None);
+    assert_eq!(bootinfo.acpi, AcpiInfo::new());
+    /* No SMP bring-up */
+    assert_eq!(bootinfo.acpi.madt_cpus(), None);
+    assert_eq!(bootinfo.capabilities, BootCapabilities::new());
+}
+
+/* FBPT with only the basic boot record, and the FPDT pointing to it */
+fn fpdt(times: [u64; 5]) -> (Vec<u8>, Vec<u8>) {
+    let mut fbpt = b"FBPT".to_vec();
+    fbpt.extend_from_slice(&56u32.to_le_bytes());
+    fbpt.extend_from_slice(&[2, 0, 48, 2, 0, 0, 0, 0]);
+    for time in times.iter() {
+        fbpt.extend_from_slice(&time.to_le_bytes());
+    }
+
+    let mut pointer = vec![0, 0, 16, 1, 0, 0, 0, 0];
+    pointer.extend_from_slice(&(fbpt.as_ptr() as u64).to_le_bytes());
+    let fpdt = table(b"FPDT", HEADER_SIZE + 16, &[(HEADER_SIZE, &pointer)]);
+    return (fpdt, fbpt);
+}
+
+fn names(events: &[TimelineEvent]) -> Vec<&[u8]> {
+    events.iter().map(|x| x.name()).collect()
+}
+
+#[test]
+fn firmware_boot() {
+    /* 2.5 seconds of firmware before LoadImage */
+    let (fpdt, fbpt) = fpdt([500_000_000, 3_000_000_000, 3_010_000_000, 0, 0]);
+    let (_xsdt, rsdp) = context(&[&fpdt]);
+    let acpi = unsafe { AcpiContext::discover(&rsdp, 0) }.unwrap();
+
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.read_acpi(Some(&acpi));
+    assert_eq!(bootinfo.acpi.fbpt, fbpt.as_ptr() as u64);
+    let perf = bootinfo.acpi.firmware_boot.unwrap();
+    assert_eq!(perf.loader_load_image, 3_000_000_000);
+    assert!(bootinfo.capabilities.fpdt());
+
+    /* At 2GHz, with the loader entered at TSC 10^10 */
+    bootinfo.mark("serial flush");
+    bootinfo.prepend_firmware_boot(2000, 10_000_000_000);
+    let timeline = bootinfo.timeline.as_slice();
+    assert_eq!(
+        names(timeline),
+        [
+            &b"firmware reset end"[..],
+            b"firmware load image",
+            b"firmware start image",
+            b"serial flush"
+        ]
+    );
+    let tsc: Vec<u64> = timeline[..3].iter().map(|x| x.tsc).collect();
+    assert_eq!(tsc, [10_000_000_000 - 5_020_000_000, 10_000_000_000 - 20_000_000, 10_000_000_000]);
+}
+
+#[test]
+fn partial_firmware_boot() {
+    let perf = FirmwareBootPerformance {
+        reset_end: 0,
+        loader_load_image: 0,
+        loader_start_image: 2_000_000,
+        exit_boot_services_entry: 0,
+        exit_boot_services_exit: 0,
+    };
+    /* Reset at 0 counts, a missing LoadImage doesn't, and the TSC can't
+     * go below 0 */
+    let events = firmware_events(&perf, 1000, 1_000_000);
+    assert_eq!(names(&events), [&b"firmware reset end"[..], b"firmware start image"]);
+    assert_eq!([events[0].tsc, events[1].tsc], [0, 1_000_000]);
+
+    /* Nothing to count back from */
+    let perf = FirmwareBootPerformance {
+        loader_start_image: 0,
+        ..perf
+    };
+    assert!(firmware_events(&perf, 1000, 1_000_000).is_empty());
+
+    /* A corrupt FBPT is no FPDT */
+    let (fpdt, mut fbpt) = fpdt([1, 2, 3, 0, 0]);
+    fbpt[10] = 40;
+    let (_xsdt, rsdp) = context(&[&fpdt]);
+    let acpi = unsafe { AcpiContext::discover(&rsdp, 0) }.unwrap();
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.read_acpi(Some(&acpi));
+    assert_eq!(bootinfo.acpi.firmware_boot, None);
+    assert!(!bootinfo.capabilities.fpdt());
+    bootinfo.prepend_firmware_boot(1000, 1_000_000);
+    assert!(bootinfo.timeline.is_empty());
+}
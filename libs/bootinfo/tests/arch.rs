@@ -0,0 +1,32 @@
+#![cfg(target_arch = "x86_64")]
+
+use bootinfo::{ArchPaging, Bootinfo, ConsoleDevice, Paging, RootTable, Uart};
+use cpu::paging::{PML4Entry, Table, PAGE_SIZE};
+use cpu::VirtAddr;
+
+/* Not initialized, so nothing may reach the port */
+fn console<C: ConsoleDevice>(console: &mut C) -> Option<u8> {
+    console.send_bytes(b"hello");
+    return console.try_recv();
+}
+
+#[test]
+fn uart_is_the_console() {
+    let mut uart = Uart::new(0x3F8, 115_200);
+    assert!(!ConsoleDevice::is_ready(&uart));
+    assert_eq!(console(&mut uart), None);
+    assert!(!uart.is_wedged());
+}
+
+#[test]
+fn x86_paging() {
+    fn same_type(root: &RootTable) -> &Table<PML4Entry> {
+        root
+    }
+
+    let bootinfo = Box::new(Bootinfo::new());
+    let root = same_type(&bootinfo.paging_root);
+    assert_eq!(Paging::PAGE_SIZE, PAGE_SIZE);
+    assert!(unsafe { Paging::translate(root, VirtAddr::new(0x1000)) }.is_none());
+    assert!(unsafe { bootinfo.translate(0x1000) }.is_none());
+}
@@ -0,0 +1,64 @@
+use bootinfo::{AllocPurpose, ArenaError, BootArena};
+
+fn make_arena(size: usize) -> BootArena {
+    let buf = Box::leak(vec![0u8; size].into_boxed_slice());
+    BootArena::new(buf)
+}
+
+#[test]
+fn pinned_reservations() {
+    let mut arena = make_arena(4096);
+
+    let bytes = arena.alloc_bytes(AllocPurpose::Cmdline, 13, 1).unwrap();
+    assert!(!arena.is_pinned(&*bytes));
+
+    let table = arena.reserve_pinned(AllocPurpose::Idt, [7u64; 16]).unwrap();
+    assert_eq!(table as *const _ as usize % 8, 0);
+    assert_eq!(*table, [7u64; 16]);
+    assert!(arena.is_pinned(&*table));
+    assert!(arena.is_pinned(&table[3]));
+
+    let reservations = arena.reservations();
+    assert_eq!(reservations.len(), 2);
+    assert_eq!(reservations[1].purpose, AllocPurpose::Idt);
+    assert!(reservations[1].pinned);
+
+    let outside = 0u64;
+    assert!(!arena.is_pinned(&outside));
+}
+
+#[test]
+fn reset_after_pinning() {
+    let mut arena = make_arena(4096);
+
+    arena.alloc_bytes(AllocPurpose::Other, 100, 1).unwrap();
+    assert_eq!(unsafe { arena.reset() }, Ok(()));
+    assert_eq!(arena.remaining(), 4096);
+
+    arena.reserve_pinned(AllocPurpose::Idt, [0u8; 256]).unwrap();
+    assert_eq!(unsafe { arena.reset() }, Err(ArenaError::Pinned));
+    assert_eq!(arena.reservations().len(), 1);
+}
+
+#[test]
+fn frozen_and_full() {
+    let mut arena = make_arena(64);
+
+    assert_eq!(
+        arena.alloc_bytes(AllocPurpose::Symbols, 65, 1),
+        Err(ArenaError::OutOfMemory)
+    );
+    arena.alloc_bytes(AllocPurpose::Symbols, 64, 1).unwrap();
+    assert_eq!(
+        arena.alloc_bytes(AllocPurpose::Symbols, 1, 1),
+        Err(ArenaError::OutOfMemory)
+    );
+
+    arena.freeze();
+    assert!(arena.is_frozen());
+    assert_eq!(
+        arena.reserve_pinned(AllocPurpose::Gdt, 0u64),
+        Err(ArenaError::Frozen)
+    );
+    assert_eq!(unsafe { arena.reset() }, Err(ArenaError::Frozen));
+}
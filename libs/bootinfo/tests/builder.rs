@@ -0,0 +1,73 @@
+use bootinfo::*;
+use cpu::{PhysAddr, PhysRange, PhysSlice};
+use uefi::memory::{Attributes, Descriptor, Type};
+
+fn descriptor(start: u64, len: u64) -> Descriptor {
+    let range = PhysRange::new(start, len).unwrap();
+    Descriptor::new(Type::Conventional, range, Attributes::new()).unwrap()
+}
+
+fn builder() -> BootinfoBuilder<Missing, Missing, Missing> {
+    let bootinfo = Box::leak(Box::new(Bootinfo::new()));
+    unsafe { BootinfoBuilder::new(bootinfo) }
+}
+
+fn kernel() -> PhysSlice<u8> {
+    PhysSlice::new(PhysAddr::new(0x20_0000).unwrap(), 0x40_0000)
+}
+
+#[test]
+fn finish() {
+    let mut builder = builder().console(SerialSinks::new()).memory_map(vec![
+        descriptor(0x10_0000, 0x10_0000),
+        descriptor(0x100_0000, 0x1000),
+    ]);
+    assert_ne!(builder.this.as_u64(), 0);
+    assert_eq!(builder.uefi_meminfo.len(), 2);
+    assert_eq!(builder.memory.regions, 2);
+    assert_eq!(builder.memory.below_4g, 0x10_1000);
+
+    /* The arena is usable in between */
+    let idt = builder
+        .arena()
+        .reserve_pinned(AllocPurpose::Idt, [0u64; 32])
+        .unwrap();
+    assert!(builder.arena().is_pinned(idt));
+
+    let builder = builder.kernel(kernel());
+    let bootinfo = builder.finish();
+    assert_eq!(bootinfo.kernel_pslice.addr().as_u64(), 0x20_0000);
+    let kernel = (PhysRange::from(kernel()), ReservedKind::Kernel);
+    assert!(bootinfo.reserved_ranges().any(|x| x == kernel));
+    assert!(bootinfo.checksum_ok());
+    assert_ne!(bootinfo.checksum, [0u8; 32]);
+}
+
+#[test]
+fn checksum() {
+    let builder = builder()
+        .memory_map(vec![])
+        .kernel(kernel())
+        .console(SerialSinks::new());
+    let mut bootinfo = builder.finish();
+    assert!(bootinfo.checksum_ok());
+
+    /* Optional parts aren't covered */
+    unsafe { bootinfo.get_mut() }.mark("after finish");
+    assert!(bootinfo.checksum_ok());
+
+    unsafe { bootinfo.get_mut() }.kernel_pslice = PhysSlice::null();
+    assert!(!bootinfo.checksum_ok());
+}
+
+#[test]
+fn memory_map_truncates() {
+    let map = (0..300).map(|i| descriptor(i * 0x1000, 0x1000));
+    let builder = builder().memory_map(map);
+    assert_eq!(builder.uefi_meminfo.len(), builder.uefi_meminfo.capacity());
+    assert!(builder.capabilities.memory_map_truncated());
+
+    let map = (0..192).map(|i| descriptor(i * 0x1000, 0x1000));
+    let builder = self::builder().memory_map(map);
+    assert!(!builder.capabilities.memory_map_truncated());
+}
@@ -0,0 +1,59 @@
+use bootinfo::*;
+
+/* Fleet tooling decodes the raw word, a bit must never move */
+#[test]
+fn stable_bits() {
+    let new = BootCapabilities::new;
+    let bits = [
+        (new().set_tsc_calibrated(), 0),
+        (new().set_microcode_applied(), 1),
+        (new().set_memory_map_truncated(), 2),
+        (new().set_physical_memory_clamped(), 3),
+        (new().set_kernel_verified(), 4),
+        (new().set_secure_boot(), 5),
+        (new().set_abi_note(), 6),
+        (new().set_percpu(), 7),
+        (new().set_handoff_trampoline(), 8),
+        (new().set_entropy_seeded(), 9),
+        (new().set_esrt(), 10),
+        (new().set_legacy_serial(), 11),
+        (new().set_tables_verified(), 12),
+        (new().set_avx(), 13),
+        (new().set_slot_fallback(), 14),
+        (new().set_nx_unavailable(), 15),
+        (new().set_kexec(), 16),
+        (new().set_strict(), 17),
+        (new().set_acpi(), 18),
+        (new().set_madt(), 19),
+        (new().set_hpet(), 20),
+        (new().set_acpi_reset(), 21),
+        (new().set_uart(), 22),
+        (new().set_fpdt(), 23),
+        (new().set_crash_dump(), 24),
+    ];
+    for &(caps, bit) in &bits {
+        assert_eq!(caps.as_u64(), 1 << bit, "{:?}", caps);
+    }
+}
+
+#[test]
+fn typed_view() {
+    let caps = BootCapabilities::from_u64(0b10_0011);
+    assert!(caps.tsc_calibrated() && caps.microcode_applied() && caps.secure_boot());
+    assert!(!caps.kernel_verified());
+    assert_eq!(
+        format!("{:?}", caps),
+        "tsc_calibrated | microcode_applied | secure_boot"
+    );
+    assert_eq!(format!("{:?}", BootCapabilities::new()), "(empty)");
+}
+
+#[test]
+fn record() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    assert_eq!(bootinfo.capabilities, BootCapabilities::new());
+
+    bootinfo.record(BootCapabilities::set_esrt);
+    bootinfo.record(|x| x.set_avx());
+    assert_eq!(bootinfo.capabilities.as_u64(), 1 << 10 | 1 << 13);
+}
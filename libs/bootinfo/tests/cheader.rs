@@ -0,0 +1,115 @@
+#![cfg(all(target_arch = "x86_64", target_os = "linux"))]
+
+use bootinfo::cheader::*;
+use bootinfo::{BootCapabilities, Bootinfo, ReservedKind};
+use std::fmt::Write;
+use std::process::Command;
+
+const HOST: &str = "x86_64-unknown-linux-gnu";
+
+fn header() -> String {
+    let mut header = String::new();
+    write_c_header(&mut header).unwrap();
+    return header;
+}
+
+#[test]
+fn layout_covered() {
+    let fields = bootinfo_fields();
+    assert_eq!(check_layout(&fields), Ok(()));
+    assert_eq!(fields[0].offset, 0);
+    assert_eq!(fields.last().unwrap().name, "_pinned");
+
+    /* A field missing from the list shows up as a gap */
+    let mut missing = fields.to_vec();
+    let buf = missing.iter().position(|f| f.name == "buf").unwrap();
+    missing.remove(buf);
+    let e = check_layout(&missing).unwrap_err();
+    assert!(
+        matches!(
+            e,
+            LayoutError::Gap {
+                field: "uefi_meminfo",
+                ..
+            }
+        ),
+        "{:?}",
+        e
+    );
+}
+
+#[test]
+fn names() {
+    let header = header();
+    assert!(header.contains("#define SOVOS_CAP_ACPI_RESET (1ull << 21)\n"));
+    assert!(header.contains("    SOVOS_RESERVED_RUNTIME_SERVICES = 1,\n"));
+    assert!(header.contains("    SOVOS_STAGE_EXITED_BOOT_SERVICES = 4,\n"));
+    assert!(header.contains("    SOVOS_CONDITION_MEM_MAP_TRUNCATED = 0,\n"));
+    assert!(header.contains("    uint64_t capabilities;\n"));
+    assert!(header.contains("#define SOVOS_REQUIREMENTS_NOTE_TYPE 2u\n"));
+}
+
+/* Compiles a C program against the header that prints what the C
+ * compiler thinks, and compares it with the Rust side */
+#[test]
+fn round_trip() {
+    let dir = std::env::temp_dir().join(format!("bootinfo-h-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("bootinfo.h"), header()).unwrap();
+
+    let fields = bootinfo_fields();
+    let mut expected = String::new();
+    let mut c = String::from(
+        "#include <stddef.h>\n#include <stdio.h>\n#include \"bootinfo.h\"\n\nint main(void) {\n",
+    );
+    let mut print = |name: &str, value: &str, rust: usize| {
+        writeln!(
+            c,
+            "    printf(\"%s %zu\\n\", \"{}\", (size_t)({}));",
+            name, value
+        )
+        .unwrap();
+        writeln!(expected, "{} {}", name, rust).unwrap();
+    };
+    print(
+        "size",
+        "sizeof(struct sovos_bootinfo)",
+        std::mem::size_of::<Bootinfo>(),
+    );
+    for field in fields.iter().filter(|f| f.size != 0) {
+        let offset = format!("offsetof(struct sovos_bootinfo, {})", field.name);
+        print(field.name, &offset, field.offset);
+        let size = format!("sizeof(((struct sovos_bootinfo *)0)->{})", field.name);
+        print(field.name, &size, field.size);
+    }
+    for &(name, bit) in BootCapabilities::FLAGS {
+        let define = format!("SOVOS_CAP_{}", name.to_uppercase());
+        print(&define, &format!("{} >> {}", define, bit), 1);
+    }
+    print(
+        "SOVOS_RESERVED_BOOTINFO",
+        "SOVOS_RESERVED_BOOTINFO",
+        ReservedKind::Bootinfo as usize,
+    );
+    c.push_str("    return 0;\n}\n");
+    std::fs::write(dir.join("check.c"), c).unwrap();
+
+    let exe = dir.join("check");
+    let status = cc::Build::new()
+        .target(HOST)
+        .host(HOST)
+        .opt_level(0)
+        .cargo_metadata(false)
+        .get_compiler()
+        .to_command()
+        .args(["-std=c11", "-Wall", "-Werror", "-o"])
+        .arg(&exe)
+        .arg(dir.join("check.c"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(&exe).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
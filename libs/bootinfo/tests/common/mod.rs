@@ -0,0 +1,12 @@
+//! Fixtures shared by the tests
+#![allow(dead_code)]
+
+use bootinfo::Bootinfo;
+use core::pin::Pin;
+
+/// A `Bootinfo` that won't move again, initialized in place
+pub fn pinned_bootinfo() -> Pin<Box<Bootinfo>> {
+    let mut bootinfo = Box::pin(Bootinfo::new());
+    unsafe { bootinfo.as_mut().init_this() };
+    return bootinfo;
+}
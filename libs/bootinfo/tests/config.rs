@@ -0,0 +1,46 @@
+use bootinfo::Config;
+
+const TEXT: &[u8] = b"# boot options
+verify_tables = 1
+log=serial # trailing comment
+log=both
+empty=
+broken line
+";
+
+#[test]
+fn get() {
+    let config = Config::new(TEXT);
+    assert_eq!(config.get("verify_tables"), Some("1"));
+    assert_eq!(config.get("log"), Some("both"));
+    assert_eq!(config.get("empty"), Some(""));
+    assert_eq!(config.get("broken line"), None);
+    assert_eq!(config.get("missing"), None);
+}
+
+#[test]
+fn flag() {
+    let config = Config::new(b"a=1\nb=on\nc=0\nd=maybe\n# e=1\n");
+    assert!(config.flag("a"));
+    assert!(config.flag("b"));
+    assert!(!config.flag("c"));
+    assert!(!config.flag("d"));
+    assert!(!config.flag("e"));
+    assert!(!Config::empty().flag("a"));
+}
+
+#[test]
+fn parse_u64() {
+    use bootinfo::parse_u64;
+
+    assert_eq!(parse_u64("4096"), Some(4096));
+    assert_eq!(parse_u64("0x1000"), Some(0x1000));
+    assert_eq!(
+        parse_u64("0xffff_8000_0000_0000"),
+        Some(0xffff_8000_0000_0000)
+    );
+    assert_eq!(parse_u64("0x"), None);
+    assert_eq!(parse_u64(""), None);
+    assert_eq!(parse_u64("12a"), None);
+    assert_eq!(parse_u64("0x1_0000_0000_0000_0000"), None);
+}
@@ -0,0 +1,174 @@
+use bootinfo::*;
+use cpu::PhysRange;
+use std::mem::size_of;
+
+const WRITER: [u8; 16] = [0x11; 16];
+const READER: [u8; 16] = [0x22; 16];
+/// What the rest of the region holds, a dump or garbage
+const PATTERN: u64 = 0xa5a5_5a5a_dead_beef;
+
+/// Synthetic region of `bytes` bytes, aligned like RAM would be
+fn region(bytes: usize) -> Vec<u64> {
+    vec![PATTERN; bytes / 8]
+}
+
+fn bytes(buf: &mut [u64]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 8) }
+}
+
+fn header_mut(buf: &mut [u64]) -> &mut CrashDumpHeader {
+    unsafe { &mut *(buf.as_mut_ptr() as *mut CrashDumpHeader) }
+}
+
+fn range(buf: &[u64]) -> PhysRange {
+    PhysRange::new(0x1000_0000, buf.len() as u64 * 8).unwrap()
+}
+
+/// A region an earlier boot of `WRITER` left a `len` byte dump in
+fn with_dump(size: usize, len: u64) -> Vec<u64> {
+    let mut buf = region(size);
+    let capacity = (size - CRASHDUMP_HEADER_SIZE) as u64;
+    *header_mut(&mut buf) = CrashDumpHeader::fresh(capacity, [0; 16]).with_dump(len, WRITER);
+    return buf;
+}
+
+fn config(text: &str) -> Result<Option<CrashDumpConfig>, CrashDumpError> {
+    CrashDumpConfig::from_config(&Config::new(text.as_bytes()))
+}
+
+#[test]
+fn header_layout() {
+    assert_eq!(size_of::<CrashDumpHeader>(), 56);
+    assert_eq!(&CRASHDUMP_MAGIC.to_le_bytes(), b"SOVOSDMP");
+
+    let mut buf = region(4096);
+    let mut opened = CrashDumpRegion::open(range(&buf), bytes(&mut buf), WRITER).unwrap();
+    assert_eq!(buf[0], CRASHDUMP_MAGIC);
+    assert_eq!(buf[1], CRASHDUMP_VERSION as u64);
+    assert_eq!(buf[2], 4096 - 56);
+    assert_eq!(buf[3], 0);
+    assert_eq!(&buf[4..6], &[0x1111_1111_1111_1111; 2]);
+    assert_eq!(buf[6], opened.header.checksum());
+
+    /* What the kernel writes after a dump */
+    *header_mut(&mut buf) = opened.header.with_dump(0x100, READER);
+    assert_eq!(buf[3], 0x100);
+    assert_eq!(&buf[4..6], &[0x2222_2222_2222_2222; 2]);
+    opened.header = CrashDumpHeader::find(bytes(&mut buf)).unwrap();
+    assert_eq!(opened.header.length, 0x100);
+    assert_eq!(opened.header.with_dump(u64::MAX, READER).length, 4096 - 56);
+}
+
+#[test]
+fn from_config() {
+    assert_eq!(config(""), Ok(None));
+    assert_eq!(
+        config("crashdump=0x1000_0000"),
+        Ok(Some(CrashDumpConfig {
+            addr: 0x1000_0000,
+            size: DEFAULT_CRASHDUMP_SIZE,
+        }))
+    );
+    let settings = config("crashdump=0x1000_0000\ncrashdump_size=16M").unwrap().unwrap();
+    assert_eq!(settings.size, 16 << 20);
+    assert_eq!(settings.pages(), 4096);
+    assert_eq!(config("crashdump=0x1000_0000\ncrashdump_size=0x3000").unwrap().unwrap().pages(), 3);
+
+    assert_eq!(config("crashdump=0x1000_0800"), Err(CrashDumpError::BadAddress));
+    assert_eq!(config("crashdump=high"), Err(CrashDumpError::BadAddress));
+    assert_eq!(config("crashdump=0x1000_0000\ncrashdump_size=1000"), Err(CrashDumpError::BadSize));
+    assert_eq!(config("crashdump=0x1000_0000\ncrashdump_size=0"), Err(CrashDumpError::BadSize));
+    assert_eq!(config("crashdump=0x1000_0000\ncrashdump_size=M"), Err(CrashDumpError::BadSize));
+}
+
+#[test]
+fn validation() {
+    let mut buf = with_dump(8192, 100);
+    assert_eq!(CrashDumpHeader::find(bytes(&mut buf)).unwrap().boot_id, WRITER);
+
+    /* Garbage and zeroed RAM */
+    assert_eq!(CrashDumpHeader::find(bytes(&mut region(8192))), Err(CrashDumpError::BadMagic));
+    assert_eq!(CrashDumpHeader::find(bytes(&mut vec![0u64; 1024])), Err(CrashDumpError::BadMagic));
+
+    let mut bad = buf.clone();
+    header_mut(&mut bad).version = 2;
+    assert_eq!(CrashDumpHeader::find(bytes(&mut bad)), Err(CrashDumpError::BadVersion(2)));
+
+    /* A bit flipped anywhere before the checksum */
+    for word in 1..6 {
+        let mut bad = buf.clone();
+        bad[word] ^= 1 << 40;
+        assert!(CrashDumpHeader::find(bytes(&mut bad)).is_err(), "word {}", word);
+    }
+    let mut bad = buf.clone();
+    header_mut(&mut bad).boot_id[3] ^= 1;
+    assert_eq!(CrashDumpHeader::find(bytes(&mut bad)), Err(CrashDumpError::BadChecksum));
+
+    /* Written for a region of another size */
+    let mut bigger = buf.clone();
+    bigger.extend(region(4096));
+    assert_eq!(CrashDumpHeader::find(bytes(&mut bigger)), Err(CrashDumpError::BadGeometry));
+    let mut bad = buf.clone();
+    let header = header_mut(&mut bad);
+    header.length = header.capacity + 1;
+    header.checksum = header.checksum();
+    assert_eq!(CrashDumpHeader::find(bytes(&mut bad)), Err(CrashDumpError::BadGeometry));
+
+    assert_eq!(CrashDumpHeader::find(&bytes(&mut buf)[1..]), Err(CrashDumpError::Misaligned));
+    assert_eq!(CrashDumpHeader::find(&bytes(&mut buf)[..48]), Err(CrashDumpError::TooSmall));
+}
+
+#[test]
+fn fresh_region() {
+    /* Only the header is written, the rest isn't even zeroed */
+    let mut buf = region(8192);
+    let opened = CrashDumpRegion::open(range(&buf), bytes(&mut buf), READER).unwrap();
+    assert_eq!(opened.state, CrashDumpState::Fresh);
+    assert_eq!(opened.range, range(&buf));
+    assert_eq!(opened.header, CrashDumpHeader::find(bytes(&mut buf)).unwrap());
+    assert_eq!((opened.header.boot_id, opened.header.length), (READER, 0));
+    assert!(buf[7..].iter().all(|&w| w == PATTERN));
+    assert_eq!(opened.dump(bytes(&mut buf)), None);
+
+    /* An empty one from an earlier boot is taken over */
+    let mut buf = with_dump(8192, 0);
+    let opened = CrashDumpRegion::open(range(&buf), bytes(&mut buf), READER).unwrap();
+    assert_eq!((opened.state, opened.header.boot_id), (CrashDumpState::Fresh, READER));
+    assert_eq!(CrashDumpHeader::find(bytes(&mut buf)).unwrap().boot_id, READER);
+
+    /* So is a dump with a broken header */
+    let mut buf = with_dump(8192, 100);
+    buf[3] = 200;
+    let opened = CrashDumpRegion::open(range(&buf), bytes(&mut buf), READER).unwrap();
+    assert_eq!(opened.state, CrashDumpState::Fresh);
+
+    let mut buf = region(40);
+    let error = CrashDumpRegion::open(PhysRange::empty(), bytes(&mut buf), READER);
+    assert_eq!(error, Err(CrashDumpError::TooSmall));
+}
+
+#[test]
+fn existing_dump() {
+    let mut buf = with_dump(8192, 100);
+    let before = buf.clone();
+    let mut opened = CrashDumpRegion::open(range(&buf), bytes(&mut buf), READER).unwrap();
+    assert_eq!(opened.state, CrashDumpState::Dump);
+    assert_eq!((opened.header.boot_id, opened.header.length), (WRITER, 100));
+    assert_eq!(buf, before);
+
+    /* Still there on the boot after, until it's cleared */
+    let again = CrashDumpRegion::open(range(&buf), bytes(&mut buf), [0x33; 16]).unwrap();
+    assert_eq!(again, opened);
+
+    let dump = opened.dump(bytes(&mut buf)).unwrap().to_vec();
+    assert_eq!(dump.len(), CRASHDUMP_HEADER_SIZE + 100);
+    assert_eq!(&dump[..8], b"SOVOSDMP");
+    assert_eq!(&dump[56..64], &PATTERN.to_le_bytes());
+
+    opened.clear(bytes(&mut buf), READER).unwrap();
+    assert_eq!(opened.state, CrashDumpState::Fresh);
+    assert_eq!(opened.header, CrashDumpHeader::find(bytes(&mut buf)).unwrap());
+    assert_eq!(&buf[7..], &before[7..]);
+    let next = CrashDumpRegion::open(range(&buf), bytes(&mut buf), READER).unwrap();
+    assert_eq!(next.state, CrashDumpState::Fresh);
+}
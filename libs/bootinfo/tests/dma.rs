@@ -0,0 +1,98 @@
+use bootinfo::*;
+use cpu::PhysRange;
+use uefi::memory::{Attributes, Descriptor, Type};
+
+const MIB: u64 = 1 << 20;
+const GIB: u64 = 1 << 30;
+
+fn conventional(start: u64, len: u64) -> Descriptor {
+    let range = PhysRange::new(start, len).unwrap();
+    Descriptor::new(Type::Conventional, range, Attributes::new()).unwrap()
+}
+
+fn ceiling(text: &str) -> Result<Option<u64>, DmaCeilingError> {
+    dma_ceiling(&Config::new(text.as_bytes()))
+}
+
+#[test]
+fn config() {
+    assert_eq!(ceiling(""), Ok(None));
+    assert_eq!(ceiling("dma_ceiling=4G"), Ok(Some(4 * GIB)));
+    assert_eq!(ceiling("dma_ceiling=512m"), Ok(Some(512 * MIB)));
+    assert_eq!(ceiling("dma_ceiling=64K"), Ok(Some(0x1_0000)));
+    assert_eq!(ceiling("dma_ceiling=0x1_0000_0000"), Ok(Some(4 * GIB)));
+
+    assert_eq!(ceiling("dma_ceiling=G"), Err(DmaCeilingError::BadNumber));
+    assert_eq!(ceiling("dma_ceiling=4T"), Err(DmaCeilingError::BadNumber));
+    assert_eq!(
+        ceiling("dma_ceiling=0x400000000000G"),
+        Err(DmaCeilingError::BadNumber)
+    );
+    assert_eq!(ceiling("dma_ceiling=0"), Err(DmaCeilingError::TooLow));
+}
+
+#[test]
+fn scarce_low_memory() {
+    /* 3M free below 4G, plenty above */
+    let map = [conventional(GIB, 3 * MIB), conventional(8 * GIB, GIB)];
+    let mut frames = MapFrameAllocator::new(&map, &[]);
+    let ceiling = Some(4 * GIB);
+
+    /* Below the ceiling while it fits, then anywhere */
+    let first = alloc_dma(&mut frames, 2 * MIB, 0x1000, ceiling).unwrap();
+    assert_eq!(first.start(), GIB);
+    let second = alloc_dma(&mut frames, 2 * MIB, 0x1000, ceiling).unwrap();
+    assert_eq!(second.start(), 8 * GIB);
+    /* Lowest first, so DMA allocations have to come before the rest */
+    assert_eq!(frames.alloc_frames_below(MIB, 0x1000, 4 * GIB), None);
+
+    /* Straddling the ceiling doesn't count as below it */
+    let map = [conventional(4 * GIB - MIB, 4 * MIB)];
+    let mut frames = MapFrameAllocator::new(&map, &[]);
+    assert_eq!(frames.alloc_frames_below(2 * MIB, 0x1000, 4 * GIB), None);
+    let range = alloc_dma(&mut frames, 2 * MIB, 0x1000, ceiling).unwrap();
+    assert_eq!(range.end(), 4 * GIB + MIB);
+
+    /* Without a ceiling it's just `alloc_frames` */
+    let map = [conventional(8 * GIB, GIB)];
+    let mut frames = MapFrameAllocator::new(&map, &[]);
+    assert!(alloc_dma(&mut frames, MIB, 0x1000, None).is_some());
+}
+
+#[test]
+fn tags() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    let low = PhysRange::new(GIB, MIB).unwrap();
+    let high = PhysRange::new(8 * GIB, MIB).unwrap();
+
+    /* Nothing to violate without a ceiling */
+    assert_eq!(bootinfo.dma_ceiling(), None);
+    assert!(bootinfo.tag_dma(high, ReservedKind::Trace));
+
+    bootinfo.dma_regions.clear();
+    bootinfo.dma_ceiling = 4 * GIB;
+    assert!(bootinfo.tag_dma(low, ReservedKind::Module));
+    assert!(!bootinfo.tag_dma(high, ReservedKind::PerCpu));
+    assert_eq!(
+        bootinfo.dma_regions.as_slice(),
+        [
+            DmaRegion {
+                range: low,
+                kind: ReservedKind::Module,
+                below_ceiling: true
+            },
+            DmaRegion {
+                range: high,
+                kind: ReservedKind::PerCpu,
+                below_ceiling: false
+            },
+        ]
+    );
+
+    /* A full table still answers */
+    for _ in 0..MAX_DMA_REGIONS {
+        bootinfo.tag_dma(low, ReservedKind::Module);
+    }
+    assert!(!bootinfo.tag_dma(high, ReservedKind::Module));
+    assert_eq!(bootinfo.dma_regions.len(), MAX_DMA_REGIONS);
+}
@@ -0,0 +1,104 @@
+#![feature(abi_efiapi)]
+
+use bootinfo::{ConsoleDevice, EfiSerial, SerialSinks, Uart};
+use core::fmt::Write;
+use std::cell::RefCell;
+use uefi::SerialIoProtocol;
+
+const TIMEOUT: usize = 0x8000_0000_0000_0012;
+
+thread_local! {
+    static BAUD: RefCell<u64> = RefCell::new(0);
+    static WRITTEN: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+    static INPUT: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+}
+
+extern "efiapi" fn mock_set_attributes(
+    _this: &SerialIoProtocol,
+    baud: u64,
+    _fifo_depth: u32,
+    _timeout: u32,
+    _parity: u32,
+    _data_bits: u8,
+    _stop_bits: u32,
+) -> usize {
+    BAUD.with(|b| *b.borrow_mut() = baud);
+    return 0;
+}
+
+extern "efiapi" fn mock_write(_this: &SerialIoProtocol, size: &mut usize, buf: *const u8) -> usize {
+    let bytes = unsafe { std::slice::from_raw_parts(buf, *size) };
+    WRITTEN.with(|w| w.borrow_mut().extend_from_slice(bytes));
+    return 0;
+}
+
+extern "efiapi" fn mock_read(_this: &SerialIoProtocol, size: &mut usize, buf: *mut u8) -> usize {
+    INPUT.with(|input| {
+        let mut input = input.borrow_mut();
+        if input.is_empty() {
+            *size = 0;
+            return TIMEOUT;
+        }
+        *size = 1;
+        unsafe { *buf = input.remove(0) };
+        return 0;
+    })
+}
+
+/* Same layout as SerialIoProtocol */
+#[repr(C)]
+struct MockSerialIo {
+    revision: u32,
+    functions: [usize; 6],
+    mode: usize,
+}
+
+fn mock() -> Box<MockSerialIo> {
+    let mut mock = Box::new(MockSerialIo {
+        revision: 0x0001_0000,
+        functions: [0; 6],
+        mode: 0,
+    });
+    mock.functions[1] = mock_set_attributes as usize;
+    mock.functions[4] = mock_write as usize;
+    mock.functions[5] = mock_read as usize;
+    return mock;
+}
+
+fn protocol(mock: &MockSerialIo) -> &SerialIoProtocol {
+    unsafe { &*(mock as *const MockSerialIo as *const SerialIoProtocol) }
+}
+
+#[test]
+fn same_baud_as_legacy() {
+    let mock = mock();
+    let uart = Uart::new(0x3F8, 9600);
+    let mut efi = unsafe { EfiSerial::init(protocol(&mock), &uart) }.unwrap();
+    assert_eq!(BAUD.with(|b| *b.borrow()), 9600);
+
+    assert!(efi.is_ready());
+    assert_eq!(efi.try_recv(), None);
+    INPUT.with(|input| *input.borrow_mut() = b"q".to_vec());
+    assert_eq!(efi.try_recv(), Some(b'q'));
+}
+
+#[test]
+fn fallback_until_exit() {
+    let mock = mock();
+    /* Not initialized, like a machine without a legacy UART */
+    let mut sinks = SerialSinks::new();
+    assert!(sinks.needs_fallback());
+
+    let efi = unsafe { EfiSerial::init(protocol(&mock), sinks.console()) }.unwrap();
+    sinks.use_efi_serial(efi);
+    assert!(sinks.has_efi_serial());
+
+    write!(sinks, "boot {}\n", 1).unwrap();
+    INPUT.with(|input| *input.borrow_mut() = b"x".to_vec());
+    assert_eq!(sinks.try_recv(), Some(b'x'));
+
+    sinks.exit_boot_services();
+    assert!(!sinks.has_efi_serial());
+    write!(sinks, "lost").unwrap();
+    assert_eq!(WRITTEN.with(|w| w.borrow().clone()), b"boot 1\n");
+}
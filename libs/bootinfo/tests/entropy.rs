@@ -0,0 +1,109 @@
+use bootinfo::sha256::Sha256;
+use bootinfo::{health_check, EntropyPool, EntropySource, HealthError};
+
+fn hex(digest: [u8; 32]) -> String {
+    digest.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/* A sample that passes every check */
+fn good_sample(seed: u8) -> [u8; 32] {
+    let mut sample = [0u8; 32];
+    for (i, x) in sample.iter_mut().enumerate() {
+        *x = (i as u8).wrapping_mul(37) ^ seed;
+    }
+    return sample;
+}
+
+#[test]
+fn sha256_vectors() {
+    assert_eq!(
+        hex(Sha256::digest(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex(Sha256::digest(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hex(Sha256::digest(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+
+    /* Split updates crossing block boundaries */
+    let data = [0x5au8; 200];
+    let mut hasher = Sha256::new();
+    for chunk in data.chunks(7) {
+        hasher.update(chunk);
+    }
+    assert_eq!(hasher.finish(), Sha256::digest(&data));
+}
+
+#[test]
+fn pathological_samples() {
+    assert_eq!(health_check(&[]), Err(HealthError::Empty));
+    assert_eq!(health_check(&[0u8; 32]), Err(HealthError::AllZero));
+    assert_eq!(health_check(&[0xFFu8; 32]), Err(HealthError::AllOnes));
+
+    let mut stuck_byte = good_sample(1);
+    stuck_byte[10..18].copy_from_slice(&[0x42; 8]);
+    assert_eq!(health_check(&stuck_byte), Err(HealthError::Repetition));
+
+    /* rdrand stuck at one value */
+    let word = 0x0123_4567_89ab_cdefu64.to_le_bytes();
+    let stuck_word = [word, word, word, word].concat();
+    assert_eq!(health_check(&stuck_word), Err(HealthError::Repetition));
+
+    assert_eq!(health_check(&good_sample(1)), Ok(()));
+    let mut short_run = good_sample(1);
+    short_run[0..7].copy_from_slice(&[0x42; 7]);
+    assert_eq!(health_check(&short_run), Ok(()));
+}
+
+#[test]
+fn failed_sources_contribute_nothing() {
+    let mut pool = EntropyPool::new();
+    pool.add(EntropySource::Rdseed, &good_sample(1)).unwrap();
+    let (clean, _) = pool.finish();
+
+    let mut pool = EntropyPool::new();
+    assert_eq!(
+        pool.add(EntropySource::EfiRng, &[0u8; 32]),
+        Err(HealthError::AllZero)
+    );
+    pool.add(EntropySource::Rdseed, &good_sample(1)).unwrap();
+    assert_eq!(
+        pool.add(EntropySource::Rdrand, &[0xFFu8; 32]),
+        Err(HealthError::AllOnes)
+    );
+    let (seed, status) = pool.finish();
+
+    assert_eq!(seed, clean);
+    assert!(status.is_available(EntropySource::EfiRng));
+    assert!(!status.is_healthy(EntropySource::EfiRng));
+    assert!(status.is_healthy(EntropySource::Rdseed));
+    assert!(status.rdrand_available() && !status.rdrand_healthy());
+    assert!(!status.is_available(EntropySource::TscJitter));
+    assert_eq!(status.healthy_sources(), 1);
+}
+
+#[test]
+fn sources_are_mixed() {
+    let mut a = EntropyPool::new();
+    a.add(EntropySource::Rdseed, &good_sample(1)).unwrap();
+    a.add(EntropySource::TscJitter, &good_sample(2)).unwrap();
+    let (a, status) = a.finish();
+    assert_eq!(status.healthy_sources(), 2);
+
+    let mut b = EntropyPool::new();
+    b.add(EntropySource::Rdseed, &good_sample(1)).unwrap();
+    b.add(EntropySource::TscJitter, &good_sample(3)).unwrap();
+    assert_ne!(a, b.finish().0);
+
+    /* Same bytes from another source give a different seed */
+    let mut c = EntropyPool::new();
+    c.add(EntropySource::Rdrand, &good_sample(1)).unwrap();
+    c.add(EntropySource::TscJitter, &good_sample(2)).unwrap();
+    assert_ne!(a, c.finish().0);
+}
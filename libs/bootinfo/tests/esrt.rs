@@ -0,0 +1,91 @@
+use bootinfo::*;
+use cpu::PhysRange;
+use uefi::memory::{Attributes, Descriptor, Type};
+use uefi::{Esrt, Guid, ESRT_VERSION};
+
+#[repr(C, align(4096))]
+struct Page([u8; 4096]);
+
+fn descriptor(start: u64, len: u64) -> Descriptor {
+    let range = PhysRange::new(start, len).unwrap();
+    Descriptor::new(Type::BootServicesData, range, Attributes::new()).unwrap()
+}
+
+/// ESRT with `count` entries, entry `i` has version `i`
+fn table(count: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&count.to_le_bytes());
+    bytes.extend_from_slice(&count.to_le_bytes());
+    bytes.extend_from_slice(&ESRT_VERSION.to_le_bytes());
+    for i in 0..count {
+        let guid: [u8; 16] = unsafe { core::mem::transmute(Guid::EFI_GLOBAL_VARIABLE) };
+        bytes.extend_from_slice(&guid);
+        for field in [1, i, i / 2, 0, i, 0xC000_0000 + i] {
+            bytes.extend_from_slice(&u32::to_le_bytes(field));
+        }
+    }
+    return bytes;
+}
+
+#[test]
+fn compact_copy() {
+    let bytes = table(3);
+    let esrt = Esrt::parse(&bytes).unwrap();
+    let mut bootinfo = Box::new(Bootinfo::new());
+
+    assert_eq!(bootinfo.record_esrt(&esrt), 0);
+    assert_eq!(bootinfo.esrt.len(), 3);
+    assert_eq!(
+        bootinfo.esrt[2],
+        FirmwareResource {
+            fw_class: Guid::EFI_GLOBAL_VARIABLE,
+            version: 2,
+            lowest_supported_version: 1,
+            last_attempt_status: 0xC000_0002,
+        }
+    );
+}
+
+#[test]
+fn bounded() {
+    let count = MAX_FIRMWARE_RESOURCES as u32 + 4;
+    let bytes = table(count);
+    let esrt = Esrt::parse(&bytes).unwrap();
+    let mut bootinfo = Box::new(Bootinfo::new());
+
+    assert_eq!(bootinfo.record_esrt(&esrt), 4);
+    assert_eq!(bootinfo.esrt_dropped, 4);
+    assert_eq!(bootinfo.esrt.len(), MAX_FIRMWARE_RESOURCES);
+    assert_eq!(bootinfo.esrt.last().unwrap().version, count - 5);
+
+    /* Recording again starts over */
+    let small = table(1);
+    assert_eq!(bootinfo.record_esrt(&Esrt::parse(&small).unwrap()), 0);
+    assert_eq!(bootinfo.esrt.len(), 1);
+}
+
+#[test]
+fn table_memory_ends_with_the_region() {
+    let mut pages = Box::new([Page([0; 4096]), Page([0; 4096])]);
+    let start = pages.as_ptr() as u64;
+    let bytes = table(3);
+    /* Table placed so that its last entry crosses into the second page */
+    let offset = 4096 - 100;
+    pages[0].0[offset..].copy_from_slice(&bytes[..100]);
+    pages[1].0[..bytes.len() - 100].copy_from_slice(&bytes[100..]);
+    let addr = start + offset as u64;
+
+    let two_pages = [descriptor(start, 8192)];
+    let memory = unsafe { table_memory(&two_pages, addr) }.unwrap();
+    assert_eq!(memory.len(), 100 + 4096);
+    assert_eq!(Esrt::parse(memory).unwrap().len(), 3);
+
+    /* The region claims less than the table, so it's cut off */
+    let one_page = [descriptor(start, 4096)];
+    let memory = unsafe { table_memory(&one_page, addr) }.unwrap();
+    assert_eq!(memory.len(), 100);
+    assert_eq!(Esrt::parse(memory).unwrap_err(), uefi::EsrtError::Truncated);
+
+    let elsewhere = [descriptor(start + 8192, 4096)];
+    assert!(unsafe { table_memory(&elsewhere, addr) }.is_none());
+}
@@ -0,0 +1,253 @@
+#![feature(abi_efiapi)]
+
+use bootinfo::{Bootinfo, InterruptFlag, InterruptSources, PreExitReport};
+use std::cell::Cell;
+use uefi::{BootServices, Clock, Error, ImageHandle, MemoryMapBuffer, SystemTable};
+
+const INVALID_PARAMETER: usize = 0x8000_0000_0000_0002;
+const BUFFER_TOO_SMALL: usize = 0x8000_0000_0000_0005;
+const DESCRIPTOR_SIZE: usize = 48;
+
+thread_local! {
+    static MAP_KEY: Cell<usize> = Cell::new(0);
+    static EXIT_FAILURES: Cell<usize> = Cell::new(0);
+    static EXIT_CALLS: Cell<usize> = Cell::new(0);
+    static MAP_PAGES: std::cell::RefCell<Vec<Vec<u64>>> = std::cell::RefCell::new(Vec::new());
+    /* RFLAGS.IF and a watchdog armed by the loader, as seen by firmware */
+    static INTERRUPTS_ENABLED: Cell<bool> = Cell::new(false);
+    static WATCHDOG_ARMED: Cell<bool> = Cell::new(false);
+    /* Whether anything could interrupt during an exit attempt */
+    static LIVE_AT_EXIT: Cell<bool> = Cell::new(false);
+}
+
+struct MockFlag;
+
+impl InterruptFlag for MockFlag {
+    fn is_enabled(&self) -> bool {
+        INTERRUPTS_ENABLED.with(|x| x.get())
+    }
+    fn disable(&mut self) {
+        INTERRUPTS_ENABLED.with(|x| x.set(false));
+    }
+}
+
+unsafe fn disarm_watchdog(_: u64) {
+    WATCHDOG_ARMED.with(|x| x.set(false));
+}
+
+extern "efiapi" fn mock_get_memory_map(
+    size: &mut usize,
+    map: *mut u8,
+    key: &mut usize,
+    descriptor_size: &mut usize,
+    version: &mut u32,
+) -> usize {
+    if *size < 2 * DESCRIPTOR_SIZE {
+        *size = 2 * DESCRIPTOR_SIZE;
+        return BUFFER_TOO_SMALL;
+    }
+
+    let key_value = MAP_KEY.with(|k| {
+        k.set(k.get() + 1);
+        k.get()
+    });
+
+    /* Two conventional memory descriptors, 48 bytes each like on real firmware */
+    let mut buf = [0u64; 12];
+    buf[0] = 7;
+    buf[1] = 0x10_0000;
+    buf[3] = 16;
+    buf[6] = 7;
+    buf[7] = 0x100_0000 * key_value as u64;
+    buf[9] = 32;
+
+    unsafe { map.copy_from_nonoverlapping(buf.as_ptr() as *const u8, 2 * DESCRIPTOR_SIZE) };
+    *size = 2 * DESCRIPTOR_SIZE;
+    *key = key_value;
+    *descriptor_size = DESCRIPTOR_SIZE;
+    *version = 1;
+    return 0;
+}
+
+extern "efiapi" fn mock_allocate_pages(_typ: u32, _memory_type: u32, pages: usize, addr: &mut u64) -> usize {
+    let mut buf = vec![0u64; pages * 512];
+    *addr = buf.as_mut_ptr() as u64;
+    MAP_PAGES.with(|x| x.borrow_mut().push(buf));
+    return 0;
+}
+
+extern "efiapi" fn mock_exit_boot_services(_image: usize, key: usize) -> usize {
+    EXIT_CALLS.with(|c| c.set(c.get() + 1));
+    let live = INTERRUPTS_ENABLED.with(|x| x.get()) && WATCHDOG_ARMED.with(|x| x.get());
+    LIVE_AT_EXIT.with(|x| x.set(x.get() || live));
+    assert_eq!(key, MAP_KEY.with(|k| k.get()));
+
+    /* Map changes after every failed exit */
+    let failures = EXIT_FAILURES.with(|f| f.get());
+    if failures > 0 {
+        EXIT_FAILURES.with(|f| f.set(failures - 1));
+        return INVALID_PARAMETER;
+    }
+    return 0;
+}
+
+/* Every reading of the time advances it by 30ms,
+ * so the 100ms deadline allows 4 attempts */
+struct MockClock(Cell<u64>);
+
+impl Clock for MockClock {
+    fn now_us(&self) -> u64 {
+        let now = self.0.get();
+        self.0.set(now + 30_000);
+        now
+    }
+    fn sleep_us(&self, us: u64) {
+        self.0.set(self.0.get() + us);
+    }
+}
+
+/* Same layout as BootServices, up to locate_protocol */
+#[repr(C)]
+struct MockBootServices {
+    header: [u64; 3],
+    services: [usize; 38],
+}
+
+fn run(failures: usize) -> (Box<Bootinfo>, Result<(), Error>, usize) {
+    return run_with(failures, &mut InterruptSources::new());
+}
+
+fn run_with(
+    failures: usize,
+    sources: &mut InterruptSources,
+) -> (Box<Bootinfo>, Result<(), Error>, usize) {
+    return run_full(failures, sources, &mut MemoryMapBuffer::new());
+}
+
+fn run_full(
+    failures: usize,
+    sources: &mut InterruptSources,
+    memmap: &mut MemoryMapBuffer,
+) -> (Box<Bootinfo>, Result<(), Error>, usize) {
+    let mut mock = MockBootServices {
+        header: [0; 3],
+        services: [0; 38],
+    };
+    mock.services[2] = mock_allocate_pages as usize;
+    mock.services[4] = mock_get_memory_map as usize;
+    mock.services[26] = mock_exit_boot_services as usize;
+
+    MAP_KEY.with(|k| k.set(0));
+    EXIT_CALLS.with(|c| c.set(0));
+    EXIT_FAILURES.with(|f| f.set(failures));
+    LIVE_AT_EXIT.with(|x| x.set(false));
+
+    let st: SystemTable = unsafe { core::mem::zeroed() };
+    st.boot_services
+        .set(&mock as *const MockBootServices as *const BootServices);
+    let image: ImageHandle = unsafe { core::mem::transmute(1usize) };
+
+    let mut bootinfo = Box::new(Bootinfo::new());
+    let clock = MockClock(Cell::new(0));
+    let result = unsafe { bootinfo.retrieve_and_exit(&st, &image, &clock, memmap, sources, &mut MockFlag) };
+    let calls = EXIT_CALLS.with(|c| c.get());
+
+    if result.is_ok() {
+        assert_eq!(bootinfo.uefi_systable as *const _, &st as *const _);
+    }
+    return (bootinfo, result, calls);
+}
+
+#[test]
+fn exits_first_time() {
+    let (bootinfo, result, calls) = run(0);
+    assert_eq!(result, Ok(()));
+    assert_eq!(calls, 1);
+    assert_eq!(bootinfo.uefi_meminfo.len(), 2);
+    assert_eq!(bootinfo.uefi_meminfo[0].phys_start, 0x10_0000);
+    assert_eq!(bootinfo.uefi_meminfo[1].pages, 32);
+}
+
+fn timeline(bootinfo: &Bootinfo) -> Vec<String> {
+    let names = bootinfo.timeline.iter().map(|x| String::from_utf8_lossy(x.name()).into_owned());
+    return names.collect();
+}
+
+#[test]
+fn retries_with_fresh_map() {
+    let mut memmap = MemoryMapBuffer::new();
+    let (bootinfo, result, calls) = run_full(2, &mut InterruptSources::new(), &mut memmap);
+    assert_eq!(result, Ok(()));
+    assert_eq!(calls, 3);
+
+    /* Map from the last, successful attempt */
+    assert_eq!(bootinfo.uefi_meminfo[1].phys_start, 0x300_0000);
+
+    /* Asked for the size once, then the same buffer every time */
+    assert_eq!((memmap.calls, memmap.allocations), (4, 1));
+    let expected = [
+        "memory map probe 96B",
+        "memory map 96B",
+        "exit boot services retry",
+        "memory map 96B",
+        "exit boot services retry",
+        "memory map 96B",
+    ];
+    let names = timeline(&bootinfo);
+    assert_eq!(names.len(), expected.len());
+    for (name, expected) in names.iter().zip(expected.iter()) {
+        assert!(name.starts_with(expected), "{} isn't {}", name, expected);
+    }
+}
+
+#[test]
+fn gives_up() {
+    let (bootinfo, result, calls) = run(100);
+    assert_eq!(result, Err(Error::InvalidParameter));
+    assert_eq!(calls, 4);
+    assert!(bootinfo.uefi_meminfo.is_empty());
+    assert!(bootinfo.uefi_systable.is_null());
+}
+
+#[test]
+fn quiesces_armed_watchdog() {
+    INTERRUPTS_ENABLED.with(|x| x.set(true));
+    WATCHDOG_ARMED.with(|x| x.set(true));
+    let mut sources = InterruptSources::new();
+    let id = sources.register("watchdog", disarm_watchdog, 0).unwrap();
+
+    let (bootinfo, result, calls) = run_with(1, &mut sources);
+    assert_eq!(result, Ok(()));
+    assert_eq!(calls, 2);
+    /* Nothing could have fired during or after any attempt */
+    assert!(!LIVE_AT_EXIT.with(|x| x.get()));
+    assert!(!INTERRUPTS_ENABLED.with(|x| x.get()));
+    assert!(!WATCHDOG_ARMED.with(|x| x.get()));
+    assert!(!sources.is_armed(id));
+
+    assert_eq!(
+        bootinfo.pre_exit,
+        PreExitReport {
+            interrupts_disabled: true,
+            sources_disarmed: 1,
+        }
+    );
+    let names: Vec<&[u8]> = bootinfo.timeline.iter().map(|x| x.name()).collect();
+    assert_eq!(
+        &names[..2],
+        [
+            &b"interrupts disabled before exit"[..],
+            &b"interrupt sources disarmed"[..]
+        ]
+    );
+}
+
+#[test]
+fn quiet_machine_needs_nothing() {
+    INTERRUPTS_ENABLED.with(|x| x.set(false));
+    let mut sources = InterruptSources::new();
+    let (bootinfo, result, _) = run_with(0, &mut sources);
+    assert_eq!(result, Ok(()));
+    assert_eq!(bootinfo.pre_exit, PreExitReport::new());
+    assert!(timeline(&bootinfo).iter().all(|x| x.starts_with("memory map")));
+}
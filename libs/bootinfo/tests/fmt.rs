@@ -0,0 +1,21 @@
+use bootinfo::{KernelPermPolicy, Module};
+use cpu::{PhysAddr, PhysSlice};
+
+#[test]
+fn module() {
+    let data = PhysSlice::new(PhysAddr::new(0x80_0000).unwrap(), 0x1_8000);
+    let module = Module::new("initrd.img", data);
+    assert_eq!(
+        format!("{:?}", module),
+        "Module { name: \"initrd.img\", kind: Initrd, \
+         data: PhysSlice { addr: 0x0000_0000_0080_0000, len: 98304, size: 96 KiB } }"
+    );
+}
+
+#[test]
+fn policy() {
+    assert_eq!(
+        format!("{:?}", KernelPermPolicy::new()),
+        "KernelPermPolicy { text: R-X, rodata: R--, data: RW-, allow_rwx: false }"
+    );
+}
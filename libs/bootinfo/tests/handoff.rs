@@ -0,0 +1,85 @@
+use bootinfo::{check_dual_mapping, Bootinfo, HandoffError, Trampoline, TRAMPOLINE};
+use cpu::paging::{PDEntry, PDFlags, PDPEntry, PDPFlags, PML4Entry, PML4Flags, PTEntry, PTFlags};
+use cpu::phys::{IdentityMapping, PhysWrite, PhysWriter};
+use cpu::{PhysAddr, PhysRange};
+
+#[repr(align(4096))]
+struct AlignedPage([u8; 4096]);
+
+fn index(virt: u64, level: u32) -> usize {
+    ((virt >> (12 + 9 * level)) % 512) as usize
+}
+
+fn phys_of<T>(x: &T) -> PhysAddr {
+    PhysAddr::new(x as *const T as u64).unwrap()
+}
+
+/* Maps a single 4K page using the tables embedded in a Bootinfo,
+ * in-process addresses stand in for physical ones */
+fn tables_mapping(virt: u64, phys: u64) -> Box<Bootinfo> {
+    let mut tables = Box::new(Bootinfo::new());
+
+    let pdp = phys_of(&tables.pdp);
+    let pd = phys_of(&tables.pd);
+    let pt = phys_of(&tables.page_table);
+    tables.paging_root[index(virt, 3)] = PML4Entry::new(pdp, PML4Flags::new().set_present());
+    tables.pdp[index(virt, 2)] = PDPEntry::new(pd, PDPFlags::new().set_present());
+    tables.pd[index(virt, 1)] = PDEntry::new(pt, PDFlags::new().set_present());
+
+    let phys = PhysAddr::new(phys).unwrap();
+    tables.page_table[index(virt, 0)] = PTEntry::new(phys, PTFlags::new().set_present());
+    return tables;
+}
+
+#[test]
+fn installs_when_dual_mapped() {
+    let mut page = Box::new(AlignedPage([0u8; 4096]));
+    let virt = page.0.as_mut_ptr() as u64;
+    let old = tables_mapping(virt, virt);
+    let new = tables_mapping(virt, virt);
+
+    let offset = 4096 - TRAMPOLINE.len() as u64;
+    let allowed = [PhysRange::new(virt, 4096).unwrap()];
+    let mut writes = Vec::new();
+    let mut log = |w: &PhysWrite| writes.push(*w);
+    let trampoline = unsafe {
+        let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+        Trampoline::install(
+            &old.paging_root,
+            &new.paging_root,
+            virt + offset,
+            &mut writer,
+        )
+    };
+    assert_eq!(trampoline.unwrap().virt(), virt + offset);
+    assert_eq!(&page.0[offset as usize..], &TRAMPOLINE[..]);
+    assert_eq!(writes.len(), 1);
+    assert_eq!(writes[0].purpose, "handoff trampoline");
+    assert_eq!(writes[0].range.start(), virt + offset);
+}
+
+#[test]
+fn rejects_broken_mappings() {
+    let virt = 0x7fff_0000_0000;
+    let old = tables_mapping(virt, 0x10_0000);
+
+    let new = tables_mapping(virt, 0x20_0000);
+    let result = unsafe { check_dual_mapping(&old.paging_root, &new.paging_root, virt) };
+    let mismatch = HandoffError::Mismatch {
+        old: 0x10_0000,
+        new: 0x20_0000,
+    };
+    assert_eq!(result.map(|x| x.as_u64()), Err(mismatch));
+
+    let new = tables_mapping(virt + 4096, 0x10_0000);
+    let result = unsafe { check_dual_mapping(&old.paging_root, &new.paging_root, virt) };
+    assert_eq!(result.map(|x| x.as_u64()), Err(HandoffError::NotMappedNew));
+    let result = unsafe { check_dual_mapping(&new.paging_root, &old.paging_root, virt) };
+    assert_eq!(result.map(|x| x.as_u64()), Err(HandoffError::NotMappedOld));
+
+    let new = tables_mapping(virt, 0x10_0000);
+    let result = unsafe { check_dual_mapping(&old.paging_root, &new.paging_root, virt + 4090) };
+    assert_eq!(result.map(|x| x.as_u64()), Err(HandoffError::CrossesPage));
+    let result = unsafe { check_dual_mapping(&old.paging_root, &new.paging_root, virt + 8) };
+    assert_eq!(result.map(|x| x.as_u64()), Ok(0x10_0008));
+}
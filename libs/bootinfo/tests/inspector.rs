@@ -0,0 +1,218 @@
+#![cfg(feature = "inspector")]
+
+mod common;
+
+use bootinfo::inspector::{execute, parse, read_line, Address, Command, ParseError};
+use bootinfo::{Bootinfo, MapGranularity, SegmentPerms};
+use common::*;
+use cpu::PhysRange;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use uefi::{Clock, PolledInput, TimedOut};
+
+const VIRT: u64 = 0x80_0000_0000;
+
+#[repr(C, align(4096))]
+struct Page([u8; 4096]);
+
+fn run(bootinfo: &Bootinfo, line: &str, confirm: bool) -> String {
+    let mut out = String::new();
+    let command = parse(line).unwrap();
+    unsafe { execute(&mut out, bootinfo, &command, &mut |_| confirm).unwrap() };
+    return out;
+}
+
+#[test]
+fn parse_commands() {
+    assert_eq!(
+        parse("r 0x1000 16"),
+        Ok(Command::Read {
+            addr: Address::Phys(0x1000),
+            len: 16
+        })
+    );
+    assert_eq!(
+        parse("  r   v:0x8000  0x10 "),
+        Ok(Command::Read {
+            addr: Address::Virt(0x8000),
+            len: 16
+        })
+    );
+    match parse("w 0x2000 1 0x2 0xff").unwrap() {
+        Command::Write { addr, bytes } => {
+            assert_eq!(addr, Address::Phys(0x2000));
+            assert_eq!(&bytes[..], &[1, 2, 0xff]);
+        }
+        x => panic!("{:?}", x),
+    }
+    assert_eq!(
+        parse("pt 0xffff_8000_0000_0000"),
+        Ok(Command::PageTable(0xffff_8000_0000_0000))
+    );
+    assert_eq!(parse("mm"), Ok(Command::MemoryMap));
+    assert_eq!(parse("bi"), Ok(Command::Bootinfo));
+    assert_eq!(parse("q"), Ok(Command::Quit));
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(parse(""), Err(ParseError::Empty));
+    assert_eq!(parse("   "), Err(ParseError::Empty));
+    assert_eq!(parse("x"), Err(ParseError::UnknownCommand));
+    assert_eq!(parse("r 0x1000"), Err(ParseError::MissingArgument));
+    assert_eq!(parse("r zzz 1"), Err(ParseError::BadNumber));
+    assert_eq!(parse("r 0 4097"), Err(ParseError::TooLong));
+    assert_eq!(parse("w 0"), Err(ParseError::MissingArgument));
+    assert_eq!(parse("w 0 256"), Err(ParseError::BadNumber));
+    assert_eq!(
+        parse("w 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0"),
+        Err(ParseError::TooLong)
+    );
+    assert_eq!(parse("mm now"), Err(ParseError::TrailingArgument));
+}
+
+#[test]
+fn read_phys() {
+    let bootinfo = pinned_bootinfo();
+    let buf = *b"inspector test!!";
+    let line = format!("r {:#x} 16", buf.as_ptr() as u64);
+
+    let out = run(&bootinfo, &line, false);
+    assert!(
+        out.contains("69 6e 73 70 65 63 74 6f  72 20 74 65 73 74 21 21"),
+        "{}",
+        out
+    );
+    assert!(out.contains("|inspector test!!|"), "{}", out);
+}
+
+#[test]
+fn write_needs_confirmation() {
+    let bootinfo = pinned_bootinfo();
+    let mut buf = [0u8; 4];
+    let line = format!("w {:#x} 0xaa 0xbb", buf.as_mut_ptr() as u64);
+
+    let out = run(&bootinfo, &line, false);
+    assert!(out.contains("not written"), "{}", out);
+    assert_eq!(unsafe { core::ptr::read_volatile(&buf) }, [0, 0, 0, 0]);
+
+    run(&bootinfo, &line, true);
+    assert_eq!(
+        unsafe { core::ptr::read_volatile(&buf) },
+        [0xaa, 0xbb, 0, 0]
+    );
+}
+
+#[test]
+fn virtual_addresses() {
+    let mut bootinfo = pinned_bootinfo();
+    let mut page = Box::new(Page([0; 4096]));
+    page.0[..4].copy_from_slice(b"virt");
+    let phys = PhysRange::new(page.0.as_ptr() as u64, 4096).unwrap();
+    unsafe {
+        bootinfo
+            .as_mut()
+            .map_range(VIRT, phys, MapGranularity::Page, SegmentPerms::RW)
+            .unwrap()
+    };
+
+    let out = run(&bootinfo, &format!("r v:{:#x} 4", VIRT), false);
+    assert!(out.contains("|virt|"), "{}", out);
+
+    /* The page after isn't mapped */
+    let out = run(&bootinfo, &format!("r v:{:#x} 2", VIRT + 4095), false);
+    assert!(out.contains("is not mapped"), "{}", out);
+
+    run(&bootinfo, &format!("w v:{:#x} 0x56", VIRT), true);
+    assert_eq!(unsafe { core::ptr::read_volatile(&page.0[0]) }, b'V');
+
+    let out = run(&bootinfo, &format!("pt {:#x}", VIRT), false);
+    assert!(out.contains("PML4[  1]"), "{}", out);
+    assert!(out.contains("PT  [  0]"), "{}", out);
+    assert!(out.contains("->"), "{}", out);
+}
+
+/* Every reading moves time 1us on */
+struct MockClock(Rc<Cell<u64>>);
+
+impl Clock for MockClock {
+    fn now_us(&self) -> u64 {
+        let now = self.0.get();
+        self.0.set(now + 1);
+        return now;
+    }
+    fn sleep_us(&self, _us: u64) {
+        unreachable!();
+    }
+}
+
+/* Bytes typed at the given times, echo collected */
+struct Console {
+    now: Rc<Cell<u64>>,
+    typed: VecDeque<(u64, u8)>,
+    echo: String,
+}
+
+impl PolledInput for Console {
+    fn poll(&mut self) -> Option<u8> {
+        match self.typed.front() {
+            Some(&(at, byte)) if self.now.get() > at => {
+                self.typed.pop_front();
+                Some(byte)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.echo.push_str(s);
+        Ok(())
+    }
+}
+
+fn typing(typed: &[(u64, u8)]) -> (MockClock, Console) {
+    let now = Rc::new(Cell::new(0));
+    let console = Console {
+        now: now.clone(),
+        typed: typed.iter().copied().collect(),
+        echo: String::new(),
+    };
+    return (MockClock(now), console);
+}
+
+#[test]
+fn line_editing() {
+    let typed = [
+        (0, b'a'),
+        (5, b'b'),
+        (6, 0x7f),
+        (7, b'c'),
+        (8, 0x01),
+        (9, b'\r'),
+    ];
+    let (clock, mut console) = typing(&typed);
+    let line = read_line(&mut console, &clock, 100).unwrap();
+    assert_eq!(line.as_str(), "ac");
+    assert_eq!(console.echo, "ab\x08 \x08c\n");
+}
+
+#[test]
+fn line_timeout() {
+    /* A BREAK on an unattended line, nobody finishes the line */
+    let (clock, mut console) = typing(&[(0, 0x00), (1, b'q')]);
+    assert_eq!(read_line(&mut console, &clock, 1000), Err(TimedOut));
+    assert_eq!(console.echo, "q");
+}
+
+#[test]
+fn line_just_in_time() {
+    let (clock, mut console) = typing(&[(0, b'q'), (1000, b'\r')]);
+    let line = read_line(&mut console, &clock, 1000).unwrap();
+    assert_eq!(line.as_str(), "q");
+
+    let (clock, mut console) = typing(&[(0, b'q'), (1001, b'\r')]);
+    assert_eq!(read_line(&mut console, &clock, 1000), Err(TimedOut));
+}
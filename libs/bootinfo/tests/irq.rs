@@ -0,0 +1,84 @@
+use bootinfo::{quiesce_interrupts, InterruptFlag, InterruptSources, IrqError};
+use bootinfo::{PreExitReport, MAX_INTERRUPT_SOURCES};
+use std::cell::Cell;
+
+thread_local! {
+    static DISARMED: Cell<u64> = Cell::new(0);
+}
+
+/* Records which sources were disarmed, one bit per argument */
+unsafe fn disarm(arg: u64) {
+    DISARMED.with(|x| x.set(x.get() | 1 << arg));
+}
+
+struct Flag(bool);
+
+impl InterruptFlag for Flag {
+    fn is_enabled(&self) -> bool {
+        self.0
+    }
+    fn disable(&mut self) {
+        self.0 = false;
+    }
+}
+
+#[test]
+fn register_and_disarm() {
+    DISARMED.with(|x| x.set(0));
+    let mut sources = InterruptSources::new();
+    let uart = sources.register("ttyS0 rx", disarm, 0).unwrap();
+    let timer = sources.register("apic timer", disarm, 1).unwrap();
+    assert!(sources.is_armed(uart) && sources.is_armed(timer));
+    assert_eq!(
+        sources.armed().collect::<Vec<_>>(),
+        ["ttyS0 rx", "apic timer"]
+    );
+
+    unsafe { sources.disarm(timer) }.unwrap();
+    assert_eq!(DISARMED.with(|x| x.get()), 0b10);
+    assert_eq!(sources.armed().collect::<Vec<_>>(), ["ttyS0 rx"]);
+
+    /* Already disarmed ones aren't touched again */
+    DISARMED.with(|x| x.set(0));
+    assert_eq!(unsafe { sources.disarm_all() }, 1);
+    assert_eq!(DISARMED.with(|x| x.get()), 0b01);
+    assert_eq!(unsafe { sources.disarm_all() }, 0);
+    assert_eq!(sources.armed().count(), 0);
+
+    assert_eq!(unsafe { sources.disarm(7) }, Err(IrqError::UnknownSource));
+}
+
+#[test]
+fn bounded() {
+    let mut sources = InterruptSources::new();
+    for i in 0..MAX_INTERRUPT_SOURCES {
+        sources.register("source", disarm, i as u64).unwrap();
+    }
+    assert_eq!(
+        sources.register("one too many", disarm, 0),
+        Err(IrqError::TooManySources)
+    );
+}
+
+#[test]
+fn quiesce() {
+    DISARMED.with(|x| x.set(0));
+    let mut sources = InterruptSources::new();
+    sources.register("a", disarm, 2).unwrap();
+    sources.register("b", disarm, 3).unwrap();
+
+    let mut flag = Flag(true);
+    let report = unsafe { quiesce_interrupts(&mut sources, &mut flag) };
+    assert!(!flag.0);
+    assert_eq!(
+        report,
+        PreExitReport {
+            interrupts_disabled: true,
+            sources_disarmed: 2,
+        }
+    );
+    assert_eq!(DISARMED.with(|x| x.get()), 0b1100);
+
+    let report = unsafe { quiesce_interrupts(&mut sources, &mut flag) };
+    assert_eq!(report, PreExitReport::new());
+}
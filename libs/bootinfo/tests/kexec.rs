@@ -0,0 +1,401 @@
+use bootinfo::*;
+use core::num::NonZeroU64;
+use cpu::paging::{PDEntry, PDFlags, PDPEntry, PDPFlags, PML4Entry, PML4Flags, PTEntry, PTFlags};
+use cpu::paging::{MEGAPAGE_SIZE, PAGE_SIZE};
+use cpu::phys::{IdentityMapping, PhysWrite, PhysWriter};
+use cpu::{PhysAddr, PhysRange};
+use elf::{Class, Data, Header, HeaderIdent, Machine, OsAbi, ProgramHeader};
+use elf::{EHSIZE_X64, EV_CURRENT, MAGIC, PF_R, PF_W, PF_X};
+use uefi::memory::{Attributes, Descriptor, Type};
+
+const TEXT: &[u8] = b"\x90\x90\x90\xf4 kernel text";
+const RODATA: &[u8] = b"kernel rodata";
+const DATA: &[u8] = b"kernel data, followed by bss";
+/* File offsets of the segments, after the headers */
+const TEXT_OFFSET: u64 = 0x1000;
+const RODATA_OFFSET: u64 = 0x2000;
+const DATA_OFFSET: u64 = 0x3000;
+const DATA_MEMSZ: u64 = 0x3000;
+const NOTE_OFFSET: u64 = 0x3800;
+
+fn segments() -> [ProgramHeader; 3] {
+    let text = ProgramHeader::new_load(
+        PF_R | PF_X,
+        TEXT_OFFSET,
+        KERNEL_BASE,
+        TEXT.len() as u64,
+        TEXT.len() as u64,
+        MEGAPAGE_SIZE,
+    );
+    let rodata = ProgramHeader::new_load(
+        PF_R,
+        RODATA_OFFSET,
+        KERNEL_BASE + MEGAPAGE_SIZE,
+        RODATA.len() as u64,
+        RODATA.len() as u64,
+        MEGAPAGE_SIZE,
+    );
+    let data = ProgramHeader::new_load(
+        PF_R | PF_W,
+        DATA_OFFSET,
+        KERNEL_BASE + 2 * MEGAPAGE_SIZE,
+        DATA.len() as u64,
+        DATA_MEMSZ,
+        MEGAPAGE_SIZE,
+    );
+    return [text, rodata, data];
+}
+
+/* ELF header, program headers and segment contents, in a u64 buffer
+ * for alignment */
+fn make_elf(pheaders: &[ProgramHeader], entry: u64) -> Vec<u64> {
+    let header = Header {
+        e_ident: HeaderIdent {
+            ei_magic: MAGIC,
+            ei_class: Class::Bits64 as u8,
+            ei_data: Data::Lsb as u8,
+            ei_version: EV_CURRENT,
+            ei_osabi: OsAbi::SystemV as u8,
+            ei_abiversion: 0,
+            ei_pad: [0; 7],
+        },
+        e_type: elf::Type::Executable as u16,
+        e_machine: Machine::X64 as u16,
+        e_version: EV_CURRENT as u32,
+        e_entry: NonZeroU64::new(entry),
+        e_phoff: NonZeroU64::new(EHSIZE_X64 as u64),
+        e_shoff: None,
+        e_flags: 0,
+        e_ehsize: EHSIZE_X64 as u16,
+        e_phentsize: core::mem::size_of::<ProgramHeader>() as u16,
+        e_phnum: pheaders.len() as u16,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    };
+
+    let mut buf = vec![0u64; 0x4000 / 8];
+    unsafe {
+        let ptr = buf.as_mut_ptr() as *mut Header;
+        ptr.write(header);
+        let ptr = ptr.add(1) as *mut ProgramHeader;
+        ptr.copy_from_nonoverlapping(pheaders.as_ptr(), pheaders.len());
+    }
+    let bytes = as_bytes_mut(&mut buf);
+    bytes[TEXT_OFFSET as usize..][..TEXT.len()].copy_from_slice(TEXT);
+    bytes[RODATA_OFFSET as usize..][..RODATA.len()].copy_from_slice(RODATA);
+    bytes[DATA_OFFSET as usize..][..DATA.len()].copy_from_slice(DATA);
+    return buf;
+}
+
+fn as_bytes(buf: &[u64]) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(buf.as_ptr() as *const u8, buf.len() * 8) }
+}
+
+fn as_bytes_mut(buf: &mut [u64]) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 8) }
+}
+
+/* Leaked host memory standing in for physical memory, 2M aligned and
+ * filled with garbage so that missed zeroing shows */
+fn fake_phys(len: u64) -> PhysRange {
+    let buf = vec![0xAAu8; (len + MEGAPAGE_SIZE) as usize].leak();
+    let start = (buf.as_ptr() as u64 + MEGAPAGE_SIZE - 1) & !(MEGAPAGE_SIZE - 1);
+    return PhysRange::new(start, len).unwrap();
+}
+
+unsafe fn phys_bytes(addr: u64, len: u64) -> &'static [u8] {
+    core::slice::from_raw_parts(addr as *const u8, len as usize)
+}
+
+fn conventional(range: PhysRange) -> Descriptor {
+    Descriptor::new(
+        Type::Conventional,
+        range,
+        Attributes::new().set_write_back(),
+    )
+    .unwrap()
+}
+
+fn previous() -> core::pin::Pin<Box<Bootinfo>> {
+    let mut previous = Box::pin(Bootinfo::new());
+    let bootinfo = unsafe { previous.as_mut().get_unchecked_mut() };
+    bootinfo.uefi_revision = uefi::Revision::new(2, 70);
+    bootinfo.seed = [0x5A; 32];
+    bootinfo.lineage = BootLineage::from_seed(&bootinfo.seed);
+    bootinfo.record(BootCapabilities::set_entropy_seeded);
+    bootinfo.record(BootCapabilities::set_tsc_calibrated);
+    bootinfo.acpi.hpet = 0xfed0_0000;
+    bootinfo.record(BootCapabilities::set_hpet);
+    return previous;
+}
+
+fn kexec(elf: &[u8], map: &[Descriptor], previous: &Bootinfo) -> Result<KexecImage, KexecError> {
+    let kernel = KernelImage::parse(elf).unwrap();
+    let mut frames = MapFrameAllocator::new(map, &[]);
+    let mut log = |_: &PhysWrite| {};
+    unsafe { kexec_prepare(&kernel, map, previous, &mut frames, &mut log) }
+}
+
+#[test]
+fn end_to_end() {
+    let memory = fake_phys(16 * MEGAPAGE_SIZE);
+    let map = [conventional(memory)];
+    let elf = make_elf(&segments(), KERNEL_BASE + 0x3);
+    let previous = previous();
+
+    let image = kexec(as_bytes(&elf), &map, &previous).unwrap();
+    let bootinfo = &*image.bootinfo;
+    assert!(bootinfo.checksum_ok());
+    assert_eq!(image.entry, KERNEL_BASE + 0x3);
+    assert_eq!(image.cr3, &bootinfo.paging_root as *const _ as u64);
+    assert_eq!(bootinfo.this.as_u64(), image.block.bootinfo.start());
+    assert_eq!(bootinfo.kernel_pslice.addr().as_u64(), image.kernel.start());
+
+    /* Kernel segments through the new tables */
+    let text = unsafe { bootinfo.translate(KERNEL_BASE) }.unwrap().as_u64();
+    assert_eq!(text, image.kernel.start());
+    assert_eq!(unsafe { phys_bytes(text, TEXT.len() as u64) }, TEXT);
+    let rodata = unsafe { bootinfo.translate(KERNEL_BASE + MEGAPAGE_SIZE) }.unwrap();
+    assert_eq!(
+        unsafe { phys_bytes(rodata.as_u64(), RODATA.len() as u64) },
+        RODATA
+    );
+    let data = unsafe { bootinfo.translate(KERNEL_BASE + 2 * MEGAPAGE_SIZE) }
+        .unwrap()
+        .as_u64();
+    assert_eq!(unsafe { phys_bytes(data, DATA.len() as u64) }, DATA);
+    let bss = unsafe { phys_bytes(data + DATA.len() as u64, MEGAPAGE_SIZE - DATA.len() as u64) };
+    assert!(bss.iter().all(|&x| x == 0));
+
+    /* Everything the trampoline touches after switching is 1:1 */
+    for &addr in &[
+        image.block.trampoline.start(),
+        image.stack_pointer(),
+        image.block.stack.start(),
+        bootinfo.this.as_u64(),
+    ] {
+        assert_eq!(
+            unsafe { bootinfo.translate(addr) }.map(|x| x.as_u64()),
+            Some(addr)
+        );
+    }
+    assert_eq!(image.stack_pointer() % 16, 8);
+    assert!(unsafe { bootinfo.translate(0) }.is_none());
+
+    /* Carried forward */
+    assert!(bootinfo.uefi_revision == previous.uefi_revision);
+    assert_eq!(bootinfo.seed, previous.seed);
+    assert_eq!(bootinfo.lineage.generation, 1);
+    assert_eq!(bootinfo.lineage.root_id, previous.lineage.boot_id);
+    assert_ne!(bootinfo.lineage.boot_id, previous.lineage.boot_id);
+    assert!(bootinfo.capabilities.kexec());
+    assert!(bootinfo.capabilities.entropy_seeded());
+    assert!(!bootinfo.capabilities.tsc_calibrated());
+    assert_eq!(bootinfo.acpi, previous.acpi);
+    assert!(bootinfo.capabilities.hpet());
+    assert_eq!(bootinfo.abi, AbiContract::legacy());
+}
+
+#[test]
+fn new_frames_are_taken_in_memory_map() {
+    let memory = fake_phys(16 * MEGAPAGE_SIZE);
+    let map = [conventional(memory)];
+    let elf = make_elf(&segments(), KERNEL_BASE);
+    let previous = previous();
+
+    let image = kexec(as_bytes(&elf), &map, &previous).unwrap();
+    let meminfo = &image.bootinfo.uefi_meminfo;
+    let pages: u64 = meminfo.iter().map(|x| x.pages).sum();
+    assert_eq!(pages, memory.len() / PAGE_SIZE);
+
+    let loader_data = |range: PhysRange| {
+        meminfo.iter().any(|x| {
+            x.memory_type() == Some(Type::LoaderData)
+                && x.phys_range().unwrap().contains_range(&range)
+        })
+    };
+    assert!(loader_data(image.kernel));
+    assert!(loader_data(image.block.range()));
+    assert!(meminfo
+        .iter()
+        .all(|x| x.attributes.bits() == map[0].attributes.bits()));
+}
+
+#[test]
+fn installs_trampoline() {
+    let memory = fake_phys(16 * MEGAPAGE_SIZE);
+    let map = [conventional(memory)];
+    let elf = make_elf(&segments(), KERNEL_BASE);
+    let previous = previous();
+    let image = kexec(as_bytes(&elf), &map, &previous).unwrap();
+
+    /* The running kernel's tables map the trampoline page 1:1 too */
+    let virt = image.block.trampoline.start();
+    let index = |level: u32| ((virt >> (12 + 9 * level)) % 512) as usize;
+    let phys_of = |x: u64| PhysAddr::new(x).unwrap();
+    let mut old = Box::new(Bootinfo::new());
+    let (pdp, pd, pt) = (
+        &old.pdp as *const _ as u64,
+        &old.pd as *const _ as u64,
+        &old.page_table as *const _ as u64,
+    );
+    old.paging_root[index(3)] = PML4Entry::new(phys_of(pdp), PML4Flags::new().set_present());
+    old.pdp[index(2)] = PDPEntry::new(phys_of(pd), PDPFlags::new().set_present());
+    old.pd[index(1)] = PDEntry::new(phys_of(pt), PDFlags::new().set_present());
+    old.page_table[index(0)] = PTEntry::new(phys_of(virt), PTFlags::new().set_present());
+
+    let allowed = [image.block.trampoline];
+    let mut log = |_: &PhysWrite| {};
+    let trampoline = unsafe {
+        let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+        image.install_trampoline(&old.paging_root, &mut writer)
+    };
+    assert_eq!(trampoline.unwrap().virt(), virt);
+    assert_eq!(
+        unsafe { phys_bytes(virt, TRAMPOLINE.len() as u64) },
+        &TRAMPOLINE
+    );
+}
+
+#[test]
+fn out_of_memory() {
+    /* Room for the kernel, not for the block after it */
+    let memory = fake_phys(3 * MEGAPAGE_SIZE);
+    let map = [conventional(memory)];
+    let elf = make_elf(&segments(), KERNEL_BASE);
+    let previous = previous();
+
+    let result = kexec(as_bytes(&elf), &map, &previous);
+    assert!(matches!(result, Err(KexecError::OutOfMemory)));
+}
+
+#[test]
+fn requirements_checked() {
+    let memory = fake_phys(16 * MEGAPAGE_SIZE);
+    let map = [conventional(memory)];
+    let previous = previous();
+
+    let note = requirements_note(KernelRequirements {
+        min_stack: 2 * KEXEC_STACK_SIZE,
+        ..KernelRequirements::DEFAULT
+    });
+    let size = core::mem::size_of_val(&note);
+    let mut pheaders = segments().to_vec();
+    let mut header = ProgramHeader::new_load(PF_R, NOTE_OFFSET, 0, size as u64, size as u64, 4);
+    header.p_type = elf::SegmentType::Note.to_integer();
+    pheaders.push(header);
+    let mut elf = make_elf(&pheaders, KERNEL_BASE);
+    let bytes = unsafe { core::slice::from_raw_parts(&note as *const _ as *const u8, size) };
+    as_bytes_mut(&mut elf)[NOTE_OFFSET as usize..][..size].copy_from_slice(bytes);
+
+    let kernel = KernelImage::parse(as_bytes(&elf)).unwrap();
+    assert_eq!(
+        kernel.requirements().unwrap().min_stack,
+        2 * KEXEC_STACK_SIZE
+    );
+    let result = kexec(as_bytes(&elf), &map, &previous);
+    assert!(matches!(
+        result,
+        Err(KexecError::Requirement(UnmetRequirement::StackSize { need, have }))
+            if need == 2 * KEXEC_STACK_SIZE && have == KEXEC_STACK_SIZE
+    ));
+
+    /* Without the note the block's stack is enough */
+    let elf = make_elf(&segments(), KERNEL_BASE);
+    let kernel = KernelImage::parse(as_bytes(&elf)).unwrap();
+    assert_eq!(kernel.requirements().unwrap(), KernelRequirements::DEFAULT);
+    let image = kexec(as_bytes(&elf), &map, &previous).unwrap();
+    assert_eq!(image.bootinfo.kernel_stack, image.block.stack);
+}
+
+#[test]
+fn no_entry() {
+    let memory = fake_phys(16 * MEGAPAGE_SIZE);
+    let map = [conventional(memory)];
+    let previous = previous();
+
+    /* In rodata, not text */
+    let elf = make_elf(&segments(), KERNEL_BASE + MEGAPAGE_SIZE);
+    let result = kexec(as_bytes(&elf), &map, &previous);
+    assert!(matches!(result, Err(KexecError::NoEntry)));
+}
+
+#[test]
+fn kernel_image_layout() {
+    let elf = make_elf(&segments(), KERNEL_BASE);
+    let kernel = KernelImage::parse(as_bytes(&elf)).unwrap();
+    assert_eq!(kernel.size(), 3 * MEGAPAGE_SIZE);
+    assert_eq!(kernel.entry(), Some(KERNEL_BASE));
+    assert!(kernel.other_headers().is_empty());
+
+    let loads = kernel.loads(0x4000_0000).unwrap();
+    assert_eq!(loads[2].vaddr, KERNEL_BASE + 2 * MEGAPAGE_SIZE);
+    assert_eq!(loads[2].filled.start(), 0x4000_0000 + 2 * MEGAPAGE_SIZE);
+    assert_eq!(loads[2].filled.len(), DATA.len() as u64);
+    assert_eq!(loads[2].bss.end(), 0x4000_0000 + 3 * MEGAPAGE_SIZE);
+    assert_eq!(loads[1].megapages().len(), 1);
+    assert!(kernel.loads(0x4000_1000).is_none());
+
+    let mut writable_text = segments();
+    writable_text[0].p_flags |= PF_W;
+    let elf = make_elf(&writable_text, KERNEL_BASE);
+    let result = KernelImage::parse(as_bytes(&elf));
+    assert!(matches!(result, Err(KernelImageError::Layout { index: 0 })));
+
+    let mut gap = segments();
+    gap[2].p_vaddr += MEGAPAGE_SIZE;
+    let elf = make_elf(&gap, KERNEL_BASE);
+    let result = KernelImage::parse(as_bytes(&elf));
+    assert!(matches!(result, Err(KernelImageError::Layout { index: 2 })));
+
+    let elf = make_elf(&segments()[..2], KERNEL_BASE);
+    let result = KernelImage::parse(as_bytes(&elf));
+    assert!(matches!(result, Err(KernelImageError::MissingSegment)));
+}
+
+#[test]
+fn map_allocator() {
+    let range = |start: u64, len: u64| PhysRange::new(start, len).unwrap();
+    let map = [
+        conventional(range(0, MEGAPAGE_SIZE)),
+        Descriptor::new(
+            Type::LoaderData,
+            range(0x20_0000, 0x20_0000),
+            Attributes::new(),
+        )
+        .unwrap(),
+        conventional(range(0x40_0000, 0x40_0000)),
+    ];
+    let reserved = [range(0x40_0000, 0x3000)];
+    let mut frames = MapFrameAllocator::new(&map, &reserved);
+
+    /* Never below LOW_MEMORY_END */
+    assert_eq!(
+        frames.alloc_frames(1, 1),
+        Some(range(LOW_MEMORY_END, 0x1000))
+    );
+    /* Only 1M left in the first region, LoaderData is skipped,
+     * the next 2M boundary after the reserved range */
+    assert_eq!(
+        frames.alloc_frames(MEGAPAGE_SIZE, MEGAPAGE_SIZE),
+        Some(range(0x60_0000, MEGAPAGE_SIZE))
+    );
+    /* Bump allocator, what was skipped stays free */
+    assert_eq!(frames.alloc_frames(0x1000, 0x1000), None);
+}
+
+#[test]
+fn lineage() {
+    let first = BootLineage::from_seed(&[1; 32]);
+    assert_eq!(first, BootLineage::from_seed(&[1; 32]));
+    assert_ne!(first.boot_id, BootLineage::from_seed(&[2; 32]).boot_id);
+    assert_eq!((first.root_id, first.generation), (first.boot_id, 0));
+
+    let second = first.next();
+    let third = second.next();
+    assert_eq!((second.generation, third.generation), (1, 2));
+    assert_eq!(third.root_id, first.boot_id);
+    assert_ne!(second.boot_id, first.boot_id);
+    assert_ne!(third.boot_id, second.boot_id);
+}
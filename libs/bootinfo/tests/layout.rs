@@ -0,0 +1,65 @@
+use bootinfo::*;
+use core::mem::size_of;
+use cpu::{PhysRange, PhysSlice};
+use uefi::memory::{Attributes, Descriptor, Type};
+
+fn descriptor(start: u64, len: u64) -> Descriptor {
+    let range = PhysRange::new(start, len).unwrap();
+    Descriptor::new(Type::Conventional, range, Attributes::new()).unwrap()
+}
+
+/* The loader and kernel agree on these through the ABI note, changing
+ * them is an incompatible change of the handoff */
+#[test]
+fn default_layout() {
+    assert_eq!(DEFAULT_BUF_SIZE, 16384);
+    assert_eq!(<Bootinfo>::BUF_SIZE, DEFAULT_BUF_SIZE);
+
+    let bootinfo = Box::new(Bootinfo::new());
+    let base = &*bootinfo as *const Bootinfo as usize;
+    let buf = bootinfo.buf.as_ptr() as usize;
+    assert_eq!(buf - base, 0x4018);
+    assert_eq!(size_of::<Bootinfo>(), 0xc000);
+}
+
+#[test]
+fn budget() {
+    let total = budget_size(&BUF_BUDGET);
+    assert!(total <= DEFAULT_BUF_SIZE);
+    /* Why the default isn't 8K anymore */
+    assert!(total > LEGACY_BUF_SIZE as usize);
+
+    assert_eq!(budgeted(AllocPurpose::Idt), 4096);
+    assert_eq!(budgeted(AllocPurpose::Other), 0);
+}
+
+#[test]
+fn budget_fits_arena() {
+    let bootinfo = Box::leak(Box::new(Bootinfo::new()));
+    let mut builder = unsafe { BootinfoBuilder::new(bootinfo) }
+        .memory_map(vec![descriptor(0x10_0000, 0x10_0000)]);
+    assert_eq!(builder.arena().remaining(), DEFAULT_BUF_SIZE);
+
+    for planned in &BUF_BUDGET {
+        builder
+            .arena()
+            .alloc_bytes(planned.purpose, planned.size, planned.align)
+            .unwrap();
+    }
+    assert_eq!(builder.arena().reservations().len(), BUF_BUDGET.len());
+}
+
+#[test]
+fn custom_buf() {
+    let bootinfo: &'static mut Bootinfo<32768> = Box::leak(Box::new(Bootinfo::with_buf()));
+    let builder = unsafe { BootinfoBuilder::new(bootinfo) }
+        .memory_map(vec![descriptor(0x10_0000, 0x10_0000)]);
+    assert_eq!(builder.buf.len(), 32768);
+
+    let bootinfo = builder
+        .kernel(PhysSlice::null())
+        .console(SerialSinks::new())
+        .finish();
+    assert!(bootinfo.checksum_ok());
+    assert!(size_of::<Bootinfo<32768>>() > size_of::<Bootinfo>());
+}
@@ -0,0 +1,176 @@
+#![cfg(feature = "limine-compat")]
+
+use bootinfo::limine::*;
+use bootinfo::Module;
+use cpu::{PhysAddr, PhysRange, PhysSlice};
+use std::convert::TryInto;
+use uefi::memory::{Attributes, Descriptor, Type};
+
+const BASE: u64 = 0x80_0000;
+
+fn descriptor(typ: Type, start: u64, len: u64) -> Descriptor {
+    let range = PhysRange::new(start, len).unwrap();
+    Descriptor::new(typ, range, Attributes::new().set_write_back()).unwrap()
+}
+
+/// Word `i` of the buffer behind kernel address `addr`
+fn word(buf: &[u8], addr: u64, i: usize) -> u64 {
+    let at = (addr - BASE) as usize + 8 * i;
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+fn request(id: [u64; 2], revision: u64) -> Vec<u8> {
+    let words = [COMMON_MAGIC[0], COMMON_MAGIC[1], id[0], id[1], revision, 0];
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+#[test]
+fn ids_round_trip() {
+    for kind in RequestKind::ALL {
+        assert_eq!(RequestKind::from_id(kind.id()), Some(kind));
+    }
+    assert_eq!(RequestKind::from_id([1, 2]), None);
+}
+
+#[test]
+fn scan() {
+    let mut image = vec![0u8; 24];
+    image.extend(request(RequestKind::Hhdm.id(), 0));
+    /* Not at an 8 byte boundary */
+    image.extend([0u8; 4]);
+    image.extend(request(RequestKind::Rsdp.id(), 0));
+    image.extend([0u8; 4]);
+    image.extend(request([0xdead, 0xbeef], 2));
+    /* Cut off by the end of the image */
+    image.extend(&request(RequestKind::Memmap.id(), 0)[..40]);
+
+    let found: Vec<Request> = requests(&image).collect();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].offset, 24);
+    assert_eq!(found[0].kind(), Some(RequestKind::Hhdm));
+    assert_eq!(found[1].offset, 24 + 48 + 4 + 48 + 4);
+    assert_eq!(found[1].kind(), None);
+    assert_eq!(found[1].revision, 2);
+
+    /* Unknown ones are left alone */
+    let answered = answer_requests(&mut image, |r| r.kind().map(|_| 0x1234_5678));
+    assert_eq!(answered, 1);
+    assert_eq!(&image[24 + 40..24 + 48], &0x1234_5678u64.to_le_bytes());
+    assert_eq!(&image[found[1].offset + 40..found[1].offset + 48], &[0; 8]);
+    assert_eq!(requests(&image).collect::<Vec<_>>(), found);
+}
+
+#[test]
+fn simple_responses() {
+    let mut buf = vec![0xAAu8; 4096];
+    let mut responses = Responses::new(&mut buf, BASE);
+    let hhdm = responses.hhdm(0).unwrap();
+    let rsdp = responses.rsdp(0xE_0000).unwrap();
+    let time = responses.boot_time(1_700_000_000).unwrap();
+    assert_eq!(responses.used(), 48);
+
+    assert_eq!([word(&buf, hhdm, 0), word(&buf, hhdm, 1)], [0, 0]);
+    assert_eq!([word(&buf, rsdp, 0), word(&buf, rsdp, 1)], [0, 0xE_0000]);
+    assert_eq!(word(&buf, time, 1), 1_700_000_000);
+}
+
+#[test]
+fn memmap() {
+    let kernel = PhysRange::new(0x20_0000, 0x40_0000).unwrap();
+    let meminfo = [
+        descriptor(Type::Conventional, 0, 0x9_F000),
+        descriptor(Type::LoaderData, 0x20_0000, 0x20_0000),
+        descriptor(Type::LoaderData, 0x60_0000, 0x1000),
+        descriptor(Type::BootServicesData, 0x61_0000, 0x1000),
+        descriptor(Type::AcpiReclaim, 0x62_0000, 0x1000),
+        descriptor(Type::AcpiNVS, 0x63_0000, 0x1000),
+        descriptor(Type::Mmio, 0xFEC0_0000, 0x1000),
+    ];
+    let types = [
+        memmap::USABLE,
+        memmap::KERNEL_AND_MODULES,
+        memmap::BOOTLOADER_RECLAIMABLE,
+        memmap::USABLE,
+        memmap::ACPI_RECLAIMABLE,
+        memmap::ACPI_NVS,
+        memmap::RESERVED,
+    ];
+
+    let mut buf = vec![0xAAu8; 4096];
+    let response = Responses::new(&mut buf, BASE).memmap(&meminfo, kernel).unwrap();
+    assert_eq!(word(&buf, response, 0), 0);
+    assert_eq!(word(&buf, response, 1), meminfo.len() as u64);
+
+    let entries = word(&buf, response, 2);
+    for (i, (d, typ)) in meminfo.iter().zip(types).enumerate() {
+        let entry = word(&buf, entries, i);
+        assert_eq!(entry % 8, 0);
+        assert_eq!(word(&buf, entry, 0), d.phys_start);
+        assert_eq!(word(&buf, entry, 1), d.pages * 4096);
+        assert_eq!(word(&buf, entry, 2), typ, "entry {}", i);
+    }
+}
+
+#[test]
+fn modules() {
+    let modules = [
+        Module::new("initrd.img", PhysSlice::new(PhysAddr::new(0x100_0000).unwrap(), 5000)),
+        Module::new("sovos.cfg", PhysSlice::new(PhysAddr::new(0x100_2000).unwrap(), 30)),
+    ];
+
+    let mut buf = vec![0xAAu8; 4096];
+    let response = Responses::new(&mut buf, BASE).modules(&modules).unwrap();
+    assert_eq!(word(&buf, response, 1), 2);
+
+    let files = word(&buf, response, 2);
+    for (i, module) in modules.iter().enumerate() {
+        let file = word(&buf, files, i);
+        assert_eq!(word(&buf, file, 0), 0);
+        assert_eq!(word(&buf, file, 1), module.data.addr().as_u64());
+        assert_eq!(word(&buf, file, 2), module.data.len() as u64);
+
+        let path = (word(&buf, file, 3) - BASE) as usize;
+        let len = buf[path..].iter().position(|&b| b == 0).unwrap();
+        assert_eq!(&buf[path..path + len], module.name());
+        let cmdline = (word(&buf, file, 4) - BASE) as usize;
+        assert_eq!(buf[cmdline], 0);
+
+        /* Partition fields stay zero */
+        for w in 5..14 {
+            assert_eq!(word(&buf, file, w), 0);
+        }
+    }
+}
+
+#[test]
+fn full() {
+    let meminfo: Vec<Descriptor> = (0..8).map(|i| descriptor(Type::Conventional, i * 0x1000, 0x1000)).collect();
+    let kernel = PhysRange::new(0x20_0000, 0x20_0000).unwrap();
+
+    let mut buf = vec![0u8; 8 * 32 - 1];
+    let mut responses = Responses::new(&mut buf, BASE);
+    assert_eq!(responses.memmap(&meminfo, kernel), None);
+    assert!(responses.rsdp(0xE_0000).is_some());
+}
+
+#[test]
+fn unix_time() {
+    let mut time = uefi::Time::new();
+    time.year = 2024;
+    time.month = 2;
+    time.day = 29;
+    time.hour = 12;
+    time.time_zone = uefi::Time::UNSPECIFIED_TIMEZONE;
+    assert_eq!(bootinfo::limine::unix_time(&time), 1709208000);
+
+    time.year = 1970;
+    time.month = 1;
+    time.day = 1;
+    time.hour = 0;
+    assert_eq!(bootinfo::limine::unix_time(&time), 0);
+
+    /* 01:00 an hour east of UTC */
+    time.hour = 1;
+    time.time_zone = 60;
+    assert_eq!(bootinfo::limine::unix_time(&time), 0);
+}
@@ -0,0 +1,281 @@
+mod common;
+
+use bootinfo::*;
+use common::*;
+use core::pin::Pin;
+use cpu::paging::{MEGAPAGE_SIZE, PAGE_SIZE};
+use cpu::phys::{IdentityMapping, OffsetMapping, PhysWrite, PhysWriter};
+use cpu::{PhysAddr, PhysRange};
+use uefi::memory::{Attributes, Descriptor, Type};
+
+/* Where a real-mode AP trampoline would live */
+const TRAMPOLINE_PAGE: u64 = 0x8000;
+
+/* Low memory identity-mapped, like firmware leaves it */
+fn identity_low(granularity: MapGranularity) -> Pin<Box<Bootinfo>> {
+    let mut bootinfo = pinned_bootinfo();
+    let low = PhysRange::new(0, MEGAPAGE_SIZE).unwrap();
+    let mapped = unsafe {
+        bootinfo
+            .as_mut()
+            .map_range(0, low, granularity, SegmentPerms::RW)
+    };
+    assert_eq!(mapped.unwrap().granularity, granularity);
+    return bootinfo;
+}
+
+fn descriptor(typ: Type, start: u64, len: u64) -> Descriptor {
+    let range = PhysRange::new(start, len).unwrap();
+    Descriptor::new(typ, range, Attributes::new().set_write_back()).unwrap()
+}
+
+fn trampoline_whitelist() -> LowMemWhitelist {
+    let mut whitelist = LowMemWhitelist::new();
+    whitelist.keep(TRAMPOLINE_PAGE + 0x10).unwrap();
+    return whitelist;
+}
+
+#[test]
+fn null_page() {
+    let mut bootinfo = identity_low(MapGranularity::Page);
+    let whitelist = LowMemWhitelist::new();
+    let unmapped = unsafe {
+        bootinfo
+            .as_mut()
+            .unmap_null_guard(NULL_GUARD_SIZE, &whitelist)
+    };
+    assert_eq!(unmapped, Ok(1));
+
+    assert!(unsafe { bootinfo.translate(0) }.is_none());
+    assert!(unsafe { bootinfo.translate(0xFFF) }.is_none());
+    assert_eq!(unsafe { bootinfo.translate(0x1000) }.unwrap().as_u64(), 0x1000);
+
+    /* Already unmapped */
+    let unmapped = unsafe {
+        bootinfo
+            .as_mut()
+            .unmap_null_guard(NULL_GUARD_SIZE, &whitelist)
+    };
+    assert_eq!(unmapped, Ok(0));
+}
+
+#[test]
+fn wide_guard_keeps_whitelist() {
+    let mut bootinfo = identity_low(MapGranularity::Page);
+    let whitelist = trampoline_whitelist();
+    let unmapped = unsafe {
+        bootinfo
+            .as_mut()
+            .unmap_null_guard(WIDE_NULL_GUARD_SIZE, &whitelist)
+    };
+    assert_eq!(unmapped, Ok(15));
+
+    assert!(unsafe { bootinfo.translate(0x4000) }.is_none());
+    assert!(unsafe { bootinfo.translate(0xF000) }.is_none());
+    assert_eq!(
+        unsafe { bootinfo.translate(TRAMPOLINE_PAGE) }.unwrap().as_u64(),
+        TRAMPOLINE_PAGE
+    );
+    assert_eq!(unsafe { bootinfo.translate(0x1_0000) }.unwrap().as_u64(), 0x1_0000);
+}
+
+#[test]
+fn guard_errors() {
+    let mut bootinfo = identity_low(MapGranularity::Megapage);
+    let whitelist = LowMemWhitelist::new();
+    let unmapped = unsafe {
+        bootinfo
+            .as_mut()
+            .unmap_null_guard(NULL_GUARD_SIZE, &whitelist)
+    };
+    assert_eq!(unmapped, Err(LowMemError::LargePage));
+    assert!(unsafe { bootinfo.translate(0) }.is_some());
+
+    let unmapped = unsafe { bootinfo.as_mut().unmap_null_guard(0x1800, &whitelist) };
+    assert_eq!(unmapped, Err(LowMemError::BadGuard));
+
+    /* Nothing mapped, nothing to do */
+    let mut bootinfo = pinned_bootinfo();
+    let unmapped = unsafe {
+        bootinfo
+            .as_mut()
+            .unmap_null_guard(NULL_GUARD_SIZE, &whitelist)
+    };
+    assert_eq!(unmapped, Ok(0));
+
+    /* No init_this */
+    let mut bootinfo = Box::pin(Bootinfo::new());
+    let unmapped = unsafe {
+        bootinfo
+            .as_mut()
+            .unmap_null_guard(NULL_GUARD_SIZE, &whitelist)
+    };
+    assert_eq!(unmapped, Err(LowMemError::Unpinned));
+}
+
+#[test]
+fn whitelist() {
+    let mut whitelist = LowMemWhitelist::new();
+    assert_eq!(whitelist.keep(LOW_MEMORY_END), Err(LowMemError::NotLow));
+    whitelist.keep(0x8000).unwrap();
+    whitelist.keep(0x8FFF).unwrap();
+    assert_eq!(whitelist.pages(), &[0x8000]);
+    assert!(whitelist.contains(0x8123));
+    assert!(!whitelist.contains(0x9000));
+
+    for page in 1..4 {
+        whitelist.keep(0x8000 + page * PAGE_SIZE).unwrap();
+    }
+    assert_eq!(whitelist.keep(0x1000), Err(LowMemError::TooManyPages));
+}
+
+#[test]
+fn poison() {
+    /* Stand-in for the first MiB of physical memory */
+    let mut memory = vec![0u16; (LOW_MEMORY_END / 2) as usize];
+    let mapping = OffsetMapping(memory.as_mut_ptr() as u64);
+    let meminfo = [
+        descriptor(Type::Conventional, 0, 0x9_F000),
+        /* EBDA */
+        descriptor(Type::Reserved, 0x9_F000, 0x1000),
+        descriptor(Type::BootServicesData, 0xA_0000, 0x1_0000),
+        descriptor(Type::Conventional, 0xB_0000, 0x1000),
+        /* Only the part below 1MiB is low memory */
+        descriptor(Type::Conventional, 0xF_F000, 0x10_1000),
+    ];
+
+    let whitelist = trampoline_whitelist();
+    let allowed = [PhysRange::new(0, LOW_MEMORY_END).unwrap()];
+    let mut writes = Vec::new();
+    let mut log = |w: &PhysWrite| writes.push(w.range);
+    let poisoned = unsafe {
+        let mut writer = PhysWriter::new(&allowed, mapping, &mut log);
+        poison_low_memory(
+            &meminfo,
+            NULL_GUARD_SIZE,
+            &whitelist,
+            DEFAULT_POISON,
+            &mut writer,
+        )
+    };
+    assert_eq!(poisoned, 0x9_F000 - 0x2000 + 0x1000 + 0x1000);
+    /* One write per run around the trampoline, not one per page */
+    let range = |start, end| PhysRange::from_start_end(start, end).unwrap();
+    assert_eq!(
+        writes,
+        [
+            range(0x1000, TRAMPOLINE_PAGE),
+            range(TRAMPOLINE_PAGE + 0x1000, 0x9_F000),
+            range(0xB_0000, 0xB_1000),
+            range(0xF_F000, LOW_MEMORY_END),
+        ]
+    );
+
+    let word = |addr: u64| memory[(addr / 2) as usize];
+    assert_eq!(word(0), 0);
+    assert_eq!(word(0xFFE), 0);
+    assert_eq!(word(0x1000), 0xDEAD);
+    assert_eq!(word(0x7FFE), 0xDEAD);
+    assert_eq!(word(TRAMPOLINE_PAGE), 0);
+    assert_eq!(word(0x9_EFFE), 0xDEAD);
+    assert_eq!(word(0x9_F000), 0);
+    assert_eq!(word(0xA_0000), 0);
+    assert_eq!(word(0xB_0000), 0xDEAD);
+    assert_eq!(word(0xF_FFFE), 0xDEAD);
+}
+
+#[repr(C, align(4096))]
+struct RawTable([u64; 512]);
+
+const P_RW: u64 = 0b11;
+const PS: u64 = 1 << 7;
+
+/* Firmware-like hierarchy where `pdp[0]` is whatever `low` says */
+struct LiveTables {
+    pml4: Box<RawTable>,
+    pdp: Box<RawTable>,
+    pd: Box<RawTable>,
+    spare: Box<[RawTable; 2]>,
+}
+
+impl LiveTables {
+    fn new(gigapage: bool) -> Self {
+        let mut tables = Self {
+            pml4: Box::new(RawTable([0; 512])),
+            pdp: Box::new(RawTable([0; 512])),
+            pd: Box::new(RawTable([0; 512])),
+            spare: Box::new([RawTable([!0; 512]), RawTable([!0; 512])]),
+        };
+        tables.pml4.0[0] = addr(&tables.pdp) | P_RW;
+        if gigapage {
+            tables.pdp.0[0] = P_RW | PS;
+        } else {
+            tables.pdp.0[0] = addr(&tables.pd) | P_RW;
+            /* Write-back through PAT entry 4, NX */
+            tables.pd.0[0] = P_RW | PS | (1 << 12) | (1 << 63);
+        }
+        return tables;
+    }
+
+    fn unmap(&mut self, guard: u64, whitelist: &LowMemWhitelist) -> Result<usize, LowMemError> {
+        let root = PhysAddr::new(addr(&self.pml4)).unwrap();
+        let spare = PhysRange::new(addr(&self.spare[0]), 2 * PAGE_SIZE).unwrap();
+        unsafe { unmap_live_null_guard(root, guard, whitelist, spare, &IdentityMapping) }
+    }
+}
+
+fn addr<T>(x: &T) -> u64 {
+    x as *const T as u64
+}
+
+#[test]
+fn live_megapage_is_split() {
+    let mut tables = LiveTables::new(false);
+    let whitelist = trampoline_whitelist();
+    assert_eq!(tables.unmap(WIDE_NULL_GUARD_SIZE, &whitelist), Ok(15));
+
+    let pt = &tables.spare[1].0;
+    assert_eq!(tables.pd.0[0], addr(pt) | P_RW | (1 << 63));
+    assert_eq!(pt[0], 0);
+    assert_eq!(pt[0xF], 0);
+    /* PAT moves from bit 12 to bit 7 */
+    let attrs = P_RW | (1 << 7) | (1 << 63);
+    assert_eq!(pt[8], TRAMPOLINE_PAGE | attrs);
+    assert_eq!(pt[0x10], 0x10000 | attrs);
+    assert_eq!(pt[511], 0x1F_F000 | attrs);
+    /* No gigapage, the spare directory isn't touched */
+    assert_eq!(tables.spare[0].0[0], !0);
+
+    /* Already unmapped, nothing left to split */
+    assert_eq!(tables.unmap(WIDE_NULL_GUARD_SIZE, &whitelist), Ok(0));
+}
+
+#[test]
+fn live_gigapage_is_split() {
+    let mut tables = LiveTables::new(true);
+    assert_eq!(tables.unmap(NULL_GUARD_SIZE, &LowMemWhitelist::new()), Ok(1));
+
+    let pd = &tables.spare[0].0;
+    let pt = &tables.spare[1].0;
+    assert_eq!(tables.pdp.0[0], addr(pd) | P_RW);
+    assert_eq!(pd[0], addr(pt) | P_RW);
+    assert_eq!(pd[1], MEGAPAGE_SIZE | P_RW | PS);
+    assert_eq!(pd[511], 511 * MEGAPAGE_SIZE | P_RW | PS);
+    assert_eq!(pt[0], 0);
+    assert_eq!(pt[1], 0x1000 | P_RW);
+}
+
+#[test]
+fn live_not_mapped() {
+    let mut tables = LiveTables::new(false);
+    tables.pml4.0[0] = 0;
+    assert_eq!(tables.unmap(NULL_GUARD_SIZE, &LowMemWhitelist::new()), Ok(0));
+
+    let root = PhysAddr::new(addr(&tables.pml4)).unwrap();
+    let one_page = PhysRange::new(addr(&tables.spare[0]), PAGE_SIZE).unwrap();
+    let whitelist = LowMemWhitelist::new();
+    let unmapped = unsafe {
+        unmap_live_null_guard(root, NULL_GUARD_SIZE, &whitelist, one_page, &IdentityMapping)
+    };
+    assert_eq!(unmapped, Err(LowMemError::NoSpareTables));
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use bootinfo::{Bootinfo, KernelPermPolicy, MapGranularity, MapKernelError, OwnedTable};
+use bootinfo::{SegmentPerms, KERNEL_BASE};
+use common::*;
+use core::pin::Pin;
+use cpu::paging::{Entry, MEGAPAGE_SIZE};
+use cpu::{PhysAddr, PhysSlice};
+
+fn megapages(addr: u64, count: u64) -> PhysSlice<cpu::paging::Megapage> {
+    PhysSlice::new(PhysAddr::new(addr).unwrap(), count)
+}
+
+#[test]
+fn default_is_wx() {
+    let policy = KernelPermPolicy::default();
+    assert_eq!(policy.text, SegmentPerms::RX);
+    assert_eq!(policy.rodata, SegmentPerms::R);
+    assert_eq!(policy.data, SegmentPerms::RW);
+    assert_eq!(policy.validate(), Ok(()));
+
+    let mut bootinfo = pinned_bootinfo();
+    let result = unsafe {
+        bootinfo.as_mut().map_kernel(
+            megapages(0x20_0000, 2),
+            megapages(0x80_0000, 1),
+            megapages(0x100_0000, 1),
+            &policy,
+            MapGranularity::Megapage,
+        )
+    };
+    let regions = result.unwrap();
+    assert!(regions
+        .iter()
+        .all(|r| r.granularity == MapGranularity::Megapage));
+    assert_eq!(regions[1].virt.start(), KERNEL_BASE + 2 * MEGAPAGE_SIZE);
+    assert_eq!(regions[2].phys.start(), 0x100_0000);
+
+    let phys = |virt| unsafe { bootinfo.translate(virt) }.map(|x| x.as_u64());
+    assert_eq!(phys(KERNEL_BASE), Some(0x20_0000));
+    assert_eq!(phys(KERNEL_BASE + MEGAPAGE_SIZE + 5), Some(0x40_0005));
+    assert_eq!(phys(KERNEL_BASE + 2 * MEGAPAGE_SIZE), Some(0x80_0000));
+    assert_eq!(phys(KERNEL_BASE + 3 * MEGAPAGE_SIZE), Some(0x100_0000));
+    assert_eq!(phys(KERNEL_BASE + 4 * MEGAPAGE_SIZE), None);
+
+    let text = bootinfo.pd[0].flags();
+    assert!(!text.writable() && !text.nx());
+    let rodata = bootinfo.pd[2].flags();
+    assert!(!rodata.writable() && rodata.nx());
+    let data = bootinfo.pd[3].flags();
+    assert!(data.writable() && data.nx());
+}
+
+fn map_small(
+    bootinfo: Pin<&mut Bootinfo>,
+    policy: &KernelPermPolicy,
+) -> Result<(), MapKernelError> {
+    unsafe {
+        bootinfo
+            .map_kernel(
+                megapages(0x20_0000, 1),
+                megapages(0x40_0000, 1),
+                megapages(0x60_0000, 1),
+                policy,
+                MapGranularity::Megapage,
+            )
+            .map(|_| ())
+    }
+}
+
+#[test]
+fn rwx_needs_opt_in() {
+    let mut policy = KernelPermPolicy::new();
+    policy.text = SegmentPerms::RWX;
+    assert_eq!(policy.validate(), Err(MapKernelError::RwxNotAllowed));
+
+    let mut bootinfo = pinned_bootinfo();
+    assert_eq!(
+        map_small(bootinfo.as_mut(), &policy),
+        Err(MapKernelError::RwxNotAllowed)
+    );
+    assert!(!bootinfo.paging_root[511].is_present());
+
+    policy.allow_rwx = true;
+    assert_eq!(map_small(bootinfo.as_mut(), &policy), Ok(()));
+    let text = bootinfo.pd[0].flags();
+    assert!(text.writable() && !text.nx());
+}
+
+#[test]
+fn bad_segments() {
+    let policy = KernelPermPolicy::new();
+    let mut bootinfo = pinned_bootinfo();
+
+    let result = unsafe {
+        bootinfo.as_mut().map_kernel(
+            megapages(0x20_1000, 1),
+            megapages(0x40_0000, 1),
+            megapages(0x60_0000, 1),
+            &policy,
+            MapGranularity::Megapage,
+        )
+    };
+    assert_eq!(result, Err(MapKernelError::Misaligned));
+
+    let result = unsafe {
+        bootinfo.as_mut().map_kernel(
+            megapages(0x20_0000, 500),
+            megapages(0x40_0000, 10),
+            megapages(0x60_0000, 10),
+            &policy,
+            MapGranularity::Megapage,
+        )
+    };
+    assert_eq!(result, Err(MapKernelError::TooLarge));
+}
+
+#[test]
+fn needs_this() {
+    let policy = KernelPermPolicy::new();
+    let mut bootinfo = Box::pin(Bootinfo::new());
+    assert!(bootinfo.as_ref().table_phys(OwnedTable::Pml4).is_none());
+
+    let result = unsafe {
+        bootinfo.as_mut().map_kernel(
+            megapages(0x20_0000, 1),
+            megapages(0x40_0000, 1),
+            megapages(0x60_0000, 1),
+            &policy,
+            MapGranularity::Megapage,
+        )
+    };
+    assert_eq!(result, Err(MapKernelError::Unpinned));
+    assert!(!bootinfo.paging_root[511].is_present());
+}
+
+#[test]
+fn table_phys_follows_this() {
+    let bootinfo = pinned_bootinfo();
+    let base = &*bootinfo as *const Bootinfo as u64;
+    assert_eq!(bootinfo.this.as_u64(), base);
+
+    let phys = |table| bootinfo.as_ref().table_phys(table).unwrap().as_u64();
+    assert_eq!(phys(OwnedTable::Pml4), base);
+    assert_eq!(phys(OwnedTable::Pdp), &bootinfo.pdp as *const _ as u64);
+    assert_eq!(phys(OwnedTable::Pd), &bootinfo.pd as *const _ as u64);
+    assert_eq!(
+        phys(OwnedTable::Pt),
+        &bootinfo.page_table as *const _ as u64
+    );
+}
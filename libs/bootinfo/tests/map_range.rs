@@ -0,0 +1,144 @@
+mod common;
+
+use bootinfo::{Bootinfo, MapGranularity, MapKernelError, SegmentPerms};
+use common::*;
+use core::pin::Pin;
+use cpu::paging::{Entry, GIGAPAGE_SIZE, MEGAPAGE_SIZE, PAGE_SIZE};
+use cpu::PhysRange;
+
+const VIRT: u64 = 0x80_0000_0000;
+
+fn map(
+    bootinfo: &mut Pin<Box<Bootinfo>>,
+    virt: u64,
+    phys: u64,
+    len: u64,
+    preferred: MapGranularity,
+) -> Result<MapGranularity, MapKernelError> {
+    let phys = PhysRange::new(phys, len).unwrap();
+    let region = unsafe {
+        bootinfo
+            .as_mut()
+            .map_range(virt, phys, preferred, SegmentPerms::RW)
+    };
+    return region.map(|r| r.granularity);
+}
+
+#[test]
+fn fit() {
+    use MapGranularity::*;
+
+    assert_eq!(Gigapage.fit(0, 0, GIGAPAGE_SIZE), Some(Gigapage));
+    assert_eq!(
+        Gigapage.fit(0, MEGAPAGE_SIZE, GIGAPAGE_SIZE),
+        Some(Megapage)
+    );
+    assert_eq!(Gigapage.fit(0, 0, GIGAPAGE_SIZE + PAGE_SIZE), Some(Page));
+    assert_eq!(Megapage.fit(0, 0, GIGAPAGE_SIZE), Some(Megapage));
+    assert_eq!(Page.fit(0, 0, MEGAPAGE_SIZE), Some(Page));
+    assert_eq!(Megapage.fit(0x10, 0, MEGAPAGE_SIZE), None);
+}
+
+#[test]
+fn each_granularity() {
+    let mut bootinfo = pinned_bootinfo();
+    let phys = |bootinfo: &Bootinfo, virt| unsafe { bootinfo.translate(virt) }.map(|x| x.as_u64());
+
+    let got = map(
+        &mut bootinfo,
+        VIRT,
+        0x1_0000_0000,
+        GIGAPAGE_SIZE,
+        MapGranularity::Gigapage,
+    );
+    assert_eq!(got, Ok(MapGranularity::Gigapage));
+    assert_eq!(phys(&bootinfo, VIRT + 0x1234_5678), Some(0x1_1234_5678));
+
+    let virt = VIRT + GIGAPAGE_SIZE;
+    let got = map(
+        &mut bootinfo,
+        virt,
+        0x4000_0000,
+        2 * MEGAPAGE_SIZE,
+        MapGranularity::Megapage,
+    );
+    assert_eq!(got, Ok(MapGranularity::Megapage));
+    assert_eq!(phys(&bootinfo, virt + MEGAPAGE_SIZE + 1), Some(0x4020_0001));
+
+    /* Falls back to 4K, as the physical address is only 4K aligned */
+    let virt = VIRT + GIGAPAGE_SIZE + 4 * MEGAPAGE_SIZE;
+    let got = map(
+        &mut bootinfo,
+        virt,
+        0x5000_3000,
+        3 * PAGE_SIZE,
+        MapGranularity::Megapage,
+    );
+    assert_eq!(got, Ok(MapGranularity::Page));
+    assert_eq!(phys(&bootinfo, virt + 2 * PAGE_SIZE + 8), Some(0x5000_5008));
+    assert_eq!(phys(&bootinfo, virt + 3 * PAGE_SIZE), None);
+
+    let flags = bootinfo.page_table[0].flags();
+    assert!(flags.present() && flags.writable() && flags.nx());
+}
+
+#[test]
+fn conflicts_map_nothing() {
+    let mut bootinfo = pinned_bootinfo();
+
+    let got = map(
+        &mut bootinfo,
+        VIRT + PAGE_SIZE,
+        0x20_0000,
+        PAGE_SIZE,
+        MapGranularity::Page,
+    );
+    assert_eq!(got, Ok(MapGranularity::Page));
+
+    /* Overlaps the page mapped above */
+    let got = map(
+        &mut bootinfo,
+        VIRT,
+        0x30_0000,
+        4 * PAGE_SIZE,
+        MapGranularity::Page,
+    );
+    assert_eq!(got, Err(MapKernelError::Conflict));
+    assert!(unsafe { bootinfo.translate(VIRT) }.is_none());
+
+    /* The PD entry already points to the page table */
+    let got = map(
+        &mut bootinfo,
+        VIRT,
+        0x40_0000,
+        MEGAPAGE_SIZE,
+        MapGranularity::Megapage,
+    );
+    assert_eq!(got, Err(MapKernelError::Conflict));
+
+    /* The PDP entry already points to the PD */
+    let got = map(
+        &mut bootinfo,
+        VIRT,
+        0,
+        GIGAPAGE_SIZE,
+        MapGranularity::Gigapage,
+    );
+    assert_eq!(got, Err(MapKernelError::Conflict));
+
+    /* Only one page table, so 4K pages can't span two 2M regions */
+    let virt = VIRT + MEGAPAGE_SIZE - PAGE_SIZE;
+    let got = map(
+        &mut bootinfo,
+        virt,
+        0x60_0000,
+        2 * PAGE_SIZE,
+        MapGranularity::Page,
+    );
+    assert_eq!(got, Err(MapKernelError::TooLarge));
+
+    assert_eq!(
+        unsafe { bootinfo.translate(VIRT + PAGE_SIZE) }.map(|x| x.as_u64()),
+        Some(0x20_0000)
+    );
+}
@@ -0,0 +1,112 @@
+use bootinfo::*;
+use cpu::microcode::{CpuSignature, MicrocodeError, HEADER_SIZE};
+use cpu::{PhysAddr, PhysSlice};
+
+const SIGNATURE: u32 = 0x000806EC;
+const CPU: CpuSignature = CpuSignature {
+    signature: SIGNATURE,
+    platform_id: 7,
+};
+
+fn put(bytes: &mut [u8], at: usize, x: u32) {
+    bytes[at..at + 4].copy_from_slice(&x.to_le_bytes());
+}
+
+/// 1KiB update for platform 7 of `signature`
+fn update(signature: u32, revision: u32) -> Vec<u8> {
+    let mut bytes = vec![0u8; 1024];
+    put(&mut bytes, 0, 1);
+    put(&mut bytes, 4, revision);
+    put(&mut bytes, 12, signature);
+    put(&mut bytes, 20, 1);
+    put(&mut bytes, 24, 1 << 7);
+    put(&mut bytes, 28, (1024 - HEADER_SIZE) as u32);
+    put(&mut bytes, 32, 1024);
+    bytes[HEADER_SIZE] = 0x5A;
+
+    let sum = bytes.chunks_exact(4).fold(0u32, |sum, x| {
+        sum.wrapping_add(u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+    });
+    put(&mut bytes, 16, sum.wrapping_neg());
+    return bytes;
+}
+
+#[test]
+fn selects_newest_matching() {
+    let mut corrupt = update(SIGNATURE, 0xFF);
+    corrupt[HEADER_SIZE + 1] = 1;
+
+    let mut file = update(SIGNATURE, 0xD0);
+    file.extend_from_slice(&update(SIGNATURE + 1, 0xF0));
+    file.extend_from_slice(&corrupt);
+    file.extend_from_slice(&update(SIGNATURE, 0xEA));
+    file.extend_from_slice(&update(SIGNATURE, 0xB4));
+
+    let mut skipped = Vec::new();
+    let picked = select_microcode(&file, CPU, 0xC6, |at, e| skipped.push((at, e))).unwrap();
+    assert_eq!(picked.header.revision, 0xEA);
+    assert_eq!(
+        skipped,
+        [
+            (1024, MicrocodeError::SignatureMismatch),
+            (2048, MicrocodeError::BadChecksum),
+            (
+                0,
+                MicrocodeError::NotNewer {
+                    current: 0xEA,
+                    update: 0xD0
+                }
+            ),
+            (
+                4096,
+                MicrocodeError::NotNewer {
+                    current: 0xC6,
+                    update: 0xB4
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn nothing_newer() {
+    let file = update(SIGNATURE, 0xD0);
+    let mut skipped = Vec::new();
+    assert!(select_microcode(&file, CPU, 0xD0, |at, e| skipped.push((at, e))).is_none());
+    assert_eq!(
+        skipped,
+        [(
+            0,
+            MicrocodeError::NotNewer {
+                current: 0xD0,
+                update: 0xD0
+            }
+        )]
+    );
+}
+
+#[test]
+fn status() {
+    let file = update(SIGNATURE, 0xEA);
+    let picked = select_microcode(&file, CPU, 0xC6, |_, _| panic!()).unwrap();
+
+    let status = MicrocodeStatus::applied(0xC6, &picked);
+    assert!(status.applied);
+    assert_eq!((status.old_revision, status.new_revision), (0xC6, 0xEA));
+    assert_eq!(status.sha256, sha256::Sha256::digest(&file));
+
+    let status = MicrocodeStatus::unchanged(0xC6);
+    assert!(!status.applied);
+    assert_eq!(status.new_revision, 0xC6);
+}
+
+#[test]
+fn module_kind() {
+    let data = PhysSlice::new(PhysAddr::new(0x10_0000).unwrap(), 4096);
+    assert_eq!(
+        Module::new("microcode.bin", data).kind,
+        ModuleKind::Microcode
+    );
+    assert_eq!(Module::new("kernel.elf", data).kind, ModuleKind::Kernel);
+    assert_eq!(Module::new("notes.txt", data).kind, ModuleKind::Other);
+}
@@ -0,0 +1,130 @@
+use bootinfo::*;
+use cpu::CpuInfo;
+
+fn everything() -> CpuInfo {
+    CpuInfo::new()
+        .set_smep()
+        .set_smap()
+        .set_umip()
+        .set_nx()
+        .set_misc_enable()
+}
+
+#[test]
+fn parse() {
+    let m = Mitigations::parse("smep=on smap=on umip=auto nx=require").unwrap();
+    assert_eq!(m.get(Mitigation::Smep), Setting::On);
+    assert_eq!(m.get(Mitigation::Smap), Setting::On);
+    assert_eq!(m.get(Mitigation::Umip), Setting::Auto);
+    assert_eq!(m.get(Mitigation::Nx), Setting::Require);
+    assert_eq!(m.get(Mitigation::Tsd), Setting::Keep);
+    assert_eq!(m.get(Mitigation::FastStrings), Setting::Keep);
+
+    /* Later wins, like repeated config keys */
+    let m = Mitigations::parse("tsd=on  tsd=off\tfast_strings=off").unwrap();
+    assert_eq!(m.get(Mitigation::Tsd), Setting::Off);
+    assert_eq!(m.get(Mitigation::FastStrings), Setting::Off);
+
+    assert_eq!(Mitigations::parse(""), Ok(Mitigations::new()));
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(Mitigations::parse("smep"), Err(MitigationError::Malformed));
+    assert_eq!(
+        Mitigations::parse("smep=on spectre=on"),
+        Err(MitigationError::UnknownItem)
+    );
+    assert_eq!(
+        Mitigations::parse("nx=maybe"),
+        Err(MitigationError::UnknownSetting(Mitigation::Nx))
+    );
+}
+
+#[test]
+fn from_config() {
+    let config = Config::new(b"serial=0\nmitigations = smep=on nx=require # hardened\n");
+    let m = Mitigations::from_config(&config).unwrap();
+    assert_eq!(m.get(Mitigation::Smep), Setting::On);
+    assert_eq!(m.get(Mitigation::Nx), Setting::Require);
+
+    let m = Mitigations::from_config(&Config::empty()).unwrap();
+    assert_eq!(m, Mitigations::new());
+}
+
+#[test]
+fn plan_outcomes() {
+    let m = Mitigations::parse("smep=on smap=off umip=auto nx=require").unwrap();
+    let plan = m.plan(&everything()).unwrap();
+
+    assert_eq!(plan.action(Mitigation::Smep), Action::Enable);
+    assert_eq!(plan.action(Mitigation::Smap), Action::Disable);
+    assert_eq!(plan.action(Mitigation::Umip), Action::Enable);
+    assert_eq!(plan.action(Mitigation::Nx), Action::Enable);
+    assert_eq!(plan.action(Mitigation::Tsd), Action::Nothing);
+
+    assert_eq!(plan.record.get(Mitigation::Smep), Outcome::Applied);
+    assert_eq!(plan.record.get(Mitigation::Smap), Outcome::Applied);
+    assert_eq!(plan.record.get(Mitigation::Tsd), Outcome::Skipped);
+    assert_eq!(plan.record.get(Mitigation::FastStrings), Outcome::Skipped);
+}
+
+#[test]
+fn unsupported() {
+    let m = Mitigations::parse("smep=on smap=auto umip=off fast_strings=off tsd=on").unwrap();
+    let plan = m.plan(&CpuInfo::new()).unwrap();
+
+    for mitigation in [
+        Mitigation::Smep,
+        Mitigation::Smap,
+        Mitigation::Umip,
+        Mitigation::FastStrings,
+    ] {
+        assert_eq!(plan.action(mitigation), Action::Nothing);
+        assert_eq!(plan.record.get(mitigation), Outcome::NotSupported);
+    }
+    /* The TSC is always there */
+    assert_eq!(plan.action(Mitigation::Tsd), Action::Enable);
+    assert_eq!(plan.record.get(Mitigation::Tsd), Outcome::Applied);
+}
+
+#[test]
+fn require_aborts() {
+    let m = Mitigations::parse("smep=on nx=require").unwrap();
+    let no_nx = everything().clear_nx();
+    assert_eq!(
+        m.plan(&no_nx),
+        Err(MitigationError::Required(Mitigation::Nx))
+    );
+    assert!(m.plan(&everything()).is_ok());
+}
+
+#[test]
+fn use_nx() {
+    let no_nx = everything().clear_nx();
+    let keep = Mitigations::new();
+    assert_eq!(keep.use_nx(&everything(), true), Ok(true));
+    assert_eq!(keep.use_nx(&everything(), false), Ok(false));
+    /* EFER.NXE can't be on without NX, but don't trust firmware on that */
+    assert_eq!(keep.use_nx(&no_nx, true), Ok(false));
+
+    for &(line, nx) in &[("nx=auto", true), ("nx=on", true), ("nx=off", false)] {
+        let m = Mitigations::parse(line).unwrap();
+        assert_eq!(m.use_nx(&everything(), false), Ok(nx), "{}", line);
+        assert_eq!(m.use_nx(&no_nx, false), Ok(false), "{}", line);
+    }
+
+    let require = Mitigations::parse("nx=require").unwrap();
+    assert_eq!(require.use_nx(&everything(), false), Ok(true));
+    assert_eq!(
+        require.use_nx(&no_nx, false),
+        Err(MitigationError::Required(Mitigation::Nx))
+    );
+}
+
+#[test]
+fn names_round_trip() {
+    for &mitigation in &Mitigation::ALL {
+        assert_eq!(Mitigation::from_name(mitigation.name()), Some(mitigation));
+    }
+}
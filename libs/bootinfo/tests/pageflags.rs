@@ -0,0 +1,91 @@
+mod common;
+
+use bootinfo::*;
+use common::*;
+use cpu::paging::{Bits, Entry, MEGAPAGE_SIZE, PAGE_SIZE};
+use cpu::PhysRange;
+
+const VIRT: u64 = 0x80_0000_0000;
+const NX: u64 = 1 << 63;
+
+#[test]
+fn constants() {
+    assert_eq!(KERNEL_TEXT.as_u64(), 1);
+    assert_eq!(KERNEL_RODATA.as_u64(), 1 | NX);
+    assert_eq!(KERNEL_DATA.as_u64(), 0b11 | NX);
+    assert_eq!(KERNEL_RODATA_NO_NX.as_u64(), 1);
+    assert_eq!(KERNEL_DATA_NO_NX.as_u64(), 0b11);
+
+    assert_eq!(KERNEL_DATA.pt().as_u64(), 0b11 | NX);
+    assert_eq!(KERNEL_DATA.pd().as_u64(), 0b1000_0011 | NX);
+}
+
+#[test]
+fn select() {
+    assert_eq!(PageFlags::select(true), PageFlags::WITH_NX);
+    assert_eq!(PageFlags::select(false), PageFlags::WITHOUT_NX);
+    assert_eq!(Bootinfo::new().page_flags, PageFlags::WITH_NX);
+
+    for &flags in &[PageFlags::WITH_NX, PageFlags::WITHOUT_NX] {
+        assert_eq!(flags.leaf(SegmentPerms::RX), KERNEL_TEXT);
+        assert_eq!(flags.leaf(SegmentPerms::RWX), KERNEL_RWX);
+    }
+    assert_eq!(PageFlags::WITH_NX.leaf(SegmentPerms::R), KERNEL_RODATA);
+    assert_eq!(
+        PageFlags::WITHOUT_NX.leaf(SegmentPerms::RW),
+        KERNEL_DATA_NO_NX
+    );
+    for perms in [
+        SegmentPerms::R,
+        SegmentPerms::RW,
+        SegmentPerms::RX,
+        SegmentPerms::RWX,
+    ] {
+        let nx = PageFlags::WITHOUT_NX.leaf(perms).as_u64() & NX;
+        assert_eq!(nx, 0, "{:?}", perms);
+    }
+}
+
+#[test]
+fn effective_policy() {
+    let policy = KernelPermPolicy::new();
+    assert_eq!(policy.effective(&PageFlags::WITH_NX), policy);
+
+    let degraded = policy.effective(&PageFlags::WITHOUT_NX);
+    assert_eq!(degraded.text, SegmentPerms::RX);
+    assert_eq!(degraded.rodata, SegmentPerms::RX);
+    assert_eq!(degraded.data, SegmentPerms::RWX);
+    /* Only what the policy asks for is refused */
+    assert_eq!(policy.validate(), Ok(()));
+}
+
+/* Bit 63 is reserved without EFER.NXE, no leaf may have it */
+#[test]
+fn mappings_without_nx() {
+    for &(flags, nx) in &[(PageFlags::WITH_NX, NX), (PageFlags::WITHOUT_NX, 0)] {
+        let mut bootinfo = pinned_bootinfo();
+        unsafe { bootinfo.as_mut().get_unchecked_mut() }.page_flags = flags;
+        let page = PhysRange::new(0x20_0000, PAGE_SIZE).unwrap();
+        let megapage = PhysRange::new(0x40_0000, MEGAPAGE_SIZE).unwrap();
+        unsafe {
+            let b = bootinfo.as_mut();
+            b.map_range(VIRT, page, MapGranularity::Page, SegmentPerms::RW)
+                .unwrap();
+        }
+        unsafe {
+            let b = bootinfo.as_mut();
+            b.map_range(
+                VIRT + MEGAPAGE_SIZE,
+                megapage,
+                MapGranularity::Megapage,
+                SegmentPerms::R,
+            )
+            .unwrap();
+        }
+
+        assert_eq!(bootinfo.page_table[0].as_u64() & NX, nx);
+        assert!(bootinfo.page_table[0].flags().writable());
+        assert_eq!(bootinfo.pd[1].as_u64() & NX, nx);
+        assert!(bootinfo.pd[1].flags().leaf());
+    }
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use bootinfo::{percpu_stride, Bootinfo, MapKernelError, PerCpuError, PERCPU_BASE};
+use common::*;
+use core::pin::Pin;
+use cpu::paging::PAGE_SIZE;
+use cpu::PhysRange;
+
+#[repr(C, align(4096))]
+struct Pages([u8; 4 * 4096]);
+
+#[test]
+fn stride() {
+    assert_eq!(percpu_stride(1), Some(PAGE_SIZE));
+    assert_eq!(percpu_stride(PAGE_SIZE), Some(PAGE_SIZE));
+    assert_eq!(percpu_stride(PAGE_SIZE + 1), Some(2 * PAGE_SIZE));
+    assert_eq!(percpu_stride(0), None);
+    assert_eq!(percpu_stride(u64::MAX), None);
+}
+
+#[test]
+fn mapped_per_cpu() {
+    let mut bootinfo = pinned_bootinfo();
+    let pages = Box::new(Pages([0; 4 * 4096]));
+    let base = pages.0.as_ptr() as u64;
+    let phys = PhysRange::new(base, 4 * PAGE_SIZE).unwrap();
+
+    let slice = unsafe { bootinfo.as_mut().map_percpu(phys, 2, 2 * PAGE_SIZE) }.unwrap();
+    assert_eq!(slice.addr().as_u64(), base);
+    assert_eq!(slice.len() as u64, 4 * PAGE_SIZE);
+
+    let area = bootinfo.percpu;
+    assert_eq!(area.cpus, 2);
+    assert_eq!(area.stride, 2 * PAGE_SIZE);
+    assert_eq!(area.cpu_virt(0), Some(PERCPU_BASE));
+    assert_eq!(area.cpu_virt(1), Some(PERCPU_BASE + 2 * PAGE_SIZE));
+    assert_eq!(area.cpu_virt(2), None);
+
+    let cpu1 = unsafe { bootinfo.translate(area.cpu_virt(1).unwrap()) }.unwrap();
+    assert_eq!(cpu1.as_u64(), base + 2 * PAGE_SIZE);
+    assert!(unsafe { bootinfo.translate(PERCPU_BASE + 4 * PAGE_SIZE) }.is_none());
+}
+
+#[test]
+fn bad_layout() {
+    let mut bootinfo = pinned_bootinfo();
+    let phys = PhysRange::new(0x10_0000, 4 * PAGE_SIZE).unwrap();
+
+    let map = |bootinfo: &mut Pin<Box<Bootinfo>>, cpus, stride| unsafe {
+        bootinfo.as_mut().map_percpu(phys, cpus, stride).map(|_| ())
+    };
+    assert_eq!(map(&mut bootinfo, 0, PAGE_SIZE), Err(PerCpuError::NoCpus));
+    assert_eq!(map(&mut bootinfo, 3, PAGE_SIZE), Err(PerCpuError::TooLarge));
+    assert_eq!(bootinfo.percpu.cpus, 0);
+
+    /* Second reservation collides with the first */
+    map(&mut bootinfo, 4, PAGE_SIZE).unwrap();
+    assert_eq!(
+        map(&mut bootinfo, 4, PAGE_SIZE),
+        Err(PerCpuError::Map(MapKernelError::Conflict))
+    );
+}
@@ -0,0 +1,189 @@
+use arrayvec::ArrayVec;
+use bootinfo::{clamp_memory_map, overlapping_descriptors, Bootinfo, MemoryClamp, MemorySummary};
+use cpu::PhysRange;
+use uefi::memory::{Attributes, Descriptor, Type};
+
+const MIB: u64 = 1 << 20;
+const GIB: u64 = 1 << 30;
+
+fn descriptor(typ: Type, start: u64, len: u64) -> Descriptor {
+    let range = PhysRange::new(start, len).unwrap();
+    Descriptor::new(typ, range, Attributes::new().set_write_back()).unwrap()
+}
+
+fn summary(map: &[Descriptor]) -> Vec<(Option<Type>, u64, u64)> {
+    map.iter()
+        .map(|d| (d.memory_type(), d.phys_start, d.pages * 4096))
+        .collect()
+}
+
+#[test]
+fn nothing_above_limit() {
+    let mut map: ArrayVec<Descriptor, 8> = ArrayVec::new();
+    map.push(descriptor(Type::Conventional, MIB, 15 * MIB));
+    map.push(descriptor(Type::Mmio, 60 * GIB, MIB));
+
+    let clamp = clamp_memory_map(&mut map, 36);
+    assert_eq!(
+        clamp,
+        MemoryClamp {
+            lost_bytes: 0,
+            regions: 0,
+            usable_end: 16 * MIB,
+        }
+    );
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn split_and_retype() {
+    /* A fake 32 bit MAXPHYADDR, like a 128 GiB machine with 36 bits */
+    let mut map: ArrayVec<Descriptor, 8> = ArrayVec::new();
+    map.push(descriptor(Type::Conventional, 0, 3 * GIB));
+    map.push(descriptor(Type::Reserved, 3 * GIB, GIB));
+    map.push(descriptor(Type::LoaderData, 4 * GIB - 2 * MIB, 4 * MIB));
+    map.push(descriptor(Type::Conventional, 5 * GIB, GIB));
+    map.push(descriptor(Type::Mmio, 7 * GIB, MIB));
+
+    let clamp = clamp_memory_map(&mut map, 32);
+    assert_eq!(clamp.lost_bytes, 2 * MIB + GIB);
+    assert_eq!(clamp.regions, 2);
+    assert_eq!(clamp.usable_end, 4 * GIB);
+
+    assert_eq!(
+        summary(&map),
+        [
+            (Some(Type::Conventional), 0, 3 * GIB),
+            (Some(Type::Reserved), 3 * GIB, GIB),
+            (Some(Type::LoaderData), 4 * GIB - 2 * MIB, 2 * MIB),
+            (Some(Type::Unusable), 5 * GIB, GIB),
+            /* Not usable memory is left alone, even above the limit */
+            (Some(Type::Mmio), 7 * GIB, MIB),
+            (Some(Type::Unusable), 4 * GIB, 2 * MIB),
+        ]
+    );
+}
+
+#[test]
+fn full_map_drops_excess() {
+    let mut map: ArrayVec<Descriptor, 1> = ArrayVec::new();
+    map.push(descriptor(Type::Conventional, 0, 2 * GIB));
+
+    let clamp = clamp_memory_map(&mut map, 30);
+    assert_eq!(clamp.lost_bytes, GIB);
+    assert_eq!(summary(&map), [(Some(Type::Conventional), 0, GIB)]);
+}
+
+#[test]
+fn bootinfo_flag() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo
+        .uefi_meminfo
+        .push(descriptor(Type::Conventional, 0, GIB));
+
+    bootinfo.clamp_physical_memory(36);
+    assert!(!bootinfo.physical_memory_clamped);
+    assert!(!bootinfo.capabilities.physical_memory_clamped());
+
+    bootinfo.clamp_physical_memory(29);
+    assert!(bootinfo.physical_memory_clamped);
+    assert!(bootinfo.capabilities.physical_memory_clamped());
+    assert_eq!(bootinfo.uefi_meminfo[0].pages * 4096, GIB / 2);
+}
+
+#[test]
+fn summary_of_typical_map() {
+    /* Out of order, with adjacent free descriptors of different types */
+    let map = [
+        descriptor(Type::Conventional, 5 * GIB, GIB),
+        descriptor(Type::Conventional, 0x1000, 0x9_f000 - 0x1000),
+        descriptor(Type::Reserved, 0x9_f000, 0x6_1000),
+        descriptor(Type::LoaderData, MIB, MIB),
+        descriptor(Type::BootServicesData, 2 * MIB, 14 * MIB),
+        descriptor(Type::Conventional, 16 * MIB, 2032 * MIB),
+        descriptor(Type::Mmio, 3 * GIB, GIB),
+        descriptor(Type::Conventional, 4 * GIB - 4 * MIB, 4 * MIB),
+        descriptor(Type::BootServicesCode, 4 * GIB, GIB),
+    ];
+
+    let memory = MemorySummary::from_map(&map);
+    assert_eq!(memory.below_1m, 0x9_e000);
+    assert_eq!(memory.below_4g, 0x9_e000 + 14 * MIB + 2032 * MIB + 4 * MIB);
+    assert_eq!(memory.above_4g, 2 * GIB);
+    assert_eq!(memory.total(), memory.below_4g + memory.above_4g);
+    /* 4G-4M..6G is one region across the 4 GiB line */
+    assert_eq!(memory.regions, 3);
+    assert_eq!(
+        memory.largest,
+        PhysRange::from_start_end(4 * GIB - 4 * MIB, 6 * GIB).unwrap()
+    );
+}
+
+#[test]
+fn summary_region_across_4g() {
+    let map = [descriptor(Type::Conventional, 3 * GIB, 2 * GIB)];
+
+    let memory = MemorySummary::from_map(&map);
+    assert_eq!(memory.below_1m, 0);
+    assert_eq!(memory.below_4g, GIB);
+    assert_eq!(memory.above_4g, GIB);
+    assert_eq!(memory.regions, 1);
+    assert_eq!(memory.largest, PhysRange::new(3 * GIB, 2 * GIB).unwrap());
+}
+
+#[test]
+fn summary_nothing_free_below_4g() {
+    let map = [
+        descriptor(Type::LoaderCode, 0, MIB),
+        descriptor(Type::Persistent, MIB, GIB),
+        descriptor(Type::Conventional, 8 * GIB, GIB),
+        descriptor(Type::Conventional, 16 * GIB, 2 * GIB),
+    ];
+
+    let memory = MemorySummary::from_map(&map);
+    assert_eq!(memory.below_1m, 0);
+    assert_eq!(memory.below_4g, 0);
+    assert_eq!(memory.above_4g, 3 * GIB);
+    assert_eq!(memory.regions, 2);
+    assert_eq!(memory.largest, PhysRange::new(16 * GIB, 2 * GIB).unwrap());
+}
+
+#[test]
+fn summary_of_empty_map() {
+    assert_eq!(MemorySummary::from_map(&[]), MemorySummary::new());
+    assert!(MemorySummary::new().largest.is_empty());
+}
+
+#[test]
+fn summary_follows_clamp() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo
+        .uefi_meminfo
+        .push(descriptor(Type::Conventional, 0, 8 * GIB));
+    bootinfo.summarize_memory();
+    assert_eq!(bootinfo.memory.above_4g, 4 * GIB);
+
+    /* What was cut can't be taken by the kernel anymore */
+    bootinfo.clamp_physical_memory(32);
+    assert_eq!(bootinfo.memory.above_4g, 0);
+    assert_eq!(bootinfo.memory.below_4g, 4 * GIB);
+    assert_eq!(bootinfo.memory.largest, PhysRange::new(0, 4 * GIB).unwrap());
+}
+
+#[test]
+fn overlaps() {
+    let sane = [
+        descriptor(Type::Conventional, 0, MIB),
+        descriptor(Type::Mmio, 4 * GIB, MIB),
+        descriptor(Type::LoaderData, MIB, MIB),
+    ];
+    assert_eq!(overlapping_descriptors(&sane), 0);
+
+    /* Overlaps the first one and, once, the second */
+    let broken = [
+        descriptor(Type::Conventional, 0, 2 * MIB),
+        descriptor(Type::LoaderData, MIB, 2 * MIB),
+        descriptor(Type::AcpiReclaim, MIB + 4096, 4096),
+    ];
+    assert_eq!(overlapping_descriptors(&broken), 2);
+}
@@ -0,0 +1,234 @@
+use bootinfo::*;
+use cpu::PhysRange;
+use elf::Notes;
+use uefi::memory::{Attributes, Descriptor, Type};
+
+fn range(start: u64, len: u64) -> PhysRange {
+    PhysRange::new(start, len).unwrap()
+}
+
+fn requirements() -> KernelRequirements {
+    KernelRequirements {
+        min_stack: 0x2_0000,
+        stack_align: 0x1000,
+        min_free_contiguous: 0x100_0000,
+        max_bootinfo_distance: 0x8000_0000,
+    }
+}
+
+/* Meets `requirements` exactly */
+fn layout() -> EntryLayout {
+    EntryLayout {
+        stack: range(0x10_0000, 0x2_0000),
+        kernel: range(0x20_0000, 0x60_0000),
+        bootinfo: range(0x8020_0000 - 0xc000, 0xc000),
+        largest_free: 0x100_0000,
+    }
+}
+
+fn note_bytes<T>(note: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(note as *const T as *const u8, core::mem::size_of::<T>()) }
+}
+
+#[test]
+fn desc_round_trip() {
+    let desc = requirements().to_desc();
+    assert_eq!(desc.len(), REQUIREMENTS_DESC_SIZE);
+    assert_eq!(&desc[..8], &0x2_0000u64.to_le_bytes());
+    assert_eq!(&desc[24..], &0x8000_0000u64.to_le_bytes());
+    assert_eq!(KernelRequirements::from_desc(&desc), Ok(requirements()));
+
+    /* Fields added later don't bother older loaders */
+    let mut longer = desc.to_vec();
+    longer.extend_from_slice(&[0xFF; 8]);
+    assert_eq!(KernelRequirements::from_desc(&longer), Ok(requirements()));
+
+    assert_eq!(
+        KernelRequirements::from_desc(&desc[..31]),
+        Err(AbiError::Malformed)
+    );
+    let mut odd = requirements();
+    odd.stack_align = 24;
+    assert_eq!(
+        KernelRequirements::from_desc(&odd.to_desc()),
+        Err(AbiError::Malformed)
+    );
+
+    let zeroes = [0u8; REQUIREMENTS_DESC_SIZE];
+    assert_eq!(
+        KernelRequirements::from_desc(&zeroes),
+        Ok(KernelRequirements::DEFAULT)
+    );
+}
+
+#[test]
+fn abi_desc_round_trip() {
+    let required = KernelFeatures::new().set_percpu();
+    let note = AbiNote::new(required, KERNEL_BASE);
+    assert_eq!(note.bootinfo_version, BOOTINFO_VERSION);
+    assert_eq!(note.buf_size as usize, DEFAULT_BUF_SIZE);
+    assert_eq!(AbiNote::from_desc(&note.to_desc()), Ok(note));
+}
+
+sovos_notes! {
+    required: KernelFeatures::new().set_percpu(),
+    kernel_base: KERNEL_BASE,
+    requirements: KernelRequirements {
+        min_stack: 0x2_0000,
+        ..KernelRequirements::DEFAULT
+    },
+}
+
+#[test]
+fn macro_notes_parse() {
+    let mut bytes = note_bytes(&SOVOS_ABI_NOTE).to_vec();
+    bytes.extend_from_slice(note_bytes(&SOVOS_REQUIREMENTS_NOTE));
+
+    let notes: Vec<_> = Notes::new(&bytes, 4).collect();
+    assert_eq!(notes.len(), 2);
+    assert!(notes.iter().all(|x| x.name == ABI_NOTE_NAME));
+
+    assert_eq!(notes[0].note_type, ABI_NOTE_TYPE);
+    let abi = AbiNote::from_desc(notes[0].desc).unwrap();
+    assert_eq!(
+        abi,
+        AbiNote::new(KernelFeatures::new().set_percpu(), KERNEL_BASE)
+    );
+    let contract = negotiate(Some(&abi), KernelFeatures::supported(), DEFAULT_BUF_SIZE);
+    assert!(contract.is_ok());
+
+    assert_eq!(notes[1].note_type, REQUIREMENTS_NOTE_TYPE);
+    let requirements = KernelRequirements::from_desc(notes[1].desc).unwrap();
+    assert_eq!(requirements.min_stack, 0x2_0000);
+    assert_eq!(requirements.stack_align, 0);
+}
+
+#[test]
+fn defaults() {
+    let defaults = KernelRequirements::default();
+    assert_eq!(defaults, KernelRequirements::DEFAULT);
+    assert_eq!(defaults.stack_size(), KERNEL_STACK_SIZE);
+    assert_eq!(defaults.stack_align(), DEFAULT_STACK_ALIGN);
+    assert_eq!(defaults.check(&layout()), Ok(()));
+
+    /* No requirement on the distance at all */
+    let mut far = layout();
+    far.bootinfo = range(0xF_0000_0000, 0xc000);
+    assert_eq!(defaults.check(&far), Ok(()));
+}
+
+#[test]
+fn each_requirement() {
+    let requirements = requirements();
+    assert_eq!(requirements.check(&layout()), Ok(()));
+
+    let mut small = layout();
+    small.stack = range(0x10_0000, 0x1_F000);
+    assert_eq!(
+        requirements.check(&small),
+        Err(UnmetRequirement::StackSize {
+            need: 0x2_0000,
+            have: 0x1_F000
+        })
+    );
+
+    let mut misaligned = layout();
+    misaligned.stack = range(0x10_0008, 0x2_0000);
+    assert_eq!(
+        requirements.check(&misaligned),
+        Err(UnmetRequirement::StackAlign {
+            need: 0x1000,
+            have: 8
+        })
+    );
+
+    let mut full = layout();
+    full.largest_free = 0xFF_F000;
+    assert_eq!(
+        requirements.check(&full),
+        Err(UnmetRequirement::FreeMemory {
+            need: 0x100_0000,
+            have: 0xFF_F000
+        })
+    );
+
+    let mut far = layout();
+    far.bootinfo = range(0x8020_0000, 0xc000);
+    assert_eq!(
+        requirements.check(&far),
+        Err(UnmetRequirement::BootinfoDistance {
+            allowed: 0x8000_0000,
+            have: 0x8000_c000
+        })
+    );
+    /* Below the kernel counts too */
+    let mut below = layout();
+    below.kernel = range(0x8000_0000, 0x60_0000);
+    below.bootinfo = range(0x1000, 0xc000);
+    assert!(matches!(
+        requirements.check(&below),
+        Err(UnmetRequirement::BootinfoDistance { .. })
+    ));
+
+    /* The first unmet one is named */
+    let mut both = small;
+    both.largest_free = 0;
+    assert!(matches!(
+        requirements.check(&both),
+        Err(UnmetRequirement::StackSize { .. })
+    ));
+}
+
+#[test]
+fn stack_allocation() {
+    let memory = PhysRange::new(0x10_1000, 0x100_0000).unwrap();
+    let map = [Descriptor::new(Type::Conventional, memory, Attributes::new()).unwrap()];
+    let mut frames = MapFrameAllocator::new(&map, &[]);
+
+    let requirements = requirements();
+    assert_eq!(requirements.stack_size(), 0x2_0000);
+    let stack = requirements.alloc_stack(&mut frames).unwrap();
+    assert_eq!(stack.len(), 0x2_0000);
+    let entry = EntryLayout { stack, ..layout() };
+    assert_eq!(requirements.check(&entry), Ok(()));
+
+    /* Alignment beyond a page rounds the size up, so the top is aligned */
+    let wide = KernelRequirements {
+        min_stack: 0x1_8001,
+        stack_align: 0x1_0000,
+        ..KernelRequirements::DEFAULT
+    };
+    assert_eq!(wide.stack_size(), 0x2_0000);
+    let stack = wide.alloc_stack(&mut frames).unwrap();
+    assert_eq!(stack.end() % 0x1_0000, 0);
+    let entry = EntryLayout { stack, ..layout() };
+    assert_eq!(wide.check(&entry), Ok(()));
+
+    let small = KernelRequirements {
+        min_stack: 0x100,
+        ..KernelRequirements::DEFAULT
+    };
+    assert_eq!(small.stack_size(), KERNEL_STACK_SIZE);
+}
+
+#[test]
+fn bootinfo_requirements() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.kernel_stack = range(0x10_0000, KERNEL_STACK_SIZE);
+    assert_eq!(bootinfo.entry_layout().stack, bootinfo.kernel_stack);
+    assert_eq!(
+        bootinfo.check_requirements(&KernelRequirements::DEFAULT),
+        Ok(())
+    );
+    assert_eq!(bootinfo.last_error, None);
+
+    let e = bootinfo.check_requirements(&requirements()).unwrap_err();
+    assert_eq!(
+        e,
+        UnmetRequirement::StackSize {
+            need: 0x2_0000,
+            have: KERNEL_STACK_SIZE
+        }
+    );
+    assert_eq!(bootinfo.last_error, Some(BootError::Requirement(e)));
+}
@@ -0,0 +1,158 @@
+use bootinfo::{Bootinfo, Module, ReservedKind, ReservedTable};
+use cpu::{PhysAddr, PhysRange, PhysSlice};
+use uefi::memory::{Attributes, Descriptor, Type};
+use ReservedKind::{Acpi, Firmware, Handoff, Kernel, Loader, Trace};
+
+const MIB: u64 = 1 << 20;
+
+fn range(start: u64, end: u64) -> PhysRange {
+    PhysRange::from_start_end(start, end).unwrap()
+}
+
+fn descriptor(typ: Type, start: u64, end: u64) -> Descriptor {
+    Descriptor::new(typ, range(start, end), Attributes::new()).unwrap()
+}
+
+fn entries<const N: usize>(table: &ReservedTable<N>) -> Vec<(u64, u64, ReservedKind)> {
+    table
+        .iter()
+        .map(|(range, kind)| (range.start(), range.end(), kind))
+        .collect()
+}
+
+#[test]
+fn sorted() {
+    let mut table = ReservedTable::<8>::new();
+    table.insert(range(8 * MIB, 9 * MIB), Firmware);
+    table.insert(range(MIB, 2 * MIB), Loader);
+    table.insert(range(4 * MIB, 5 * MIB), Acpi);
+    table.insert(PhysRange::empty(), Kernel);
+    assert_eq!(
+        entries(&table),
+        [
+            (MIB, 2 * MIB, Loader),
+            (4 * MIB, 5 * MIB, Acpi),
+            (8 * MIB, 9 * MIB, Firmware),
+        ]
+    );
+    assert!(!table.widened());
+}
+
+#[test]
+fn overlaps_coalesce() {
+    /* The kernel inside the LoaderData it was allocated as */
+    let mut table = ReservedTable::<8>::new();
+    table.insert(range(MIB, 9 * MIB), Loader);
+    table.insert(range(2 * MIB, 3 * MIB), Kernel);
+    assert_eq!(entries(&table), [(MIB, 9 * MIB, Kernel)]);
+
+    /* Bridging two entries takes both, the most specific kind wins */
+    table.insert(range(12 * MIB, 13 * MIB), ReservedKind::Module);
+    table.insert(range(8 * MIB, 12 * MIB + 1), Firmware);
+    assert_eq!(entries(&table), [(MIB, 13 * MIB, Kernel)]);
+}
+
+#[test]
+fn adjacent() {
+    let mut table = ReservedTable::<8>::new();
+    table.insert(range(MIB, 2 * MIB), Loader);
+    table.insert(range(2 * MIB, 3 * MIB), Loader);
+    table.insert(range(3 * MIB, 4 * MIB), ReservedKind::Module);
+    table.insert(range(0, MIB), Loader);
+    assert_eq!(
+        entries(&table),
+        [
+            (0, 3 * MIB, Loader),
+            (3 * MIB, 4 * MIB, ReservedKind::Module)
+        ]
+    );
+}
+
+#[test]
+fn overflow_widens() {
+    let mut table = ReservedTable::<3>::new();
+    table.insert(range(0, MIB), Firmware);
+    table.insert(range(10 * MIB, 11 * MIB), Acpi);
+    table.insert(range(11 * MIB + 4096, 12 * MIB), Loader);
+    assert!(!table.widened());
+
+    /* The 4K gap is the smallest, between two old entries */
+    table.insert(range(40 * MIB, 41 * MIB), ReservedKind::Module);
+    assert!(table.widened());
+    assert_eq!(
+        entries(&table),
+        [
+            (0, MIB, Firmware),
+            (10 * MIB, 12 * MIB, Loader),
+            (40 * MIB, 41 * MIB, ReservedKind::Module),
+        ]
+    );
+
+    /* Now the new range is closest to a neighbour and takes it in */
+    table.insert(range(42 * MIB, 43 * MIB), Trace);
+    assert_eq!(
+        entries(&table),
+        [
+            (0, MIB, Firmware),
+            (10 * MIB, 12 * MIB, Loader),
+            (40 * MIB, 43 * MIB, ReservedKind::Module),
+        ]
+    );
+}
+
+#[test]
+fn overflow_keeps_everything() {
+    let mut table = ReservedTable::<4>::new();
+    let inserted: Vec<PhysRange> = (0..32u64)
+        .map(|i| range(i * i * MIB, i * i * MIB + 4096))
+        .collect();
+    for &range in &inserted {
+        table.insert(range, Loader);
+    }
+
+    assert!(table.widened());
+    assert_eq!(table.len(), 4);
+    for range in &inserted {
+        assert!(table.iter().any(|(x, _)| x.contains_range(range)));
+    }
+    let starts: Vec<u64> = table.iter().map(|(x, _)| x.start()).collect();
+    assert!(starts.windows(2).all(|x| x[0] < x[1]));
+}
+
+#[test]
+fn from_every_source() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.uefi_meminfo.extend([
+        descriptor(Type::Conventional, 0, 8 * MIB),
+        descriptor(Type::LoaderData, 8 * MIB, 16 * MIB),
+        descriptor(Type::BootServicesData, 16 * MIB, 32 * MIB),
+        descriptor(Type::AcpiNVS, 32 * MIB, 33 * MIB),
+        descriptor(Type::Mmio, 0xfec0_0000, 0xfec0_1000),
+    ]);
+    bootinfo.kernel_pslice = PhysSlice::new(PhysAddr::new(8 * MIB).unwrap(), MIB);
+    let module = PhysSlice::new(PhysAddr::new(12 * MIB).unwrap(), 4096);
+    bootinfo.modules.push(Module::new("init", module));
+    /* A trampoline page in what the map calls free */
+    bootinfo.reserve_region(range(0x8000, 0x9000), Handoff);
+
+    bootinfo.merge_reserved();
+    let reserved: Vec<_> = bootinfo
+        .reserved_ranges()
+        .map(|(range, kind)| (range.start(), range.end(), kind))
+        .collect();
+    assert_eq!(
+        reserved,
+        [
+            (0x8000, 0x9000, Handoff),
+            (8 * MIB, 16 * MIB, Kernel),
+            (32 * MIB, 33 * MIB, Acpi),
+            (0xfec0_0000, 0xfec0_1000, Firmware),
+        ]
+    );
+    assert!(!bootinfo.reserved.widened());
+
+    /* Merging again starts over */
+    bootinfo.uefi_meminfo.clear();
+    bootinfo.merge_reserved();
+    assert_eq!(bootinfo.reserved_ranges().count(), 3);
+}
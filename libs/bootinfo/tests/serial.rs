@@ -0,0 +1,239 @@
+use bootinfo::{loopback_echo, probe_uart, PortIo, UartProbe, PROBE_SPINS};
+use bootinfo::{Config, SerialError, SerialSinks, Uart, COM_PORTS, DEFAULT_BAUD};
+use bootinfo::{TxRing, TX_RING_SIZE};
+use std::fmt::Write;
+
+const BASE: u16 = 0x3F8;
+
+/// Registers of one 16550 at `BASE`, with knobs for broken ones
+#[derive(Default)]
+struct FakeUart {
+    scratch: u8,
+    lcr: u8,
+    mcr: u8,
+    rx: Option<u8>,
+    /// Nothing there, every read is 0xFF
+    floating: bool,
+    /// Scratch reads back as 0
+    no_scratch: bool,
+    /// Loopback mode doesn't feed TX into RX
+    no_loopback: bool,
+    /// THR never empties
+    stuck: bool,
+    /// Has FIFOs, a 16550 rather than a 16450
+    fifo: bool,
+    lsr_reads: u32,
+    /// Bytes that went out on the line
+    sent: Vec<u8>,
+}
+
+impl PortIo for FakeUart {
+    fn inb(&mut self, port: u16) -> u8 {
+        if self.floating {
+            return 0xFF;
+        }
+        match port - BASE {
+            0 => self.rx.take().unwrap_or(0),
+            2 if self.fifo => 0xC1,
+            2 => 0x01,
+            4 => self.mcr,
+            5 => {
+                self.lsr_reads += 1;
+                let thr_empty = if self.stuck { 0 } else { 0x60 };
+                thr_empty | self.rx.is_some() as u8
+            }
+            7 if self.no_scratch => 0,
+            7 => self.scratch,
+            _ => 0,
+        }
+    }
+
+    fn outb(&mut self, port: u16, x: u8) {
+        match port - BASE {
+            /* Divisor latch */
+            0 if self.lcr & 0x80 != 0 => {}
+            0 if self.mcr & 0x10 != 0 && !self.no_loopback => self.rx = Some(x),
+            0 if self.mcr & 0x10 == 0 => self.sent.push(x),
+            3 => self.lcr = x,
+            4 => self.mcr = x,
+            7 => self.scratch = x,
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn divisors() {
+    assert_eq!(Uart::new(0x3F8, 115_200).divisor(), Some(1));
+    assert_eq!(Uart::new(0x3F8, 9600).divisor(), Some(12));
+    assert_eq!(Uart::new(0x3F8, 38_400).divisor(), Some(3));
+
+    /* Too fast for the standard clock, fine with a 24MHz one */
+    let fast = Uart::new(0x2F8, 1_500_000);
+    assert_eq!(fast.divisor(), None);
+    assert_eq!(fast.with_clock(24_000_000).divisor(), Some(1));
+
+    assert_eq!(Uart::new(0x3F8, 0).divisor(), None);
+    assert_eq!(Uart::new(0x3F8, 1).divisor(), None);
+}
+
+#[test]
+fn defaults() {
+    let sinks = SerialSinks::from_config(&Config::empty()).unwrap();
+    assert_eq!(sinks, SerialSinks::new());
+    assert_eq!(sinks.console_index(), 0);
+    assert_eq!(sinks.console().base(), COM_PORTS[0]);
+    assert_eq!(sinks.console().baud(), DEFAULT_BAUD);
+    assert!(!sinks.ports[1].is_enabled());
+}
+
+#[test]
+fn two_ports() {
+    let config = Config::new(b"ttyS0=115200\nttyS1=1500000\nttyS1.clock=24000000\nconsole=ttyS1\n");
+    let sinks = SerialSinks::from_config(&config).unwrap();
+
+    assert_eq!(sinks.console_index(), 1);
+    assert!(sinks.ports.iter().all(|port| port.is_enabled()));
+    assert_eq!(sinks.ports[1].baud(), 1_500_000);
+    assert_eq!(sinks.ports[1].divisor(), Some(1));
+    assert!(!sinks.ports[0].is_wedged());
+}
+
+#[test]
+fn bad_configs() {
+    let sinks = |text: &'static [u8]| SerialSinks::from_config(&Config::new(text));
+
+    assert_eq!(sinks(b"ttyS1=1500000\n"), Err(SerialError::UnsupportedBaud));
+    assert_eq!(sinks(b"ttyS0=fast\n"), Err(SerialError::Malformed));
+    assert_eq!(sinks(b"console=ttyS7\n"), Err(SerialError::Malformed));
+    assert_eq!(sinks(b"console=ttyS1\n"), Err(SerialError::ConsoleDisabled));
+    assert_eq!(sinks(b"ttyS0=off\n"), Err(SerialError::ConsoleDisabled));
+    assert!(sinks(b"ttyS0=off\nttyS1=9600\nconsole=ttyS1\n").is_ok());
+}
+
+#[test]
+fn probes() {
+    let mut present = FakeUart {
+        scratch: 0x42,
+        ..FakeUart::default()
+    };
+    assert_eq!(probe_uart(&mut present, BASE), UartProbe::Present);
+    assert_eq!(present.scratch, 0x42);
+
+    let mut floating = FakeUart {
+        floating: true,
+        ..FakeUart::default()
+    };
+    assert_eq!(probe_uart(&mut floating, BASE), UartProbe::FloatingBus);
+    let mut no_scratch = FakeUart {
+        no_scratch: true,
+        ..FakeUart::default()
+    };
+    assert_eq!(probe_uart(&mut no_scratch, BASE), UartProbe::NoScratch);
+}
+
+#[test]
+fn loopback() {
+    let mut uart = FakeUart {
+        mcr: 0x0B,
+        /* Stale input is drained first */
+        rx: Some(b'x'),
+        ..FakeUart::default()
+    };
+    assert!(loopback_echo(&mut uart, BASE));
+    assert_eq!(uart.mcr, 0x0B);
+    assert_eq!(uart.rx, None);
+
+    let mut deaf = FakeUart {
+        mcr: 0x03,
+        no_loopback: true,
+        ..FakeUart::default()
+    };
+    assert!(!loopback_echo(&mut deaf, BASE));
+    assert_eq!(deaf.mcr, 0x03);
+    assert!(deaf.lsr_reads <= 3 * PROBE_SPINS);
+
+    let mut stuck = FakeUart {
+        stuck: true,
+        ..FakeUart::default()
+    };
+    assert!(!loopback_echo(&mut stuck, BASE));
+    assert!(stuck.lsr_reads <= 3 * PROBE_SPINS);
+}
+
+#[test]
+fn program() {
+    let mut uart = Uart::new(BASE, DEFAULT_BAUD);
+    assert_eq!(uart.probe(), UartProbe::Untested);
+    assert_eq!(uart.program(&mut FakeUart::default()), Ok(()));
+    assert!(uart.is_ready());
+    assert_eq!(uart.probe(), UartProbe::Present);
+
+    let mut deaf = FakeUart {
+        no_loopback: true,
+        ..FakeUart::default()
+    };
+    let e = SerialError::NotPresent(UartProbe::NoLoopback);
+    assert_eq!(uart.program(&mut deaf), Err(e));
+    assert!(!uart.is_ready());
+    /* Nothing but the loopback byte was sent */
+    assert_eq!(deaf.mcr, 0);
+
+    let mut floating = FakeUart {
+        floating: true,
+        ..FakeUart::default()
+    };
+    let e = SerialError::NotPresent(UartProbe::FloatingBus);
+    assert_eq!(uart.program(&mut floating), Err(e));
+
+    /* Disabled ports are never touched */
+    let mut off = Uart::disabled(BASE);
+    assert_eq!(off.program(&mut floating), Ok(()));
+    assert_eq!(off.probe(), UartProbe::Untested);
+}
+
+#[test]
+fn fill_fifo() {
+    let ring = TxRing::<64>::new();
+    for byte in 0..40 {
+        unsafe { ring.push(byte) }.unwrap();
+    }
+
+    let mut io = FakeUart {
+        fifo: true,
+        ..FakeUart::default()
+    };
+    let mut uart = Uart::new(BASE, DEFAULT_BAUD);
+    uart.program(&mut io).unwrap();
+    assert!(uart.has_fifo());
+    assert_eq!(unsafe { uart.fill_fifo(&mut io, &ring) }, 16);
+    assert_eq!(io.sent, (0..16).collect::<Vec<u8>>());
+
+    /* Nothing is written while THR is full */
+    io.stuck = true;
+    assert_eq!(unsafe { uart.fill_fifo(&mut io, &ring) }, 0);
+    assert_eq!(ring.len(), 24);
+
+    /* A 16450 takes one byte at a time */
+    let mut io = FakeUart::default();
+    let mut uart = Uart::new(BASE, DEFAULT_BAUD);
+    uart.program(&mut io).unwrap();
+    assert!(!uart.has_fifo());
+    assert_eq!(unsafe { uart.fill_fifo(&mut io, &ring) }, 1);
+    assert_eq!(io.sent, [16]);
+}
+
+#[test]
+fn tx_ring_attached() {
+    let ring: &'static TxRing<TX_RING_SIZE> = Box::leak(Box::new(TxRing::new()));
+    let mut sinks = SerialSinks::new();
+    unsafe { sinks.use_tx_ring(ring) };
+    assert!(sinks.tx_ring().is_some());
+    assert_eq!(sinks.unbuffered(), SerialSinks::new());
+
+    /* Without a working console there's nothing to queue for */
+    write!(sinks, "lost").unwrap();
+    assert!(ring.is_empty());
+    sinks.flush();
+    assert_eq!(ring.queued(), 0);
+}
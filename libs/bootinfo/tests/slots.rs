@@ -0,0 +1,245 @@
+#![feature(abi_efiapi)]
+
+use bootinfo::*;
+use std::cell::RefCell;
+use uefi::{Guid, RuntimeServices};
+
+const NOT_FOUND: usize = 0x8000_0000_0000_000e;
+const BUFFER_TOO_SMALL: usize = 0x8000_0000_0000_0005;
+const DEVICE_ERROR: usize = 0x8000_0000_0000_0007;
+
+#[derive(Default)]
+struct Store {
+    /* (attributes, data) of SovosBootSlot */
+    variable: Option<(u32, Vec<u8>)>,
+    writes: usize,
+    fail_writes: bool,
+}
+
+thread_local! {
+    static STORE: RefCell<Store> = RefCell::new(Store::default());
+}
+
+unsafe fn check_name(name: *const u16, vendor: *const Guid) {
+    assert!(*vendor == Guid::SOVOS_VARIABLE);
+    let name = std::slice::from_raw_parts(name, SLOT_VARIABLE.len());
+    assert_eq!(name, &SLOT_VARIABLE[..]);
+}
+
+extern "efiapi" fn mock_get_variable(
+    name: *const u16,
+    vendor: *const Guid,
+    attributes: *mut u32,
+    size: *mut usize,
+    data: *mut u8,
+) -> usize {
+    unsafe {
+        check_name(name, vendor);
+        STORE.with(|store| {
+            let store = store.borrow();
+            let (attr, value) = match &store.variable {
+                Some(x) => x,
+                None => return NOT_FOUND,
+            };
+            if *size < value.len() {
+                *size = value.len();
+                return BUFFER_TOO_SMALL;
+            }
+            if !attributes.is_null() {
+                *attributes = *attr;
+            }
+            *size = value.len();
+            data.copy_from_nonoverlapping(value.as_ptr(), value.len());
+            return 0;
+        })
+    }
+}
+
+extern "efiapi" fn mock_set_variable(
+    name: *const u16,
+    vendor: *const Guid,
+    attributes: u32,
+    size: usize,
+    data: *const u8,
+) -> usize {
+    unsafe {
+        check_name(name, vendor);
+        let value = std::slice::from_raw_parts(data, size).to_vec();
+        STORE.with(|store| {
+            let mut store = store.borrow_mut();
+            if store.fail_writes {
+                return DEVICE_ERROR;
+            }
+            store.variable = Some((attributes, value));
+            store.writes += 1;
+            return 0;
+        })
+    }
+}
+
+/* Same layout as RuntimeServices */
+#[repr(C)]
+struct MockRuntimeServices {
+    header: [u64; 3],
+    services: [usize; 14],
+}
+
+fn with_runtime_services(f: impl FnOnce(&RuntimeServices)) {
+    let mut mock = MockRuntimeServices {
+        header: [0; 3],
+        services: [0; 14],
+    };
+    mock.services[6] = mock_get_variable as usize;
+    mock.services[8] = mock_set_variable as usize;
+
+    STORE.with(|store| *store.borrow_mut() = Store::default());
+    f(unsafe { &*(&mock as *const MockRuntimeServices as *const RuntimeServices) });
+}
+
+fn put(data: &[u8]) {
+    STORE.with(|store| store.borrow_mut().variable = Some((0x7, data.to_vec())));
+}
+
+fn stored() -> SlotState {
+    STORE.with(|store| {
+        let store = store.borrow();
+        let (attributes, data) = store.variable.as_ref().unwrap();
+        /* Non-volatile, and the kernel has to be able to write it */
+        assert_eq!(*attributes, 0x7);
+        SlotState::from_bytes(data).unwrap()
+    })
+}
+
+fn writes() -> usize {
+    STORE.with(|store| store.borrow().writes)
+}
+
+/// One boot, the kernel of `good` marks success, the other never does
+fn boot(rt: &RuntimeServices, good: Slot) -> Slot {
+    let plan = begin_slot_boot(rt).unwrap();
+    if plan.boot == good {
+        let state = read_slot_state(rt).unwrap();
+        write_slot_state(rt, state.mark_success()).unwrap();
+    }
+    return plan.boot;
+}
+
+#[test]
+fn encoding() {
+    let name: Vec<u16> = "SovosBootSlot\0".encode_utf16().collect();
+    assert_eq!(&SLOT_VARIABLE[..], &name[..]);
+
+    let state = SlotState::trial(Slot::B, 3);
+    assert_eq!(state.to_bytes(), [1, 1, 3, 0]);
+    assert_eq!(SlotState::from_bytes(&[1, 1, 3, 0]), Ok(state));
+    assert_eq!(
+        state.mark_success().to_bytes(),
+        [1, 1, 3, BootResult::Success as u8]
+    );
+
+    assert_eq!(SlotState::from_bytes(&[1, 1, 3]), Err(SlotError::Malformed));
+    assert_eq!(
+        SlotState::from_bytes(&[2, 1, 3, 0]),
+        Err(SlotError::Malformed)
+    );
+    assert_eq!(
+        SlotState::from_bytes(&[1, 2, 3, 0]),
+        Err(SlotError::BadSlot)
+    );
+    assert_eq!(
+        SlotState::from_bytes(&[1, 0, 3, 3]),
+        Err(SlotError::BadResult)
+    );
+}
+
+#[test]
+fn plan() {
+    let plan = SlotState::trial(Slot::B, 2).plan();
+    assert_eq!(plan.boot, Slot::B);
+    assert_eq!(plan.write, Some(SlotState::trial(Slot::B, 1)));
+    assert!(!plan.falls_back());
+
+    let plan = SlotState::trial(Slot::B, 0).plan();
+    assert_eq!(plan.boot, Slot::A);
+    assert_eq!(plan.write.unwrap().last_result, BootResult::FellBack);
+    assert!(plan.falls_back());
+
+    let plan = SlotState::trial(Slot::A, 0).mark_success().plan();
+    assert_eq!((plan.boot, plan.write), (Slot::A, None));
+}
+
+#[test]
+fn missing_variable_boots_a() {
+    with_runtime_services(|rt| {
+        let plan = begin_slot_boot(rt).unwrap();
+        assert_eq!(plan.state, SlotState::initial());
+        assert_eq!(plan.boot, Slot::A);
+        assert_eq!(writes(), 0);
+    });
+}
+
+#[test]
+fn failing_slot_falls_back() {
+    with_runtime_services(|rt| {
+        put(&SlotState::trial(Slot::B, 2).to_bytes());
+
+        assert_eq!(boot(rt, Slot::A), Slot::B);
+        assert_eq!(boot(rt, Slot::A), Slot::B);
+        assert_eq!(stored(), SlotState::trial(Slot::B, 0));
+        assert_eq!(boot(rt, Slot::A), Slot::A);
+
+        /* The known good kernel confirmed, A stays */
+        let state = stored();
+        assert_eq!(
+            (state.slot, state.last_result),
+            (Slot::A, BootResult::Success)
+        );
+        assert_eq!(boot(rt, Slot::A), Slot::A);
+    });
+}
+
+#[test]
+fn confirmed_slot_stays() {
+    with_runtime_services(|rt| {
+        put(&SlotState::trial(Slot::B, 2).to_bytes());
+
+        assert_eq!(boot(rt, Slot::B), Slot::B);
+        assert_eq!(stored(), SlotState::trial(Slot::B, 1).mark_success());
+
+        let before = writes();
+        for _ in 0..5 {
+            assert_eq!(begin_slot_boot(rt).unwrap().boot, Slot::B);
+        }
+        assert_eq!(writes(), before);
+    });
+}
+
+#[test]
+fn try_is_spent_before_loading() {
+    with_runtime_services(|rt| {
+        put(&SlotState::trial(Slot::B, 1).to_bytes());
+
+        /* Power is lost right after, the try is already gone */
+        assert_eq!(begin_slot_boot(rt).unwrap().boot, Slot::B);
+        assert_eq!(stored(), SlotState::trial(Slot::B, 0));
+        assert_eq!(begin_slot_boot(rt).unwrap().boot, Slot::A);
+    });
+}
+
+#[test]
+fn errors() {
+    with_runtime_services(|rt| {
+        put(&[1, 1, 3, 0, 0]);
+        assert_eq!(begin_slot_boot(rt), Err(SlotError::Malformed));
+        put(&[1, 1, 3, 0, 0, 0, 0]);
+        assert_eq!(begin_slot_boot(rt), Err(SlotError::Malformed));
+
+        /* Not booting a try that couldn't be counted */
+        put(&SlotState::trial(Slot::B, 1).to_bytes());
+        STORE.with(|store| store.borrow_mut().fail_writes = true);
+        assert_eq!(
+            begin_slot_boot(rt),
+            Err(SlotError::Variable(uefi::Error::DeviceError))
+        );
+    });
+}
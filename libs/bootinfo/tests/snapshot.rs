@@ -0,0 +1,55 @@
+use bootinfo::{Bootinfo, OwnedTable, TableMismatch, TableSnapshot};
+use cpu::paging::{PDEntry, PDFlags, PML4Entry, PML4Flags};
+use cpu::PhysAddr;
+
+fn make_bootinfo() -> Box<Bootinfo> {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    let pdp = PhysAddr::new(&bootinfo.pdp as *const _ as u64).unwrap();
+    bootinfo.paging_root[0] = PML4Entry::new(pdp, PML4Flags::new().set_present());
+    return bootinfo;
+}
+
+#[test]
+fn unchanged_tables_verify() {
+    let bootinfo = make_bootinfo();
+    let mut snapshot = Box::new(TableSnapshot::new());
+
+    bootinfo.snapshot_tables(&mut snapshot);
+    assert_eq!(bootinfo.verify_tables(&snapshot), Ok(()));
+}
+
+#[test]
+fn modified_entry_is_reported() {
+    let mut bootinfo = make_bootinfo();
+    let mut snapshot = Box::new(TableSnapshot::new());
+    bootinfo.snapshot_tables(&mut snapshot);
+
+    let flags = PDFlags::new().set_present().set_leaf();
+    let entry = PDEntry::new(PhysAddr::new(0x4000_0000).unwrap(), flags);
+    let raw = cpu::paging::Bits::as_u64(&entry);
+    bootinfo.pd[7] = entry;
+
+    let expected = TableMismatch {
+        table: OwnedTable::Pd,
+        index: 7,
+        expected: 0,
+        found: raw,
+    };
+    assert_eq!(bootinfo.verify_tables(&snapshot), Err(expected));
+}
+
+#[test]
+fn cleared_root_entry_is_reported() {
+    let mut bootinfo = make_bootinfo();
+    let mut snapshot = Box::new(TableSnapshot::new());
+    bootinfo.snapshot_tables(&mut snapshot);
+
+    let old = cpu::paging::Bits::as_u64(&bootinfo.paging_root[0]);
+    bootinfo.paging_root[0] = PML4Entry::new(PhysAddr::new(0).unwrap(), PML4Flags::new());
+
+    let err = bootinfo.verify_tables(&snapshot).unwrap_err();
+    assert_eq!(
+        (err.table, err.index, err.expected),
+        (OwnedTable::Pml4, 0, old)
+    );
+}
@@ -0,0 +1,208 @@
+use bootinfo::*;
+use BootStage::*;
+
+const ALL: [BootStage; 7] = [
+    Entry,
+    ConsoleReady,
+    KernelLoaded,
+    HandoffPrepared,
+    ExitedBootServices,
+    TablesReady,
+    JumpingToKernel,
+];
+
+fn run(sequence: &[BootStage]) -> (BootStages, Result<(), IllegalTransition>) {
+    let mut stages = BootStages::new();
+    let mut result = Ok(());
+    for &stage in sequence {
+        result = result.and(stages.try_advance(stage));
+    }
+    return (stages, result);
+}
+
+#[test]
+fn legal_sequences() {
+    let full = [
+        ConsoleReady,
+        KernelLoaded,
+        HandoffPrepared,
+        ExitedBootServices,
+        TablesReady,
+        JumpingToKernel,
+    ];
+    let without_handoff = [
+        ConsoleReady,
+        KernelLoaded,
+        ExitedBootServices,
+        TablesReady,
+        JumpingToKernel,
+    ];
+
+    for sequence in [&full[..], &without_handoff[..]] {
+        let (stages, result) = run(sequence);
+        assert_eq!(result, Ok(()), "{:?}", sequence);
+        assert_eq!(stages.current(), JumpingToKernel);
+        assert_eq!(stages.missing_before(JumpingToKernel), None);
+        assert_eq!(stages.illegal(), None);
+        assert_eq!(stages.illegal_count(), 0);
+    }
+}
+
+#[test]
+fn illegal_sequences() {
+    let sequences: &[(&[BootStage], IllegalTransition)] = &[
+        /* Skipped ExitBootServices */
+        (
+            &[ConsoleReady, KernelLoaded, HandoffPrepared, TablesReady],
+            IllegalTransition {
+                from: HandoffPrepared,
+                to: TablesReady,
+            },
+        ),
+        /* Out of order */
+        (
+            &[KernelLoaded, ConsoleReady],
+            IllegalTransition {
+                from: Entry,
+                to: KernelLoaded,
+            },
+        ),
+        /* Repeated */
+        (
+            &[ConsoleReady, ConsoleReady],
+            IllegalTransition {
+                from: ConsoleReady,
+                to: ConsoleReady,
+            },
+        ),
+        /* Backwards */
+        (
+            &[ConsoleReady, KernelLoaded, ExitedBootServices, KernelLoaded],
+            IllegalTransition {
+                from: ExitedBootServices,
+                to: KernelLoaded,
+            },
+        ),
+        (
+            &[JumpingToKernel],
+            IllegalTransition {
+                from: Entry,
+                to: JumpingToKernel,
+            },
+        ),
+    ];
+
+    for (sequence, first) in sequences {
+        let (stages, result) = run(sequence);
+        assert_eq!(result, Err(*first), "{:?}", sequence);
+        assert_eq!(stages.illegal(), Some(*first));
+        /* The machine follows the loader, even when it's wrong */
+        assert_eq!(stages.current(), *sequence.last().unwrap());
+    }
+}
+
+#[test]
+fn every_transition_is_in_the_table() {
+    for &from in &ALL {
+        for &to in &ALL {
+            let mut stages = BootStages::new();
+            /* Get to `from` legally, the path doesn't matter */
+            for &stage in &ALL[1..] {
+                if stages.current() == from {
+                    break;
+                }
+                if is_allowed(stages.current(), stage) {
+                    stages.try_advance(stage).unwrap();
+                }
+            }
+            assert_eq!(stages.current(), from);
+
+            assert_eq!(
+                stages.try_advance(to).is_ok(),
+                TRANSITIONS.contains(&(from, to)),
+                "{:?} -> {:?}",
+                from,
+                to
+            );
+        }
+    }
+}
+
+#[test]
+fn illegal_transitions_are_counted() {
+    let (stages, _) = run(&[KernelLoaded, Entry, TablesReady]);
+    assert_eq!(stages.illegal_count(), 3);
+    assert_eq!(
+        stages.illegal(),
+        Some(IllegalTransition {
+            from: Entry,
+            to: KernelLoaded
+        })
+    );
+}
+
+#[test]
+fn required_before_follows_the_table() {
+    for &stage in &ALL {
+        /* A required stage is always earlier */
+        for &required in required_before(stage) {
+            assert!(required < stage, "{:?} before {:?}", required, stage);
+        }
+    }
+    assert!(!required_before(JumpingToKernel).contains(&HandoffPrepared));
+    assert!(required_before(JumpingToKernel).contains(&ExitedBootServices));
+
+    let (stages, _) = run(&[ConsoleReady, KernelLoaded, HandoffPrepared]);
+    assert_eq!(stages.missing_before(HandoffPrepared), None);
+    assert_eq!(
+        stages.missing_before(JumpingToKernel),
+        Some(ExitedBootServices)
+    );
+    assert!(stages.has_reached(Entry));
+    assert!(!stages.has_reached(TablesReady));
+}
+
+#[test]
+fn watchdog_until_exit() {
+    for &stage in &ALL {
+        assert_eq!(stage.pets_watchdog(), stage < ExitedBootServices);
+    }
+}
+
+#[test]
+fn advance_marks_timeline() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.advance(ConsoleReady).unwrap();
+    bootinfo.advance(KernelLoaded).unwrap();
+
+    assert_eq!(bootinfo.stages.current(), KernelLoaded);
+    let names: Vec<&[u8]> = bootinfo.timeline.iter().map(|x| x.name()).collect();
+    assert_eq!(
+        names,
+        [&b"stage: console ready"[..], &b"stage: kernel loaded"[..]]
+    );
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "illegal boot stage transition")]
+fn advance_panics_in_debug() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    let _ = bootinfo.advance(TablesReady);
+}
+
+#[test]
+fn counted_timeline_events() {
+    let event = TimelineEvent::counted("copy", 2097152, 81234, 7);
+    assert_eq!(event.name(), b"copy 2097152B 81234c");
+    assert_eq!(event.tsc, 7);
+
+    /* Truncated to the 32 byte name */
+    let event = TimelineEvent::counted("zero", u64::MAX, u64::MAX, 0);
+    assert_eq!(event.name(), &b"zero 18446744073709551615B 18446"[..]);
+
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.mark_phys_counters();
+    let names: Vec<&[u8]> = bootinfo.timeline.iter().map(|x| &x.name()[..5]).collect();
+    assert_eq!(names, [&b"copy "[..], &b"zero "[..]]);
+}
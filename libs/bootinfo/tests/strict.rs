@@ -0,0 +1,46 @@
+use bootinfo::*;
+
+#[test]
+fn tolerated_by_default() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    assert_eq!(bootinfo.tolerate(Condition::NxUnavailable), Ok(()));
+    assert_eq!(bootinfo.tolerate(Condition::WxAudit), Ok(()));
+
+    let conditions: Vec<_> = bootinfo.conditions.iter().collect();
+    assert_eq!(conditions, [Condition::WxAudit, Condition::NxUnavailable]);
+    assert_eq!(bootinfo.last_error, None);
+}
+
+#[test]
+fn strict_fails() {
+    let mut bootinfo = Box::new(Bootinfo::new());
+    bootinfo.record(BootCapabilities::set_strict);
+
+    let e = BootError::StrictViolation(Condition::MemMapTruncated);
+    assert_eq!(bootinfo.tolerate(Condition::MemMapTruncated), Err(e));
+    assert!(bootinfo.conditions.contains(Condition::MemMapTruncated));
+    assert_eq!(bootinfo.last_error, Some(e));
+}
+
+#[test]
+fn stable_bits() {
+    for (bit, &condition) in Condition::ALL.iter().enumerate() {
+        assert_eq!(condition as u8, bit as u8);
+        assert_eq!(Condition::from_u8(bit as u8), Some(condition));
+
+        let mut conditions = Conditions::new();
+        conditions.insert(condition);
+        assert_eq!(conditions.as_u32(), 1 << bit);
+    }
+    assert_eq!(Condition::from_u8(Condition::ALL.len() as u8), None);
+}
+
+#[test]
+fn debug_lists_names() {
+    let conditions = Conditions::from_u32(0b101);
+    assert_eq!(
+        format!("{:?}", conditions),
+        "{MemMapTruncated, SectionConflict}"
+    );
+    assert!(Conditions::new().is_empty());
+}
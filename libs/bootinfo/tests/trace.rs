@@ -0,0 +1,213 @@
+use bootinfo::*;
+use std::mem::size_of;
+
+/// Synthetic trace buffer of `records` records, aligned like RAM would be
+fn buffer(records: usize) -> Vec<u64> {
+    vec![0u64; (TRACE_HEADER_SIZE + records * TRACE_RECORD_SIZE) / 8]
+}
+
+fn bytes(buf: &mut [u64]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 8) }
+}
+
+fn header_mut(buf: &mut [u64]) -> &mut TraceHeader {
+    unsafe { &mut *(buf.as_mut_ptr() as *mut TraceHeader) }
+}
+
+#[test]
+fn record_layout() {
+    assert_eq!(size_of::<TraceHeader>(), 40);
+    assert_eq!(TRACE_RECORD_SIZE, 32);
+    assert_eq!(&TRACE_MAGIC.to_le_bytes(), b"SOVOSTRC");
+
+    let mut buf = buffer(2);
+    let mut writer = TraceWriter::new(bytes(&mut buf), 7).unwrap();
+    writer.append(TraceRecord::new(
+        0x1122,
+        TraceEvent::Alloc,
+        [0x2000_0000, 0x40_0000],
+    ));
+
+    assert_eq!(buf[0], TRACE_MAGIC);
+    assert_eq!(
+        buf[1],
+        TRACE_VERSION as u64 | (TRACE_RECORD_SIZE as u64) << 32
+    );
+    assert_eq!(buf[2], 2 | 7 << 32);
+    assert_eq!(buf[3], 1);
+    assert_eq!(
+        &buf[5..9],
+        &[0x1122, TraceEvent::Alloc as u64, 0x2000_0000, 0x40_0000]
+    );
+    assert_eq!(TraceEvent::Alloc as u32, 5);
+}
+
+#[test]
+fn roundtrip() {
+    let mut buf = buffer(8);
+    let mut writer = TraceWriter::new(bytes(&mut buf), 1).unwrap();
+    assert_eq!(writer.header().capacity, 8);
+    writer.push(TraceEvent::Start, [1, 0]);
+    writer.push(TraceEvent::Stage, [BootStage::ConsoleReady as u64, 0]);
+    writer.push(TraceEvent::Map, [0xffff_ffff_c000_0000, 0x20_0000]);
+
+    let trace = PreviousTrace::find(bytes(&mut buf)).unwrap();
+    assert_eq!(trace.header.boot, 1);
+    let events: Vec<_> = trace.records().map(|r| r.event()).collect();
+    assert_eq!(
+        events,
+        [
+            Some(TraceEvent::Start),
+            Some(TraceEvent::Stage),
+            Some(TraceEvent::Map)
+        ]
+    );
+    let records: Vec<_> = trace.records().collect();
+    assert!(records[0].tsc <= records[2].tsc);
+    assert_eq!(records[2].args, [0xffff_ffff_c000_0000, 0x20_0000]);
+}
+
+#[test]
+fn wraps_around() {
+    let mut buf = buffer(4);
+    let mut writer = TraceWriter::new(bytes(&mut buf), 1).unwrap();
+    for i in 0..6 {
+        writer.append(TraceRecord::new(i, TraceEvent::Alloc, [i, 0]));
+    }
+
+    let trace = PreviousTrace::find(bytes(&mut buf)).unwrap();
+    assert_eq!(trace.header.written, 6);
+    assert_eq!(trace.header.len(), 4);
+    assert_eq!(trace.header.dropped(), 2);
+    let tsc: Vec<_> = trace.records().map(|r| r.tsc).collect();
+    assert_eq!(tsc, [2, 3, 4, 5]);
+}
+
+#[test]
+fn rejects_bad_headers() {
+    let mut buf = buffer(4);
+    assert_eq!(
+        PreviousTrace::find(bytes(&mut buf)).unwrap_err(),
+        TraceError::BadMagic
+    );
+
+    TraceWriter::new(bytes(&mut buf), 1)
+        .unwrap()
+        .push(TraceEvent::Start, [1, 0]);
+    assert!(PreviousTrace::find(bytes(&mut buf)).is_ok());
+
+    /* A bit flipped by the reset */
+    header_mut(&mut buf).written ^= 1 << 40;
+    assert_eq!(
+        PreviousTrace::find(bytes(&mut buf)).unwrap_err(),
+        TraceError::BadChecksum
+    );
+
+    let header = header_mut(&mut buf);
+    header.written = 1;
+    header.version = 2;
+    header.checksum = header.checksum();
+    assert_eq!(
+        PreviousTrace::find(bytes(&mut buf)).unwrap_err(),
+        TraceError::BadVersion(2)
+    );
+
+    /* Written by a loader with a bigger buffer */
+    let header = header_mut(&mut buf);
+    header.version = TRACE_VERSION;
+    header.capacity = 5;
+    header.checksum = header.checksum();
+    assert_eq!(
+        PreviousTrace::find(bytes(&mut buf)).unwrap_err(),
+        TraceError::BadGeometry
+    );
+
+    let header = header_mut(&mut buf);
+    header.capacity = 4;
+    header.record_size = 24;
+    header.checksum = header.checksum();
+    assert_eq!(
+        PreviousTrace::find(bytes(&mut buf)).unwrap_err(),
+        TraceError::BadGeometry
+    );
+}
+
+#[test]
+fn rejects_bad_buffers() {
+    let mut buf = buffer(1);
+    let len = TRACE_HEADER_SIZE + TRACE_RECORD_SIZE;
+    assert!(TraceWriter::new(&mut bytes(&mut buf)[..len], 1).is_ok());
+    assert_eq!(
+        TraceWriter::new(&mut bytes(&mut buf)[..len - 1], 1).err(),
+        Some(TraceError::TooSmall)
+    );
+    assert_eq!(
+        TraceWriter::new(&mut bytes(&mut buf)[4..], 1).err(),
+        Some(TraceError::Misaligned)
+    );
+    assert_eq!(
+        PreviousTrace::find(&bytes(&mut buf)[..TRACE_HEADER_SIZE]).unwrap_err(),
+        TraceError::TooSmall
+    );
+}
+
+#[test]
+fn alloc_threshold() {
+    let mut buf = buffer(4);
+    let mut writer = TraceWriter::new(bytes(&mut buf), 1)
+        .unwrap()
+        .with_alloc_threshold(0x20_0000);
+    assert!(!writer.alloc(0x1000, 0x1000));
+    assert!(writer.alloc(0x20_0000, 0x20_0000));
+    assert_eq!(writer.header().written, 1);
+}
+
+#[test]
+fn found_previous_trace() {
+    let mut buf = buffer(16);
+
+    /* A boot that got past ExitBootServices and panicked */
+    let mut writer = TraceWriter::new(bytes(&mut buf), 41).unwrap();
+    writer.push(TraceEvent::Start, [41, 0]);
+    writer.push(TraceEvent::Stage, [BootStage::ConsoleReady as u64, 0]);
+    writer.push(TraceEvent::Stage, [BootStage::KernelLoaded as u64, 0]);
+    writer.push(TraceEvent::Error, [123, 0]);
+    writer.push(TraceEvent::Stage, [BootStage::ExitedBootServices as u64, 0]);
+    writer.push(TraceEvent::Panic, [456, 0]);
+
+    /* The next boot finds it and starts over */
+    let summary = PreviousTrace::find(bytes(&mut buf)).unwrap().summary();
+    assert_eq!(summary.boot, 41);
+    assert_eq!(summary.records, 6);
+    assert_eq!(summary.dropped, 0);
+    assert_eq!(summary.last_stage, Some(BootStage::ExitedBootServices));
+    assert_eq!(summary.errors, 1);
+    assert!(summary.panicked);
+    assert_eq!(summary.last.map(|r| r.args[0]), Some(456));
+
+    let mut writer = TraceWriter::new(bytes(&mut buf), summary.boot + 1).unwrap();
+    writer.push(TraceEvent::Start, [42, 0]);
+    let trace = PreviousTrace::find(bytes(&mut buf)).unwrap();
+    assert_eq!(trace.header.boot, 42);
+    assert_eq!(trace.summary().records, 1);
+    assert_eq!(trace.summary().last_stage, None);
+    assert!(!trace.summary().panicked);
+}
+
+#[test]
+fn config() {
+    let config = Config::new(b"trace=0x2000000\ntrace_size=0x1000\n");
+    let trace = TraceConfig::from_config(&config).unwrap().unwrap();
+    assert_eq!(trace.addr, 0x200_0000);
+    assert_eq!(trace.pages(), 1);
+    assert_eq!(trace.alloc_threshold, DEFAULT_TRACE_ALLOC_THRESHOLD);
+
+    assert_eq!(TraceConfig::from_config(&Config::empty()), Ok(None));
+    let unaligned = Config::new(b"trace=0x2000010\n");
+    assert_eq!(
+        TraceConfig::from_config(&unaligned),
+        Err(TraceError::BadAddress)
+    );
+    let tiny = Config::new(b"trace=0x2000000\ntrace_size=16\n");
+    assert_eq!(TraceConfig::from_config(&tiny), Err(TraceError::BadSize));
+}
@@ -0,0 +1,92 @@
+use bootinfo::Bootinfo;
+use cpu::paging::{PDEntry, PDFlags, PDPEntry, PDPFlags, PML4Entry, PML4Flags, PTEntry, PTFlags};
+use cpu::PhysAddr;
+
+const BASE: u64 = 0x4000_0000;
+const MEGAPAGE_BASE: u64 = BASE + (1 << 21);
+
+fn phys_of<T>(x: &T) -> PhysAddr {
+    PhysAddr::new(x as *const T as u64).unwrap()
+}
+
+/* Builds tables mapping:
+ * BASE + 0..4 pages -> 0x20_0000.. (contiguous)
+ * BASE + 4 page     -> 0x30_0000
+ * BASE + 5 page     -> 0x50_0000
+ * BASE + 7 page     -> unmapped
+ * MEGAPAGE_BASE     -> 0x4000_0000 (2M page) */
+fn make_bootinfo() -> Box<Bootinfo> {
+    let mut bootinfo = Box::new(Bootinfo::new());
+
+    let pdp = phys_of(&bootinfo.pdp);
+    let pd = phys_of(&bootinfo.pd);
+    let pt = phys_of(&bootinfo.page_table);
+
+    bootinfo.paging_root[0] = PML4Entry::new(pdp, PML4Flags::new().set_present());
+    bootinfo.pdp[1] = PDPEntry::new(pd, PDPFlags::new().set_present());
+    bootinfo.pd[0] = PDEntry::new(pt, PDFlags::new().set_present());
+
+    let flags = PTFlags::new().set_present();
+    for i in 0..4 {
+        let addr = PhysAddr::new(0x20_0000 + i * 4096).unwrap();
+        bootinfo.page_table[i as usize] = PTEntry::new(addr, flags);
+    }
+    bootinfo.page_table[4] = PTEntry::new(PhysAddr::new(0x30_0000).unwrap(), flags);
+    bootinfo.page_table[5] = PTEntry::new(PhysAddr::new(0x50_0000).unwrap(), flags);
+
+    let flags = PDFlags::new().set_present().set_leaf();
+    bootinfo.pd[1] = PDEntry::new(PhysAddr::new(0x4000_0000).unwrap(), flags);
+
+    return bootinfo;
+}
+
+#[test]
+fn translate_pages() {
+    let bootinfo = make_bootinfo();
+
+    let phys = |virt| unsafe { bootinfo.translate(virt) }.map(|x| x.as_u64());
+    assert_eq!(phys(BASE), Some(0x20_0000));
+    assert_eq!(phys(BASE + 4096 + 0x123), Some(0x20_1123));
+    assert_eq!(phys(BASE + 5 * 4096 + 8), Some(0x50_0008));
+    assert_eq!(phys(BASE + 7 * 4096), None);
+    assert_eq!(phys(MEGAPAGE_BASE + 0x1_2345), Some(0x4001_2345));
+    assert_eq!(phys(0xffff_ffff_c000_0000), None);
+}
+
+#[test]
+fn contiguous_mapping() {
+    let bootinfo = make_bootinfo();
+
+    let contiguous =
+        |virt, len| unsafe { bootinfo.is_phys_contiguous(virt, len) }.map(|x| x.as_u64());
+    assert_eq!(contiguous(BASE, 4 * 4096), Some(0x20_0000));
+    assert_eq!(contiguous(BASE + 0x10, 3 * 4096), Some(0x20_0010));
+    assert_eq!(contiguous(BASE + 4096, 0), Some(0x20_1000));
+    assert_eq!(contiguous(MEGAPAGE_BASE + 4096, 1 << 20), Some(0x4000_1000));
+}
+
+#[test]
+fn fragmented_mapping() {
+    let bootinfo = make_bootinfo();
+
+    assert!(unsafe { bootinfo.is_phys_contiguous(BASE, 5 * 4096) }.is_none());
+    assert!(unsafe { bootinfo.is_phys_contiguous(BASE + 4 * 4096, 2 * 4096) }.is_none());
+    assert!(unsafe { bootinfo.is_phys_contiguous(BASE + 5 * 4096, 3 * 4096) }.is_none());
+    assert!(unsafe { bootinfo.is_phys_contiguous(BASE + 7 * 4096, 10) }.is_none());
+    assert!(unsafe { bootinfo.is_phys_contiguous(MEGAPAGE_BASE, 1 << 22) }.is_none());
+}
+
+#[test]
+fn translate_gigapage() {
+    let mut bootinfo = make_bootinfo();
+
+    /* Present, writable and gigapage bits, like firmware sets them */
+    let raw = 0x1_4000_0000 | 1 << 7 | 0b11;
+    bootinfo.pdp[2] = unsafe { cpu::paging::Bits::from_u64_unchecked(raw) };
+
+    let phys = |virt| unsafe { bootinfo.translate(virt) }.map(|x| x.as_u64());
+    assert_eq!(phys(0x8000_0000), Some(0x1_4000_0000));
+    assert_eq!(phys(0x8000_0000 + 0x1234_5678), Some(0x1_5234_5678));
+    assert_eq!(phys(0xc000_0000), None);
+    assert_eq!(phys(BASE), Some(0x20_0000));
+}
@@ -0,0 +1,41 @@
+use bootinfo::TxRing;
+
+#[test]
+fn fifo_order() {
+    let ring = TxRing::<4>::new();
+    assert!(ring.is_empty());
+    assert_eq!(ring.capacity(), 4);
+    assert_eq!(unsafe { ring.pop() }, None);
+
+    for byte in b"abcd" {
+        unsafe { ring.push(*byte) }.unwrap();
+    }
+    assert_eq!(unsafe { ring.push(b'e') }, Err(b'e'));
+    assert_eq!(ring.len(), 4);
+
+    assert_eq!(unsafe { ring.pop() }, Some(b'a'));
+    unsafe { ring.push(b'e') }.unwrap();
+    let rest: Vec<u8> = std::iter::from_fn(|| unsafe { ring.pop() }).collect();
+    assert_eq!(rest, b"bcde");
+    assert_eq!(ring.queued(), 5);
+}
+
+#[test]
+fn wraps_around() {
+    let ring = TxRing::<3>::new();
+    for round in 0..100u8 {
+        unsafe { ring.push(round) }.unwrap();
+        unsafe { ring.push(round ^ 0xFF) }.unwrap();
+        assert_eq!(unsafe { ring.pop() }, Some(round));
+        assert_eq!(unsafe { ring.pop() }, Some(round ^ 0xFF));
+    }
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn blocked_time() {
+    let ring = TxRing::<8>::new();
+    ring.add_blocked(100);
+    ring.add_blocked(23);
+    assert_eq!(ring.blocked(), 123);
+}
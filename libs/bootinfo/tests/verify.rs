@@ -0,0 +1,125 @@
+#![feature(abi_efiapi)]
+
+use bootinfo::*;
+use uefi::{Error, SecureBootState, Security2Protocol};
+
+const SECURITY_VIOLATION: usize = 0x8000_0000_0000_001a;
+const ACCESS_DENIED: usize = 0x8000_0000_0000_000f;
+
+const ENFORCING: SecureBootState = SecureBootState {
+    secure_boot: true,
+    setup_mode: false,
+};
+
+/* Both accept only "signed" files */
+extern "efiapi" fn mock_file_authentication(
+    _this: &Security2Protocol,
+    _path: *const uefi::DevicePath,
+    file: *const u8,
+    size: usize,
+    _boot_policy: bool,
+) -> usize {
+    let file = unsafe { std::slice::from_raw_parts(file, size) };
+    return if file == b"signed" {
+        0
+    } else {
+        SECURITY_VIOLATION
+    };
+}
+
+extern "sysv64" fn mock_verify(file: *const u8, size: u32) -> usize {
+    let file = unsafe { std::slice::from_raw_parts(file, size as usize) };
+    return if file == b"signed" { 0 } else { ACCESS_DENIED };
+}
+
+/// A protocol struct of just function slots
+fn protocol<T, const N: usize>(functions: [usize; N]) -> &'static T {
+    unsafe { &*(Box::leak(Box::new(functions)) as *const _ as *const T) }
+}
+
+fn firmware() -> Result<Verifier<'static>, Error> {
+    Ok(Verifier::Firmware {
+        protocol: protocol([mock_file_authentication as usize]),
+        path: None,
+    })
+}
+
+fn shim() -> Result<Verifier<'static>, Error> {
+    Ok(Verifier::Shim(protocol([mock_verify as usize, 0, 0])))
+}
+
+#[test]
+fn policy_from_config() {
+    let config = Config::new(b"verify = shim\n");
+    assert_eq!(VerifyPolicy::from_config(&config), Ok(VerifyPolicy::Shim));
+    let config = Config::new(b"verify=firmware\n");
+    assert_eq!(
+        VerifyPolicy::from_config(&config),
+        Ok(VerifyPolicy::Firmware)
+    );
+    assert_eq!(
+        VerifyPolicy::from_config(&Config::empty()),
+        Ok(VerifyPolicy::Off)
+    );
+
+    /* A typo must not turn verification off */
+    let config = Config::new(b"verify=frimware\n");
+    assert_eq!(
+        VerifyPolicy::from_config(&config),
+        Err(VerifyError::UnknownPolicy)
+    );
+}
+
+#[test]
+fn accept() {
+    let policy = VerifyPolicy::Firmware;
+    assert_eq!(
+        verify_kernel(policy, ENFORCING, firmware(), b"signed"),
+        Ok(VerifyOutcome::Accepted)
+    );
+    assert_eq!(
+        verify_kernel(VerifyPolicy::Shim, ENFORCING, shim(), b"signed"),
+        Ok(VerifyOutcome::Accepted)
+    );
+}
+
+#[test]
+fn reject() {
+    /* Rejected even without Secure Boot, the policy asked for it */
+    let off = SecureBootState::default();
+    assert_eq!(
+        verify_kernel(VerifyPolicy::Firmware, off, firmware(), b"\x7fELF"),
+        Err(VerifyError::Rejected(Error::SecurityViolation))
+    );
+    assert_eq!(
+        verify_kernel(VerifyPolicy::Shim, ENFORCING, shim(), b"\x7fELF"),
+        Err(VerifyError::Rejected(Error::AccessDenied))
+    );
+}
+
+#[test]
+fn protocol_absent() {
+    let absent = || Err(Error::NotFound);
+    assert_eq!(
+        verify_kernel(VerifyPolicy::Shim, ENFORCING, absent(), b"signed"),
+        Err(VerifyError::Unavailable(Error::NotFound))
+    );
+
+    let setup_mode = SecureBootState {
+        secure_boot: true,
+        setup_mode: true,
+    };
+    assert_eq!(
+        verify_kernel(VerifyPolicy::Firmware, setup_mode, absent(), b"signed"),
+        Ok(VerifyOutcome::NoVerifier(Error::NotFound))
+    );
+}
+
+#[test]
+fn off() {
+    /* Not even looked at */
+    assert_eq!(
+        verify_kernel(VerifyPolicy::Off, ENFORCING, firmware(), b"\x7fELF"),
+        Ok(VerifyOutcome::Skipped)
+    );
+}
@@ -0,0 +1,169 @@
+#![no_std]
+
+//! Reader for "newc" CPIO archives, as created by `cpio -o -H newc`
+
+use impl_bits::bytes::{self, Reader};
+
+pub const MAGIC: &[u8; 6] = b"070701";
+pub const MAGIC_CRC: &[u8; 6] = b"070702";
+pub const HEADER_SIZE: usize = 110;
+pub const TRAILER: &str = "TRAILER!!!";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Header doesn't start with "070701" or "070702"
+    BadMagic,
+    /// One of the header fields is not a valid hex number
+    BadHexField,
+    /// Header, name or data goes past the end of the archive
+    UnexpectedEnd,
+    /// Name is not null-terminated or not valid UTF-8
+    BadName,
+}
+
+impl From<bytes::Error> for ErrorKind {
+    fn from(_: bytes::Error) -> Self {
+        ErrorKind::UnexpectedEnd
+    }
+}
+
+/// Error together with the index of the entry it happened at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    pub index: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub mode: u32,
+    pub mtime: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+pub struct Archive<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            rest: self.data,
+            index: 0,
+            done: false,
+        }
+    }
+
+    /// Finds the first entry named `name`
+    pub fn find(&self, name: &str) -> Result<Option<Entry<'a>>, Error> {
+        for entry in self.entries() {
+            let entry = entry?;
+            if entry.name == name {
+                return Ok(Some(entry));
+            }
+        }
+
+        return Ok(None);
+    }
+}
+
+const fn align4(x: usize) -> usize {
+    (x + 3) & !3
+}
+
+fn parse_hex(field: &[u8]) -> Option<u32> {
+    let mut x = 0u32;
+    for &c in field {
+        let digit = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => return None,
+        };
+        x = x << 4 | digit as u32;
+    }
+
+    return Some(x);
+}
+
+/// Iterator over archive entries, stops after "TRAILER!!!" or the first error
+pub struct Entries<'a> {
+    rest: &'a [u8],
+    index: usize,
+    done: bool,
+}
+
+impl<'a> Entries<'a> {
+    fn parse_next(&mut self) -> Result<Option<Entry<'a>>, ErrorKind> {
+        let mut entry = Reader::new(self.rest);
+        let header = entry.take(HEADER_SIZE)?;
+        if &header[..6] != MAGIC && &header[..6] != MAGIC_CRC {
+            return Err(ErrorKind::BadMagic);
+        }
+
+        /* 13 fields, 8 hex digits each, after the magic */
+        let field =
+            |n: usize| parse_hex(&header[6 + n * 8..14 + n * 8]).ok_or(ErrorKind::BadHexField);
+        let mode = field(1)?;
+        let mtime = field(5)?;
+        let filesize = field(6)? as usize;
+        let namesize = field(11)? as usize;
+
+        let name = entry.take(namesize)?;
+        let name = match name.split_last() {
+            Some((0, name)) => name,
+            _ => return Err(ErrorKind::BadName),
+        };
+        let name = core::str::from_utf8(name).map_err(|_| ErrorKind::BadName)?;
+
+        entry.skip(align4(entry.position()) - entry.position())?;
+        let data = entry.take(filesize)?;
+
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        /* Padding after the last entry may be missing */
+        let padding = align4(entry.position()) - entry.position();
+        entry.skip(core::cmp::min(padding, entry.remaining()))?;
+        self.rest = entry.rest();
+
+        return Ok(Some(Entry {
+            name,
+            mode,
+            mtime,
+            data,
+        }));
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let index = self.index;
+        self.index += 1;
+
+        return match self.parse_next() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(kind) => {
+                self.done = true;
+                Some(Err(Error { index, kind }))
+            }
+        };
+    }
+}
@@ -0,0 +1,173 @@
+use cpio::{Archive, Error, ErrorKind};
+
+/* Writes entries the same way `cpio -o -H newc` does */
+fn newc(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let trailer: (&str, &[u8]) = ("TRAILER!!!", b"");
+
+    for (i, (name, data)) in files.iter().chain(Some(&trailer)).enumerate() {
+        let fields = [
+            i as u32 + 1,
+            0o100644,
+            0,
+            0,
+            1,
+            0x6000_0000,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            name.len() as u32 + 1,
+            0,
+        ];
+
+        out.extend_from_slice(b"070701");
+        for f in fields.iter() {
+            out.extend_from_slice(format!("{:08X}", f).as_bytes());
+        }
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+        out.extend_from_slice(data);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+    }
+
+    /* cpio pads the archive to 512 byte blocks */
+    while out.len() % 512 != 0 {
+        out.push(0);
+    }
+
+    return out;
+}
+
+#[test]
+fn find_entries() {
+    let bytes = newc(&[
+        ("sovos.cfg", b"verbose=1\n"),
+        ("kernel.elf", b"\x7FELF and then some"),
+        ("initrd.img", b""),
+    ]);
+    let archive = Archive::new(&bytes);
+
+    let cfg = archive.find("sovos.cfg").unwrap().unwrap();
+    assert_eq!(cfg.data, b"verbose=1\n");
+    assert_eq!(cfg.mode, 0o100644);
+
+    let kernel = archive.find("kernel.elf").unwrap().unwrap();
+    assert_eq!(kernel.data, b"\x7FELF and then some");
+    assert_eq!(
+        kernel.data.as_ptr() as usize % 4,
+        bytes.as_ptr() as usize % 4
+    );
+
+    let initrd = archive.find("initrd.img").unwrap().unwrap();
+    assert_eq!(initrd.data.len(), 0);
+
+    assert!(archive.find("TRAILER!!!").unwrap().is_none());
+    assert!(archive.find("missing").unwrap().is_none());
+    assert_eq!(archive.entries().count(), 3);
+}
+
+#[test]
+fn malformed_headers() {
+    let bytes = newc(&[("a", b"1"), ("b", b"22"), ("c", b"333")]);
+
+    /* Second entry starts at 110 + "a\0" padded to 4 + "1" padded to 4 */
+    let second = 116;
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[second] = b'8';
+    let err = Archive::new(&bad_magic).find("c").unwrap_err();
+    assert_eq!(
+        err,
+        Error {
+            index: 1,
+            kind: ErrorKind::BadMagic
+        }
+    );
+
+    let mut bad_hex = bytes.clone();
+    bad_hex[second + 6 + 6 * 8] = b'x';
+    let err = Archive::new(&bad_hex).find("c").unwrap_err();
+    assert_eq!(
+        err,
+        Error {
+            index: 1,
+            kind: ErrorKind::BadHexField
+        }
+    );
+
+    let truncated = &bytes[..second + 50];
+    let err = Archive::new(truncated).find("c").unwrap_err();
+    assert_eq!(
+        err,
+        Error {
+            index: 1,
+            kind: ErrorKind::UnexpectedEnd
+        }
+    );
+
+    let mut bad_name = bytes.clone();
+    bad_name[second + 111] = b'!';
+    let err = Archive::new(&bad_name).find("c").unwrap_err();
+    assert_eq!(
+        err,
+        Error {
+            index: 1,
+            kind: ErrorKind::BadName
+        }
+    );
+}
+
+#[test]
+fn truncated_archives() {
+    let bytes = newc(&[("a", b"1"), ("b", b"22"), ("c", b"333")]);
+    let trailer_end = bytes.len() - bytes.iter().rev().position(|&x| x != 0).unwrap();
+
+    /* Cut anywhere before the trailer's name ends, an entry is missing */
+    for len in 0..trailer_end {
+        let result: Result<Vec<_>, _> = Archive::new(&bytes[..len]).entries().collect();
+        let err = result.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnexpectedEnd, "{} bytes", len);
+    }
+
+    /* Sizes that don't fit anywhere */
+    for field in [6, 11] {
+        let mut huge = bytes.clone();
+        huge[6 + field * 8..14 + field * 8].copy_from_slice(b"FFFFFFFF");
+        let err = Archive::new(&huge).entries().next().unwrap().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnexpectedEnd);
+    }
+}
+
+/* Made by the real thing from three small files:
+ *   printf 'sovos.cfg\nkernel.elf\ninitrd.img\n' | cpio -o -H newc > boot.cpio */
+static BOOT_CPIO: &[u8] = include_bytes!("fixtures/boot.cpio");
+
+#[test]
+fn real_archive() {
+    let archive = Archive::new(BOOT_CPIO);
+    let names: Vec<&str> = archive.entries().map(|e| e.unwrap().name).collect();
+    assert_eq!(names, ["sovos.cfg", "kernel.elf", "initrd.img"]);
+
+    let cfg = archive.find("sovos.cfg").unwrap().unwrap();
+    assert_eq!(cfg.data, b"verbose=1\nkernel_a=kernel.elf\n");
+    assert_eq!(cfg.mode, 0o100644);
+    /* 2024-01-01 00:00:00 UTC */
+    assert_eq!(cfg.mtime, 1_704_067_200);
+
+    let kernel = archive.find("kernel.elf").unwrap().unwrap();
+    assert!(kernel.data.starts_with(b"\x7FELF"));
+    assert_eq!(kernel.data.len(), 28);
+    let initrd = archive.find("initrd.img").unwrap().unwrap();
+    assert_eq!(initrd.data, b"initrd contents\n");
+
+    /* Padded to 512 byte blocks after the trailer */
+    assert_eq!(BOOT_CPIO.len() % 512, 0);
+    assert!(archive.find("TRAILER!!!").unwrap().is_none());
+}
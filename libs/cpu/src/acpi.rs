@@ -1,4 +1,13 @@
+use crate::phys::{ByteOrder, PhysReader};
+use crate::{PhysAddr, PhysSlice};
+use core::fmt::{self, Write};
 use core::{mem, ptr};
+use impl_bits::bytes::Reader;
+
+/// Whether `bytes` sum up to zero, as every ACPI table's bytes must
+pub fn validate_checksum(bytes: &[u8]) -> bool {
+    byte_sum(bytes) == 0
+}
 
 #[repr(C, packed)]
 pub struct OldRsdp {
@@ -21,7 +30,7 @@ pub struct Rsdp {
 impl Rsdp {
     pub fn verify_checksum(&self) -> bool {
         let ptr: *const [u8; 36] = self as *const _ as *const _;
-        return unsafe { (*ptr).iter().sum::<u8>() == 0u8 };
+        return unsafe { validate_checksum(&*ptr) };
     }
 }
 
@@ -38,6 +47,22 @@ pub struct SdtHeader {
     pub creator_revision: u32,
 }
 
+impl SdtHeader {
+    pub fn signature(&self) -> [u8; 4] {
+        self.signature
+    }
+
+    /// Checks `length` bytes starting at the header
+    ///
+    /// # Safety
+    /// The whole table has to be readable.
+    pub unsafe fn validate_checksum(&self) -> bool {
+        let len = self.length as usize;
+        let bytes = core::slice::from_raw_parts(self as *const _ as *const u8, len);
+        return validate_checksum(bytes);
+    }
+}
+
 #[repr(C)]
 pub struct Xsdt {
     pub header: SdtHeader,
@@ -58,3 +83,518 @@ impl Xsdt {
         return &*ptr;
     }
 }
+
+/// ACPI signatures and OEM IDs are meant to be ASCII, firmware doesn't always agree
+struct Ascii<'a>(&'a [u8]);
+
+impl fmt::Display for Ascii<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &c in self.0 {
+            let c = if c.is_ascii_graphic() || c == b' ' {
+                c
+            } else {
+                b'?'
+            };
+            f.write_char(c as char)?;
+        }
+        return Ok(());
+    }
+}
+
+fn checksum_status(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "BAD"
+    }
+}
+
+/// Prints the RSDP and every table the XSDT points to, one per line.
+/// Problems with the XSDT are reported in the output instead of panicking.
+///
+/// # Safety
+/// Physical memory must be readable at `phys + phys_offset`.
+pub unsafe fn dump<W: Write>(rsdp: &Rsdp, phys_offset: u64, out: &mut W) -> fmt::Result {
+    let old = &rsdp.old;
+    let oem_id = old.oem_id;
+    let revision = old.revision;
+    writeln!(
+        out,
+        "RSDP: oem_id {} revision {} checksum {}",
+        Ascii(&oem_id),
+        revision,
+        checksum_status(rsdp.verify_checksum())
+    )?;
+
+    let xsdt_phys = rsdp.xsdt as u64;
+    if revision < 2 || xsdt_phys == 0 {
+        return writeln!(out, "XSDT: missing");
+    }
+
+    let xsdt = &*(xsdt_phys.wrapping_add(phys_offset) as usize as *const SdtHeader);
+    let signature = xsdt.signature();
+    let length = xsdt.length as usize;
+    if signature != *b"XSDT" {
+        return writeln!(out, "XSDT: wrong signature {}", Ascii(&signature));
+    }
+    if length < mem::size_of::<SdtHeader>() {
+        return writeln!(out, "XSDT: length {} is too short", length);
+    }
+
+    writeln!(
+        out,
+        "XSDT: {:#x} length {} checksum {}",
+        xsdt_phys,
+        length,
+        checksum_status(xsdt.validate_checksum())
+    )?;
+
+    /* Entries are 8 bytes right after the 36 byte header, so only 4 aligned */
+    let header_size = mem::size_of::<SdtHeader>();
+    let first = xsdt_phys
+        .checked_add(header_size as u64)
+        .and_then(PhysAddr::new);
+    let entries = match first {
+        Some(addr) => PhysSlice::new(addr, (length - header_size) as u64),
+        None => return writeln!(out, "XSDT: {:#x} is not a physical address", xsdt_phys),
+    };
+    let mut reader = PhysReader::with_offset(entries, phys_offset);
+    let entries = core::iter::from_fn(|| reader.read_u64(ByteOrder::Little));
+    for (i, phys) in entries.enumerate() {
+        if phys == 0 {
+            writeln!(out, "  [{}] null entry", i)?;
+            continue;
+        }
+
+        let sdt = &*(phys.wrapping_add(phys_offset) as usize as *const SdtHeader);
+        let signature = sdt.signature();
+        let length = sdt.length;
+        let valid = length as usize >= mem::size_of::<SdtHeader>() && sdt.validate_checksum();
+        writeln!(
+            out,
+            "  [{}] {} at {:#x} length {} checksum {}",
+            i,
+            Ascii(&signature),
+            phys,
+            length,
+            checksum_status(valid)
+        )?;
+    }
+
+    return Ok(());
+}
+
+/// Tables `AcpiContext` keeps, more are counted in `AcpiContext::dropped`
+pub const MAX_TABLES: usize = 32;
+/// Skipped tables `AcpiContext` keeps, more are only counted
+pub const MAX_SKIPPED: usize = 8;
+
+/// Why there is no `AcpiContext`, the machine is treated as one without ACPI
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcpiError {
+    RsdpSignature([u8; 8]),
+    /// The first 20 bytes of the RSDP don't sum up to zero
+    RsdpChecksum,
+    /// Neither the XSDT nor the RSDT is usable
+    NoRootTable,
+    RootSignature([u8; 4]),
+    RootLength(u32),
+    RootChecksum {
+        checksum: u8,
+        sum: u8,
+    },
+}
+
+/// Why a table was left out of `AcpiContext`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// `checksum` is the table's, `sum` what its bytes add up to instead of 0
+    Checksum { checksum: u8, sum: u8 },
+    /// `length` doesn't cover the header
+    Length(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SkippedTable {
+    pub signature: [u8; 4],
+    pub phys: u64,
+    pub reason: SkipReason,
+}
+
+#[derive(Clone, Copy)]
+struct TableRef {
+    signature: [u8; 4],
+    phys: u64,
+    length: u32,
+}
+
+/// Every table the XSDT, or the RSDT without one, points to, validated
+/// once by `discover`. Consumers ask for tables by signature and treat
+/// a missing one as a feature the machine doesn't have, corrupt tables
+/// look missing too.
+pub struct AcpiContext {
+    phys_offset: u64,
+    root: [u8; 4],
+    tables: [TableRef; MAX_TABLES],
+    table_count: usize,
+    skipped: [SkippedTable; MAX_SKIPPED],
+    skipped_count: usize,
+    dropped: usize,
+}
+
+fn byte_sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// Header of the table at `phys`, `Err` unless it's a whole table that
+/// sums up to zero
+unsafe fn checked_table(phys: u64, phys_offset: u64) -> Result<&'static SdtHeader, SkipReason> {
+    let sdt = &*(phys.wrapping_add(phys_offset) as usize as *const SdtHeader);
+    let length = sdt.length;
+    if (length as usize) < mem::size_of::<SdtHeader>() {
+        return Err(SkipReason::Length(length));
+    }
+
+    let bytes = core::slice::from_raw_parts(sdt as *const _ as *const u8, length as usize);
+    let sum = byte_sum(bytes);
+    if sum != 0 {
+        return Err(SkipReason::Checksum {
+            checksum: sdt.checksum,
+            sum,
+        });
+    }
+    return Ok(sdt);
+}
+
+impl AcpiContext {
+    /// Validates the RSDP and root table and every table they point to.
+    /// The XSDT is preferred, the RSDT is used if it's missing or broken.
+    ///
+    /// # Safety
+    /// Physical memory must be readable at `phys + phys_offset` for as
+    /// long as the context is used.
+    pub unsafe fn discover(rsdp: &Rsdp, phys_offset: u64) -> Result<Self, AcpiError> {
+        let old = &rsdp.old;
+        let signature = old.signature;
+        if signature != *b"RSD PTR " {
+            return Err(AcpiError::RsdpSignature(signature));
+        }
+        let old_bytes =
+            core::slice::from_raw_parts(old as *const _ as *const u8, mem::size_of::<OldRsdp>());
+        if byte_sum(old_bytes) != 0 {
+            return Err(AcpiError::RsdpChecksum);
+        }
+
+        let xsdt = rsdp.xsdt as u64;
+        let rsdt = old.rsdt_address as u64;
+        if old.revision >= 2 && xsdt != 0 && rsdp.verify_checksum() {
+            match Self::from_root(xsdt, *b"XSDT", phys_offset) {
+                Ok(x) => return Ok(x),
+                Err(e) if rsdt == 0 => return Err(e),
+                Err(_) => {}
+            }
+        }
+        if rsdt == 0 {
+            return Err(AcpiError::NoRootTable);
+        }
+        return Self::from_root(rsdt, *b"RSDT", phys_offset);
+    }
+
+    unsafe fn from_root(
+        phys: u64,
+        signature: [u8; 4],
+        phys_offset: u64,
+    ) -> Result<Self, AcpiError> {
+        let root = &*(phys.wrapping_add(phys_offset) as usize as *const SdtHeader);
+        if root.signature() != signature {
+            return Err(AcpiError::RootSignature(root.signature()));
+        }
+        let root = match checked_table(phys, phys_offset) {
+            Ok(x) => x,
+            Err(SkipReason::Length(length)) => return Err(AcpiError::RootLength(length)),
+            Err(SkipReason::Checksum { checksum, sum }) => {
+                return Err(AcpiError::RootChecksum { checksum, sum })
+            }
+        };
+
+        let mut this = Self {
+            phys_offset,
+            root: signature,
+            tables: [TableRef {
+                signature: [0; 4],
+                phys: 0,
+                length: 0,
+            }; MAX_TABLES],
+            table_count: 0,
+            skipped: [SkippedTable {
+                signature: [0; 4],
+                phys: 0,
+                reason: SkipReason::Length(0),
+            }; MAX_SKIPPED],
+            skipped_count: 0,
+            dropped: 0,
+        };
+
+        /* XSDT entries are 8 bytes, RSDT ones 4, both only 4 aligned */
+        let entry_size = if signature == *b"XSDT" { 8 } else { 4 };
+        let entries = (root.length as usize - mem::size_of::<SdtHeader>()) / entry_size;
+        let first = (root as *const SdtHeader).add(1) as *const u8;
+        for i in 0..entries {
+            let entry = first.add(i * entry_size);
+            let phys = match entry_size {
+                8 => ptr::read_unaligned(entry as *const u64),
+                _ => ptr::read_unaligned(entry as *const u32) as u64,
+            };
+            if phys == 0 {
+                continue;
+            }
+            this.add(phys);
+        }
+
+        return Ok(this);
+    }
+
+    unsafe fn add(&mut self, phys: u64) {
+        let signature =
+            (*(phys.wrapping_add(self.phys_offset) as usize as *const SdtHeader)).signature();
+        match checked_table(phys, self.phys_offset) {
+            Ok(sdt) if self.table_count < MAX_TABLES => {
+                self.tables[self.table_count] = TableRef {
+                    signature,
+                    phys,
+                    length: sdt.length,
+                };
+                self.table_count += 1;
+            }
+            Ok(_) => self.dropped += 1,
+            Err(reason) if self.skipped_count < MAX_SKIPPED => {
+                self.skipped[self.skipped_count] = SkippedTable {
+                    signature,
+                    phys,
+                    reason,
+                };
+                self.skipped_count += 1;
+            }
+            Err(_) => self.dropped += 1,
+        }
+    }
+
+    /// `XSDT` or `RSDT`, whichever the tables came from
+    pub fn root(&self) -> [u8; 4] {
+        self.root
+    }
+
+    /// Whole first valid table with `signature`, header included
+    pub fn table(&self, signature: [u8; 4]) -> Option<&[u8]> {
+        let table = self.tables[..self.table_count]
+            .iter()
+            .find(|x| x.signature == signature)?;
+        let addr = table.phys.wrapping_add(self.phys_offset) as usize as *const u8;
+        /* SAFETY: readable as promised to `discover`, and validated there */
+        return Some(unsafe { core::slice::from_raw_parts(addr, table.length as usize) });
+    }
+
+    /// Physical address of the first valid table with `signature`
+    pub fn table_phys(&self, signature: [u8; 4]) -> Option<u64> {
+        let table = self.tables[..self.table_count]
+            .iter()
+            .find(|x| x.signature == signature)?;
+        return Some(table.phys);
+    }
+
+    /// Tables left out because they are corrupt
+    pub fn skipped(&self) -> &[SkippedTable] {
+        &self.skipped[..self.skipped_count]
+    }
+
+    /// Tables that didn't fit into `MAX_TABLES` or `MAX_SKIPPED`
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+/* MADT entry types and the flags that make a CPU usable */
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_LOCAL_X2APIC: u8 = 9;
+const MADT_CPU_ENABLED: u32 = 1 << 0;
+const MADT_CPU_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// Number of CPUs the MADT lists as enabled or online capable,
+/// `None` without a MADT or if its entries are malformed
+pub fn madt_cpu_count(acpi: &AcpiContext) -> Option<usize> {
+    /* Local APIC address and flags follow the header */
+    const ENTRIES_OFFSET: usize = mem::size_of::<SdtHeader>() + 8;
+
+    let bytes = acpi.table(*b"APIC")?;
+    let mut count = 0;
+    let mut entries = Reader::new(bytes.get(ENTRIES_OFFSET..)?);
+    while let [typ, len, ..] = *entries.rest() {
+        if len < 2 {
+            return None;
+        }
+        let entry = Reader::new(entries.take(len as usize).ok()?);
+        let flags_at = match typ {
+            MADT_LOCAL_APIC => Some(4),
+            MADT_LOCAL_X2APIC => Some(8),
+            _ => None,
+        };
+        if let Some(at) = flags_at {
+            let flags = entry.u32_le_at(at).ok()?;
+            if flags & (MADT_CPU_ENABLED | MADT_CPU_ONLINE_CAPABLE) != 0 {
+                count += 1;
+            }
+        }
+    }
+
+    return Some(count);
+}
+
+/// Address space of a Generic Address Structure
+pub const GAS_SYSTEM_MEMORY: u8 = 0;
+pub const GAS_SYSTEM_IO: u8 = 1;
+pub const GAS_PCI_CONFIG: u8 = 2;
+
+/// Physical address of the HPET registers, `None` without an HPET table
+/// or if they aren't memory mapped
+pub fn hpet_address(acpi: &AcpiContext) -> Option<u64> {
+    /* Event timer block ID, then the base address GAS */
+    const BASE_OFFSET: usize = mem::size_of::<SdtHeader>() + 4;
+
+    let table = Reader::new(acpi.table(*b"HPET")?);
+    let space = table.u8_at(BASE_OFFSET).ok()?;
+    let addr = table.u64_le_at(BASE_OFFSET + 4).ok()?;
+    if space != GAS_SYSTEM_MEMORY || addr == 0 {
+        return None;
+    }
+    return Some(addr);
+}
+
+/// `value` written to `address` in `space` resets the machine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct ResetRegister {
+    /// One of the `GAS_*` address spaces
+    pub space: u8,
+    pub value: u8,
+    pub address: u64,
+}
+
+/* FADT offsets, the reset register is there since ACPI 2.0 */
+const FADT_FLAGS: usize = 112;
+const FADT_RESET_REG: usize = 116;
+const FADT_RESET_VALUE: usize = 128;
+const FADT_RESET_REG_SUP: u32 = 1 << 10;
+
+/// Reset register of the FADT, `None` without a FADT or if it doesn't
+/// claim support for it
+pub fn reset_register(acpi: &AcpiContext) -> Option<ResetRegister> {
+    let table = Reader::new(acpi.table(*b"FACP")?);
+    let flags = table.u32_le_at(FADT_FLAGS).ok()?;
+    let value = table.u8_at(FADT_RESET_VALUE).ok()?;
+    let space = table.u8_at(FADT_RESET_REG).ok()?;
+    let address = table.u64_le_at(FADT_RESET_REG + 4).ok()?;
+    if flags & FADT_RESET_REG_SUP == 0 || address == 0 {
+        return None;
+    }
+    if !matches!(space, GAS_SYSTEM_MEMORY | GAS_SYSTEM_IO | GAS_PCI_CONFIG) {
+        return None;
+    }
+    return Some(ResetRegister {
+        space,
+        value,
+        address,
+    });
+}
+
+/// Timestamps of the basic boot performance record, in nanoseconds since
+/// reset, 0 where firmware recorded none. Firmware fills in the exit boot
+/// services ones during ExitBootServices, so they are 0 before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct FirmwareBootPerformance {
+    /// Start of the firmware image, not necessarily 0
+    pub reset_end: u64,
+    /// LoadImage of the OS loader
+    pub loader_load_image: u64,
+    /// StartImage of the OS loader
+    pub loader_start_image: u64,
+    pub exit_boot_services_entry: u64,
+    pub exit_boot_services_exit: u64,
+}
+
+/* FPDT and FBPT record types, every record starts with type, length
+ * and revision */
+const FPDT_BOOT_POINTER: u16 = 0;
+const FBPT_BASIC_BOOT: u16 = 2;
+const FBPT_HEADER_SIZE: usize = 8;
+/* The basic boot record comes first, past that firmware only appends
+ * records nobody here reads */
+const FBPT_MAX_LEN: usize = 0x1_0000;
+
+/// Records of `bytes` as type and the whole record, stops at the first
+/// one that is shorter than its own header or longer than what's left
+fn performance_records(bytes: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut records = Reader::new(bytes);
+    core::iter::from_fn(move || {
+        let header = Reader::new(records.rest());
+        let typ = header.u16_le_at(0).ok()?;
+        let len = header.u8_at(2).ok()? as usize;
+        if len < 4 {
+            return None;
+        }
+        return Some((typ, records.take(len).ok()?));
+    })
+}
+
+/// Physical address of the FBPT from the FPDT's boot performance
+/// pointer record, `fpdt` is the whole table, header included
+pub fn fpdt_fbpt_address(fpdt: &[u8]) -> Option<u64> {
+    let records = fpdt.get(mem::size_of::<SdtHeader>()..)?;
+    let (_, record) = performance_records(records).find(|&(typ, _)| typ == FPDT_BOOT_POINTER)?;
+    return Reader::new(record).u64_le_at(8).ok().filter(|&x| x != 0);
+}
+
+/// Basic boot performance record of a whole FBPT, `None` without one
+pub fn parse_fbpt(fbpt: &[u8]) -> Option<FirmwareBootPerformance> {
+    let length = fbpt_length(fbpt.get(..FBPT_HEADER_SIZE)?)?;
+    return basic_boot_record(fbpt.get(FBPT_HEADER_SIZE..length)?);
+}
+
+/// Length of the FBPT starting with `header`, `None` if it isn't one
+fn fbpt_length(header: &[u8]) -> Option<usize> {
+    let header = Reader::new(header);
+    let length = header.u32_le_at(4).ok()? as usize;
+    if header.read_at(0, 4).ok()? != b"FBPT" || length < FBPT_HEADER_SIZE {
+        return None;
+    }
+    return Some(length);
+}
+
+fn basic_boot_record(records: &[u8]) -> Option<FirmwareBootPerformance> {
+    let (_, record) = performance_records(records).find(|&(typ, _)| typ == FBPT_BASIC_BOOT)?;
+    let record = Reader::new(record);
+    return Some(FirmwareBootPerformance {
+        reset_end: record.u64_le_at(8).ok()?,
+        loader_load_image: record.u64_le_at(16).ok()?,
+        loader_start_image: record.u64_le_at(24).ok()?,
+        exit_boot_services_entry: record.u64_le_at(32).ok()?,
+        exit_boot_services_exit: record.u64_le_at(40).ok()?,
+    });
+}
+
+/// Physical address of the FBPT, `None` without a FPDT pointing to one
+pub fn fbpt_address(acpi: &AcpiContext) -> Option<u64> {
+    return fpdt_fbpt_address(acpi.table(*b"FPDT")?);
+}
+
+/// Record of the FBPT the FPDT points to, `None` without a FPDT or if
+/// the FBPT is malformed. Only the first `FBPT_MAX_LEN` bytes are read.
+pub fn firmware_boot_performance(acpi: &AcpiContext) -> Option<FirmwareBootPerformance> {
+    let addr = fbpt_address(acpi)?.wrapping_add(acpi.phys_offset) as usize as *const u8;
+    /* SAFETY: readable as promised to `discover`, the FBPT is in memory
+     * firmware reserved for it like the tables */
+    let header = unsafe { core::slice::from_raw_parts(addr, FBPT_HEADER_SIZE) };
+    let length = core::cmp::min(fbpt_length(header)?, FBPT_MAX_LEN);
+    let fbpt = unsafe { core::slice::from_raw_parts(addr, length) };
+    return basic_boot_record(&fbpt[FBPT_HEADER_SIZE..]);
+}
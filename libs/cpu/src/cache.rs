@@ -0,0 +1,138 @@
+//! Cache control for memory that something other than the writing core
+//! reads next: code executed through another mapping or by another
+//! processor, and data the microcode sequencer fetches on its own.
+//!
+//! Caches are coherent between processors on x86, but cross-modified code
+//! is only guaranteed to be seen after the writer's stores are globally
+//! visible and the executing side serialized, and some update paths read
+//! memory behind the caches. `flush_range` followed by `mfence` covers the
+//! writer's half, the executing side serializes with its far jump, `mov
+//! cr3` or `cpuid`.
+
+use crate::phys::PhysMapping;
+use crate::{cpuid, PhysAddr, PhysRange};
+
+/// Line size to assume if CPUID doesn't report one
+pub const DEFAULT_LINE_SIZE: u64 = 64;
+
+/// Orders every load and store before it with every one after it
+#[inline(always)]
+pub fn mfence() {
+    unsafe {
+        asm!("mfence", options(nostack, preserves_flags));
+    }
+}
+
+/// Orders stores, needed after `clflushopt` and non-temporal or
+/// write-combining stores
+#[inline(always)]
+pub fn sfence() {
+    unsafe {
+        asm!("sfence", options(nostack, preserves_flags));
+    }
+}
+
+/// Orders loads, and keeps later instructions from starting early
+#[inline(always)]
+pub fn lfence() {
+    unsafe {
+        asm!("lfence", options(nostack, preserves_flags));
+    }
+}
+
+/// Writes back and invalidates every cache of this processor.
+/// Takes milliseconds on large caches with interrupts held off the
+/// whole time, prefer `flush_range` when the memory is known.
+///
+/// # Safety
+/// Ring 0 only, it raises #GP elsewhere.
+#[cfg(feature = "ringzero")]
+#[inline(always)]
+pub unsafe fn wbinvd() {
+    asm!("wbinvd", options(nostack, preserves_flags));
+}
+
+/// Writes back and invalidates the line containing `addr`. Ordered with
+/// stores, so no fence is needed before it.
+///
+/// # Safety
+/// `addr` must be mapped, otherwise this faults like a load.
+#[inline(always)]
+pub unsafe fn clflush(addr: *const u8) {
+    asm!("clflush [{}]", in(reg) addr, options(nostack, preserves_flags));
+}
+
+/// `clflush` that is only ordered by fences, flushes of a range can
+/// overlap. Needs `sfence` or `mfence` before the data is relied on.
+///
+/// # Safety
+/// `addr` must be mapped and the processor must have `clflushopt`.
+#[inline(always)]
+pub unsafe fn clflushopt(addr: *const u8) {
+    asm!("clflushopt [{}]", in(reg) addr, options(nostack, preserves_flags));
+}
+
+/// Instruction `flush_range` uses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushMethod {
+    Clflushopt,
+    /// Always there in long mode, SSE2 implies it
+    Clflush,
+}
+
+pub fn has_clflushopt() -> bool {
+    cpuid(0, 0).eax >= 7 && (cpuid(7, 0).ebx >> 23) & 1 == 1
+}
+
+pub fn flush_method() -> FlushMethod {
+    if has_clflushopt() {
+        FlushMethod::Clflushopt
+    } else {
+        FlushMethod::Clflush
+    }
+}
+
+/// Bytes flushed by one `clflush`, from CPUID leaf 1
+pub fn line_size() -> u64 {
+    match (cpuid(1, 0).ebx >> 8) & 0xFF {
+        0 => DEFAULT_LINE_SIZE,
+        x => x as u64 * 8,
+    }
+}
+
+/// Start of every `line_size` line touched by `start..start+len`,
+/// `line_size` must be a power of two
+pub fn lines(start: u64, len: u64, line_size: u64) -> impl Iterator<Item = u64> {
+    let first = start & !(line_size - 1);
+    let count = match len {
+        0 => 0,
+        _ => (start + len - 1) / line_size - start / line_size + 1,
+    };
+    (0..count).map(move |i| first + i * line_size)
+}
+
+/// Writes back and invalidates `range`, accessed through `mapping`, then
+/// waits for it with `mfence`. Returns the number of lines flushed.
+///
+/// # Safety
+/// All of `range` must be mapped through `mapping`.
+pub unsafe fn flush_range(range: PhysRange, mapping: &impl PhysMapping) -> u64 {
+    if range.is_empty() {
+        return 0;
+    }
+
+    /* SAFETY: `PhysRange` only holds physical addresses */
+    let start = PhysAddr::<u8>::new_unchecked(range.start());
+    let start = mapping.phys_to_virt(start).as_u64();
+    let method = flush_method();
+    let mut count = 0;
+    for line in lines(start, range.len(), line_size()) {
+        match method {
+            FlushMethod::Clflushopt => clflushopt(line as *const u8),
+            FlushMethod::Clflush => clflush(line as *const u8),
+        }
+        count += 1;
+    }
+    mfence();
+    return count;
+}
@@ -0,0 +1,58 @@
+use crate::{cpuid, impl_bits};
+
+/// Processor features the loader configures, from CPUID
+#[derive(PartialEq, Eq)]
+#[repr(transparent)]
+pub struct CpuInfo(u32);
+
+impl_bits! {
+    CpuInfo = {
+        /// CPUID.(EAX=07H,ECX=0H):EBX.SMEP
+        smep = 0,
+        /// CPUID.(EAX=07H,ECX=0H):EBX.SMAP
+        smap = 1,
+        /// CPUID.(EAX=07H,ECX=0H):ECX.UMIP
+        umip = 2,
+        /// CPUID.80000001H:EDX.NX
+        nx = 3,
+        /// IA32_MISC_ENABLE exists, on Intel family 6 and later
+        misc_enable = 4,
+    }
+}
+
+impl CpuInfo {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub fn detect() -> Self {
+        let mut info = Self::new();
+
+        let max = cpuid(0, 0);
+        if max.eax >= 7 {
+            let leaf = cpuid(7, 0);
+            if (leaf.ebx >> 7) & 1 == 1 {
+                info = info.set_smep();
+            }
+            if (leaf.ebx >> 20) & 1 == 1 {
+                info = info.set_smap();
+            }
+            if (leaf.ecx >> 2) & 1 == 1 {
+                info = info.set_umip();
+            }
+        }
+
+        if cpuid(0x8000_0000, 0).eax >= 0x8000_0001 && (cpuid(0x8000_0001, 0).edx >> 20) & 1 == 1 {
+            info = info.set_nx();
+        }
+
+        /* "GenuineIntel" */
+        let intel = max.ebx == 0x756e_6547 && max.edx == 0x4965_6e69 && max.ecx == 0x6c65_746e;
+        let family = (cpuid(1, 0).eax >> 8) & 0xF;
+        if intel && family >= 6 {
+            info = info.set_misc_enable();
+        }
+
+        return info;
+    }
+}
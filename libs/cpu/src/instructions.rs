@@ -53,3 +53,167 @@ pub unsafe fn bsf(mut x: u64) -> u8 {
 
     return x as u8;
 }
+
+#[derive(Clone, Copy, Debug)]
+pub struct CpuidResult {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+#[inline(always)]
+pub fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    let eax: u32;
+    let ebx: u64;
+    let ecx: u32;
+    let edx: u32;
+
+    /* LLVM uses rbx internally, so it can't be an operand */
+    unsafe {
+        asm!(
+            "mov {0:r}, rbx",
+            "cpuid",
+            "xchg {0:r}, rbx",
+            out(reg) ebx,
+            inout("eax") leaf => eax,
+            inout("ecx") subleaf => ecx,
+            out("edx") edx,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+
+    return CpuidResult {
+        eax,
+        ebx: ebx as u32,
+        ecx,
+        edx,
+    };
+}
+
+/// Reads the time stamp counter
+#[inline(always)]
+pub fn rdtsc() -> u64 {
+    let lower: u32;
+    let upper: u32;
+
+    unsafe {
+        asm!(
+            "rdtsc",
+            out("eax") lower,
+            out("edx") upper,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+
+    return (upper as u64) << 32 | lower as u64;
+}
+
+/// Reads performance monitoring counter `counter`, bit 30 selects
+/// fixed-function counters.
+///
+/// # Safety
+/// * The counter must exist and, outside of ring 0, CR4.PCE must be set,
+/// otherwise #GP is raised.
+#[inline(always)]
+pub unsafe fn rdpmc(counter: u32) -> u64 {
+    let lower: u32;
+    let upper: u32;
+
+    asm!(
+        "rdpmc",
+        in("ecx") counter,
+        out("eax") lower,
+        out("edx") upper,
+        options(nomem, nostack, preserves_flags),
+    );
+
+    return (upper as u64) << 32 | lower as u64;
+}
+
+/// CPUID.01H:ECX.RDRAND
+pub fn has_rdrand() -> bool {
+    (cpuid(1, 0).ecx >> 30) & 1 == 1
+}
+
+/// CPUID.(EAX=07H,ECX=0H):EBX.RDSEED
+pub fn has_rdseed() -> bool {
+    cpuid(0, 0).eax >= 7 && (cpuid(7, 0).ebx >> 18) & 1 == 1
+}
+
+/// Physical address width (MAXPHYADDR) from CPUID.80000008H:EAX[7:0],
+/// 36 on processors without that leaf, as the SDM prescribes
+pub fn phys_addr_bits() -> u8 {
+    if cpuid(0x8000_0000, 0).eax < 0x8000_0008 {
+        return 36;
+    }
+    return cpuid(0x8000_0008, 0).eax as u8;
+}
+
+/// Reads a random number from the DRBG, `None` if none was ready (CF=0),
+/// which may happen transiently, so callers should retry a few times.
+///
+/// # Safety
+/// * CPU must support it, see `has_rdrand`, otherwise #UD is raised.
+#[inline(always)]
+pub unsafe fn rdrand() -> Option<u64> {
+    let value: u64;
+    let ok: u8;
+
+    asm!(
+        "rdrand {}",
+        "setc {}",
+        out(reg) value,
+        out(reg_byte) ok,
+        options(nomem, nostack),
+    );
+
+    return if ok == 1 { Some(value) } else { None };
+}
+
+/// Reads a random number straight from the entropy source, `None` if
+/// none was ready (CF=0), which happens a lot more often than with `rdrand`.
+///
+/// # Safety
+/// * CPU must support it, see `has_rdseed`, otherwise #UD is raised.
+#[inline(always)]
+pub unsafe fn rdseed() -> Option<u64> {
+    let value: u64;
+    let ok: u8;
+
+    asm!(
+        "rdseed {}",
+        "setc {}",
+        out(reg) value,
+        out(reg_byte) ok,
+        options(nomem, nostack),
+    );
+
+    return if ok == 1 { Some(value) } else { None };
+}
+
+/// # Safety
+/// Reading some ports has side effects on the device behind them.
+#[inline(always)]
+pub unsafe fn inb(port: u16) -> u8 {
+    let x: u8;
+    asm!(
+        "in al, dx",
+        out("al") x,
+        in("dx") port,
+        options(nomem, nostack, preserves_flags)
+    );
+    return x;
+}
+
+/// # Safety
+/// Writes to an arbitrary device, see `inb`.
+#[inline(always)]
+pub unsafe fn outb(port: u16, x: u8) {
+    asm!(
+        "out dx, al",
+        in("dx") port,
+        in("al") x,
+        options(nomem, nostack, preserves_flags)
+    );
+}
@@ -1,4 +1,6 @@
 //use crate::VirtAddr;
+use crate::impl_bits;
+use impl_bits::fmt::Addr;
 
 #[derive(Clone, Copy)]
 #[repr(transparent)]
@@ -102,6 +104,68 @@ impl TableRegister {
     }
 }
 
+/// Frame the CPU pushes when delivering an interrupt or exception
+/// in 64-bit mode.
+///
+/// First RSP is aligned down to 16 bytes, then SS, RSP, RFLAGS, CS and RIP
+/// are pushed in that order, so RIP ends up at the lowest address.
+/// Exceptions with an error code (#DF, #TS, #NP, #SS, #GP, #PF, #AC, ...)
+/// then push it below RIP - the handler sees RSP pointing to the error code
+/// and 8 bytes off 16-byte alignment. Otherwise RSP points to RIP and is
+/// 16-byte aligned.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct InterruptStackFrame {
+    pub rip: u64,
+    /// Zero-extended to 64 bits
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    /// Zero-extended to 64 bits
+    pub ss: u64,
+}
+
+impl core::fmt::Debug for InterruptStackFrame {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("InterruptStackFrame")
+            .field("rip", &Addr(self.rip))
+            .field("cs", &format_args!("{:#x}", self.cs))
+            .field("rflags", &format_args!("{:#x}", self.rflags))
+            .field("rsp", &Addr(self.rsp))
+            .field("ss", &format_args!("{:#x}", self.ss))
+            .finish()
+    }
+}
+
+/// Error code pushed by a page fault, the faulting address is in CR2
+#[derive(PartialEq, Eq)]
+#[repr(transparent)]
+pub struct PageFaultErrorCode(pub u64);
+
+impl_bits!(PageFaultErrorCode = {
+    /// Set if caused by a protection violation, clear if the page was not present
+    present = 0,
+    /// Set if caused by a write
+    write = 1,
+    /// Set if caused by an access in ring 3
+    user = 2,
+    /// Reserved bit set in a paging structure entry
+    reserved_write = 3,
+    instruction_fetch = 4,
+    protection_key = 5,
+    shadow_stack = 6,
+    sgx = 15,
+});
+
+/// Stack of a page fault handler on entry, the error code
+/// is right below the interrupt frame
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PageFaultStackFrame {
+    pub error_code: PageFaultErrorCode,
+    pub frame: InterruptStackFrame,
+}
+
 #[repr(C)]
 pub struct SavedRegisters {
     /* 15 registers */
@@ -135,6 +199,12 @@ pub struct Stack {
 }
 
 impl Stack {
+    /// The part pushed by the CPU
+    pub fn frame(&self) -> &InterruptStackFrame {
+        let ptr = &self.instruction_pointer as *const u64 as *const InterruptStackFrame;
+        unsafe { &*ptr }
+    }
+
     pub fn error_code(&self) -> Option<u64> {
         if self.has_error_code == 0 {
             None
@@ -220,6 +290,8 @@ pub macro make_handler($fnname:ident) {{
                 pop r14
                 pop r15
     
+                /* has_error_code and the error code, or two zeroes */
+                add rsp, 16
                 iretq",
                 sym $fnname,
                 options(noreturn),
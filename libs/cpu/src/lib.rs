@@ -13,20 +13,36 @@
 #![allow(unused_parens)]
 #![allow(unused_unsafe)]
 
-use impl_bits::impl_bits;
+use impl_bits::{debug_enum, impl_bits};
 
 #[macro_use]
 mod macros;
 
 pub mod acpi;
+pub mod cache;
 pub mod interrupt;
+pub mod mapper;
+pub mod microcode;
 pub mod paging;
+#[cfg(feature = "ringzero")]
+pub mod perf;
+pub mod phys;
 pub mod segmentation;
+#[cfg(feature = "ringzero")]
+pub mod simd;
+#[cfg(feature = "ringzero")]
+pub mod syscall;
 
+pub use interrupt::{InterruptStackFrame, PageFaultErrorCode, PageFaultStackFrame};
+
+mod cpuinfo;
+pub use cpuinfo::*;
 mod instructions;
 pub use instructions::*;
 mod physaddr;
 pub use physaddr::*;
+mod range;
+pub use range::*;
 mod virtaddr;
 pub use virtaddr::*;
 
@@ -35,12 +51,14 @@ mod ringzero;
 #[cfg(feature = "ringzero")]
 pub use ringzero::*;
 
-#[derive(Clone, Copy)]
-pub enum Ring {
-    Zero = 0,
-    One = 1,
-    Two = 2,
-    Three = 3,
+debug_enum! {
+    #[derive(Clone, Copy)]
+    pub enum Ring {
+        Zero = 0,
+        One = 1,
+        Two = 2,
+        Three = 3,
+    }
 }
 
 #[repr(transparent)]
@@ -70,6 +88,23 @@ impl Eflags {
     pub const fn new() -> Self {
         Self(2u32)
     }
+
+    /// Current RFLAGS, readable in any ring
+    pub fn get() -> Self {
+        let flags: u64;
+
+        unsafe {
+            asm!(
+                "pushfq",
+                "pop {}",
+                out(reg) flags,
+                options(nomem, preserves_flags),
+            );
+        }
+
+        Self(flags as u32)
+    }
+
     pub fn io_privilege(self) -> Ring {
         match (self.0 >> 12) & 0b11 {
             0 => Ring::Zero,
@@ -1,6 +1,6 @@
 macro_rules! impl_pagelevel {
     {
-        pub struct $structname:ident,
+        pub enum $level:ident: $entryname:ident, huge_pages = $huge:expr,
         pub struct $flagsname:ident = {$(
             $(#[$attr:meta])*
             $fname:ident = $bit:expr,
@@ -9,6 +9,12 @@ macro_rules! impl_pagelevel {
         #[repr(transparent)]
         pub struct $flagsname(u64);
 
+        impl $flagsname {
+            pub const fn new() -> Self {
+                Self(0)
+            }
+        }
+
         $crate::impl_bits! {
             $flagsname = {
                 $(
@@ -27,32 +33,14 @@ macro_rules! impl_pagelevel {
             }
         }
 
-        #[repr(transparent)]
-        pub struct $structname(::core::cell::Cell<u64>);
-
-        //impl ::core::marker::Copy for $structname {}
-        impl ::core::clone::Clone for $structname {
-            fn clone(&self) -> Self {
-                Self(::core::cell::Cell::new(self.0.get()))
-            }
-        }
+        pub enum $level {}
 
-        impl $structname {
-            pub fn new(addr: PhysAddr, flags: $flagsname) -> Self {
-                Self(::core::cell::Cell::new(flags.as_u64() | addr.as_u64()))
-            }
-        }
-        impl Bits for $structname {
-            fn as_u64(&self) -> u64 {
-                self.0.get()
-            }
-            unsafe fn from_u64_unchecked(x: u64) -> Self {
-                Self(::core::cell::Cell::new(x))
-            }
-        }
-        impl Entry for $structname {
+        impl Level for $level {
             type Flags = $flagsname;
-            const ZEROED: Self = Self(::core::cell::Cell::new(0));
+            const NAME: &'static str = stringify!($entryname);
+            const HUGE_PAGES: bool = $huge;
         }
+
+        pub type $entryname = RawEntry<$level>;
     }
 }
@@ -0,0 +1,211 @@
+//! Maps megapages into 4-level page tables, allocating the tables in
+//! between on the way. Tables are followed as physical addresses, so they
+//! must be identity-mapped, like in the loader.
+//!
+//! `map_2m` walks from the PML4 for every megapage. `map_range_2m` is the
+//! fast path for large ranges like a direct map: one walk per 1GiB chunk,
+//! then a tight loop over the entries of that chunk's PD.
+
+use crate::paging::{Bits, Entry, PDEntry, PDFlags, PDPEntry, PDPFlags, PML4Entry, PML4Flags};
+use crate::paging::{Table, ENTRIES_PER_TABLE, MEGAPAGE_SIZE};
+use crate::{PhysAddr, PhysRange};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapError {
+    /// Addresses aren't 2MiB aligned
+    Misaligned,
+    /// The range wraps around, isn't canonical or is past physical
+    /// addresses
+    OutOfRange,
+    /// Something is already mapped there, or a gigapage is in the way
+    Conflict,
+    /// The `TableAlloc` ran out
+    OutOfTables,
+}
+
+/// Source of zeroed, identity-mapped 4KiB pages for page tables
+pub trait TableAlloc {
+    fn alloc_table(&mut self) -> Option<PhysAddr>;
+}
+
+/// What mapping took so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MapStats {
+    /// Walks from the PML4 down to a PD
+    pub walks: u64,
+    pub tables_allocated: u64,
+    /// Megapage entries written
+    pub entries: u64,
+}
+
+/* Bit 7 in PDP entries, gigapage */
+const LEAF: u64 = 1 << 7;
+
+const fn table_index(virt: u64, level: u32) -> usize {
+    ((virt >> (12 + 9 * level)) as usize) % ENTRIES_PER_TABLE
+}
+
+/// `virt..virt+len` is in one canonical half and doesn't wrap around
+fn is_canonical(virt: u64, len: u64) -> bool {
+    const LOWER_END: u64 = 1 << 47;
+    const UPPER_START: u64 = !(LOWER_END - 1);
+
+    let end = match virt.checked_add(len) {
+        Some(x) => x,
+        /* Ending exactly at the top of the address space is fine */
+        None => return virt.wrapping_add(len) == 0 && virt >= UPPER_START,
+    };
+    return end <= LOWER_END || virt >= UPPER_START;
+}
+
+pub struct Mapper<'a, A: TableAlloc> {
+    root: &'a mut Table<PML4Entry>,
+    alloc: A,
+    pub stats: MapStats,
+}
+
+impl<'a, A: TableAlloc> Mapper<'a, A> {
+    /// # Safety
+    /// Tables reachable from `root` and tables from `alloc` must be
+    /// identity-mapped and not used through other references meanwhile.
+    pub unsafe fn new(root: &'a mut Table<PML4Entry>, alloc: A) -> Self {
+        Self {
+            root,
+            alloc,
+            stats: MapStats::default(),
+        }
+    }
+
+    pub fn root(&self) -> &Table<PML4Entry> {
+        self.root
+    }
+
+    pub fn into_alloc(self) -> A {
+        self.alloc
+    }
+
+    fn alloc_table(&mut self) -> Result<PhysAddr, MapError> {
+        let table = self.alloc.alloc_table().ok_or(MapError::OutOfTables)?;
+        self.stats.tables_allocated += 1;
+        return Ok(table);
+    }
+
+    /// The PD covering `virt`. Missing tables are allocated if `allocate`,
+    /// otherwise there is no PD.
+    unsafe fn pd(
+        &mut self,
+        virt: u64,
+        allocate: bool,
+    ) -> Result<Option<*mut Table<PDEntry>>, MapError> {
+        self.stats.walks += 1;
+
+        let i4 = table_index(virt, 3);
+        if !self.root[i4].is_present() {
+            if !allocate {
+                return Ok(None);
+            }
+            let table = self.alloc_table()?;
+            let flags = PML4Flags::new().set_present().set_writable();
+            self.root[i4] = PML4Entry::new(table, flags);
+        }
+        let pdp = &mut *(self.root[i4].raw_addr().as_u64() as usize as *mut Table<PDPEntry>);
+
+        let i3 = table_index(virt, 2);
+        if pdp[i3].as_u64() & LEAF != 0 {
+            return Err(MapError::Conflict);
+        }
+        if !pdp[i3].is_present() {
+            if !allocate {
+                return Ok(None);
+            }
+            let table = self.alloc_table()?;
+            let flags = PDPFlags::new().set_present().set_writable();
+            pdp[i3] = PDPEntry::new(table, flags);
+        }
+
+        let pd = pdp[i3].raw_addr().as_u64() as usize as *mut Table<PDEntry>;
+        return Ok(Some(pd));
+    }
+
+    /// Maps one megapage, `flags` get the leaf bit
+    pub fn map_2m(&mut self, virt: u64, phys: u64, flags: PDFlags) -> Result<(), MapError> {
+        if virt % MEGAPAGE_SIZE != 0 || phys % MEGAPAGE_SIZE != 0 {
+            return Err(MapError::Misaligned);
+        }
+        if !is_canonical(virt, MEGAPAGE_SIZE) || PhysRange::new(phys, MEGAPAGE_SIZE).is_none() {
+            return Err(MapError::OutOfRange);
+        }
+
+        unsafe {
+            let pd = &mut *self.pd(virt, true)?.unwrap();
+            let pde = &mut pd[table_index(virt, 1)];
+            if pde.is_present() {
+                return Err(MapError::Conflict);
+            }
+            *pde = PDEntry::new(PhysAddr::new_unchecked(phys), flags.set_leaf());
+        }
+        self.stats.entries += 1;
+        return Ok(());
+    }
+
+    /// Maps `count` megapages from `phys_start` at `virt_start`, `flags` get
+    /// the leaf bit. Nothing is mapped on a conflict, running out of tables
+    /// leaves the chunks mapped so far.
+    pub fn map_range_2m(
+        &mut self,
+        virt_start: u64,
+        phys_start: u64,
+        count: u64,
+        flags: PDFlags,
+    ) -> Result<(), MapError> {
+        /* Everything that doesn't depend on the tables is checked once */
+        if virt_start % MEGAPAGE_SIZE != 0 || phys_start % MEGAPAGE_SIZE != 0 {
+            return Err(MapError::Misaligned);
+        }
+        let len = count
+            .checked_mul(MEGAPAGE_SIZE)
+            .ok_or(MapError::OutOfRange)?;
+        if !is_canonical(virt_start, len) || PhysRange::new(phys_start, len).is_none() {
+            return Err(MapError::OutOfRange);
+        }
+        let leaf_flags = flags.set_leaf().as_u64();
+
+        /* Up to 512 megapages per chunk, the first one may start mid-PD */
+        let chunks = |mut virt: u64, mut left: u64| {
+            core::iter::from_fn(move || {
+                if left == 0 {
+                    return None;
+                }
+                let first = table_index(virt, 1);
+                let n = left.min((ENTRIES_PER_TABLE - first) as u64);
+                let chunk = (virt, first, n as usize);
+                virt = virt.wrapping_add(n * MEGAPAGE_SIZE);
+                left -= n;
+                return Some(chunk);
+            })
+        };
+
+        unsafe {
+            for (virt, first, n) in chunks(virt_start, count) {
+                if let Some(pd) = self.pd(virt, false)? {
+                    let pd = &*pd;
+                    if pd.0[first..first + n].iter().any(|x| x.is_present()) {
+                        return Err(MapError::Conflict);
+                    }
+                }
+            }
+
+            let mut raw = phys_start | leaf_flags;
+            for (virt, first, n) in chunks(virt_start, count) {
+                let pd = &mut *self.pd(virt, true)?.unwrap();
+                for pde in &mut pd.0[first..first + n] {
+                    *pde = PDEntry::from_u64_unchecked(raw);
+                    raw += MEGAPAGE_SIZE;
+                }
+                self.stats.entries += n as u64;
+            }
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,318 @@
+//! Intel microcode updates, in the format of `intel-ucode` files: a 48-byte
+//! header, the encrypted update data and an optional extended signature
+//! table for other steppings the same update applies to.
+//!
+//! Parsing and matching are plain functions of the bytes, so a corrupt or
+//! foreign update is refused before anything touches an MSR.
+
+/// Size of `MicrocodeHeader` in the file
+pub const HEADER_SIZE: usize = 48;
+/// Data size of updates with `data_size` = 0
+pub const DEFAULT_DATA_SIZE: usize = 2000;
+/// IA32_PLATFORM_ID, bits 52:50 are the platform of the processor
+pub const IA32_PLATFORM_ID: u32 = 0x17;
+/// IA32_BIOS_UPDT_TRIG, written with the linear address of the update data
+pub const IA32_BIOS_UPDT_TRIG: u32 = 0x79;
+/// IA32_BIOS_SIGN_ID, the loaded revision is in the upper half
+pub const IA32_BIOS_SIGN_ID: u32 = 0x8B;
+
+const EXTENDED_HEADER_SIZE: usize = 20;
+const EXTENDED_SIGNATURE_SIZE: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MicrocodeError {
+    /// Shorter than its header or `total_size` says
+    Truncated,
+    /// Header version or loader revision isn't 1
+    UnsupportedFormat,
+    /// `total_size` isn't a multiple of 1KiB or can't hold the data
+    BadSize,
+    /// Dwords of the update don't sum up to 0
+    BadChecksum,
+    BadExtendedTable,
+    /// Update is for another processor
+    SignatureMismatch,
+    /// Processor already runs this or a newer revision
+    NotNewer {
+        current: u32,
+        update: u32,
+    },
+    /// Data isn't 16-byte aligned, which the trigger MSR requires
+    Misaligned,
+    /// Revision read back after the update isn't the update's
+    NotApplied {
+        expected: u32,
+        found: u32,
+    },
+}
+
+/// What identifies a processor to an update
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuSignature {
+    /// CPUID.01H:EAX
+    pub signature: u32,
+    /// IA32_PLATFORM_ID[52:50]
+    pub platform_id: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MicrocodeHeader {
+    pub revision: u32,
+    /// BCD, `0xMMDDYYYY`
+    pub date: u32,
+    pub signature: u32,
+    pub checksum: u32,
+    /// Bit mask of platform ids
+    pub processor_flags: u32,
+    pub data_size: usize,
+    pub total_size: usize,
+}
+
+/// A validated update
+#[derive(Clone, Copy, Debug)]
+pub struct IntelMicrocode<'a> {
+    pub header: MicrocodeHeader,
+    /// The whole update, `total_size` bytes
+    bytes: &'a [u8],
+}
+
+fn dword(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// `(data_size, total_size)` of the header at the start of `bytes`
+fn sizes(bytes: &[u8]) -> (usize, usize) {
+    match (dword(bytes, 28) as usize, dword(bytes, 32) as usize) {
+        (0, _) => (DEFAULT_DATA_SIZE, DEFAULT_DATA_SIZE + HEADER_SIZE),
+        (data, total) => (data, total),
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes
+        .chunks_exact(4)
+        .fold(0u32, |sum, x| sum.wrapping_add(dword(x, 0)))
+}
+
+impl<'a> IntelMicrocode<'a> {
+    /// Validates the header, the checksum of the whole update and of the
+    /// extended signature table, if there is one. Bytes after `total_size`
+    /// are ignored, they are usually the next update of the file.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, MicrocodeError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(MicrocodeError::Truncated);
+        }
+        if dword(bytes, 0) != 1 || dword(bytes, 20) != 1 {
+            return Err(MicrocodeError::UnsupportedFormat);
+        }
+
+        let (data_size, total_size) = sizes(bytes);
+        if total_size % 1024 != 0 || total_size < HEADER_SIZE + data_size || data_size % 4 != 0 {
+            return Err(MicrocodeError::BadSize);
+        }
+        let bytes = bytes.get(..total_size).ok_or(MicrocodeError::Truncated)?;
+        if checksum(bytes) != 0 {
+            return Err(MicrocodeError::BadChecksum);
+        }
+
+        let this = Self {
+            header: MicrocodeHeader {
+                revision: dword(bytes, 4),
+                date: dword(bytes, 8),
+                signature: dword(bytes, 12),
+                checksum: dword(bytes, 16),
+                processor_flags: dword(bytes, 24),
+                data_size,
+                total_size,
+            },
+            bytes,
+        };
+        this.check_extended_table()?;
+        return Ok(this);
+    }
+
+    fn extended_table(&self) -> Option<&'a [u8]> {
+        let table = &self.bytes[HEADER_SIZE + self.header.data_size..];
+        if table.is_empty() {
+            return None;
+        }
+        return Some(table);
+    }
+
+    fn check_extended_table(&self) -> Result<(), MicrocodeError> {
+        let table = match self.extended_table() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        if table.len() < EXTENDED_HEADER_SIZE {
+            return Err(MicrocodeError::BadExtendedTable);
+        }
+
+        let count = dword(table, 0) as usize;
+        let len = count
+            .checked_mul(EXTENDED_SIGNATURE_SIZE)
+            .and_then(|x| x.checked_add(EXTENDED_HEADER_SIZE))
+            .filter(|&x| x <= table.len())
+            .ok_or(MicrocodeError::BadExtendedTable)?;
+        if checksum(&table[..len]) != 0 {
+            return Err(MicrocodeError::BadExtendedTable);
+        }
+
+        /* Every signature's checksum stands for the whole update with it
+         * substituted into the header, so the three dwords sum the same */
+        let h = &self.header;
+        let sum = h
+            .signature
+            .wrapping_add(h.processor_flags)
+            .wrapping_add(h.checksum);
+        for entry in table[EXTENDED_HEADER_SIZE..len].chunks_exact(EXTENDED_SIGNATURE_SIZE) {
+            if checksum(entry) != sum {
+                return Err(MicrocodeError::BadExtendedTable);
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// `(signature, processor_flags)` of the header and the extended table
+    pub fn signatures(&self) -> impl Iterator<Item = (u32, u32)> + 'a {
+        let main = (self.header.signature, self.header.processor_flags);
+        let extended = match self.extended_table() {
+            Some(table) => {
+                let count = dword(table, 0) as usize;
+                let end = EXTENDED_HEADER_SIZE + count * EXTENDED_SIGNATURE_SIZE;
+                &table[EXTENDED_HEADER_SIZE..end]
+            }
+            None => &[],
+        };
+
+        core::iter::once(main).chain(
+            extended
+                .chunks_exact(EXTENDED_SIGNATURE_SIZE)
+                .map(|x| (dword(x, 0), dword(x, 4))),
+        )
+    }
+
+    /// The update is for `cpu`, signatures must be equal and the platform
+    /// id must be in the processor flags
+    pub fn matches(&self, cpu: CpuSignature) -> bool {
+        let platform = 1u32.checked_shl(cpu.platform_id as u32).unwrap_or(0);
+        self.signatures()
+            .any(|(signature, flags)| signature == cpu.signature && flags & platform != 0)
+    }
+
+    /// Checks that the update is for `cpu` and newer than `current`
+    pub fn check(&self, cpu: CpuSignature, current: u32) -> Result<(), MicrocodeError> {
+        if !self.matches(cpu) {
+            return Err(MicrocodeError::SignatureMismatch);
+        }
+        /* Revisions are signed, debug updates are negative */
+        if (self.header.revision as i32) <= (current as i32) {
+            return Err(MicrocodeError::NotNewer {
+                current,
+                update: self.header.revision,
+            });
+        }
+        return Ok(());
+    }
+
+    /// What the trigger MSR is given
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[HEADER_SIZE..HEADER_SIZE + self.header.data_size]
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+/// Updates of a file with several concatenated, e.g. `intel-ucode` bundles
+pub struct MicrocodeFile<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> MicrocodeFile<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+}
+
+impl<'a> Iterator for MicrocodeFile<'a> {
+    /// Offset of the update in the file and the update
+    type Item = (usize, Result<IntelMicrocode<'a>, MicrocodeError>);
+
+    /// A corrupt update is returned as an error and skipped if its size
+    /// is sane, the rest of the file can't be trusted otherwise
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let rest = self.bytes.get(offset..).filter(|x| !x.is_empty())?;
+
+        let result = IntelMicrocode::parse(rest);
+        self.offset = match result {
+            Ok(update) => offset + update.header.total_size,
+            Err(MicrocodeError::BadChecksum) | Err(MicrocodeError::BadExtendedTable) => {
+                /* The size was checked before the checksums */
+                offset + sizes(rest).1
+            }
+            Err(_) => self.bytes.len(),
+        };
+        return Some((offset, result));
+    }
+}
+
+#[cfg(feature = "ringzero")]
+impl CpuSignature {
+    pub fn current() -> Self {
+        let platform = unsafe { crate::rdmsr(IA32_PLATFORM_ID) };
+        Self {
+            signature: crate::cpuid(1, 0).eax,
+            platform_id: ((platform >> 50) & 0b111) as u8,
+        }
+    }
+}
+
+/// Revision of the loaded microcode, 0 if none was loaded since reset
+#[cfg(feature = "ringzero")]
+pub fn current_revision() -> u32 {
+    /* The documented sequence: clear, CPUID, read */
+    unsafe {
+        crate::wrmsr(IA32_BIOS_SIGN_ID, 0);
+        crate::cpuid(1, 0);
+        (crate::rdmsr(IA32_BIOS_SIGN_ID) >> 32) as u32
+    }
+}
+
+/// Checks `update` against this processor, loads it and reads the revision
+/// back. Returns the revisions before and after.
+///
+/// # Safety
+/// * Intel processors only, IA32_PLATFORM_ID doesn't exist elsewhere.
+/// * `update` must be identity-mapped, the MSR takes a linear address.
+/// * Other processors must not run while updating, the BSP is updated
+/// before any AP is started.
+#[cfg(feature = "ringzero")]
+pub unsafe fn apply(update: &IntelMicrocode) -> Result<(u32, u32), MicrocodeError> {
+    let old = current_revision();
+    update.check(CpuSignature::current(), old)?;
+
+    let data = update.data();
+    if data.as_ptr() as usize % 16 != 0 {
+        return Err(MicrocodeError::Misaligned);
+    }
+    /* The update is fetched by the sequencer, not through this core's loads,
+     * make sure it's in memory and not only in the caches */
+    if let Some(range) = crate::PhysRange::new(data.as_ptr() as u64, data.len() as u64) {
+        crate::cache::flush_range(range, &crate::phys::IdentityMapping);
+    }
+    crate::wrmsr(IA32_BIOS_UPDT_TRIG, data.as_ptr() as u64);
+
+    let new = current_revision();
+    if new != update.header.revision {
+        return Err(MicrocodeError::NotApplied {
+            expected: update.header.revision,
+            found: new,
+        });
+    }
+    return Ok((old, new));
+}
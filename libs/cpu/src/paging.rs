@@ -1,8 +1,14 @@
-use crate::PhysAddr;
+use crate::{PhysAddr, VirtAddr};
+use core::cell::Cell;
+use core::marker::PhantomData;
 
 const ADDR_MASK: u64 = ((1 << 40) - 1) << 12;
 const FLAGS_MASK: u64 = !ADDR_MASK;
 
+pub const PAGE_SIZE: u64 = 4096;
+pub const MEGAPAGE_SIZE: u64 = 2097152;
+pub const GIGAPAGE_SIZE: u64 = 1 << 30;
+
 #[repr(align(4096))]
 pub struct Page([u8; 4096]);
 #[repr(align(2097152))]
@@ -29,10 +35,68 @@ pub trait Entry: Bits {
         let flags = self.as_u64() & FLAGS_MASK;
         unsafe { Self::Flags::from_u64_unchecked(flags) }
     }
+    fn is_present(&self) -> bool {
+        self.as_u64() & 1 == 1
+    }
+}
+
+/// What differs between the levels of the paging structures, implemented
+/// by the marker types `impl_pagelevel!` declares
+pub trait Level {
+    type Flags: Bits + core::fmt::Debug;
+    /// Name of the entry type, for `Debug`
+    const NAME: &'static str;
+    /// Bit 7 makes an entry map a page instead of pointing at a table
+    const HUGE_PAGES: bool;
+}
+
+/// Entry of a paging structure at level `L`, `PTEntry` and the others
+/// are aliases of it
+#[repr(transparent)]
+pub struct RawEntry<L: Level>(Cell<u64>, PhantomData<L>);
+
+impl<L: Level> RawEntry<L> {
+    pub fn new(addr: PhysAddr, flags: L::Flags) -> Self {
+        Self(Cell::new(flags.as_u64() | addr.as_u64()), PhantomData)
+    }
+
+    /// Maps a megapage or a gigapage, never at levels without them
+    pub fn is_huge(&self) -> bool {
+        L::HUGE_PAGES && self.0.get() & (1 << 7) != 0
+    }
+}
+
+impl<L: Level> Clone for RawEntry<L> {
+    fn clone(&self) -> Self {
+        Self(Cell::new(self.0.get()), PhantomData)
+    }
+}
+
+impl<L: Level> core::fmt::Debug for RawEntry<L> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct(L::NAME)
+            .field("addr", &impl_bits::fmt::Addr(self.raw_addr().as_u64()))
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+impl<L: Level> Bits for RawEntry<L> {
+    fn as_u64(&self) -> u64 {
+        self.0.get()
+    }
+    unsafe fn from_u64_unchecked(x: u64) -> Self {
+        Self(Cell::new(x), PhantomData)
+    }
+}
+
+impl<L: Level> Entry for RawEntry<L> {
+    type Flags = L::Flags;
+    const ZEROED: Self = Self(Cell::new(0), PhantomData);
 }
 
 impl_pagelevel! {
-    pub struct PTEntry,
+    pub enum PT: PTEntry, huge_pages = false,
     pub struct PTFlags = {
         present = 0,
         writable = 1,
@@ -54,7 +118,7 @@ impl_pagelevel! {
 }
 
 impl_pagelevel! {
-    pub struct PDEntry,
+    pub enum PD: PDEntry, huge_pages = true,
     pub struct PDFlags = {
         present = 0,
         writable = 1,
@@ -76,7 +140,7 @@ impl_pagelevel! {
 }
 
 impl_pagelevel! {
-    pub struct PDPEntry,
+    pub enum PDP: PDPEntry, huge_pages = true,
     pub struct PDPFlags = {
         present = 0,
         writable = 1,
@@ -98,7 +162,7 @@ impl_pagelevel! {
 }
 
 impl_pagelevel! {
-    pub struct PML4Entry,
+    pub enum PML4: PML4Entry, huge_pages = false,
     pub struct PML4Flags = {
         present = 0,
         writable = 1,
@@ -142,3 +206,54 @@ impl<E: Entry> core::ops::IndexMut<usize> for Table<E> {
         &mut self.0[index]
     }
 }
+
+/// Index into the table at given level, where level 0 is the page table
+/// and level 3 is PML4
+const fn table_index(virt: u64, level: u32) -> usize {
+    ((virt >> (12 + 9 * level)) as usize) % ENTRIES_PER_TABLE
+}
+
+/// Walks the paging structures starting from `root` and returns the physical
+/// address `virt` is mapped to, or `None` if it is not mapped.
+/// Megapages (PD entries with `leaf` bit) and gigapages (PDP entries with
+/// bit 7, which only firmware creates) are supported.
+///
+/// # Safety
+/// * Every table reachable from `root` must be identity-mapped.
+pub unsafe fn translate(root: &Table<PML4Entry>, virt: VirtAddr) -> Option<PhysAddr> {
+    let virt = virt.as_u64();
+
+    let pml4e = &root[table_index(virt, 3)];
+    if !pml4e.is_present() {
+        return None;
+    }
+
+    let pdp = &*(pml4e.raw_addr().as_u64() as usize as *const Table<PDPEntry>);
+    let pdpe = &pdp[table_index(virt, 2)];
+    if !pdpe.is_present() {
+        return None;
+    }
+    /* We don't create gigapages, but firmware tables do */
+    if pdpe.is_huge() {
+        let base = pdpe.raw_addr().as_u64() & !(GIGAPAGE_SIZE - 1);
+        return PhysAddr::new(base + virt % GIGAPAGE_SIZE);
+    }
+
+    let pd = &*(pdpe.raw_addr().as_u64() as usize as *const Table<PDEntry>);
+    let pde = &pd[table_index(virt, 1)];
+    if !pde.is_present() {
+        return None;
+    }
+    if pde.is_huge() {
+        let base = pde.raw_addr().as_u64() & !(MEGAPAGE_SIZE - 1);
+        return PhysAddr::new(base + virt % MEGAPAGE_SIZE);
+    }
+
+    let pt = &*(pde.raw_addr().as_u64() as usize as *const Table<PTEntry>);
+    let pte = &pt[table_index(virt, 0)];
+    if !pte.is_present() {
+        return None;
+    }
+
+    return PhysAddr::new(pte.raw_addr().as_u64() + virt % PAGE_SIZE);
+}
@@ -0,0 +1,117 @@
+#![cfg(feature = "ringzero")]
+
+//! Performance monitoring counters, as configured by the firmware.
+//! Nothing is programmed here, counters that are not already counting
+//! are simply reported as unavailable.
+
+use crate::{cpuid, rdmsr, rdpmc, rdtsc, Cr4};
+
+const IA32_PERFEVTSEL0: u32 = 0x186;
+const IA32_FIXED_CTR_CTRL: u32 = 0x38d;
+const IA32_PERF_GLOBAL_CTRL: u32 = 0x38f;
+
+/* Architectural "LLC Misses" event */
+const LLC_MISSES_EVENT: u64 = 0x2e;
+const LLC_MISSES_UMASK: u64 = 0x41;
+const PERFEVTSEL_ENABLE: u64 = 1 << 22;
+
+/// Fixed counter 0 counts retired instructions
+const FIXED_INSTRUCTIONS: u32 = 1 << 30;
+const GP_COUNTER0: u32 = 0;
+
+/// Which counters can be read with `rdpmc`
+#[derive(Clone, Copy, Debug)]
+pub struct PerfCounters {
+    instructions: bool,
+    llc_misses: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PerfSnapshot {
+    pub tsc: u64,
+    pub instructions: Option<u64>,
+    pub llc_misses: Option<u64>,
+}
+
+impl PerfSnapshot {
+    /// Counters that went unavailable in either snapshot are `None`
+    pub fn delta(&self, earlier: &Self) -> Self {
+        let sub = |a: Option<u64>, b: Option<u64>| Some(a?.wrapping_sub(b?));
+        Self {
+            tsc: self.tsc.wrapping_sub(earlier.tsc),
+            instructions: sub(self.instructions, earlier.instructions),
+            llc_misses: sub(self.llc_misses, earlier.llc_misses),
+        }
+    }
+}
+
+impl PerfCounters {
+    /// Only the TSC
+    pub const fn none() -> Self {
+        Self {
+            instructions: false,
+            llc_misses: false,
+        }
+    }
+
+    /// Checks which counters are enabled (through CPUID leaf 0xA and the
+    /// control MSRs) and sets CR4.PCE.
+    ///
+    /// # Safety
+    /// * Must be run in ring 0.
+    pub unsafe fn detect() -> Self {
+        if cpuid(0, 0).eax < 0xa {
+            return Self::none();
+        }
+
+        let leaf = cpuid(0xa, 0);
+        let version = leaf.eax & 0xff;
+        let gp_counters = (leaf.eax >> 8) & 0xff;
+        /* EBX bits are set for events that are NOT available */
+        let llc_misses_available = (leaf.ebx >> 4) & 1 == 0 && ((leaf.eax >> 24) & 0xff) > 4;
+
+        /* Version 1 has neither fixed counters nor the global control */
+        if version < 2 {
+            return Self::none();
+        }
+
+        let fixed_counters = leaf.edx & 0x1f;
+        let global = rdmsr(IA32_PERF_GLOBAL_CTRL);
+
+        let instructions = fixed_counters >= 1
+            && rdmsr(IA32_FIXED_CTR_CTRL) & 0b11 != 0
+            && (global >> 32) & 1 == 1;
+
+        let llc_misses = gp_counters >= 1 && llc_misses_available && global & 1 == 1 && {
+            let evtsel = rdmsr(IA32_PERFEVTSEL0);
+            evtsel & 0xff == LLC_MISSES_EVENT
+                && (evtsel >> 8) & 0xff == LLC_MISSES_UMASK
+                && evtsel & PERFEVTSEL_ENABLE != 0
+        };
+
+        if instructions || llc_misses {
+            Cr4::set(Cr4::get().set_perf_counter());
+        }
+
+        return Self {
+            instructions,
+            llc_misses,
+        };
+    }
+
+    pub fn snapshot(&self) -> PerfSnapshot {
+        unsafe {
+            PerfSnapshot {
+                tsc: rdtsc(),
+                instructions: match self.instructions {
+                    true => Some(rdpmc(FIXED_INSTRUCTIONS)),
+                    false => None,
+                },
+                llc_misses: match self.llc_misses {
+                    true => Some(rdpmc(GP_COUNTER0)),
+                    false => None,
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use crate::{cpuid, rdtsc, PhysAddr, PhysRange, PhysSlice, VirtAddr};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// Tells where physical memory can be accessed from the current address space
+pub trait PhysMapping {
+    fn phys_to_virt<T>(&self, addr: PhysAddr<T>) -> VirtAddr<T>;
+}
+
+/// Physical memory is mapped 1:1, like it is during boot services
+pub struct IdentityMapping;
+
+impl PhysMapping for IdentityMapping {
+    fn phys_to_virt<T>(&self, addr: PhysAddr<T>) -> VirtAddr<T> {
+        VirtAddr::new(addr.as_u64())
+    }
+}
+
+/// Physical memory is mapped at a constant offset, like in a direct map
+pub struct OffsetMapping(pub u64);
+
+impl PhysMapping for OffsetMapping {
+    fn phys_to_virt<T>(&self, addr: PhysAddr<T>) -> VirtAddr<T> {
+        VirtAddr::new(addr.as_u64().wrapping_add(self.0))
+    }
+}
+
+/// Bytes moved by `copy`/`zero` and TSC cycles spent doing so
+pub struct Counters {
+    pub bytes_copied: AtomicU64,
+    pub copy_cycles: AtomicU64,
+    pub bytes_zeroed: AtomicU64,
+    pub zero_cycles: AtomicU64,
+}
+
+pub static COUNTERS: Counters = Counters {
+    bytes_copied: AtomicU64::new(0),
+    copy_cycles: AtomicU64::new(0),
+    bytes_zeroed: AtomicU64::new(0),
+    zero_cycles: AtomicU64::new(0),
+};
+
+/* 0 - not checked yet, 1 - no ERMS, 2 - ERMS */
+static ERMS: AtomicU8 = AtomicU8::new(0);
+
+/// Enhanced REP MOVSB/STOSB, when set byte-sized string operations are
+/// at least as fast as the quadword ones
+pub fn has_erms() -> bool {
+    let erms = match ERMS.load(Ordering::Relaxed) {
+        0 => {
+            let max_leaf = cpuid(0, 0).eax;
+            let erms = max_leaf >= 7 && (cpuid(7, 0).ebx >> 9) & 1 == 1;
+            ERMS.store(1 + erms as u8, Ordering::Relaxed);
+            erms
+        }
+        x => x == 2,
+    };
+
+    return erms;
+}
+
+#[inline(always)]
+unsafe fn rep_movsb(dst: *mut u8, src: *const u8, len: usize) {
+    asm!(
+        "rep movsb",
+        inout("rcx") len => _,
+        inout("rdi") dst => _,
+        inout("rsi") src => _,
+        options(nostack, preserves_flags),
+    );
+}
+
+#[inline(always)]
+unsafe fn rep_movsq(dst: *mut u8, src: *const u8, qwords: usize) {
+    asm!(
+        "rep movsq",
+        inout("rcx") qwords => _,
+        inout("rdi") dst => _,
+        inout("rsi") src => _,
+        options(nostack, preserves_flags),
+    );
+}
+
+#[inline(always)]
+unsafe fn rep_stosb(dst: *mut u8, len: usize) {
+    asm!(
+        "rep stosb",
+        inout("rcx") len => _,
+        inout("rdi") dst => _,
+        in("al") 0u8,
+        options(nostack, preserves_flags),
+    );
+}
+
+#[inline(always)]
+unsafe fn rep_stosq(dst: *mut u8, qwords: usize) {
+    asm!(
+        "rep stosq",
+        inout("rcx") qwords => _,
+        inout("rdi") dst => _,
+        in("rax") 0u64,
+        options(nostack, preserves_flags),
+    );
+}
+
+/// Splits `len` bytes starting at `dst` into unaligned head,
+/// count of aligned quadwords and unaligned tail
+fn split_aligned(dst: *mut u8, len: usize) -> (usize, usize, usize) {
+    let head = (dst as usize).wrapping_neg() % 8;
+    let head = core::cmp::min(head, len);
+    let qwords = (len - head) / 8;
+    let tail = len - head - qwords * 8;
+    (head, qwords, tail)
+}
+
+unsafe fn copy_raw(dst: *mut u8, src: *const u8, len: usize, erms: bool) {
+    if erms {
+        return rep_movsb(dst, src, len);
+    }
+
+    let (head, qwords, tail) = split_aligned(dst, len);
+    rep_movsb(dst, src, head);
+    rep_movsq(dst.add(head), src.add(head), qwords);
+    rep_movsb(dst.add(len - tail), src.add(len - tail), tail);
+}
+
+unsafe fn zero_raw(dst: *mut u8, len: usize, erms: bool) {
+    if erms {
+        return rep_stosb(dst, len);
+    }
+
+    let (head, qwords, tail) = split_aligned(dst, len);
+    rep_stosb(dst, head);
+    rep_stosq(dst.add(head), qwords);
+    rep_stosb(dst.add(len - tail), tail);
+}
+
+/// Copies `src` to the beginning of `dst`
+///
+/// # Safety
+/// * `dst` must be valid for writes and accessible through `mapping`
+/// * `dst` must not overlap with `src`
+pub unsafe fn copy(dst: PhysSlice<u8>, src: &[u8], mapping: &impl PhysMapping) {
+    assert!(
+        src.len() <= dst.len(),
+        "phys::copy - source is larger than destination"
+    );
+
+    let start = rdtsc();
+    let ptr = mapping.phys_to_virt(dst.addr()).as_ptr_mut();
+    copy_raw(ptr, src.as_ptr(), src.len(), has_erms());
+    let cycles = rdtsc().wrapping_sub(start);
+
+    COUNTERS
+        .bytes_copied
+        .fetch_add(src.len() as u64, Ordering::Relaxed);
+    COUNTERS.copy_cycles.fetch_add(cycles, Ordering::Relaxed);
+}
+
+/// Fills `dst` with zeroes
+///
+/// # Safety
+/// * `dst` must be valid for writes and accessible through `mapping`
+pub unsafe fn zero(dst: PhysSlice<u8>, mapping: &impl PhysMapping) {
+    let start = rdtsc();
+    let ptr = mapping.phys_to_virt(dst.addr()).as_ptr_mut();
+    zero_raw(ptr, dst.len(), has_erms());
+    let cycles = rdtsc().wrapping_sub(start);
+
+    COUNTERS
+        .bytes_zeroed
+        .fetch_add(dst.len() as u64, Ordering::Relaxed);
+    COUNTERS.zero_cycles.fetch_add(cycles, Ordering::Relaxed);
+}
+
+/// Same as `copy`, but always uses the quadword path, so that it can be
+/// tested on CPUs with ERMS
+#[doc(hidden)]
+pub unsafe fn copy_qwords(dst: PhysSlice<u8>, src: &[u8], mapping: &impl PhysMapping) {
+    assert!(src.len() <= dst.len());
+    let ptr = mapping.phys_to_virt(dst.addr()).as_ptr_mut();
+    copy_raw(ptr, src.as_ptr(), src.len(), false);
+}
+
+/// Same as `zero`, but always uses the quadword path
+#[doc(hidden)]
+pub unsafe fn zero_qwords(dst: PhysSlice<u8>, mapping: &impl PhysMapping) {
+    let ptr = mapping.phys_to_virt(dst.addr()).as_ptr_mut();
+    zero_raw(ptr, dst.len(), false);
+}
+
+/// Order of bytes in multi-byte integers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// Bounds-checked cursor over physical memory,
+/// every read returns `None` instead of going past the end of the slice
+pub struct PhysReader<'a> {
+    slice: PhysSlice<u8>,
+    phys_offset: u64,
+    pos: usize,
+    _phantom: core::marker::PhantomData<&'a [u8]>,
+}
+
+impl<'a> PhysReader<'a> {
+    /// # Safety
+    /// * `slice` must be identity mapped and valid for reads for `'a`
+    /// * nothing may write to `slice` during `'a`
+    pub unsafe fn new(slice: PhysSlice<u8>) -> Self {
+        Self::with_offset(slice, 0)
+    }
+
+    /// Reads `slice` at `phys_offset` above its physical address,
+    /// like `OffsetMapping` does
+    ///
+    /// # Safety
+    /// Same as `new`, for the memory at the offset
+    pub unsafe fn with_offset(slice: PhysSlice<u8>, phys_offset: u64) -> Self {
+        Self {
+            slice,
+            phys_offset,
+            pos: 0,
+            _phantom: core::marker::PhantomData,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.slice.len() - self.pos
+    }
+
+    /// Moves the cursor to `pos`, which may be equal to the length of the slice
+    pub fn seek(&mut self, pos: usize) -> Option<()> {
+        if pos > self.slice.len() {
+            return None;
+        }
+        self.pos = pos;
+        return Some(());
+    }
+
+    pub fn read_bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.remaining() {
+            return None;
+        }
+
+        let addr = self.slice.addr().as_u64() + self.pos as u64;
+        let ptr = OffsetMapping(self.phys_offset)
+            .phys_to_virt(PhysAddr::<u8>::new(addr)?)
+            .as_ptr();
+        self.pos += n;
+
+        /* SAFETY: in bounds of `slice`, guaranteed readable by `new` */
+        return Some(unsafe { core::slice::from_raw_parts(ptr, n) });
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.read_bytes(N)?);
+        return Some(out);
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        return Some(self.read_array::<1>()?[0]);
+    }
+
+    pub fn read_u16(&mut self, order: impl Into<ByteOrder>) -> Option<u16> {
+        let bytes = self.read_array()?;
+        return Some(match order.into() {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+        });
+    }
+
+    pub fn read_u32(&mut self, order: impl Into<ByteOrder>) -> Option<u32> {
+        let bytes = self.read_array()?;
+        return Some(match order.into() {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        });
+    }
+
+    pub fn read_u64(&mut self, order: impl Into<ByteOrder>) -> Option<u64> {
+        let bytes = self.read_array()?;
+        return Some(match order.into() {
+            ByteOrder::Little => u64::from_le_bytes(bytes),
+            ByteOrder::Big => u64::from_be_bytes(bytes),
+        });
+    }
+}
+
+/// A write done through a `PhysWriter`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhysWrite {
+    /// Short tag of what the write is for, e.g. "kernel bss"
+    pub purpose: &'static str,
+    pub range: PhysRange,
+}
+
+/// The only way the loader writes to physical memory it doesn't own as
+/// Rust objects, so that everything that may scribble on it is in one
+/// place. Every write is logged with its purpose, and debug builds panic
+/// if it isn't inside one of the `allowed` ranges.
+pub struct PhysWriter<'a, M: PhysMapping> {
+    allowed: &'a [PhysRange],
+    mapping: M,
+    log: &'a mut dyn FnMut(&PhysWrite),
+}
+
+impl<'a, M: PhysMapping> PhysWriter<'a, M> {
+    /// # Safety
+    /// Every range in `allowed` must be valid for writes through `mapping`
+    /// and not be referenced by anything else while the writer lives.
+    pub unsafe fn new(
+        allowed: &'a [PhysRange],
+        mapping: M,
+        log: &'a mut dyn FnMut(&PhysWrite),
+    ) -> Self {
+        Self {
+            allowed,
+            mapping,
+            log,
+        }
+    }
+
+    pub fn allowed(&self) -> &[PhysRange] {
+        self.allowed
+    }
+
+    /// Whether `range` is inside one of the allowed ranges
+    pub fn is_allowed(&self, range: &PhysRange) -> bool {
+        self.allowed.iter().any(|x| x.contains_range(range))
+    }
+
+    /// Writes `range` back from the caches once it's written, for memory
+    /// another agent reads next, see `cache::flush_range`. Returns the
+    /// number of lines flushed.
+    ///
+    /// # Safety
+    /// `range` must be inside an allowed range, only debug builds check.
+    pub unsafe fn flush(&self, range: PhysRange) -> u64 {
+        debug_assert!(
+            self.is_allowed(&range),
+            "PhysWriter: flush of {:?} outside the whitelist",
+            range
+        );
+        crate::cache::flush_range(range, &self.mapping)
+    }
+
+    /// Allowed range closest to `range`, for reporting violations
+    pub fn nearest_allowed(&self, range: &PhysRange) -> Option<PhysRange> {
+        let distance = |x: &PhysRange| {
+            if x.overlaps(range) {
+                0
+            } else if x.end() <= range.start() {
+                range.start() - x.end() + 1
+            } else {
+                x.start() - range.end() + 1
+            }
+        };
+        self.allowed.iter().copied().min_by_key(distance)
+    }
+
+    fn check(&mut self, purpose: &'static str, addr: u64, len: u64) -> *mut u8 {
+        let range = match PhysRange::new(addr, len) {
+            Some(x) => x,
+            None => panic!(
+                "PhysWriter: {} write of {} bytes at {:#x} isn't a physical range",
+                purpose, len, addr
+            ),
+        };
+        (self.log)(&PhysWrite { purpose, range });
+
+        if cfg!(debug_assertions) && !self.is_allowed(&range) {
+            panic!(
+                "PhysWriter: {} write to {:?} outside the whitelist, nearest allowed {:?}",
+                purpose,
+                range,
+                self.nearest_allowed(&range)
+            );
+        }
+
+        /* SAFETY: `PhysRange` only holds physical addresses */
+        let addr = unsafe { PhysAddr::<u8>::new_unchecked(addr) };
+        return self.mapping.phys_to_virt(addr).as_ptr_mut();
+    }
+
+    /// Sets `len` bytes at `addr` to `byte`
+    ///
+    /// # Safety
+    /// The target must be inside an allowed range, only debug builds check.
+    pub unsafe fn write_bytes(&mut self, purpose: &'static str, addr: u64, byte: u8, len: u64) {
+        let dst = self.check(purpose, addr, len);
+        if byte == 0 {
+            let start = rdtsc();
+            zero_raw(dst, len as usize, has_erms());
+            COUNTERS.bytes_zeroed.fetch_add(len, Ordering::Relaxed);
+            COUNTERS
+                .zero_cycles
+                .fetch_add(rdtsc().wrapping_sub(start), Ordering::Relaxed);
+        } else {
+            dst.write_bytes(byte, len as usize);
+        }
+    }
+
+    /// Copies `src` to `addr`
+    ///
+    /// # Safety
+    /// The target must be inside an allowed range, only debug builds check,
+    /// and must not overlap with `src`.
+    pub unsafe fn copy_from_slice(&mut self, purpose: &'static str, addr: u64, src: &[u8]) {
+        let dst = self.check(purpose, addr, src.len() as u64);
+        let start = rdtsc();
+        copy_raw(dst, src.as_ptr(), src.len(), has_erms());
+        COUNTERS
+            .bytes_copied
+            .fetch_add(src.len() as u64, Ordering::Relaxed);
+        COUNTERS
+            .copy_cycles
+            .fetch_add(rdtsc().wrapping_sub(start), Ordering::Relaxed);
+    }
+
+    /// Writes `count` copies of `value` from `addr` on, which has to be
+    /// aligned for `T`
+    ///
+    /// # Safety
+    /// The target must be inside an allowed range, only debug builds check.
+    pub unsafe fn fill<T: Copy>(
+        &mut self,
+        purpose: &'static str,
+        addr: u64,
+        value: T,
+        count: usize,
+    ) {
+        let len = (count as u64).saturating_mul(core::mem::size_of::<T>() as u64);
+        let dst = self.check(purpose, addr, len) as *mut T;
+        assert_eq!(
+            dst as usize % core::mem::align_of::<T>(),
+            0,
+            "PhysWriter: misaligned {}",
+            purpose
+        );
+        core::slice::from_raw_parts_mut(dst, count).fill(value);
+    }
+}
@@ -1,4 +1,5 @@
 use core::marker::PhantomData;
+use impl_bits::fmt::{Addr, Size};
 
 #[repr(transparent)]
 #[rustc_layout_scalar_valid_range_end(0x000f_ffff_ffff_ffff)]
@@ -41,6 +42,12 @@ impl<T> Clone for PhysAddr<T> {
     }
 }
 
+impl<T> core::fmt::Debug for PhysAddr<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "PhysAddr({})", Addr(self.addr))
+    }
+}
+
 #[repr(C)]
 pub struct PhysSlice<T = ()> {
     addr: PhysAddr<T>,
@@ -74,3 +81,16 @@ impl<T> Clone for PhysSlice<T> {
         *self
     }
 }
+
+impl<T> core::fmt::Debug for PhysSlice<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        /* Untyped slices are counted in bytes */
+        let elem_size = core::cmp::max(core::mem::size_of::<T>(), 1) as u64;
+
+        f.debug_struct("PhysSlice")
+            .field("addr", &Addr(self.addr.as_u64()))
+            .field("len", &self.size)
+            .field("size", &Size(self.size.saturating_mul(elem_size)))
+            .finish()
+    }
+}
@@ -0,0 +1,228 @@
+use crate::paging::{Megapage, Page};
+use crate::{PhysAddr, PhysSlice, VirtAddr};
+use core::marker::PhantomData;
+use impl_bits::fmt::{Addr, Size};
+
+/// Sizes of pages a range can be aligned to and iterated over
+pub trait PageSize {
+    const SIZE: u64;
+}
+
+impl PageSize for Page {
+    const SIZE: u64 = crate::paging::PAGE_SIZE;
+}
+
+impl PageSize for Megapage {
+    const SIZE: u64 = crate::paging::MEGAPAGE_SIZE;
+}
+
+/// Physical addresses are at most 52 bits wide
+const PHYS_LIMIT: u64 = 1 << 52;
+
+macro_rules! impl_range {
+    ($name:ident, $limit:expr) => {
+        /// Half-open range `start..start+len`, the end never overflows
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        pub struct $name {
+            start: u64,
+            len: u64,
+        }
+
+        impl $name {
+            pub const fn empty() -> Self {
+                Self { start: 0, len: 0 }
+            }
+
+            pub const fn new(start: u64, len: u64) -> Option<Self> {
+                let end = match start.checked_add(len) {
+                    Some(end) => end,
+                    None => return None,
+                };
+                if end > $limit {
+                    return None;
+                }
+                return Some(Self { start, len });
+            }
+
+            /// `end` is exclusive
+            pub const fn from_start_end(start: u64, end: u64) -> Option<Self> {
+                if end < start {
+                    return None;
+                }
+                return Self::new(start, end - start);
+            }
+
+            pub const fn start(&self) -> u64 {
+                self.start
+            }
+
+            /// First address past the range
+            pub const fn end(&self) -> u64 {
+                self.start + self.len
+            }
+
+            pub const fn len(&self) -> u64 {
+                self.len
+            }
+
+            pub const fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            pub const fn contains(&self, addr: u64) -> bool {
+                self.start <= addr && addr < self.end()
+            }
+
+            /// Empty ranges are contained in every range
+            pub const fn contains_range(&self, other: &Self) -> bool {
+                other.is_empty() || (self.start <= other.start && other.end() <= self.end())
+            }
+
+            /// Whether there is at least one address in both ranges
+            pub const fn overlaps(&self, other: &Self) -> bool {
+                !self.is_empty()
+                    && !other.is_empty()
+                    && self.start < other.end()
+                    && other.start < self.end()
+            }
+
+            pub fn intersection(&self, other: &Self) -> Option<Self> {
+                if !self.overlaps(other) {
+                    return None;
+                }
+
+                let start = core::cmp::max(self.start, other.start);
+                let end = core::cmp::min(self.end(), other.end());
+                return Self::from_start_end(start, end);
+            }
+
+            /// Splits into `start..addr` and `addr..end`, either may be empty
+            pub fn split_at(&self, addr: u64) -> Option<(Self, Self)> {
+                if addr < self.start || addr > self.end() {
+                    return None;
+                }
+
+                let low = Self {
+                    start: self.start,
+                    len: addr - self.start,
+                };
+                let high = Self {
+                    start: addr,
+                    len: self.end() - addr,
+                };
+                return Some((low, high));
+            }
+
+            /// Both ends are multiples of `P::SIZE`
+            pub fn is_aligned<P: PageSize>(&self) -> bool {
+                self.start % P::SIZE == 0 && self.len % P::SIZE == 0
+            }
+
+            /// Number of `P` pages touched by the range
+            pub fn page_count<P: PageSize>(&self) -> u64 {
+                if self.is_empty() {
+                    return 0;
+                }
+
+                let first = self.start / P::SIZE;
+                let last = (self.end() - 1) / P::SIZE;
+                return last - first + 1;
+            }
+        }
+
+        impl core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write!(
+                    f,
+                    "{}({}..{}, {})",
+                    stringify!($name),
+                    Addr(self.start),
+                    Addr(self.end()),
+                    Size(self.len)
+                )
+            }
+        }
+    };
+}
+
+impl_range!(PhysRange, PHYS_LIMIT);
+impl_range!(VirtRange, u64::MAX);
+
+impl PhysRange {
+    /// Every `P` page touched by the range, first one rounded down
+    pub fn pages<P: PageSize>(&self) -> Pages<PhysAddr<P>> {
+        Pages::new(self.start, self.page_count::<P>(), P::SIZE)
+    }
+}
+
+impl VirtRange {
+    /// Every `P` page touched by the range, first one rounded down
+    pub fn pages<P: PageSize>(&self) -> Pages<VirtAddr<P>> {
+        Pages::new(self.start, self.page_count::<P>(), P::SIZE)
+    }
+}
+
+impl<T> From<PhysSlice<T>> for PhysRange {
+    /// # Panics
+    /// If the slice goes past the physical address space
+    fn from(slice: PhysSlice<T>) -> Self {
+        let elem_size = core::cmp::max(core::mem::size_of::<T>(), 1) as u64;
+        let len = (slice.len() as u64).checked_mul(elem_size);
+        len.and_then(|len| Self::new(slice.addr().as_u64(), len))
+            .expect("PhysSlice out of physical address space")
+    }
+}
+
+impl From<PhysRange> for PhysSlice<u8> {
+    fn from(range: PhysRange) -> Self {
+        /* SAFETY: start < PHYS_LIMIT is checked on construction */
+        let addr = unsafe { PhysAddr::new_unchecked(range.start) };
+        PhysSlice::new(addr, range.len)
+    }
+}
+
+/// Iterator over page addresses of a `PhysRange` or `VirtRange`
+pub struct Pages<A> {
+    next: u64,
+    remaining: u64,
+    step: u64,
+    _marker: PhantomData<A>,
+}
+
+impl<A> Pages<A> {
+    fn new(start: u64, count: u64, step: u64) -> Self {
+        Self {
+            next: start - start % step,
+            remaining: count,
+            step,
+            _marker: PhantomData,
+        }
+    }
+
+    fn advance(&mut self) -> Option<u64> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let addr = self.next;
+        self.remaining -= 1;
+        self.next = self.next.wrapping_add(self.step);
+        return Some(addr);
+    }
+}
+
+impl<P> Iterator for Pages<PhysAddr<P>> {
+    type Item = PhysAddr<P>;
+    fn next(&mut self) -> Option<Self::Item> {
+        /* SAFETY: every page starts below the end of a PhysRange */
+        self.advance()
+            .map(|addr| unsafe { PhysAddr::new_unchecked(addr) })
+    }
+}
+
+impl<P> Iterator for Pages<VirtAddr<P>> {
+    type Item = VirtAddr<P>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.advance().map(VirtAddr::new)
+    }
+}
@@ -152,6 +152,42 @@ pub fn invlpcid() {
     }
 }
 
+/// Reads model specific register `msr`
+///
+/// # Safety
+/// * Reading a MSR that doesn't exist raises #GP.
+#[inline(always)]
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let lower: u32;
+    let upper: u32;
+
+    asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") lower,
+        out("edx") upper,
+        options(nomem, nostack, preserves_flags),
+    );
+
+    return (upper as u64) << 32 | lower as u64;
+}
+
+/// Writes model specific register `msr`
+///
+/// # Safety
+/// * Writing a MSR that doesn't exist or a reserved bit raises #GP.
+/// * Absolutely no safety otherwise
+#[inline(always)]
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags),
+    );
+}
+
 #[repr(transparent)]
 pub struct Cr4(u64);
 
@@ -258,6 +294,63 @@ impl Cr0 {
     }
 }
 
+/// IA32_EFER, extended feature enable register
+#[repr(transparent)]
+pub struct Efer(u64);
+
+impl_bits!(Efer = {
+    /// Enables `syscall` and `sysret`
+    syscall_enable = 0,
+    long_mode_enable = 8,
+    long_mode_active = 10,
+    nx_enable = 11,
+
+    // Only in AMD manual
+    secure_virtual_machine = 12,
+    long_mode_segment_limit = 13,
+    fast_fxsave_fxrstor = 14,
+    translation_cache_extension = 15,
+});
+
+impl Efer {
+    pub const MSR: u32 = 0xC000_0080;
+
+    pub fn get() -> Self {
+        Self(unsafe { rdmsr(Self::MSR) })
+    }
+
+    pub unsafe fn set(efer: Self) {
+        wrmsr(Self::MSR, efer.0);
+    }
+}
+
+/// IA32_MISC_ENABLE, Intel only, see `CpuInfo::misc_enable`
+#[repr(transparent)]
+pub struct MiscEnable(u64);
+
+impl_bits!(MiscEnable = {
+    /// `rep movs` and `rep stos` move whole cache lines
+    fast_strings = 0,
+    thermal_control = 3,
+    performance_monitoring = 7,
+    enhanced_speedstep = 16,
+    /// Caps CPUID leaves at 2, for OSes from the 90s
+    limit_cpuid = 22,
+    xd_disable = 34,
+});
+
+impl MiscEnable {
+    pub const MSR: u32 = 0x1A0;
+
+    pub fn get() -> Self {
+        Self(unsafe { rdmsr(Self::MSR) })
+    }
+
+    pub unsafe fn set(misc: Self) {
+        wrmsr(Self::MSR, misc.0);
+    }
+}
+
 #[repr(transparent)]
 pub struct Cr2(pub VirtAddr);
 
@@ -285,6 +378,11 @@ impl Cr3 {
         Self(addr.as_u64())
     }
 
+    /// Physical address of the PML4
+    pub fn addr(&self) -> PhysAddr<paging::Table<paging::PML4Entry>> {
+        unsafe { PhysAddr::new_unchecked(self.0 & 0x000f_ffff_ffff_f000) }
+    }
+
     pub fn set_disable_cache(self) -> Self {
         Self(self.0 | (1 << 4))
     }
@@ -313,10 +411,11 @@ impl Cr3 {
     }
 
     pub unsafe fn set(cr3: Self) {
+        /* Not nomem, this flushes the TLB */
         asm!(
-            "mov cr0, {:r}",
+            "mov cr3, {:r}",
             in(reg) cr3.0,
-            options(nomem, nostack)
+            options(nostack)
         );
     }
 }
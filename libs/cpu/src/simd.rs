@@ -0,0 +1,121 @@
+#![cfg(feature = "ringzero")]
+
+//! SSE and AVX enablement before jumping to compiled code.
+//!
+//! Long mode implies SSE2 hardware, but whether the OS bits are set is up
+//! to the firmware. With CR4.OSFXSR clear every SSE instruction raises #UD,
+//! and Rust emits them for plain memcpys and floats, so the kernel would
+//! fault long before it could print anything. AVX additionally needs
+//! CR4.OSXSAVE and the AVX state enabled in XCR0.
+
+use crate::{cpuid, Cr0, Cr4};
+
+/* XCR0 state components */
+pub const XCR0_X87: u64 = 1 << 0;
+pub const XCR0_SSE: u64 = 1 << 1;
+pub const XCR0_AVX: u64 = 1 << 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AvxError {
+    /// No `xsave`, so XCR0 can't be written
+    NoXsave,
+    NoAvx,
+    /// Processor doesn't offer the AVX state in XCR0
+    StateNotSupported,
+}
+
+pub fn has_xsave() -> bool {
+    (cpuid(1, 0).ecx >> 26) & 1 == 1
+}
+
+pub fn has_avx() -> bool {
+    (cpuid(1, 0).ecx >> 28) & 1 == 1
+}
+
+/// XCR0 bits the processor supports, 0 without `xsave`
+pub fn supported_xcr0() -> u64 {
+    if !has_xsave() || cpuid(0, 0).eax < 0xD {
+        return 0;
+    }
+    let leaf = cpuid(0xD, 0);
+    return (leaf.edx as u64) << 32 | leaf.eax as u64;
+}
+
+/// XCR0 with AVX enabled, given the current value and supported bits.
+/// AVX state can't be enabled without SSE state, and x87 state is always on.
+pub const fn avx_xcr0(current: u64, supported: u64) -> Option<u64> {
+    let needed = XCR0_X87 | XCR0_SSE | XCR0_AVX;
+    if supported & needed != needed {
+        return None;
+    }
+    return Some(current | needed);
+}
+
+/// Reads extended control register `xcr`
+///
+/// # Safety
+/// CR4.OSXSAVE must be set, otherwise this raises #UD.
+#[inline(always)]
+pub unsafe fn xgetbv(xcr: u32) -> u64 {
+    let lower: u32;
+    let upper: u32;
+
+    asm!(
+        "xgetbv",
+        in("ecx") xcr,
+        out("eax") lower,
+        out("edx") upper,
+        options(nomem, nostack, preserves_flags),
+    );
+
+    return (upper as u64) << 32 | lower as u64;
+}
+
+/// Writes extended control register `xcr`
+///
+/// # Safety
+/// CR4.OSXSAVE must be set and `value` must be a valid combination
+/// of supported state components, otherwise this raises #UD or #GP.
+#[inline(always)]
+pub unsafe fn xsetbv(xcr: u32, value: u64) {
+    asm!(
+        "xsetbv",
+        in("ecx") xcr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags),
+    );
+}
+
+/// Makes SSE instructions usable: clears CR0.EM, sets CR0.MP,
+/// CR4.OSFXSR and CR4.OSXMMEXCPT. Every long mode processor has SSE2.
+///
+/// # Safety
+/// Ring 0 only.
+pub unsafe fn enable_sse() {
+    Cr0::set(Cr0::get().clear_emulation().set_monitor_coprocessor());
+    Cr4::set(
+        Cr4::get()
+            .set_os_fxsave_fxrstor()
+            .set_os_simd_float_exceptions(),
+    );
+}
+
+/// Sets CR4.OSXSAVE and enables x87, SSE and AVX state in XCR0,
+/// after checking CPUID. `enable_sse` has to be called first.
+///
+/// # Safety
+/// Ring 0 only.
+pub unsafe fn enable_avx() -> Result<(), AvxError> {
+    if !has_xsave() {
+        return Err(AvxError::NoXsave);
+    }
+    if !has_avx() {
+        return Err(AvxError::NoAvx);
+    }
+
+    Cr4::set(Cr4::get().set_os_xsave());
+    let xcr0 = avx_xcr0(xgetbv(0), supported_xcr0()).ok_or(AvxError::StateNotSupported)?;
+    xsetbv(0, xcr0);
+    return Ok(());
+}
@@ -0,0 +1,56 @@
+#![cfg(feature = "ringzero")]
+
+//! `syscall`/`sysret` setup.
+//!
+//! Neither instruction reads the GDT, they load fixed descriptors derived
+//! from selectors in IA32_STAR, so the GDT has to be laid out like this:
+//!
+//! | selector         | descriptor               |
+//! |------------------|--------------------------|
+//! | `kernel_cs`      | 64-bit kernel code       |
+//! | `kernel_cs + 8`  | kernel data (SS)         |
+//! | `user_cs - 16`   | 32-bit user code         |
+//! | `user_cs - 8`    | user data (SS)           |
+//! | `user_cs`        | 64-bit user code         |
+//!
+//! `syscall` loads CS from `kernel_cs` and SS from `kernel_cs + 8`,
+//! `sysret` to 64-bit mode loads CS from `user_cs` and SS from
+//! `user_cs - 8`, both with RPL forced to 3. The 32-bit code descriptor
+//! is only used by `sysret` to compatibility mode, but its slot has to exist.
+
+use crate::{wrmsr, Efer};
+
+pub const IA32_STAR: u32 = 0xC000_0081;
+pub const IA32_LSTAR: u32 = 0xC000_0082;
+pub const IA32_FMASK: u32 = 0xC000_0084;
+
+/// RFLAGS bits cleared on `syscall`: TF, IF, DF, AC and NT, so that the
+/// handler starts with interrupts disabled and a sane direction flag
+pub const SYSCALL_FMASK: u64 = 1 << 8 | 1 << 9 | 1 << 10 | 1 << 14 | 1 << 18;
+
+/// Value of IA32_STAR for given selectors,
+/// `None` if `user_cs` leaves no room for the descriptors below it
+pub const fn star(kernel_cs: u16, user_cs: u16) -> Option<u64> {
+    if user_cs < 16 {
+        return None;
+    }
+
+    let sysret_base = user_cs - 16;
+    return Some((sysret_base as u64) << 48 | (kernel_cs as u64) << 32);
+}
+
+/// Sets EFER.SCE and points `syscall` at `handler`,
+/// see the module documentation for the GDT layout it expects.
+///
+/// # Safety
+/// * The GDT must be laid out as described in the module documentation.
+/// * `handler` must be a valid entry point for `syscall`: it runs in ring 0
+/// on the user stack, with the return address in RCX and RFLAGS in R11.
+pub unsafe fn enable_syscall(kernel_cs: u16, user_cs: u16, handler: u64) {
+    let star = star(kernel_cs, user_cs).expect("enable_syscall: invalid user_cs");
+
+    wrmsr(IA32_STAR, star);
+    wrmsr(IA32_LSTAR, handler);
+    wrmsr(IA32_FMASK, SYSCALL_FMASK);
+    Efer::set(Efer::get().set_syscall_enable());
+}
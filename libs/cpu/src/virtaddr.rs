@@ -1,5 +1,6 @@
 use core::marker::PhantomData;
 use core::ptr;
+use impl_bits::fmt::Addr;
 
 #[repr(transparent)]
 pub struct VirtAddr<T = ()> {
@@ -31,6 +32,12 @@ impl<T> VirtAddr<T> {
     }
 }
 
+impl<T> core::fmt::Debug for VirtAddr<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "VirtAddr({})", Addr(self.addr))
+    }
+}
+
 impl<T> Copy for VirtAddr<T> {}
 impl<T> Clone for VirtAddr<T> {
     fn clone(&self) -> Self {
@@ -0,0 +1,423 @@
+use cpu::acpi::{self, AcpiContext, AcpiError, FirmwareBootPerformance, OldRsdp, ResetRegister, Rsdp, SdtHeader};
+use cpu::acpi::{SkipReason, SkippedTable};
+use std::mem::size_of;
+
+const HEADER_SIZE: usize = size_of::<SdtHeader>();
+
+fn fix_checksum(bytes: &mut [u8], at: usize) {
+    bytes[at] = 0;
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes[at] = sum.wrapping_neg();
+}
+
+/* Table of `len` bytes with a valid checksum, at least a header long */
+fn table(signature: &[u8; 4], len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len.max(HEADER_SIZE)];
+    bytes[0..4].copy_from_slice(signature);
+    bytes[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+    bytes[10..16].copy_from_slice(b"SOVOS ");
+    fix_checksum(&mut bytes, 9);
+    return bytes;
+}
+
+fn xsdt(entries: &[u64]) -> Vec<u8> {
+    let mut bytes = table(b"XSDT", HEADER_SIZE + entries.len() * 8);
+    for (i, entry) in entries.iter().enumerate() {
+        let at = HEADER_SIZE + i * 8;
+        bytes[at..at + 8].copy_from_slice(&entry.to_le_bytes());
+    }
+    fix_checksum(&mut bytes, 9);
+    return bytes;
+}
+
+fn negated_sum(rsdp: &Rsdp, len: usize) -> u8 {
+    let bytes = unsafe { core::slice::from_raw_parts(rsdp as *const Rsdp as *const u8, len) };
+    return bytes.iter().fold(0u8, |sum, &b| sum.wrapping_sub(b));
+}
+
+fn rsdp(revision: u8, xsdt: *const u8) -> Rsdp {
+    return rsdp_with_rsdt(revision, xsdt, 0);
+}
+
+fn rsdp_with_rsdt(revision: u8, xsdt: *const u8, rsdt: u32) -> Rsdp {
+    let mut rsdp = Rsdp {
+        old: OldRsdp {
+            signature: *b"RSD PTR ",
+            checksum: 0,
+            oem_id: *b"BOCHS ",
+            revision,
+            rsdt_address: rsdt,
+        },
+        length: size_of::<Rsdp>() as u32,
+        xsdt: xsdt as *const SdtHeader,
+        ext_checksum: 0,
+        _reserved: [0; 3],
+    };
+    rsdp.old.checksum = negated_sum(&rsdp, 20);
+    rsdp.ext_checksum = negated_sum(&rsdp, 36);
+    return rsdp;
+}
+
+fn dump(rsdp: &Rsdp) -> String {
+    let mut out = String::new();
+    unsafe { acpi::dump(rsdp, 0, &mut out).unwrap() };
+    return out;
+}
+
+#[test]
+fn checksum() {
+    assert!(acpi::validate_checksum(&[]));
+    assert!(acpi::validate_checksum(&[0x80, 0x80]));
+    assert!(!acpi::validate_checksum(&[1]));
+
+    let mut facp = table(b"FACP", 0x74);
+    let header = unsafe { &*(facp.as_ptr() as *const SdtHeader) };
+    assert_eq!(header.signature(), *b"FACP");
+    assert!(unsafe { header.validate_checksum() });
+    facp[50] = 1;
+    let header = unsafe { &*(facp.as_ptr() as *const SdtHeader) };
+    assert!(!unsafe { header.validate_checksum() });
+}
+
+#[test]
+fn tables() {
+    let facp = table(b"FACP", 0x74);
+    let mut apic = table(b"APIC", 0x40);
+    apic[40] = 0xff;
+    let xsdt = xsdt(&[facp.as_ptr() as u64, 0, apic.as_ptr() as u64]);
+    let rsdp = rsdp(2, xsdt.as_ptr());
+
+    let out = dump(&rsdp);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "RSDP: oem_id BOCHS  revision 2 checksum ok");
+    assert!(lines[1].starts_with("XSDT: ") && lines[1].ends_with("length 60 checksum ok"));
+    assert!(lines[2].starts_with("  [0] FACP at ") && lines[2].ends_with("length 116 checksum ok"));
+    assert_eq!(lines[3], "  [1] null entry");
+    assert!(lines[4].starts_with("  [2] APIC at ") && lines[4].ends_with("checksum BAD"));
+    assert_eq!(lines.len(), 5);
+}
+
+#[test]
+fn tables_at_offset() {
+    /* Like a direct map, the tables are `OFFSET` above their addresses */
+    const OFFSET: u64 = 0x1000;
+    let facp = table(b"FACP", 0x74);
+    let mut xsdt = xsdt(&[facp.as_ptr() as u64 - OFFSET]);
+    /* Half an entry at the end isn't one */
+    xsdt.extend_from_slice(&[0xff; 4]);
+    let len = xsdt.len() as u32;
+    xsdt[4..8].copy_from_slice(&len.to_le_bytes());
+    fix_checksum(&mut xsdt, 9);
+    let rsdp = rsdp(2, (xsdt.as_ptr() as u64 - OFFSET) as *const u8);
+
+    let mut out = String::new();
+    unsafe { acpi::dump(&rsdp, OFFSET, &mut out).unwrap() };
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[1].ends_with("length 48 checksum ok"));
+    assert!(lines[2].starts_with("  [0] FACP at ") && lines[2].ends_with("checksum ok"));
+    assert_eq!(lines.len(), 3);
+}
+
+#[test]
+fn broken_xsdt() {
+    assert_eq!(
+        dump(&rsdp(0, core::ptr::null())),
+        "RSDP: oem_id BOCHS  revision 0 checksum ok\nXSDT: missing\n"
+    );
+    assert!(dump(&rsdp(2, core::ptr::null())).ends_with("XSDT: missing\n"));
+
+    let rsdt = table(b"RS\0T", 36);
+    assert!(dump(&rsdp(2, rsdt.as_ptr())).ends_with("XSDT: wrong signature RS?T\n"));
+
+    let mut short = table(b"XSDT", 36);
+    short[4..8].copy_from_slice(&20u32.to_le_bytes());
+    assert!(dump(&rsdp(2, short.as_ptr())).ends_with("XSDT: length 20 is too short\n"));
+}
+
+/* MADT with the given (type, flags) CPU entries and an I/O APIC */
+fn madt(cpus: &[(u8, u32)]) -> Vec<u8> {
+    let mut bytes = table(b"APIC", HEADER_SIZE);
+    bytes.extend_from_slice(&0xfee0_0000u32.to_le_bytes());
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    for (i, &(typ, flags)) in cpus.iter().enumerate() {
+        match typ {
+            0 => {
+                bytes.extend_from_slice(&[0, 8, i as u8, i as u8]);
+                bytes.extend_from_slice(&flags.to_le_bytes());
+            }
+            _ => {
+                bytes.extend_from_slice(&[9, 16, 0, 0]);
+                bytes.extend_from_slice(&(i as u32).to_le_bytes());
+                bytes.extend_from_slice(&flags.to_le_bytes());
+                bytes.extend_from_slice(&(i as u32).to_le_bytes());
+            }
+        }
+    }
+    bytes.extend_from_slice(&[1, 12, 0, 0, 0, 0, 0xc0, 0xfe, 0, 0, 0, 0]);
+
+    let len = bytes.len() as u32;
+    bytes[4..8].copy_from_slice(&len.to_le_bytes());
+    fix_checksum(&mut bytes, 9);
+    return bytes;
+}
+
+fn discover(rsdp: &Rsdp) -> Result<AcpiContext, AcpiError> {
+    return unsafe { AcpiContext::discover(rsdp, 0) };
+}
+
+#[test]
+fn madt_cpus() {
+    /* Enabled, disabled, online capable x2APIC, enabled */
+    let madt = madt(&[(0, 1), (0, 0), (9, 2), (0, 1)]);
+    let facp = table(b"FACP", 0x74);
+    let xsdt = xsdt(&[facp.as_ptr() as u64, madt.as_ptr() as u64]);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+
+    assert_eq!(acpi.root(), *b"XSDT");
+    assert_eq!(acpi.table(*b"FACP").unwrap().as_ptr(), facp.as_ptr());
+    assert_eq!(acpi.table(*b"FACP").unwrap().len(), 0x74);
+    assert_eq!(acpi.table_phys(*b"APIC"), Some(madt.as_ptr() as u64));
+    assert!(acpi.table(*b"HPET").is_none());
+    assert_eq!(acpi::madt_cpu_count(&acpi), Some(3));
+}
+
+#[test]
+fn no_madt() {
+    let facp = table(b"FACP", 0x74);
+    let without = xsdt(&[facp.as_ptr() as u64]);
+    let acpi = discover(&rsdp(2, without.as_ptr())).unwrap();
+    assert_eq!(acpi::madt_cpu_count(&acpi), None);
+
+    /* Entry claiming to go past the end of the table */
+    let mut broken = madt(&[(0, 1)]);
+    let len = broken.len();
+    broken[len - 11] = 40;
+    fix_checksum(&mut broken, 9);
+    let xsdt = xsdt(&[broken.as_ptr() as u64]);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+    assert_eq!(acpi::madt_cpu_count(&acpi), None);
+}
+
+fn hpet(space: u8, addr: u64) -> Vec<u8> {
+    let mut bytes = table(b"HPET", 56);
+    bytes[40] = space;
+    bytes[44..52].copy_from_slice(&addr.to_le_bytes());
+    fix_checksum(&mut bytes, 9);
+    return bytes;
+}
+
+/* FADT with the reset register flag and the register set */
+fn fadt(supported: bool, space: u8, addr: u64, value: u8) -> Vec<u8> {
+    let mut bytes = table(b"FACP", 0x114);
+    let flags: u32 = if supported { 1 << 10 } else { 0 };
+    bytes[112..116].copy_from_slice(&flags.to_le_bytes());
+    bytes[116] = space;
+    bytes[117] = 8;
+    bytes[120..128].copy_from_slice(&addr.to_le_bytes());
+    bytes[128] = value;
+    fix_checksum(&mut bytes, 9);
+    return bytes;
+}
+
+#[test]
+fn missing_and_corrupt() {
+    /* No HPET, and a FADT that was patched without fixing the checksum */
+    let madt = madt(&[(0, 1), (0, 1)]);
+    let mut facp = fadt(true, 1, 0xcf9, 6);
+    facp[128] = 0xe;
+    let xsdt = xsdt(&[facp.as_ptr() as u64, 0, madt.as_ptr() as u64]);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+
+    assert_eq!(
+        acpi.skipped(),
+        [SkippedTable {
+            signature: *b"FACP",
+            phys: facp.as_ptr() as u64,
+            reason: SkipReason::Checksum {
+                checksum: facp[9],
+                sum: 8,
+            },
+        }]
+    );
+    assert_eq!(acpi.dropped(), 0);
+    assert_eq!(acpi::madt_cpu_count(&acpi), Some(2));
+    assert_eq!(acpi::hpet_address(&acpi), None);
+    assert_eq!(acpi::reset_register(&acpi), None);
+}
+
+#[test]
+fn consumers() {
+    let hpet = hpet(0, 0xfed0_0000);
+    let facp = fadt(true, 1, 0xcf9, 6);
+    let xsdt = xsdt(&[hpet.as_ptr() as u64, facp.as_ptr() as u64]);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+    assert_eq!(acpi::hpet_address(&acpi), Some(0xfed0_0000));
+    assert_eq!(
+        acpi::reset_register(&acpi),
+        Some(ResetRegister {
+            space: acpi::GAS_SYSTEM_IO,
+            value: 6,
+            address: 0xcf9,
+        })
+    );
+
+    /* HPET in I/O space and a FADT without the reset flag */
+    let hpet = self::hpet(1, 0x40);
+    let facp = fadt(false, 1, 0xcf9, 6);
+    let xsdt = self::xsdt(&[hpet.as_ptr() as u64, facp.as_ptr() as u64]);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+    assert_eq!(acpi::hpet_address(&acpi), None);
+    assert_eq!(acpi::reset_register(&acpi), None);
+
+    /* ACPI 1.0 FADT, too short for the reset register */
+    let facp = table(b"FACP", 0x74);
+    let xsdt = self::xsdt(&[facp.as_ptr() as u64]);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+    assert_eq!(acpi::reset_register(&acpi), None);
+}
+
+#[test]
+fn broken_root() {
+    assert_eq!(
+        discover(&rsdp(0, core::ptr::null())).err(),
+        Some(AcpiError::NoRootTable)
+    );
+    assert_eq!(
+        discover(&rsdp(2, core::ptr::null())).err(),
+        Some(AcpiError::NoRootTable)
+    );
+
+    let mut bad = rsdp(2, core::ptr::null());
+    bad.old.oem_id = *b"BOCHS!";
+    assert_eq!(discover(&bad).err(), Some(AcpiError::RsdpChecksum));
+
+    let rsdt = table(b"RSDT", 36);
+    assert_eq!(
+        discover(&rsdp(2, rsdt.as_ptr())).err(),
+        Some(AcpiError::RootSignature(*b"RSDT"))
+    );
+
+    let mut xsdt = xsdt(&[]);
+    xsdt[20] = 1;
+    let sum = xsdt.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    assert_eq!(
+        discover(&rsdp(2, xsdt.as_ptr())).err(),
+        Some(AcpiError::RootChecksum {
+            checksum: xsdt[9],
+            sum
+        })
+    );
+}
+
+/* Tables laid out in one buffer, the RSDT only holds 32 bit addresses */
+#[test]
+fn rsdt_fallback() {
+    const RSDT_AT: usize = 0x100;
+    const MADT_AT: usize = 0x200;
+    let madt = madt(&[(0, 1)]);
+    let mut rsdt = table(b"RSDT", HEADER_SIZE + 8);
+    rsdt[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&(MADT_AT as u32).to_le_bytes());
+    fix_checksum(&mut rsdt, 9);
+
+    let mut memory = vec![0u8; 0x1000];
+    memory[RSDT_AT..RSDT_AT + rsdt.len()].copy_from_slice(&rsdt);
+    memory[MADT_AT..MADT_AT + madt.len()].copy_from_slice(&madt);
+    let offset = memory.as_ptr() as u64;
+
+    /* No XSDT at all, and one with a bad checksum */
+    let mut xsdt = xsdt(&[]);
+    xsdt[20] = 1;
+    for rsdp in &[
+        rsdp_with_rsdt(0, core::ptr::null(), RSDT_AT as u32),
+        rsdp_with_rsdt(
+            2,
+            (xsdt.as_ptr() as u64).wrapping_sub(offset) as *const u8,
+            RSDT_AT as u32,
+        ),
+    ] {
+        let acpi = unsafe { AcpiContext::discover(rsdp, offset) }.unwrap();
+        assert_eq!(acpi.root(), *b"RSDT");
+        assert_eq!(acpi.table_phys(*b"APIC"), Some(MADT_AT as u64));
+        assert_eq!(acpi::madt_cpu_count(&acpi), Some(1));
+    }
+}
+
+/* FPDT and the FBPT it points to, with the FBPT at 0x7a4f_e000 */
+const FPDT: &[u8] = include_bytes!("fixtures/fpdt.bin");
+const FBPT: &[u8] = include_bytes!("fixtures/fbpt.bin");
+
+#[test]
+fn fpdt_fixture() {
+    assert_eq!(acpi::fpdt_fbpt_address(FPDT), Some(0x7a4f_e000));
+    assert_eq!(
+        acpi::parse_fbpt(FBPT),
+        Some(FirmwareBootPerformance {
+            reset_end: 1_876_512_345,
+            loader_load_image: 6_012_773_880,
+            loader_start_image: 6_031_405_112,
+            exit_boot_services_entry: 0,
+            exit_boot_services_exit: 0,
+        })
+    );
+}
+
+#[test]
+fn malformed_fbpt() {
+    /* Length past the end, and a record cut short by it */
+    let mut fbpt = FBPT.to_vec();
+    fbpt[4] += 1;
+    assert_eq!(acpi::parse_fbpt(&fbpt), None);
+    fbpt[4] -= 9;
+    assert_eq!(acpi::parse_fbpt(&fbpt), None);
+
+    /* A record of length 0 ends the walk */
+    let mut fbpt = FBPT.to_vec();
+    fbpt[10] = 0;
+    assert_eq!(acpi::parse_fbpt(&fbpt), None);
+
+    let mut fbpt = FBPT.to_vec();
+    fbpt[0] = b'X';
+    assert_eq!(acpi::parse_fbpt(&fbpt), None);
+    assert_eq!(acpi::parse_fbpt(&FBPT[..6]), None);
+
+    /* Only an S3 pointer */
+    assert_eq!(acpi::fpdt_fbpt_address(&FPDT[..HEADER_SIZE]), None);
+    let mut fpdt = FPDT.to_vec();
+    fpdt[HEADER_SIZE] = 1;
+    assert_eq!(acpi::fpdt_fbpt_address(&fpdt), None);
+}
+
+#[test]
+fn truncated_fixtures() {
+    /* Every prefix parses to nothing or to the whole thing, without panicking */
+    for len in 0..FBPT.len() {
+        assert_eq!(acpi::parse_fbpt(&FBPT[..len]), None, "{} bytes", len);
+    }
+    for len in 0..=FPDT.len() {
+        let address = acpi::fpdt_fbpt_address(&FPDT[..len]);
+        assert!(address.is_none() || address == Some(0x7a4f_e000), "{} bytes", len);
+    }
+}
+
+#[test]
+fn firmware_boot_performance() {
+    let fbpt = FBPT.to_vec();
+    let mut fpdt = FPDT.to_vec();
+    fpdt[HEADER_SIZE + 8..HEADER_SIZE + 16].copy_from_slice(&(fbpt.as_ptr() as u64).to_le_bytes());
+    fix_checksum(&mut fpdt, 9);
+    let xsdt = xsdt(&[fpdt.as_ptr() as u64]);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+    assert_eq!(acpi::fbpt_address(&acpi), Some(fbpt.as_ptr() as u64));
+    assert_eq!(acpi::firmware_boot_performance(&acpi), acpi::parse_fbpt(FBPT));
+
+    /* Pointing somewhere that isn't a FBPT */
+    let madt = madt(&[]);
+    fpdt[HEADER_SIZE + 8..HEADER_SIZE + 16].copy_from_slice(&(madt.as_ptr() as u64).to_le_bytes());
+    fix_checksum(&mut fpdt, 9);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+    assert_eq!(acpi::firmware_boot_performance(&acpi), None);
+
+    let xsdt = self::xsdt(&[]);
+    let acpi = discover(&rsdp(2, xsdt.as_ptr())).unwrap();
+    assert_eq!(acpi::firmware_boot_performance(&acpi), None);
+}
@@ -0,0 +1,63 @@
+use cpu::cache::{self, FlushMethod};
+use cpu::phys::{IdentityMapping, OffsetMapping};
+use cpu::PhysRange;
+
+#[test]
+fn line_size_from_cpuid() {
+    let size = cache::line_size();
+    assert!(size.is_power_of_two());
+    assert!((32..=256).contains(&size));
+}
+
+#[test]
+fn flush_method_agrees_with_cpuid() {
+    let clflushopt = cache::flush_method() == FlushMethod::Clflushopt;
+    assert_eq!(clflushopt, cache::has_clflushopt());
+}
+
+#[test]
+fn lines_stride() {
+    let lines: Vec<_> = cache::lines(0x1010, 0x80, 64).collect();
+    assert_eq!(lines, [0x1000, 0x1040, 0x1080]);
+    assert_eq!(cache::lines(0x1000, 64, 64).count(), 1);
+    assert_eq!(cache::lines(0x103F, 2, 64).count(), 2);
+    assert_eq!(cache::lines(0x1000, 0, 64).count(), 0);
+
+    for window in cache::lines(0x12345, 0x1000, 128)
+        .collect::<Vec<_>>()
+        .windows(2)
+    {
+        assert_eq!(window[1] - window[0], 128);
+    }
+}
+
+#[test]
+fn flush_range_buffer() {
+    let mut buf = vec![0x5Au8; 0x3000];
+    let start = buf.as_ptr() as u64 + 3;
+    let range = PhysRange::new(start, 0x2000).unwrap();
+
+    let expected = cache::lines(start, 0x2000, cache::line_size()).count() as u64;
+    let flushed = unsafe { cache::flush_range(range, &IdentityMapping) };
+    assert_eq!(flushed, expected);
+    assert!(flushed >= 0x2000 / cache::line_size());
+
+    /* Flushing doesn't change what's there */
+    buf[0x100] = 0xA5;
+    unsafe { cache::flush_range(range, &IdentityMapping) };
+    assert_eq!(buf[0x100], 0xA5);
+    assert_eq!(buf[0x101], 0x5A);
+
+    /* Through another mapping of the same memory */
+    let offset = OffsetMapping(0x1000);
+    let shifted = PhysRange::new(start - 0x1000, 0x2000).unwrap();
+    assert_eq!(unsafe { cache::flush_range(shifted, &offset) }, expected);
+
+    assert_eq!(
+        unsafe { cache::flush_range(PhysRange::empty(), &IdentityMapping) },
+        0
+    );
+    cache::mfence();
+    cache::sfence();
+    cache::lfence();
+}
@@ -0,0 +1,41 @@
+use cpu::paging::{PDEntry, PDFlags};
+use cpu::{PhysAddr, PhysSlice, Ring, VirtAddr};
+
+#[test]
+fn addresses() {
+    let phys = PhysAddr::<u8>::new(0x1234_5000).unwrap();
+    assert_eq!(format!("{:?}", phys), "PhysAddr(0x0000_0000_1234_5000)");
+
+    let virt = VirtAddr::<u8>::new(0xffff_ffff_c000_0000);
+    assert_eq!(format!("{:?}", virt), "VirtAddr(0xffff_ffff_c000_0000)");
+}
+
+#[test]
+fn slices() {
+    let bytes = PhysSlice::<u8>::new(PhysAddr::new(0x20_0000).unwrap(), 6144);
+    assert_eq!(
+        format!("{:?}", bytes),
+        "PhysSlice { addr: 0x0000_0000_0020_0000, len: 6144, size: 6 KiB }"
+    );
+
+    let qwords = PhysSlice::<u64>::new(PhysAddr::new(0x1000).unwrap(), 512);
+    assert_eq!(
+        format!("{:?}", qwords),
+        "PhysSlice { addr: 0x0000_0000_0000_1000, len: 512, size: 4 KiB }"
+    );
+}
+
+#[test]
+fn page_entries() {
+    let flags = PDFlags::new().set_present().set_leaf().set_nx();
+    let entry = PDEntry::new(PhysAddr::new(0x40_0000).unwrap(), flags);
+    assert_eq!(
+        format!("{:?}", entry),
+        "PDEntry { addr: 0x0000_0000_0040_0000, flags: present | leaf | nx }"
+    );
+}
+
+#[test]
+fn ring() {
+    assert_eq!(format!("{:?}", Ring::Three), "Three (0x3)");
+}
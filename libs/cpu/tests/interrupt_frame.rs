@@ -0,0 +1,59 @@
+use core::mem::{align_of, size_of};
+use cpu::interrupt::Stack;
+use cpu::{InterruptStackFrame, PageFaultErrorCode, PageFaultStackFrame};
+
+#[test]
+fn layout() {
+    assert_eq!(size_of::<InterruptStackFrame>(), 40);
+    assert_eq!(align_of::<InterruptStackFrame>(), 8);
+    assert_eq!(size_of::<PageFaultStackFrame>(), 48);
+
+    /* Pushed SS, RSP, RFLAGS, CS, RIP, error code - lowest address last */
+    let pushed: [u64; 6] = [0b110, 0x1000, 0x08, 0x202, 0x7ff0, 0x10];
+    let frame = unsafe { &*(pushed.as_ptr() as *const PageFaultStackFrame) };
+    assert_eq!(frame.frame.rip, 0x1000);
+    assert_eq!(frame.frame.cs, 0x08);
+    assert_eq!(frame.frame.rflags, 0x202);
+    assert_eq!(frame.frame.rsp, 0x7ff0);
+    assert_eq!(frame.frame.ss, 0x10);
+    assert!(frame.error_code.write() && frame.error_code.user());
+    assert!(!frame.error_code.present());
+}
+
+#[test]
+fn from_saved_stack() {
+    let mut raw = [0u64; 22];
+    raw[15] = 1; /* has_error_code */
+    raw[16] = 0b10001; /* error code */
+    raw[17] = 0xffff_ffff_c000_1234;
+    raw[18] = 0x08;
+    raw[19] = 0x2;
+    raw[20] = 0xffff_ffff_c010_0000;
+    raw[21] = 0x10;
+
+    let stack = unsafe { &*(raw.as_ptr() as *const Stack) };
+    assert_eq!(stack.error_code(), Some(0b10001));
+    assert_eq!(stack.frame().rip, 0xffff_ffff_c000_1234);
+    assert_eq!(stack.frame().ss, 0x10);
+}
+
+#[test]
+fn debug() {
+    let frame = PageFaultStackFrame {
+        error_code: PageFaultErrorCode(0b10001),
+        frame: InterruptStackFrame {
+            rip: 0xffff_ffff_c000_1234,
+            cs: 0x08,
+            rflags: 0x202,
+            rsp: 0xffff_ffff_c010_0000,
+            ss: 0x10,
+        },
+    };
+
+    assert_eq!(
+        format!("{:?}", frame),
+        "PageFaultStackFrame { error_code: present | instruction_fetch, \
+         frame: InterruptStackFrame { rip: 0xffff_ffff_c000_1234, cs: 0x8, \
+         rflags: 0x202, rsp: 0xffff_ffff_c010_0000, ss: 0x10 } }"
+    );
+}
@@ -0,0 +1,168 @@
+use cpu::mapper::{MapError, MapStats, Mapper, TableAlloc};
+use cpu::paging::{self, PDFlags, PML4Entry, PTEntry, Table, GIGAPAGE_SIZE, MEGAPAGE_SIZE};
+use cpu::{PhysAddr, VirtAddr};
+
+const DIRECT_MAP: u64 = 0xFFFF_8000_0000_0000;
+const TIB: u64 = 1 << 40;
+
+/// Leaked heap tables, host addresses stand in for physical ones
+struct HeapTables {
+    left: usize,
+}
+
+impl TableAlloc for HeapTables {
+    fn alloc_table(&mut self) -> Option<PhysAddr> {
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+        let table: &'static mut Table<PTEntry> = Box::leak(Box::new(Table::new()));
+        return PhysAddr::new(table as *mut _ as u64);
+    }
+}
+
+fn root() -> &'static mut Table<PML4Entry> {
+    Box::leak(Box::new(Table::new()))
+}
+
+fn mapper(root: &mut Table<PML4Entry>, tables: usize) -> Mapper<'_, HeapTables> {
+    unsafe { Mapper::new(root, HeapTables { left: tables }) }
+}
+
+fn flags() -> PDFlags {
+    PDFlags::new().set_present().set_writable().set_nx()
+}
+
+fn translate(root: &Table<PML4Entry>, virt: u64) -> Option<u64> {
+    unsafe { paging::translate(root, VirtAddr::new(virt)).map(|x| x.as_u64()) }
+}
+
+/* xorshift, for sampling addresses */
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Maps `count` megapages both ways, checks they agree on sampled
+/// addresses and that bulk does no more work, returns the stats of the
+/// naive and the bulk path
+fn compare(virt: u64, phys: u64, count: u64) -> (MapStats, MapStats) {
+    let (naive_root, bulk_root) = (root(), root());
+
+    let mut naive = mapper(naive_root, usize::MAX);
+    for i in 0..count {
+        let offset = i * MEGAPAGE_SIZE;
+        naive.map_2m(virt + offset, phys + offset, flags()).unwrap();
+    }
+    let naive_stats = naive.stats;
+
+    let mut bulk = mapper(bulk_root, usize::MAX);
+    bulk.map_range_2m(virt, phys, count, flags()).unwrap();
+    let bulk_stats = bulk.stats;
+    assert_eq!(bulk_stats.entries, naive_stats.entries);
+    assert!(bulk_stats.tables_allocated <= naive_stats.tables_allocated);
+    assert!(bulk_stats.walks < naive_stats.walks);
+
+    let len = count * MEGAPAGE_SIZE;
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    let samples = (0..4096).map(|_| rng.next() % len).chain([0, len - 1]);
+    for offset in samples {
+        let expected = Some(phys + offset);
+        assert_eq!(translate(naive_root, virt + offset), expected);
+        assert_eq!(translate(bulk_root, virt + offset), expected);
+    }
+    assert_eq!(translate(bulk_root, virt + len), None);
+    if virt != 0 {
+        assert_eq!(translate(bulk_root, virt - 1), None);
+    }
+
+    return (naive_stats, bulk_stats);
+}
+
+#[test]
+fn direct_map_1tib() {
+    let count = TIB / MEGAPAGE_SIZE;
+    let (naive, bulk) = compare(DIRECT_MAP, 0, count);
+
+    /* 2 PDPs and 1024 PDs either way */
+    assert_eq!(naive.tables_allocated, 2 + 1024);
+    assert_eq!(bulk.tables_allocated, naive.tables_allocated);
+    assert_eq!(bulk.entries, count);
+    assert_eq!(naive.entries, count);
+
+    /* A walk per megapage against a check and a fill per 1GiB */
+    assert_eq!(naive.walks, count);
+    assert_eq!(bulk.walks, 2 * 1024);
+}
+
+#[test]
+fn unaligned_chunks() {
+    /* Starts and ends mid-PD and crosses a PML4 entry */
+    let virt = 512 * GIGAPAGE_SIZE - 3 * MEGAPAGE_SIZE;
+    let (naive, bulk) = compare(virt, 0x40_0000, 3 + 512 + 100);
+    assert_eq!(bulk.tables_allocated, naive.tables_allocated);
+    assert_eq!(bulk.entries, naive.entries);
+    /* Chunks of 3, 512 and 100 megapages */
+    assert_eq!(bulk.walks, 2 * 3);
+}
+
+#[test]
+fn conflict_maps_nothing() {
+    let mut mapper = mapper(root(), usize::MAX);
+    let busy = DIRECT_MAP + GIGAPAGE_SIZE + 5 * MEGAPAGE_SIZE;
+    mapper.map_2m(busy, 0, flags()).unwrap();
+    let before = mapper.stats;
+
+    assert_eq!(
+        mapper.map_range_2m(DIRECT_MAP, 0, 1024, flags()),
+        Err(MapError::Conflict)
+    );
+    assert_eq!(mapper.stats.entries, before.entries);
+    assert_eq!(mapper.stats.tables_allocated, before.tables_allocated);
+    assert_eq!(translate(mapper.root(), DIRECT_MAP), None);
+
+    /* Around it is fine */
+    let count = (busy - DIRECT_MAP) / MEGAPAGE_SIZE;
+    mapper.map_range_2m(DIRECT_MAP, 0, count, flags()).unwrap();
+    assert_eq!(translate(mapper.root(), DIRECT_MAP + 0x1234), Some(0x1234));
+}
+
+#[test]
+fn errors() {
+    let mut mapper = mapper(root(), 2);
+    let m = MEGAPAGE_SIZE;
+
+    assert_eq!(
+        mapper.map_range_2m(DIRECT_MAP + 4096, 0, 1, flags()),
+        Err(MapError::Misaligned)
+    );
+    assert_eq!(mapper.map_2m(0, m / 2, flags()), Err(MapError::Misaligned));
+    /* Crosses into the non-canonical hole */
+    assert_eq!(
+        mapper.map_range_2m((1 << 47) - m, 0, 2, flags()),
+        Err(MapError::OutOfRange)
+    );
+    assert_eq!(
+        mapper.map_range_2m(0, 1 << 52, 1, flags()),
+        Err(MapError::OutOfRange)
+    );
+    assert_eq!(
+        mapper.map_range_2m(0, 0, u64::MAX, flags()),
+        Err(MapError::OutOfRange)
+    );
+    assert_eq!(mapper.map_range_2m(0, 0, 0, flags()), Ok(()));
+    /* The top of the address space */
+    assert_eq!(mapper.map_2m(0u64.wrapping_sub(m), 0, flags()), Ok(()));
+
+    /* Both tables were used up by the last one */
+    assert_eq!(
+        mapper.map_range_2m(DIRECT_MAP, 0, 1, flags()),
+        Err(MapError::OutOfTables)
+    );
+}
@@ -0,0 +1,232 @@
+use cpu::microcode::{CpuSignature, IntelMicrocode, MicrocodeError, MicrocodeFile, HEADER_SIZE};
+
+const SIGNATURE: u32 = 0x000906EA;
+const REVISION: u32 = 0xDE;
+
+fn put(bytes: &mut [u8], at: usize, x: u32) {
+    bytes[at..at + 4].copy_from_slice(&x.to_le_bytes());
+}
+
+/// Adds `delta` to the dword at `at`
+fn add(bytes: &mut [u8], at: usize, delta: u32) {
+    let x = u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+    put(bytes, at, x.wrapping_add(delta));
+}
+
+fn sum(bytes: &[u8]) -> u32 {
+    bytes.chunks_exact(4).fold(0u32, |sum, x| {
+        sum.wrapping_add(u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+    })
+}
+
+/// Update for `SIGNATURE` on platforms 1 and 4, with the given extended
+/// signatures, padded to a 1KiB multiple
+fn update(extended: &[(u32, u32)]) -> Vec<u8> {
+    let data_size = 1000;
+    let ext_size = if extended.is_empty() {
+        0
+    } else {
+        20 + 12 * extended.len()
+    };
+    let total = HEADER_SIZE + data_size + ext_size;
+    let total = (total + 1023) & !1023;
+    /* Extended table right after the data, the rest of the data area is padding */
+    let data_size = total - HEADER_SIZE - ext_size;
+
+    let mut bytes = vec![0u8; total];
+    put(&mut bytes, 0, 1);
+    put(&mut bytes, 4, REVISION);
+    put(&mut bytes, 8, 0x0302_2021);
+    put(&mut bytes, 12, SIGNATURE);
+    put(&mut bytes, 20, 1);
+    put(&mut bytes, 24, 0b10010);
+    put(&mut bytes, 28, data_size as u32);
+    put(&mut bytes, 32, total as u32);
+    for (i, byte) in bytes[HEADER_SIZE..HEADER_SIZE + data_size]
+        .iter_mut()
+        .enumerate()
+    {
+        *byte = (i * 7) as u8;
+    }
+
+    let checksum = sum(&bytes).wrapping_neg();
+    put(&mut bytes, 16, checksum);
+
+    if !extended.is_empty() {
+        let table = HEADER_SIZE + data_size;
+        put(&mut bytes, table, extended.len() as u32);
+        let triple = SIGNATURE.wrapping_add(0b10010).wrapping_add(checksum);
+        for (i, &(signature, flags)) in extended.iter().enumerate() {
+            let at = table + 20 + i * 12;
+            put(&mut bytes, at, signature);
+            put(&mut bytes, at + 4, flags);
+            put(
+                &mut bytes,
+                at + 8,
+                triple.wrapping_sub(signature).wrapping_sub(flags),
+            );
+        }
+        let ext_checksum = sum(&bytes[table..table + ext_size]).wrapping_neg();
+        put(&mut bytes, table + 4, ext_checksum);
+
+        /* The extended table is part of the update's checksum too */
+        put(&mut bytes, 16, 0);
+        let checksum = sum(&bytes).wrapping_neg();
+        put(&mut bytes, 16, checksum);
+    }
+    return bytes;
+}
+
+fn cpu(signature: u32, platform_id: u8) -> CpuSignature {
+    CpuSignature {
+        signature,
+        platform_id,
+    }
+}
+
+#[test]
+fn parse_valid() {
+    let bytes = update(&[]);
+    let update = IntelMicrocode::parse(&bytes).unwrap();
+    assert_eq!(update.header.revision, REVISION);
+    assert_eq!(update.header.signature, SIGNATURE);
+    assert_eq!(update.header.total_size, 1024 * 2);
+    assert_eq!(update.data().len(), update.header.data_size);
+    assert_eq!(update.data()[7], 49);
+
+    /* The next update of the file is ignored */
+    let mut file = bytes.clone();
+    file.extend_from_slice(&[0xFF; 100]);
+    assert_eq!(IntelMicrocode::parse(&file).unwrap().as_bytes(), &bytes[..]);
+}
+
+#[test]
+fn matching() {
+    let bytes = update(&[]);
+    let update = IntelMicrocode::parse(&bytes).unwrap();
+
+    assert!(update.matches(cpu(SIGNATURE, 1)));
+    assert!(update.matches(cpu(SIGNATURE, 4)));
+    assert!(!update.matches(cpu(SIGNATURE, 0)));
+    assert!(!update.matches(cpu(SIGNATURE + 1, 1)));
+
+    assert_eq!(update.check(cpu(SIGNATURE, 1), 0xB4), Ok(()));
+    assert_eq!(
+        update.check(cpu(SIGNATURE, 2), 0xB4),
+        Err(MicrocodeError::SignatureMismatch)
+    );
+    assert_eq!(
+        update.check(cpu(SIGNATURE, 1), REVISION),
+        Err(MicrocodeError::NotNewer {
+            current: REVISION,
+            update: REVISION
+        })
+    );
+}
+
+#[test]
+fn extended_signatures() {
+    let bytes = update(&[(0x000906EB, 0b1), (0x000906EC, 0b100)]);
+    let update = IntelMicrocode::parse(&bytes).unwrap();
+    assert_eq!(update.signatures().count(), 3);
+
+    assert!(update.matches(cpu(0x000906EB, 0)));
+    assert!(!update.matches(cpu(0x000906EB, 2)));
+    assert!(update.matches(cpu(0x000906EC, 2)));
+    assert!(update.matches(cpu(SIGNATURE, 4)));
+}
+
+#[test]
+fn corrupt() {
+    let good = update(&[]);
+
+    let mut bytes = good.clone();
+    bytes[HEADER_SIZE + 10] ^= 1;
+    assert_eq!(
+        IntelMicrocode::parse(&bytes).unwrap_err(),
+        MicrocodeError::BadChecksum
+    );
+
+    assert_eq!(
+        IntelMicrocode::parse(&good[..good.len() - 4]).unwrap_err(),
+        MicrocodeError::Truncated
+    );
+    assert_eq!(
+        IntelMicrocode::parse(&good[..40]).unwrap_err(),
+        MicrocodeError::Truncated
+    );
+
+    let mut bytes = good.clone();
+    put(&mut bytes, 0, 2);
+    assert_eq!(
+        IntelMicrocode::parse(&bytes).unwrap_err(),
+        MicrocodeError::UnsupportedFormat
+    );
+
+    let mut bytes = good.clone();
+    put(&mut bytes, 32, 1000);
+    assert_eq!(
+        IntelMicrocode::parse(&bytes).unwrap_err(),
+        MicrocodeError::BadSize
+    );
+}
+
+#[test]
+fn corrupt_extended_table() {
+    let good = update(&[(0x000906EB, 0b1)]);
+    /* One extended signature, the table is the last 32 bytes */
+    let table = good.len() - 32;
+    let update = IntelMicrocode::parse(&good).unwrap();
+    assert_eq!(table, HEADER_SIZE + update.header.data_size);
+
+    /* Signature changed and the main checksum fixed up, the entry's own
+     * checksum and the table's give it away */
+    let mut bytes = good.clone();
+    add(&mut bytes, table + 20, 1);
+    add(&mut bytes, 16, 1u32.wrapping_neg());
+    assert_eq!(
+        IntelMicrocode::parse(&bytes).unwrap_err(),
+        MicrocodeError::BadExtendedTable
+    );
+
+    /* Count running past the end, main checksum fixed up */
+    let mut bytes = good.clone();
+    put(&mut bytes, table, 1000);
+    add(&mut bytes, 16, 999u32.wrapping_neg());
+    assert_eq!(
+        IntelMicrocode::parse(&bytes).unwrap_err(),
+        MicrocodeError::BadExtendedTable
+    );
+}
+
+#[test]
+fn file_of_updates() {
+    let first = update(&[]);
+    let mut corrupt = update(&[(0x000906EB, 0b1)]);
+    corrupt[HEADER_SIZE] ^= 1;
+    let last = update(&[]);
+
+    let mut file = first.clone();
+    file.extend_from_slice(&corrupt);
+    file.extend_from_slice(&last);
+
+    /* The corrupt one is reported and skipped over */
+    let updates: Vec<_> = MicrocodeFile::new(&file).collect();
+    assert_eq!(updates.len(), 3);
+    assert_eq!(updates[0].0, 0);
+    assert!(updates[0].1.is_ok());
+    assert_eq!(updates[1].0, first.len());
+    assert_eq!(updates[1].1.unwrap_err(), MicrocodeError::BadChecksum);
+    assert_eq!(updates[2].0, first.len() + corrupt.len());
+    assert_eq!(updates[2].1.unwrap().as_bytes(), &last[..]);
+
+    /* Garbage ends the file */
+    let mut file = first.clone();
+    file.extend_from_slice(&[0xFF; 64]);
+    file.extend_from_slice(&last);
+    let updates: Vec<_> = MicrocodeFile::new(&file).collect();
+    assert_eq!(updates.len(), 2);
+    assert_eq!(updates[1].1.unwrap_err(), MicrocodeError::UnsupportedFormat);
+
+    assert_eq!(MicrocodeFile::new(&[]).count(), 0);
+}
@@ -0,0 +1,44 @@
+use cpu::phys::{ByteOrder, PhysReader};
+use cpu::{PhysAddr, PhysSlice};
+
+fn pslice(buf: &[u8]) -> PhysSlice<u8> {
+    let addr = PhysAddr::new(buf.as_ptr() as u64).unwrap();
+    PhysSlice::new(addr, buf.len() as u64)
+}
+
+#[test]
+fn endianness() {
+    let buf = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+    let mut r = unsafe { PhysReader::new(pslice(&buf)) };
+
+    assert_eq!(r.read_u16(ByteOrder::Little), Some(0x0201));
+    assert_eq!(r.read_u16(ByteOrder::Big), Some(0x0304));
+    assert_eq!(r.read_u32(ByteOrder::Big), Some(0x05060708));
+    assert_eq!(r.remaining(), 0);
+
+    r.seek(0).unwrap();
+    assert_eq!(r.read_u64(ByteOrder::Little), Some(0x0807060504030201));
+    r.seek(0).unwrap();
+    assert_eq!(r.read_u64(ByteOrder::Big), Some(0x0102030405060708));
+}
+
+#[test]
+fn boundary() {
+    let buf = [0xAA, 0xBB, 0xCC, 0xDD, 0xEE];
+    let mut r = unsafe { PhysReader::new(pslice(&buf)) };
+
+    assert_eq!(r.read_u32(ByteOrder::Little), Some(0xDDCCBBAA));
+    /* over-read fails without moving the cursor */
+    assert_eq!(r.read_u16(ByteOrder::Little), None);
+    assert_eq!(r.read_u64(ByteOrder::Little), None);
+    assert_eq!(r.position(), 4);
+    assert_eq!(r.read_u8(), Some(0xEE));
+    assert_eq!(r.read_u8(), None);
+    assert_eq!(r.read_bytes(0), Some(&[][..]));
+
+    assert_eq!(r.seek(5), Some(()));
+    assert_eq!(r.seek(6), None);
+    r.seek(2).unwrap();
+    assert_eq!(r.read_bytes(4), None);
+    assert_eq!(r.read_bytes(3), Some(&buf[2..]));
+}
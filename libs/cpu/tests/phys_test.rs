@@ -0,0 +1,82 @@
+use cpu::phys::{self, IdentityMapping};
+use cpu::{PhysAddr, PhysSlice};
+
+/* xorshift, good enough for picking sizes and offsets */
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn pslice(buf: &mut [u8]) -> PhysSlice<u8> {
+    let addr = PhysAddr::new(buf.as_mut_ptr() as u64).unwrap();
+    PhysSlice::new(addr, buf.len() as u64)
+}
+
+#[test]
+fn copy_matches_naive() {
+    let mut rng = Rng(0x1234_5678_9abc_def1);
+    let src: Vec<u8> = (0..8192).map(|_| rng.next() as u8).collect();
+
+    for i in 0..512 {
+        let len = (rng.next() % 4096) as usize;
+        let src_off = (rng.next() % 64) as usize;
+        let dst_off = (rng.next() % 64) as usize;
+        let src = &src[src_off..src_off + len];
+
+        let mut fast = vec![0xAAu8; 4200];
+        let mut naive = fast.clone();
+
+        for (d, s) in naive[dst_off..].iter_mut().zip(src) {
+            *d = *s;
+        }
+
+        let dst = pslice(&mut fast[dst_off..dst_off + len]);
+        unsafe {
+            if i % 2 == 0 {
+                phys::copy(dst, src, &IdentityMapping);
+            } else {
+                phys::copy_qwords(dst, src, &IdentityMapping);
+            }
+        }
+
+        assert_eq!(
+            fast, naive,
+            "len={} src_off={} dst_off={}",
+            len, src_off, dst_off
+        );
+    }
+}
+
+#[test]
+fn zero_matches_naive() {
+    let mut rng = Rng(0xfeed_f00d_dead_beef);
+
+    for i in 0..512 {
+        let len = (rng.next() % 4096) as usize;
+        let off = (rng.next() % 64) as usize;
+
+        let mut fast: Vec<u8> = (0..4200).map(|_| rng.next() as u8).collect();
+        let mut naive = fast.clone();
+
+        for x in naive[off..off + len].iter_mut() {
+            *x = 0;
+        }
+
+        let dst = pslice(&mut fast[off..off + len]);
+        unsafe {
+            if i % 2 == 0 {
+                phys::zero(dst, &IdentityMapping);
+            } else {
+                phys::zero_qwords(dst, &IdentityMapping);
+            }
+        }
+
+        assert_eq!(fast, naive, "len={} off={}", len, off);
+    }
+}
@@ -0,0 +1,63 @@
+use cpu::phys::{IdentityMapping, PhysWrite, PhysWriter};
+use cpu::PhysRange;
+
+fn range_of(buf: &[u8]) -> PhysRange {
+    PhysRange::new(buf.as_ptr() as u64, buf.len() as u64).unwrap()
+}
+
+#[test]
+fn writes_and_logs() {
+    let mut buf = vec![0xAAu8; 64];
+    let base = buf.as_ptr() as u64;
+    let allowed = [range_of(&buf)];
+    let mut writes = Vec::new();
+    let mut log = |w: &PhysWrite| writes.push(*w);
+
+    unsafe {
+        let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+        writer.copy_from_slice("copy", base, b"sovos");
+        writer.write_bytes("zero", base + 8, 0, 8);
+        writer.write_bytes("ones", base + 16, 0xFF, 4);
+        writer.fill("words", base + 32, 0xDEADu16, 16);
+    }
+
+    assert_eq!(&buf[..5], b"sovos");
+    assert_eq!(buf[5], 0xAA);
+    assert!(buf[8..16].iter().all(|&x| x == 0));
+    assert_eq!(&buf[16..21], &[0xFF, 0xFF, 0xFF, 0xFF, 0xAA]);
+    assert_eq!(&buf[32..36], &[0xAD, 0xDE, 0xAD, 0xDE]);
+    assert_eq!(buf[63], 0xDE);
+
+    let purposes: Vec<_> = writes.iter().map(|w| w.purpose).collect();
+    assert_eq!(purposes, ["copy", "zero", "ones", "words"]);
+    assert_eq!(writes[3].range, PhysRange::new(base + 32, 32).unwrap());
+    buf.clear();
+}
+
+#[test]
+fn nearest_allowed() {
+    let r = |start, len| PhysRange::new(start, len).unwrap();
+    let allowed = [r(0x1000, 0x1000), r(0x8000, 0x1000), r(0x10_0000, 0x1000)];
+    let mut log = |_: &PhysWrite| {};
+    let writer = unsafe { PhysWriter::new(&allowed, IdentityMapping, &mut log) };
+
+    assert!(writer.is_allowed(&r(0x1800, 0x800)));
+    assert!(!writer.is_allowed(&r(0x1800, 0x801)));
+    assert!(writer.is_allowed(&r(0x5000, 0)));
+    assert_eq!(writer.nearest_allowed(&r(0x2000, 0x10)), Some(allowed[0]));
+    assert_eq!(writer.nearest_allowed(&r(0x7000, 0x100)), Some(allowed[1]));
+    assert_eq!(writer.nearest_allowed(&r(0x8800, 0x1000)), Some(allowed[1]));
+}
+
+#[test]
+#[should_panic(expected = "poison write to PhysRange(0x0000_0000_0000_2000..0x0000_0000_0000_2010, 16 B) \
+                           outside the whitelist, nearest allowed \
+                           Some(PhysRange(0x0000_0000_0000_1000..0x0000_0000_0000_2000, 4 KiB))")]
+fn violation_panics() {
+    let allowed = [PhysRange::new(0x1000, 0x1000).unwrap()];
+    let mut log = |_: &PhysWrite| {};
+    unsafe {
+        let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+        writer.write_bytes("poison", 0x2000, 0xCC, 0x10);
+    }
+}
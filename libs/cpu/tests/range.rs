@@ -0,0 +1,132 @@
+use cpu::paging::{Megapage, Page};
+use cpu::{PhysAddr, PhysRange, PhysSlice, VirtAddr, VirtRange};
+
+/* xorshift, good enough for picking ranges */
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /* Small ranges near each other, so that they often touch and overlap */
+    fn range(&mut self) -> PhysRange {
+        let start = self.next() % 64;
+        let len = self.next() % 16;
+        PhysRange::new(start, len).unwrap()
+    }
+}
+
+fn addrs(r: &PhysRange) -> Vec<u64> {
+    (r.start()..r.end()).collect()
+}
+
+#[test]
+fn constructors() {
+    assert_eq!(PhysRange::new(0x1000, 0x1000).unwrap().end(), 0x2000);
+    assert_eq!(
+        PhysRange::new(1 << 52, 0),
+        PhysRange::from_start_end(1 << 52, 1 << 52)
+    );
+    assert!(PhysRange::new((1 << 52) - 1, 2).is_none());
+    assert!(PhysRange::new(u64::MAX, 1).is_none());
+    assert!(PhysRange::from_start_end(0x2000, 0x1000).is_none());
+
+    assert!(VirtRange::new(u64::MAX - 1, 1).is_some());
+    assert!(VirtRange::new(u64::MAX, 1).is_none());
+    assert!(VirtRange::from_start_end(0, u64::MAX).is_some());
+}
+
+#[test]
+fn end_is_exclusive() {
+    let r = PhysRange::new(0x1000, 0x1000).unwrap();
+    assert!(r.contains(0x1000));
+    assert!(r.contains(0x1fff));
+    assert!(!r.contains(0x2000));
+
+    let next = PhysRange::new(0x2000, 0x1000).unwrap();
+    assert!(!r.overlaps(&next));
+    assert_eq!(r.intersection(&next), None);
+}
+
+#[test]
+fn set_operations() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+    for _ in 0..10_000 {
+        let a = rng.range();
+        let b = rng.range();
+        let (xs, ys) = (addrs(&a), addrs(&b));
+        let common: Vec<u64> = xs.iter().copied().filter(|x| ys.contains(x)).collect();
+
+        assert_eq!(a.overlaps(&b), !common.is_empty(), "{:?} {:?}", a, b);
+        assert_eq!(a.overlaps(&b), b.overlaps(&a));
+        assert_eq!(a.contains_range(&b), ys.iter().all(|y| xs.contains(y)));
+
+        match a.intersection(&b) {
+            Some(i) => {
+                assert_eq!(addrs(&i), common);
+                assert!(a.contains_range(&i) && b.contains_range(&i));
+            }
+            None => assert!(common.is_empty()),
+        }
+        assert_eq!(a.intersection(&b), b.intersection(&a));
+
+        let at = rng.next() % 96;
+        match a.split_at(at) {
+            Some((low, high)) => {
+                assert_eq!(low.end(), high.start());
+                assert_eq!(low.len() + high.len(), a.len());
+                assert!(!low.overlaps(&high));
+                assert_eq!([addrs(&low), addrs(&high)].concat(), xs);
+            }
+            None => assert!(at < a.start() || at > a.end()),
+        }
+    }
+}
+
+#[test]
+fn pages() {
+    let r = PhysRange::new(0x1800, 0x2000).unwrap();
+    assert!(!r.is_aligned::<Page>());
+    let pages: Vec<u64> = r.pages::<Page>().map(|p| p.as_u64()).collect();
+    assert_eq!(pages, [0x1000, 0x2000, 0x3000]);
+    assert_eq!(r.page_count::<Megapage>(), 1);
+
+    let r = PhysRange::new(0x20_0000, 0x40_0000).unwrap();
+    assert!(r.is_aligned::<Megapage>());
+    let pages: Vec<PhysAddr<Megapage>> = r.pages::<Megapage>().collect();
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[1].as_u64(), 0x40_0000);
+
+    assert_eq!(PhysRange::empty().pages::<Page>().count(), 0);
+
+    /* The last page of the address space doesn't wrap around */
+    let top = VirtRange::from_start_end(u64::MAX - 0xfff, u64::MAX).unwrap();
+    let pages: Vec<VirtAddr<Page>> = top.pages::<Page>().collect();
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].as_u64(), u64::MAX - 0xfff);
+}
+
+#[test]
+fn phys_slice() {
+    let slice = PhysSlice::<Megapage>::new(PhysAddr::new(0x20_0000).unwrap(), 3);
+    let r = PhysRange::from(slice);
+    assert_eq!(r, PhysRange::new(0x20_0000, 0x60_0000).unwrap());
+
+    let bytes: PhysSlice<u8> = r.into();
+    assert_eq!(bytes.addr().as_u64(), 0x20_0000);
+    assert_eq!(bytes.len(), 0x60_0000);
+}
+
+#[test]
+fn debug() {
+    let r = PhysRange::new(0x1000, 0x2000).unwrap();
+    assert_eq!(
+        format!("{:?}", r),
+        "PhysRange(0x0000_0000_0000_1000..0x0000_0000_0000_3000, 8 KiB)"
+    );
+}
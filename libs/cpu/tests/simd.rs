@@ -0,0 +1,32 @@
+#![cfg(feature = "ringzero")]
+
+use cpu::simd::{avx_xcr0, XCR0_AVX, XCR0_SSE, XCR0_X87};
+
+#[test]
+fn xcr0_for_avx() {
+    let all = XCR0_X87 | XCR0_SSE | XCR0_AVX;
+    assert_eq!(avx_xcr0(XCR0_X87, all), Some(all));
+
+    /* Other enabled components are kept */
+    let avx512 = 0b1110_0000;
+    assert_eq!(
+        avx_xcr0(XCR0_X87 | avx512, all | avx512),
+        Some(all | avx512)
+    );
+
+    /* AVX without SSE state is an invalid XCR0 */
+    assert_eq!(avx_xcr0(XCR0_X87, XCR0_X87 | XCR0_AVX), None);
+    assert_eq!(avx_xcr0(XCR0_X87, XCR0_X87 | XCR0_SSE), None);
+}
+
+#[test]
+fn detection_agrees_with_std() {
+    assert_eq!(cpu::simd::has_avx(), std::is_x86_feature_detected!("avx"));
+    assert_eq!(
+        cpu::simd::has_xsave(),
+        std::is_x86_feature_detected!("xsave")
+    );
+    if std::is_x86_feature_detected!("avx") {
+        assert_eq!(cpu::simd::supported_xcr0() & XCR0_AVX, XCR0_AVX);
+    }
+}
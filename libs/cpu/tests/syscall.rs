@@ -0,0 +1,28 @@
+#![cfg(feature = "ringzero")]
+
+use cpu::syscall::{star, SYSCALL_FMASK};
+
+#[test]
+fn star_layout() {
+    /* Same layout as Linux: kernel CS 0x10, user CS 0x33 (RPL 3) */
+    let star = star(0x10, 0x33).unwrap();
+    assert_eq!(star >> 32 & 0xffff, 0x10);
+    assert_eq!(star >> 48, 0x23);
+    assert_eq!(star & 0xffff_ffff, 0);
+
+    /* sysret to 64-bit mode: CS = base + 16, SS = base + 8 */
+    assert_eq!((star >> 48) + 16, 0x33);
+    assert_eq!((star >> 48) + 8, 0x2b);
+}
+
+#[test]
+fn no_room_for_user_descriptors() {
+    assert_eq!(star(0x08, 0x0b), None);
+    assert!(star(0x08, 0x13).is_some());
+}
+
+#[test]
+fn fmask_clears_interrupts() {
+    assert_eq!(SYSCALL_FMASK & (1 << 9), 1 << 9);
+    assert_eq!(SYSCALL_FMASK & (1 << 10), 1 << 10);
+}
@@ -0,0 +1,170 @@
+//! Files whose `ei_data` isn't the host's byte order. Headers are copied
+//! out of the file anyway, so the copies get their fields swapped instead
+//! of the structs having a second, borrowed form.
+
+use crate::{read_unaligned, Data, Dyn, Header, Header32, ProgramHeader, ProgramHeader32};
+use crate::{Rel, Rela, SectionHeader, Symbol};
+use bytemuck::Pod;
+use core::num::NonZeroU64;
+
+impl Data {
+    /// Byte order of the host
+    #[cfg(target_endian = "little")]
+    pub const NATIVE: Self = Self::Lsb;
+    #[cfg(target_endian = "big")]
+    pub const NATIVE: Self = Self::Msb;
+}
+
+/// Something read from a file that may need its multi-byte fields swapped
+pub trait ByteSwap: Pod {
+    fn swap_bytes(self) -> Self;
+}
+
+/// `read_unaligned` from a file with `data` byte order
+pub fn read_in_order<T: ByteSwap>(bytes: &[u8], data: Data) -> T {
+    let x: T = read_unaligned(bytes);
+    if data == Data::NATIVE {
+        return x;
+    }
+    return x.swap_bytes();
+}
+
+fn swap_nonzero(x: Option<NonZeroU64>) -> Option<NonZeroU64> {
+    x.and_then(|x| NonZeroU64::new(x.get().swap_bytes()))
+}
+
+impl ByteSwap for u32 {
+    fn swap_bytes(self) -> Self {
+        u32::swap_bytes(self)
+    }
+}
+
+impl ByteSwap for Header {
+    fn swap_bytes(self) -> Self {
+        Self {
+            e_ident: self.e_ident,
+            e_type: self.e_type.swap_bytes(),
+            e_machine: self.e_machine.swap_bytes(),
+            e_version: self.e_version.swap_bytes(),
+            e_entry: swap_nonzero(self.e_entry),
+            e_phoff: swap_nonzero(self.e_phoff),
+            e_shoff: swap_nonzero(self.e_shoff),
+            e_flags: self.e_flags.swap_bytes(),
+            e_ehsize: self.e_ehsize.swap_bytes(),
+            e_phentsize: self.e_phentsize.swap_bytes(),
+            e_phnum: self.e_phnum.swap_bytes(),
+            e_shentsize: self.e_shentsize.swap_bytes(),
+            e_shnum: self.e_shnum.swap_bytes(),
+            e_shstrndx: self.e_shstrndx.swap_bytes(),
+        }
+    }
+}
+
+impl ByteSwap for Header32 {
+    fn swap_bytes(self) -> Self {
+        Self {
+            e_ident: self.e_ident,
+            e_type: self.e_type.swap_bytes(),
+            e_machine: self.e_machine.swap_bytes(),
+            e_version: self.e_version.swap_bytes(),
+            e_entry: self.e_entry.swap_bytes(),
+            e_phoff: self.e_phoff.swap_bytes(),
+            e_shoff: self.e_shoff.swap_bytes(),
+            e_flags: self.e_flags.swap_bytes(),
+            e_ehsize: self.e_ehsize.swap_bytes(),
+            e_phentsize: self.e_phentsize.swap_bytes(),
+            e_phnum: self.e_phnum.swap_bytes(),
+            e_shentsize: self.e_shentsize.swap_bytes(),
+            e_shnum: self.e_shnum.swap_bytes(),
+            e_shstrndx: self.e_shstrndx.swap_bytes(),
+        }
+    }
+}
+
+impl ByteSwap for ProgramHeader {
+    fn swap_bytes(self) -> Self {
+        Self {
+            p_type: self.p_type.swap_bytes(),
+            p_flags: self.p_flags.swap_bytes(),
+            p_offset: self.p_offset.swap_bytes(),
+            p_vaddr: self.p_vaddr.swap_bytes(),
+            p_paddr: self.p_paddr.swap_bytes(),
+            p_filesz: self.p_filesz.swap_bytes(),
+            p_memsz: self.p_memsz.swap_bytes(),
+            p_align: self.p_align.swap_bytes(),
+        }
+    }
+}
+
+impl ByteSwap for ProgramHeader32 {
+    fn swap_bytes(self) -> Self {
+        Self {
+            p_type: self.p_type.swap_bytes(),
+            p_offset: self.p_offset.swap_bytes(),
+            p_vaddr: self.p_vaddr.swap_bytes(),
+            p_paddr: self.p_paddr.swap_bytes(),
+            p_filesz: self.p_filesz.swap_bytes(),
+            p_memsz: self.p_memsz.swap_bytes(),
+            p_flags: self.p_flags.swap_bytes(),
+            p_align: self.p_align.swap_bytes(),
+        }
+    }
+}
+
+impl ByteSwap for SectionHeader {
+    fn swap_bytes(self) -> Self {
+        Self {
+            sh_name: self.sh_name.swap_bytes(),
+            sh_type: self.sh_type.swap_bytes(),
+            sh_flags: self.sh_flags.swap_bytes(),
+            sh_addr: self.sh_addr.swap_bytes(),
+            sh_offset: self.sh_offset.swap_bytes(),
+            sh_size: self.sh_size.swap_bytes(),
+            sh_link: self.sh_link.swap_bytes(),
+            sh_info: self.sh_info.swap_bytes(),
+            sh_addralign: self.sh_addralign.swap_bytes(),
+            sh_entsize: self.sh_entsize.swap_bytes(),
+        }
+    }
+}
+
+impl ByteSwap for Symbol {
+    fn swap_bytes(self) -> Self {
+        Self {
+            st_name: self.st_name.swap_bytes(),
+            st_info: self.st_info,
+            st_other: self.st_other,
+            st_shndx: self.st_shndx.swap_bytes(),
+            st_value: self.st_value.swap_bytes(),
+            st_size: self.st_size.swap_bytes(),
+        }
+    }
+}
+
+impl ByteSwap for Rela {
+    fn swap_bytes(self) -> Self {
+        Self {
+            r_offset: self.r_offset.swap_bytes(),
+            r_info: self.r_info.swap_bytes(),
+            r_addend: self.r_addend.swap_bytes(),
+        }
+    }
+}
+
+impl ByteSwap for Rel {
+    fn swap_bytes(self) -> Self {
+        Self {
+            r_offset: self.r_offset.swap_bytes(),
+            r_info: self.r_info.swap_bytes(),
+        }
+    }
+}
+
+impl ByteSwap for Dyn {
+    fn swap_bytes(self) -> Self {
+        Self {
+            d_tag: self.d_tag.swap_bytes(),
+            d_val: self.d_val.swap_bytes(),
+        }
+    }
+}
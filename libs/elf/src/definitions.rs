@@ -2,7 +2,11 @@
 #![allow(dead_code)]
 
 use bytemuck::{Contiguous, Pod, Zeroable};
+use crate::{ParseError, StrTab};
+use core::fmt;
 use core::num::NonZeroU64;
+use impl_bits::debug_enum;
+use impl_bits::fmt::{Addr, Size};
 
 pub const MAGIC: [u8; 4] = *b"\x7FELF";
 pub const EV_CURRENT: u8 = 1;
@@ -12,6 +16,14 @@ pub const PT_LOAD: u32 = 1;
 pub const PF_X: u32 = (1 << 0);
 pub const PF_W: u32 = (1 << 1);
 pub const PF_R: u32 = (1 << 2);
+/// Section index of no section, like a missing `.shstrtab`
+pub const SHN_UNDEF: u16 = 0;
+/// The real index didn't fit, it's in section 0
+pub const SHN_XINDEX: u16 = 0xffff;
+pub const SHF_WRITE: u64 = (1 << 0);
+pub const SHF_ALLOC: u64 = (1 << 1);
+pub const SHF_EXECINSTR: u64 = (1 << 2);
+pub const SHF_TLS: u64 = (1 << 10);
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -27,6 +39,10 @@ pub struct HeaderIdent {
 
 impl HeaderIdent {
     pub fn os_abi(&self) -> Option<OsAbi> {
+        self.try_os_abi().ok()
+    }
+
+    pub fn try_os_abi(&self) -> Result<OsAbi, ParseError> {
         let osabi = match self.ei_osabi {
             0 => OsAbi::SystemV,
             1 => OsAbi::Hpux,
@@ -42,10 +58,10 @@ impl HeaderIdent {
             64 => OsAbi::ArmAEABI,
             97 => OsAbi::Arm,
             255 => OsAbi::Standalone,
-            _ => return None,
+            x => return Err(ParseError::UnknownOsAbi(x)),
         };
 
-        return Some(osabi);
+        return Ok(osabi);
     }
 }
 
@@ -70,20 +86,59 @@ pub struct Header {
 
 impl Header {
     pub fn machine(&self) -> Option<Machine> {
-        let machine = match self.e_machine {
-            0 => Machine::None,
-            20 => Machine::PowerPC,
-            21 => Machine::Power64,
-            40 => Machine::Arm,
-            3 => Machine::X86,
-            62 => Machine::X64,
-            183 => Machine::AArch64,
-            224 => Machine::AmdGpu,
-            243 => Machine::RiscV,
-            _ => return None,
-        };
+        self.try_machine().ok()
+    }
+
+    pub fn try_machine(&self) -> Result<Machine, ParseError> {
+        machine(self.e_machine)
+    }
+}
+
+/// `e_machine` of either header as a `Machine`
+fn machine(e_machine: u16) -> Result<Machine, ParseError> {
+    let machine = match e_machine {
+        0 => Machine::None,
+        20 => Machine::PowerPC,
+        21 => Machine::Power64,
+        40 => Machine::Arm,
+        3 => Machine::X86,
+        62 => Machine::X64,
+        183 => Machine::AArch64,
+        224 => Machine::AmdGpu,
+        243 => Machine::RiscV,
+        x => return Err(ParseError::UnknownMachine(x)),
+    };
+
+    return Ok(machine);
+}
+
+/// `Elf32_Ehdr`, see `AnyHeader`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Header32 {
+    pub e_ident: HeaderIdent,
+    pub e_type: u16,
+    pub e_machine: u16,
+    pub e_version: u32,
+    pub e_entry: u32,
+    pub e_phoff: u32,
+    pub e_shoff: u32,
+    pub e_flags: u32,
+    pub e_ehsize: u16,
+    pub e_phentsize: u16,
+    pub e_phnum: u16,
+    pub e_shentsize: u16,
+    pub e_shnum: u16,
+    pub e_shstrndx: u16,
+}
 
-        return Some(machine);
+impl Header32 {
+    pub fn machine(&self) -> Option<Machine> {
+        self.try_machine().ok()
+    }
+
+    pub fn try_machine(&self) -> Result<Machine, ParseError> {
+        machine(self.e_machine)
     }
 }
 
@@ -105,10 +160,35 @@ pub struct ProgramHeader {
 }
 
 impl ProgramHeader {
+    /// Creates a PT_LOAD header, `flags` are a combination of `PF_*`
+    pub const fn new_load(
+        flags: u32,
+        offset: u64,
+        vaddr: u64,
+        filesz: u64,
+        memsz: u64,
+        align: u64,
+    ) -> Self {
+        Self {
+            p_type: SegmentType::Load.to_integer(),
+            p_flags: flags,
+            p_offset: offset,
+            p_vaddr: vaddr,
+            p_paddr: vaddr,
+            p_filesz: filesz,
+            p_memsz: memsz,
+            p_align: align,
+        }
+    }
+
     pub fn segment_type(&self) -> Option<SegmentType> {
         SegmentType::from_integer(self.p_type)
     }
 
+    pub fn try_segment_type(&self) -> Result<SegmentType, ParseError> {
+        SegmentType::try_from_integer(self.p_type)
+    }
+
     pub fn is_executable(&self) -> bool {
         (self.p_flags >> 0) & 1 == 1
     }
@@ -126,8 +206,8 @@ impl ProgramHeader {
     }
 }
 
-impl core::fmt::Debug for ProgramHeader {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+impl fmt::Debug for ProgramHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut perms = ['_'; 3];
         if self.is_readable() {
             perms[0] = 'R';
@@ -139,20 +219,52 @@ impl core::fmt::Debug for ProgramHeader {
             perms[2] = 'X';
         }
 
-        f.write_fmt(format_args!(
-            "ProgramHeader {{ type: {:?}, flags: {}{}{}, \
-        offset: 0x{:X}, vaddr: 0x{:X}, paddr: 0x{:X}, filesz: {}, memsz: {}, p_align: 0x{:X} }}",
-            SegmentType::from_integer(self.p_type),
-            perms[0],
-            perms[1],
-            perms[2],
-            self.p_offset,
-            self.p_vaddr,
-            self.p_paddr,
-            self.p_filesz,
-            self.p_memsz,
-            self.p_align,
-        ))
+        let typ = SegmentType::from_integer(self.p_type);
+        let mut s = f.debug_struct("ProgramHeader");
+        match typ {
+            Some(typ) => s.field("type", &typ),
+            None => s.field("type", &format_args!("Unknown ({:#x})", self.p_type)),
+        };
+        s.field(
+            "flags",
+            &format_args!("{}{}{}", perms[0], perms[1], perms[2]),
+        )
+        .field("offset", &format_args!("{:#x}", self.p_offset))
+        .field("vaddr", &Addr(self.p_vaddr))
+        .field("paddr", &Addr(self.p_paddr))
+        .field("filesz", &Size(self.p_filesz))
+        .field("memsz", &Size(self.p_memsz))
+        .field("align", &format_args!("{:#x}", self.p_align))
+        .finish()
+    }
+}
+
+/// `Elf32_Phdr`, with `p_flags` moved after the sizes
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramHeader32 {
+    pub p_type: u32,
+    pub p_offset: u32,
+    pub p_vaddr: u32,
+    pub p_paddr: u32,
+    pub p_filesz: u32,
+    pub p_memsz: u32,
+    pub p_flags: u32,
+    pub p_align: u32,
+}
+
+impl From<ProgramHeader32> for ProgramHeader {
+    fn from(ph: ProgramHeader32) -> Self {
+        Self {
+            p_type: ph.p_type,
+            p_flags: ph.p_flags,
+            p_offset: ph.p_offset as u64,
+            p_vaddr: ph.p_vaddr as u64,
+            p_paddr: ph.p_paddr as u64,
+            p_filesz: ph.p_filesz as u64,
+            p_memsz: ph.p_memsz as u64,
+            p_align: ph.p_align as u64,
+        }
     }
 }
 
@@ -173,110 +285,204 @@ pub struct SectionHeader {
     pub sh_entsize: u64,
 }
 
-pub enum SectionType {
-    Null = 0,
-    Progbits,
-    Symtab,
-    Strtab,
-    Rela,
-    Hash,
-    Dynamic,
-    Note,
-    Nobits,
-    Rel = 9,
+impl SectionHeader {
+    pub fn is_writable(&self) -> bool {
+        self.sh_flags & SHF_WRITE != 0
+    }
+    /// Section occupies memory during execution
+    pub fn is_alloc(&self) -> bool {
+        self.sh_flags & SHF_ALLOC != 0
+    }
+    pub fn is_executable(&self) -> bool {
+        self.sh_flags & SHF_EXECINSTR != 0
+    }
+    /// Template of thread-local data, `.tbss` takes no space in its segment
+    pub fn is_tls(&self) -> bool {
+        self.sh_flags & SHF_TLS != 0
+    }
 
-    Dynsym = 11,
+    /// Name of this section in `shstrtab`, the section name string table
+    /// of `file`. `None` if either lies outside its container, or the name
+    /// isn't NUL terminated or isn't UTF-8.
+    pub fn name<'a>(&self, file: &'a [u8], shstrtab: &SectionHeader) -> Option<&'a str> {
+        StrTab::from_section(file, shstrtab)?.get(self.sh_name)
+    }
+}
 
-    InitArray = 14,
-    FiniArray,
-    PreinitArray,
-    Group,
-    SymtabShIndex = 18,
+debug_enum! {
+    pub enum SectionType {
+        Null = 0,
+        Progbits,
+        Symtab,
+        Strtab,
+        Rela,
+        Hash,
+        Dynamic,
+        Note,
+        Nobits,
+        Rel = 9,
+
+        Dynsym = 11,
+
+        InitArray = 14,
+        FiniArray,
+        PreinitArray,
+        Group,
+        SymtabShIndex = 18,
+    }
 }
 
-#[repr(u8)]
-#[derive(Clone, Copy, Debug)]
-pub enum Class {
-    Bits32 = 1,
-    Bits64 = 2,
+debug_enum! {
+    #[repr(u8)]
+    #[derive(Clone, Copy)]
+    pub enum Class {
+        Bits32 = 1,
+        Bits64 = 2,
+    }
 }
 
 impl Class {
     pub fn from_integer(x: u8) -> Option<Self> {
+        Self::try_from_integer(x).ok()
+    }
+
+    pub fn try_from_integer(x: u8) -> Result<Self, ParseError> {
         let r = match x {
             1 => Self::Bits32,
             2 => Self::Bits64,
-            _ => return None,
+            _ => return Err(ParseError::UnsupportedClass(x)),
         };
 
-        return Some(r);
+        return Ok(r);
     }
 }
 
-#[repr(u8)]
-#[derive(Clone, Copy, Debug)]
-pub enum Data {
-    Lsb = 1,
-    Msb = 2,
+debug_enum! {
+    #[repr(u8)]
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum Data {
+        Lsb = 1,
+        Msb = 2,
+    }
 }
 
 impl Data {
     pub fn from_integer(x: u8) -> Option<Self> {
+        Self::try_from_integer(x).ok()
+    }
+
+    pub fn try_from_integer(x: u8) -> Result<Self, ParseError> {
         let r = match x {
             1 => Self::Lsb,
             2 => Self::Msb,
-            _ => return None,
+            _ => return Err(ParseError::UnsupportedData(x)),
         };
 
-        return Some(r);
+        return Ok(r);
     }
 }
 
-#[repr(u16)]
-#[derive(Clone, Copy, Debug)]
-pub enum Type {
-    None = 0,
-    Relocatable = 1,
-    Executable = 2,
-    SharedObject = 3,
-    Core = 4,
+debug_enum! {
+    #[repr(u16)]
+    #[derive(Clone, Copy)]
+    pub enum Type {
+        None = 0,
+        Relocatable = 1,
+        Executable = 2,
+        SharedObject = 3,
+        Core = 4,
+    }
 }
 
-#[repr(u16)]
-#[derive(Clone, Copy, Debug)]
-pub enum Machine {
-    None = 0,
-    PowerPC = 20,
-    Power64 = 21,
-    Arm = 40,
-    X86 = 3,
-    X64 = 62,
-    AArch64 = 183,
-    AmdGpu = 224,
-    RiscV = 243,
-}
-
-#[repr(u8)]
-#[derive(Clone, Copy, Debug)]
-pub enum OsAbi {
-    SystemV = 0,      /* UNIX System V ABI */
-    Hpux = 1,         /* HP-UX */
-    NetBSD = 2,       /* NetBSD.  */
-    GnuLinux = 3,     /* Object uses GNU ELF extensions.  */
-    Solaris = 6,      /* Sun Solaris.  */
-    Aix = 7,          /* IBM AIX.  */
-    Irix = 8,         /* SGI Irix.  */
-    FreeBSD = 9,      /* FreeBSD.  */
-    Tru64 = 10,       /* Compaq TRU64 UNIX.  */
-    Modesto = 11,     /* Novell Modesto.  */
-    OpenBSD = 12,     /* OpenBSD.  */
-    ArmAEABI = 64,    /* ARM EABI */
-    Arm = 97,         /* ARM */
-    Standalone = 255, /* Standalone (embedded) application */
+debug_enum! {
+    #[repr(u16)]
+    #[derive(Clone, Copy)]
+    pub enum Machine {
+        None = 0,
+        PowerPC = 20,
+        Power64 = 21,
+        Arm = 40,
+        X86 = 3,
+        X64 = 62,
+        AArch64 = 183,
+        AmdGpu = 224,
+        RiscV = 243,
+    }
+}
+
+debug_enum! {
+    #[repr(u8)]
+    #[derive(Clone, Copy)]
+    pub enum OsAbi {
+        SystemV = 0,      /* UNIX System V ABI */
+        Hpux = 1,         /* HP-UX */
+        NetBSD = 2,       /* NetBSD.  */
+        GnuLinux = 3,     /* Object uses GNU ELF extensions.  */
+        Solaris = 6,      /* Sun Solaris.  */
+        Aix = 7,          /* IBM AIX.  */
+        Irix = 8,         /* SGI Irix.  */
+        FreeBSD = 9,      /* FreeBSD.  */
+        Tru64 = 10,       /* Compaq TRU64 UNIX.  */
+        Modesto = 11,     /* Novell Modesto.  */
+        OpenBSD = 12,     /* OpenBSD.  */
+        ArmAEABI = 64,    /* ARM EABI */
+        Arm = 97,         /* ARM */
+        Standalone = 255, /* Standalone (embedded) application */
+    }
+}
+
+/// The name `readelf -h` gives, like "EXEC (executable)"
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "NONE (none)",
+            Self::Relocatable => "REL (relocatable)",
+            Self::Executable => "EXEC (executable)",
+            Self::SharedObject => "DYN (shared object)",
+            Self::Core => "CORE (core dump)",
+        })
+    }
+}
+
+impl fmt::Display for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::PowerPC => "PowerPC",
+            Self::Power64 => "PowerPC64",
+            Self::Arm => "ARM",
+            Self::X86 => "x86",
+            Self::X64 => "x86-64",
+            Self::AArch64 => "AArch64",
+            Self::AmdGpu => "AMD GPU",
+            Self::RiscV => "RISC-V",
+        })
+    }
+}
+
+impl fmt::Display for OsAbi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::SystemV => "System V",
+            Self::Hpux => "HP-UX",
+            Self::NetBSD => "NetBSD",
+            Self::GnuLinux => "GNU/Linux",
+            Self::Solaris => "Solaris",
+            Self::Aix => "AIX",
+            Self::Irix => "IRIX",
+            Self::FreeBSD => "FreeBSD",
+            Self::Tru64 => "Tru64",
+            Self::Modesto => "Novell Modesto",
+            Self::OpenBSD => "OpenBSD",
+            Self::ArmAEABI => "ARM EABI",
+            Self::Arm => "ARM",
+            Self::Standalone => "Standalone",
+        })
+    }
 }
 
 #[repr(u32)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum SegmentType {
     Null,
     Load,
@@ -287,12 +493,22 @@ pub enum SegmentType {
     ProgramHeader,
     ThreadLocalStorage,
 
+    /* GNU extensions, from the OS specific range */
+    GnuEhFrame,
+    GnuStack,
+    GnuRelro,
+    GnuProperty,
+
     OsSpecific(u32),
     CpuSpecific(u32),
 }
 
 impl SegmentType {
     pub fn from_integer(x: u32) -> Option<Self> {
+        Self::try_from_integer(x).ok()
+    }
+
+    pub fn try_from_integer(x: u32) -> Result<Self, ParseError> {
         let ret = match x {
             0 => Self::Null,
             1 => Self::Load,
@@ -302,12 +518,161 @@ impl SegmentType {
             5 => Self::SharedLib,
             6 => Self::ProgramHeader,
             7 => Self::ThreadLocalStorage,
+            0x6474e550 => Self::GnuEhFrame,
+            0x6474e551 => Self::GnuStack,
+            0x6474e552 => Self::GnuRelro,
+            0x6474e553 => Self::GnuProperty,
             0x60000000..=0x6fffffff => Self::OsSpecific(x),
             0x70000000..=0x7fffffff => Self::CpuSpecific(x),
-            _ => return None,
+            _ => return Err(ParseError::UnknownSegmentType(x)),
         };
 
-        return Some(ret);
+        return Ok(ret);
+    }
+
+    /// Inverse of `from_integer`, gives the raw `p_type` value
+    pub const fn to_integer(&self) -> u32 {
+        match *self {
+            Self::Null => 0,
+            Self::Load => 1,
+            Self::Dynamic => 2,
+            Self::Interpreter => 3,
+            Self::Note => 4,
+            Self::SharedLib => 5,
+            Self::ProgramHeader => 6,
+            Self::ThreadLocalStorage => 7,
+            Self::GnuEhFrame => 0x6474e550,
+            Self::GnuStack => 0x6474e551,
+            Self::GnuRelro => 0x6474e552,
+            Self::GnuProperty => 0x6474e553,
+            Self::OsSpecific(x) => x,
+            Self::CpuSpecific(x) => x,
+        }
+    }
+}
+
+impl fmt::Debug for SegmentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Null => "Null",
+            Self::Load => "Load",
+            Self::Dynamic => "Dynamic",
+            Self::Interpreter => "Interpreter",
+            Self::Note => "Note",
+            Self::SharedLib => "SharedLib",
+            Self::ProgramHeader => "ProgramHeader",
+            Self::ThreadLocalStorage => "ThreadLocalStorage",
+            Self::GnuEhFrame => "GnuEhFrame",
+            Self::GnuStack => "GnuStack",
+            Self::GnuRelro => "GnuRelro",
+            Self::GnuProperty => "GnuProperty",
+            Self::OsSpecific(_) => "OsSpecific",
+            Self::CpuSpecific(_) => "CpuSpecific",
+        };
+
+        impl_bits::fmt::write_enum(f, name, self.to_integer() as u64)
+    }
+}
+
+/// The `PT_*` constant, the OS and processor specific ranges with the
+/// raw value, like "PT_LOOS+0x1 (0x60000001)"
+impl fmt::Display for SegmentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Null => "PT_NULL",
+            Self::Load => "PT_LOAD",
+            Self::Dynamic => "PT_DYNAMIC",
+            Self::Interpreter => "PT_INTERP",
+            Self::Note => "PT_NOTE",
+            Self::SharedLib => "PT_SHLIB",
+            Self::ProgramHeader => "PT_PHDR",
+            Self::ThreadLocalStorage => "PT_TLS",
+            Self::GnuEhFrame => "PT_GNU_EH_FRAME",
+            Self::GnuStack => "PT_GNU_STACK",
+            Self::GnuRelro => "PT_GNU_RELRO",
+            Self::GnuProperty => "PT_GNU_PROPERTY",
+            Self::OsSpecific(_) => "PT_LOOS",
+            Self::CpuSpecific(_) => "PT_LOPROC",
+        };
+
+        match self {
+            Self::OsSpecific(x) => write!(f, "{}+{:#x} ({:#x})", name, x - 0x6000_0000, x),
+            Self::CpuSpecific(x) => write!(f, "{}+{:#x} ({:#x})", name, x - 0x7000_0000, x),
+            _ => f.write_str(name),
+        }
+    }
+}
+
+/// Number of segments of each `SegmentType`, for diagnostics
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct SegmentCounts {
+    pub null: u16,
+    pub load: u16,
+    pub dynamic: u16,
+    pub interpreter: u16,
+    pub note: u16,
+    pub shared_lib: u16,
+    pub program_header: u16,
+    pub tls: u16,
+    pub gnu_eh_frame: u16,
+    pub gnu_stack: u16,
+    pub gnu_relro: u16,
+    pub gnu_property: u16,
+    pub os_specific: u16,
+    pub cpu_specific: u16,
+    /// `p_type` outside of any known range
+    pub unknown: u16,
+}
+
+impl SegmentCounts {
+    pub fn add(&mut self, typ: Option<SegmentType>) {
+        let count = match typ {
+            Some(SegmentType::Null) => &mut self.null,
+            Some(SegmentType::Load) => &mut self.load,
+            Some(SegmentType::Dynamic) => &mut self.dynamic,
+            Some(SegmentType::Interpreter) => &mut self.interpreter,
+            Some(SegmentType::Note) => &mut self.note,
+            Some(SegmentType::SharedLib) => &mut self.shared_lib,
+            Some(SegmentType::ProgramHeader) => &mut self.program_header,
+            Some(SegmentType::ThreadLocalStorage) => &mut self.tls,
+            Some(SegmentType::GnuEhFrame) => &mut self.gnu_eh_frame,
+            Some(SegmentType::GnuStack) => &mut self.gnu_stack,
+            Some(SegmentType::GnuRelro) => &mut self.gnu_relro,
+            Some(SegmentType::GnuProperty) => &mut self.gnu_property,
+            Some(SegmentType::OsSpecific(_)) => &mut self.os_specific,
+            Some(SegmentType::CpuSpecific(_)) => &mut self.cpu_specific,
+            None => &mut self.unknown,
+        };
+        *count = count.saturating_add(1);
+    }
+}
+
+/// Only types that are present, e.g. `SegmentCounts { load: 3, gnu_stack: 1 }`
+impl fmt::Debug for SegmentCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            ("null", self.null),
+            ("load", self.load),
+            ("dynamic", self.dynamic),
+            ("interpreter", self.interpreter),
+            ("note", self.note),
+            ("shared_lib", self.shared_lib),
+            ("program_header", self.program_header),
+            ("tls", self.tls),
+            ("gnu_eh_frame", self.gnu_eh_frame),
+            ("gnu_stack", self.gnu_stack),
+            ("gnu_relro", self.gnu_relro),
+            ("gnu_property", self.gnu_property),
+            ("os_specific", self.os_specific),
+            ("cpu_specific", self.cpu_specific),
+            ("unknown", self.unknown),
+        ];
+
+        let mut s = f.debug_struct("SegmentCounts");
+        for &(name, count) in fields.iter().filter(|(_, count)| *count != 0) {
+            s.field(name, &count);
+        }
+        s.finish()
     }
 }
 
@@ -317,9 +682,18 @@ unsafe impl Pod for HeaderIdent {}
 unsafe impl Zeroable for ProgramHeader {}
 unsafe impl Pod for ProgramHeader {}
 
+unsafe impl Zeroable for SectionHeader {}
+unsafe impl Pod for SectionHeader {}
+
 unsafe impl Zeroable for Header {}
 unsafe impl Pod for Header {}
 
+unsafe impl Zeroable for Header32 {}
+unsafe impl Pod for Header32 {}
+
+unsafe impl Zeroable for ProgramHeader32 {}
+unsafe impl Pod for ProgramHeader32 {}
+
 unsafe impl Contiguous for Type {
     type Int = u16;
     const MIN_VALUE: u16 = Type::None as u16;
@@ -0,0 +1,348 @@
+//! Entries of the PT_DYNAMIC segment, what a dynamic linker reads to find
+//! the string and symbol tables, relocations and dependencies of an object
+
+use crate::{Header, HeaderIter, ProgramHeader, SegmentType};
+use bytemuck::{Pod, Zeroable};
+use core::fmt;
+use impl_bits::fmt::Addr;
+
+/// `DT_FLAGS` bits
+pub const DF_ORIGIN: u64 = 1 << 0;
+pub const DF_SYMBOLIC: u64 = 1 << 1;
+pub const DF_TEXTREL: u64 = 1 << 2;
+pub const DF_BIND_NOW: u64 = 1 << 3;
+pub const DF_STATIC_TLS: u64 = 1 << 4;
+
+/// `DT_FLAGS_1` bits
+pub const DF_1_NOW: u64 = 1 << 0;
+pub const DF_1_PIE: u64 = 1 << 27;
+
+/// `Elf64_Dyn`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Dyn {
+    /// `DynamicTag`, signed because of the OS and processor specific ranges
+    pub d_tag: i64,
+    /// `d_val` or `d_ptr`, which one depends on the tag
+    pub d_val: u64,
+}
+
+unsafe impl Zeroable for Dyn {}
+unsafe impl Pod for Dyn {}
+
+impl Dyn {
+    pub fn tag(&self) -> DynamicTag {
+        DynamicTag::from_integer(self.d_tag)
+    }
+}
+
+impl fmt::Debug for Dyn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Dyn")
+            .field("tag", &self.tag())
+            .field("val", &Addr(self.d_val))
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DynamicTag {
+    /// Ends the table
+    Null,
+    /// Offset in the string table of a library this one needs
+    Needed,
+    PltRelSz,
+    PltGot,
+    Hash,
+    StrTab,
+    SymTab,
+    Rela,
+    RelaSz,
+    RelaEnt,
+    StrSz,
+    SymEnt,
+    Init,
+    Fini,
+    SoName,
+    RPath,
+    Symbolic,
+    Rel,
+    RelSz,
+    RelEnt,
+    PltRel,
+    Debug,
+    TextRel,
+    JmpRel,
+    BindNow,
+    InitArray,
+    FiniArray,
+    InitArraySz,
+    FiniArraySz,
+    RunPath,
+    Flags,
+
+    /* GNU extensions, from the OS specific range */
+    GnuHash,
+    RelaCount,
+    RelCount,
+    Flags1,
+
+    /// Kept as is, so nothing in the table is lost
+    Unknown(i64),
+}
+
+impl DynamicTag {
+    pub fn from_integer(x: i64) -> Self {
+        match x {
+            0 => Self::Null,
+            1 => Self::Needed,
+            2 => Self::PltRelSz,
+            3 => Self::PltGot,
+            4 => Self::Hash,
+            5 => Self::StrTab,
+            6 => Self::SymTab,
+            7 => Self::Rela,
+            8 => Self::RelaSz,
+            9 => Self::RelaEnt,
+            10 => Self::StrSz,
+            11 => Self::SymEnt,
+            12 => Self::Init,
+            13 => Self::Fini,
+            14 => Self::SoName,
+            15 => Self::RPath,
+            16 => Self::Symbolic,
+            17 => Self::Rel,
+            18 => Self::RelSz,
+            19 => Self::RelEnt,
+            20 => Self::PltRel,
+            21 => Self::Debug,
+            22 => Self::TextRel,
+            23 => Self::JmpRel,
+            24 => Self::BindNow,
+            25 => Self::InitArray,
+            26 => Self::FiniArray,
+            27 => Self::InitArraySz,
+            28 => Self::FiniArraySz,
+            29 => Self::RunPath,
+            30 => Self::Flags,
+            0x6ffffef5 => Self::GnuHash,
+            0x6ffffff9 => Self::RelaCount,
+            0x6ffffffa => Self::RelCount,
+            0x6ffffffb => Self::Flags1,
+            x => Self::Unknown(x),
+        }
+    }
+
+    /// Inverse of `from_integer`, gives the raw `d_tag` value
+    pub const fn to_integer(&self) -> i64 {
+        match *self {
+            Self::Null => 0,
+            Self::Needed => 1,
+            Self::PltRelSz => 2,
+            Self::PltGot => 3,
+            Self::Hash => 4,
+            Self::StrTab => 5,
+            Self::SymTab => 6,
+            Self::Rela => 7,
+            Self::RelaSz => 8,
+            Self::RelaEnt => 9,
+            Self::StrSz => 10,
+            Self::SymEnt => 11,
+            Self::Init => 12,
+            Self::Fini => 13,
+            Self::SoName => 14,
+            Self::RPath => 15,
+            Self::Symbolic => 16,
+            Self::Rel => 17,
+            Self::RelSz => 18,
+            Self::RelEnt => 19,
+            Self::PltRel => 20,
+            Self::Debug => 21,
+            Self::TextRel => 22,
+            Self::JmpRel => 23,
+            Self::BindNow => 24,
+            Self::InitArray => 25,
+            Self::FiniArray => 26,
+            Self::InitArraySz => 27,
+            Self::FiniArraySz => 28,
+            Self::RunPath => 29,
+            Self::Flags => 30,
+            Self::GnuHash => 0x6ffffef5,
+            Self::RelaCount => 0x6ffffff9,
+            Self::RelCount => 0x6ffffffa,
+            Self::Flags1 => 0x6ffffffb,
+            Self::Unknown(x) => x,
+        }
+    }
+}
+
+impl fmt::Debug for DynamicTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Null => "Null",
+            Self::Needed => "Needed",
+            Self::PltRelSz => "PltRelSz",
+            Self::PltGot => "PltGot",
+            Self::Hash => "Hash",
+            Self::StrTab => "StrTab",
+            Self::SymTab => "SymTab",
+            Self::Rela => "Rela",
+            Self::RelaSz => "RelaSz",
+            Self::RelaEnt => "RelaEnt",
+            Self::StrSz => "StrSz",
+            Self::SymEnt => "SymEnt",
+            Self::Init => "Init",
+            Self::Fini => "Fini",
+            Self::SoName => "SoName",
+            Self::RPath => "RPath",
+            Self::Symbolic => "Symbolic",
+            Self::Rel => "Rel",
+            Self::RelSz => "RelSz",
+            Self::RelEnt => "RelEnt",
+            Self::PltRel => "PltRel",
+            Self::Debug => "Debug",
+            Self::TextRel => "TextRel",
+            Self::JmpRel => "JmpRel",
+            Self::BindNow => "BindNow",
+            Self::InitArray => "InitArray",
+            Self::FiniArray => "FiniArray",
+            Self::InitArraySz => "InitArraySz",
+            Self::FiniArraySz => "FiniArraySz",
+            Self::RunPath => "RunPath",
+            Self::Flags => "Flags",
+            Self::GnuHash => "GnuHash",
+            Self::RelaCount => "RelaCount",
+            Self::RelCount => "RelCount",
+            Self::Flags1 => "Flags1",
+            Self::Unknown(_) => "Unknown",
+        };
+
+        impl_bits::fmt::write_enum(f, name, self.to_integer() as u64)
+    }
+}
+
+/// Entries of a dynamic table up to DT_NULL, or to the first one that
+/// doesn't fit in the file
+#[derive(Clone)]
+pub struct Dynamic<'a> {
+    entries: HeaderIter<'a, Dyn>,
+}
+
+impl<'a> Dynamic<'a> {
+    pub fn new(entries: HeaderIter<'a, Dyn>) -> Self {
+        Self { entries }
+    }
+
+    /// The common tags in a single pass, see `DynamicInfo`
+    pub fn info(self) -> DynamicInfo {
+        let mut info = DynamicInfo::default();
+        for entry in self {
+            info.add(&entry);
+        }
+        return info;
+    }
+}
+
+impl<'a> Iterator for Dynamic<'a> {
+    type Item = Dyn;
+
+    fn next(&mut self) -> Option<Dyn> {
+        let entry = self.entries.next()?;
+        if entry.tag() == DynamicTag::Null {
+            self.entries = HeaderIter::empty();
+            return None;
+        }
+        return Some(entry);
+    }
+}
+
+impl<'a> core::iter::FusedIterator for Dynamic<'a> {}
+
+/// What a loader looks up in the dynamic table, collected in one pass.
+/// Addresses are `d_ptr` as is, the load bias isn't added. A tag that
+/// appears more than once keeps its last value, except for DT_NEEDED.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DynamicInfo {
+    /// DT_NEEDED entries, their names are `Dynamic` entries of that tag
+    pub needed: usize,
+    pub strtab: Option<u64>,
+    pub strsz: Option<u64>,
+    pub symtab: Option<u64>,
+    pub syment: Option<u64>,
+    pub hash: Option<u64>,
+    pub gnu_hash: Option<u64>,
+    pub rela: Option<u64>,
+    pub relasz: Option<u64>,
+    pub relaent: Option<u64>,
+    pub rel: Option<u64>,
+    pub relsz: Option<u64>,
+    pub relent: Option<u64>,
+    /// PLT relocations, of the type in DT_PLTREL
+    pub jmprel: Option<u64>,
+    pub pltrelsz: Option<u64>,
+    pub init: Option<u64>,
+    pub fini: Option<u64>,
+    pub init_array: Option<u64>,
+    pub init_arraysz: Option<u64>,
+    pub fini_array: Option<u64>,
+    pub fini_arraysz: Option<u64>,
+    /// Offset of the object's own name in the string table
+    pub soname: Option<u64>,
+    /// `DF_*` bits, DT_BIND_NOW and DT_TEXTREL are folded into them
+    pub flags: u64,
+    /// `DF_1_*` bits
+    pub flags_1: u64,
+}
+
+impl DynamicInfo {
+    pub fn add(&mut self, entry: &Dyn) {
+        let val = Some(entry.d_val);
+        match entry.tag() {
+            DynamicTag::Needed => self.needed += 1,
+            DynamicTag::StrTab => self.strtab = val,
+            DynamicTag::StrSz => self.strsz = val,
+            DynamicTag::SymTab => self.symtab = val,
+            DynamicTag::SymEnt => self.syment = val,
+            DynamicTag::Hash => self.hash = val,
+            DynamicTag::GnuHash => self.gnu_hash = val,
+            DynamicTag::Rela => self.rela = val,
+            DynamicTag::RelaSz => self.relasz = val,
+            DynamicTag::RelaEnt => self.relaent = val,
+            DynamicTag::Rel => self.rel = val,
+            DynamicTag::RelSz => self.relsz = val,
+            DynamicTag::RelEnt => self.relent = val,
+            DynamicTag::JmpRel => self.jmprel = val,
+            DynamicTag::PltRelSz => self.pltrelsz = val,
+            DynamicTag::Init => self.init = val,
+            DynamicTag::Fini => self.fini = val,
+            DynamicTag::InitArray => self.init_array = val,
+            DynamicTag::InitArraySz => self.init_arraysz = val,
+            DynamicTag::FiniArray => self.fini_array = val,
+            DynamicTag::FiniArraySz => self.fini_arraysz = val,
+            DynamicTag::SoName => self.soname = val,
+            DynamicTag::Flags => self.flags |= entry.d_val,
+            DynamicTag::BindNow => self.flags |= DF_BIND_NOW,
+            DynamicTag::TextRel => self.flags |= DF_TEXTREL,
+            DynamicTag::Flags1 => self.flags_1 |= entry.d_val,
+            _ => {}
+        }
+    }
+
+    /// Whether every symbol has to be bound before running any code
+    pub fn bind_now(&self) -> bool {
+        self.flags & DF_BIND_NOW != 0 || self.flags_1 & DF_1_NOW != 0
+    }
+}
+
+impl Header {
+    /// Entries of the PT_DYNAMIC segment of `file`, in the file's byte
+    /// order, `None` without one
+    pub fn dynamic<'a>(&self, file: &'a [u8]) -> Option<Dynamic<'a>> {
+        let is_dynamic = |ph: &ProgramHeader| ph.segment_type() == Some(SegmentType::Dynamic);
+        let ph = self.iter_program_headers(file).find(is_dynamic)?;
+        let size = core::mem::size_of::<Dyn>() as u64;
+        let count = (ph.p_filesz / size) as usize;
+        let entries = HeaderIter::new(file, ph.p_offset, size, count).in_order(self.data());
+        return Some(Dynamic::new(entries));
+    }
+}
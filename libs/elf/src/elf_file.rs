@@ -0,0 +1,169 @@
+//! Files of either class, for tools that also read 32-bit binaries. The
+//! loader only boots 64-bit kernels and keeps using `Elf`.
+
+use crate::{check_program_headers, parse_ident, read_in_order, Class, Data, Machine};
+use crate::{Header, Header32, HeaderIter, ParseError, ProgramHeader, ProgramHeader32};
+use crate::{ProgramHeaderIter, EHSIZE_X64, EHSIZE_X86, EV_CURRENT};
+
+/// Header of either class, picked by `ei_class`. Fields whose width
+/// depends on the class are widened to `u64`.
+#[derive(Clone, Copy, Debug)]
+pub enum AnyHeader {
+    Bits32(Header32),
+    Bits64(Header),
+}
+
+impl AnyHeader {
+    /// `Header::parse` for both classes. `e_ehsize` has to match
+    /// `ei_class`, so a 64-bit file with a 32-bit header size is refused.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        let header = match parse_ident(bytes)? {
+            (Class::Bits32, data) => Self::Bits32(read_in_order(&bytes[..EHSIZE_X86], data)),
+            (Class::Bits64, _) => Self::Bits64(Header::parse(bytes)?),
+        };
+
+        let (version, ehsize) = match &header {
+            Self::Bits32(h) => (h.e_version, h.e_ehsize),
+            Self::Bits64(h) => (h.e_version, h.e_ehsize),
+        };
+        if version != EV_CURRENT as u32 {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+        if ehsize as usize != header.header_size() {
+            return Err(ParseError::HeaderSize(ehsize));
+        }
+        return Ok(header);
+    }
+
+    pub fn class(&self) -> Class {
+        match self {
+            Self::Bits32(_) => Class::Bits32,
+            Self::Bits64(_) => Class::Bits64,
+        }
+    }
+
+    /// `EHSIZE_X86` or `EHSIZE_X64`
+    pub fn header_size(&self) -> usize {
+        match self {
+            Self::Bits32(_) => EHSIZE_X86,
+            Self::Bits64(_) => EHSIZE_X64,
+        }
+    }
+
+    /// Byte order of the file, see `Header::data`
+    pub fn data(&self) -> Data {
+        let ei_data = match self {
+            Self::Bits32(h) => h.e_ident.ei_data,
+            Self::Bits64(h) => h.e_ident.ei_data,
+        };
+        Data::from_integer(ei_data).unwrap_or(Data::NATIVE)
+    }
+
+    pub fn e_type(&self) -> u16 {
+        match self {
+            Self::Bits32(h) => h.e_type,
+            Self::Bits64(h) => h.e_type,
+        }
+    }
+
+    pub fn machine(&self) -> Option<Machine> {
+        match self {
+            Self::Bits32(h) => h.machine(),
+            Self::Bits64(h) => h.machine(),
+        }
+    }
+
+    /// Entry point, 0 without one
+    pub fn entry(&self) -> u64 {
+        match self {
+            Self::Bits32(h) => h.e_entry as u64,
+            Self::Bits64(h) => h.e_entry.map_or(0, |x| x.get()),
+        }
+    }
+
+    /// `e_phoff`, 0 without program headers
+    pub fn phoff(&self) -> u64 {
+        match self {
+            Self::Bits32(h) => h.e_phoff as u64,
+            Self::Bits64(h) => h.e_phoff.map_or(0, |x| x.get()),
+        }
+    }
+
+    /// Program headers of `file`, which this header belongs to, widened
+    /// to `ProgramHeader` whatever the class. Stops at the first one that
+    /// runs past the end of `file`, like `Header::iter_program_headers`.
+    pub fn program_headers<'a>(&self, file: &'a [u8]) -> ProgramHeaders<'a> {
+        match self {
+            Self::Bits32(h) if h.e_phoff != 0 => {
+                let iter = HeaderIter::new(
+                    file,
+                    h.e_phoff as u64,
+                    h.e_phentsize as u64,
+                    h.e_phnum as usize,
+                );
+                ProgramHeaders::Bits32(iter.in_order(self.data()))
+            }
+            Self::Bits32(_) => ProgramHeaders::Bits32(HeaderIter::empty()),
+            Self::Bits64(h) => ProgramHeaders::Bits64(h.iter_program_headers(file)),
+        }
+    }
+}
+
+/// A file of either class, with the header and program header table
+/// checked like `Elf::parse` does, for any machine, type and byte order
+#[derive(Clone, Copy, Debug)]
+pub struct ElfFile<'a> {
+    pub header: AnyHeader,
+    pub data: &'a [u8],
+}
+
+impl<'a> ElfFile<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        let header = AnyHeader::parse(bytes)?;
+        let phoff = header.phoff();
+        match header {
+            AnyHeader::Bits32(h) => {
+                check_program_headers::<ProgramHeader32>(bytes, phoff, h.e_phentsize, h.e_phnum)?
+            }
+            AnyHeader::Bits64(h) => {
+                check_program_headers::<ProgramHeader>(bytes, phoff, h.e_phentsize, h.e_phnum)?
+            }
+        }
+        return Ok(Self {
+            header,
+            data: bytes,
+        });
+    }
+
+    /// See `AnyHeader::program_headers`, after `parse` none are missing
+    pub fn program_headers(&self) -> ProgramHeaders<'a> {
+        self.header.program_headers(self.data)
+    }
+}
+
+/// Iterator of `AnyHeader::program_headers`
+#[derive(Clone)]
+pub enum ProgramHeaders<'a> {
+    Bits32(HeaderIter<'a, ProgramHeader32>),
+    Bits64(ProgramHeaderIter<'a>),
+}
+
+impl<'a> Iterator for ProgramHeaders<'a> {
+    type Item = ProgramHeader;
+
+    fn next(&mut self) -> Option<ProgramHeader> {
+        match self {
+            Self::Bits32(iter) => iter.next().map(ProgramHeader::from),
+            Self::Bits64(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Bits32(iter) => iter.size_hint(),
+            Self::Bits64(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<'a> core::iter::FusedIterator for ProgramHeaders<'a> {}
@@ -1,10 +1,30 @@
 #![no_std]
 
+mod byte_order;
+pub use byte_order::*;
 mod definitions;
 pub use definitions::*;
+mod dynamic;
+pub use dynamic::*;
+mod elf_file;
+pub use elf_file::*;
+mod note;
+pub use note::*;
+mod relocation;
+pub use relocation::*;
+mod segment_map;
+pub use segment_map::*;
+mod strtab;
+pub use strtab::*;
+mod symbol;
+pub use symbol::*;
+mod table;
+pub use table::*;
 
-use bytemuck;
+use core::convert::TryFrom;
+use core::fmt;
 use core::mem;
+use impl_bits::bytes::Reader;
 
 pub struct Elf<'a, M: ElfMachine> {
     pub data: &'a [u8],
@@ -30,14 +50,12 @@ impl ElfMachine for Amd64 {
 
 #[derive(Clone, Copy, Debug)]
 pub enum MemoryError {
-    WrongAlignment,
     UnexpectedEnd,
     SizeMismatch,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Error {
-    WrongAlignment,
     UnexpectedEnd,
     NotElf,
     NotExec,
@@ -48,11 +66,235 @@ pub enum Error {
     UnsupportedVersion,
 }
 
-impl<'a, M: ElfMachine> Elf<'a, M> {
-    pub fn program_headers(&self) -> Result<&[ProgramHeader], MemoryError> {
-        let header = self.header();
+/// Why `Elf::parse` refused an image, every way a file found on disk can
+/// be broken gets its own variant. Each carries the offending raw value,
+/// so `Display` can say more than "parse failed" over serial.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Shorter than the `need` bytes of the ELF header
+    TooShort { need: usize, have: usize },
+    BadMagic,
+    /// `ei_class` of another machine
+    UnsupportedClass(u8),
+    /// `ei_data` of another machine
+    UnsupportedData(u8),
+    /// `ei_version` or `e_version` isn't `EV_CURRENT`
+    UnsupportedVersion(u32),
+    /// `ei_osabi` of another machine
+    WrongOsAbi(u8),
+    /// `ei_abiversion` of another machine
+    WrongAbiVersion(u8),
+    /// `e_type` isn't `Type::Executable`
+    NotExec(u16),
+    WrongMachine(u16),
+    /// `ei_osabi` isn't any `OsAbi`
+    UnknownOsAbi(u8),
+    /// `e_machine` isn't any `Machine`
+    UnknownMachine(u16),
+    /// `p_type` outside of every range of `SegmentType`
+    UnknownSegmentType(u32),
+    /// Not a single PT_LOAD segment
+    NoLoadSegments,
+    /// Program header `.0`, a PT_LOAD, has `p_filesz` over `p_memsz`
+    FileSizeOverMemSize(usize),
+    /// PT_LOAD program header `.0` ends past the top of the address space
+    SegmentOverflow(usize),
+    /// PT_LOAD program headers `.0` and `.1` share virtual memory
+    SegmentOverlap(usize, usize),
+    /// `e_ehsize` isn't the header size of `ei_class`, `EHSIZE_X64` or
+    /// `EHSIZE_X86`
+    HeaderSize(u16),
+    /// `e_phentsize` is smaller than `ProgramHeader`, entries would be
+    /// read past their end
+    ProgramHeaderSize(u16),
+    /// `e_phoff` is 0 while there are program headers, or it or the end
+    /// of their table doesn't fit into `usize`
+    ProgramHeaderOffset(u64),
+    /// The program header table ends at `end`, past the `len` bytes of
+    /// the image
+    ProgramHeadersTruncated { end: u64, len: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::TooShort { need, have } => {
+                write!(f, "only {} of {} header bytes", have, need)
+            }
+            Self::BadMagic => f.write_str("not an ELF file, bad magic"),
+            Self::UnsupportedClass(x) => write!(f, "unsupported class {}", x),
+            Self::UnsupportedData(x) => write!(f, "unsupported byte order {}", x),
+            Self::UnsupportedVersion(x) => write!(f, "unsupported version {}", x),
+            Self::WrongOsAbi(x) => write!(f, "wrong OS ABI {}", x),
+            Self::WrongAbiVersion(x) => write!(f, "wrong ABI version {}", x),
+            Self::NotExec(x) => write!(f, "not an executable, e_type {:#x}", x),
+            Self::WrongMachine(x) => write!(f, "wrong machine {:#x}", x),
+            Self::UnknownOsAbi(x) => write!(f, "unknown OS ABI {}", x),
+            Self::UnknownMachine(x) => write!(f, "unknown machine {:#x}", x),
+            Self::UnknownSegmentType(x) => write!(f, "unknown segment type {:#x}", x),
+            Self::NoLoadSegments => f.write_str("no PT_LOAD segments"),
+            Self::FileSizeOverMemSize(i) => write!(f, "segment {} is larger in the file", i),
+            Self::SegmentOverflow(i) => write!(f, "segment {} ends past the address space", i),
+            Self::SegmentOverlap(a, b) => write!(f, "segments {} and {} overlap", a, b),
+            Self::HeaderSize(x) => write!(f, "wrong header size {}", x),
+            Self::ProgramHeaderSize(x) => write!(f, "program headers of {} bytes", x),
+            Self::ProgramHeaderOffset(x) => write!(f, "program headers at {:#x}", x),
+            Self::ProgramHeadersTruncated { end, len } => {
+                write!(f, "program headers end at {:#x}, past {:#x}", end, len)
+            }
+        }
+    }
+}
+
+/// Why `Header::validate_for_target` refused a header, with what was
+/// found instead of what was expected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidateError {
+    /// `e_machine` of another machine
+    WrongMachine(u16),
+    /// `ei_class` of another machine
+    WrongClass(u8),
+    /// `e_type` is neither `Type::Executable` nor `Type::SharedObject`
+    NotRunnable(u16),
+    /// `e_entry` is 0
+    NoEntry,
+}
+
+impl fmt::Display for ValidateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::WrongMachine(x) => write!(f, "built for machine {:#x}", x),
+            Self::WrongClass(x) => write!(f, "built for class {}", x),
+            Self::NotRunnable(x) => write!(f, "not runnable, e_type {:#x}", x),
+            Self::NoEntry => f.write_str("no entry point"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SegmentError {
+    Memory(MemoryError),
+    /// First PT_LOAD segment whose `p_vaddr` isn't on a page boundary
+    Misaligned { vaddr: u64 },
+}
+
+impl From<MemoryError> for SegmentError {
+    fn from(e: MemoryError) -> Self {
+        SegmentError::Memory(e)
+    }
+}
+
+/// Page size `Header::load_bounds` rounds segment ends up to
+pub const LOAD_PAGE_SIZE: u64 = 4096;
 
-        let phoff = match header.e_phoff {
+/// Virtual memory the PT_LOAD segments of a file span, see
+/// `Header::load_bounds`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadBounds {
+    /// Lowest `p_vaddr`, aligned down to its `p_align`
+    pub min_vaddr: u64,
+    /// End of the highest segment, aligned up to `LOAD_PAGE_SIZE`, exclusive
+    pub max_vaddr: u64,
+    /// Sum of the aligned sizes of every segment, gaps left out
+    pub total_memsz: u64,
+    /// Largest `p_align`, what the image has to be placed at
+    pub max_align: u64,
+}
+
+impl LoadBounds {
+    /// Bytes from `min_vaddr` to `max_vaddr`, gaps included
+    pub fn span(&self) -> u64 {
+        self.max_vaddr - self.min_vaddr
+    }
+}
+
+/// Why `Header::interpreter` refused a PT_INTERP segment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterpError {
+    /// `p_offset` and `p_filesz` run past the end of the image
+    OutOfBounds,
+    /// No path, not even the null
+    Empty,
+    /// The last byte isn't the terminating null
+    MissingNul,
+    /// A null before the terminating one
+    InteriorNul,
+    NotUtf8,
+    /// More than one PT_INTERP, the first of which is fine
+    Multiple,
+}
+
+/// `p_flags` of the PT_GNU_STACK segment, see `Header::stack_flags`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackFlags {
+    pub flags: u32,
+}
+
+impl StackFlags {
+    /// Whether the image wants to run code on its stack
+    pub fn executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+}
+
+impl Header {
+    /// Copy of the header at the start of `bytes`, which may come from
+    /// anywhere and have any alignment. Only what is needed to read the
+    /// rest of the file is checked: the magic, a 64-bit layout, which is
+    /// the only one `Header` describes, and the versions. Fields of a file
+    /// in the other byte order than the host's are swapped, see `data`.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        let data = match parse_ident(bytes)? {
+            (Class::Bits64, data) => data,
+            (Class::Bits32, _) => return Err(ParseError::UnsupportedClass(Class::Bits32 as u8)),
+        };
+        let header: Header = read_in_order(&bytes[..EHSIZE_X64], data);
+        if header.e_version != EV_CURRENT as u32 {
+            return Err(ParseError::UnsupportedVersion(header.e_version));
+        }
+        return Ok(header);
+    }
+
+    /// Whether the file can run on `expected`: its machine and class,
+    /// an executable or shared object, and an entry point. Before jumping
+    /// to it, `parse` only checks what's needed to read the rest.
+    pub fn validate_for_target(
+        &self,
+        expected: Machine,
+        expected_class: Class,
+    ) -> Result<(), ValidateError> {
+        if self.e_machine != expected as u16 {
+            return Err(ValidateError::WrongMachine(self.e_machine));
+        }
+        if self.e_ident.ei_class != expected_class as u8 {
+            return Err(ValidateError::WrongClass(self.e_ident.ei_class));
+        }
+        if self.e_type != Type::Executable as u16 && self.e_type != Type::SharedObject as u16 {
+            return Err(ValidateError::NotRunnable(self.e_type));
+        }
+        if self.e_entry.is_none() {
+            return Err(ValidateError::NoEntry);
+        }
+        return Ok(());
+    }
+
+    /// Byte order of the file, tables read through this header are
+    /// swapped to the host's like the header itself
+    pub fn data(&self) -> Data {
+        Data::from_integer(self.e_ident.ei_data).unwrap_or(Data::NATIVE)
+    }
+
+    /// Program header table of `file`, which this header belongs to.
+    /// Empty without looking at `e_phoff` or `e_phentsize` when `e_phnum`
+    /// is 0, like `Elf::parse`.
+    pub fn program_headers<'a>(
+        &self,
+        file: &'a [u8],
+    ) -> Result<HeaderTable<'a, ProgramHeader>, MemoryError> {
+        if self.e_phnum == 0 {
+            return Ok(HeaderTable::empty().in_order(self.data()));
+        }
+        let phoff = match self.e_phoff {
             Some(x) => x.get(),
             None => return Err(MemoryError::UnexpectedEnd),
         };
@@ -61,82 +303,581 @@ impl<'a, M: ElfMachine> Elf<'a, M> {
         }
         let phoff = phoff as usize;
 
-        if header.e_phentsize as usize != mem::size_of::<ProgramHeader>() {
+        /* Larger entries are padded, smaller ones would be read past their end */
+        let stride = self.e_phentsize as usize;
+        if stride < mem::size_of::<ProgramHeader>() {
             return Err(MemoryError::SizeMismatch);
         }
-        let len_bytes = header.e_phnum as usize * mem::size_of::<ProgramHeader>();
+        let len_bytes = self.e_phnum as usize * stride;
 
-        let start = phoff;
-        let end = start + len_bytes;
+        let chunk = phoff
+            .checked_add(len_bytes)
+            .and_then(|end| file.get(phoff..end))
+            .ok_or(MemoryError::UnexpectedEnd)?;
 
-        let chunk = match self.data.get(start..end) {
-            Some(x) => x,
-            None => return Err(MemoryError::UnexpectedEnd),
-        };
+        let table = HeaderTable::with_stride(chunk, stride).ok_or(MemoryError::SizeMismatch)?;
+        return Ok(table.in_order(self.data()));
+    }
 
-        return match bytemuck::try_cast_slice(chunk) {
-            Ok(x) => Ok(x),
-            Err(bytemuck::PodCastError::AlignmentMismatch) => Err(MemoryError::WrongAlignment),
-            Err(_) => unreachable!(),
-        };
+    /// Program headers of `file` one at a time, stopping at the first one
+    /// that runs past its end. For when a truncated table isn't worth an
+    /// error, `program_headers` checks the whole table instead and keeps
+    /// that name. Entries are copies, not `&'a ProgramHeader`, since `file`
+    /// may have any alignment. `load_segments` narrows it to PT_LOAD.
+    pub fn iter_program_headers<'a>(&self, file: &'a [u8]) -> ProgramHeaderIter<'a> {
+        match self.e_phoff {
+            Some(phoff) => ProgramHeaderIter::new(
+                file,
+                phoff.get(),
+                self.e_phentsize as u64,
+                self.e_phnum as usize,
+            )
+            .in_order(self.data()),
+            None => ProgramHeaderIter::empty(),
+        }
     }
 
-    pub fn header(&self) -> &Header {
-        bytemuck::from_bytes(&self.data[..EHSIZE_X64])
+    /// How many segments of each type `file` has, in a single pass
+    pub fn segment_counts(&self, file: &[u8]) -> Result<SegmentCounts, MemoryError> {
+        let mut counts = SegmentCounts::default();
+        for ph in self.program_headers(file)?.iter() {
+            counts.add(ph.segment_type());
+        }
+        return Ok(counts);
     }
 
-    pub fn from_bytes(elf: &'a [u8]) -> Result<Self, Error> {
-        let ptr = elf.as_ptr() as usize;
-        if ptr % 8 != 0 {
-            return Err(Error::WrongAlignment);
+    /// Checks that every PT_LOAD segment starts on a `page_size` boundary
+    /// in virtual memory, so it can be mapped with pages of that size without
+    /// splitting. Unlike `p_align`, which only relates `p_offset` to `p_vaddr`,
+    /// this is about the absolute address. `page_size` must not be 0.
+    pub fn all_segments_page_aligned(
+        &self,
+        file: &[u8],
+        page_size: u64,
+    ) -> Result<(), SegmentError> {
+        for ph in self.program_headers(file)?.iter() {
+            if ph.p_type == PT_LOAD && ph.p_vaddr % page_size != 0 {
+                return Err(SegmentError::Misaligned { vaddr: ph.p_vaddr });
+            }
         }
+        return Ok(());
+    }
 
-        let header_ident = match elf.get(..mem::size_of::<HeaderIdent>()) {
-            Some(x) => x,
-            None => return Err(Error::UnexpectedEnd),
-        };
-        let header_ident: &HeaderIdent = bytemuck::from_bytes(header_ident);
+    /// Lowest and highest virtual address of the PT_LOAD segments of
+    /// `image`, to allocate for the whole image up front. Each segment
+    /// starts at `p_vaddr` aligned down to `p_align` and ends aligned up
+    /// to `LOAD_PAGE_SIZE`. Refuses a program header table that isn't
+    /// inside `image`, like `Elf::parse`, and segments that can't come
+    /// from a sane linker: one with more file than memory, one that ends
+    /// past the top of the address space, or two that overlap.
+    pub fn load_bounds(&self, image: &[u8]) -> Result<LoadBounds, ParseError> {
+        /* Otherwise the iterator below stops early and misses segments */
+        let phoff = self.e_phoff.map_or(0, |x| x.get());
+        check_program_headers::<ProgramHeader>(image, phoff, self.e_phentsize, self.e_phnum)?;
+
+        let loads = self
+            .iter_program_headers(image)
+            .enumerate()
+            .filter(|(_, ph)| ph.p_type == PT_LOAD);
 
-        if header_ident.ei_magic != MAGIC {
-            return Err(Error::NotElf);
+        let mut bounds: Option<LoadBounds> = None;
+        for (index, ph) in loads.clone() {
+            if ph.p_filesz > ph.p_memsz {
+                return Err(ParseError::FileSizeOverMemSize(index));
+            }
+            let overflow = ParseError::SegmentOverflow(index);
+            let vend = ph.p_vaddr.checked_add(ph.p_memsz).ok_or(overflow)?;
+            /* Only the bytes of each, sharing a page is fine */
+            let overlaps = |other: &ProgramHeader| {
+                ph.p_vaddr < other.p_vaddr + other.p_memsz
+                    && other.p_vaddr < vend
+                    && ph.p_memsz != 0
+                    && other.p_memsz != 0
+            };
+            let mut earlier = loads.clone().take_while(|&(i, _)| i < index);
+            if let Some((other, _)) = earlier.find(|(_, other)| overlaps(other)) {
+                return Err(ParseError::SegmentOverlap(other, index));
+            }
+
+            /* 0 and 1 both mean no alignment */
+            let align = core::cmp::max(ph.p_align, 1);
+            let start = ph.p_vaddr - ph.p_vaddr % align;
+            let end = vend.checked_add(LOAD_PAGE_SIZE - 1).ok_or(overflow)? / LOAD_PAGE_SIZE
+                * LOAD_PAGE_SIZE;
+
+            bounds = Some(match bounds {
+                Some(b) => LoadBounds {
+                    min_vaddr: core::cmp::min(b.min_vaddr, start),
+                    max_vaddr: core::cmp::max(b.max_vaddr, end),
+                    total_memsz: b.total_memsz.checked_add(end - start).ok_or(overflow)?,
+                    max_align: core::cmp::max(b.max_align, align),
+                },
+                None => LoadBounds {
+                    min_vaddr: start,
+                    max_vaddr: end,
+                    total_memsz: end - start,
+                    max_align: align,
+                },
+            });
         }
-        if header_ident.ei_class != M::CLASS as u8 {
-            return Err(Error::WrongClass);
+        return bounds.ok_or(ParseError::NoLoadSegments);
+    }
+
+    /// `load_bounds` without the reason it failed
+    pub fn load_image_bounds(&self, image: &[u8]) -> Option<LoadBounds> {
+        self.load_bounds(image).ok()
+    }
+
+    /// Path of the program interpreter from PT_INTERP, like
+    /// "/lib/ld-musl-x86_64.so.1", `None` without one. Only the first
+    /// PT_INTERP is read, any error in it is reported before a second one
+    /// is, as `InterpError::Multiple`.
+    pub fn interpreter<'a>(&self, image: &'a [u8]) -> Option<Result<&'a str, InterpError>> {
+        let is_interp = |ph: &ProgramHeader| ph.segment_type() == Some(SegmentType::Interpreter);
+        let mut segments = self.iter_program_headers(image).filter(is_interp);
+        let ph = segments.next()?;
+        let path = interpreter_path(&ph, image);
+        if path.is_ok() && segments.next().is_some() {
+            return Some(Err(InterpError::Multiple));
         }
-        if header_ident.ei_data != M::ENDIANESS as u8 {
-            return Err(Error::WrongEndianess);
+        return Some(path);
+    }
+
+    /// What PT_GNU_STACK asks of the stack. `None` without one, the loader
+    /// then decides on its own whether the stack is executable.
+    pub fn stack_flags(&self, image: &[u8]) -> Option<StackFlags> {
+        let is_stack = |ph: &ProgramHeader| ph.segment_type() == Some(SegmentType::GnuStack);
+        let ph = self.iter_program_headers(image).find(is_stack)?;
+        return Some(StackFlags { flags: ph.p_flags });
+    }
+
+    /// How many section headers `file` has. When there are too many for
+    /// `e_shnum` it is 0 and the real count is in `sh_size` of section 0.
+    pub fn section_count(&self, file: &[u8]) -> Result<usize, MemoryError> {
+        let shoff = match self.e_shoff {
+            Some(x) if self.e_shnum == 0 => x.get(),
+            _ => return Ok(self.e_shnum as usize),
+        };
+
+        if shoff > usize::MAX as u64 {
+            return Err(MemoryError::UnexpectedEnd);
         }
-        if header_ident.ei_version != EV_CURRENT {
-            return Err(Error::UnsupportedVersion);
+        let start = shoff as usize;
+        let first: SectionHeader = match start
+            .checked_add(mem::size_of::<SectionHeader>())
+            .and_then(|end| file.get(start..end))
+        {
+            Some(x) => read_in_order(x, self.data()),
+            None => return Err(MemoryError::UnexpectedEnd),
+        };
+
+        if first.sh_size > usize::MAX as u64 {
+            return Err(MemoryError::UnexpectedEnd);
         }
-        if header_ident.ei_osabi != M::OSABI as u8 {
-            return Err(Error::WrongOsAbi);
+        return Ok(first.sh_size as usize);
+    }
+
+    /// Section header table of `file`, which this header belongs to.
+    /// All of it has to be inside `file`.
+    pub fn section_headers<'a>(
+        &self,
+        file: &'a [u8],
+    ) -> Result<HeaderTable<'a, SectionHeader>, MemoryError> {
+        let shoff = match self.e_shoff {
+            Some(x) => x.get(),
+            None => return Ok(HeaderTable::empty()),
+        };
+        if shoff > usize::MAX as u64 {
+            return Err(MemoryError::UnexpectedEnd);
         }
-        if header_ident.ei_abiversion != M::ABIVERSION {
-            return Err(Error::WrongOsAbi);
+        let shoff = shoff as usize;
+
+        if self.e_shentsize as usize != mem::size_of::<SectionHeader>() {
+            return Err(MemoryError::SizeMismatch);
         }
+        let len_bytes = self
+            .section_count(file)?
+            .checked_mul(mem::size_of::<SectionHeader>())
+            .ok_or(MemoryError::UnexpectedEnd)?;
 
-        assert_eq!(mem::size_of::<Header>(), EHSIZE_X64);
+        let chunk = shoff
+            .checked_add(len_bytes)
+            .and_then(|end| file.get(shoff..end))
+            .ok_or(MemoryError::UnexpectedEnd)?;
 
-        let header = match elf.get(..EHSIZE_X64) {
+        let table = HeaderTable::new(chunk).ok_or(MemoryError::SizeMismatch)?;
+        return Ok(table.in_order(self.data()));
+    }
+
+    /// Section `index` of `file`, `Ok(None)` past the end of the table.
+    /// Takes more than `u16`, which extended section numbering needs.
+    pub fn section(&self, file: &[u8], index: u32) -> Result<Option<SectionHeader>, MemoryError> {
+        Ok(self.section_headers(file)?.get(index as usize))
+    }
+
+    /// Section headers of `file` one at a time, stopping at the first one
+    /// that runs past its end. `section_headers` checks the whole table.
+    pub fn iter_section_headers<'a>(&self, file: &'a [u8]) -> SectionHeaderIter<'a> {
+        match self.e_shoff {
+            Some(shoff) => SectionHeaderIter::new(
+                file,
+                shoff.get(),
+                self.e_shentsize as u64,
+                self.section_count(file).unwrap_or(0),
+            )
+            .in_order(self.data()),
+            None => SectionHeaderIter::empty(),
+        }
+    }
+
+    /// Section name string table of `file`, for `SectionHeader::name`
+    pub fn shstrtab(&self, file: &[u8]) -> Option<SectionHeader> {
+        let mut sections = self.iter_section_headers(file);
+        let index = match self.e_shstrndx {
+            SHN_UNDEF => return None,
+            SHN_XINDEX => sections.clone().next()?.sh_link as usize,
+            i => i as usize,
+        };
+        return sections.nth(index);
+    }
+
+    /// Section called `name` according to the section name string table
+    pub fn section_by_name(
+        &self,
+        file: &[u8],
+        name: &str,
+    ) -> Result<Option<SectionHeader>, MemoryError> {
+        let strtab = match self.shstrtab_checked(file)? {
             Some(x) => x,
-            None => return Err(Error::UnexpectedEnd),
+            None => return Ok(None),
         };
-        let header: &Header = bytemuck::from_bytes(header);
+        let mut sections = self.section_headers(file)?.iter();
+        return Ok(sections.find(|section| section.name(file, &strtab) == Some(name)));
+    }
 
-        if header.e_type != Type::Executable as u16 {
-            return Err(Error::NotExec);
-        }
-        if header.e_machine != M::MACHINE as u16 {
-            return Err(Error::WrongMachine);
+    /// Name of `section` in the section name string table, see
+    /// `SectionHeader::name`. `None` if the file has no such table or the
+    /// name is malformed.
+    pub fn section_name<'a>(
+        &self,
+        file: &'a [u8],
+        section: &SectionHeader,
+    ) -> Result<Option<&'a str>, MemoryError> {
+        let strtab = self.shstrtab_checked(file)?;
+        return Ok(strtab.and_then(|strtab| section.name(file, &strtab)));
+    }
+
+    /// `shstrtab` that tells a missing table from a broken one
+    fn shstrtab_checked(&self, file: &[u8]) -> Result<Option<SectionHeader>, MemoryError> {
+        let sections = self.section_headers(file)?;
+        let index = match self.e_shstrndx {
+            SHN_UNDEF => return Ok(None),
+            /* Too large for e_shstrndx, section 0 has it */
+            SHN_XINDEX => match sections.get(0) {
+                Some(first) => first.sh_link as usize,
+                None => return Err(MemoryError::UnexpectedEnd),
+            },
+            i => i as usize,
+        };
+        return sections
+            .get(index)
+            .map(Some)
+            .ok_or(MemoryError::UnexpectedEnd);
+    }
+
+    /// Sections with `SHF_ALLOC`, that is the ones a relocatable object loader
+    /// has to place in memory, ordered by `sh_addr` (ties by index)
+    pub fn alloc_sections<'a>(
+        &self,
+        file: &'a [u8],
+    ) -> Result<impl Iterator<Item = SectionHeader> + 'a, MemoryError> {
+        return Ok(AllocSections {
+            sections: self.section_headers(file)?,
+            last: None,
+        });
+    }
+}
+
+/* Selection sort done lazily, as there is no allocator to sort into */
+struct AllocSections<'a> {
+    sections: HeaderTable<'a, SectionHeader>,
+    /// `(sh_addr, index)` of the previously returned section
+    last: Option<(u64, usize)>,
+}
+
+impl<'a> Iterator for AllocSections<'a> {
+    type Item = SectionHeader;
+    fn next(&mut self) -> Option<Self::Item> {
+        let last = self.last;
+        let (_, index) = self
+            .sections
+            .iter()
+            .enumerate()
+            .filter(|(_, sh)| sh.is_alloc())
+            .map(|(i, sh)| (sh.sh_addr, i))
+            .filter(|&key| last.map_or(true, |last| key > last))
+            .min()?;
+
+        let section = self.sections.get(index)?;
+        self.last = Some((section.sh_addr, index));
+        return Some(section);
+    }
+}
+
+fn interpreter_path<'a>(ph: &ProgramHeader, image: &'a [u8]) -> Result<&'a str, InterpError> {
+    let offset = usize::try_from(ph.p_offset).map_err(|_| InterpError::OutOfBounds)?;
+    let len = usize::try_from(ph.p_filesz).map_err(|_| InterpError::OutOfBounds)?;
+    let bytes = Reader::new(image)
+        .read_at(offset, len)
+        .map_err(|_| InterpError::OutOfBounds)?;
+    let path = match bytes.split_last() {
+        None | Some((0, [])) => return Err(InterpError::Empty),
+        Some((0, path)) => path,
+        Some(_) => return Err(InterpError::MissingNul),
+    };
+    if path.contains(&0) {
+        return Err(InterpError::InteriorNul);
+    }
+    return core::str::from_utf8(path).map_err(|_| InterpError::NotUtf8);
+}
+
+impl<'a, M: ElfMachine> Elf<'a, M> {
+    /// See `Header::interpreter`
+    pub fn interpreter(&self) -> Option<Result<&'a str, InterpError>> {
+        self.header().interpreter(self.data)
+    }
+
+    pub fn program_headers(&self) -> Result<HeaderTable<'a, ProgramHeader>, MemoryError> {
+        self.header().program_headers(self.data)
+    }
+
+    /// See `Header::load_bounds`
+    pub fn load_bounds(&self) -> Result<LoadBounds, ParseError> {
+        self.header().load_bounds(self.data)
+    }
+
+    /// PT_LOAD segments, for a loader that only wants to copy them in.
+    /// After `parse` none are missing, see `Header::iter_program_headers`.
+    pub fn load_segments(&self) -> impl Iterator<Item = ProgramHeader> + Clone + 'a {
+        self.header().iter_program_headers(self.data).load_segments()
+    }
+
+    pub fn section_headers(&self) -> Result<HeaderTable<'a, SectionHeader>, MemoryError> {
+        self.header().section_headers(self.data)
+    }
+
+    /// See `Header::section`
+    pub fn section(&self, index: u32) -> Result<Option<SectionHeader>, MemoryError> {
+        self.header().section(self.data, index)
+    }
+
+    /// Section name string table, `None` without one (`e_shstrndx` is
+    /// `SHN_UNDEF`) or if it or the section table is broken
+    pub fn shstrtab(&self) -> Option<StrTab<'a>> {
+        let section = self.header().shstrtab_checked(self.data).ok()??;
+        return StrTab::from_section(self.data, &section);
+    }
+
+    /// Name of `section`, `None` without a section name string table or
+    /// if the name is outside it, not NUL terminated or not UTF-8
+    pub fn section_name(&self, section: &SectionHeader) -> Option<&'a str> {
+        self.shstrtab()?.get(section.sh_name)
+    }
+
+    /// `.symtab`, or `.dynsym` of a stripped file, with the names.
+    /// `None` without either or if the one found is broken.
+    pub fn symbols(&self) -> Option<Symbols<'a>> {
+        let header = self.header();
+        let find = |typ: SectionType| {
+            let typ = typ as u32;
+            header
+                .iter_section_headers(self.data)
+                .find(|section| section.sh_type == typ)
+        };
+
+        let section = find(SectionType::Symtab).or_else(|| find(SectionType::Dynsym))?;
+        return Symbols::new(&header, self.data, &section);
+    }
+
+    /// Bytes of the file backing `ph`, that is `p_filesz` bytes at `p_offset`
+    pub fn segment_data(&self, ph: &ProgramHeader) -> Result<&'a [u8], MemoryError> {
+        let start = ph.p_offset;
+        let end = match start.checked_add(ph.p_filesz) {
+            Some(x) => x,
+            None => return Err(MemoryError::UnexpectedEnd),
+        };
+        if end > usize::MAX as u64 {
+            return Err(MemoryError::UnexpectedEnd);
         }
-        if header.e_version != EV_CURRENT as u32 {
-            return Err(Error::UnsupportedVersion);
+
+        return match self.data.get(start as usize..end as usize) {
+            Some(x) => Ok(x),
+            None => Err(MemoryError::UnexpectedEnd),
+        };
+    }
+
+    /// Checks whether the lowest `p_vaddr` of PT_LOAD segments equals
+    /// `phys_base`, that is whether the image loaded at `phys_base` can run
+    /// identity-mapped, without an extra higher-half mapping pass
+    pub fn is_identity_linkable(&self, phys_base: u64) -> bool {
+        if self.program_headers().is_err() {
+            return false;
         }
 
+        let lowest = self.load_segments().map(|ph| ph.p_vaddr).min();
+
+        return lowest == Some(phys_base);
+    }
+
+    /// Copy of the ELF header, `data` doesn't have to be aligned
+    pub fn header(&self) -> Header {
+        read_in_order(&self.data[..EHSIZE_X64], M::ENDIANESS)
+    }
+
+    /// `elf` may have any alignment, headers are copied out on access.
+    /// Only the identification and the header are checked, see `parse`.
+    pub fn from_bytes(elf: &'a [u8]) -> Result<Self, Error> {
+        check_header::<M>(elf).map_err(header_error)?;
         return Ok(Self {
             data: elf,
             _phantom: core::marker::PhantomData,
         });
     }
+
+    /// `from_bytes` for images that can't be trusted: also checks
+    /// `e_ehsize` and that the program header table is inside `bytes`,
+    /// so `program_headers` can't fail afterwards
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        let header = check_header::<M>(bytes)?;
+        if header.e_ehsize as usize != EHSIZE_X64 {
+            return Err(ParseError::HeaderSize(header.e_ehsize));
+        }
+
+        let phoff = header.e_phoff.map_or(0, |x| x.get());
+        check_program_headers::<ProgramHeader>(bytes, phoff, header.e_phentsize, header.e_phnum)?;
+
+        return Ok(Self {
+            data: bytes,
+            _phantom: core::marker::PhantomData,
+        });
+    }
+}
+
+const _: () = assert!(mem::size_of::<Header>() == EHSIZE_X64);
+
+/// Checks the identification, common to every layout, and that `bytes`
+/// holds the header of its class. Returns the class and the byte order
+/// of the rest.
+pub(crate) fn parse_ident(bytes: &[u8]) -> Result<(Class, Data), ParseError> {
+    let need = mem::size_of::<HeaderIdent>();
+    if bytes.len() < need {
+        return Err(ParseError::TooShort { need, have: bytes.len() });
+    }
+    let ident: HeaderIdent = read_unaligned(bytes);
+
+    if ident.ei_magic != MAGIC {
+        return Err(ParseError::BadMagic);
+    }
+    let class = Class::try_from_integer(ident.ei_class)?;
+    let data = Data::try_from_integer(ident.ei_data)?;
+    if ident.ei_version != EV_CURRENT {
+        return Err(ParseError::UnsupportedVersion(ident.ei_version as u32));
+    }
+
+    let need = match class {
+        Class::Bits32 => EHSIZE_X86,
+        Class::Bits64 => EHSIZE_X64,
+    };
+    if bytes.len() < need {
+        return Err(ParseError::TooShort { need, have: bytes.len() });
+    }
+    return Ok((class, data));
+}
+
+/// Checks that the `phnum` entries of `phentsize` bytes at `phoff` hold
+/// a `T` each and are inside `bytes`, nothing to check without entries
+pub(crate) fn check_program_headers<T>(
+    bytes: &[u8],
+    phoff: u64,
+    phentsize: u16,
+    phnum: u16,
+) -> Result<(), ParseError> {
+    if phnum == 0 {
+        return Ok(());
+    }
+    if (phentsize as usize) < mem::size_of::<T>() {
+        return Err(ParseError::ProgramHeaderSize(phentsize));
+    }
+    if phoff == 0 {
+        return Err(ParseError::ProgramHeaderOffset(0));
+    }
+    let len = phnum as u64 * phentsize as u64;
+    let end = match phoff.checked_add(len) {
+        Some(end) if end <= usize::MAX as u64 => end,
+        _ => return Err(ParseError::ProgramHeaderOffset(phoff)),
+    };
+    if end > bytes.len() as u64 {
+        return Err(ParseError::ProgramHeadersTruncated {
+            end,
+            len: bytes.len(),
+        });
+    }
+    return Ok(());
+}
+
+/// What `Elf::from_bytes` reports for a `check_header` failure
+fn header_error(e: ParseError) -> Error {
+    match e {
+        ParseError::TooShort { .. } => Error::UnexpectedEnd,
+        ParseError::BadMagic => Error::NotElf,
+        ParseError::UnsupportedClass(_) => Error::WrongClass,
+        ParseError::UnsupportedData(_) => Error::WrongEndianess,
+        ParseError::UnsupportedVersion(_) => Error::UnsupportedVersion,
+        ParseError::WrongOsAbi(_) | ParseError::WrongAbiVersion(_) => Error::WrongOsAbi,
+        ParseError::NotExec(_) => Error::NotExec,
+        ParseError::WrongMachine(_) => Error::WrongMachine,
+        /* Only `Header::try_*`, `Elf::parse` and `load_bounds` look further */
+        ParseError::UnknownOsAbi(_)
+        | ParseError::UnknownMachine(_)
+        | ParseError::UnknownSegmentType(_)
+        | ParseError::HeaderSize(_)
+        | ParseError::NoLoadSegments
+        | ParseError::FileSizeOverMemSize(_)
+        | ParseError::SegmentOverflow(_)
+        | ParseError::SegmentOverlap(..)
+        | ParseError::ProgramHeaderSize(_)
+        | ParseError::ProgramHeaderOffset(_)
+        | ParseError::ProgramHeadersTruncated { .. } => {
+            unreachable!("check_header returned {:?}", e)
+        }
+    }
+}
+
+/// Identification and header checks shared by `Elf::from_bytes` and
+/// `Elf::parse`, on top of `Header::parse`
+fn check_header<M: ElfMachine>(elf: &[u8]) -> Result<Header, ParseError> {
+    /* Before the rest, which is garbage in the wrong byte order */
+    let (_, data) = parse_ident(elf)?;
+    if data != M::ENDIANESS {
+        return Err(ParseError::UnsupportedData(data as u8));
+    }
+
+    let header = Header::parse(elf)?;
+    let ident = header.e_ident;
+    if ident.ei_class != M::CLASS as u8 {
+        return Err(ParseError::UnsupportedClass(ident.ei_class));
+    }
+    if ident.ei_osabi != M::OSABI as u8 {
+        return Err(ParseError::WrongOsAbi(ident.ei_osabi));
+    }
+    if ident.ei_abiversion != M::ABIVERSION {
+        return Err(ParseError::WrongAbiVersion(ident.ei_abiversion));
+    }
+    if header.e_type != Type::Executable as u16 {
+        return Err(ParseError::NotExec(header.e_type));
+    }
+    if header.e_machine != M::MACHINE as u16 {
+        return Err(ParseError::WrongMachine(header.e_machine));
+    }
+    return Ok(header);
 }
@@ -0,0 +1,161 @@
+use crate::{Data, Elf, ElfMachine, Header, MemoryError, ProgramHeader, SegmentType};
+use impl_bits::bytes::Reader;
+
+/// Owner of the notes GNU tools write
+pub const GNU_NOTE_NAME: &str = "GNU";
+/// `--build-id`, the descriptor is the ID, usually a 20 byte SHA-1
+pub const NT_GNU_BUILD_ID: u32 = 3;
+/// Program properties like CET, padded to 8 on 64-bit
+pub const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+
+/// One entry of a PT_NOTE segment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Note<'a> {
+    /// Owner, without the terminating null
+    pub name: &'a str,
+    /// Meaning of `desc`, up to the owner
+    pub note_type: u32,
+    pub desc: &'a [u8],
+}
+
+/// Entries of a note segment. Fields are padded to `align`, which is 4
+/// for everything but GNU property notes. A truncated entry ends iteration,
+/// one whose name isn't UTF-8 is skipped.
+/// Sizes and types are in the host's byte order unless made `in_order`.
+#[derive(Clone, Copy, Debug)]
+pub struct Notes<'a> {
+    bytes: &'a [u8],
+    align: usize,
+    data: Data,
+}
+
+const fn align_up(x: usize, align: usize) -> Option<usize> {
+    match x.checked_add(align - 1) {
+        Some(x) => Some(x & !(align - 1)),
+        None => None,
+    }
+}
+
+impl<'a> Notes<'a> {
+    /// `align` of 8 is used as is, anything else means 4
+    pub fn new(bytes: &'a [u8], align: u64) -> Self {
+        let align = if align == 8 { 8 } else { 4 };
+        Self {
+            bytes,
+            align,
+            data: Data::NATIVE,
+        }
+    }
+
+    /// Entries of a file with `data` byte order
+    pub fn in_order(self, data: Data) -> Self {
+        Self { data, ..self }
+    }
+
+    /// Name with its null, type and desc of the next entry
+    fn next_raw(&mut self) -> Option<(&'a [u8], u32, &'a [u8])> {
+        let mut entry = Reader::new(self.bytes);
+        let word = |at| match self.data {
+            Data::Lsb => entry.u32_le_at(at),
+            Data::Msb => entry.u32_be_at(at),
+        };
+        let namesz = word(0).ok()? as usize;
+        let descsz = word(4).ok()? as usize;
+        let note_type = word(8).ok()?;
+        let align = self.align;
+        let pad = |entry: &Reader| Some(align_up(entry.position(), align)? - entry.position());
+
+        entry.skip(align_up(12, align)?).ok()?;
+        let name = entry.take(namesz).ok()?;
+        entry.skip(pad(&entry)?).ok()?;
+        let desc = entry.take(descsz).ok()?;
+        /* The last entry may lack padding */
+        entry
+            .skip(core::cmp::min(pad(&entry)?, entry.remaining()))
+            .ok()?;
+        self.bytes = entry.rest();
+        return Some((name, note_type, desc));
+    }
+}
+
+impl<'a> Iterator for Notes<'a> {
+    type Item = Note<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (name, note_type, desc) = self.next_raw()?;
+            let name = match name.split_last() {
+                Some((&0, name)) => name,
+                _ => name,
+            };
+            if let Ok(name) = core::str::from_utf8(name) {
+                return Some(Note {
+                    name,
+                    note_type,
+                    desc,
+                });
+            }
+        }
+    }
+}
+
+/// File bytes of a segment
+fn segment_bytes<'a>(file: &'a [u8], ph: &ProgramHeader) -> Result<&'a [u8], MemoryError> {
+    let start = ph.p_offset as usize;
+    return start
+        .checked_add(ph.p_filesz as usize)
+        .and_then(|end| file.get(start..end))
+        .ok_or(MemoryError::UnexpectedEnd);
+}
+
+impl Header {
+    /// Entries of every PT_NOTE segment of `file`, in program header order,
+    /// read in the file's byte order
+    pub fn notes<'a>(
+        &self,
+        file: &'a [u8],
+    ) -> Result<impl Iterator<Item = Note<'a>> + 'a, MemoryError> {
+        let is_note = |ph: &ProgramHeader| ph.segment_type() == Some(SegmentType::Note);
+        let mut segments = self.program_headers(file)?.iter().filter(is_note);
+        /* Checked upfront, so that iteration can't fail */
+        for ph in segments.clone() {
+            segment_bytes(file, &ph)?;
+        }
+
+        let data = self.data();
+        let mut notes = Notes::new(&[], 4);
+        return Ok(core::iter::from_fn(move || loop {
+            if let Some(note) = notes.next() {
+                return Some(note);
+            }
+            let ph = segments.next()?;
+            notes = Notes::new(segment_bytes(file, &ph).ok()?, ph.p_align).in_order(data);
+        }));
+    }
+
+    /// First note owned by `name` with type `note_type`
+    pub fn find_note<'a>(
+        &self,
+        file: &'a [u8],
+        name: &str,
+        note_type: u32,
+    ) -> Result<Option<Note<'a>>, MemoryError> {
+        let mut notes = self.notes(file)?;
+        return Ok(notes.find(|note| note.name == name && note.note_type == note_type));
+    }
+
+    /// Descriptor of the GNU build ID note of `image`, `None` without one
+    /// or if the note segments are broken
+    pub fn build_id<'a>(&self, image: &'a [u8]) -> Option<&'a [u8]> {
+        let note = self
+            .find_note(image, GNU_NOTE_NAME, NT_GNU_BUILD_ID)
+            .ok()??;
+        return Some(note.desc);
+    }
+}
+
+impl<'a, M: ElfMachine> Elf<'a, M> {
+    /// See `Header::build_id`
+    pub fn build_id(&self) -> Option<&'a [u8]> {
+        self.header().build_id(self.data)
+    }
+}
@@ -0,0 +1,211 @@
+//! Relocations, with an addend as x86-64 uses them or with the addend
+//! in the relocated field
+
+use crate::{Data, HeaderIter, SectionHeader, SectionType};
+use bytemuck::{Pod, Zeroable};
+use core::fmt;
+use impl_bits::fmt::Addr;
+
+pub const R_X86_64_NONE: u32 = 0;
+/// `S + A`
+pub const R_X86_64_64: u32 = 1;
+/// `S + A - P`
+pub const R_X86_64_PC32: u32 = 2;
+pub const R_X86_64_GOT32: u32 = 3;
+pub const R_X86_64_PLT32: u32 = 4;
+pub const R_X86_64_COPY: u32 = 5;
+pub const R_X86_64_GLOB_DAT: u32 = 6;
+pub const R_X86_64_JUMP_SLOT: u32 = 7;
+/// `B + A`, the load bias plus the addend
+pub const R_X86_64_RELATIVE: u32 = 8;
+pub const R_X86_64_GOTPCREL: u32 = 9;
+pub const R_X86_64_32: u32 = 10;
+pub const R_X86_64_32S: u32 = 11;
+pub const R_X86_64_DTPMOD64: u32 = 16;
+pub const R_X86_64_DTPOFF64: u32 = 17;
+pub const R_X86_64_TPOFF64: u32 = 18;
+/// `B + A` is a resolver function returning the value
+pub const R_X86_64_IRELATIVE: u32 = 37;
+
+/// `Elf64_Rela`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Rela {
+    /// Virtual address of the field to relocate
+    pub r_offset: u64,
+    /// Symbol index in the high half, type in the low half
+    pub r_info: u64,
+    pub r_addend: i64,
+}
+
+unsafe impl Zeroable for Rela {}
+unsafe impl Pod for Rela {}
+
+pub type RelaIter<'a> = HeaderIter<'a, Rela>;
+
+/// `Elf64_Rel`, the addend is the relocated field's value
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Rel {
+    /// Virtual address of the field to relocate
+    pub r_offset: u64,
+    /// Symbol index in the high half, type in the low half
+    pub r_info: u64,
+}
+
+unsafe impl Zeroable for Rel {}
+unsafe impl Pod for Rel {}
+
+pub type RelIter<'a> = HeaderIter<'a, Rel>;
+
+/// `Rela` or `Rel`, what `apply_relative_relocations` needs of an entry
+pub trait Relocation: Copy {
+    fn r_offset(&self) -> u64;
+    fn r_info(&self) -> u64;
+    /// Addend of the entry, `field` is what's at `r_offset` in the image
+    fn addend(&self, field: [u8; 8]) -> i64;
+}
+
+impl Relocation for Rela {
+    fn r_offset(&self) -> u64 {
+        self.r_offset
+    }
+
+    fn r_info(&self) -> u64 {
+        self.r_info
+    }
+
+    fn addend(&self, _field: [u8; 8]) -> i64 {
+        self.r_addend
+    }
+}
+
+impl Relocation for Rel {
+    fn r_offset(&self) -> u64 {
+        self.r_offset
+    }
+
+    fn r_info(&self) -> u64 {
+        self.r_info
+    }
+
+    fn addend(&self, field: [u8; 8]) -> i64 {
+        i64::from_le_bytes(field)
+    }
+}
+
+impl Rela {
+    /// Index in the symbol table the section links to
+    pub fn symbol(&self) -> u32 {
+        (self.r_info >> 32) as u32
+    }
+
+    /// One of `R_X86_64_*`
+    pub fn rel_type(&self) -> u32 {
+        self.r_info as u32
+    }
+}
+
+impl Rel {
+    /// Index in the symbol table the section links to
+    pub fn symbol(&self) -> u32 {
+        (self.r_info >> 32) as u32
+    }
+
+    /// One of `R_X86_64_*`
+    pub fn rel_type(&self) -> u32 {
+        self.r_info as u32
+    }
+}
+
+impl fmt::Debug for Rela {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rela")
+            .field("offset", &Addr(self.r_offset))
+            .field("symbol", &self.symbol())
+            .field("type", &self.rel_type())
+            .field("addend", &format_args!("{:#x}", self.r_addend))
+            .finish()
+    }
+}
+
+impl fmt::Debug for Rel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rel")
+            .field("offset", &Addr(self.r_offset))
+            .field("symbol", &self.symbol())
+            .field("type", &self.rel_type())
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelocationError {
+    /// Anything but `R_X86_64_RELATIVE` and `R_X86_64_NONE`
+    Unsupported { r_offset: u64, r_type: u32 },
+    /// The field at `r_offset` isn't within the image
+    OutOfBounds { r_offset: u64 },
+}
+
+impl SectionHeader {
+    /// Entries of this section of `file`, read in its `data` byte order.
+    /// `None` unless it is a `SHT_RELA` section of `Rela`s that lies
+    /// within `file`.
+    pub fn relocations<'a>(&self, file: &'a [u8], data: Data) -> Option<RelaIter<'a>> {
+        if self.sh_type != SectionType::Rela as u32 {
+            return None;
+        }
+        return Some(self.entries(file)?.in_order(data));
+    }
+
+    /// Entries of this `SHT_REL` section of `file`, like `relocations`
+    pub fn rel_relocations<'a>(&self, file: &'a [u8], data: Data) -> Option<RelIter<'a>> {
+        if self.sh_type != SectionType::Rel as u32 {
+            return None;
+        }
+        return Some(self.entries(file)?.in_order(data));
+    }
+}
+
+/// Applies `relocations` to `image`, the loaded image with offset 0 at
+/// virtual address 0, as if it was loaded `load_bias` bytes higher.
+/// Returns how many were applied, or stops at the first one that isn't
+/// `R_X86_64_RELATIVE` and leaves `image` partly relocated. `Rel`
+/// addends are read from the image before it's patched.
+pub fn apply_relative_relocations<R: Relocation>(
+    image: &mut [u8],
+    relocations: impl IntoIterator<Item = R>,
+    load_bias: u64,
+) -> Result<usize, RelocationError> {
+    let mut applied = 0;
+    for entry in relocations {
+        match entry.r_info() as u32 {
+            R_X86_64_NONE => continue,
+            R_X86_64_RELATIVE => (),
+            r_type => {
+                return Err(RelocationError::Unsupported {
+                    r_offset: entry.r_offset(),
+                    r_type,
+                })
+            }
+        }
+
+        let r_offset = entry.r_offset();
+        let out_of_bounds = RelocationError::OutOfBounds { r_offset };
+        if r_offset > usize::MAX as u64 {
+            return Err(out_of_bounds);
+        }
+        let start = r_offset as usize;
+        let field = start
+            .checked_add(8)
+            .and_then(|end| image.get_mut(start..end))
+            .ok_or(out_of_bounds)?;
+
+        let mut addend = [0u8; 8];
+        addend.copy_from_slice(field);
+        let value = load_bias.wrapping_add(entry.addend(addend) as u64);
+        field.copy_from_slice(&value.to_le_bytes());
+        applied += 1;
+    }
+    return Ok(applied);
+}
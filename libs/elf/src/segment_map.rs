@@ -0,0 +1,123 @@
+//! Which PT_LOAD segment every allocated section ended up in. Segment
+//! permissions are what gets mapped, so a linker script that puts a
+//! section into the wrong segment only shows up as a fault much later,
+//! like a write to `.data` placed in the RX segment.
+
+use crate::{Header, HeaderTable, MemoryError, ProgramHeader, SectionHeader, SectionType, PT_LOAD};
+use core::fmt;
+
+/// Table header matching `SectionPlacement::write_row`
+pub const SECTION_MAP_HEADER: &str =
+    "Nr  Name                 Address            Size       Flg  Segment\n";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectionConflict {
+    /// Not inside any PT_LOAD segment, so it isn't loaded at all
+    Outside,
+    /// Starts in the segment but ends past it
+    SpansSegments,
+    /// SHF_WRITE in a segment without PF_W
+    WritableInReadOnly,
+    /// SHF_EXECINSTR in a segment without PF_X
+    ExecutableInNoExec,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SectionPlacement {
+    /// Index in the section header table
+    pub index: usize,
+    pub section: SectionHeader,
+    /// Index in the program header table and the PT_LOAD segment the
+    /// section starts in
+    pub segment: Option<(usize, ProgramHeader)>,
+    /// The first problem found, in the order of `SectionConflict`
+    pub conflict: Option<SectionConflict>,
+}
+
+impl SectionPlacement {
+    fn new(index: usize, section: SectionHeader, segments: HeaderTable<ProgramHeader>) -> Self {
+        let start = section.sh_addr;
+        /* `.tbss` overlaps whatever follows it, it has no bytes of its own */
+        let tbss = section.is_tls() && section.sh_type == SectionType::Nobits as u32;
+        let size = if tbss { 0 } else { section.sh_size };
+        let end = start.saturating_add(size);
+
+        /* An empty section may sit right at the end of its segment */
+        let segment = segments.iter().enumerate().find(|(_, ph)| {
+            let seg_end = ph.p_vaddr.saturating_add(ph.p_memsz);
+            ph.p_type == PT_LOAD
+                && ph.p_vaddr <= start
+                && (start < seg_end || size == 0 && start == seg_end)
+        });
+
+        let conflict = match segment {
+            None => Some(SectionConflict::Outside),
+            Some((_, ph)) if end > ph.p_vaddr.saturating_add(ph.p_memsz) => {
+                Some(SectionConflict::SpansSegments)
+            }
+            Some((_, ph)) if section.is_writable() && !ph.is_writable() => {
+                Some(SectionConflict::WritableInReadOnly)
+            }
+            Some((_, ph)) if section.is_executable() && !ph.is_executable() => {
+                Some(SectionConflict::ExecutableInNoExec)
+            }
+            Some(_) => None,
+        };
+
+        return Self {
+            index,
+            section,
+            segment,
+            conflict,
+        };
+    }
+
+    /// One line of a readelf-like table under `SECTION_MAP_HEADER`
+    pub fn write_row(&self, out: &mut dyn fmt::Write, name: Option<&str>) -> fmt::Result {
+        let name = name.unwrap_or("?");
+        let sh = &self.section;
+        let flags = [
+            if sh.is_writable() { 'W' } else { ' ' },
+            'A',
+            if sh.is_executable() { 'X' } else { ' ' },
+        ];
+        write!(
+            out,
+            "{:<3} {:<20} {:#018x} {:#010x} {}{}{} ",
+            self.index, name, sh.sh_addr, sh.sh_size, flags[0], flags[1], flags[2],
+        )?;
+
+        match self.segment {
+            Some((i, ph)) => {
+                let perms = [
+                    if ph.is_readable() { 'R' } else { ' ' },
+                    if ph.is_writable() { 'W' } else { ' ' },
+                    if ph.is_executable() { 'E' } else { ' ' },
+                ];
+                write!(out, " {:<3} {}{}{}", i, perms[0], perms[1], perms[2])?;
+            }
+            None => write!(out, " -      ")?,
+        }
+        match self.conflict {
+            Some(conflict) => write!(out, "  {:?}\n", conflict),
+            None => write!(out, "\n"),
+        }
+    }
+}
+
+impl Header {
+    /// Where every SHF_ALLOC section of `file` lies among the PT_LOAD
+    /// segments, by virtual address, in section table order
+    pub fn section_segment_map<'a>(
+        &self,
+        file: &'a [u8],
+    ) -> Result<impl Iterator<Item = SectionPlacement> + 'a, MemoryError> {
+        let sections = self.section_headers(file)?;
+        let segments = self.program_headers(file)?;
+        return Ok(sections
+            .iter()
+            .enumerate()
+            .filter(|(_, sh)| sh.is_alloc())
+            .map(move |(i, sh)| SectionPlacement::new(i, sh, segments)));
+    }
+}
@@ -0,0 +1,35 @@
+use crate::SectionHeader;
+use core::convert::TryFrom;
+
+/// A string table, like `.shstrtab` or `.strtab`: NUL terminated strings
+/// looked up by their offset from the start of the table
+#[derive(Clone, Copy, Debug)]
+pub struct StrTab<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StrTab<'a> {
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Contents of `section` in `file`, `None` if they aren't inside it
+    pub fn from_section(file: &'a [u8], section: &SectionHeader) -> Option<Self> {
+        let start = usize::try_from(section.sh_offset).ok()?;
+        let len = usize::try_from(section.sh_size).ok()?;
+        let bytes = file.get(start..start.checked_add(len)?)?;
+        return Some(Self::new(bytes));
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// String at `offset`. `None` if it starts outside the table, isn't
+    /// NUL terminated inside it or isn't UTF-8.
+    pub fn get(&self, offset: u32) -> Option<&'a str> {
+        let s = self.bytes.get(offset as usize..)?;
+        let len = s.iter().position(|&c| c == 0)?;
+        return core::str::from_utf8(&s[..len]).ok();
+    }
+}
@@ -0,0 +1,189 @@
+//! Entries of `.symtab` and `.dynsym`
+
+use crate::{Header, HeaderIter, SectionHeader, SectionType, StrTab};
+use bytemuck::{Pod, Zeroable};
+use core::fmt;
+use impl_bits::debug_enum;
+use impl_bits::fmt::{Addr, Size};
+
+/// `Elf64_Sym`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Symbol {
+    /// Offset in the string table the symbol table links to
+    pub st_name: u32,
+    /// `SymbolBinding` in the high nibble, `SymbolType` in the low one
+    pub st_info: u8,
+    /// Visibility in the low 2 bits
+    pub st_other: u8,
+    /// Index of the section the symbol is defined in, 0 if undefined
+    pub st_shndx: u16,
+    pub st_value: u64,
+    pub st_size: u64,
+}
+
+unsafe impl Zeroable for Symbol {}
+unsafe impl Pod for Symbol {}
+
+pub type SymbolIter<'a> = HeaderIter<'a, Symbol>;
+
+impl Symbol {
+    pub fn binding(&self) -> Option<SymbolBinding> {
+        SymbolBinding::from_integer(self.st_info >> 4)
+    }
+
+    pub fn symbol_type(&self) -> Option<SymbolType> {
+        SymbolType::from_integer(self.st_info & 0xF)
+    }
+
+    pub fn is_undefined(&self) -> bool {
+        self.st_shndx == 0
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("Symbol");
+        s.field("name", &self.st_name);
+        match self.binding() {
+            Some(x) => s.field("binding", &x),
+            None => s.field("binding", &format_args!("Unknown ({})", self.st_info >> 4)),
+        };
+        match self.symbol_type() {
+            Some(x) => s.field("type", &x),
+            None => s.field("type", &format_args!("Unknown ({})", self.st_info & 0xF)),
+        };
+        s.field("shndx", &self.st_shndx)
+            .field("value", &Addr(self.st_value))
+            .field("size", &Size(self.st_size))
+            .finish()
+    }
+}
+
+debug_enum! {
+    #[repr(u8)]
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum SymbolBinding {
+        Local = 0,
+        Global = 1,
+        Weak = 2,
+        /* GNU extension, from the OS specific range */
+        GnuUnique = 10,
+    }
+}
+
+impl SymbolBinding {
+    pub fn from_integer(x: u8) -> Option<Self> {
+        let r = match x {
+            0 => Self::Local,
+            1 => Self::Global,
+            2 => Self::Weak,
+            10 => Self::GnuUnique,
+            _ => return None,
+        };
+
+        return Some(r);
+    }
+}
+
+debug_enum! {
+    #[repr(u8)]
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum SymbolType {
+        NoType = 0,
+        Object = 1,
+        Func = 2,
+        Section = 3,
+        File = 4,
+        Common = 5,
+        Tls = 6,
+        /* GNU extension, from the OS specific range */
+        GnuIfunc = 10,
+    }
+}
+
+impl SymbolType {
+    pub fn from_integer(x: u8) -> Option<Self> {
+        let r = match x {
+            0 => Self::NoType,
+            1 => Self::Object,
+            2 => Self::Func,
+            3 => Self::Section,
+            4 => Self::File,
+            5 => Self::Common,
+            6 => Self::Tls,
+            10 => Self::GnuIfunc,
+            _ => return None,
+        };
+
+        return Some(r);
+    }
+}
+
+impl SectionHeader {
+    /// Entries of this section of `file`. `None` unless it is a symbol
+    /// table of `Symbol`s that lies within `file`.
+    pub fn symbols<'a>(&self, file: &'a [u8]) -> Option<SymbolIter<'a>> {
+        let is_symtab = self.sh_type == SectionType::Symtab as u32
+            || self.sh_type == SectionType::Dynsym as u32;
+        if !is_symtab {
+            return None;
+        }
+        return self.entries(file);
+    }
+}
+
+/// A symbol table with the string table its `sh_link` names. Yields every
+/// symbol with its name, `None` if `st_name` isn't a string of the table.
+#[derive(Clone)]
+pub struct Symbols<'a> {
+    symbols: SymbolIter<'a>,
+    strtab: StrTab<'a>,
+}
+
+impl<'a> Symbols<'a> {
+    /// `section` of `file`, `None` unless it is a symbol table linked to
+    /// a string table and both lie within `file`
+    pub fn new(header: &Header, file: &'a [u8], section: &SectionHeader) -> Option<Self> {
+        let symbols = section.symbols(file)?.in_order(header.data());
+        let strings = header.section(file, section.sh_link).ok()??;
+        if strings.sh_type != SectionType::Strtab as u32 {
+            return None;
+        }
+
+        let strtab = StrTab::from_section(file, &strings)?;
+        return Some(Self { symbols, strtab });
+    }
+
+    pub fn strtab(&self) -> StrTab<'a> {
+        self.strtab
+    }
+
+    /// Name of the function `addr` is in and how far into it `addr` is,
+    /// for backtraces. Functions without a size or a name never match.
+    pub fn resolve_address(&self, addr: u64) -> Option<(&'a str, u64)> {
+        for (symbol, name) in self.clone() {
+            if symbol.symbol_type() != Some(SymbolType::Func) || symbol.is_undefined() {
+                continue;
+            }
+
+            let offset = addr.wrapping_sub(symbol.st_value);
+            if addr < symbol.st_value || offset >= symbol.st_size {
+                continue;
+            }
+            if let Some(name) = name {
+                return Some((name, offset));
+            }
+        }
+        return None;
+    }
+}
+
+impl<'a> Iterator for Symbols<'a> {
+    type Item = (Symbol, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let symbol = self.symbols.next()?;
+        return Some((symbol, self.strtab.get(symbol.st_name)));
+    }
+}
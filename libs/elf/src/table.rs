@@ -0,0 +1,211 @@
+use crate::{read_in_order, ByteSwap, Data, ProgramHeader, SectionHeader, PT_LOAD};
+use bytemuck::Pod;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+
+/// Copies a `T` out of the start of `bytes`, which may have any alignment.
+/// Panics if `bytes` is too short.
+pub fn read_unaligned<T: Pod>(bytes: &[u8]) -> T {
+    let bytes = &bytes[..mem::size_of::<T>()];
+    /* SAFETY: length checked above, every bit pattern is a valid Pod */
+    unsafe {
+        return (bytes.as_ptr() as *const T).read_unaligned();
+    }
+}
+
+/// Table of headers inside a file buffer of any alignment.
+/// Entries are small and copied out on access instead of borrowed,
+/// so this works on buffers from archives or filesystem reads.
+/// They are in the host's byte order unless made `in_order`.
+#[derive(Clone, Copy)]
+pub struct HeaderTable<'a, T: ByteSwap> {
+    bytes: &'a [u8],
+    /// Bytes from one entry to the next, at least the size of `T`
+    stride: usize,
+    data: Data,
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, T: ByteSwap> HeaderTable<'a, T> {
+    /// `None` if `bytes` isn't a whole number of `T`s
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        Self::with_stride(bytes, mem::size_of::<T>())
+    }
+
+    /// Entries `stride` bytes apart, like `e_phentsize` larger than the
+    /// header. `None` if `stride` can't hold a `T` or `bytes` isn't
+    /// a whole number of entries.
+    pub fn with_stride(bytes: &'a [u8], stride: usize) -> Option<Self> {
+        if stride == 0 || stride < mem::size_of::<T>() || bytes.len() % stride != 0 {
+            return None;
+        }
+        return Some(Self {
+            bytes,
+            stride,
+            data: Data::NATIVE,
+            _phantom: PhantomData,
+        });
+    }
+
+    pub const fn empty() -> Self {
+        Self {
+            bytes: &[],
+            stride: mem::size_of::<T>(),
+            data: Data::NATIVE,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Entries of a file with `data` byte order
+    pub fn in_order(self, data: Data) -> Self {
+        Self { data, ..self }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len() / self.stride
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<T> {
+        let size = mem::size_of::<T>();
+        let start = index.checked_mul(self.stride)?;
+        let entry = self.bytes.get(start..start.checked_add(size)?)?;
+        return Some(read_in_order(entry, self.data));
+    }
+
+    /// Like `<[T]>::split_first`
+    pub fn split_first(&self) -> Option<(T, Self)> {
+        let first = self.get(0)?;
+        let rest = Self {
+            bytes: &self.bytes[self.stride..],
+            ..*self
+        };
+        return Some((first, rest));
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = T> + ExactSizeIterator + Clone + 'a {
+        let data = self.data;
+        self.bytes
+            .chunks_exact(self.stride)
+            .map(move |entry| read_in_order::<T>(entry, data))
+    }
+}
+
+impl<'a, T: ByteSwap + fmt::Debug> fmt::Debug for HeaderTable<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Headers `stride` bytes apart, copied out like `HeaderTable` entries.
+/// Unlike `Header::program_headers` and `Header::section_headers` nothing
+/// is checked up front, iteration stops at the first entry that doesn't
+/// fit in the file.
+#[derive(Clone)]
+pub struct HeaderIter<'a, T: ByteSwap> {
+    file: &'a [u8],
+    offset: usize,
+    stride: usize,
+    remaining: usize,
+    data: Data,
+    _phantom: PhantomData<T>,
+}
+
+pub type ProgramHeaderIter<'a> = HeaderIter<'a, ProgramHeader>;
+pub type SectionHeaderIter<'a> = HeaderIter<'a, SectionHeader>;
+
+impl<'a, T: ByteSwap> HeaderIter<'a, T> {
+    /// `count` entries from `offset` in `file`. A `stride` larger than `T`
+    /// skips the extra bytes of each entry, a smaller one can't hold an
+    /// entry and yields nothing.
+    pub fn new(file: &'a [u8], offset: u64, stride: u64, count: usize) -> Self {
+        let fits = offset <= usize::MAX as u64
+            && stride <= usize::MAX as u64
+            && stride as usize >= mem::size_of::<T>();
+        Self {
+            file,
+            offset: offset as usize,
+            stride: stride as usize,
+            remaining: if fits { count } else { 0 },
+            data: Data::NATIVE,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub const fn empty() -> Self {
+        Self {
+            file: &[],
+            offset: 0,
+            stride: 0,
+            remaining: 0,
+            data: Data::NATIVE,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Entries of a file with `data` byte order
+    pub fn in_order(self, data: Data) -> Self {
+        Self { data, ..self }
+    }
+}
+
+impl<'a> ProgramHeaderIter<'a> {
+    /// Only PT_LOAD segments, the ones a loader maps
+    pub fn load_segments(self) -> impl Iterator<Item = ProgramHeader> + Clone + 'a {
+        self.filter(|ph| ph.p_type == PT_LOAD)
+    }
+}
+
+impl<'a, T: ByteSwap> Iterator for HeaderIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let entry = self
+            .offset
+            .checked_add(mem::size_of::<T>())
+            .and_then(|end| self.file.get(self.offset..end));
+        let entry = match entry {
+            Some(x) => x,
+            None => {
+                self.remaining = 0;
+                return None;
+            }
+        };
+
+        self.remaining -= 1;
+        /* Past the end at worst, the next lookup fails */
+        self.offset = self.offset.saturating_add(self.stride);
+        return Some(read_in_order(entry, self.data));
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+impl<'a, T: ByteSwap> core::iter::FusedIterator for HeaderIter<'a, T> {}
+
+impl SectionHeader {
+    /// This section of `file` as a table of `T`s, `None` unless `sh_entsize`
+    /// is the size of `T` and the section is whole entries within `file`
+    pub(crate) fn entries<'a, T: ByteSwap>(&self, file: &'a [u8]) -> Option<HeaderIter<'a, T>> {
+        let size = mem::size_of::<T>() as u64;
+        if self.sh_entsize != size || self.sh_size % size != 0 {
+            return None;
+        }
+
+        let end = self.sh_offset.checked_add(self.sh_size)?;
+        if end > file.len() as u64 {
+            return None;
+        }
+        let count = (self.sh_size / size) as usize;
+        return Some(HeaderIter::new(file, self.sh_offset, size, count));
+    }
+}
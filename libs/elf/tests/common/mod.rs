@@ -0,0 +1,153 @@
+//! ELF files built in memory, shared by the tests
+#![allow(dead_code)]
+
+use core::mem::size_of;
+use core::num::NonZeroU64;
+use elf::*;
+
+pub const PH_SIZE: usize = size_of::<ProgramHeader>();
+pub const SH_SIZE: usize = size_of::<SectionHeader>();
+
+pub fn bytes_of<T>(x: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(x as *const T as *const u8, size_of::<T>()) }
+}
+
+/// A byte of padding, then `entries`, so the table is misaligned
+pub fn unaligned_table<T>(entries: &[T]) -> Vec<u8> {
+    let mut bytes = vec![0u8];
+    for entry in entries {
+        bytes.extend_from_slice(bytes_of(entry));
+    }
+    return bytes;
+}
+
+/// An x86-64 executable with `phnum` program headers right after it
+pub fn header(phnum: u16) -> Header {
+    Header {
+        e_ident: HeaderIdent {
+            ei_magic: MAGIC,
+            ei_class: Class::Bits64 as u8,
+            ei_data: Data::Lsb as u8,
+            ei_version: EV_CURRENT,
+            ei_osabi: OsAbi::SystemV as u8,
+            ei_abiversion: 0,
+            ei_pad: [0; 7],
+        },
+        e_type: Type::Executable as u16,
+        e_machine: Machine::X64 as u16,
+        e_version: EV_CURRENT as u32,
+        e_entry: NonZeroU64::new(0x20_0000),
+        e_phoff: NonZeroU64::new(EHSIZE_X64 as u64),
+        e_shoff: None,
+        e_flags: 0,
+        e_ehsize: EHSIZE_X64 as u16,
+        e_phentsize: PH_SIZE as u16,
+        e_phnum: phnum,
+        e_shentsize: SH_SIZE as u16,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    }
+}
+
+pub fn segment(typ: SegmentType, offset: usize, len: usize, align: u64) -> ProgramHeader {
+    let mut ph = ProgramHeader::new_load(PF_R, offset as u64, 0, len as u64, len as u64, align);
+    ph.p_type = typ.to_integer();
+    return ph;
+}
+
+pub fn section(name: u32, sh_type: SectionType, offset: usize, size: usize) -> SectionHeader {
+    SectionHeader {
+        sh_name: name,
+        sh_type: sh_type as u32,
+        sh_flags: 0,
+        sh_addr: 0,
+        sh_offset: offset as u64,
+        sh_size: size as u64,
+        sh_link: 0,
+        sh_info: 0,
+        sh_addralign: 1,
+        sh_entsize: 0,
+    }
+}
+
+/// Laid out as the header, program headers, section headers, then `data`.
+///
+/// Entries are `e_phentsize` and `e_shentsize` apart, padded with 0xAA.
+/// Add every table entry before pushing data, the offsets `push` hands
+/// out move otherwise.
+pub struct File {
+    pub header: Header,
+    pub pheaders: Vec<ProgramHeader>,
+    pub sections: Vec<SectionHeader>,
+    pub data: Vec<u8>,
+}
+
+impl File {
+    pub fn new() -> Self {
+        Self {
+            header: header(0),
+            pheaders: Vec::new(),
+            sections: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    pub fn with_segments(pheaders: &[ProgramHeader]) -> Self {
+        let mut file = Self::new();
+        file.pheaders.extend_from_slice(pheaders);
+        return file;
+    }
+
+    pub fn with_sections(sections: &[SectionHeader]) -> Self {
+        let mut file = Self::new();
+        file.sections.extend_from_slice(sections);
+        return file;
+    }
+
+    pub fn section_table_offset(&self) -> usize {
+        EHSIZE_X64 + self.pheaders.len() * self.header.e_phentsize as usize
+    }
+
+    pub fn data_offset(&self) -> usize {
+        self.section_table_offset() + self.sections.len() * self.header.e_shentsize as usize
+    }
+
+    /// Appends `bytes` to the data, returns where they are in the file
+    pub fn push(&mut self, bytes: &[u8]) -> usize {
+        let offset = self.data_offset() + self.data.len();
+        self.data.extend_from_slice(bytes);
+        return offset;
+    }
+
+    /// Adds the section name table as the last section
+    pub fn shstrtab(&mut self, name: u32, names: &[u8]) {
+        self.header.e_shstrndx = self.sections.len() as u16;
+        self.sections
+            .push(section(name, SectionType::Strtab, 0, names.len()));
+        let offset = self.push(names);
+        self.sections.last_mut().unwrap().sh_offset = offset as u64;
+    }
+
+    pub fn build(&self) -> (Header, Vec<u8>) {
+        let mut header = self.header;
+        header.e_phnum = self.pheaders.len() as u16;
+        header.e_shnum = self.sections.len() as u16;
+        if !self.sections.is_empty() {
+            header.e_shoff = NonZeroU64::new(self.section_table_offset() as u64);
+        }
+
+        let mut bytes = bytes_of(&header).to_vec();
+        for ph in self.pheaders.iter() {
+            let start = bytes.len();
+            bytes.extend_from_slice(bytes_of(ph));
+            bytes.resize(start + header.e_phentsize as usize, 0xAA);
+        }
+        for sh in self.sections.iter() {
+            let start = bytes.len();
+            bytes.extend_from_slice(bytes_of(sh));
+            bytes.resize(start + header.e_shentsize as usize, 0xAA);
+        }
+        bytes.extend_from_slice(&self.data);
+        return (header, bytes);
+    }
+}
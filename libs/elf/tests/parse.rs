@@ -0,0 +1,806 @@
+mod common;
+
+use common::*;
+use core::num::NonZeroU64;
+use elf::*;
+
+mod parse {
+    use super::*;
+
+    /* `header` followed by its program headers */
+    fn image(header: Header) -> Vec<u8> {
+        let load = ProgramHeader::new_load(PF_R | PF_X, 0, 0x1000, 0x100, 0x100, 0x1000);
+        let mut bytes = bytes_of(&header).to_vec();
+        for _ in 0..header.e_phnum {
+            bytes.extend_from_slice(bytes_of(&load));
+        }
+        return bytes;
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Elf<'_, Amd64>, ParseError> {
+        Elf::parse(bytes)
+    }
+
+    #[test]
+    fn valid() {
+        let bytes = image(header(2));
+        let elf = parse(&bytes).unwrap();
+        assert_eq!(elf.header().e_phnum, 2);
+        let pheaders = elf.program_headers().unwrap();
+        assert_eq!(pheaders.len(), 2);
+        assert!(pheaders.iter().all(|ph| ph.p_type == PT_LOAD));
+
+        /* Any alignment will do */
+        let mut shifted = vec![0u8];
+        shifted.extend_from_slice(&bytes);
+        assert!(parse(&shifted[1..]).is_ok());
+
+        /* No program headers, nothing to check */
+        let mut empty = header(0);
+        empty.e_phoff = None;
+        empty.e_phentsize = 0;
+        let bytes = image(empty);
+        let elf = parse(&bytes).unwrap();
+        assert!(elf.program_headers().unwrap().is_empty());
+        assert_eq!(elf.header().iter_program_headers(&bytes).count(), 0);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = image(header(2));
+        let too_short = |need, have| Some(ParseError::TooShort { need, have });
+        assert_eq!(parse(&[]).err(), too_short(16, 0));
+        assert_eq!(parse(&bytes[..10]).err(), too_short(16, 10));
+        assert_eq!(
+            parse(&bytes[..EHSIZE_X64 - 1]).err(),
+            too_short(EHSIZE_X64, EHSIZE_X64 - 1)
+        );
+
+        let end = bytes.len() as u64;
+        assert_eq!(
+            parse(&bytes[..bytes.len() - 1]).err(),
+            Some(ParseError::ProgramHeadersTruncated {
+                end,
+                len: bytes.len() - 1
+            })
+        );
+        assert_eq!(
+            parse(&bytes[..EHSIZE_X64]).err(),
+            Some(ParseError::ProgramHeadersTruncated {
+                end,
+                len: EHSIZE_X64
+            })
+        );
+    }
+
+    #[test]
+    fn identification() {
+        type Corrupt = fn(&mut Header);
+        let cases: [(Corrupt, ParseError, Error); 9] = [
+            (
+                |h| h.e_ident.ei_magic[1] = b'F',
+                ParseError::BadMagic,
+                Error::NotElf,
+            ),
+            (
+                |h| h.e_ident.ei_class = Class::Bits32 as u8,
+                ParseError::UnsupportedClass(Class::Bits32 as u8),
+                Error::WrongClass,
+            ),
+            (
+                |h| h.e_ident.ei_data = Data::Msb as u8,
+                ParseError::UnsupportedData(Data::Msb as u8),
+                Error::WrongEndianess,
+            ),
+            (
+                |h| h.e_ident.ei_version = 0,
+                ParseError::UnsupportedVersion(0),
+                Error::UnsupportedVersion,
+            ),
+            (
+                |h| h.e_version = 2,
+                ParseError::UnsupportedVersion(2),
+                Error::UnsupportedVersion,
+            ),
+            (
+                |h| h.e_ident.ei_osabi = 3,
+                ParseError::WrongOsAbi(3),
+                Error::WrongOsAbi,
+            ),
+            (
+                |h| h.e_ident.ei_abiversion = 1,
+                ParseError::WrongAbiVersion(1),
+                Error::WrongOsAbi,
+            ),
+            (
+                |h| h.e_type = Type::Relocatable as u16,
+                ParseError::NotExec(1),
+                Error::NotExec,
+            ),
+            (
+                |h| h.e_machine = 0x28,
+                ParseError::WrongMachine(0x28),
+                Error::WrongMachine,
+            ),
+        ];
+        for (corrupt, expected, lenient) in cases.iter() {
+            let mut header = header(1);
+            corrupt(&mut header);
+            let bytes = image(header);
+            assert_eq!(parse(&bytes).err(), Some(*expected));
+            /* The lenient entry point still agrees */
+            assert_eq!(Elf::<Amd64>::from_bytes(&bytes).err(), Some(*lenient));
+        }
+    }
+
+    #[test]
+    fn accessors() {
+        let mut h = header(1);
+        assert!(matches!(h.try_machine(), Ok(Machine::X64)));
+        h.e_machine = 0x1234;
+        assert!(h.machine().is_none());
+        assert_eq!(
+            h.try_machine().err(),
+            Some(ParseError::UnknownMachine(0x1234))
+        );
+        h.e_ident.ei_osabi = 200;
+        assert_eq!(
+            h.e_ident.try_os_abi().err(),
+            Some(ParseError::UnknownOsAbi(200))
+        );
+
+        assert_eq!(
+            Class::try_from_integer(3).err(),
+            Some(ParseError::UnsupportedClass(3))
+        );
+        assert_eq!(
+            Data::try_from_integer(0).err(),
+            Some(ParseError::UnsupportedData(0))
+        );
+        assert_eq!(SegmentType::try_from_integer(1), Ok(SegmentType::Load));
+        assert_eq!(
+            SegmentType::try_from_integer(0x8000_0000),
+            Err(ParseError::UnknownSegmentType(0x8000_0000))
+        );
+    }
+
+    #[test]
+    fn display() {
+        let cases = [
+            (ParseError::BadMagic, "not an ELF file, bad magic"),
+            (
+                ParseError::TooShort { need: 64, have: 10 },
+                "only 10 of 64 header bytes",
+            ),
+            (ParseError::WrongMachine(0x28), "wrong machine 0x28"),
+            (ParseError::NotExec(3), "not an executable, e_type 0x3"),
+            (
+                ParseError::UnknownSegmentType(0x8000_0000),
+                "unknown segment type 0x80000000",
+            ),
+        ];
+        for (error, text) in cases.iter() {
+            assert_eq!(error.to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn header_sizes() {
+        let mut bad = header(1);
+        bad.e_ehsize = 52;
+        assert_eq!(parse(&image(bad)).err(), Some(ParseError::HeaderSize(52)));
+
+        let mut small = header(1);
+        small.e_phentsize = 32;
+        assert_eq!(
+            parse(&image(small)).err(),
+            Some(ParseError::ProgramHeaderSize(32))
+        );
+        /* `from_bytes` lets it through, `program_headers` catches it late */
+        let bytes = image(small);
+        let elf = Elf::<Amd64>::from_bytes(&bytes).unwrap();
+        assert!(elf.program_headers().is_err());
+
+        /* Padded entries are fine, the padding must be in the file too */
+        let mut padded = header(2);
+        padded.e_phentsize = 64;
+        let mut bytes = bytes_of(&padded).to_vec();
+        let load = ProgramHeader::new_load(PF_R, 0, 0x1000, 0x100, 0x100, 0x1000);
+        for _ in 0..2 {
+            bytes.extend_from_slice(bytes_of(&load));
+            bytes.extend_from_slice(&[0xAA; 8]);
+        }
+        let elf = parse(&bytes).unwrap();
+        let pheaders = elf.program_headers().unwrap();
+        assert_eq!(pheaders.len(), 2);
+        assert!(pheaders.iter().all(|ph| ph.p_vaddr == 0x1000));
+        assert_eq!(pheaders.get(1).unwrap().p_memsz, 0x100);
+        assert_eq!(
+            parse(&bytes[..bytes.len() - 1]).err(),
+            Some(ParseError::ProgramHeadersTruncated {
+                end: bytes.len() as u64,
+                len: bytes.len() - 1
+            })
+        );
+    }
+
+    #[test]
+    fn program_header_offset() {
+        let mut overflow = header(1);
+        overflow.e_phoff = NonZeroU64::new(u64::MAX - 8);
+        assert_eq!(
+            parse(&image(overflow)).err(),
+            Some(ParseError::ProgramHeaderOffset(u64::MAX - 8))
+        );
+
+        let mut missing = header(1);
+        missing.e_phoff = None;
+        assert_eq!(
+            parse(&image(missing)).err(),
+            Some(ParseError::ProgramHeaderOffset(0))
+        );
+
+        let mut far = header(1);
+        far.e_phoff = NonZeroU64::new(0x1_0000);
+        let bytes = image(far);
+        assert_eq!(
+            parse(&bytes).err(),
+            Some(ParseError::ProgramHeadersTruncated {
+                end: 0x1_0000 + core::mem::size_of::<ProgramHeader>() as u64,
+                len: bytes.len()
+            })
+        );
+    }
+
+    #[test]
+    fn header_only() {
+        let bytes = image(header(1));
+        assert_eq!(
+            Header::parse(&bytes[..10]).err(),
+            Some(ParseError::TooShort { need: 16, have: 10 })
+        );
+        assert_eq!(
+            Header::parse(&bytes[..EHSIZE_X64 - 1]).err(),
+            Some(ParseError::TooShort {
+                need: EHSIZE_X64,
+                have: EHSIZE_X64 - 1
+            })
+        );
+
+        /* Just the header, at an odd address */
+        let mut shifted = vec![0u8];
+        shifted.extend_from_slice(&bytes[..EHSIZE_X64]);
+        let parsed = Header::parse(&shifted[1..]).unwrap();
+        assert_eq!(parsed.e_entry, NonZeroU64::new(0x20_0000));
+        assert_eq!(parsed.e_phnum, 1);
+
+        /* Identification only, not what the file is for */
+        let mut other = header(1);
+        other.e_type = Type::Relocatable as u16;
+        other.e_machine = 0x28;
+        assert!(Header::parse(&image(other)).is_ok());
+
+        let mut garbage = bytes.clone();
+        garbage[..4].copy_from_slice(b"MZ\x90\0");
+        assert_eq!(Header::parse(&garbage).err(), Some(ParseError::BadMagic));
+        let mut narrow = header(1);
+        narrow.e_ident.ei_class = Class::Bits32 as u8;
+        assert_eq!(
+            Header::parse(&image(narrow)).err(),
+            Some(ParseError::UnsupportedClass(Class::Bits32 as u8))
+        );
+    }
+}
+
+mod validate {
+    use super::*;
+
+    fn validate(corrupt: fn(&mut Header)) -> Result<(), ValidateError> {
+        let mut header = header(1);
+        corrupt(&mut header);
+        header.validate_for_target(Machine::X64, Class::Bits64)
+    }
+
+    #[test]
+    fn runnable() {
+        assert_eq!(validate(|_| {}), Ok(()));
+        assert_eq!(validate(|h| h.e_type = Type::SharedObject as u16), Ok(()));
+    }
+
+    #[test]
+    fn mismatches() {
+        type Corrupt = fn(&mut Header);
+        let cases: [(Corrupt, ValidateError); 5] = [
+            (|h| h.e_machine = 0xb7, ValidateError::WrongMachine(0xb7)),
+            (|h| h.e_ident.ei_class = 1, ValidateError::WrongClass(1)),
+            (|h| h.e_type = 1, ValidateError::NotRunnable(1)),
+            (|h| h.e_type = 4, ValidateError::NotRunnable(4)),
+            (|h| h.e_entry = None, ValidateError::NoEntry),
+        ];
+        for (corrupt, expected) in cases.iter() {
+            assert_eq!(validate(*corrupt), Err(*expected));
+        }
+
+        /* Machine first, whatever else is wrong */
+        let mut other = header(1);
+        other.e_ident.ei_class = 1;
+        other.e_entry = None;
+        assert_eq!(
+            other.validate_for_target(Machine::AArch64, Class::Bits64),
+            Err(ValidateError::WrongMachine(0x3e))
+        );
+        assert_eq!(
+            ValidateError::WrongMachine(0x3e).to_string(),
+            "built for machine 0x3e"
+        );
+    }
+}
+
+mod identity {
+    use super::*;
+
+    fn make_elf(pheaders: &[ProgramHeader]) -> Vec<u8> {
+        File::with_segments(pheaders).build().1
+    }
+
+    #[test]
+    fn higher_half() {
+        let buf = make_elf(&[
+            ProgramHeader::new_load(
+                PF_R | PF_X,
+                0,
+                0xffff_ffff_c000_0000,
+                0x1000,
+                0x1000,
+                0x20_0000,
+            ),
+            ProgramHeader::new_load(
+                PF_R | PF_W,
+                0,
+                0xffff_ffff_c020_0000,
+                0x1000,
+                0x1000,
+                0x20_0000,
+            ),
+        ]);
+        let elf: Elf<Amd64> = Elf::from_bytes(&buf).unwrap();
+
+        assert!(!elf.is_identity_linkable(0x20_0000));
+        assert!(elf.is_identity_linkable(0xffff_ffff_c000_0000));
+    }
+
+    #[test]
+    fn identity() {
+        let mut note = ProgramHeader::new_load(PF_R, 0, 0x1000, 0x100, 0x100, 8);
+        note.p_type = SegmentType::Note.to_integer();
+
+        let buf = make_elf(&[
+            note,
+            ProgramHeader::new_load(PF_R | PF_W, 0, 0x40_0000, 0x1000, 0x1000, 0x20_0000),
+            ProgramHeader::new_load(PF_R | PF_X, 0, 0x20_0000, 0x1000, 0x1000, 0x20_0000),
+        ]);
+        let elf: Elf<Amd64> = Elf::from_bytes(&buf).unwrap();
+
+        assert!(elf.is_identity_linkable(0x20_0000));
+        assert!(!elf.is_identity_linkable(0x1000));
+        assert!(!elf.is_identity_linkable(0x40_0000));
+    }
+
+    #[test]
+    fn no_load_segments() {
+        let buf = make_elf(&[]);
+        let elf: Elf<Amd64> = Elf::from_bytes(&buf).unwrap();
+
+        assert!(!elf.is_identity_linkable(0));
+    }
+}
+
+mod unaligned {
+    use super::*;
+
+    const STRTAB: &[u8] = b"\0.text\0.shstrtab\0";
+    const TEXT: &[u8] = b"\x0f\x0b\xf4\xeb\xfd";
+    const DATA: &[u8] = b"data";
+
+    /* Headers, string table, text, data */
+    fn make_elf() -> Vec<u8> {
+        let mut file = File::with_segments(&[
+            ProgramHeader::new_load(PF_R | PF_X, 0, 0x20_0000, 5, 5, 0x1000),
+            ProgramHeader::new_load(PF_R | PF_W, 0, 0x20_1000, 4, 0x20, 0x1000),
+        ]);
+        file.sections.push(section(0, SectionType::Null, 0, 0));
+        file.sections
+            .push(section(1, SectionType::Progbits, 0, TEXT.len()));
+        file.shstrtab(7, STRTAB);
+        let text = file.push(TEXT) as u64;
+        file.sections[1].sh_offset = text;
+        file.pheaders[0].p_offset = text;
+        file.pheaders[1].p_offset = file.push(DATA) as u64;
+        return file.build().1;
+    }
+
+    /* Copies `file` to `offset` bytes past an 8 byte boundary */
+    fn misaligned<'a>(storage: &'a mut Vec<u64>, file: &[u8], offset: usize) -> &'a [u8] {
+        storage.clear();
+        storage.resize((file.len() + offset + 7) / 8, 0);
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut u8, storage.len() * 8)
+        };
+        bytes[offset..offset + file.len()].copy_from_slice(file);
+        return &bytes[offset..offset + file.len()];
+    }
+
+    #[test]
+    fn every_offset() {
+        let file = make_elf();
+        let mut storage = Vec::new();
+
+        for offset in 0..8 {
+            let file = misaligned(&mut storage, &file, offset);
+            assert_eq!(file.as_ptr() as usize % 8, offset);
+
+            let elf: Elf<Amd64> = Elf::from_bytes(file).unwrap();
+            assert_eq!(elf.header().e_entry, NonZeroU64::new(0x20_0000));
+            assert!(elf.is_identity_linkable(0x20_0000));
+
+            /* What a loader does: copy file data, zero the rest */
+            let pheaders = elf.program_headers().unwrap();
+            assert_eq!(pheaders.len(), 2);
+            let mut loaded = Vec::new();
+            for ph in pheaders.iter().filter(|ph| ph.p_type == PT_LOAD) {
+                let mut segment = vec![0u8; ph.p_memsz as usize];
+                let data = elf.segment_data(&ph).unwrap();
+                segment[..data.len()].copy_from_slice(data);
+                loaded.push(segment);
+            }
+            assert_eq!(loaded[0], TEXT);
+            assert_eq!(&loaded[1][..4], DATA);
+            assert!(loaded[1][4..].iter().all(|&b| b == 0));
+
+            let header = elf.header();
+            let counts = header.segment_counts(file).unwrap();
+            assert_eq!(counts.load, 2);
+
+            let text = header.section_by_name(file, ".text").unwrap().unwrap();
+            assert_eq!(text.sh_size, TEXT.len() as u64);
+            assert_eq!(header.section_headers(file).unwrap().len(), 3);
+        }
+    }
+
+    #[test]
+    fn header_table() {
+        let file = make_elf();
+        let mut storage = Vec::new();
+        let file = misaligned(&mut storage, &file, 3);
+        let elf: Elf<Amd64> = Elf::from_bytes(file).unwrap();
+
+        let pheaders = elf.program_headers().unwrap();
+        let (text, rest) = pheaders.split_first().unwrap();
+        assert!(text.is_executable());
+        assert_eq!(rest.len(), 1);
+        assert!(rest.get(0).unwrap().is_writable());
+        assert!(rest.get(1).is_none());
+        assert_eq!(pheaders.iter().rev().next().unwrap().p_vaddr, 0x20_1000);
+
+        /* Half an entry is not a table */
+        assert!(HeaderTable::<ProgramHeader>::new(&file[..PH_SIZE + 1]).is_none());
+        assert!(HeaderTable::<ProgramHeader>::empty().is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let file = make_elf();
+        let mut storage = Vec::new();
+        let file = misaligned(&mut storage, &file, 5);
+
+        assert!(matches!(
+            Elf::<Amd64>::from_bytes(&file[..EHSIZE_X64 - 1]),
+            Err(Error::UnexpectedEnd)
+        ));
+
+        let header = Elf::<Amd64>::from_bytes(file).unwrap().header();
+        assert!(matches!(
+            header.program_headers(&file[..EHSIZE_X64 + PH_SIZE]),
+            Err(MemoryError::UnexpectedEnd)
+        ));
+    }
+}
+
+mod big_endian {
+    use super::*;
+
+    /// `file` with every header field in the other byte order
+    fn swapped(file: &File, data: Data) -> Vec<u8> {
+        let (mut header, _) = file.build();
+        header.e_ident.ei_data = data as u8;
+
+        let mut bytes = bytes_of(&header.swap_bytes()).to_vec();
+        for ph in file.pheaders.iter() {
+            bytes.extend_from_slice(bytes_of(&ph.swap_bytes()));
+        }
+        for sh in file.sections.iter() {
+            bytes.extend_from_slice(bytes_of(&sh.swap_bytes()));
+        }
+        bytes.extend_from_slice(&file.data);
+        return bytes;
+    }
+
+    fn other() -> Data {
+        match Data::NATIVE {
+            Data::Lsb => Data::Msb,
+            Data::Msb => Data::Lsb,
+        }
+    }
+
+    #[test]
+    fn power64() {
+        let mut file = File::with_segments(&[segment(SegmentType::Load, 0x1000, 0x200, 0x1000)]);
+        file.header.e_machine = Machine::Power64 as u16;
+        file.sections.push(section(0, SectionType::Null, 0, 0));
+        file.shstrtab(1, b"\0.shstrtab\0");
+        let bytes = swapped(&file, other());
+
+        let header = Header::parse(&bytes).unwrap();
+        assert_eq!(header.data(), other());
+        assert!(matches!(header.machine(), Some(Machine::Power64)));
+        assert_eq!(header.e_entry, NonZeroU64::new(0x20_0000));
+        assert_eq!((header.e_phnum, header.e_shnum), (1, 2));
+
+        let pheaders = header.program_headers(&bytes).unwrap();
+        assert_eq!(pheaders.get(0).unwrap().p_offset, 0x1000);
+        assert_eq!(pheaders.get(0).unwrap().p_filesz, 0x200);
+        let iterated: Vec<u64> = header.iter_program_headers(&bytes).map(|ph| ph.p_align).collect();
+        assert_eq!(iterated, [0x1000]);
+
+        let shstrtab = header.shstrtab(&bytes).unwrap();
+        assert_eq!(shstrtab.name(&bytes, &shstrtab), Some(".shstrtab"));
+        assert_eq!(header.section_by_name(&bytes, ".shstrtab").unwrap().unwrap().sh_size, 11);
+    }
+
+    /* 32-bit PowerPC executable, one segment covering the whole file */
+    #[rustfmt::skip]
+    const PPC32: [u8; 84] = [
+        0x7f, b'E', b'L', b'F', 1, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0x00, 0x02,             /* e_type: EXEC */
+        0x00, 0x14,             /* e_machine: PowerPC */
+        0x00, 0x00, 0x00, 0x01, /* e_version */
+        0x10, 0x00, 0x01, 0x00, /* e_entry */
+        0x00, 0x00, 0x00, 0x34, /* e_phoff */
+        0x00, 0x00, 0x00, 0x00, /* e_shoff */
+        0x00, 0x00, 0x00, 0x00, /* e_flags */
+        0x00, 0x34,             /* e_ehsize */
+        0x00, 0x20,             /* e_phentsize */
+        0x00, 0x01,             /* e_phnum */
+        0x00, 0x28,             /* e_shentsize */
+        0x00, 0x00,             /* e_shnum */
+        0x00, 0x00,             /* e_shstrndx */
+
+        0x00, 0x00, 0x00, 0x01, /* p_type: LOAD */
+        0x00, 0x00, 0x00, 0x00, /* p_offset */
+        0x10, 0x00, 0x00, 0x00, /* p_vaddr */
+        0x10, 0x00, 0x00, 0x00, /* p_paddr */
+        0x00, 0x00, 0x00, 0x54, /* p_filesz */
+        0x00, 0x00, 0x10, 0x00, /* p_memsz */
+        0x00, 0x00, 0x00, 0x05, /* p_flags: R X */
+        0x00, 0x01, 0x00, 0x00, /* p_align */
+    ];
+
+    /* 64-bit PowerPC executable with a GNU note */
+    #[rustfmt::skip]
+    const PPC64: [u8; 140] = [
+        0x7f, b'E', b'L', b'F', 2, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0x00, 0x02,             /* e_type: EXEC */
+        0x00, 0x15,             /* e_machine: Power64 */
+        0x00, 0x00, 0x00, 0x01, /* e_version */
+        0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, /* e_entry */
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, /* e_phoff */
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, /* e_shoff */
+        0x00, 0x00, 0x00, 0x02, /* e_flags: ELFv2 */
+        0x00, 0x40,             /* e_ehsize */
+        0x00, 0x38,             /* e_phentsize */
+        0x00, 0x01,             /* e_phnum */
+        0x00, 0x40,             /* e_shentsize */
+        0x00, 0x00,             /* e_shnum */
+        0x00, 0x00,             /* e_shstrndx */
+
+        0x00, 0x00, 0x00, 0x04, /* p_type: NOTE */
+        0x00, 0x00, 0x00, 0x04, /* p_flags: R */
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x78, /* p_offset */
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, /* p_vaddr */
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, /* p_paddr */
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14, /* p_filesz */
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14, /* p_memsz */
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, /* p_align */
+
+        0x00, 0x00, 0x00, 0x04, /* n_namesz */
+        0x00, 0x00, 0x00, 0x04, /* n_descsz */
+        0x00, 0x00, 0x00, 0x01, /* n_type */
+        b'G', b'N', b'U', 0,
+        0x00, 0x00, 0x00, 0x2a,
+    ];
+
+    #[test]
+    fn hand_built_ppc32() {
+        let file = ElfFile::parse(&PPC32).unwrap();
+        assert!(matches!(file.header.class(), Class::Bits32));
+        assert_eq!(file.header.data(), Data::Msb);
+        assert!(matches!(file.header.machine(), Some(Machine::PowerPC)));
+        assert_eq!(file.header.e_type(), 2);
+        assert_eq!(file.header.entry(), 0x1000_0100);
+        assert_eq!(file.header.phoff(), 0x34);
+
+        let pheaders: Vec<ProgramHeader> = file.program_headers().collect();
+        assert_eq!(pheaders.len(), 1);
+        let ph = pheaders[0];
+        assert_eq!(ph.p_type, PT_LOAD);
+        assert_eq!(ph.p_flags, PF_R | PF_X);
+        assert_eq!((ph.p_offset, ph.p_vaddr, ph.p_paddr), (0, 0x1000_0000, 0x1000_0000));
+        assert_eq!((ph.p_filesz, ph.p_memsz, ph.p_align), (0x54, 0x1000, 0x1_0000));
+    }
+
+    #[test]
+    fn hand_built_ppc64() {
+        let header = Header::parse(&PPC64).unwrap();
+        assert_eq!(header.data(), Data::Msb);
+        assert!(matches!(header.machine(), Some(Machine::Power64)));
+        assert_eq!(header.e_entry, NonZeroU64::new(0x1000_0000));
+        assert_eq!(header.e_phoff, NonZeroU64::new(0x40));
+        assert_eq!((header.e_flags, header.e_ehsize, header.e_phentsize), (2, 64, 56));
+
+        let ph = header.program_headers(&PPC64).unwrap().get(0).unwrap();
+        assert!(matches!(ph.segment_type(), Some(SegmentType::Note)));
+        assert_eq!((ph.p_flags, ph.p_offset, ph.p_filesz, ph.p_align), (PF_R, 0x78, 0x14, 4));
+
+        let notes: Vec<Note> = header.notes(&PPC64).unwrap().collect();
+        assert_eq!(
+            notes,
+            [Note {
+                name: "GNU",
+                note_type: 1,
+                desc: &[0, 0, 0, 0x2a]
+            }]
+        );
+        assert_eq!(ElfFile::parse(&PPC64).unwrap().header.entry(), 0x1000_0000);
+    }
+
+    #[test]
+    fn native_only_for_machines() {
+        let mut file = File::new();
+        file.header.e_ident.ei_data = other() as u8;
+        let (_, bytes) = file.build();
+        /* Fields in the wrong order are garbage, the byte order is the error */
+        assert_eq!(
+            Elf::<Amd64>::parse(&swapped(&file, other())).err(),
+            Some(ParseError::UnsupportedData(other() as u8))
+        );
+        assert_eq!(
+            Elf::<Amd64>::parse(&bytes).err(),
+            Some(ParseError::UnsupportedData(other() as u8))
+        );
+
+        let mut unknown = file.header;
+        unknown.e_ident.ei_data = 3;
+        let unknown = File { header: unknown, ..File::new() };
+        assert_eq!(
+            Header::parse(&unknown.build().1).err(),
+            Some(ParseError::UnsupportedData(3))
+        );
+    }
+}
+
+mod elf_file {
+    use super::*;
+    use core::mem::size_of;
+
+    /* i686 executable with two segments right after the header */
+    fn i686() -> Vec<u8> {
+        let header = Header32 {
+            e_ident: HeaderIdent {
+                ei_class: Class::Bits32 as u8,
+                ..header(0).e_ident
+            },
+            e_type: Type::Executable as u16,
+            e_machine: Machine::X86 as u16,
+            e_version: EV_CURRENT as u32,
+            e_entry: 0x0804_9000,
+            e_phoff: EHSIZE_X86 as u32,
+            e_shoff: 0,
+            e_flags: 0,
+            e_ehsize: EHSIZE_X86 as u16,
+            e_phentsize: size_of::<ProgramHeader32>() as u16,
+            e_phnum: 2,
+            e_shentsize: 40,
+            e_shnum: 0,
+            e_shstrndx: 0,
+        };
+        let segment = |flags, vaddr: u32, size| ProgramHeader32 {
+            p_type: PT_LOAD,
+            p_offset: vaddr & 0xfff,
+            p_vaddr: vaddr,
+            p_paddr: vaddr,
+            p_filesz: size,
+            p_memsz: size,
+            p_flags: flags,
+            p_align: 0x1000,
+        };
+
+        let mut bytes = bytes_of(&header).to_vec();
+        bytes.extend_from_slice(bytes_of(&segment(PF_R | PF_X, 0x0804_9000, 0x200)));
+        bytes.extend_from_slice(bytes_of(&segment(PF_R | PF_W, 0x0804_b000, 0xffff_0000)));
+        return bytes;
+    }
+
+    #[test]
+    fn bits32() {
+        let bytes = i686();
+        let file = ElfFile::parse(&bytes).unwrap();
+        assert!(matches!(file.header.class(), Class::Bits32));
+        assert!(matches!(file.header.machine(), Some(Machine::X86)));
+        assert_eq!(file.header.entry(), 0x0804_9000);
+
+        let segments: Vec<ProgramHeader> = file.program_headers().collect();
+        assert_eq!(segments.len(), 2);
+        assert!(segments[0].is_executable() && !segments[0].is_writable());
+        assert!(segments[1].is_writable());
+        assert_eq!(segments[1].p_vaddr, 0x0804_b000);
+        /* Widened, not sign extended */
+        assert_eq!(segments[1].p_memsz, 0xffff_0000);
+
+        /* Elf and Header stay 64-bit only */
+        let unsupported = Some(ParseError::UnsupportedClass(Class::Bits32 as u8));
+        assert_eq!(Header::parse(&bytes).err(), unsupported);
+        assert_eq!(Elf::<Amd64>::parse(&bytes).err(), unsupported);
+    }
+
+    #[test]
+    fn bits64() {
+        let file = File::with_segments(&[segment(SegmentType::Load, 0, 0x100, 0x1000)]);
+        let (_, bytes) = file.build();
+        let file = ElfFile::parse(&bytes).unwrap();
+        assert!(matches!(file.header.class(), Class::Bits64));
+        assert_eq!(file.header.entry(), 0x20_0000);
+        assert_eq!(file.program_headers().count(), 1);
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = i686();
+        assert_eq!(
+            ElfFile::parse(&bytes[..EHSIZE_X86 - 1]).err(),
+            Some(ParseError::TooShort {
+                need: EHSIZE_X86,
+                have: EHSIZE_X86 - 1
+            })
+        );
+        assert_eq!(
+            ElfFile::parse(&bytes[..bytes.len() - 1]).err(),
+            Some(ParseError::ProgramHeadersTruncated {
+                end: bytes.len() as u64,
+                len: bytes.len() - 1
+            })
+        );
+
+        /* The header alone parses, its table is only checked by ElfFile */
+        let any = AnyHeader::parse(&bytes[..EHSIZE_X86]).unwrap();
+        assert_eq!(any.program_headers(&bytes[..EHSIZE_X86]).count(), 0);
+        assert_eq!(any.program_headers(&bytes).count(), 2);
+
+        /* Only 52 bytes of a 64 byte header */
+        let mut header = header(0);
+        header.e_ehsize = EHSIZE_X86 as u16;
+        assert_eq!(
+            AnyHeader::parse(bytes_of(&header)).err(),
+            Some(ParseError::HeaderSize(EHSIZE_X86 as u16))
+        );
+        let mut bytes = i686();
+        bytes[40..42].copy_from_slice(&(EHSIZE_X64 as u16).to_le_bytes());
+        assert_eq!(
+            ElfFile::parse(&bytes).err(),
+            Some(ParseError::HeaderSize(EHSIZE_X64 as u16))
+        );
+
+        let mut bytes = i686();
+        bytes[4] = 3;
+        assert_eq!(ElfFile::parse(&bytes).err(), Some(ParseError::UnsupportedClass(3)));
+    }
+}
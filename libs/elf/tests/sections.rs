@@ -0,0 +1,677 @@
+mod common;
+
+use common::*;
+use elf::*;
+
+mod alloc_sections {
+    use super::*;
+
+    fn section(sh_type: u32, flags: u64, addr: u64, size: u64) -> SectionHeader {
+        SectionHeader {
+            sh_name: 0,
+            sh_type,
+            sh_flags: flags,
+            sh_addr: addr,
+            sh_offset: 0,
+            sh_size: size,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 8,
+            sh_entsize: 0,
+        }
+    }
+
+    fn make_file(sections: &[SectionHeader]) -> (Header, Vec<u8>) {
+        File::with_sections(sections).build()
+    }
+
+    #[test]
+    fn only_alloc_sorted_by_addr() {
+        let (header, buf) = make_file(&[
+            section(SectionType::Null as u32, 0, 0, 0),
+            section(
+                SectionType::Progbits as u32,
+                SHF_ALLOC | SHF_WRITE,
+                0x3000,
+                0x10,
+            ),
+            section(SectionType::Symtab as u32, 0, 0, 0x100),
+            section(
+                SectionType::Progbits as u32,
+                SHF_ALLOC | SHF_EXECINSTR,
+                0x1000,
+                0x20,
+            ),
+            section(SectionType::Strtab as u32, 0, 0, 0x40),
+            section(
+                SectionType::Nobits as u32,
+                SHF_ALLOC | SHF_WRITE,
+                0x3000,
+                0x80,
+            ),
+            section(SectionType::Progbits as u32, SHF_ALLOC, 0x2000, 0x30),
+        ]);
+
+        let sizes: Vec<u64> = header
+            .alloc_sections(&buf)
+            .unwrap()
+            .map(|sh| sh.sh_size)
+            .collect();
+
+        /* Equal addresses keep the order of the table */
+        assert_eq!(sizes, [0x20, 0x30, 0x10, 0x80]);
+
+        let sections = header.section_headers(&buf).unwrap();
+        assert_eq!(sections.len(), 7);
+        let text = sections.get(3).unwrap();
+        assert!(text.is_executable() && !text.is_writable());
+        assert!(!sections.get(2).unwrap().is_alloc());
+        assert!(sections.get(7).is_none());
+    }
+
+    #[test]
+    fn relocatable_object() {
+        /* In ET_REL files every sh_addr is zero */
+        let (header, buf) = make_file(&[
+            section(SectionType::Null as u32, 0, 0, 0),
+            section(
+                SectionType::Progbits as u32,
+                SHF_ALLOC | SHF_EXECINSTR,
+                0,
+                1,
+            ),
+            section(SectionType::Rela as u32, 0, 0, 2),
+            section(SectionType::Progbits as u32, SHF_ALLOC, 0, 3),
+            section(SectionType::Nobits as u32, SHF_ALLOC | SHF_WRITE, 0, 4),
+        ]);
+
+        let sizes: Vec<u64> = header
+            .alloc_sections(&buf)
+            .unwrap()
+            .map(|sh| sh.sh_size)
+            .collect();
+        assert_eq!(sizes, [1, 3, 4]);
+    }
+
+    #[test]
+    fn malformed_table() {
+        let (mut header, buf) = make_file(&[section(0, SHF_ALLOC, 0, 0)]);
+
+        header.e_shnum = 2;
+        assert!(matches!(
+            header.alloc_sections(&buf),
+            Err(MemoryError::UnexpectedEnd)
+        ));
+
+        header.e_shnum = 1;
+        header.e_shentsize = 40;
+        assert!(matches!(
+            header.section_headers(&buf),
+            Err(MemoryError::SizeMismatch)
+        ));
+
+        header.e_shoff = None;
+        assert_eq!(header.alloc_sections(&buf).unwrap().count(), 0);
+    }
+}
+
+mod section_table {
+    use super::*;
+
+    fn sections() -> [SectionHeader; 3] {
+        [
+            section(0, SectionType::Null, 0, 0),
+            section(1, SectionType::Progbits, 0x1000, 0x10),
+            section(2, SectionType::Symtab, 0x2000, 0x30),
+        ]
+    }
+
+    #[test]
+    fn by_index() {
+        let (header, buf) = File::with_sections(&sections()).build();
+        assert_eq!(header.section_count(&buf).unwrap(), 3);
+        let section = header.section(&buf, 2).unwrap().unwrap();
+        assert_eq!(section.sh_offset, 0x2000);
+        assert!(header.section(&buf, 3).unwrap().is_none());
+        assert!(header.section(&buf, u32::MAX).unwrap().is_none());
+    }
+
+    #[test]
+    fn extended_count() {
+        /* Too many for e_shnum, section 0 has the count */
+        let mut sections = sections();
+        sections[0].sh_size = 3;
+        let (mut header, buf) = File::with_sections(&sections).build();
+        header.e_shnum = 0;
+        assert_eq!(header.section_count(&buf).unwrap(), 3);
+        assert_eq!(header.section_headers(&buf).unwrap().len(), 3);
+        assert_eq!(header.iter_section_headers(&buf).count(), 3);
+        assert_eq!(header.section(&buf, 1).unwrap().unwrap().sh_offset, 0x1000);
+
+        /* Claiming more than the file has */
+        let mut sections = self::sections();
+        sections[0].sh_size = 4;
+        let (mut header, buf) = File::with_sections(&sections).build();
+        header.e_shnum = 0;
+        assert_eq!(header.section_count(&buf).unwrap(), 4);
+        assert!(matches!(
+            header.section(&buf, 1),
+            Err(MemoryError::UnexpectedEnd)
+        ));
+        assert_eq!(header.iter_section_headers(&buf).count(), 3);
+
+        /* Section 0 itself cut off */
+        let end = header.e_shoff.unwrap().get() as usize + 8;
+        assert!(matches!(
+            header.section_count(&buf[..end]),
+            Err(MemoryError::UnexpectedEnd)
+        ));
+        assert_eq!(header.iter_section_headers(&buf[..end]).count(), 0);
+
+        /* No table at all */
+        header.e_shoff = None;
+        assert_eq!(header.section_count(&buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn past_the_file() {
+        let (header, buf) = File::with_sections(&sections()).build();
+        let end = buf.len() - 1;
+        assert!(matches!(
+            header.section_headers(&buf[..end]),
+            Err(MemoryError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            header.section(&buf[..end], 0),
+            Err(MemoryError::UnexpectedEnd)
+        ));
+
+        let mut huge = header;
+        huge.e_shoff = core::num::NonZeroU64::new(u64::MAX - 8);
+        assert!(huge.section_headers(&buf).is_err());
+        assert!(huge.section_count(&buf).is_ok());
+    }
+}
+
+mod section_by_name {
+    use super::*;
+
+    const STRTAB: &[u8] = b"\0.text\0.handoff\0.shstrtab\0";
+
+    fn named(name: u32, sh_type: SectionType) -> SectionHeader {
+        SectionHeader {
+            sh_addr: 0x1000 * name as u64,
+            ..section(name, sh_type, 0, 0)
+        }
+    }
+
+    /* Section header table, then the name string table */
+    fn make_file(shstrndx: u16) -> (Header, Vec<u8>) {
+        let mut file = File::with_sections(&[
+            named(0, SectionType::Null),
+            named(1, SectionType::Progbits),
+            named(7, SectionType::Progbits),
+        ]);
+        file.shstrtab(16, STRTAB);
+        file.header.e_shstrndx = shstrndx;
+        return file.build();
+    }
+
+    #[test]
+    fn finds_section() {
+        let (header, buf) = make_file(3);
+        let file = &buf[..];
+
+        let handoff = header.section_by_name(file, ".handoff").unwrap().unwrap();
+        assert_eq!(handoff.sh_addr, 0x7000);
+        let text = header.section_by_name(file, ".text").unwrap().unwrap();
+        assert_eq!(text.sh_addr, 0x1000);
+
+        /* Prefixes and suffixes of names don't count */
+        assert!(header.section_by_name(file, ".hand").unwrap().is_none());
+        assert!(header.section_by_name(file, "text").unwrap().is_none());
+    }
+
+    #[test]
+    fn no_string_table() {
+        let (header, buf) = make_file(0);
+        let file = &buf[..];
+        assert!(header.section_by_name(file, ".text").unwrap().is_none());
+
+        let (header, buf) = make_file(9);
+        let file = &buf[..];
+        assert!(header.section_by_name(file, ".text").is_err());
+    }
+}
+
+mod section_names {
+    use super::*;
+
+    /* The last name is missing its NUL */
+    const STRTAB: &[u8] = b"\0.symtab\0.dynamic\0.shstrtab\0\xff\xfe\0.cut";
+
+    /* Section headers `shentsize` apart, then the name table */
+    fn make_file(shentsize: usize) -> (Header, Vec<u8>) {
+        let mut file = File::with_sections(&[
+            section(0, SectionType::Null, 0, 0),
+            section(1, SectionType::Symtab, 0, 0),
+            section(9, SectionType::Dynamic, 0, 0),
+            section(18, SectionType::Strtab, 0, STRTAB.len()),
+            section(28, SectionType::Progbits, 0, 0),
+            section(31, SectionType::Progbits, 0, 0),
+        ]);
+        file.header.e_shentsize = shentsize as u16;
+        file.header.e_shstrndx = 3;
+        file.sections[3].sh_offset = file.push(STRTAB) as u64;
+        return file.build();
+    }
+
+    fn names(header: &Header, file: &[u8]) -> Vec<Option<String>> {
+        let shstrtab = header.shstrtab(file).unwrap();
+        header
+            .iter_section_headers(file)
+            .map(|section| section.name(file, &shstrtab).map(String::from))
+            .collect()
+    }
+    #[test]
+    fn by_name() {
+        let (header, file) = make_file(SH_SIZE);
+        let shstrtab = header.shstrtab(&file).unwrap();
+        let find = |name| {
+            header
+                .iter_section_headers(&file)
+                .find(|section| section.name(&file, &shstrtab) == Some(name))
+        };
+        assert_eq!(find(".symtab").unwrap().sh_type, SectionType::Symtab as u32);
+        assert_eq!(
+            find(".dynamic").unwrap().sh_type,
+            SectionType::Dynamic as u32
+        );
+        assert!(find(".dyn").is_none());
+
+        /* Agrees with the strict lookup */
+        let strict = header.section_by_name(&file, ".dynamic").unwrap().unwrap();
+        assert_eq!(strict.sh_name, find(".dynamic").unwrap().sh_name);
+    }
+
+    #[test]
+    fn malformed_names() {
+        let (header, file) = make_file(SH_SIZE);
+        let expected = [
+            Some(""),
+            Some(".symtab"),
+            Some(".dynamic"),
+            Some(".shstrtab"),
+            /* Not UTF-8, then unterminated */
+            None,
+            None,
+        ];
+        let expected: Vec<_> = expected.iter().map(|x| x.map(String::from)).collect();
+        assert_eq!(names(&header, &file), expected);
+
+        /* Past the end of the table */
+        let shstrtab = header.shstrtab(&file).unwrap();
+        let mut far = section(STRTAB.len() as u32 + 1, SectionType::Progbits, 0, 0);
+        assert_eq!(far.name(&file, &shstrtab), None);
+        far.sh_name = u32::MAX;
+        assert_eq!(far.name(&file, &shstrtab), None);
+
+        /* Table past the end of the file */
+        let mut cut = shstrtab;
+        cut.sh_size += 1;
+        assert_eq!(
+            section(1, SectionType::Symtab, 0, 0).name(&file, &cut),
+            None
+        );
+        cut.sh_offset = u64::MAX;
+        assert_eq!(
+            section(1, SectionType::Symtab, 0, 0).name(&file, &cut),
+            None
+        );
+    }
+
+    #[test]
+    fn larger_entries() {
+        let (header, file) = make_file(SH_SIZE + 16);
+        assert_eq!(names(&header, &file)[2].as_deref(), Some(".dynamic"));
+        assert!(header.section_headers(&file).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let (header, file) = make_file(SH_SIZE);
+        let table_end = EHSIZE_X64 + 6 * SH_SIZE;
+        assert_eq!(header.iter_section_headers(&file[..table_end]).count(), 6);
+        assert_eq!(
+            header.iter_section_headers(&file[..table_end - 1]).count(),
+            5
+        );
+
+        /* The string table's own header is cut off */
+        let short = &file[..EHSIZE_X64 + 3 * SH_SIZE];
+        assert!(header.shstrtab(short).is_none());
+
+        let mut none = header;
+        none.e_shstrndx = 0;
+        assert!(none.shstrtab(&file).is_none());
+        none.e_shoff = None;
+        assert_eq!(none.iter_section_headers(&file).count(), 0);
+    }
+
+    #[test]
+    fn strtab() {
+        let strtab = StrTab::new(STRTAB);
+        assert_eq!(strtab.get(0), Some(""));
+        assert_eq!(strtab.get(1), Some(".symtab"));
+        /* Any offset, even into the middle of a name */
+        assert_eq!(strtab.get(2), Some("symtab"));
+        assert_eq!(strtab.get(28), None);
+        assert_eq!(strtab.get(31), None);
+        assert_eq!(strtab.get(STRTAB.len() as u32), None);
+        assert_eq!(StrTab::new(&[]).get(0), None);
+    }
+
+    #[test]
+    fn parsed() {
+        let (_, file) = make_file(SH_SIZE);
+        let elf: Elf<Amd64> = Elf::from_bytes(&file).unwrap();
+        let sections = elf.section_headers().unwrap();
+        assert_eq!(elf.section_name(&sections.get(2).unwrap()), Some(".dynamic"));
+        assert_eq!(elf.section_name(&sections.get(4).unwrap()), None);
+        assert_eq!(elf.shstrtab().unwrap().as_bytes(), STRTAB);
+
+        /* SHN_UNDEF, there are no names */
+        let mut header = elf.header();
+        header.e_shstrndx = SHN_UNDEF;
+        let mut unnamed = file.clone();
+        unnamed[..EHSIZE_X64].copy_from_slice(bytes_of(&header));
+        let elf: Elf<Amd64> = Elf::from_bytes(&unnamed).unwrap();
+        assert!(elf.shstrtab().is_none());
+        assert_eq!(elf.section_name(&sections.get(2).unwrap()), None);
+    }
+
+    #[test]
+    fn extended_index() {
+        /* Section 0 links to the real index */
+        let (mut header, mut file) = make_file(SH_SIZE);
+        header.e_shstrndx = SHN_XINDEX;
+        file[..EHSIZE_X64].copy_from_slice(bytes_of(&header));
+        let link = EHSIZE_X64 + 40;
+        file[link..link + 4].copy_from_slice(&3u32.to_le_bytes());
+
+        assert_eq!(header.shstrtab(&file).unwrap().sh_type, SectionType::Strtab as u32);
+        let symtab = header.section(&file, 1).unwrap().unwrap();
+        assert_eq!(header.section_name(&file, &symtab).unwrap(), Some(".symtab"));
+        let elf: Elf<Amd64> = Elf::from_bytes(&file).unwrap();
+        assert_eq!(elf.section_name(&symtab), Some(".symtab"));
+
+        /* Out of range */
+        file[link..link + 4].copy_from_slice(&6u32.to_le_bytes());
+        assert!(header.shstrtab(&file).is_none());
+        assert!(header.section_name(&file, &symtab).is_err());
+    }
+}
+
+mod segment_map {
+    use super::*;
+
+    const BASE: u64 = 0xffff_ffff_c000_0000;
+    const MEGAPAGE: u64 = 0x20_0000;
+    const STRTAB: &[u8] = b"\0.text\0.rodata\0.data\0.bss\0.tbss\0.shstrtab\0.comment\0";
+
+    fn name(name: &str) -> u32 {
+        let needle = format!("\0{}\0", name);
+        let pos = STRTAB
+            .windows(needle.len())
+            .position(|w| w == needle.as_bytes())
+            .unwrap();
+        return pos as u32 + 1;
+    }
+
+    fn section(
+        sh_name: &str,
+        sh_type: SectionType,
+        flags: u64,
+        addr: u64,
+        size: u64,
+    ) -> SectionHeader {
+        SectionHeader {
+            sh_name: name(sh_name),
+            sh_type: sh_type as u32,
+            sh_flags: flags,
+            sh_addr: addr,
+            sh_offset: 0,
+            sh_size: size,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 16,
+            sh_entsize: 0,
+        }
+    }
+
+    fn load(flags: u32, vaddr: u64, memsz: u64) -> ProgramHeader {
+        ProgramHeader::new_load(flags, 0, vaddr, 0, memsz, MEGAPAGE)
+    }
+
+    /* Program headers, section headers with a null section first and
+     * the name string table last, then the names */
+    fn make_file(pheaders: &[ProgramHeader], sections: &[SectionHeader]) -> (Header, Vec<u8>) {
+        let mut file = File::with_segments(pheaders);
+        file.sections.push(unsafe { core::mem::zeroed() });
+        file.sections.extend_from_slice(sections);
+        file.shstrtab(name(".shstrtab"), STRTAB);
+        return file.build();
+    }
+
+    /// The kernel's three segments, text, rodata and data/bss
+    fn segments() -> [ProgramHeader; 3] {
+        [
+            load(PF_R | PF_X, BASE, MEGAPAGE),
+            load(PF_R, BASE + MEGAPAGE, MEGAPAGE),
+            load(PF_R | PF_W, BASE + 2 * MEGAPAGE, MEGAPAGE),
+        ]
+    }
+
+    fn conflicts(header: &Header, buf: &[u8]) -> Vec<(String, Option<SectionConflict>)> {
+        let file = buf;
+        header
+            .section_segment_map(file)
+            .unwrap()
+            .map(|p| {
+                let name = header.section_name(file, &p.section).unwrap().unwrap();
+                (String::from(name), p.conflict)
+            })
+            .collect()
+    }
+    #[test]
+    fn good_layout() {
+        const AW: u64 = SHF_ALLOC | SHF_WRITE;
+        let (header, buf) = make_file(
+            &segments(),
+            &[
+                section(
+                    ".text",
+                    SectionType::Progbits,
+                    SHF_ALLOC | SHF_EXECINSTR,
+                    BASE,
+                    0x1234,
+                ),
+                section(
+                    ".rodata",
+                    SectionType::Progbits,
+                    SHF_ALLOC,
+                    BASE + MEGAPAGE,
+                    0x800,
+                ),
+                section(
+                    ".data",
+                    SectionType::Progbits,
+                    AW,
+                    BASE + 2 * MEGAPAGE,
+                    0x100,
+                ),
+                /* Overlaps .bss without taking space */
+                section(
+                    ".tbss",
+                    SectionType::Nobits,
+                    AW | SHF_TLS,
+                    BASE + 3 * MEGAPAGE - 0x10,
+                    0x40,
+                ),
+                section(
+                    ".bss",
+                    SectionType::Nobits,
+                    AW,
+                    BASE + 2 * MEGAPAGE + 0x100,
+                    0x1000,
+                ),
+                /* Not loaded, so not checked */
+                section(".comment", SectionType::Progbits, 0, 0, 0x20),
+            ],
+        );
+
+        let map: Vec<_> = header.section_segment_map(&buf).unwrap().collect();
+        assert_eq!(map.len(), 5);
+        assert!(map.iter().all(|p| p.conflict.is_none()));
+        let segments: Vec<_> = map.iter().map(|p| p.segment.unwrap().0).collect();
+        assert_eq!(segments, [0, 1, 2, 2, 2]);
+        assert_eq!(map[0].index, 1);
+    }
+
+    /// What a linker script that forgot to start a new segment for `.data`,
+    /// and that doesn't align `.rodata`, produces
+    #[test]
+    fn broken_linker_script() {
+        const AW: u64 = SHF_ALLOC | SHF_WRITE;
+        let (header, buf) = make_file(
+            &segments(),
+            &[
+                section(
+                    ".text",
+                    SectionType::Progbits,
+                    SHF_ALLOC | SHF_EXECINSTR,
+                    BASE,
+                    0x1000,
+                ),
+                section(".data", SectionType::Progbits, AW, BASE + 0x1000, 0x100),
+                section(
+                    ".rodata",
+                    SectionType::Progbits,
+                    SHF_ALLOC,
+                    BASE + MEGAPAGE - 0x100,
+                    0x800,
+                ),
+                section(
+                    ".tbss",
+                    SectionType::Nobits,
+                    AW | SHF_TLS,
+                    BASE + MEGAPAGE,
+                    0x10,
+                ),
+                section(".bss", SectionType::Nobits, AW, BASE + 3 * MEGAPAGE, 0x1000),
+                section(
+                    ".comment",
+                    SectionType::Progbits,
+                    SHF_ALLOC | SHF_EXECINSTR,
+                    BASE + 2 * MEGAPAGE,
+                    0x10,
+                ),
+            ],
+        );
+
+        let found = conflicts(&header, &buf);
+        let expected = [
+            (".text", None),
+            (".data", Some(SectionConflict::WritableInReadOnly)),
+            (".rodata", Some(SectionConflict::SpansSegments)),
+            (".tbss", Some(SectionConflict::WritableInReadOnly)),
+            (".bss", Some(SectionConflict::Outside)),
+            (".comment", Some(SectionConflict::ExecutableInNoExec)),
+        ];
+        let expected: Vec<_> = expected.iter().map(|&(n, c)| (n.to_string(), c)).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn empty_section_at_segment_end() {
+        let (header, buf) = make_file(
+            &segments(),
+            &[section(
+                ".data",
+                SectionType::Progbits,
+                SHF_ALLOC | SHF_WRITE,
+                BASE + 3 * MEGAPAGE,
+                0,
+            )],
+        );
+        let placement = header.section_segment_map(&buf).unwrap().next().unwrap();
+        assert_eq!(placement.segment.map(|s| s.0), Some(2));
+        assert_eq!(placement.conflict, None);
+    }
+
+    #[test]
+    fn ignores_other_segments() {
+        let mut stack = load(PF_R | PF_W, BASE + 0x1000, 0x1000);
+        stack.p_type = 0x6474_e551;
+        let (header, buf) = make_file(
+            &[stack, load(PF_R | PF_X, BASE, MEGAPAGE)],
+            &[section(
+                ".data",
+                SectionType::Progbits,
+                SHF_ALLOC | SHF_WRITE,
+                BASE + 0x1000,
+                0x10,
+            )],
+        );
+        let placement = header.section_segment_map(&buf).unwrap().next().unwrap();
+        assert_eq!(placement.segment.map(|s| s.0), Some(1));
+        assert_eq!(
+            placement.conflict,
+            Some(SectionConflict::WritableInReadOnly)
+        );
+    }
+
+    #[test]
+    fn table_rows() {
+        let (header, buf) = make_file(
+            &segments(),
+            &[
+                section(
+                    ".text",
+                    SectionType::Progbits,
+                    SHF_ALLOC | SHF_EXECINSTR,
+                    BASE,
+                    0x1234,
+                ),
+                section(
+                    ".data",
+                    SectionType::Progbits,
+                    SHF_ALLOC | SHF_WRITE,
+                    BASE,
+                    0x10,
+                ),
+                section(
+                    ".bss",
+                    SectionType::Nobits,
+                    SHF_ALLOC | SHF_WRITE,
+                    0x1000,
+                    0x10,
+                ),
+            ],
+        );
+        let file = &buf[..];
+        let mut table = String::from(SECTION_MAP_HEADER);
+        for placement in header.section_segment_map(file).unwrap() {
+            let name = header.section_name(file, &placement.section).unwrap();
+            placement.write_row(&mut table, name).unwrap();
+        }
+
+        assert_eq!(
+            table,
+            "Nr  Name                 Address            Size       Flg  Segment\n\
+             1   .text                0xffffffffc0000000 0x00001234  AX  0   R E\n\
+             2   .data                0xffffffffc0000000 0x00000010 WA   0   R E  WritableInReadOnly\n\
+             3   .bss                 0x0000000000001000 0x00000010 WA   -        Outside\n"
+        );
+    }
+}
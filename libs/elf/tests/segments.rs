@@ -0,0 +1,951 @@
+mod common;
+
+use common::*;
+use core::num::NonZeroU64;
+use elf::*;
+
+mod program_header_iter {
+    use super::*;
+
+    /* Header then `pheaders`, each padded to `phentsize` */
+    fn image(phentsize: usize, pheaders: &[ProgramHeader]) -> (Header, Vec<u8>) {
+        let mut file = File::with_segments(pheaders);
+        file.header.e_phentsize = phentsize as u16;
+        return file.build();
+    }
+
+    fn pheaders() -> [ProgramHeader; 3] {
+        let mut note = ProgramHeader::new_load(PF_R, 0x3000, 0, 0x20, 0x20, 4);
+        note.p_type = SegmentType::Note.to_integer();
+        [
+            ProgramHeader::new_load(PF_R | PF_X, 0x1000, 0x20_0000, 0x100, 0x100, 0x1000),
+            note,
+            ProgramHeader::new_load(PF_R | PF_W, 0x2000, 0x20_1000, 0x10, 0x80, 0x1000),
+        ]
+    }
+
+    #[test]
+    fn loads() {
+        let (header, bytes) = image(PH_SIZE, &pheaders());
+        let mut vaddrs = Vec::new();
+        for ph in header.iter_program_headers(&bytes) {
+            if ph.segment_type() == Some(SegmentType::Load) {
+                vaddrs.push(ph.p_vaddr);
+            }
+        }
+        assert_eq!(vaddrs, [0x20_0000, 0x20_1000]);
+        let loads = header.iter_program_headers(&bytes).load_segments();
+        assert!(loads.map(|ph| ph.p_vaddr).eq(vaddrs.iter().copied()));
+
+        /* Same entries as the checked table */
+        let table = header.program_headers(&bytes).unwrap();
+        let key = |ph: ProgramHeader| (ph.p_type, ph.p_offset, ph.p_vaddr, ph.p_memsz);
+        assert!(header
+            .iter_program_headers(&bytes)
+            .map(key)
+            .eq(table.iter().map(key)));
+
+        /* Any alignment will do */
+        let mut shifted = vec![0u8];
+        shifted.extend_from_slice(&bytes);
+        assert_eq!(header.iter_program_headers(&shifted[1..]).count(), 3);
+    }
+
+    #[test]
+    fn larger_entries() {
+        let (header, bytes) = image(PH_SIZE + 8, &pheaders());
+        let vaddrs: Vec<u64> = header
+            .iter_program_headers(&bytes)
+            .map(|ph| ph.p_vaddr)
+            .collect();
+        assert_eq!(vaddrs, [0x20_0000, 0, 0x20_1000]);
+        /* The checked table strides the same way */
+        let table = header.program_headers(&bytes).unwrap();
+        assert!(table.iter().map(|ph| ph.p_vaddr).eq(vaddrs.iter().copied()));
+
+        /* Too small to hold an entry */
+        let (header, bytes) = image(PH_SIZE, &pheaders());
+        let mut small = header;
+        small.e_phentsize = PH_SIZE as u16 - 8;
+        assert_eq!(small.iter_program_headers(&bytes).count(), 0);
+    }
+
+    #[test]
+    fn truncated() {
+        let (header, bytes) = image(PH_SIZE + 8, &pheaders());
+        /* The last entry's padding isn't needed */
+        let end = bytes.len() - 8;
+        assert_eq!(header.iter_program_headers(&bytes[..end]).count(), 3);
+        assert_eq!(header.iter_program_headers(&bytes[..end - 1]).count(), 2);
+        assert_eq!(header.iter_program_headers(&bytes[..EHSIZE_X64]).count(), 0);
+        assert_eq!(header.iter_program_headers(&bytes[..10]).count(), 0);
+
+        /* Stays done */
+        let mut iter = header.iter_program_headers(&bytes[..end - 1]);
+        assert_eq!(iter.size_hint(), (0, Some(3)));
+        assert!(iter.by_ref().nth(2).is_none());
+        assert!(iter.next().is_none());
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn bad_offset() {
+        let (header, bytes) = image(PH_SIZE, &pheaders());
+        let mut missing = header;
+        missing.e_phoff = None;
+        assert_eq!(missing.iter_program_headers(&bytes).count(), 0);
+
+        let mut huge = header;
+        huge.e_phoff = NonZeroU64::new(u64::MAX - 8);
+        assert_eq!(huge.iter_program_headers(&bytes).count(), 0);
+    }
+
+    #[test]
+    fn parsed() {
+        let (_, bytes) = image(64, &pheaders());
+        let elf: Elf<Amd64> = Elf::parse(&bytes).unwrap();
+        let vaddrs: Vec<u64> = elf.load_segments().map(|ph| ph.p_vaddr).collect();
+        assert_eq!(vaddrs, [0x20_0000, 0x20_1000]);
+        assert!(elf.is_identity_linkable(0x20_0000));
+
+        /* Not parsed, so the cut off entry just ends it */
+        let elf: Elf<Amd64> = Elf::from_bytes(&bytes[..bytes.len() - 16]).unwrap();
+        assert_eq!(elf.load_segments().count(), 1);
+        assert!(!elf.is_identity_linkable(0x20_0000));
+    }
+}
+
+mod page_aligned {
+    use super::*;
+
+    const MEGAPAGE: u64 = 0x20_0000;
+
+    fn make_file(pheaders: &[ProgramHeader]) -> (Header, Vec<u8>) {
+        File::with_segments(pheaders).build()
+    }
+
+    fn load(vaddr: u64) -> ProgramHeader {
+        ProgramHeader::new_load(PF_R, 0, vaddr, 0, 0x1000, 0x1000)
+    }
+
+    fn aligned(header: &Header, buf: &[u8], page_size: u64) -> bool {
+        header.all_segments_page_aligned(buf, page_size).is_ok()
+    }
+
+    #[test]
+    fn megapage_aligned() {
+        let (header, buf) = make_file(&[
+            load(0x20_0000),
+            load(0x40_0000),
+            load(0xFFFF_FFFF_8000_0000),
+        ]);
+        assert!(aligned(&header, &buf, MEGAPAGE));
+        assert!(aligned(&header, &buf, 0x1000));
+    }
+
+    #[test]
+    fn reports_first_misaligned() {
+        /* Congruent with the file offset for 4K pages, which is all p_align asks for */
+        let (header, buf) = make_file(&[load(0x20_0000), load(0x20_1000), load(0x20_3000)]);
+        assert!(aligned(&header, &buf, 0x1000));
+        assert!(matches!(
+            header.all_segments_page_aligned(&buf, MEGAPAGE),
+            Err(SegmentError::Misaligned { vaddr: 0x20_1000 })
+        ));
+    }
+
+    #[test]
+    fn only_load_segments() {
+        let mut stack = load(0x1234);
+        stack.p_type = SegmentType::GnuStack.to_integer();
+        let (header, buf) = make_file(&[stack, load(0x20_0000)]);
+        assert!(aligned(&header, &buf, MEGAPAGE));
+    }
+
+    #[test]
+    fn truncated_table() {
+        let (header, buf) = make_file(&[load(0x20_0000)]);
+        let file = &buf[..EHSIZE_X64 + PH_SIZE - 1];
+        assert!(matches!(
+            header.all_segments_page_aligned(file, MEGAPAGE),
+            Err(SegmentError::Memory(MemoryError::UnexpectedEnd))
+        ));
+    }
+}
+
+mod load_bounds {
+    use super::*;
+
+    fn load(vaddr: u64, memsz: u64, align: u64) -> ProgramHeader {
+        ProgramHeader::new_load(PF_R, 0, vaddr, 0, memsz, align)
+    }
+
+    fn bounds(pheaders: &[ProgramHeader]) -> Result<LoadBounds, ParseError> {
+        let (header, buf) = File::with_segments(pheaders).build();
+        let bounds = header.load_bounds(&buf);
+        assert_eq!(header.load_image_bounds(&buf), bounds.ok());
+        return bounds;
+    }
+
+    #[test]
+    fn kernel_layout() {
+        /* Text, rodata and data/bss one megapage apart, like `KernelImage` wants */
+        let base = 0xFFFF_FFFF_8000_0000;
+        let b = bounds(&[
+            load(base, 0x1_2345, 0x20_0000),
+            load(base + 0x20_0000, 0x800, 0x20_0000),
+            load(base + 0x40_0000, 0x20_0001, 0x20_0000),
+        ])
+        .unwrap();
+        assert_eq!(
+            b,
+            LoadBounds {
+                min_vaddr: base,
+                max_vaddr: base + 0x60_1000,
+                total_memsz: 0x1_3000 + 0x1000 + 0x20_1000,
+                max_align: 0x20_0000,
+            }
+        );
+        assert_eq!(b.span(), 0x60_1000);
+    }
+
+    #[test]
+    fn alignment() {
+        /* Start down to p_align, end up to the page size, not to p_align */
+        let b = bounds(&[load(0x20_1234, 0x10, 0x20_0000)]).unwrap();
+        assert_eq!((b.min_vaddr, b.max_vaddr), (0x20_0000, 0x20_2000));
+        assert_eq!(b.total_memsz, 0x2000);
+
+        /* 0 and 1 mean unaligned */
+        for align in [0, 1] {
+            let b = bounds(&[load(0x1234, 0x10, align)]).unwrap();
+            assert_eq!((b.min_vaddr, b.max_vaddr), (0x1234, 0x2000));
+            assert_eq!(b.max_align, 1);
+        }
+    }
+
+    #[test]
+    fn out_of_order_with_gap() {
+        let b = bounds(&[
+            load(0x40_0000, 0x1000, 0x1000),
+            load(0x10_0000, 0x1800, 0x10),
+        ]);
+        assert_eq!(
+            b,
+            Ok(LoadBounds {
+                min_vaddr: 0x10_0000,
+                max_vaddr: 0x40_1000,
+                total_memsz: 0x3000,
+                max_align: 0x1000,
+            })
+        );
+        assert_eq!(b.unwrap().span(), 0x30_1000);
+    }
+
+    #[test]
+    fn only_load_segments() {
+        let mut stack = load(0x1000, 0x10_0000, 0x10);
+        stack.p_type = SegmentType::GnuStack.to_integer();
+        assert_eq!(bounds(&[stack]), Err(ParseError::NoLoadSegments));
+        assert_eq!(bounds(&[]), Err(ParseError::NoLoadSegments));
+        let b = bounds(&[stack, load(0x20_0000, 0x1000, 0x1000)]).unwrap();
+        assert_eq!(b.min_vaddr, 0x20_0000);
+    }
+
+    #[test]
+    fn overflow() {
+        let overflow = Err(ParseError::SegmentOverflow(0));
+        assert_eq!(bounds(&[load(u64::MAX - 0xfff, 0x1000, 0x1000)]), overflow);
+        assert_eq!(bounds(&[load(u64::MAX - 0xfff, 0x800, 0x1000)]), overflow);
+    }
+
+    #[test]
+    fn overlap() {
+        /* Sharing a page is fine, sharing bytes isn't */
+        let text = load(0x1000, 0x1800, 0x1000);
+        assert!(bounds(&[text, load(0x2800, 0x800, 0x1000)]).is_ok());
+        assert_eq!(
+            bounds(&[text, load(0x27ff, 0x800, 0x1000)]),
+            Err(ParseError::SegmentOverlap(0, 1))
+        );
+
+        /* Reported by program header index, other types in between count */
+        let mut note = load(0x1000, 0x10, 4);
+        note.p_type = SegmentType::Note.to_integer();
+        let inside = load(0x1100, 0x10, 0x10);
+        assert_eq!(
+            bounds(&[text, note, load(0x8000, 0x10, 0x10), inside]),
+            Err(ParseError::SegmentOverlap(0, 3))
+        );
+
+        /* Empty segments take no memory */
+        assert!(bounds(&[text, load(0x1800, 0, 0x10)]).is_ok());
+    }
+
+    #[test]
+    fn file_size() {
+        let mut data = load(0x1000, 0x100, 0x1000);
+        data.p_filesz = 0x100;
+        assert!(bounds(&[data]).is_ok());
+        data.p_filesz = 0x101;
+        assert_eq!(
+            bounds(&[load(0x10_0000, 0x10, 0x10), data]),
+            Err(ParseError::FileSizeOverMemSize(1))
+        );
+    }
+
+    #[test]
+    fn broken_table() {
+        /* A PT_LOAD cut off at the end isn't left out, the whole image is refused */
+        let overlapping = [load(0x1000, 0x1000, 0x1000), load(0x1800, 0x1000, 0x1000)];
+        let (header, bytes) = File::with_segments(&overlapping).build();
+        let cut = EHSIZE_X64 + PH_SIZE + 8;
+        assert_eq!(
+            header.load_bounds(&bytes[..cut]),
+            Err(ParseError::ProgramHeadersTruncated {
+                end: (EHSIZE_X64 + 2 * PH_SIZE) as u64,
+                len: cut,
+            })
+        );
+
+        let mut narrow = header;
+        narrow.e_phentsize = PH_SIZE as u16 - 8;
+        assert_eq!(
+            narrow.load_bounds(&bytes),
+            Err(ParseError::ProgramHeaderSize(PH_SIZE as u16 - 8))
+        );
+        let mut nowhere = header;
+        nowhere.e_phoff = None;
+        assert_eq!(
+            nowhere.load_bounds(&bytes),
+            Err(ParseError::ProgramHeaderOffset(0))
+        );
+    }
+
+    #[test]
+    fn from_elf() {
+        let (_, bytes) = File::with_segments(&[load(0x20_0000, 0x1000, 0x1000)]).build();
+        let elf = Elf::<Amd64>::from_bytes(&bytes).unwrap();
+        assert_eq!(elf.load_bounds().unwrap().span(), 0x1000);
+    }
+}
+
+mod interpreter {
+    use super::*;
+
+    const LD: &[u8] = b"/lib/ld-musl-x86_64.so.1\0";
+
+    /* PT_PHDR, PT_INTERP with `path`, then a PT_LOAD */
+    fn image(path: &[u8]) -> (Header, Vec<u8>) {
+        let mut file = File::with_segments(&[
+            segment(SegmentType::ProgramHeader, EHSIZE_X64, 3 * PH_SIZE, 8),
+            segment(SegmentType::Interpreter, 0, path.len(), 1),
+            segment(SegmentType::Load, 0, 0, 0x1000),
+        ]);
+        file.pheaders[1].p_offset = file.push(path) as u64;
+        return file.build();
+    }
+
+    #[test]
+    fn path() {
+        let (header, bytes) = image(LD);
+        assert_eq!(
+            header.interpreter(&bytes),
+            Some(Ok("/lib/ld-musl-x86_64.so.1"))
+        );
+
+        /* Static executables have none */
+        let (header, bytes) =
+            File::with_segments(&[segment(SegmentType::Load, 0, 0, 0x1000)]).build();
+        assert_eq!(header.interpreter(&bytes), None);
+    }
+
+    #[test]
+    fn malformed() {
+        for (path, error) in [
+            (&LD[..LD.len() - 1], InterpError::MissingNul),
+            (b"/lib/ld\0.so\0", InterpError::InteriorNul),
+            (b"/lib/\xff.so\0", InterpError::NotUtf8),
+            (b"\0", InterpError::Empty),
+            (b"", InterpError::Empty),
+        ] {
+            let (header, bytes) = image(path);
+            assert_eq!(header.interpreter(&bytes), Some(Err(error)), "{:?}", path);
+        }
+
+        /* Past the end of the image, and overflowing it */
+        let (header, bytes) = image(LD);
+        let error = Some(Err(InterpError::OutOfBounds));
+        assert_eq!(header.interpreter(&bytes[..bytes.len() - 1]), error);
+        let mut file = File::with_segments(&[segment(SegmentType::Interpreter, 0, 0, 1)]);
+        file.pheaders[0].p_offset = u64::MAX;
+        file.pheaders[0].p_filesz = 2;
+        let (header, bytes) = file.build();
+        assert_eq!(header.interpreter(&bytes), error);
+    }
+
+    #[test]
+    fn multiple() {
+        let mut file = File::with_segments(&[
+            segment(SegmentType::Interpreter, 0, LD.len(), 1),
+            segment(SegmentType::Interpreter, 0, 0, 1),
+        ]);
+        let offset = file.push(LD) as u64;
+        file.pheaders[0].p_offset = offset;
+        let (header, bytes) = file.build();
+        assert_eq!(header.interpreter(&bytes), Some(Err(InterpError::Multiple)));
+
+        /* The first one is read before the second is looked for */
+        file.pheaders[0].p_filesz -= 1;
+        let (header, bytes) = file.build();
+        assert_eq!(
+            header.interpreter(&bytes),
+            Some(Err(InterpError::MissingNul))
+        );
+    }
+
+    #[test]
+    fn from_elf() {
+        let (_, bytes) = image(LD);
+        let elf = Elf::<Amd64>::from_bytes(&bytes).unwrap();
+        assert_eq!(elf.interpreter(), Some(Ok("/lib/ld-musl-x86_64.so.1")));
+    }
+}
+
+mod stack_flags {
+    use super::*;
+
+    fn stack(flags: u32) -> ProgramHeader {
+        let mut ph = segment(SegmentType::GnuStack, 0, 0, 0x10);
+        ph.p_flags = flags;
+        return ph;
+    }
+
+    fn flags(pheaders: &[ProgramHeader]) -> Option<StackFlags> {
+        let (header, bytes) = File::with_segments(pheaders).build();
+        header.stack_flags(&bytes)
+    }
+
+    #[test]
+    fn executable() {
+        let load = segment(SegmentType::Load, 0, 0, 0x1000);
+        let rw = flags(&[load, stack(PF_R | PF_W)]).unwrap();
+        assert_eq!(rw.flags, PF_R | PF_W);
+        assert!(!rw.executable());
+        let rwx = flags(&[stack(PF_R | PF_W | PF_X), load]).unwrap();
+        assert!(rwx.executable());
+    }
+
+    #[test]
+    fn absent() {
+        assert_eq!(flags(&[segment(SegmentType::Load, 0, 0, 0x1000)]), None);
+        assert_eq!(flags(&[]), None);
+    }
+}
+
+mod dynamic {
+    use super::*;
+
+    fn entry(tag: DynamicTag, val: u64) -> Dyn {
+        Dyn {
+            d_tag: tag.to_integer(),
+            d_val: val,
+        }
+    }
+
+    /* A PT_DYNAMIC segment with `entries`, misaligned in the file */
+    fn image(entries: &[Dyn]) -> (Header, Vec<u8>) {
+        let table = unaligned_table(entries);
+        let mut file = File::with_segments(&[segment(SegmentType::Dynamic, 0, table.len() - 1, 8)]);
+        file.pheaders[0].p_offset = file.push(&table) as u64 + 1;
+        return file.build();
+    }
+
+    #[test]
+    fn tags() {
+        for x in (0..=30).chain([0x6ffffef5, 0x6ffffff9, 0x6ffffffa, 0x6ffffffb]) {
+            let tag = DynamicTag::from_integer(x);
+            assert!(!matches!(tag, DynamicTag::Unknown(_)), "{:#x}", x);
+            assert_eq!(tag.to_integer(), x);
+        }
+        for x in [31, 0x6000_000d, 0x7000_0001, -1] {
+            assert_eq!(DynamicTag::from_integer(x), DynamicTag::Unknown(x));
+            assert_eq!(DynamicTag::from_integer(x).to_integer(), x);
+        }
+        assert_eq!(format!("{:?}", DynamicTag::Flags1), "Flags1 (0x6ffffffb)");
+        assert_eq!(
+            format!("{:?}", DynamicTag::Unknown(0x7000_0001)),
+            "Unknown (0x70000001)"
+        );
+    }
+
+    #[test]
+    fn stops_at_null() {
+        let (header, bytes) = image(&[
+            entry(DynamicTag::Needed, 1),
+            entry(DynamicTag::Unknown(0x7000_0001), 7),
+            entry(DynamicTag::StrTab, 0x2000),
+            entry(DynamicTag::Null, 0),
+            entry(DynamicTag::SymTab, 0x3000),
+        ]);
+        let tags: Vec<DynamicTag> = header.dynamic(&bytes).unwrap().map(|d| d.tag()).collect();
+        assert_eq!(
+            tags,
+            [
+                DynamicTag::Needed,
+                DynamicTag::Unknown(0x7000_0001),
+                DynamicTag::StrTab
+            ]
+        );
+
+        /* Or at the end of the segment or file without one */
+        let (header, bytes) = image(&[entry(DynamicTag::Needed, 1), entry(DynamicTag::Init, 2)]);
+        assert_eq!(header.dynamic(&bytes).unwrap().count(), 2);
+        assert_eq!(
+            header.dynamic(&bytes[..bytes.len() - 1]).unwrap().count(),
+            1
+        );
+
+        let (header, bytes) = File::with_segments(&[segment(SegmentType::Load, 0, 0, 8)]).build();
+        assert!(header.dynamic(&bytes).is_none());
+    }
+
+    #[test]
+    fn info() {
+        let (header, bytes) = image(&[
+            entry(DynamicTag::Needed, 1),
+            entry(DynamicTag::Needed, 11),
+            entry(DynamicTag::SoName, 20),
+            entry(DynamicTag::StrTab, 0x2000),
+            entry(DynamicTag::StrSz, 0x80),
+            entry(DynamicTag::SymTab, 0x3000),
+            entry(DynamicTag::SymEnt, 24),
+            entry(DynamicTag::Rela, 0x4000),
+            entry(DynamicTag::RelaSz, 0x48),
+            entry(DynamicTag::RelaEnt, 24),
+            entry(DynamicTag::Init, 0x5000),
+            entry(DynamicTag::Fini, 0x5010),
+            entry(DynamicTag::Flags, DF_TEXTREL),
+            entry(DynamicTag::Flags1, DF_1_PIE),
+            entry(DynamicTag::Unknown(0x7000_0001), 7),
+            entry(DynamicTag::Null, 0),
+            entry(DynamicTag::Hash, 0x6000),
+        ]);
+        let info = header.dynamic(&bytes).unwrap().info();
+        assert_eq!(
+            info,
+            DynamicInfo {
+                needed: 2,
+                soname: Some(20),
+                strtab: Some(0x2000),
+                strsz: Some(0x80),
+                symtab: Some(0x3000),
+                syment: Some(24),
+                rela: Some(0x4000),
+                relasz: Some(0x48),
+                relaent: Some(24),
+                init: Some(0x5000),
+                fini: Some(0x5010),
+                flags: DF_TEXTREL,
+                flags_1: DF_1_PIE,
+                ..DynamicInfo::default()
+            }
+        );
+        assert!(!info.bind_now());
+
+        let (header, bytes) = image(&[entry(DynamicTag::BindNow, 0)]);
+        assert!(header.dynamic(&bytes).unwrap().info().bind_now());
+    }
+
+    #[test]
+    fn big_endian() {
+        let mut table = Vec::new();
+        for (tag, val) in [(DynamicTag::StrTab, 0x2000u64), (DynamicTag::Null, 0)] {
+            table.extend_from_slice(&tag.to_integer().to_be_bytes());
+            table.extend_from_slice(&val.to_be_bytes());
+        }
+        let entries = HeaderIter::<Dyn>::new(&table, 0, 16, 2).in_order(Data::Msb);
+        let info = Dynamic::new(entries).info();
+        assert_eq!(info.strtab, Some(0x2000));
+    }
+}
+
+mod segment_counts {
+    use super::*;
+
+    fn typed(typ: SegmentType) -> ProgramHeader {
+        segment(typ, 0, 0, 8)
+    }
+
+    fn make_file(pheaders: &[ProgramHeader]) -> (Header, Vec<u8>) {
+        File::with_segments(pheaders).build()
+    }
+
+    #[test]
+    fn dynamically_linked() {
+        /* Same as `readelf -l /bin/ls` on a typical x86_64 Linux */
+        let (header, buf) = make_file(&[
+            typed(SegmentType::ProgramHeader),
+            typed(SegmentType::Interpreter),
+            typed(SegmentType::Load),
+            typed(SegmentType::Load),
+            typed(SegmentType::Load),
+            typed(SegmentType::Load),
+            typed(SegmentType::Dynamic),
+            typed(SegmentType::Note),
+            typed(SegmentType::Note),
+            typed(SegmentType::GnuProperty),
+            typed(SegmentType::GnuEhFrame),
+            typed(SegmentType::GnuStack),
+            typed(SegmentType::GnuRelro),
+        ]);
+
+        let counts = header.segment_counts(&buf).unwrap();
+        assert_eq!(counts.load, 4);
+        assert_eq!(counts.note, 2);
+        assert_eq!(counts.interpreter, 1);
+        assert_eq!(counts.dynamic, 1);
+        assert_eq!(counts.tls, 0);
+        assert_eq!(counts.unknown, 0);
+
+        assert_eq!(
+            format!("{:?}", counts),
+            "SegmentCounts { load: 4, dynamic: 1, interpreter: 1, note: 2, \
+             program_header: 1, gnu_eh_frame: 1, gnu_stack: 1, gnu_relro: 1, gnu_property: 1 }"
+        );
+    }
+
+    #[test]
+    fn vendor_and_unknown_types() {
+        let mut unknown = typed(SegmentType::Null);
+        unknown.p_type = 0x8000_0000;
+
+        let (header, buf) = make_file(&[
+            typed(SegmentType::ThreadLocalStorage),
+            typed(SegmentType::OsSpecific(0x6000_0001)),
+            typed(SegmentType::CpuSpecific(0x7000_0001)),
+            unknown,
+        ]);
+
+        let counts = header.segment_counts(&buf).unwrap();
+        assert_eq!(counts.tls, 1);
+        assert_eq!(counts.os_specific, 1);
+        assert_eq!(counts.cpu_specific, 1);
+        assert_eq!(counts.unknown, 1);
+    }
+
+    #[test]
+    fn empty() {
+        let (header, buf) = make_file(&[]);
+        let counts = header.segment_counts(&buf).unwrap();
+        assert_eq!(counts, SegmentCounts::default());
+        assert_eq!(format!("{:?}", counts), "SegmentCounts");
+    }
+}
+
+mod segment_type {
+    use super::*;
+
+    #[test]
+    fn round_trip_variants() {
+        let variants = [
+            SegmentType::Null,
+            SegmentType::Load,
+            SegmentType::Dynamic,
+            SegmentType::Interpreter,
+            SegmentType::Note,
+            SegmentType::SharedLib,
+            SegmentType::ProgramHeader,
+            SegmentType::ThreadLocalStorage,
+            SegmentType::GnuEhFrame,
+            SegmentType::GnuStack,
+            SegmentType::GnuRelro,
+            SegmentType::GnuProperty,
+            SegmentType::OsSpecific(0x6000_0000),
+            SegmentType::OsSpecific(0x6fff_ffff),
+            SegmentType::CpuSpecific(0x7000_0000),
+            SegmentType::CpuSpecific(0x7fff_ffff),
+        ];
+
+        for x in variants.iter() {
+            assert_eq!(SegmentType::from_integer(x.to_integer()), Some(*x));
+        }
+    }
+
+    #[test]
+    fn round_trip_integers() {
+        let ranges = [0..16, 0x6000_0000..0x6000_0010, 0x6474_e540..0x6474_e560];
+
+        for x in ranges
+            .iter()
+            .cloned()
+            .flatten()
+            .chain([0x7000_0003, 0x8000_0000])
+        {
+            if let Some(typ) = SegmentType::from_integer(x) {
+                assert_eq!(typ.to_integer(), x);
+            }
+        }
+
+        assert_eq!(
+            SegmentType::from_integer(0x6474e551),
+            Some(SegmentType::GnuStack)
+        );
+        assert_eq!(SegmentType::from_integer(8), None);
+        assert_eq!(SegmentType::from_integer(0x8000_0000), None);
+    }
+
+    #[test]
+    fn new_load() {
+        let ph = ProgramHeader::new_load(
+            PF_R | PF_X,
+            0x1000,
+            0xffff_ffff_c000_0000,
+            0x200,
+            0x300,
+            1 << 21,
+        );
+        assert_eq!(ph.segment_type(), Some(SegmentType::Load));
+        assert_eq!(ph.p_type, 1);
+        assert!(ph.is_readable());
+        assert!(ph.is_executable());
+        assert!(!ph.is_writable());
+    }
+}
+
+mod fmt {
+    use super::*;
+
+    #[test]
+    fn enums() {
+        assert_eq!(format!("{:?}", Machine::X64), "X64 (0x3e)");
+        assert_eq!(format!("{:?}", SegmentType::Load), "Load (0x1)");
+        assert_eq!(
+            format!("{:?}", SegmentType::GnuStack),
+            "GnuStack (0x6474e551)"
+        );
+        assert_eq!(
+            format!("{:?}", SegmentType::OsSpecific(0x6000_0001)),
+            "OsSpecific (0x60000001)"
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Machine::X64.to_string(), "x86-64");
+        assert_eq!(OsAbi::SystemV.to_string(), "System V");
+        assert_eq!(Type::Executable.to_string(), "EXEC (executable)");
+        assert_eq!(SegmentType::Load.to_string(), "PT_LOAD");
+        assert_eq!(SegmentType::GnuStack.to_string(), "PT_GNU_STACK");
+        assert_eq!(
+            SegmentType::OsSpecific(0x6000_0001).to_string(),
+            "PT_LOOS+0x1 (0x60000001)"
+        );
+        assert_eq!(
+            SegmentType::CpuSpecific(0x7000_0003).to_string(),
+            "PT_LOPROC+0x3 (0x70000003)"
+        );
+    }
+
+    #[test]
+    fn program_header() {
+        let ph = ProgramHeader::new_load(
+            PF_R | PF_X,
+            0x1000,
+            0xffff_ffff_c000_0000,
+            0x1800,
+            0x20_0000,
+            0x20_0000,
+        );
+        assert_eq!(
+            format!("{:?}", ph),
+            "ProgramHeader { type: Load (0x1), flags: R_X, offset: 0x1000, \
+             vaddr: 0xffff_ffff_c000_0000, paddr: 0xffff_ffff_c000_0000, \
+             filesz: 6 KiB, memsz: 2 MiB, align: 0x200000 }"
+        );
+    }
+}
+
+mod notes {
+    use super::*;
+
+    /* namesz, descsz, type, name and desc, each padded to `align` */
+    fn note(name: &[u8], n_type: u32, desc: &[u8], align: usize) -> Vec<u8> {
+        let pad = |v: &mut Vec<u8>| v.resize((v.len() + align - 1) / align * align, 0);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&n_type.to_le_bytes());
+        pad(&mut bytes);
+        bytes.extend_from_slice(name);
+        pad(&mut bytes);
+        bytes.extend_from_slice(desc);
+        pad(&mut bytes);
+        return bytes;
+    }
+
+    /* Header, program headers, then every segment's contents */
+    fn make_file(segments: &[(SegmentType, Vec<u8>, u64)]) -> (Header, Vec<u8>) {
+        let mut file = File::new();
+        for (typ, contents, align) in segments.iter() {
+            file.pheaders.push(segment(*typ, 0, contents.len(), *align));
+        }
+        for (i, (_, contents, _)) in segments.iter().enumerate() {
+            file.pheaders[i].p_offset = file.push(contents) as u64;
+        }
+        return file.build();
+    }
+
+    #[test]
+    fn two_segments() {
+        let gnu = [
+            note(b"GNU\0", 3, b"build-id", 4),
+            note(b"GNU\0", 5, &[1; 12], 4),
+        ]
+        .concat();
+        let sovos = note(b"SOVOS\0", 1, &[7; 16], 4);
+        let (header, file) = make_file(&[
+            (SegmentType::Note, gnu, 4),
+            (SegmentType::Load, vec![0xCC; 64], 0x1000),
+            (SegmentType::Note, sovos, 4),
+        ]);
+
+        let notes: Vec<Note> = header.notes(&file).unwrap().collect();
+        assert_eq!(notes.len(), 3);
+        assert_eq!(notes[0].name, "GNU");
+        assert_eq!(notes[0].note_type, 3);
+        assert_eq!(notes[0].desc, b"build-id");
+        assert_eq!(notes[1].desc.len(), 12);
+
+        let found = header.find_note(&file, "SOVOS", 1).unwrap().unwrap();
+        assert_eq!(found.desc, &[7; 16]);
+        assert!(header.find_note(&file, "SOVOS", 2).unwrap().is_none());
+        assert!(header.find_note(&file, "SOV", 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn eight_byte_alignment() {
+        /* GNU property notes pad to 8 */
+        let notes = [note(b"GNU\0", 5, &[2; 12], 8), note(b"X\0", 9, b"abc", 8)].concat();
+        let (header, file) = make_file(&[(SegmentType::Note, notes, 8)]);
+
+        let notes: Vec<Note> = header.notes(&file).unwrap().collect();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[1].name, "X");
+        assert_eq!(notes[1].desc, b"abc");
+    }
+
+    #[test]
+    fn truncated() {
+        let mut bytes = note(b"SOVOS\0", 1, &[7; 16], 4);
+        /* Unpadded last entry is fine */
+        let (header, file) = make_file(&[(
+            SegmentType::Note,
+            note(b"A\0", 1, b"x", 4)[..17].to_vec(),
+            4,
+        )]);
+        assert_eq!(header.notes(&file).unwrap().next().unwrap().desc, b"x");
+
+        /* Desc cut short, iteration stops */
+        bytes.truncate(bytes.len() - 4);
+        let (header, file) = make_file(&[(SegmentType::Note, bytes, 4)]);
+        assert_eq!(header.notes(&file).unwrap().count(), 0);
+
+        /* Segment past the end of the file */
+        let (header, file) = make_file(&[(SegmentType::Note, vec![0; 12], 4)]);
+        assert!(matches!(
+            header.notes(&file[..file.len() - 1]),
+            Err(MemoryError::UnexpectedEnd)
+        ));
+    }
+
+    #[test]
+    fn every_prefix() {
+        for align in [4, 8] {
+            let first = note(b"GNU\0", 3, b"build-id", align);
+            let bytes = [first.clone(), note(b"X\0", 9, b"abc", align)].concat();
+            /* Each note is there once its desc is, padding or not */
+            let desc_end =
+                |note: &[u8]| note.len() - note.iter().rev().position(|&x| x != 0).unwrap();
+            let ends = [desc_end(&first), first.len() + desc_end(&bytes[first.len()..])];
+
+            for len in 0..=bytes.len() {
+                let count = Notes::new(&bytes[..len], align as u64).count();
+                let expected = ends.iter().filter(|&&end| end <= len).count();
+                assert_eq!(count, expected, "align {}, {} bytes", align, len);
+            }
+        }
+
+        /* Sizes past the end of anything */
+        for at in [0, 4] {
+            let mut bytes = note(b"GNU\0", 3, b"build-id", 4);
+            bytes[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert_eq!(Notes::new(&bytes, 4).count(), 0);
+        }
+    }
+
+    #[test]
+    fn build_id() {
+        let id: Vec<u8> = (0..20).collect();
+        let property = note(b"GNU\0", NT_GNU_PROPERTY_TYPE_0, &[0; 16], 8);
+        let notes = [
+            note(b"GNU\0", 1, b"abi tag!", 4),
+            note(b"Go\0", NT_GNU_BUILD_ID, b"not this one", 4),
+            note(b"GNU\0", NT_GNU_BUILD_ID, &id, 4),
+        ]
+        .concat();
+        let (_, file) = make_file(&[
+            (SegmentType::Note, property, 8),
+            (SegmentType::Note, notes, 4),
+        ]);
+        let elf = Elf::<Amd64>::from_bytes(&file).unwrap();
+        assert_eq!(elf.build_id(), Some(&id[..]));
+
+        /* Descriptor running past the segment ends the walk before it */
+        let mut broken = note(b"GNU\0", NT_GNU_BUILD_ID, &id, 4);
+        broken[4] += 1;
+        let (_, file) = make_file(&[(SegmentType::Note, broken, 4)]);
+        assert_eq!(Elf::<Amd64>::from_bytes(&file).unwrap().build_id(), None);
+
+        let (_, file) = make_file(&[(SegmentType::Load, id, 0x1000)]);
+        assert_eq!(Elf::<Amd64>::from_bytes(&file).unwrap().build_id(), None);
+    }
+
+    #[test]
+    fn build_id_padding() {
+        /* Names of every length mod 4 before it, each padded differently */
+        let id = [0xab; 20];
+        let notes = [
+            note(b"G\0", 1, b"a", 4),
+            note(b"Go\0", 4, b"ab", 4),
+            note(b"SOVOS\0", 1, b"abc", 4),
+            note(b"GNU\0", NT_GNU_BUILD_ID, &id, 4),
+        ]
+        .concat();
+        let (header, file) = make_file(&[(SegmentType::Note, notes, 4)]);
+        assert_eq!(header.build_id(&file), Some(&id[..]));
+
+        let notes = header.notes(&file).unwrap();
+        let names: Vec<&str> = notes.map(|n| n.name).collect();
+        assert_eq!(names, ["G", "Go", "SOVOS", "GNU"]);
+
+        /* A name that isn't text is skipped, not the rest of the segment */
+        let bad = [note(b"\xff\0", 1, b"", 4), note(b"GNU\0", 2, b"", 4)].concat();
+        let notes: Vec<Note> = Notes::new(&bad, 4).collect();
+        assert_eq!(
+            notes,
+            [Note {
+                name: GNU_NOTE_NAME,
+                note_type: 2,
+                desc: &[]
+            }]
+        );
+    }
+
+    #[test]
+    fn no_notes() {
+        let (header, file) = make_file(&[(SegmentType::Load, vec![0; 8], 8)]);
+        assert_eq!(header.notes(&file).unwrap().count(), 0);
+        assert!(header.find_note(&file, "SOVOS", 1).unwrap().is_none());
+    }
+}
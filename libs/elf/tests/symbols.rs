@@ -0,0 +1,401 @@
+mod common;
+
+use common::*;
+use core::mem::size_of;
+use elf::*;
+
+mod symbols {
+    use super::*;
+
+    const SYM_SIZE: usize = size_of::<Symbol>();
+
+    fn symbol(name: u32, info: u8, shndx: u16, value: u64, size: u64) -> Symbol {
+        Symbol {
+            st_name: name,
+            st_info: info,
+            st_other: 0,
+            st_shndx: shndx,
+            st_value: value,
+            st_size: size,
+        }
+    }
+    fn symtab(sh_type: SectionType, offset: usize, count: usize) -> SectionHeader {
+        SectionHeader {
+            sh_name: 0,
+            sh_type: sh_type as u32,
+            sh_flags: 0,
+            sh_addr: 0,
+            sh_offset: offset as u64,
+            sh_size: (count * SYM_SIZE) as u64,
+            sh_link: 0,
+            sh_info: 1,
+            sh_addralign: 8,
+            sh_entsize: SYM_SIZE as u64,
+        }
+    }
+
+    fn file() -> (Vec<u8>, SectionHeader) {
+        let symbols = [
+            symbol(0, 0, 0, 0, 0),
+            symbol(1, 0x04, 0xFFF1, 0, 0),
+            symbol(9, 0x12, 1, 0xFFFF_FFFF_8000_1000, 0x40),
+            symbol(15, 0x21, 2, 0xFFFF_FFFF_8000_2000, 8),
+            symbol(20, 0xA6, 3, 0x10, 4),
+        ];
+        let bytes = unaligned_table(&symbols);
+        return (bytes, symtab(SectionType::Symtab, 1, symbols.len()));
+    }
+
+    #[test]
+    fn info_nibbles() {
+        let (bytes, section) = file();
+        let symbols: Vec<Symbol> = section.symbols(&bytes).unwrap().collect();
+        assert_eq!(symbols.len(), 5);
+
+        let decoded: Vec<_> = symbols
+            .iter()
+            .map(|sym| (sym.binding(), sym.symbol_type()))
+            .collect();
+        assert_eq!(
+            decoded,
+            [
+                (Some(SymbolBinding::Local), Some(SymbolType::NoType)),
+                (Some(SymbolBinding::Local), Some(SymbolType::File)),
+                (Some(SymbolBinding::Global), Some(SymbolType::Func)),
+                (Some(SymbolBinding::Weak), Some(SymbolType::Object)),
+                (Some(SymbolBinding::GnuUnique), Some(SymbolType::Tls)),
+            ]
+        );
+        assert!(symbols[0].is_undefined());
+        assert_eq!(symbols[2].st_value, 0xFFFF_FFFF_8000_1000);
+        assert_eq!(symbols[2].st_size, 0x40);
+
+        let odd = symbol(0, 0x3F, 0, 0, 0);
+        assert_eq!(odd.binding(), None);
+        assert_eq!(odd.symbol_type(), None);
+    }
+
+    #[test]
+    fn only_symbol_tables() {
+        let (bytes, section) = file();
+        let mut dynsym = section;
+        dynsym.sh_type = SectionType::Dynsym as u32;
+        assert_eq!(dynsym.symbols(&bytes).unwrap().count(), 5);
+
+        let mut strtab = section;
+        strtab.sh_type = SectionType::Strtab as u32;
+        assert!(strtab.symbols(&bytes).is_none());
+
+        let mut entsize = section;
+        entsize.sh_entsize = 16;
+        assert!(entsize.symbols(&bytes).is_none());
+
+        let mut partial = section;
+        partial.sh_size -= 1;
+        assert!(partial.symbols(&bytes).is_none());
+    }
+
+    #[test]
+    fn bounds() {
+        let (bytes, section) = file();
+        assert!(section.symbols(&bytes[..bytes.len() - 1]).is_none());
+
+        let mut far = section;
+        far.sh_offset = u64::MAX - 8;
+        assert!(far.symbols(&bytes).is_none());
+
+        let empty = symtab(SectionType::Symtab, 0, 0);
+        assert_eq!(empty.symbols(&[]).unwrap().count(), 0);
+    }
+}
+
+mod named {
+    use super::*;
+
+    const SYM_SIZE: usize = size_of::<Symbol>();
+    const TEXT: u64 = 0xFFFF_FFFF_8000_0000;
+    const STRINGS: &[u8] = b"\0kmain\0panic\0data\0";
+
+    fn symbol(name: u32, info: u8, value: u64, size: u64) -> Symbol {
+        Symbol {
+            st_name: name,
+            st_info: info,
+            st_other: 0,
+            st_shndx: 1,
+            st_value: value,
+            st_size: size,
+        }
+    }
+
+    /// Null section, the symbol table, then its string table
+    fn build(sh_type: SectionType, symbols: &[Symbol]) -> (Header, Vec<u8>) {
+        let mut symtab = section(0, sh_type, 0, symbols.len() * SYM_SIZE);
+        symtab.sh_entsize = SYM_SIZE as u64;
+        symtab.sh_link = 2;
+        let strtab = section(0, SectionType::Strtab, 0, STRINGS.len());
+        let mut file = File::with_sections(&[section(0, SectionType::Null, 0, 0), symtab, strtab]);
+
+        let table: Vec<u8> = symbols.iter().flat_map(|x| bytes_of(x).to_vec()).collect();
+        file.sections[1].sh_offset = file.push(&table) as u64;
+        file.sections[2].sh_offset = file.push(STRINGS) as u64;
+        return file.build();
+    }
+
+    fn symbols() -> Vec<Symbol> {
+        vec![
+            symbol(0, 0, 0, 0),
+            symbol(1, 0x12, TEXT + 0x1000, 0x40),
+            symbol(7, 0x12, TEXT + 0x1040, 0x10),
+            symbol(13, 0x11, TEXT + 0x1048, 8),
+            /* Past the end of the strings */
+            symbol(100, 0x12, TEXT + 0x2000, 0x10),
+            symbol(1, 0x12, TEXT + 0x3000, 0),
+        ]
+    }
+
+    #[test]
+    fn names() {
+        let (_, image) = build(SectionType::Symtab, &symbols());
+        let elf: Elf<Amd64> = Elf::from_bytes(&image).unwrap();
+        let names: Vec<Option<&str>> = elf.symbols().unwrap().map(|(_, name)| name).collect();
+        assert_eq!(
+            names,
+            [
+                Some(""),
+                Some("kmain"),
+                Some("panic"),
+                Some("data"),
+                None,
+                Some("kmain")
+            ]
+        );
+        assert_eq!(elf.symbols().unwrap().strtab().as_bytes(), STRINGS);
+
+        /* Stripped files still have the dynamic ones */
+        let (_, image) = build(SectionType::Dynsym, &symbols());
+        let elf: Elf<Amd64> = Elf::from_bytes(&image).unwrap();
+        assert_eq!(elf.symbols().unwrap().count(), 6);
+    }
+
+    #[test]
+    fn resolve_address() {
+        let (_, image) = build(SectionType::Symtab, &symbols());
+        let elf: Elf<Amd64> = Elf::from_bytes(&image).unwrap();
+        let symbols = elf.symbols().unwrap();
+
+        assert_eq!(symbols.resolve_address(TEXT + 0x1000), Some(("kmain", 0)));
+        assert_eq!(symbols.resolve_address(TEXT + 0x103f), Some(("kmain", 0x3f)));
+        /* The object inside `panic` doesn't count */
+        assert_eq!(symbols.resolve_address(TEXT + 0x1048), Some(("panic", 8)));
+        assert_eq!(symbols.resolve_address(TEXT + 0x1050), None);
+        /* Nameless and sizeless functions */
+        assert_eq!(symbols.resolve_address(TEXT + 0x2000), None);
+        assert_eq!(symbols.resolve_address(TEXT + 0x3000), None);
+        assert_eq!(symbols.resolve_address(0), None);
+    }
+
+    #[test]
+    fn broken_links() {
+        let (header, mut file) = build(SectionType::Symtab, &symbols());
+        let symtab = header.section(&file, 1).unwrap().unwrap();
+        assert!(Symbols::new(&header, &file, &symtab).is_some());
+
+        let mut unlinked = symtab;
+        unlinked.sh_link = 7;
+        assert!(Symbols::new(&header, &file, &unlinked).is_none());
+        let mut to_itself = symtab;
+        to_itself.sh_link = 1;
+        assert!(Symbols::new(&header, &file, &to_itself).is_none());
+
+        /* String table cut off by the end of the file */
+        let len = file.len();
+        file.truncate(len - 1);
+        assert!(Symbols::new(&header, &file, &symtab).is_none());
+    }
+}
+
+mod relocations {
+    use super::*;
+
+    const RELA_SIZE: usize = size_of::<Rela>();
+    const BIAS: u64 = 0xFFFF_FFFF_8000_0000;
+
+    fn rela(offset: u64, sym: u32, r_type: u32, addend: i64) -> Rela {
+        Rela {
+            r_offset: offset,
+            r_info: (sym as u64) << 32 | r_type as u64,
+            r_addend: addend,
+        }
+    }
+
+    fn file(relocations: &[Rela]) -> (Vec<u8>, SectionHeader) {
+        let section = SectionHeader {
+            sh_flags: SHF_ALLOC,
+            sh_addralign: 8,
+            sh_entsize: RELA_SIZE as u64,
+            ..common::section(0, SectionType::Rela, 1, relocations.len() * RELA_SIZE)
+        };
+        return (unaligned_table(relocations), section);
+    }
+
+    fn u64_at(image: &[u8], offset: usize) -> u64 {
+        let mut x = [0u8; 8];
+        x.copy_from_slice(&image[offset..offset + 8]);
+        u64::from_le_bytes(x)
+    }
+    #[test]
+    fn info() {
+        let r = rela(0x1000, 7, 1, 0);
+        assert_eq!(r.symbol(), 7);
+        assert_eq!(r.rel_type(), 1);
+        let r = rela(0, u32::MAX, R_X86_64_RELATIVE, -1);
+        assert_eq!(r.symbol(), u32::MAX);
+        assert_eq!(r.rel_type(), R_X86_64_RELATIVE);
+    }
+
+    #[test]
+    fn section() {
+        let relocations = [
+            rela(0x10, 0, R_X86_64_RELATIVE, 0x2000),
+            rela(0x18, 0, R_X86_64_RELATIVE, 0x3000),
+        ];
+        let (bytes, section) = file(&relocations);
+        let offsets: Vec<u64> = section
+            .relocations(&bytes, Data::NATIVE)
+            .unwrap()
+            .map(|r| r.r_offset)
+            .collect();
+        assert_eq!(offsets, [0x10, 0x18]);
+
+        let mut symtab = section;
+        symtab.sh_type = SectionType::Symtab as u32;
+        assert!(symtab.relocations(&bytes, Data::NATIVE).is_none());
+        /* REL has no addend, its entries are smaller */
+        let mut rel = section;
+        rel.sh_entsize = 16;
+        assert!(rel.relocations(&bytes, Data::NATIVE).is_none());
+        assert!(section
+            .relocations(&bytes[..bytes.len() - 1], Data::NATIVE)
+            .is_none());
+    }
+
+    #[test]
+    fn big_endian() {
+        let mut bytes = Vec::new();
+        for x in [0x10u64, R_X86_64_RELATIVE as u64, 0x2000].iter() {
+            bytes.extend_from_slice(&x.to_be_bytes());
+        }
+        let section = SectionHeader {
+            sh_entsize: RELA_SIZE as u64,
+            ..common::section(0, SectionType::Rela, 0, RELA_SIZE)
+        };
+        let r = section
+            .relocations(&bytes, Data::Msb)
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(
+            (r.r_offset, r.rel_type(), r.r_addend),
+            (0x10, R_X86_64_RELATIVE, 0x2000)
+        );
+
+        /* The same minus the addend */
+        let rel = SectionHeader {
+            sh_entsize: size_of::<Rel>() as u64,
+            ..common::section(0, SectionType::Rel, 0, size_of::<Rel>())
+        };
+        let r = rel
+            .rel_relocations(&bytes, Data::Msb)
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!((r.r_offset, r.rel_type()), (0x10, R_X86_64_RELATIVE));
+    }
+
+    #[test]
+    fn relative() {
+        let relocations = [
+            rela(0x00, 0, R_X86_64_RELATIVE, 0x2000),
+            rela(0x08, 0, R_X86_64_NONE, 0),
+            rela(0x18, 0, R_X86_64_RELATIVE, -0x10),
+        ];
+        let (bytes, section) = file(&relocations);
+        let mut image = vec![0xAAu8; 0x20];
+
+        let applied = section.relocations(&bytes, Data::NATIVE).unwrap();
+        assert_eq!(apply_relative_relocations(&mut image, applied, BIAS), Ok(2));
+        assert_eq!(u64_at(&image, 0x00), BIAS + 0x2000);
+        assert_eq!(u64_at(&image, 0x08), 0xAAAA_AAAA_AAAA_AAAA);
+        assert_eq!(u64_at(&image, 0x18), BIAS - 0x10);
+    }
+
+    #[test]
+    fn errors() {
+        let mut image = vec![0u8; 0x20];
+
+        /* R_X86_64_64 needs the symbol table */
+        let unsupported = [
+            rela(0x00, 0, R_X86_64_RELATIVE, 0x2000),
+            rela(0x08, 3, 1, 0),
+        ];
+        assert_eq!(
+            apply_relative_relocations(&mut image, unsupported.iter().copied(), BIAS),
+            Err(RelocationError::Unsupported {
+                r_offset: 0x08,
+                r_type: 1
+            })
+        );
+
+        for &offset in [0x19, 0x20, u64::MAX - 4].iter() {
+            let outside = [rela(offset, 0, R_X86_64_RELATIVE, 0)];
+            assert_eq!(
+                apply_relative_relocations(&mut image, outside.iter().copied(), BIAS),
+                Err(RelocationError::OutOfBounds { r_offset: offset })
+            );
+        }
+    }
+
+    fn rel(offset: u64, r_type: u32) -> Rel {
+        Rel {
+            r_offset: offset,
+            r_info: r_type as u64,
+        }
+    }
+
+    #[test]
+    fn implicit_addends() {
+        let relocations = [rel(0x00, R_X86_64_RELATIVE), rel(0x10, R_X86_64_RELATIVE)];
+        let section = SectionHeader {
+            sh_entsize: size_of::<Rel>() as u64,
+            ..common::section(0, SectionType::Rel, 1, 2 * size_of::<Rel>())
+        };
+        let bytes = unaligned_table(&relocations);
+        let table = section.rel_relocations(&bytes, Data::NATIVE).unwrap();
+        assert!(section.relocations(&bytes, Data::NATIVE).is_none());
+
+        let mut image = vec![0u8; 0x18];
+        image[0x00..0x08].copy_from_slice(&0x2000u64.to_le_bytes());
+        image[0x10..0x18].copy_from_slice(&(-0x10i64).to_le_bytes());
+        assert_eq!(apply_relative_relocations(&mut image, table, BIAS), Ok(2));
+        assert_eq!(u64_at(&image, 0x00), BIAS + 0x2000);
+        assert_eq!(u64_at(&image, 0x10), BIAS - 0x10);
+
+        /* The last one straddles the end, the first is already applied */
+        let straddling = [rel(0x00, R_X86_64_RELATIVE), rel(0x14, R_X86_64_RELATIVE)];
+        let mut image = vec![0u8; 0x18];
+        assert_eq!(
+            apply_relative_relocations(&mut image, straddling.iter().copied(), BIAS),
+            Err(RelocationError::OutOfBounds { r_offset: 0x14 })
+        );
+        assert_eq!(u64_at(&image, 0x00), BIAS);
+
+        let unsupported = [rel(0x00, R_X86_64_GLOB_DAT)];
+        assert_eq!(
+            apply_relative_relocations(&mut image, unsupported.iter().copied(), BIAS),
+            Err(RelocationError::Unsupported {
+                r_offset: 0,
+                r_type: R_X86_64_GLOB_DAT
+            })
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Bounds-checked reads out of byte slices, shared by the parsers of
+//! binary formats (ACPI tables, ELF notes, CPIO archives, ...) so that
+//! none of them indexes a slice it didn't check the length of
+
+/// A read past the end of the slice
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    /// Where the read started, from the start of the slice
+    pub offset: usize,
+    /// Bytes it wanted
+    pub len: usize,
+}
+
+/// Cursor over a byte slice. Reads either move the cursor, or are `_at`
+/// an offset from the start of the slice and leave it where it is. A
+/// failed read doesn't move it either.
+#[derive(Clone, Copy, Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+macro_rules! ints {
+    ($($t:ident: $le:ident $be:ident $le_at:ident $be_at:ident;)*) => {$(
+        pub fn $le(&mut self) -> Result<$t, Error> {
+            return Ok($t::from_le_bytes(self.array()?));
+        }
+
+        pub fn $be(&mut self) -> Result<$t, Error> {
+            return Ok($t::from_be_bytes(self.array()?));
+        }
+
+        pub fn $le_at(&self, offset: usize) -> Result<$t, Error> {
+            return Ok($t::from_le_bytes(self.array_at(offset)?));
+        }
+
+        pub fn $be_at(&self, offset: usize) -> Result<$t, Error> {
+            return Ok($t::from_be_bytes(self.array_at(offset)?));
+        }
+    )*};
+}
+
+impl<'a> Reader<'a> {
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    /// Offset of the cursor
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Bytes after the cursor
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    /// What's after the cursor
+    pub fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.pos..]
+    }
+
+    /// `n` bytes at `offset`
+    pub fn read_at(&self, offset: usize, n: usize) -> Result<&'a [u8], Error> {
+        let error = Error { offset, len: n };
+        let end = offset.checked_add(n).ok_or(error)?;
+        return self.bytes.get(offset..end).ok_or(error);
+    }
+
+    /// The next `n` bytes
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let bytes = self.read_at(self.pos, n)?;
+        self.pos += n;
+        return Ok(bytes);
+    }
+
+    pub fn skip(&mut self, n: usize) -> Result<(), Error> {
+        self.take(n)?;
+        return Ok(());
+    }
+
+    fn array_at<const N: usize>(&self, offset: usize) -> Result<[u8; N], Error> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.read_at(offset, N)?);
+        return Ok(out);
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let out = self.array_at(self.pos)?;
+        self.pos += N;
+        return Ok(out);
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        return Ok(self.array::<1>()?[0]);
+    }
+
+    pub fn u8_at(&self, offset: usize) -> Result<u8, Error> {
+        return Ok(self.array_at::<1>(offset)?[0]);
+    }
+
+    ints! {
+        u16: u16_le u16_be u16_le_at u16_be_at;
+        u32: u32_le u32_be u32_le_at u32_be_at;
+        u64: u64_le u64_be u64_le_at u64_be_at;
+    }
+}
@@ -0,0 +1,147 @@
+//! Formatting conventions shared by all the libs:
+//! * addresses as `0x0000_0000_0020_0000`
+//! * sizes humanized, e.g. `4 KiB`, `1.5 MiB`
+//! * flags as pipe-separated names, `present | writable`
+//! * enums by name with raw value in parens, `Load (0x1)`
+
+use core::fmt;
+
+/// Formats an address as 16 hex digits with underscores every 4 nibbles
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Addr(pub u64);
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let x = self.0;
+        write!(
+            f,
+            "0x{:04x}_{:04x}_{:04x}_{:04x}",
+            (x >> 48) & 0xffff,
+            (x >> 32) & 0xffff,
+            (x >> 16) & 0xffff,
+            x & 0xffff,
+        )
+    }
+}
+
+impl fmt::Debug for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Formats a size in bytes using the largest binary unit that fits,
+/// with one (truncated) decimal digit if it is not exact
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Size(pub u64);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && self.0 >> (10 * (unit + 1)) != 0 {
+            unit += 1;
+        }
+
+        let whole = self.0 >> (10 * unit);
+        if unit == 0 {
+            return write!(f, "{} {}", whole, UNITS[unit]);
+        }
+
+        let rest = self.0 & ((1u64 << (10 * unit)) - 1);
+        if rest == 0 {
+            return write!(f, "{} {}", whole, UNITS[unit]);
+        }
+
+        let tenths = ((rest as u128 * 10) >> (10 * unit)) as u64;
+        return write!(f, "{}.{} {}", whole, tenths, UNITS[unit]);
+    }
+}
+
+impl fmt::Debug for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Formats bytes 16 per line, each line starting with the address
+/// of its first byte and ending with the printable ASCII characters:
+/// `0x0000_0000_0000_1000  48 69 00 ...  |Hi.|`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct HexDump<'a> {
+    pub addr: u64,
+    pub bytes: &'a [u8],
+}
+
+impl fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, line) in self.bytes.chunks(16).enumerate() {
+            let addr = self.addr.wrapping_add(16 * i as u64);
+            write!(f, "{} ", Addr(addr))?;
+            for j in 0..16 {
+                /* Extra gap between the two halves */
+                if j % 8 == 0 {
+                    write!(f, " ")?;
+                }
+                match line.get(j) {
+                    Some(byte) => write!(f, "{:02x} ", byte)?,
+                    None => write!(f, "   ")?,
+                }
+            }
+
+            write!(f, " |")?;
+            for &byte in line {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f, "|")?;
+        }
+        return Ok(());
+    }
+}
+
+/// Writes `name (0xraw)`, the format used for enums
+pub fn write_enum(f: &mut fmt::Formatter<'_>, name: &str, raw: u64) -> fmt::Result {
+    write!(f, "{} ({:#x})", name, raw)
+}
+
+/// Defines a fieldless enum, implements `Debug` for it as `Name (0xraw)`
+/// and adds `name()` returning just the name of the variant
+#[macro_export]
+macro_rules! debug_enum {
+    {
+        $(#[$attr:meta])*
+        $vis:vis enum $enumname:ident {$(
+            $(#[$vattr:meta])*
+            $variant:ident $(= $value:expr)?,
+        )*}
+    } => {
+        $(#[$attr])*
+        $vis enum $enumname {$(
+            $(#[$vattr])*
+            $variant $(= $value)?,
+        )*}
+
+        impl $enumname {
+            pub const fn name(&self) -> &'static str {
+                match self {$(
+                    Self::$variant => stringify!($variant),
+                )*}
+            }
+        }
+
+        impl ::core::fmt::Debug for $enumname {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                let raw = match self {$(
+                    Self::$variant => Self::$variant as u64,
+                )*};
+                $crate::fmt::write_enum(f, self.name(), raw)
+            }
+        }
+    }
+}
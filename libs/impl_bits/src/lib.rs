@@ -3,6 +3,9 @@
 #[doc(hidden)]
 pub use paste;
 
+pub mod bytes;
+pub mod fmt;
+
 #[macro_export]
 macro_rules! impl_bits {
     {
@@ -12,6 +15,11 @@ macro_rules! impl_bits {
         )*}
     } => {
         impl $structname {
+            /// Name and bit of every flag, in declaration order
+            pub const FLAGS: &'static [(&'static str, u32)] = &[
+                $((stringify!($fname), $bit),)*
+            ];
+
             #[inline(always)]
             const fn __with_all_flags() -> Self {
                 Self(0 $(| (1<<$bit))*)
@@ -47,16 +55,22 @@ macro_rules! impl_bits {
         }
         impl ::core::fmt::Debug for $structname {
             fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-                f.write_str("[")?;
+                let mut first = true;
 
                 $(
                 if self.$fname() {
-                    let bitname = concat!(stringify!($fname), ", ");
-                    f.write_str(bitname)?;
+                    if !first {
+                        f.write_str(" | ")?;
+                    }
+                    f.write_str(stringify!($fname))?;
+                    first = false;
                 }
                 )*
 
-                f.write_str("]")
+                if first {
+                    f.write_str("(empty)")?;
+                }
+                return Ok(());
             }
         }
     }
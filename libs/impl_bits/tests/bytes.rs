@@ -0,0 +1,97 @@
+use impl_bits::bytes::{Error, Reader};
+
+const BYTES: [u8; 16] = [
+    0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10,
+];
+
+#[test]
+fn integers() {
+    let mut r = Reader::new(&BYTES);
+    assert_eq!(r.u8(), Ok(0x01));
+    assert_eq!(r.u16_le(), Ok(0x0302));
+    assert_eq!(r.u16_be(), Ok(0x0405));
+    assert_eq!(r.u8(), Ok(0x06));
+    assert_eq!(r.u32_le(), Ok(0x0a09_0807));
+    assert_eq!(r.position(), 10);
+
+    let mut r = Reader::new(&BYTES);
+    assert_eq!(r.u64_le(), Ok(0x0807_0605_0403_0201));
+    assert_eq!(r.u64_be(), Ok(0x090a_0b0c_0d0e_0f10));
+    assert_eq!(r.remaining(), 0);
+
+    /* Absolute reads don't care about the cursor, nor move it */
+    assert_eq!(r.u32_be_at(0), Ok(0x0102_0304));
+    assert_eq!(r.u32_le_at(12), Ok(0x100f_0e0d));
+    assert_eq!(r.u16_be_at(1), Ok(0x0203));
+    assert_eq!(r.u16_le_at(14), Ok(0x100f));
+    assert_eq!(r.u64_be_at(8), Ok(0x090a_0b0c_0d0e_0f10));
+    assert_eq!(r.u64_le_at(0), Ok(0x0807_0605_0403_0201));
+    assert_eq!(r.u8_at(15), Ok(0x10));
+    assert_eq!(r.position(), 16);
+}
+
+#[test]
+fn slices() {
+    let mut r = Reader::new(&BYTES);
+    assert_eq!(r.take(3), Ok(&BYTES[..3]));
+    assert_eq!(r.skip(4), Ok(()));
+    assert_eq!(r.rest(), &BYTES[7..]);
+    assert_eq!(r.take(0), Ok(&[][..]));
+    assert_eq!(r.read_at(2, 2), Ok(&BYTES[2..4]));
+    assert_eq!(r.read_at(16, 0), Ok(&[][..]));
+    assert_eq!(r.take(9), Ok(&BYTES[7..]));
+    assert_eq!(r.rest(), &[]);
+}
+
+#[test]
+fn errors() {
+    let mut r = Reader::new(&BYTES);
+    r.skip(14).unwrap();
+    assert_eq!(r.u32_le(), Err(Error { offset: 14, len: 4 }));
+    assert_eq!(r.take(3), Err(Error { offset: 14, len: 3 }));
+    assert_eq!(r.skip(3), Err(Error { offset: 14, len: 3 }));
+    /* Failed reads leave the cursor alone */
+    assert_eq!(r.u16_be(), Ok(0x0f10));
+    assert_eq!(r.u8(), Err(Error { offset: 16, len: 1 }));
+
+    assert_eq!(r.u64_le_at(9), Err(Error { offset: 9, len: 8 }));
+    assert_eq!(r.u8_at(16), Err(Error { offset: 16, len: 1 }));
+    assert_eq!(r.read_at(usize::MAX, 2), Err(Error { offset: usize::MAX, len: 2 }));
+    assert_eq!(r.read_at(1, usize::MAX), Err(Error { offset: 1, len: usize::MAX }));
+}
+
+/* Every read on every prefix of the input, none may panic and each has
+ * to fail exactly when it would run past the end */
+#[test]
+fn truncated_inputs() {
+    for len in 0..=BYTES.len() {
+        let bytes = &BYTES[..len];
+        for start in 0..=len {
+            let mut r = Reader::new(bytes);
+            r.skip(start).unwrap();
+            let left = len - start;
+            let copy = || r;
+
+            assert_eq!(copy().u8().is_ok(), left >= 1);
+            assert_eq!(copy().u16_le().is_ok(), left >= 2);
+            assert_eq!(copy().u16_be().is_ok(), left >= 2);
+            assert_eq!(copy().u32_le().is_ok(), left >= 4);
+            assert_eq!(copy().u32_be().is_ok(), left >= 4);
+            assert_eq!(copy().u64_le().is_ok(), left >= 8);
+            assert_eq!(copy().u64_be().is_ok(), left >= 8);
+            for n in 0..=BYTES.len() + 1 {
+                assert_eq!(copy().take(n).is_ok(), n <= left);
+                assert_eq!(r.read_at(start, n).is_ok(), n <= left);
+            }
+
+            assert_eq!(r.u16_le_at(start).is_ok(), left >= 2);
+            assert_eq!(r.u32_be_at(start).is_ok(), left >= 4);
+            assert_eq!(r.u64_le_at(start).is_ok(), left >= 8);
+
+            /* Reading until it fails ends exactly at the end */
+            while r.u32_le().is_ok() {}
+            while r.u8().is_ok() {}
+            assert_eq!(r.position(), len);
+        }
+    }
+}
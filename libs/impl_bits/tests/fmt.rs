@@ -0,0 +1,87 @@
+use impl_bits::fmt::{Addr, HexDump, Size};
+use impl_bits::{debug_enum, impl_bits};
+
+struct Flags(u32);
+
+impl_bits! {
+    Flags = {
+        present = 0,
+        writable = 1,
+        nx = 31,
+    }
+}
+
+debug_enum! {
+    #[derive(Clone, Copy)]
+    #[repr(u16)]
+    enum Color {
+        Red = 1,
+        Green,
+        Blue = 0x20,
+    }
+}
+
+#[test]
+fn addr() {
+    assert_eq!(format!("{}", Addr(0)), "0x0000_0000_0000_0000");
+    assert_eq!(format!("{:?}", Addr(0x20_0000)), "0x0000_0000_0020_0000");
+    assert_eq!(
+        format!("{}", Addr(0xffff_ffff_c000_1234)),
+        "0xffff_ffff_c000_1234"
+    );
+}
+
+#[test]
+fn size() {
+    assert_eq!(format!("{}", Size(0)), "0 B");
+    assert_eq!(format!("{}", Size(1023)), "1023 B");
+    assert_eq!(format!("{}", Size(4096)), "4 KiB");
+    assert_eq!(format!("{}", Size(1536)), "1.5 KiB");
+    assert_eq!(format!("{}", Size(2 << 20)), "2 MiB");
+    assert_eq!(format!("{}", Size((3 << 30) + (1 << 29))), "3.5 GiB");
+    assert_eq!(format!("{}", Size(u64::MAX)), "15.9 EiB");
+}
+
+#[test]
+fn flags() {
+    assert_eq!(format!("{:?}", Flags(0)), "(empty)");
+    assert_eq!(format!("{:?}", Flags(0).set_present()), "present");
+    let all = Flags(0).set_present().set_writable().set_nx();
+    assert_eq!(format!("{:?}", all), "present | writable | nx");
+}
+
+#[test]
+fn enums() {
+    assert_eq!(format!("{:?}", Color::Red), "Red (0x1)");
+    assert_eq!(format!("{:?}", Color::Green), "Green (0x2)");
+    assert_eq!(format!("{:?}", Color::Blue), "Blue (0x20)");
+    assert_eq!(Color::Blue.name(), "Blue");
+}
+
+#[test]
+fn hexdump() {
+    let bytes: Vec<u8> = (0x3c..0x50).collect();
+    let dump = HexDump {
+        addr: 0x1000,
+        bytes: &bytes,
+    };
+    assert_eq!(
+        dump.to_string(),
+        "0x0000_0000_0000_1000  3c 3d 3e 3f 40 41 42 43  44 45 46 47 48 49 4a 4b  |<=>?@ABCDEFGHIJK|\n\
+         0x0000_0000_0000_1010  4c 4d 4e 4f                                       |LMNO|\n"
+    );
+
+    let dump = HexDump {
+        addr: 0,
+        bytes: b"a\0\xff ",
+    };
+    assert!(dump.to_string().ends_with("|a.. |\n"));
+    assert_eq!(
+        HexDump {
+            addr: 0,
+            bytes: &[]
+        }
+        .to_string(),
+        ""
+    );
+}
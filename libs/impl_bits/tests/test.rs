@@ -31,3 +31,12 @@ fn one() {
     assert!(!f.two());
     assert!(!f.three());
 }
+
+#[test]
+fn flags() {
+    let names: Vec<_> = Flags::FLAGS.iter().map(|&(name, _)| name).collect();
+    assert_eq!(names, ["one", "two", "three", "zero"]);
+    for &(_, bit) in Flags::FLAGS {
+        assert!(Flags::with_all_flags().0 & (1 << bit) != 0);
+    }
+}
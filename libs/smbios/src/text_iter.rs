@@ -3,7 +3,8 @@
 ///
 /// Note that this iterator is not fused, meaning that if it is used
 /// after hitting double-null terminator and returning None, it can
-/// return "garbage" slice
+/// return "garbage" slice. Strings run to the end of `slice` at most, one
+/// without its null terminator ends iteration like the double null does.
 pub struct TextIterator<'a> {
     pub slice: &'a [u8],
 }
@@ -12,19 +13,8 @@ impl<'a> Iterator for TextIterator<'a> {
     type Item = &'a [u8];
 
     fn next(&mut self) -> Option<Self::Item> {
-        let n = self
-            .slice
-            .iter()
-            .position(|&x| x == 0)
-            .expect("smbios::TextIterator - can't find null terminator");
-
-        /* SAFETY: n must be in range [0, slice.len()) */
-        let (text, rest) = unsafe {
-            (
-                self.slice.get_unchecked(..n),
-                self.slice.get_unchecked(n + 1..),
-            )
-        };
+        let n = self.slice.iter().position(|&x| x == 0)?;
+        let (text, rest) = (&self.slice[..n], &self.slice[n + 1..]);
 
         if text.len() == 0 {
             return None;
@@ -0,0 +1,24 @@
+use smbios::TextIterator;
+
+#[test]
+fn strings() {
+    let strings = TextIterator {
+        slice: b"American Megatrends\0Version 1.0\0\0\x01\x1b",
+    };
+    let strings: Vec<&[u8]> = strings.collect();
+    assert_eq!(strings, [&b"American Megatrends"[..], b"Version 1.0"]);
+
+    assert_eq!(TextIterator { slice: b"\0\0" }.count(), 0);
+}
+
+#[test]
+fn missing_terminator() {
+    /* A structure cut short ends the strings instead of panicking */
+    let set = b"American Megatrends\0Version 1.0\0\0";
+    for len in 0..set.len() {
+        let strings = TextIterator { slice: &set[..len] };
+        assert!(strings.count() <= 2, "{} bytes", len);
+    }
+    let strings = TextIterator { slice: b"one\0tw" };
+    assert!(strings.eq([&b"one"[..]]));
+}
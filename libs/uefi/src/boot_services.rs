@@ -7,7 +7,7 @@ pub struct BootServices {
     pub raise_tpl: usize,
     pub restore_tpl: usize,
 
-    pub allocate_pages: usize,
+    allocate_pages: Option<extern "efiapi" fn(AllocateType, u32, usize, &mut u64) -> RawStatus>,
     pub free_pages: usize,
 
     /// Parameters
@@ -80,19 +80,21 @@ pub struct BootServices {
     >,
 
     pub allocate_pool: usize,
-    pub free_pool: usize,
+    free_pool: Option<extern "efiapi" fn(*mut u8) -> RawStatus>,
 
-    pub create_event: usize,
-    pub set_timer: usize,
-    pub wait_for_event: usize,
+    create_event:
+        Option<extern "efiapi" fn(u32, usize, usize, usize, &mut Event) -> RawStatus>,
+    set_timer: Option<extern "efiapi" fn(Event, TimerDelay, u64) -> RawStatus>,
+    wait_for_event:
+        Option<extern "efiapi" fn(usize, *const Event, &mut usize) -> RawStatus>,
     pub signal_event: usize,
-    pub close_event: usize,
+    close_event: Option<extern "efiapi" fn(Event) -> RawStatus>,
     pub check_event: usize,
 
     pub install_proto_interface: usize,
     pub reinstall_proto_interface: usize,
     pub uninstall_proto_interface: usize,
-    pub handle_protocol: usize,
+    handle_protocol: Option<extern "efiapi" fn(usize, &Guid, &mut *const u8) -> RawStatus>,
     __reserved: usize,
     pub register_protocol_notify: usize,
     pub locate_handle: usize,
@@ -133,18 +135,36 @@ pub struct BootServices {
     /// A UEFI OS loader should not make calls to any boot service function other
     /// than GetMemoryMap() after the first call to ExitBootServices().
     exit_boot_services: Option<extern "efiapi" fn(ImageHandle, memory::MapKey) -> RawStatus>,
+    pub get_next_monotonic_count: usize,
+    /// Busy-waits at least given number of microseconds
+    stall: Option<extern "efiapi" fn(usize) -> RawStatus>,
+    /// Arms the watchdog, which resets the machine if it isn't re-armed
+    /// or disarmed in time. Firmware arms it for 5 minutes before starting
+    /// an image and disarms it in ExitBootServices.
+    set_watchdog_timer: Option<extern "efiapi" fn(usize, u64, usize, *const u16) -> RawStatus>,
+    /// Connects drivers to a controller, recursively to every child
+    /// controller it produces if the last argument is true
+    connect_controller: Option<extern "efiapi" fn(usize, *const usize, *const u8, bool) -> RawStatus>,
+    pub disconnect_controller: usize,
+    pub open_protocol: usize,
+    pub close_protocol: usize,
+    pub open_protocol_info: usize,
+    pub protocols_per_handle: usize,
+    /// Returns handles in a pool buffer the caller frees
+    locate_handle_buffer: Option<
+        extern "efiapi" fn(
+            LocateSearchType,
+            *const Guid,
+            *const u8,
+            &mut usize,
+            &mut *mut Handle,
+        ) -> RawStatus,
+    >,
+
+    /// Returns the first interface of protocol `guid`, no matter which
+    /// handle it is installed on. Registration key is unused.
+    locate_protocol: Option<extern "efiapi" fn(&Guid, *const u8, &mut *const u8) -> RawStatus>,
     /*
-    get_next_monotonic_count: usize,
-    stall: usize,
-    set_watchdog_timer: usize,
-    connect_controller: usize,
-    disconnect_controller: usize,
-    open_protocol: usize,
-    close_protocol: usize,
-    open_protocol_info: usize,
-    protocols_per_handle: usize,
-    locate_handle_buffer: usize,
-    locate_protocol: usize,
     install_multiple_protocol_interfaces: usize,
     uninstall_multiple_protocol_interfaces: usize,
 
@@ -162,6 +182,26 @@ impl BootServices {
         buf: &'buf mut [MaybeUninit<u64>],
     ) -> Result<(memory::MapKey, memory::DescriptorIterator), Error> {
         let mut size: usize = core::mem::size_of_val(buf);
+        let (key, descriptor_size) = self.raw_get_memory_map(buf, &mut size)?;
+
+        let init_size = size / core::mem::size_of::<u64>();
+        let init_buffer: *mut [MaybeUninit<u64>] = &mut buf[..init_size];
+        let init_buffer = init_buffer as *mut [u64];
+
+        unsafe {
+            let iter = memory::DescriptorIterator::new(&*init_buffer, descriptor_size);
+            return Ok((key, iter));
+        }
+    }
+
+    /// GetMemoryMap into `buf`, `size` is the size of `buf` on input and
+    /// the size of the map on output, also on `Error::BufferTooSmall`.
+    /// Returns the map key and descriptor size.
+    pub(crate) fn raw_get_memory_map(
+        &self,
+        buf: &mut [MaybeUninit<u64>],
+        size: &mut usize,
+    ) -> Result<(memory::MapKey, usize), Error> {
         let mut key = memory::MapKey(0xDEAD_BEEF);
         let mut descriptor_size = 0usize;
         let mut descriptor_version = 0u32;
@@ -170,7 +210,7 @@ impl BootServices {
             .get_memory_map
             .expect("buggy UEFI: get_memory_map is null");
         let status = (get_memory_map)(
-            &mut size,
+            size,
             buf.as_mut_ptr() as *mut memory::Descriptor,
             &mut key,
             &mut descriptor_size,
@@ -192,15 +232,237 @@ impl BootServices {
         }
 
         assert_eq!(status.0, 0);
+        return Ok((key, descriptor_size));
+    }
 
-        let init_size = size / core::mem::size_of::<u64>();
-        let init_buffer: *mut [MaybeUninit<u64>] = &mut buf[..init_size];
-        let init_buffer = init_buffer as *mut [u64];
+    /// Allocates `pages` 4KiB pages of `memory_type` memory, `addr` is
+    /// the maximum or exact address for `AllocateType::MaxAddress`
+    /// and `AllocateType::Address`. Returns physical address of the first page.
+    pub fn allocate_pages(
+        &self,
+        typ: AllocateType,
+        memory_type: memory::Type,
+        pages: usize,
+        addr: u64,
+    ) -> Result<u64, Error> {
+        let allocate_pages = self
+            .allocate_pages
+            .expect("buggy UEFI: allocate_pages is null");
 
-        unsafe {
-            let iter = memory::DescriptorIterator::new(&*init_buffer, descriptor_size);
-            return Ok((key, iter));
+        let mut addr = addr;
+        let status = (allocate_pages)(typ, memory_type as u32, pages, &mut addr);
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(addr);
+    }
+
+    /// Queries `handle` for the protocol identified by `guid`.
+    ///
+    /// # Safety
+    /// * `T` must be the interface structure of the protocol
+    pub unsafe fn handle_protocol<T>(&self, handle: &Handle, guid: &Guid) -> Result<&T, Error> {
+        let handle_protocol = self
+            .handle_protocol
+            .expect("buggy UEFI: handle_protocol is null");
+
+        let mut interface = core::ptr::null();
+        let status = (handle_protocol)(handle.0, guid, &mut interface);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
         }
+        if interface.is_null() {
+            return Err(Error::NotFound);
+        }
+
+        return Ok(&*(interface as *const T));
+    }
+
+    pub fn stall(&self, microseconds: usize) -> Result<(), Error> {
+        let stall = self.stall.expect("buggy UEFI: stall is null");
+        let status = (stall)(microseconds);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    /// Timer event without a notification function, for `wait_for_event`
+    pub fn create_timer_event(&self) -> Result<Event, Error> {
+        let create_event = self
+            .create_event
+            .expect("buggy UEFI: create_event is null");
+
+        let mut event = Event(0);
+        let status = (create_event)(EVT_TIMER, 0, 0, 0, &mut event);
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(event);
+    }
+
+    /// Arms or cancels `event`, `us` is rounded to 100ns units
+    pub fn set_timer(&self, event: Event, delay: TimerDelay, us: u64) -> Result<(), Error> {
+        let set_timer = self.set_timer.expect("buggy UEFI: set_timer is null");
+        let status = (set_timer)(event, delay, us.saturating_mul(10));
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    /// Blocks until one of `events` is signaled, returns its index.
+    /// Only valid at TPL_APPLICATION, which is where the loader runs.
+    pub fn wait_for_event(&self, events: &[Event]) -> Result<usize, Error> {
+        let wait_for_event = self
+            .wait_for_event
+            .expect("buggy UEFI: wait_for_event is null");
+
+        let mut index = 0usize;
+        let status = (wait_for_event)(events.len(), events.as_ptr(), &mut index);
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+        if index >= events.len() {
+            return Err(Error::InvalidParameter);
+        }
+
+        return Ok(index);
+    }
+
+    pub fn close_event(&self, event: Event) -> Result<(), Error> {
+        let close_event = self
+            .close_event
+            .expect("buggy UEFI: close_event is null");
+        let status = (close_event)(event);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    /// Every handle supporting `protocol`, or all handles in the system.
+    /// Handles are only valid until drivers are connected or disconnected,
+    /// see `HandleSet`.
+    pub fn locate_handle_buffer(&self, protocol: Option<&Guid>) -> Result<HandleBuffer<'_>, Error> {
+        let locate_handle_buffer = self
+            .locate_handle_buffer
+            .expect("buggy UEFI: locate_handle_buffer is null");
+
+        let (typ, guid) = match protocol {
+            Some(guid) => (LocateSearchType::ByProtocol, guid as *const Guid),
+            None => (LocateSearchType::AllHandles, core::ptr::null()),
+        };
+        let mut len = 0usize;
+        let mut handles = core::ptr::null_mut();
+        let status = (locate_handle_buffer)(typ, guid, core::ptr::null(), &mut len, &mut handles);
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+        if handles.is_null() && len != 0 {
+            return Err(Error::InvalidParameter);
+        }
+
+        return Ok(HandleBuffer::new(self, handles, len));
+    }
+
+    /// Frees memory from AllocatePool or returned by firmware in pool memory
+    ///
+    /// # Safety
+    /// * `buf` must not be used afterwards
+    pub unsafe fn free_pool(&self, buf: *mut u8) -> Result<(), Error> {
+        let free_pool = self.free_pool.expect("buggy UEFI: free_pool is null");
+        let status = (free_pool)(buf);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    /// Connects every driver that supports `handle` to it
+    pub fn connect_controller(&self, handle: &Handle, recursive: bool) -> Result<(), Error> {
+        let connect_controller = self
+            .connect_controller
+            .expect("buggy UEFI: connect_controller is null");
+        let status = (connect_controller)(handle.0, core::ptr::null(), core::ptr::null(), recursive);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    /// Re-arms the watchdog for `seconds`, 0 disables it
+    pub fn set_watchdog_timer(&self, seconds: usize) -> Result<(), Error> {
+        let set_watchdog_timer = self
+            .set_watchdog_timer
+            .expect("buggy UEFI: set_watchdog_timer is null");
+        let status = (set_watchdog_timer)(seconds, 0, 0, core::ptr::null());
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    /// # Safety
+    /// * `T` must be the interface type of protocol `guid`
+    pub unsafe fn locate_protocol<T>(&self, guid: &Guid) -> Result<&T, Error> {
+        let locate_protocol = self
+            .locate_protocol
+            .expect("buggy UEFI: locate_protocol is null");
+
+        let mut interface = core::ptr::null();
+        let status = (locate_protocol)(guid, core::ptr::null(), &mut interface);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+        if interface.is_null() {
+            return Err(Error::NotFound);
+        }
+
+        return Ok(&*(interface as *const T));
+    }
+
+    /// EFI_RNG_PROTOCOL, if the platform has one
+    pub fn rng(&self) -> Result<&RngProtocol, Error> {
+        unsafe { self.locate_protocol(&Guid::EFI_RNG_PROTOCOL) }
+    }
+
+    /// First EFI_SERIAL_IO_PROTOCOL, if the platform has one.
+    /// Only usable until ExitBootServices.
+    pub fn serial_io(&self) -> Result<&SerialIoProtocol, Error> {
+        unsafe { self.locate_protocol(&Guid::EFI_SERIAL_IO_PROTOCOL) }
+    }
+
+    /// EFI_SECURITY2_ARCH_PROTOCOL, present on firmware that can enforce
+    /// Secure Boot
+    pub fn security2(&self) -> Result<&Security2Protocol, Error> {
+        unsafe { self.locate_protocol(&Guid::EFI_SECURITY2_ARCH_PROTOCOL) }
+    }
+
+    /// SHIM_LOCK_PROTOCOL, if we were started by shim
+    pub fn shim_lock(&self) -> Result<&ShimLock, Error> {
+        unsafe { self.locate_protocol(&Guid::SHIM_LOCK_PROTOCOL) }
+    }
+
+    /// EFI_LOADED_IMAGE_PROTOCOL of given image
+    pub fn loaded_image(&self, image: &ImageHandle) -> Result<&LoadedImage, Error> {
+        unsafe { self.handle_protocol(&image.0, &Guid::EFI_LOADED_IMAGE_PROTOCOL) }
     }
 
     /// Terminates boot services.
@@ -210,15 +472,17 @@ impl BootServices {
     /// Several fields of the EFI System Table should be set to 0, like `console_in_handle`,
     /// `con_in` and similar and also `boot_services`. Also, since the table is changed CRC
     /// checksum must be recomputed.
+    /// If it fails with `Error::InvalidParameter`, the memory map has changed
+    /// and must be retrieved again before retrying.
     pub unsafe fn exit_boot_services(
         &self,
-        handle: ImageHandle,
+        handle: &ImageHandle,
         key: memory::MapKey,
     ) -> Result<(), Error> {
         let exit_bservices = self
             .exit_boot_services
             .expect("buggy UEFI: exit_boot_services is null");
-        let status = (exit_bservices)(handle, key);
+        let status = (exit_bservices)(ImageHandle(Handle((handle.0).0)), key);
 
         assert_eq!(status.get_efi_warning(), None);
         if let Some(err) = status.get_efi_error() {
@@ -230,6 +494,15 @@ impl BootServices {
     }
 }
 
+/// EFI_ALLOCATE_TYPE
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum AllocateType {
+    AnyPages = 0,
+    MaxAddress,
+    Address,
+}
+
 impl Verify for BootServices {
     const SIGNATURE: u64 = 0x56524553544f4f42;
     fn get_header(&self) -> &TableHeader {
@@ -0,0 +1,95 @@
+use super::*;
+
+/// The only `fw_resource_version` defined so far
+pub const ESRT_VERSION: u64 = 1;
+
+/// EFI_SYSTEM_RESOURCE_TABLE header, `fw_resource_count` entries follow
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct EsrtHeader {
+    pub fw_resource_count: u32,
+    pub fw_resource_count_max: u32,
+    pub fw_resource_version: u64,
+}
+
+/// EFI_SYSTEM_RESOURCE_ENTRY, a firmware component updatable by capsule
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct EsrtEntry {
+    pub fw_class: Guid,
+    /// 0 unknown, 1 system firmware, 2 device firmware, 3 UEFI driver
+    pub fw_type: u32,
+    pub fw_version: u32,
+    pub lowest_supported_fw_version: u32,
+    pub capsule_flags: u32,
+    pub last_attempt_version: u32,
+    /// 0 success, see LAST_ATTEMPT_STATUS_* in the spec for the rest
+    pub last_attempt_status: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EsrtError {
+    /// Header or entries reach past the end of the table's memory
+    Truncated,
+    /// `fw_resource_count` is over `fw_resource_count_max`
+    BadCount {
+        count: u32,
+        max: u32,
+    },
+    UnsupportedVersion(u64),
+}
+
+/// Validated ESRT, entries are read without alignment requirements
+#[derive(Clone, Copy, Debug)]
+pub struct Esrt<'a> {
+    pub header: EsrtHeader,
+    entries: &'a [u8],
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<EsrtHeader>();
+const ENTRY_SIZE: usize = core::mem::size_of::<EsrtEntry>();
+
+impl<'a> Esrt<'a> {
+    /// `bytes` starts at the table and ends where its memory does, the
+    /// counts in the header are checked against it before anything is read
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, EsrtError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(EsrtError::Truncated);
+        }
+        let header = unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const EsrtHeader) };
+
+        if header.fw_resource_version != ESRT_VERSION {
+            return Err(EsrtError::UnsupportedVersion(header.fw_resource_version));
+        }
+        if header.fw_resource_count > header.fw_resource_count_max {
+            return Err(EsrtError::BadCount {
+                count: header.fw_resource_count,
+                max: header.fw_resource_count_max,
+            });
+        }
+
+        let len = (header.fw_resource_count as usize)
+            .checked_mul(ENTRY_SIZE)
+            .ok_or(EsrtError::Truncated)?;
+        let entries = bytes
+            .get(HEADER_SIZE..)
+            .and_then(|x| x.get(..len))
+            .ok_or(EsrtError::Truncated)?;
+
+        return Ok(Self { header, entries });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() / ENTRY_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = EsrtEntry> + 'a {
+        self.entries
+            .chunks_exact(ENTRY_SIZE)
+            .map(|x| unsafe { core::ptr::read_unaligned(x.as_ptr() as *const EsrtEntry) })
+    }
+}
@@ -0,0 +1,16 @@
+/// EVT_TIMER, the event is signaled by `set_timer`
+pub const EVT_TIMER: u32 = 0x8000_0000;
+
+/// EFI_EVENT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Event(pub(crate) usize);
+
+/// EFI_TIMER_DELAY
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TimerDelay {
+    Cancel = 0,
+    Periodic,
+    Relative,
+}
@@ -0,0 +1,162 @@
+use super::*;
+
+/// EFI_FILE_MODE_READ
+const MODE_READ: u64 = 1;
+/// EFI_FILE_MODE_WRITE
+const MODE_WRITE: u64 = 2;
+/// EFI_FILE_MODE_CREATE
+const MODE_CREATE: u64 = 1 << 63;
+
+/// EFI_FILE_PROTOCOL, an open file or directory on a volume.
+/// Only what reading a whole file and writing one out needs is typed,
+/// every handle `open` and `create` return has to be `close`d.
+#[repr(C)]
+pub struct File {
+    pub revision: u64,
+    open: Option<extern "efiapi" fn(&File, &mut *const File, *const u16, u64, u64) -> RawStatus>,
+    close: Option<extern "efiapi" fn(&File) -> RawStatus>,
+    pub delete: usize,
+    read: Option<extern "efiapi" fn(&File, &mut usize, *mut u8) -> RawStatus>,
+    write: Option<extern "efiapi" fn(&File, &mut usize, *const u8) -> RawStatus>,
+    get_position: Option<extern "efiapi" fn(&File, &mut u64) -> RawStatus>,
+    set_position: Option<extern "efiapi" fn(&File, u64) -> RawStatus>,
+    pub get_info: usize,
+    pub set_info: usize,
+    flush: Option<extern "efiapi" fn(&File) -> RawStatus>,
+}
+
+impl File {
+    /// Opens `path` relative to this directory for reading.
+    /// `path` is UCS-2 with `\` as the separator and must end with a NUL.
+    pub fn open(&self, path: &[u16]) -> Result<&File, Error> {
+        return self.open_mode(path, MODE_READ);
+    }
+
+    /// Opens `path` like `open` does, for writing, creating the file if
+    /// it isn't there. An existing file is written over from the start
+    /// but not truncated.
+    pub fn create(&self, path: &[u16]) -> Result<&File, Error> {
+        return self.open_mode(path, MODE_READ | MODE_WRITE | MODE_CREATE);
+    }
+
+    fn open_mode(&self, path: &[u16], mode: u64) -> Result<&File, Error> {
+        if path.last() != Some(&0) {
+            return Err(Error::InvalidParameter);
+        }
+
+        let open = self.open.expect("buggy UEFI: open is null");
+        let mut file = core::ptr::null();
+        let status = (open)(self, &mut file, path.as_ptr(), mode, 0);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+        if file.is_null() {
+            return Err(Error::NotFound);
+        }
+
+        return Ok(unsafe { &*file });
+    }
+
+    pub fn close(&self) {
+        /* Can't fail according to the spec */
+        let close = self.close.expect("buggy UEFI: close is null");
+        (close)(self);
+    }
+
+    /// Returns how many bytes were read, 0 at the end of the file
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = self.read.expect("buggy UEFI: read is null");
+        let mut size = buf.len();
+        let status = (read)(self, &mut size, buf.as_mut_ptr());
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(size);
+    }
+
+    /// Fills `buf` from the current position, `Err(EndOfFile)` if the
+    /// file ends first. Firmware may return less than asked for, so this
+    /// keeps reading.
+    pub fn read_exact(&self, buf: &mut [u8]) -> Result<(), Error> {
+        let mut done = 0;
+        while done < buf.len() {
+            match self.read(&mut buf[done..])? {
+                0 => return Err(Error::EndOfFile),
+                n => done += n,
+            }
+        }
+        return Ok(());
+    }
+
+    /// Returns how many bytes were written
+    pub fn write(&self, buf: &[u8]) -> Result<usize, Error> {
+        let write = self.write.expect("buggy UEFI: write is null");
+        let mut size = buf.len();
+        let status = (write)(self, &mut size, buf.as_ptr());
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(size);
+    }
+
+    /// Writes all of `buf` at the current position, `write` for firmware
+    /// that takes less than given
+    pub fn write_all(&self, buf: &[u8]) -> Result<(), Error> {
+        let mut done = 0;
+        while done < buf.len() {
+            match self.write(&buf[done..])? {
+                0 => return Err(Error::DeviceError),
+                n => done += n,
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn flush(&self) -> Result<(), Error> {
+        let flush = self.flush.expect("buggy UEFI: flush is null");
+        let status = (flush)(self);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    pub fn position(&self) -> Result<u64, Error> {
+        let get_position = self.get_position.expect("buggy UEFI: get_position is null");
+        let mut position = 0;
+        let status = (get_position)(self, &mut position);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(position);
+    }
+
+    pub fn set_position(&self, position: u64) -> Result<(), Error> {
+        let set_position = self.set_position.expect("buggy UEFI: set_position is null");
+        let status = (set_position)(self, position);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    /// Size of the file, found by seeking to its end, which `u64::MAX`
+    /// does. Leaves the position at the start.
+    pub fn size(&self) -> Result<u64, Error> {
+        self.set_position(u64::MAX)?;
+        let size = self.position()?;
+        self.set_position(0)?;
+        return Ok(size);
+    }
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+/// Default for `FirmwareYield::new`, frequent enough for USB keyboards
+pub const YIELD_INTERVAL_US: u64 = 2_000;
+
+/// Gives firmware a chance to run pending timer events. `Stall(0)` returns
+/// immediately, but firmware checks its timer queue on the way.
+pub fn yield_to_firmware(boot_services: &BootServices) {
+    let _ = boot_services.stall(0);
+}
+
+/// Decides when a loop is due to yield, so that calling at every
+/// chunk boundary doesn't turn into a firmware call per chunk
+#[derive(Clone, Copy, Debug)]
+pub struct YieldLimiter<C: Clock> {
+    clock: C,
+    interval_us: u64,
+    last_us: u64,
+}
+
+impl<C: Clock> YieldLimiter<C> {
+    pub fn new(clock: C, interval_us: u64) -> Self {
+        let last_us = clock.now_us();
+        Self {
+            clock,
+            interval_us,
+            last_us,
+        }
+    }
+
+    /// True at most once every `interval_us`
+    pub fn due(&mut self) -> bool {
+        let now = self.clock.now_us();
+        if now.saturating_sub(self.last_us) < self.interval_us {
+            return false;
+        }
+        self.last_us = now;
+        return true;
+    }
+}
+
+/// Rate-limited `yield_to_firmware` for long loops before ExitBootServices.
+/// Borrows the boot services table, so it can't be kept past the scope that
+/// owns it, drop it before exiting boot services.
+pub struct FirmwareYield<'bs, C: Clock> {
+    boot_services: &'bs BootServices,
+    limiter: YieldLimiter<C>,
+}
+
+impl<'bs, C: Clock> FirmwareYield<'bs, C> {
+    pub fn new(boot_services: &'bs BootServices, clock: C) -> Self {
+        Self::with_interval(boot_services, clock, YIELD_INTERVAL_US)
+    }
+
+    pub fn with_interval(boot_services: &'bs BootServices, clock: C, interval_us: u64) -> Self {
+        Self {
+            boot_services,
+            limiter: YieldLimiter::new(clock, interval_us),
+        }
+    }
+
+    /// Call at chunk boundaries, yields only if the interval has passed
+    pub fn maybe_yield(&mut self) {
+        if self.limiter.due() {
+            yield_to_firmware(self.boot_services);
+        }
+    }
+}
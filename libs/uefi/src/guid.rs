@@ -2,30 +2,27 @@
 #[repr(C)]
 pub struct Guid(u32, u16, u16, [u8; 8]);
 
-impl core::fmt::Debug for Guid {
+/// Canonical form, e.g. `8868e871-e4f1-11d3-bc22-0080c73c8881`
+impl core::fmt::Display for Guid {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-        /*
-        let table_name: &'static str = match *self {
-            Self::MPS_TABLE => "MPS Table",
-            Self::EFI_ACPI_TABLE => "EFI ACPI Table",
-            Self::ACPI_TABLE => "ACPI Table",
-            Self::SMBIOS_TABLE => "SMBIOS Table",
-            Self::SMBIOS3_TABLE => "SMBIOS 3 Table",
-            Self::SAL_SYSTEM_TABLE => "SAL System Table",
-            _ => "",
-        };
-        */
+        let d = &self.3;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+            self.0, self.1, self.2, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7],
+        )
+    }
+}
 
+impl core::fmt::Debug for Guid {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         let table_name = self.as_str();
 
         if table_name == "Unknown" {
-            return f.write_fmt(format_args!(
-                "Guid(0x{:x}, 0x{:x}, 0x{:x}, {:?}",
-                self.0, self.1, self.2, self.3,
-            ));
+            return write!(f, "Guid({})", self);
         }
 
-        return f.debug_tuple("Guid").field(&table_name).finish();
+        return write!(f, "Guid({} {})", self, table_name);
     }
 }
 
@@ -60,6 +57,31 @@ impl_guids! {
     EFI_BOOT_MANAGER_POLICY_CONNECT_ALL =
         { 0x113B2126, 0xFC8A, 0x11E3, { 0xBD, 0x6C, 0xB8, 0xE8, 0x56, 0x2C, 0xBA, 0xFA } },
 
+    EFI_GLOBAL_VARIABLE =
+        {0x8BE4DF61,0x93CA,0x11d2, {0xAA,0x0D,0x00,0xE0,0x98,0x03,0x2B,0x8C}},
+    /* Vendor of our own variables, e.g. the A/B slot state */
+    SOVOS_VARIABLE =
+        {0x5d0a9f3c,0x8b2e,0x4c71, {0x9a,0x44,0x1e,0x6b,0x73,0x02,0xc5,0xd8}},
+
+    EFI_LOADED_IMAGE_PROTOCOL =
+        {0x5B1B31A1,0x9562,0x11d2, {0x8E,0x3F,0x00,0xA0,0xC9,0x69,0x72,0x3B}},
+
+    EFI_SIMPLE_FILE_SYSTEM_PROTOCOL =
+        {0x964e5b22,0x6459,0x11d2, {0x8e,0x39,0x00,0xa0,0xc9,0x69,0x72,0x3b}},
+    EFI_BLOCK_IO_PROTOCOL =
+        {0x964e5b21,0x6459,0x11d2, {0x8e,0x39,0x00,0xa0,0xc9,0x69,0x72,0x3b}},
+
+    EFI_RNG_PROTOCOL =
+        {0x3152BCA5,0xEADE,0x433D, {0x86,0x2E,0xC0,0x1C,0xDC,0x29,0x1F,0x44}},
+
+    EFI_SERIAL_IO_PROTOCOL =
+        {0xBB25CF6F,0xF1D4,0x11D2, {0x9A,0x0C,0x00,0x90,0x27,0x3F,0xC1,0xFD}},
+
+    EFI_SECURITY2_ARCH_PROTOCOL =
+        {0x94ab2f58,0x1438,0x4ef1, {0x91,0x52,0x18,0x94,0x1a,0x3a,0x0e,0x68}},
+    SHIM_LOCK_PROTOCOL =
+        {0x605dab50,0xe046,0x4300, {0xab,0xb6,0x3d,0xd8,0x10,0xdd,0x8b,0x23}},
+
     EFI_ACPI_20_TABLE =
         {0x8868e871,0xe4f1,0x11d3, {0xbc,0x22,0x00,0x80,0xc7,0x3c,0x88,0x81}},
     ACPI_TABLE =
@@ -73,6 +95,12 @@ impl_guids! {
     MPS_TABLE =
         {0xeb9d2d2f,0x2d88,0x11d3, {0x9a,0x16,0x00,0x90,0x27,0x3f,0xc1,0x4d}},
 
+    EFI_RT_PROPERTIES_TABLE =
+        {0xeb66918a,0x7eef,0x402a, {0x84,0x2e,0x93,0x1d,0x21,0xc3,0x8a,0xe9}},
+
+    EFI_SYSTEM_RESOURCE_TABLE =
+        {0xb122a263,0x3661,0x4f68, {0x99,0x29,0x78,0xf8,0xb0,0xd6,0x21,0x80}},
+
     EFI_DEBUG_IMAGE_INFO_TABLE =
         {0x49152E77,0x1ADA,0x4764, {0xB7,0xA2,0x7A,0xFE,0xFE,0xD9,0x5E,0x8B}},
 }
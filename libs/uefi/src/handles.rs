@@ -0,0 +1,264 @@
+use super::*;
+
+use core::marker::PhantomData;
+
+/// EFI_LOCATE_SEARCH_TYPE
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum LocateSearchType {
+    AllHandles = 0,
+    ByRegisterNotify,
+    ByProtocol,
+}
+
+/// Handles from LocateHandleBuffer, the pool buffer is freed on drop
+pub struct HandleBuffer<'bs> {
+    boot_services: &'bs BootServices,
+    ptr: *mut Handle,
+    len: usize,
+}
+
+impl<'bs> HandleBuffer<'bs> {
+    pub(crate) fn new(boot_services: &'bs BootServices, ptr: *mut Handle, len: usize) -> Self {
+        Self {
+            boot_services,
+            ptr,
+            len,
+        }
+    }
+
+    pub fn handles(&self) -> &[Handle] {
+        if self.ptr.is_null() {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for HandleBuffer<'_> {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            let _ = unsafe { self.boot_services.free_pool(self.ptr as *mut u8) };
+        }
+    }
+}
+
+/// Connects drivers to every handle, recursively, so that devices behind
+/// buses firmware didn't bother with, like USB sticks, get their block
+/// and file system protocols. Handles enumerated before are stale after
+/// this, `HandleSet::enumerate` again. Returns how many handles got a
+/// driver, handles no driver supports are skipped.
+pub fn reconnect_all(boot_services: &BootServices) -> Result<usize, Error> {
+    let all = boot_services.locate_handle_buffer(None)?;
+    let connected = all
+        .handles()
+        .iter()
+        .filter(|&handle| boot_services.connect_controller(handle, true).is_ok())
+        .count();
+    return Ok(connected);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandleError {
+    /// The handle is from an enumeration that was replaced since
+    StaleHandle,
+    Efi(Error),
+}
+
+/// Protocol a `TypedHandle` is for
+pub trait HandleClass {
+    const PROTOCOL: Guid;
+    type Interface;
+}
+
+/// EFI_SIMPLE_FILE_SYSTEM_PROTOCOL
+#[repr(C)]
+pub struct SimpleFileSystemProtocol {
+    pub revision: u64,
+    open_volume:
+        Option<extern "efiapi" fn(&SimpleFileSystemProtocol, &mut *const File) -> RawStatus>,
+}
+
+impl SimpleFileSystemProtocol {
+    /// Root directory of the volume, `close` it when done
+    pub fn open_volume(&self) -> Result<&File, Error> {
+        let open_volume = self.open_volume.expect("buggy UEFI: open_volume is null");
+        let mut root = core::ptr::null();
+        let status = (open_volume)(self, &mut root);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+        if root.is_null() {
+            return Err(Error::NotFound);
+        }
+
+        return Ok(unsafe { &*root });
+    }
+}
+
+/// EFI_BLOCK_IO_MEDIA, fields up to revision 1
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct BlockIoMedia {
+    pub media_id: u32,
+    pub removable_media: bool,
+    pub media_present: bool,
+    /// Partition of a disk, not the whole device
+    pub logical_partition: bool,
+    pub read_only: bool,
+    pub write_caching: bool,
+    pub block_size: u32,
+    pub io_align: u32,
+    pub last_block: u64,
+}
+
+/// EFI_BLOCK_IO_PROTOCOL
+#[repr(C)]
+pub struct BlockIoProtocol {
+    pub revision: u64,
+    media: *const BlockIoMedia,
+    pub reset: usize,
+    pub read_blocks: usize,
+    pub write_blocks: usize,
+    pub flush_blocks: usize,
+}
+
+impl BlockIoProtocol {
+    pub fn media(&self) -> Option<&BlockIoMedia> {
+        unsafe { self.media.as_ref() }
+    }
+}
+
+#[derive(Debug)]
+pub enum FileSystem {}
+
+impl HandleClass for FileSystem {
+    const PROTOCOL: Guid = Guid::EFI_SIMPLE_FILE_SYSTEM_PROTOCOL;
+    type Interface = SimpleFileSystemProtocol;
+}
+
+#[derive(Debug)]
+pub enum BlockDevice {}
+
+impl HandleClass for BlockDevice {
+    const PROTOCOL: Guid = Guid::EFI_BLOCK_IO_PROTOCOL;
+    type Interface = BlockIoProtocol;
+}
+
+/// Handle supporting `C::PROTOCOL`, from enumeration number `generation`
+/// of a `HandleSet`
+#[derive(Debug)]
+pub struct TypedHandle<C: HandleClass> {
+    raw: usize,
+    generation: u32,
+    class: PhantomData<C>,
+}
+
+impl<C: HandleClass> Clone for TypedHandle<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C: HandleClass> Copy for TypedHandle<C> {}
+
+impl<C: HandleClass> PartialEq for TypedHandle<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw && self.generation == other.generation
+    }
+}
+
+impl<C: HandleClass> Eq for TypedHandle<C> {}
+
+impl<C: HandleClass> TypedHandle<C> {
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+pub type FsHandle = TypedHandle<FileSystem>;
+pub type BlockHandle = TypedHandle<BlockDevice>;
+
+/// The handles supporting one protocol. Some firmware returns success
+/// and garbage for a handle that went away when drivers were reconnected,
+/// so handles carry the generation of the enumeration they came from and
+/// `open` refuses them once `enumerate` ran again.
+pub struct HandleSet<'bs, C: HandleClass> {
+    boot_services: &'bs BootServices,
+    buffer: Option<HandleBuffer<'bs>>,
+    generation: u32,
+    class: PhantomData<C>,
+}
+
+impl<'bs, C: HandleClass> HandleSet<'bs, C> {
+    /// Empty until `enumerate`
+    pub fn new(boot_services: &'bs BootServices) -> Self {
+        Self {
+            boot_services,
+            buffer: None,
+            generation: 0,
+            class: PhantomData,
+        }
+    }
+
+    /// Runs LocateHandleBuffer again, every handle given out so far is
+    /// stale afterwards, even if firmware returns the same values.
+    /// Returns the number of handles.
+    pub fn enumerate(&mut self) -> Result<usize, Error> {
+        self.buffer = None;
+        self.generation = self.generation.wrapping_add(1);
+        match self.boot_services.locate_handle_buffer(Some(&C::PROTOCOL)) {
+            Ok(buffer) => self.buffer = Some(buffer),
+            Err(Error::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        return Ok(self.len());
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    fn raw(&self) -> &[Handle] {
+        self.buffer.as_ref().map_or(&[], |b| b.handles())
+    }
+
+    pub fn len(&self) -> usize {
+        self.raw().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = TypedHandle<C>> + '_ {
+        let generation = self.generation;
+        self.raw().iter().map(move |h| TypedHandle {
+            raw: h.0,
+            generation,
+            class: PhantomData,
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<TypedHandle<C>> {
+        self.iter().nth(index)
+    }
+
+    /// `Err(StaleHandle)` unless `handle` is from the current enumeration
+    pub fn check(&self, handle: TypedHandle<C>) -> Result<(), HandleError> {
+        if handle.generation != self.generation {
+            return Err(HandleError::StaleHandle);
+        }
+        return Ok(());
+    }
+
+    /// The protocol interface on `handle`, firmware isn't called with
+    /// a stale handle
+    pub fn open(&self, handle: TypedHandle<C>) -> Result<&'bs C::Interface, HandleError> {
+        self.check(handle)?;
+        let raw = Handle(handle.raw);
+        return unsafe { self.boot_services.handle_protocol(&raw, &C::PROTOCOL) }
+            .map_err(HandleError::Efi);
+    }
+}
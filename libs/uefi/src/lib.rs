@@ -4,23 +4,64 @@
 use core::mem::MaybeUninit;
 
 mod boot_services;
+mod esrt;
+mod event;
+mod file;
+mod firmware_yield;
 mod guid;
+mod handles;
 mod header;
+mod loaded_image;
 pub mod memory;
+mod memory_map;
+mod retry;
+mod rng;
 mod runtime_services;
+mod security;
+mod serial_io;
 mod status;
 mod system_table;
+mod text_input;
+mod time;
+mod variable;
 
 pub use boot_services::*;
+pub use esrt::*;
+pub use event::*;
+pub use file::*;
+pub use firmware_yield::*;
 pub use guid::*;
+pub use handles::*;
 pub use header::*;
+pub use loaded_image::*;
+pub use memory_map::*;
+pub use retry::*;
+pub use rng::*;
 pub use runtime_services::*;
+pub use security::*;
+pub use serial_io::*;
 pub use status::*;
 pub use system_table::*;
+pub use text_input::*;
+pub use time::*;
+pub use variable::*;
 
 /// Version of EFI spec that this crate is based on.
 pub const SPECIFICATION_VERSION: Revision = Revision::new(2, 70);
 
+/// Returns `Err(Error::Unsupported)` from the enclosing function if the table
+/// `$table` (anything implementing `Verify`) reports a revision older than
+/// `$major.$minor`. Minor revision uses the same encoding as the spec,
+/// so UEFI 2.5 is `requires_revision!(st, 2, 50)`.
+#[macro_export]
+macro_rules! requires_revision {
+    ($table:expr, $major:expr, $minor:expr) => {
+        if $crate::Verify::get_header($table).revision < $crate::Revision::new($major, $minor) {
+            return Err($crate::Error::Unsupported);
+        }
+    };
+}
+
 /// A type that can be used to check whether `efi_main` has good signature
 pub type EfiImageEntryPointFunc = extern "efiapi" fn(ImageHandle, *const SystemTable) -> RawStatus;
 
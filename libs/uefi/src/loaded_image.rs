@@ -0,0 +1,67 @@
+use super::*;
+
+/// EFI_LOADED_IMAGE_PROTOCOL, describes an image that has been loaded
+/// into memory, most notably our own one
+#[repr(C)]
+pub struct LoadedImage {
+    pub revision: u32,
+    pub parent_handle: Handle,
+    pub system_table: *const SystemTable,
+
+    /// Device the image was loaded from
+    pub device_handle: Handle,
+    pub file_path: *const DevicePath,
+    _reserved: usize,
+
+    pub load_options_size: u32,
+    pub load_options: *const u8,
+
+    pub image_base: usize,
+    pub image_size: u64,
+    pub image_code_type: u32,
+    pub image_data_type: u32,
+    pub unload: usize,
+}
+
+impl LoadedImage {
+    /// Raw bytes of the options the image was started with
+    pub fn load_options(&self) -> &[u8] {
+        if self.load_options.is_null() {
+            return &[];
+        }
+
+        let len = self.load_options_size as usize;
+        unsafe { &*core::ptr::slice_from_raw_parts(self.load_options, len) }
+    }
+
+    /// Last space separated word of the options read as UCS-2 text up to
+    /// the first NUL, copied to `buf` with a NUL after it. The UEFI shell
+    /// passes the whole command line, image name first, a boot entry just
+    /// what was configured. `None` if there is no word or it doesn't fit.
+    pub fn last_load_option<'b>(&self, buf: &'b mut [u16]) -> Option<&'b [u16]> {
+        const SPACE: u16 = b' ' as u16;
+        let options = self.load_options();
+        let at = |i: usize| u16::from_le_bytes([options[2 * i], options[2 * i + 1]]);
+
+        let len = options.len() / 2;
+        let mut end = (0..len).position(|i| at(i) == 0).unwrap_or(len);
+        while end > 0 && at(end - 1) == SPACE {
+            end -= 1;
+        }
+        let start = (0..end).rposition(|i| at(i) == SPACE).map_or(0, |i| i + 1);
+        if start == end || end - start >= buf.len() {
+            return None;
+        }
+
+        for (dst, i) in buf.iter_mut().zip(start..end) {
+            *dst = at(i);
+        }
+        buf[end - start] = 0;
+        return Some(&buf[..=end - start]);
+    }
+
+    /// Path of the image's file on `device_handle`
+    pub fn file_path(&self) -> Option<&DevicePath> {
+        unsafe { self.file_path.as_ref() }
+    }
+}
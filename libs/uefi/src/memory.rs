@@ -1,8 +1,18 @@
-use impl_bits::impl_bits;
+use cpu::paging::Page;
+use cpu::{PhysRange, VirtRange};
+use impl_bits::fmt::{Addr, Size};
+use impl_bits::{debug_enum, impl_bits};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct MapKey(pub(crate) u64);
 
+impl MapKey {
+    pub const fn new(key: u64) -> Self {
+        Self(key)
+    }
+}
+
 #[repr(C)]
 pub struct Descriptor {
     /// Type of the memory region. Type EFI_MEMORY_TYPE is defined in the
@@ -38,52 +48,84 @@ pub struct Descriptor {
 
 impl core::fmt::Debug for Descriptor {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-        f.debug_struct("Descriptor")
-            .field("typ", &self.memory_type())
-            .field("phys_start", &self.phys_start)
-            .field("virt_start", &self.virt_start)
+        let mut s = f.debug_struct("Descriptor");
+        match self.memory_type() {
+            Some(typ) => s.field("typ", &typ),
+            None => s.field("typ", &format_args!("Unknown ({:#x})", self.typ)),
+        };
+        s.field("phys_start", &Addr(self.phys_start))
+            .field("virt_start", &Addr(self.virt_start))
             .field("pages", &self.pages)
+            .field("size", &Size(self.pages.saturating_mul(4096)))
             .field("attributes", &self.attributes)
             .finish()
     }
 }
 
 impl Descriptor {
+    /// Identity-mapped descriptor covering `range`,
+    /// `None` if it isn't aligned to 4KiB pages
+    pub fn new(typ: Type, range: PhysRange, attributes: Attributes) -> Option<Self> {
+        if !range.is_aligned::<Page>() {
+            return None;
+        }
+
+        return Some(Self {
+            typ: typ as u32,
+            _padding: 0,
+            phys_start: range.start(),
+            virt_start: range.start(),
+            pages: range.len() / 4096,
+            attributes,
+        });
+    }
+
     pub fn memory_type(&self) -> Option<Type> {
         Type::from_int(self.typ)
     }
+
+    /// `None` if firmware gave a region past the physical address space
+    pub fn phys_range(&self) -> Option<PhysRange> {
+        PhysRange::new(self.phys_start, self.pages.checked_mul(4096)?)
+    }
+
+    pub fn virt_range(&self) -> Option<VirtRange> {
+        VirtRange::new(self.virt_start, self.pages.checked_mul(4096)?)
+    }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-#[repr(u32)]
-pub enum Type {
-    Reserved = 0,
-    LoaderCode,
-    LoaderData,
-    BootServicesCode,
-    BootServicesData,
-    RuntimeServicesCode,
-    RuntimeServicesData,
+debug_enum! {
+    #[derive(PartialEq, Eq)]
+    #[repr(u32)]
+    pub enum Type {
+        Reserved = 0,
+        LoaderCode,
+        LoaderData,
+        BootServicesCode,
+        BootServicesData,
+        RuntimeServicesCode,
+        RuntimeServicesData,
 
-    /// Free (unallocated) memory
-    Conventional,
+        /// Free (unallocated) memory
+        Conventional,
 
-    Unusable,
+        Unusable,
 
-    /// Memory which holds ACPI tables
-    AcpiReclaim,
+        /// Memory which holds ACPI tables
+        AcpiReclaim,
 
-    /// Reserved for use by the firmware
-    AcpiNVS,
+        /// Reserved for use by the firmware
+        AcpiNVS,
 
-    Mmio,
-    MmioPortSpace,
+        Mmio,
+        MmioPortSpace,
 
-    /// Address space reserved by the firmware for code that is part of the processor.
-    PalCode,
+        /// Address space reserved by the firmware for code that is part of the processor.
+        PalCode,
 
-    /// A memory region that operates as EfiConventionalMemory. However, it happens to also support byte-addressable non-volatility.
-    Persistent,
+        /// A memory region that operates as EfiConventionalMemory. However, it happens to also support byte-addressable non-volatility.
+        Persistent,
+    }
 }
 
 impl Type {
@@ -148,6 +190,29 @@ impl<'buf> Iterator for DescriptorIterator<'buf> {
     }
 }
 
+/// EFI_MEMORY_ATTRIBUTES_TABLE, describes memory protections of runtime
+/// services regions. Descriptors follow the header.
+#[repr(C)]
+pub struct AttributesTable {
+    pub version: u32,
+    pub number_of_entries: u32,
+    pub descriptor_size: u32,
+    _reserved: u32,
+}
+
+impl AttributesTable {
+    pub fn entries(&self) -> DescriptorIterator {
+        let len = self.number_of_entries as usize * self.descriptor_size as usize;
+        let len = len / core::mem::size_of::<u64>();
+
+        unsafe {
+            let ptr = (self as *const Self).add(1) as *const u64;
+            let buf = &*core::ptr::slice_from_raw_parts(ptr, len);
+            DescriptorIterator::new(buf, self.descriptor_size as usize)
+        }
+    }
+}
+
 #[repr(transparent)]
 pub struct Attributes(u64);
 
@@ -171,3 +236,13 @@ impl_bits! {
         runtime = 63,
     }
 }
+
+impl Attributes {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,179 @@
+use super::*;
+
+/// What `MemoryMapBuffer` needs from firmware, `BootServices` outside of tests
+pub trait MemoryMapFirmware {
+    /// GetMemoryMap into `buf`. `size` is set to the bytes written, or to
+    /// the bytes needed on `Error::BufferTooSmall`. Returns the map key
+    /// and the size of a descriptor.
+    fn raw_memory_map(
+        &self,
+        buf: &mut [MaybeUninit<u64>],
+        size: &mut usize,
+    ) -> Result<(memory::MapKey, usize), Error>;
+
+    /// `pages` pages of `LoaderData`, returns the address of the first one
+    fn allocate_map_pages(&self, pages: usize) -> Result<u64, Error>;
+}
+
+impl MemoryMapFirmware for BootServices {
+    fn raw_memory_map(
+        &self,
+        buf: &mut [MaybeUninit<u64>],
+        size: &mut usize,
+    ) -> Result<(memory::MapKey, usize), Error> {
+        return self.raw_get_memory_map(buf, size);
+    }
+
+    fn allocate_map_pages(&self, pages: usize) -> Result<u64, Error> {
+        return self.allocate_pages(AllocateType::AnyPages, memory::Type::LoaderData, pages, 0);
+    }
+}
+
+/// One GetMemoryMap call of `MemoryMapBuffer::fetch`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapCall {
+    /// Size of the map, what it would have been if it was too small
+    pub bytes: usize,
+    /// TSC cycles the call took
+    pub cycles: u64,
+    pub too_small: bool,
+}
+
+/// Memory map buffer kept across ExitBootServices attempts. It starts
+/// out empty, so the first call only asks for the size, unless made
+/// `with_capacity`, and it is only replaced by a bigger one when the
+/// firmware says BUFFER_TOO_SMALL. The pages are never freed, freeing
+/// would change the map again.
+pub struct MemoryMapBuffer {
+    buf: &'static mut [MaybeUninit<u64>],
+    /// Bytes of the last map `fetch` got
+    size: usize,
+    descriptor_size: usize,
+    /// GetMemoryMap calls so far
+    pub calls: u32,
+    /// Buffers allocated so far
+    pub allocations: u32,
+}
+
+impl MemoryMapBuffer {
+    /// Allocating the buffer can split a free descriptor in three,
+    /// and firmware may add a few of its own before the next call
+    pub const SLACK: usize = 1024;
+
+    /// Bigger buffers a single `fetch` tries before giving up
+    const MAX_GROWTH: u32 = 4;
+
+    pub const fn new() -> Self {
+        Self {
+            buf: &mut [],
+            size: 0,
+            descriptor_size: 0,
+            calls: 0,
+            allocations: 0,
+        }
+    }
+
+    /// A buffer of at least `bytes` bytes right away, big enough ones
+    /// save the call that only asks for the size
+    pub fn with_capacity(firmware: &impl MemoryMapFirmware, bytes: usize) -> Result<Self, Error> {
+        let mut this = Self::new();
+        this.allocate(firmware, bytes)?;
+        return Ok(this);
+    }
+
+    /// In bytes
+    pub fn capacity(&self) -> usize {
+        core::mem::size_of_val(self.buf)
+    }
+
+    fn allocate(&mut self, firmware: &impl MemoryMapFirmware, bytes: usize) -> Result<(), Error> {
+        let pages = bytes.checked_add(4095).ok_or(Error::OutOfResources)? / 4096;
+        let addr = firmware.allocate_map_pages(pages)?;
+
+        /* SAFETY: freshly allocated pages nothing else points to */
+        self.buf = unsafe {
+            core::slice::from_raw_parts_mut(addr as *mut MaybeUninit<u64>, pages * 512)
+        };
+        self.allocations += 1;
+        return Ok(());
+    }
+
+    /// Gets the current map into the buffer, replacing it with a bigger
+    /// one while it doesn't fit. `on_call` is told about every
+    /// GetMemoryMap call. Returns the key of the map, `descriptors`
+    /// walks it.
+    pub fn fetch(
+        &mut self,
+        firmware: &impl MemoryMapFirmware,
+        on_call: impl FnMut(MapCall),
+    ) -> Result<memory::MapKey, Error> {
+        return self.fetch_growing(firmware, Self::MAX_GROWTH, on_call);
+    }
+
+    /// `fetch` for after a failed ExitBootServices, when nothing but
+    /// GetMemoryMap may be called, so a map that outgrew the buffer
+    /// and its slack is `Error::BufferTooSmall`
+    pub fn refetch(
+        &mut self,
+        firmware: &impl MemoryMapFirmware,
+        on_call: impl FnMut(MapCall),
+    ) -> Result<memory::MapKey, Error> {
+        return self.fetch_growing(firmware, 0, on_call);
+    }
+
+    fn fetch_growing(
+        &mut self,
+        firmware: &impl MemoryMapFirmware,
+        max_growth: u32,
+        mut on_call: impl FnMut(MapCall),
+    ) -> Result<memory::MapKey, Error> {
+        self.size = 0;
+        let mut growth = 0;
+
+        loop {
+            let mut size = self.capacity();
+            let start = cpu::rdtsc();
+            let result = firmware.raw_memory_map(self.buf, &mut size);
+            let cycles = cpu::rdtsc().wrapping_sub(start);
+            self.calls += 1;
+
+            let too_small = result == Err(Error::BufferTooSmall);
+            if result.is_ok() || too_small {
+                on_call(MapCall {
+                    bytes: size,
+                    cycles,
+                    too_small,
+                });
+            }
+
+            match result {
+                Ok((key, descriptor_size)) => {
+                    self.size = core::cmp::min(size, self.capacity());
+                    self.descriptor_size = descriptor_size;
+                    return Ok(key);
+                }
+                Err(Error::BufferTooSmall) if growth < max_growth => {
+                    growth += 1;
+                    let bytes = size.checked_add(Self::SLACK).ok_or(Error::BufferTooSmall)?;
+                    self.allocate(firmware, bytes)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Descriptors of the map the last successful `fetch` got, none
+    /// after a failed one
+    pub fn descriptors(&self) -> memory::DescriptorIterator {
+        if self.size == 0 {
+            return memory::DescriptorIterator::new(&[], core::mem::size_of::<memory::Descriptor>());
+        }
+
+        let words = self.size / core::mem::size_of::<u64>();
+        let init: *const [MaybeUninit<u64>] = &self.buf[..words];
+        /* SAFETY: firmware wrote the first `size` bytes */
+        unsafe {
+            return memory::DescriptorIterator::new(&*(init as *const [u64]), self.descriptor_size);
+        }
+    }
+}
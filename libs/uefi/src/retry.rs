@@ -0,0 +1,134 @@
+use super::*;
+
+/// Time source for `retry_with`
+pub trait Clock {
+    fn now_us(&self) -> u64;
+    fn sleep_us(&self, us: u64);
+}
+
+/// TSC calibrated against the firmware's `Stall`,
+/// keeps working after boot services are gone
+#[derive(Clone, Copy, Debug)]
+pub struct TscClock {
+    ticks_per_us: u64,
+}
+
+impl TscClock {
+    pub const fn new(ticks_per_us: u64) -> Self {
+        Self { ticks_per_us }
+    }
+
+    /// Measures the TSC across a 10ms `Stall`
+    pub fn calibrate(boot_services: &BootServices) -> Result<Self, Error> {
+        const STALL_US: u64 = 10_000;
+
+        let start = cpu::rdtsc();
+        boot_services.stall(STALL_US as usize)?;
+        let ticks = cpu::rdtsc().wrapping_sub(start);
+
+        return Ok(Self::new(core::cmp::max(ticks / STALL_US, 1)));
+    }
+
+    pub const fn ticks_per_us(&self) -> u64 {
+        self.ticks_per_us
+    }
+}
+
+impl Clock for TscClock {
+    fn now_us(&self) -> u64 {
+        cpu::rdtsc() / self.ticks_per_us
+    }
+
+    fn sleep_us(&self, us: u64) {
+        let end = cpu::rdtsc().saturating_add(us.saturating_mul(self.ticks_per_us));
+        while cpu::rdtsc() < end {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Delay between attempts, in microseconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    None,
+    Fixed(u64),
+    /// Starts at the given delay and doubles after every attempt
+    Doubling(u64),
+}
+
+impl Backoff {
+    /// Delay after failed attempt number `attempt`, counting from 1
+    pub fn delay_us(&self, attempt: u32) -> u64 {
+        match *self {
+            Backoff::None => 0,
+            Backoff::Fixed(us) => us,
+            Backoff::Doubling(us) => {
+                let shift = core::cmp::min(attempt.saturating_sub(1), 63);
+                us.checked_shl(shift)
+                    .filter(|&x| x >> shift == us)
+                    .unwrap_or(u64::MAX)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Time after the first attempt when no new attempt is started
+    pub deadline_us: u64,
+    pub backoff: Backoff,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryError {
+    /// Status of the last attempt, there was no time for another one
+    Deadline(Error),
+    /// Status the caller's predicate didn't consider worth retrying
+    NonRetryable(Error),
+}
+
+impl RetryError {
+    pub fn status(&self) -> Error {
+        match *self {
+            RetryError::Deadline(e) => e,
+            RetryError::NonRetryable(e) => e,
+        }
+    }
+}
+
+/// Calls `f` until it succeeds, it fails with a status `retryable` rejects,
+/// or the next attempt would start past the deadline.
+/// `on_retry` is called with the attempt number and status before each retry,
+/// usually to log it.
+pub fn retry_with<T>(
+    clock: &impl Clock,
+    policy: RetryPolicy,
+    retryable: impl Fn(Error) -> bool,
+    mut on_retry: impl FnMut(u32, Error),
+    mut f: impl FnMut() -> Result<T, Error>,
+) -> Result<T, RetryError> {
+    let start = clock.now_us();
+    let mut attempt = 1;
+
+    loop {
+        let err = match f() {
+            Ok(x) => return Ok(x),
+            Err(e) => e,
+        };
+        if !retryable(err) {
+            return Err(RetryError::NonRetryable(err));
+        }
+
+        let delay = policy.backoff.delay_us(attempt);
+        let elapsed = clock.now_us().saturating_sub(start);
+        if elapsed.saturating_add(delay) > policy.deadline_us {
+            return Err(RetryError::Deadline(err));
+        }
+
+        on_retry(attempt, err);
+        if delay != 0 {
+            clock.sleep_us(delay);
+        }
+        attempt += 1;
+    }
+}
@@ -0,0 +1,22 @@
+use super::*;
+
+/// EFI_RNG_PROTOCOL, random numbers from the platform
+#[repr(C)]
+pub struct RngProtocol {
+    pub get_info: usize,
+    get_rng: Option<extern "efiapi" fn(&RngProtocol, *const Guid, usize, *mut u8) -> RawStatus>,
+}
+
+impl RngProtocol {
+    /// Fills `buf` using the default algorithm of the platform
+    pub fn get_rng(&self, buf: &mut [u8]) -> Result<(), Error> {
+        let get_rng = self.get_rng.expect("buggy UEFI: get_rng is null");
+        let status = (get_rng)(self, core::ptr::null(), buf.len(), buf.as_mut_ptr());
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+}
@@ -1,29 +1,41 @@
 use super::*;
 
+use impl_bits::impl_bits;
+
 #[repr(C)]
 pub struct RuntimeServices {
     header: TableHeader,
 
-    get_time: usize,
+    get_time: Option<extern "efiapi" fn(&mut Time, *mut TimeCapabilities) -> RawStatus>,
     set_time: usize,
     get_wakeup_time: usize,
     set_wakeup_time: usize,
     set_virtual_address_map: usize,
     convert_pointer: usize,
 
-    get_variable: usize,
+    get_variable: Option<
+        extern "efiapi" fn(
+            *const u16,
+            &Guid,
+            *mut VariableAttributes,
+            &mut usize,
+            *mut u8,
+        ) -> RawStatus,
+    >,
     get_next_variable_name: usize,
-    set_variable: usize,
+    set_variable: Option<
+        extern "efiapi" fn(*const u16, &Guid, VariableAttributes, usize, *const u8) -> RawStatus,
+    >,
 
     get_next_high_mono_count: usize,
 
-    reset_system: usize,
+    reset_system: Option<extern "efiapi" fn(ResetType, RawStatus, usize, *const u8) -> !>,
+
     // UEFI 2.0
-    /*
-    update_capsule,
-    query_capsule_capabilities,
-    query_variable_info,
-    */
+    update_capsule: usize,
+    query_capsule_capabilities: usize,
+    query_variable_info:
+        Option<extern "efiapi" fn(VariableAttributes, &mut u64, &mut u64, &mut u64) -> RawStatus>,
 }
 
 impl Verify for RuntimeServices {
@@ -32,3 +44,202 @@ impl Verify for RuntimeServices {
         &self.header
     }
 }
+
+impl RuntimeServices {
+    /// Reads current time from the platform's real time clock.
+    /// Returns `None` when the service is missing or fails, e.g. because
+    /// there is no RTC.
+    pub fn get_time(&self) -> Option<Time> {
+        let get_time = self.get_time?;
+        let mut time = Time::new();
+
+        let status = (get_time)(&mut time, core::ptr::null_mut());
+        if status.0 != RawStatus::OK.0 {
+            return None;
+        }
+
+        return Some(time);
+    }
+
+    /// Reads variable `name` (null-terminated UCS-2) into `buf`,
+    /// returns its attributes and the part of `buf` that was filled.
+    /// If `buf` is too small, `Error::BufferTooSmall` is returned.
+    pub fn get_variable<'b>(
+        &self,
+        name: &[u16],
+        vendor: &Guid,
+        buf: &'b mut [u8],
+    ) -> Result<(VariableAttributes, &'b mut [u8]), Error> {
+        assert_eq!(
+            name.last(),
+            Some(&0),
+            "variable name must be null-terminated"
+        );
+
+        let get_variable = self.get_variable.expect("buggy UEFI: get_variable is null");
+
+        let mut attributes = VariableAttributes::new();
+        let mut size = buf.len();
+        let status = (get_variable)(
+            name.as_ptr(),
+            vendor,
+            &mut attributes,
+            &mut size,
+            buf.as_mut_ptr(),
+        );
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+        if size > buf.len() {
+            return Err(Error::BadBufferSize);
+        }
+
+        return Ok((attributes, &mut buf[..size]));
+    }
+
+    /// Sets variable `name` (null-terminated UCS-2) to exactly `data`.
+    /// Empty `data` deletes the variable.
+    pub fn set_variable(
+        &self,
+        name: &[u16],
+        vendor: &Guid,
+        attributes: VariableAttributes,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        assert_eq!(
+            name.last(),
+            Some(&0),
+            "variable name must be null-terminated"
+        );
+
+        let set_variable = self.set_variable.expect("buggy UEFI: set_variable is null");
+
+        let status = (set_variable)(name.as_ptr(), vendor, attributes, data.len(), data.as_ptr());
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    /// Reads a variable that holds a single u64, like `OsIndications`.
+    /// Variables of any other size are rejected with `Error::BadBufferSize`.
+    pub fn get_variable_u64(
+        &self,
+        name: &[u16],
+        vendor: &Guid,
+    ) -> Result<(VariableAttributes, u64), Error> {
+        let mut buf = [0u8; 8];
+        let (attributes, data) = self.get_variable(name, vendor, &mut buf)?;
+        if data.len() != 8 {
+            return Err(Error::BadBufferSize);
+        }
+
+        return Ok((attributes, u64::from_le_bytes(buf)));
+    }
+
+    /// Writes all 8 bytes of `value`. Some firmware bricks 64-bit
+    /// variables when they are written with a different size.
+    pub fn set_variable_u64(
+        &self,
+        name: &[u16],
+        vendor: &Guid,
+        attributes: VariableAttributes,
+        value: u64,
+    ) -> Result<(), Error> {
+        self.set_variable(name, vendor, attributes, &value.to_le_bytes())
+    }
+
+    /// Resets the whole platform, `data` is a null-terminated UCS-2 string
+    /// optionally followed by binary data, used by firmware to log the reason.
+    pub fn reset_system(&self, typ: ResetType, status: RawStatus, data: &[u8]) -> ! {
+        let reset_system = self.reset_system.expect("buggy UEFI: reset_system is null");
+
+        let ptr = if data.is_empty() {
+            core::ptr::null()
+        } else {
+            data.as_ptr()
+        };
+        (reset_system)(typ, status, data.len(), ptr)
+    }
+
+    /// Returns information about the storage of variables
+    /// with given attributes. Available since UEFI 2.0
+    pub fn query_variable_info(
+        &self,
+        attributes: VariableAttributes,
+    ) -> Result<VariableInfo, Error> {
+        requires_revision!(self, 2, 0);
+
+        let query_variable_info = self
+            .query_variable_info
+            .expect("buggy UEFI: query_variable_info is null");
+
+        let mut info = VariableInfo {
+            max_storage_size: 0,
+            remaining_storage_size: 0,
+            max_variable_size: 0,
+        };
+        let status = (query_variable_info)(
+            attributes,
+            &mut info.max_storage_size,
+            &mut info.remaining_storage_size,
+            &mut info.max_variable_size,
+        );
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(info);
+    }
+}
+
+/// EFI_RESET_TYPE
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ResetType {
+    Cold = 0,
+    Warm,
+    Shutdown,
+    PlatformSpecific,
+}
+
+#[derive(Debug)]
+pub struct VariableInfo {
+    pub max_storage_size: u64,
+    pub remaining_storage_size: u64,
+    pub max_variable_size: u64,
+}
+
+#[repr(transparent)]
+pub struct VariableAttributes(u32);
+
+impl_bits! {
+    VariableAttributes = {
+        non_volatile = 0,
+        bootservice_access = 1,
+        runtime_access = 2,
+        hardware_error_record = 3,
+        authenticated_write_access = 4,
+        time_based_authenticated_write_access = 5,
+        append_write = 6,
+        enhanced_authenticated_access = 7,
+    }
+}
+
+impl VariableAttributes {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+}
+
+/// EFI_RT_PROPERTIES_TABLE, tells which runtime services are still
+/// callable after ExitBootServices
+#[repr(C)]
+pub struct RtPropertiesTable {
+    pub version: u16,
+    pub length: u16,
+    pub runtime_services_supported: u32,
+}
@@ -0,0 +1,74 @@
+use super::*;
+
+/// Header of a device path node, the path continues past it up to an end
+/// node, `length` bytes per node
+#[repr(C)]
+pub struct DevicePath {
+    pub typ: u8,
+    pub subtype: u8,
+    pub length: [u8; 2],
+}
+
+/// EFI_SECURITY2_ARCH_PROTOCOL, the firmware's image verification, which
+/// enforces Secure Boot in LoadImage
+#[repr(C)]
+pub struct Security2Protocol {
+    file_authentication: Option<
+        extern "efiapi" fn(
+            &Security2Protocol,
+            *const DevicePath,
+            *const u8,
+            usize,
+            bool,
+        ) -> RawStatus,
+    >,
+}
+
+impl Security2Protocol {
+    /// Checks `file` against the platform's policy, as if LoadImage got it
+    /// from `path`. Rejection is `Error::SecurityViolation` or
+    /// `Error::AccessDenied`.
+    pub fn file_authentication(
+        &self,
+        path: Option<&DevicePath>,
+        file: &[u8],
+        boot_policy: bool,
+    ) -> Result<(), Error> {
+        let file_authentication = self
+            .file_authentication
+            .expect("buggy UEFI: file_authentication is null");
+        let path = path.map_or(core::ptr::null(), |x| x as *const _);
+        let status = (file_authentication)(self, path, file.as_ptr(), file.len(), boot_policy);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+}
+
+/// SHIM_LOCK_PROTOCOL, installed by shim to verify images against its
+/// own keys and MOK. Unlike UEFI protocols it uses the SysV ABI on x86_64.
+#[repr(C)]
+pub struct ShimLock {
+    verify: Option<extern "sysv64" fn(*const u8, u32) -> RawStatus>,
+    pub hash: usize,
+    pub context: usize,
+}
+
+impl ShimLock {
+    pub fn verify(&self, file: &[u8]) -> Result<(), Error> {
+        let verify = self.verify.expect("buggy shim: verify is null");
+        if file.len() > u32::MAX as usize {
+            return Err(Error::BadBufferSize);
+        }
+        let status = (verify)(file.as_ptr(), file.len() as u32);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+}
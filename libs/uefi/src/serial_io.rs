@@ -0,0 +1,132 @@
+use super::*;
+
+use impl_bits::{debug_enum, impl_bits};
+
+debug_enum! {
+    #[derive(PartialEq, Eq)]
+    #[repr(u32)]
+    pub enum Parity {
+        /// Whatever the device defaults to
+        Default = 0,
+        No,
+        Even,
+        Odd,
+        Mark,
+        Space,
+    }
+}
+
+debug_enum! {
+    #[derive(PartialEq, Eq)]
+    #[repr(u32)]
+    pub enum StopBits {
+        /// Whatever the device defaults to
+        Default = 0,
+        One,
+        OneFive,
+        Two,
+    }
+}
+
+/// Modem lines and buffer state returned by GetControl
+#[repr(transparent)]
+pub struct SerialControl(u32);
+
+impl_bits! {
+    SerialControl = {
+        data_terminal_ready = 0,
+        request_to_send = 1,
+        clear_to_send = 4,
+        data_set_ready = 5,
+        ring_indicate = 6,
+        carrier_detect = 7,
+        input_buffer_empty = 8,
+        output_buffer_empty = 9,
+        hardware_loopback = 12,
+        software_loopback = 13,
+        hardware_flow_control = 14,
+    }
+}
+
+impl SerialControl {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+}
+
+/// EFI_SERIAL_IO_PROTOCOL, a UART driven by firmware. On machines without
+/// legacy COM ports it is often backed by a USB or LPSS UART, which is
+/// gone after ExitBootServices.
+#[repr(C)]
+pub struct SerialIoProtocol {
+    pub revision: u32,
+    pub reset: usize,
+    set_attributes:
+        Option<extern "efiapi" fn(&SerialIoProtocol, u64, u32, u32, u32, u8, u32) -> RawStatus>,
+    pub set_control: usize,
+    get_control: Option<extern "efiapi" fn(&SerialIoProtocol, &mut u32) -> RawStatus>,
+    write: Option<extern "efiapi" fn(&SerialIoProtocol, &mut usize, *const u8) -> RawStatus>,
+    read: Option<extern "efiapi" fn(&SerialIoProtocol, &mut usize, *mut u8) -> RawStatus>,
+    pub mode: usize,
+}
+
+impl SerialIoProtocol {
+    /// Sets baud rate and line format, keeping the default FIFO depth
+    /// and timeout. 0 for `baud` or `data_bits` means the device default.
+    pub fn set_attributes(
+        &self,
+        baud: u64,
+        parity: Parity,
+        data_bits: u8,
+        stop_bits: StopBits,
+    ) -> Result<(), Error> {
+        let set_attributes = self
+            .set_attributes
+            .expect("buggy UEFI: set_attributes is null");
+        let status = (set_attributes)(self, baud, 0, 0, parity as u32, data_bits, stop_bits as u32);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    pub fn get_control(&self) -> Result<SerialControl, Error> {
+        let get_control = self.get_control.expect("buggy UEFI: get_control is null");
+        let mut control = 0u32;
+        let status = (get_control)(self, &mut control);
+
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(SerialControl(control));
+    }
+
+    /// Returns how many bytes were written, which is less than
+    /// `buf.len()` if the device timed out
+    pub fn write(&self, buf: &[u8]) -> Result<usize, Error> {
+        let write = self.write.expect("buggy UEFI: write is null");
+        let mut size = buf.len();
+        let status = (write)(self, &mut size, buf.as_ptr());
+
+        match status.get_efi_error() {
+            None | Some(Error::Timeout) => return Ok(size),
+            Some(err) => return Err(err),
+        }
+    }
+
+    /// Returns how many bytes were read, 0 if nothing arrived before
+    /// the timeout
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = self.read.expect("buggy UEFI: read is null");
+        let mut size = buf.len();
+        let status = (read)(self, &mut size, buf.as_mut_ptr());
+
+        match status.get_efi_error() {
+            None | Some(Error::Timeout) => return Ok(size),
+            Some(err) => return Err(err),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use impl_bits::debug_enum;
+
 #[repr(transparent)]
 pub struct RawStatus(pub usize);
 
@@ -29,50 +31,70 @@ impl RawStatus {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-pub enum Error {
-    LoadError = 1,
-    InvalidParameter,
-    Unsupported,
-    BadBufferSize,
-    BufferTooSmall,
-    NotReady,
-    DeviceError,
-    WriteProtected,
-    OutOfResources,
-    VolumeCorrupted,
-    VolumeFull,
-    NoMedia,
-    MediaChanged,
-    NotFound,
-    AccessDenied,
-    NoResponse,
-    NoMapping,
-    Timeout,
-    NotStarted,
-    AlreadyStarted,
-    Aborted,
-    IcmpError,
-    TftpError,
-    ProtocolError,
-    IncompatibleVersion,
-    SecurityViolation,
-    CrcError,
-    EndOfMedia,
-    EndOfFile,
-    InvalidLanguage,
-    CompromisedData,
-    IpAddressConflict,
-    HttpError,
+impl core::fmt::Debug for RawStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = if self.0 == 0 {
+            "Success"
+        } else if let Some(error) = self.get_efi_error() {
+            error.name()
+        } else if let Some(warning) = self.get_efi_warning() {
+            warning.name()
+        } else {
+            "Unknown"
+        };
+
+        impl_bits::fmt::write_enum(f, name, self.0 as u64)
+    }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-pub enum Warning {
-    UnknownGlyph = 1,
-    DeleteFailure,
-    WriteFailure,
-    BufferTooSmall,
-    StaleData,
-    Filesystem,
-    ResetRequired,
+debug_enum! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        LoadError = 1,
+        InvalidParameter,
+        Unsupported,
+        BadBufferSize,
+        BufferTooSmall,
+        NotReady,
+        DeviceError,
+        WriteProtected,
+        OutOfResources,
+        VolumeCorrupted,
+        VolumeFull,
+        NoMedia,
+        MediaChanged,
+        NotFound,
+        AccessDenied,
+        NoResponse,
+        NoMapping,
+        Timeout,
+        NotStarted,
+        AlreadyStarted,
+        Aborted,
+        IcmpError,
+        TftpError,
+        ProtocolError,
+        IncompatibleVersion,
+        SecurityViolation,
+        CrcError,
+        EndOfMedia,
+        EndOfFile,
+        InvalidLanguage,
+        CompromisedData,
+        IpAddressConflict,
+        HttpError,
+    }
+}
+
+debug_enum! {
+    #[derive(PartialEq, Eq)]
+    pub enum Warning {
+        UnknownGlyph = 1,
+        DeleteFailure,
+        WriteFailure,
+        BufferTooSmall,
+        StaleData,
+        Filesystem,
+        ResetRequired,
+    }
 }
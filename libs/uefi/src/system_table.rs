@@ -52,6 +52,21 @@ pub struct SystemTable {
 }
 
 impl SystemTable {
+    /// UEFI specification revision the firmware conforms to,
+    /// as (major, minor), e.g. (2, 70) for UEFI 2.7
+    pub fn uefi_revision(&self) -> (u16, u16) {
+        self.header.revision.as_tuple()
+    }
+
+    /// See [`RuntimeServices::get_time`]
+    pub fn get_time(&self) -> Option<Time> {
+        if self.runtime_services.is_null() {
+            return None;
+        }
+
+        unsafe { (*self.runtime_services).get_time() }
+    }
+
     pub fn vendor(&self) -> &[u16] {
         let ptr = self.firmware_vendor;
         let mut i = 0usize;
@@ -69,4 +84,32 @@ impl SystemTable {
         let sz = self.number_of_table_entries as usize;
         unsafe { &*core::ptr::slice_from_raw_parts(self.config_table, sz) }
     }
+
+    /// Returns address of the configuration table identified by `guid`
+    pub fn find_config(&self, guid: Guid) -> Option<usize> {
+        self.config_slice()
+            .iter()
+            .find(|cfg| cfg.guid == guid)
+            .map(|cfg| cfg.table)
+    }
+
+    /// EFI_MEMORY_ATTRIBUTES_TABLE, available since UEFI 2.6
+    pub fn memory_attributes_table(&self) -> Result<&memory::AttributesTable, Error> {
+        requires_revision!(self, 2, 60);
+
+        return match self.find_config(Guid::EFI_MEMORY_ATTRIBUTES_TABLE) {
+            Some(table) => unsafe { Ok(&*(table as *const memory::AttributesTable)) },
+            None => Err(Error::NotFound),
+        };
+    }
+
+    /// EFI_RT_PROPERTIES_TABLE, available since UEFI 2.8
+    pub fn rt_properties(&self) -> Result<&RtPropertiesTable, Error> {
+        requires_revision!(self, 2, 80);
+
+        return match self.find_config(Guid::EFI_RT_PROPERTIES_TABLE) {
+            Some(table) => unsafe { Ok(&*(table as *const RtPropertiesTable)) },
+            None => Err(Error::NotFound),
+        };
+    }
 }
@@ -0,0 +1,155 @@
+use super::*;
+
+/// How often a polled source is checked while waiting
+pub const INPUT_POLL_INTERVAL_US: u64 = 10_000;
+
+/// EFI_INPUT_KEY, `unicode_char` is 0 for keys like arrows that only
+/// have a scan code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct InputKey {
+    pub scan_code: u16,
+    pub unicode_char: u16,
+}
+
+/// EFI_SIMPLE_TEXT_INPUT_PROTOCOL, the firmware console's keyboard
+#[repr(C)]
+pub struct SimpleTextInput {
+    pub reset: usize,
+    read_key_stroke: Option<extern "efiapi" fn(&SimpleTextInput, &mut InputKey) -> RawStatus>,
+    /// Signaled while a key is waiting to be read
+    pub wait_for_key: Event,
+}
+
+impl SimpleTextInput {
+    /// Next pending key, `Error::NotReady` if there is none
+    pub fn read_key_stroke(&self) -> Result<InputKey, Error> {
+        let read_key_stroke = self
+            .read_key_stroke
+            .expect("buggy UEFI: read_key_stroke is null");
+
+        let mut key = InputKey {
+            scan_code: 0,
+            unicode_char: 0,
+        };
+        let status = (read_key_stroke)(self, &mut key);
+        if let Some(err) = status.get_efi_error() {
+            return Err(err);
+        }
+
+        return Ok(key);
+    }
+}
+
+impl SystemTable {
+    /// ConIn, gone after ExitBootServices
+    pub fn con_in(&self) -> Option<&SimpleTextInput> {
+        let con_in = self.con_in.get() as *const SimpleTextInput;
+        return unsafe { con_in.as_ref() };
+    }
+}
+
+/// Input without an event to wait on, like a UART
+pub trait PolledInput {
+    fn poll(&mut self) -> Option<u8>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuInput {
+    Key(InputKey),
+    /// Byte from the polled source
+    Serial(u8),
+    Timeout,
+}
+
+/// Nothing arrived before the deadline
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimedOut;
+
+/// Waits for a byte from `polled` until `deadline_us` of `clock`, `u64::MAX`
+/// to wait forever. Needs no firmware, so unlike `wait_key_or_timeout` it
+/// keeps working after ExitBootServices, but it spins. A byte that arrived
+/// by the time the deadline was noticed is still taken.
+pub fn poll_until(
+    polled: &mut dyn PolledInput,
+    clock: &dyn Clock,
+    deadline_us: u64,
+) -> Result<u8, TimedOut> {
+    loop {
+        let now = clock.now_us();
+        if let Some(byte) = polled.poll() {
+            return Ok(byte);
+        }
+        if now >= deadline_us {
+            return Err(TimedOut);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Waits for a key from `con_in` or `polled` until `deadline_us` of
+/// `clock`, without spinning. With only `con_in` this is a single
+/// WaitForEvent on the key and a timer for the exact remaining time.
+/// A polled source can't signal anything, so then the timer is cut to
+/// `INPUT_POLL_INTERVAL_US` and `polled` is checked every time it fires.
+pub fn wait_key_or_timeout(
+    boot_services: &BootServices,
+    con_in: Option<&SimpleTextInput>,
+    mut polled: Option<&mut dyn PolledInput>,
+    clock: &dyn Clock,
+    deadline_us: u64,
+) -> Result<MenuInput, Error> {
+    let timer = boot_services.create_timer_event()?;
+    let result = wait_with_timer(
+        boot_services,
+        timer,
+        con_in,
+        &mut polled,
+        clock,
+        deadline_us,
+    );
+    let _ = boot_services.close_event(timer);
+    return result;
+}
+
+fn wait_with_timer(
+    boot_services: &BootServices,
+    timer: Event,
+    con_in: Option<&SimpleTextInput>,
+    polled: &mut Option<&mut dyn PolledInput>,
+    clock: &dyn Clock,
+    deadline_us: u64,
+) -> Result<MenuInput, Error> {
+    let mut events = [timer; 2];
+    let mut count = 1;
+    if let Some(con_in) = con_in {
+        events[1] = con_in.wait_for_key;
+        count = 2;
+    }
+
+    loop {
+        /* A key pressed before the wait started is already pending */
+        if let Some(Ok(key)) = con_in.map(|c| c.read_key_stroke()) {
+            return Ok(MenuInput::Key(key));
+        }
+        if let Some(byte) = polled.as_mut().and_then(|p| p.poll()) {
+            return Ok(MenuInput::Serial(byte));
+        }
+
+        let left = deadline_us.saturating_sub(clock.now_us());
+        if left == 0 {
+            return Ok(MenuInput::Timeout);
+        }
+        let wait = match polled {
+            Some(_) => core::cmp::min(left, INPUT_POLL_INTERVAL_US),
+            None => left,
+        };
+
+        boot_services.set_timer(timer, TimerDelay::Relative, wait)?;
+        let index = boot_services.wait_for_event(&events[..count])?;
+        /* The whole remaining time passed, don't trust the clock to agree */
+        if index == 0 && wait == left {
+            return Ok(MenuInput::Timeout);
+        }
+    }
+}
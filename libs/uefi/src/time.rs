@@ -0,0 +1,57 @@
+/// EFI_TIME, as returned by the runtime GetTime service
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct Time {
+    /// 1900 - 9999
+    pub year: u16,
+    /// 1 - 12
+    pub month: u8,
+    /// 1 - 31
+    pub day: u8,
+    /// 0 - 23
+    pub hour: u8,
+    /// 0 - 59
+    pub minute: u8,
+    /// 0 - 59
+    pub second: u8,
+    _pad1: u8,
+    /// 0 - 999,999,999
+    pub nanosecond: u32,
+    /// Offset from UTC in minutes, -1440 to 1440 or 2047
+    /// (`Time::UNSPECIFIED_TIMEZONE`)
+    pub time_zone: i16,
+    pub daylight: u8,
+    _pad2: u8,
+}
+
+impl Time {
+    pub const UNSPECIFIED_TIMEZONE: i16 = 0x07FF;
+
+    pub const fn new() -> Self {
+        Self {
+            year: 0,
+            month: 0,
+            day: 0,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            _pad1: 0,
+            nanosecond: 0,
+            time_zone: 0,
+            daylight: 0,
+            _pad2: 0,
+        }
+    }
+}
+
+/// EFI_TIME_CAPABILITIES
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct TimeCapabilities {
+    /// Resolution of the clock in counts per second
+    pub resolution: u32,
+    /// Accuracy in parts per million
+    pub accuracy: u32,
+    /// Whether setting the time clears sub-resolution time
+    pub sets_to_zero: bool,
+}
@@ -0,0 +1,120 @@
+use super::*;
+
+use impl_bits::impl_bits;
+
+/// Converts ASCII `s` into a null-terminated UCS-2 string,
+/// `N` must be at least `s.len() + 1`
+pub const fn ucs2<const N: usize>(s: &str) -> [u16; N] {
+    let bytes = s.as_bytes();
+    let mut out = [0u16; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        out[i] = bytes[i] as u16;
+        i += 1;
+    }
+    return out;
+}
+
+/// Names of EFI_GLOBAL_VARIABLE variables
+pub const OS_INDICATIONS: [u16; 14] = ucs2("OsIndications");
+pub const OS_INDICATIONS_SUPPORTED: [u16; 23] = ucs2("OsIndicationsSupported");
+pub const SECURE_BOOT: [u16; 11] = ucs2("SecureBoot");
+pub const SETUP_MODE: [u16; 10] = ucs2("SetupMode");
+
+/// Bits of `OsIndications` and `OsIndicationsSupported`
+#[repr(transparent)]
+pub struct OsIndications(u64);
+
+impl_bits! {
+    OsIndications = {
+        boot_to_fw_ui = 0,
+        timestamp_revocation = 1,
+        file_capsule_delivery_supported = 2,
+        fmp_capsule_supported = 3,
+        capsule_result_var_supported = 4,
+        start_os_recovery = 5,
+        start_platform_recovery = 6,
+        json_config_data_refresh = 7,
+    }
+}
+
+impl OsIndications {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+}
+
+/// `SecureBoot` and `SetupMode`, firmware older than UEFI 2.3.1 has
+/// neither and can't enforce anything
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SecureBootState {
+    pub secure_boot: bool,
+    pub setup_mode: bool,
+}
+
+impl SecureBootState {
+    /// Firmware refuses unsigned images, no keys can be enrolled
+    /// without authentication
+    pub const fn is_enforcing(&self) -> bool {
+        self.secure_boot && !self.setup_mode
+    }
+}
+
+impl RuntimeServices {
+    pub fn secure_boot_state(&self) -> Result<SecureBootState, Error> {
+        let flag = |name: &[u16]| {
+            let mut buf = [0u8; 1];
+            match self.get_variable(name, &Guid::EFI_GLOBAL_VARIABLE, &mut buf) {
+                Ok((_, [x])) => Ok(*x == 1),
+                Ok(_) => Err(Error::BadBufferSize),
+                Err(Error::NotFound) => Ok(false),
+                Err(e) => Err(e),
+            }
+        };
+
+        return Ok(SecureBootState {
+            secure_boot: flag(&SECURE_BOOT)?,
+            setup_mode: flag(&SETUP_MODE)?,
+        });
+    }
+
+    pub fn os_indications_supported(&self) -> Result<OsIndications, Error> {
+        let (_, bits) =
+            self.get_variable_u64(&OS_INDICATIONS_SUPPORTED, &Guid::EFI_GLOBAL_VARIABLE)?;
+        return Ok(OsIndications(bits));
+    }
+
+    /// Asks the firmware to stop in its setup UI on the next boot,
+    /// a reset has to follow. Other bits of `OsIndications` are preserved.
+    /// Returns `Error::Unsupported` if firmware doesn't support it.
+    pub fn request_boot_to_fw_ui(&self) -> Result<(), Error> {
+        if !self.os_indications_supported()?.boot_to_fw_ui() {
+            return Err(Error::Unsupported);
+        }
+
+        let current = match self.get_variable_u64(&OS_INDICATIONS, &Guid::EFI_GLOBAL_VARIABLE) {
+            Ok((_, bits)) => bits,
+            Err(Error::NotFound) => 0,
+            Err(e) => return Err(e),
+        };
+
+        let indications = OsIndications(current).set_boot_to_fw_ui();
+        let attributes = VariableAttributes::new()
+            .set_non_volatile()
+            .set_bootservice_access()
+            .set_runtime_access();
+
+        return self.set_variable_u64(
+            &OS_INDICATIONS,
+            &Guid::EFI_GLOBAL_VARIABLE,
+            attributes,
+            indications.bits(),
+        );
+    }
+}
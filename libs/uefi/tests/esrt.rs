@@ -0,0 +1,96 @@
+use uefi::{Esrt, EsrtError, Guid, ESRT_VERSION};
+
+fn header(count: u32, max: u32, version: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&count.to_le_bytes());
+    bytes.extend_from_slice(&max.to_le_bytes());
+    bytes.extend_from_slice(&version.to_le_bytes());
+    return bytes;
+}
+
+/// `fw_class` is a well known GUID, `seed` is spread over the rest
+fn entry(bytes: &mut Vec<u8>, fw_class: Guid, seed: u32) {
+    let guid: [u8; 16] = unsafe { core::mem::transmute(fw_class) };
+    bytes.extend_from_slice(&guid);
+    for field in 0..6 {
+        bytes.extend_from_slice(&(seed * 10 + field).to_le_bytes());
+    }
+}
+
+fn three_entries() -> Vec<u8> {
+    let mut bytes = header(3, 4, ESRT_VERSION);
+    entry(&mut bytes, Guid::EFI_GLOBAL_VARIABLE, 1);
+    entry(&mut bytes, Guid::EFI_RNG_PROTOCOL, 2);
+    entry(&mut bytes, Guid::SMBIOS3_TABLE, 3);
+    return bytes;
+}
+
+#[test]
+fn parse_three() {
+    let bytes = three_entries();
+    assert_eq!(bytes.len(), 16 + 3 * 40);
+    let esrt = Esrt::parse(&bytes).unwrap();
+    assert_eq!(esrt.len(), 3);
+    assert_eq!(esrt.header.fw_resource_count_max, 4);
+
+    let entries: Vec<_> = esrt.entries().collect();
+    assert_eq!(entries[0].fw_class, Guid::EFI_GLOBAL_VARIABLE);
+    assert_eq!(entries[1].fw_class, Guid::EFI_RNG_PROTOCOL);
+    assert_eq!(entries[2].fw_class, Guid::SMBIOS3_TABLE);
+
+    let last = entries[2];
+    assert_eq!(last.fw_type, 30);
+    assert_eq!(last.fw_version, 31);
+    assert_eq!(last.lowest_supported_fw_version, 32);
+    assert_eq!(last.capsule_flags, 33);
+    assert_eq!(last.last_attempt_version, 34);
+    assert_eq!(last.last_attempt_status, 35);
+}
+
+#[test]
+fn unaligned() {
+    let mut bytes = vec![0u8];
+    bytes.extend_from_slice(&three_entries());
+    let esrt = Esrt::parse(&bytes[1..]).unwrap();
+    assert_eq!(esrt.entries().nth(1).unwrap().fw_version, 21);
+}
+
+#[test]
+fn bounds() {
+    let bytes = three_entries();
+    /* Table memory ends inside the last entry */
+    assert_eq!(
+        Esrt::parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+        EsrtError::Truncated
+    );
+    assert_eq!(Esrt::parse(&bytes[..15]).unwrap_err(), EsrtError::Truncated);
+
+    /* A count that would overflow the multiplication on 32-bit */
+    let mut huge = header(u32::MAX, u32::MAX, ESRT_VERSION);
+    huge.extend_from_slice(&bytes[16..]);
+    assert_eq!(Esrt::parse(&huge).unwrap_err(), EsrtError::Truncated);
+
+    /* Trailing memory is fine */
+    let mut longer = bytes.clone();
+    longer.extend_from_slice(&[0xFF; 64]);
+    assert_eq!(Esrt::parse(&longer).unwrap().len(), 3);
+}
+
+#[test]
+fn bad_header() {
+    let mut bytes = three_entries();
+    bytes[..16].copy_from_slice(&header(3, 2, ESRT_VERSION));
+    assert_eq!(
+        Esrt::parse(&bytes).unwrap_err(),
+        EsrtError::BadCount { count: 3, max: 2 }
+    );
+
+    bytes[..16].copy_from_slice(&header(3, 3, 2));
+    assert_eq!(
+        Esrt::parse(&bytes).unwrap_err(),
+        EsrtError::UnsupportedVersion(2)
+    );
+
+    let empty = header(0, 0, ESRT_VERSION);
+    assert!(Esrt::parse(&empty).unwrap().is_empty());
+}
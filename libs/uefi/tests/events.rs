@@ -0,0 +1,266 @@
+#![feature(abi_efiapi)]
+
+use std::cell::RefCell;
+use uefi::*;
+
+const NOT_READY: usize = 0x8000_0000_0000_0006;
+const TIMER: usize = 0x7133;
+const KEY_EVENT: usize = 0x4b45;
+const KEY: InputKey = InputKey {
+    scan_code: 0,
+    unicode_char: b'x' as u16,
+};
+
+/// Firmware with a clock that only moves while waiting
+#[derive(Default)]
+struct Firmware {
+    now_us: u64,
+    /// When the key is pressed, if ever
+    key_at_us: Option<u64>,
+    /// Fire time of the armed timer, in 100ns units
+    timer_at: Option<u64>,
+    timers_armed: Vec<u64>,
+    waits: usize,
+    open_events: usize,
+}
+
+thread_local! {
+    static FIRMWARE: RefCell<Firmware> = RefCell::new(Firmware::default());
+}
+
+struct MockClock;
+
+impl Clock for MockClock {
+    fn now_us(&self) -> u64 {
+        FIRMWARE.with(|f| f.borrow().now_us)
+    }
+    fn sleep_us(&self, _us: u64) {
+        unreachable!("waiting must go through WaitForEvent");
+    }
+}
+
+fn key_pending(f: &Firmware) -> bool {
+    f.key_at_us.map_or(false, |at| f.now_us >= at)
+}
+
+extern "efiapi" fn mock_create_event(
+    typ: u32,
+    _tpl: usize,
+    _notify: usize,
+    _context: usize,
+    event: &mut usize,
+) -> usize {
+    assert_eq!(typ, EVT_TIMER);
+    FIRMWARE.with(|f| f.borrow_mut().open_events += 1);
+    *event = TIMER;
+    return 0;
+}
+
+extern "efiapi" fn mock_set_timer(event: usize, delay: u32, trigger: u64) -> usize {
+    assert_eq!(event, TIMER);
+    assert_eq!(delay, TimerDelay::Relative as u32);
+    FIRMWARE.with(|f| {
+        let mut f = f.borrow_mut();
+        f.timer_at = Some(f.now_us * 10 + trigger);
+        f.timers_armed.push(trigger);
+    });
+    return 0;
+}
+
+extern "efiapi" fn mock_wait_for_event(
+    count: usize,
+    events: *const usize,
+    index: &mut usize,
+) -> usize {
+    let events = unsafe { std::slice::from_raw_parts(events, count) };
+    FIRMWARE.with(|f| {
+        let mut f = f.borrow_mut();
+        f.waits += 1;
+        let timer_us = f.timer_at.take().expect("waiting on an unarmed timer") / 10;
+        let key = events.iter().position(|&e| e == KEY_EVENT);
+        match (key, f.key_at_us) {
+            (Some(i), Some(at)) if at <= timer_us => {
+                f.now_us = f.now_us.max(at);
+                *index = i;
+            }
+            _ => {
+                f.now_us = timer_us;
+                *index = events.iter().position(|&e| e == TIMER).unwrap();
+            }
+        }
+    });
+    return 0;
+}
+
+extern "efiapi" fn mock_close_event(event: usize) -> usize {
+    assert_eq!(event, TIMER);
+    FIRMWARE.with(|f| f.borrow_mut().open_events -= 1);
+    return 0;
+}
+
+extern "efiapi" fn mock_read_key_stroke(_this: &SimpleTextInput, key: &mut InputKey) -> usize {
+    FIRMWARE.with(|f| {
+        let mut f = f.borrow_mut();
+        if !key_pending(&f) {
+            return NOT_READY;
+        }
+        f.key_at_us = None;
+        *key = KEY;
+        return 0;
+    })
+}
+
+/* Same layout as BootServices */
+#[repr(C)]
+struct MockBootServices {
+    header: [u64; 3],
+    services: [usize; 38],
+}
+
+/* Same layout as SimpleTextInput */
+#[repr(C)]
+struct MockTextInput {
+    reset: usize,
+    read_key_stroke: usize,
+    wait_for_key: usize,
+}
+
+/// Serial console that gets a byte at `at_us`
+struct MockSerial {
+    at_us: u64,
+    polls: usize,
+}
+
+impl PolledInput for MockSerial {
+    fn poll(&mut self) -> Option<u8> {
+        self.polls += 1;
+        if MockClock.now_us() >= self.at_us {
+            return Some(b'\r');
+        }
+        return None;
+    }
+}
+
+fn with_firmware(
+    key_at_us: Option<u64>,
+    f: impl FnOnce(&BootServices, &SimpleTextInput),
+) -> Firmware {
+    let mut bs = MockBootServices {
+        header: [0; 3],
+        services: [0; 38],
+    };
+    bs.services[7] = mock_create_event as usize;
+    bs.services[8] = mock_set_timer as usize;
+    bs.services[9] = mock_wait_for_event as usize;
+    bs.services[11] = mock_close_event as usize;
+    let con_in = MockTextInput {
+        reset: 0,
+        read_key_stroke: mock_read_key_stroke as usize,
+        wait_for_key: KEY_EVENT,
+    };
+
+    FIRMWARE.with(|fw| {
+        *fw.borrow_mut() = Firmware {
+            now_us: 1_000,
+            key_at_us,
+            ..Firmware::default()
+        }
+    });
+    f(
+        unsafe { &*(&bs as *const MockBootServices as *const BootServices) },
+        unsafe { &*(&con_in as *const MockTextInput as *const SimpleTextInput) },
+    );
+    let firmware = FIRMWARE.with(|fw| fw.replace(Firmware::default()));
+    assert_eq!(firmware.open_events, 0, "timer event leaked");
+    return firmware;
+}
+
+#[test]
+fn key_first() {
+    let firmware = with_firmware(Some(300_000), |bs, con_in| {
+        let input = wait_key_or_timeout(bs, Some(con_in), None, &MockClock, 1_001_000);
+        assert_eq!(input, Ok(MenuInput::Key(KEY)));
+    });
+    assert_eq!(firmware.now_us, 300_000);
+    assert_eq!(firmware.waits, 1);
+    assert_eq!(firmware.timers_armed, [10_000_000]);
+}
+
+#[test]
+fn timeout_first() {
+    let firmware = with_firmware(Some(2_000_000), |bs, con_in| {
+        let input = wait_key_or_timeout(bs, Some(con_in), None, &MockClock, 1_001_000);
+        assert_eq!(input, Ok(MenuInput::Timeout));
+    });
+    /* One wait for exactly the remaining time, no polling */
+    assert_eq!(firmware.now_us, 1_001_000);
+    assert_eq!(firmware.waits, 1);
+    assert_eq!(firmware.timers_armed, [10_000_000]);
+}
+
+#[test]
+fn key_already_pending() {
+    let firmware = with_firmware(Some(0), |bs, con_in| {
+        let input = wait_key_or_timeout(bs, Some(con_in), None, &MockClock, 1_001_000);
+        assert_eq!(input, Ok(MenuInput::Key(KEY)));
+    });
+    assert_eq!(firmware.waits, 0);
+}
+
+#[test]
+fn deadline_passed() {
+    let firmware = with_firmware(None, |bs, con_in| {
+        let input = wait_key_or_timeout(bs, Some(con_in), None, &MockClock, 500);
+        assert_eq!(input, Ok(MenuInput::Timeout));
+    });
+    assert_eq!(firmware.waits, 0);
+}
+
+#[test]
+fn serial_byte_first() {
+    let mut serial = MockSerial {
+        at_us: 36_000,
+        polls: 0,
+    };
+    let firmware = with_firmware(None, |bs, _| {
+        let input = wait_key_or_timeout(bs, None, Some(&mut serial), &MockClock, 1_001_000);
+        assert_eq!(input, Ok(MenuInput::Serial(b'\r')));
+    });
+    /* Checked every INPUT_POLL_INTERVAL_US, not spun on */
+    assert_eq!(firmware.now_us, 41_000);
+    assert_eq!(firmware.waits, 4);
+    assert_eq!(serial.polls, 5);
+    assert!(firmware
+        .timers_armed
+        .iter()
+        .all(|&t| t == INPUT_POLL_INTERVAL_US * 10));
+}
+
+#[test]
+fn serial_timeout_is_exact() {
+    let mut serial = MockSerial {
+        at_us: u64::MAX,
+        polls: 0,
+    };
+    let firmware = with_firmware(Some(100_000), |bs, con_in| {
+        let input = wait_key_or_timeout(bs, Some(con_in), Some(&mut serial), &MockClock, 26_000);
+        assert_eq!(input, Ok(MenuInput::Timeout));
+    });
+    assert_eq!(firmware.now_us, 26_000);
+    assert_eq!(firmware.timers_armed, [100_000, 100_000, 50_000]);
+}
+
+#[test]
+fn key_while_polling_serial() {
+    let mut serial = MockSerial {
+        at_us: u64::MAX,
+        polls: 0,
+    };
+    let firmware = with_firmware(Some(15_000), |bs, con_in| {
+        let input = wait_key_or_timeout(bs, Some(con_in), Some(&mut serial), &MockClock, 1_001_000);
+        assert_eq!(input, Ok(MenuInput::Key(KEY)));
+    });
+    /* The key event ends the wait, not the next poll */
+    assert_eq!(firmware.now_us, 15_000);
+    assert_eq!(firmware.waits, 2);
+}
@@ -0,0 +1,264 @@
+#![feature(abi_efiapi)]
+
+use std::cell::RefCell;
+use uefi::*;
+
+const NOT_FOUND: usize = 0x8000_0000_0000_000e;
+const PATH: &str = "\\boot\\sovos.cpio";
+const DUMP_PATH: &str = "\\crashdump.bin";
+/// Read, write and create
+const CREATE_MODE: u64 = 0x8000_0000_0000_0003;
+/// Firmware reads at most this much at once
+const CHUNK: usize = 1000;
+
+#[derive(Default)]
+struct Volume {
+    position: u64,
+    /// Handles not closed yet
+    open: usize,
+    /// Contents of `DUMP_PATH`
+    written: Vec<u8>,
+    flushed: bool,
+}
+
+thread_local! {
+    static VOLUME: RefCell<Volume> = RefCell::new(Volume::default());
+}
+
+fn contents() -> Vec<u8> {
+    (0..4321u32).map(|i| (i * 7) as u8).collect()
+}
+
+fn ucs2(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain([0]).collect()
+}
+
+/* Same layout as File */
+#[repr(C)]
+struct MockFile {
+    revision: u64,
+    open: extern "efiapi" fn(&MockFile, &mut *const MockFile, *const u16, u64, u64) -> usize,
+    close: extern "efiapi" fn(&MockFile) -> usize,
+    delete: usize,
+    read: extern "efiapi" fn(&MockFile, &mut usize, *mut u8) -> usize,
+    write: extern "efiapi" fn(&MockFile, &mut usize, *const u8) -> usize,
+    get_position: extern "efiapi" fn(&MockFile, &mut u64) -> usize,
+    set_position: extern "efiapi" fn(&MockFile, u64) -> usize,
+    get_info: usize,
+    set_info: usize,
+    flush: extern "efiapi" fn(&MockFile) -> usize,
+}
+
+/* Same layout as SimpleFileSystemProtocol */
+#[repr(C)]
+struct MockFileSystem {
+    revision: u64,
+    open_volume: extern "efiapi" fn(&MockFileSystem, &mut *const MockFile) -> usize,
+}
+
+const fn mock_file() -> MockFile {
+    MockFile {
+        revision: 0x0001_0000,
+        open: mock_open,
+        close: mock_close,
+        delete: 0,
+        read: mock_read,
+        write: mock_write,
+        get_position: mock_get_position,
+        set_position: mock_set_position,
+        get_info: 0,
+        set_info: 0,
+        flush: mock_flush,
+    }
+}
+
+static ROOT: MockFile = mock_file();
+static ARCHIVE: MockFile = mock_file();
+static DUMP: MockFile = mock_file();
+static FILE_SYSTEM: MockFileSystem = MockFileSystem {
+    revision: 0x0001_0000,
+    open_volume: mock_open_volume,
+};
+
+extern "efiapi" fn mock_open_volume(_this: &MockFileSystem, root: &mut *const MockFile) -> usize {
+    VOLUME.with(|v| v.borrow_mut().open += 1);
+    *root = &ROOT;
+    return 0;
+}
+
+extern "efiapi" fn mock_open(
+    this: &MockFile,
+    file: &mut *const MockFile,
+    path: *const u16,
+    mode: u64,
+    _attributes: u64,
+) -> usize {
+    assert!(std::ptr::eq(this, &ROOT));
+    let len = (0..).position(|i| unsafe { *path.add(i) } == 0).unwrap();
+    let path = String::from_utf16(unsafe { std::slice::from_raw_parts(path, len) }).unwrap();
+    let opened = match mode {
+        1 if path == PATH => &ARCHIVE,
+        CREATE_MODE if path == DUMP_PATH => &DUMP,
+        1 => return NOT_FOUND,
+        _ => panic!("opened {} with mode {:#x}", path, mode),
+    };
+
+    VOLUME.with(|v| {
+        let mut v = v.borrow_mut();
+        v.open += 1;
+        v.position = 0;
+    });
+    *file = opened;
+    return 0;
+}
+
+extern "efiapi" fn mock_close(_this: &MockFile) -> usize {
+    VOLUME.with(|v| v.borrow_mut().open -= 1);
+    return 0;
+}
+
+extern "efiapi" fn mock_read(this: &MockFile, size: &mut usize, buf: *mut u8) -> usize {
+    assert!(std::ptr::eq(this, &ARCHIVE));
+    let contents = contents();
+    VOLUME.with(|v| {
+        let mut v = v.borrow_mut();
+        let start = v.position as usize;
+        let len = (*size).min(CHUNK).min(contents.len() - start);
+        unsafe { buf.copy_from_nonoverlapping(contents[start..].as_ptr(), len) };
+        v.position += len as u64;
+        *size = len;
+    });
+    return 0;
+}
+
+extern "efiapi" fn mock_write(this: &MockFile, size: &mut usize, buf: *const u8) -> usize {
+    assert!(std::ptr::eq(this, &DUMP));
+    VOLUME.with(|v| {
+        let mut v = v.borrow_mut();
+        let len = (*size).min(CHUNK);
+        let data = unsafe { std::slice::from_raw_parts(buf, len) };
+        let start = v.position as usize;
+        let end = start + len;
+        if v.written.len() < end {
+            v.written.resize(end, 0);
+        }
+        v.written[start..end].copy_from_slice(data);
+        v.position += len as u64;
+        *size = len;
+    });
+    return 0;
+}
+
+extern "efiapi" fn mock_flush(this: &MockFile) -> usize {
+    assert!(std::ptr::eq(this, &DUMP));
+    VOLUME.with(|v| v.borrow_mut().flushed = true);
+    return 0;
+}
+
+extern "efiapi" fn mock_get_position(_this: &MockFile, position: &mut u64) -> usize {
+    *position = VOLUME.with(|v| v.borrow().position);
+    return 0;
+}
+
+extern "efiapi" fn mock_set_position(_this: &MockFile, position: u64) -> usize {
+    let end = contents().len() as u64;
+    let position = if position == u64::MAX { end } else { position };
+    VOLUME.with(|v| v.borrow_mut().position = position);
+    return 0;
+}
+
+fn file_system() -> &'static SimpleFileSystemProtocol {
+    unsafe { &*(&FILE_SYSTEM as *const MockFileSystem as *const SimpleFileSystemProtocol) }
+}
+
+#[test]
+fn read_whole_file() {
+    let root = file_system().open_volume().unwrap();
+    let file = root.open(&ucs2(PATH)).unwrap();
+
+    let size = file.size().unwrap();
+    assert_eq!(size, contents().len() as u64);
+    assert_eq!(file.position(), Ok(0));
+
+    /* Takes more than one call */
+    let mut data = vec![0u8; size as usize];
+    file.read_exact(&mut data).unwrap();
+    assert_eq!(data, contents());
+    assert_eq!(file.read(&mut [0; 8]), Ok(0));
+
+    file.close();
+    root.close();
+    assert_eq!(VOLUME.with(|v| v.borrow().open), 0);
+}
+
+#[test]
+fn write_whole_file() {
+    let root = file_system().open_volume().unwrap();
+    let file = root.create(&ucs2(DUMP_PATH)).unwrap();
+
+    /* Takes more than one call too */
+    file.write_all(&contents()).unwrap();
+    file.flush().unwrap();
+    assert_eq!(file.position(), Ok(contents().len() as u64));
+
+    file.close();
+    root.close();
+    VOLUME.with(|v| {
+        let v = v.borrow();
+        assert_eq!(v.written, contents());
+        assert!(v.flushed);
+        assert_eq!(v.open, 0);
+    });
+}
+
+#[test]
+fn errors() {
+    let root = file_system().open_volume().unwrap();
+    assert!(matches!(root.open(&ucs2("\\kernel.elf")), Err(Error::NotFound)));
+
+    /* Not passed to firmware without the NUL */
+    let path = ucs2(PATH);
+    assert!(matches!(
+        root.open(&path[..path.len() - 1]),
+        Err(Error::InvalidParameter)
+    ));
+
+    let file = root.open(&path).unwrap();
+    file.set_position(4000).unwrap();
+    let mut rest = [0u8; 400];
+    assert_eq!(file.read_exact(&mut rest), Err(Error::EndOfFile));
+
+    file.close();
+    root.close();
+    assert_eq!(VOLUME.with(|v| v.borrow().open), 0);
+}
+
+#[test]
+fn last_load_option() {
+    let last = |options: &str| {
+        let bytes: Vec<u8> = options.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let mut image: LoadedImage = unsafe { core::mem::zeroed() };
+        image.load_options = bytes.as_ptr();
+        image.load_options_size = bytes.len() as u32;
+
+        let mut buf = [0u16; 32];
+        let word = image.last_load_option(&mut buf).map(|w| {
+            assert_eq!(w.last(), Some(&0));
+            String::from_utf16(&w[..w.len() - 1]).unwrap()
+        });
+        return word;
+    };
+
+    /* From a boot entry and from the shell */
+    assert_eq!(last("\\sovos.cpio").as_deref(), Some("\\sovos.cpio"));
+    assert_eq!(
+        last("sovos.efi  \\boot\\sovos.cpio \0junk").as_deref(),
+        Some("\\boot\\sovos.cpio")
+    );
+
+    assert_eq!(last(""), None);
+    assert_eq!(last("   \0\\sovos.cpio"), None);
+    /* The NUL needs room too */
+    assert_eq!(last(&"x".repeat(31)).as_deref(), Some(&*"x".repeat(31)));
+    assert_eq!(last(&"x".repeat(32)), None);
+}
@@ -0,0 +1,42 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use uefi::{Clock, YieldLimiter};
+
+#[derive(Clone)]
+struct MockClock(Rc<Cell<u64>>);
+
+impl Clock for MockClock {
+    fn now_us(&self) -> u64 {
+        self.0.get()
+    }
+    fn sleep_us(&self, us: u64) {
+        self.0.set(self.0.get() + us);
+    }
+}
+
+#[test]
+fn rate_limited() {
+    let clock = MockClock(Rc::new(Cell::new(500)));
+    let mut limiter = YieldLimiter::new(clock.clone(), 2_000);
+
+    /* Chunks processed faster than the interval don't yield */
+    assert!(!limiter.due());
+    clock.sleep_us(1_999);
+    assert!(!limiter.due());
+    clock.sleep_us(1);
+    assert!(limiter.due());
+    assert!(!limiter.due());
+
+    /* A slow chunk yields once, not once per missed interval */
+    clock.sleep_us(10_000);
+    assert!(limiter.due());
+    assert!(!limiter.due());
+}
+
+#[test]
+fn zero_interval() {
+    let clock = MockClock(Rc::new(Cell::new(0)));
+    let mut limiter = YieldLimiter::new(clock, 0);
+    assert!(limiter.due());
+    assert!(limiter.due());
+}
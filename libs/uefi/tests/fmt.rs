@@ -0,0 +1,40 @@
+use uefi::memory::{DescriptorIterator, Type};
+use uefi::{Error, Guid, RawStatus};
+
+#[test]
+fn guids() {
+    assert_eq!(
+        format!("{}", Guid::EFI_ACPI_20_TABLE),
+        "8868e871-e4f1-11d3-bc22-0080c73c8881"
+    );
+    assert_eq!(
+        format!("{:?}", Guid::SMBIOS3_TABLE),
+        "Guid(f2fd1544-9794-4a2c-992e-e5bbcf20e394 SMBIOS3_TABLE)"
+    );
+}
+
+#[test]
+fn statuses() {
+    assert_eq!(format!("{:?}", Error::NotFound), "NotFound (0xe)");
+    assert_eq!(format!("{:?}", RawStatus(0)), "Success (0x0)");
+    assert_eq!(
+        format!("{:?}", RawStatus(0x8000_0000_0000_000e)),
+        "NotFound (0x800000000000000e)"
+    );
+    assert_eq!(format!("{:?}", RawStatus(4)), "BufferTooSmall (0x4)");
+}
+
+#[test]
+fn memory_descriptors() {
+    assert_eq!(format!("{:?}", Type::Conventional), "Conventional (0x7)");
+
+    /* typ, padding, phys_start, virt_start, pages, attributes */
+    let buf = [7u64, 0x10_0000, 0, 0x300, (1 << 3) | (1 << 63)];
+    let desc = DescriptorIterator::new(&buf, 40).next().unwrap();
+    assert_eq!(
+        format!("{:?}", desc),
+        "Descriptor { typ: Conventional (0x7), phys_start: 0x0000_0000_0010_0000, \
+         virt_start: 0x0000_0000_0000_0000, pages: 768, size: 3 MiB, \
+         attributes: write_back | runtime }"
+    );
+}
@@ -0,0 +1,213 @@
+#![feature(abi_efiapi)]
+
+use std::cell::RefCell;
+use uefi::*;
+
+const NOT_FOUND: usize = 0x8000_0000_0000_000e;
+const UNSUPPORTED: usize = 0x8000_0000_0000_0003;
+
+const ESP: usize = 0x10;
+const USB_CONTROLLER: usize = 0x20;
+const USB_STICK: usize = 0x30;
+
+static FS_INTERFACE: [u64; 2] = [0x0001_0000, 0];
+
+/// The ESP is there from the start, the USB stick's volume only appears
+/// once a driver is connected to its controller
+#[derive(Default)]
+struct Firmware {
+    usb_connected: bool,
+    /// Buffers handed out by LocateHandleBuffer and not freed yet
+    buffers: Vec<(usize, usize)>,
+    handle_protocol_calls: usize,
+}
+
+thread_local! {
+    static FIRMWARE: RefCell<Firmware> = RefCell::new(Firmware::default());
+}
+
+fn handles_of(f: &Firmware, protocol: Option<&Guid>) -> Vec<usize> {
+    let mut handles = match protocol {
+        None => vec![ESP, USB_CONTROLLER],
+        Some(&Guid::EFI_SIMPLE_FILE_SYSTEM_PROTOCOL) => vec![ESP],
+        Some(_) => vec![],
+    };
+    if f.usb_connected {
+        handles.push(USB_STICK);
+    }
+    return handles;
+}
+
+extern "efiapi" fn mock_locate_handle_buffer(
+    typ: u32,
+    protocol: *const Guid,
+    _key: *const u8,
+    len: &mut usize,
+    buf: &mut *mut usize,
+) -> usize {
+    FIRMWARE.with(|f| {
+        let mut f = f.borrow_mut();
+        let protocol = match typ {
+            0 => None,
+            2 => Some(unsafe { &*protocol }),
+            _ => panic!("search type {}", typ),
+        };
+        let handles = handles_of(&f, protocol);
+        if handles.is_empty() {
+            return NOT_FOUND;
+        }
+        let handles = Box::leak(handles.into_boxed_slice());
+        *len = handles.len();
+        *buf = handles.as_mut_ptr();
+        f.buffers.push((*buf as usize, *len));
+        return 0;
+    })
+}
+
+extern "efiapi" fn mock_free_pool(buf: *mut u8) -> usize {
+    FIRMWARE.with(|f| {
+        let mut f = f.borrow_mut();
+        let i = f
+            .buffers
+            .iter()
+            .position(|&(ptr, _)| ptr == buf as usize)
+            .expect("freeing memory that isn't from the pool");
+        let (ptr, len) = f.buffers.remove(i);
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr as *mut usize, len)) });
+        return 0;
+    })
+}
+
+extern "efiapi" fn mock_connect_controller(
+    handle: usize,
+    _drivers: *const usize,
+    _path: *const u8,
+    recursive: bool,
+) -> usize {
+    assert!(recursive);
+    if handle != USB_CONTROLLER {
+        return NOT_FOUND;
+    }
+    FIRMWARE.with(|f| f.borrow_mut().usb_connected = true);
+    return 0;
+}
+
+extern "efiapi" fn mock_handle_protocol(
+    handle: usize,
+    guid: &Guid,
+    interface: &mut *const u8,
+) -> usize {
+    FIRMWARE.with(|f| {
+        let mut f = f.borrow_mut();
+        f.handle_protocol_calls += 1;
+        /* Block I/O is listed, but its driver fails to hand it out */
+        if *guid != Guid::EFI_SIMPLE_FILE_SYSTEM_PROTOCOL
+            || !handles_of(&f, Some(guid)).contains(&handle)
+        {
+            return UNSUPPORTED;
+        }
+        *interface = FS_INTERFACE.as_ptr() as *const u8;
+        return 0;
+    })
+}
+
+/* Same layout as BootServices */
+#[repr(C)]
+struct MockBootServices {
+    header: [u64; 3],
+    services: [usize; 38],
+}
+
+fn with_boot_services(f: impl FnOnce(&BootServices)) -> Firmware {
+    let mut bs = MockBootServices {
+        header: [0; 3],
+        services: [0; 38],
+    };
+    bs.services[6] = mock_free_pool as usize;
+    bs.services[16] = mock_handle_protocol as usize;
+    bs.services[30] = mock_connect_controller as usize;
+    bs.services[36] = mock_locate_handle_buffer as usize;
+
+    FIRMWARE.with(|fw| *fw.borrow_mut() = Firmware::default());
+    f(unsafe { &*(&bs as *const MockBootServices as *const BootServices) });
+    let firmware = FIRMWARE.with(|fw| fw.replace(Firmware::default()));
+    assert!(firmware.buffers.is_empty(), "handle buffer leaked");
+    return firmware;
+}
+
+#[test]
+fn enumerate() {
+    with_boot_services(|bs| {
+        let mut volumes = HandleSet::<FileSystem>::new(bs);
+        assert!(volumes.is_empty());
+        assert_eq!(volumes.enumerate(), Ok(1));
+        assert_eq!(volumes.generation(), 1);
+
+        let esp = volumes.get(0).unwrap();
+        assert_eq!(esp.generation(), 1);
+        assert_eq!(volumes.iter().collect::<Vec<_>>(), [esp]);
+        let fs = volumes.open(esp).unwrap();
+        assert_eq!(fs.revision, 0x0001_0000);
+        assert!(volumes.get(1).is_none());
+    });
+}
+
+#[test]
+fn usb_stick_after_reconnect() {
+    let firmware = with_boot_services(|bs| {
+        let mut volumes = HandleSet::<FileSystem>::new(bs);
+        assert_eq!(volumes.enumerate(), Ok(1));
+        let esp = volumes.get(0).unwrap();
+
+        assert_eq!(reconnect_all(bs), Ok(1));
+        assert_eq!(volumes.enumerate(), Ok(2));
+        let stick = volumes.get(1).unwrap();
+        assert!(volumes.open(stick).is_ok());
+
+        /* Same raw handle, but from before the reconnect */
+        assert_eq!(volumes.open(esp).err(), Some(HandleError::StaleHandle));
+        assert_eq!(volumes.check(esp), Err(HandleError::StaleHandle));
+        assert_ne!(volumes.get(0), Some(esp));
+        assert!(volumes.open(volumes.get(0).unwrap()).is_ok());
+    });
+    /* Firmware never saw the stale handle */
+    assert_eq!(firmware.handle_protocol_calls, 2);
+}
+
+#[test]
+fn stale_after_enumerating_again() {
+    with_boot_services(|bs| {
+        let mut volumes = HandleSet::<FileSystem>::new(bs);
+        volumes.enumerate().unwrap();
+        let esp = volumes.get(0).unwrap();
+        volumes.enumerate().unwrap();
+        assert_eq!(volumes.open(esp).err(), Some(HandleError::StaleHandle));
+    });
+}
+
+#[test]
+fn no_handles() {
+    with_boot_services(|bs| {
+        let mut disks = HandleSet::<BlockDevice>::new(bs);
+        assert_eq!(disks.enumerate(), Ok(0));
+        assert!(disks.is_empty());
+        assert_eq!(disks.generation(), 1);
+    });
+}
+
+#[test]
+fn firmware_errors() {
+    let firmware = with_boot_services(|bs| {
+        let mut volumes = HandleSet::<FileSystem>::new(bs);
+        volumes.enumerate().unwrap();
+        reconnect_all(bs).unwrap();
+        let mut disks = HandleSet::<BlockDevice>::new(bs);
+        disks.enumerate().unwrap();
+        let stick = disks.get(0).unwrap();
+        assert_eq!(
+            disks.open(stick).err(),
+            Some(HandleError::Efi(Error::Unsupported))
+        );
+    });
+    assert_eq!(firmware.handle_protocol_calls, 1);
+}
@@ -0,0 +1,32 @@
+use cpu::PhysRange;
+use uefi::memory::{Attributes, Descriptor, Type};
+
+#[test]
+fn descriptor_ranges() {
+    let range = PhysRange::new(0x10_0000, 0x3000).unwrap();
+    let d = Descriptor::new(
+        Type::Conventional,
+        range,
+        Attributes::new().set_write_back(),
+    )
+    .unwrap();
+    assert_eq!(d.pages, 3);
+    assert_eq!(d.memory_type(), Some(Type::Conventional));
+    assert_eq!(d.phys_range(), Some(range));
+    assert_eq!(d.virt_range().unwrap().start(), 0x10_0000);
+
+    let unaligned = PhysRange::new(0x10_0800, 0x1000).unwrap();
+    assert!(Descriptor::new(Type::Conventional, unaligned, Attributes::new()).is_none());
+}
+
+#[test]
+fn bogus_descriptor() {
+    let range = PhysRange::new(0xf_ffff_ffff_f000, 0x1000).unwrap();
+    let mut d = Descriptor::new(Type::Reserved, range, Attributes::new()).unwrap();
+    assert!(d.phys_range().is_some());
+
+    d.pages = 2;
+    assert_eq!(d.phys_range(), None);
+    d.pages = u64::MAX;
+    assert_eq!(d.virt_range(), None);
+}
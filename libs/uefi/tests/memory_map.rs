@@ -0,0 +1,166 @@
+use std::cell::{Cell, RefCell};
+use std::mem::MaybeUninit;
+use uefi::memory::MapKey;
+use uefi::{Error, MapCall, MemoryMapBuffer, MemoryMapFirmware};
+
+const DESCRIPTOR_SIZE: usize = 48;
+
+/// Firmware whose map has `descriptors` descriptors and which says
+/// BUFFER_TOO_SMALL to the first `too_small` calls even when the buffer
+/// fits, like one whose map grows between calls
+struct MockFirmware {
+    descriptors: Cell<usize>,
+    too_small: Cell<u32>,
+    /// Kept alive for the buffers handed out
+    pages: RefCell<Vec<Vec<u64>>>,
+}
+
+impl MockFirmware {
+    fn new(descriptors: usize, too_small: u32) -> Self {
+        Self {
+            descriptors: Cell::new(descriptors),
+            too_small: Cell::new(too_small),
+            pages: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn map_size(&self) -> usize {
+        self.descriptors.get() * DESCRIPTOR_SIZE
+    }
+}
+
+impl MemoryMapFirmware for MockFirmware {
+    fn raw_memory_map(
+        &self,
+        buf: &mut [MaybeUninit<u64>],
+        size: &mut usize,
+    ) -> Result<(MapKey, usize), Error> {
+        let needed = self.map_size();
+        let fits = *size >= needed;
+        *size = needed;
+        if !fits || self.too_small.get() > 0 {
+            self.too_small.set(self.too_small.get().saturating_sub(1));
+            return Err(Error::BufferTooSmall);
+        }
+
+        for i in 0..self.descriptors.get() {
+            let words = &mut buf[i * DESCRIPTOR_SIZE / 8..][..DESCRIPTOR_SIZE / 8];
+            for w in words.iter_mut() {
+                *w = MaybeUninit::new(0);
+            }
+            words[0] = MaybeUninit::new(7);
+            words[1] = MaybeUninit::new(0x10_0000 * (i as u64 + 1));
+            words[3] = MaybeUninit::new(1);
+        }
+        return Ok((MapKey::new(self.descriptors.get() as u64), DESCRIPTOR_SIZE));
+    }
+
+    fn allocate_map_pages(&self, pages: usize) -> Result<u64, Error> {
+        let mut buf = vec![0u64; pages * 512];
+        let addr = buf.as_mut_ptr() as u64;
+        self.pages.borrow_mut().push(buf);
+
+        /* Like real firmware, allocating adds a descriptor */
+        self.descriptors.set(self.descriptors.get() + 1);
+        return Ok(addr);
+    }
+}
+
+fn fetch(buffer: &mut MemoryMapBuffer, firmware: &MockFirmware) -> (Result<MapKey, Error>, Vec<MapCall>) {
+    let mut calls = Vec::new();
+    let result = buffer.fetch(firmware, |call| calls.push(call));
+    return (result, calls);
+}
+
+#[test]
+fn probes_then_reuses() {
+    let firmware = MockFirmware::new(20, 0);
+    let mut buffer = MemoryMapBuffer::new();
+    assert_eq!(buffer.capacity(), 0);
+
+    let (result, calls) = fetch(&mut buffer, &firmware);
+    assert_eq!(result, Ok(MapKey::new(21)));
+    assert_eq!(calls.len(), 2);
+    assert!(calls[0].too_small);
+    assert_eq!(calls[0].bytes, 20 * DESCRIPTOR_SIZE);
+    assert_eq!(calls[1].bytes, 21 * DESCRIPTOR_SIZE);
+    assert_eq!(buffer.allocations, 1);
+    assert_eq!(buffer.descriptors().count(), 21);
+
+    /* A retry after a failed exit fits in the same buffer */
+    let (result, calls) = fetch(&mut buffer, &firmware);
+    assert_eq!(result, Ok(MapKey::new(21)));
+    assert_eq!(calls.len(), 1);
+    assert_eq!(buffer.allocations, 1);
+    assert_eq!(buffer.calls, 3);
+    let starts: Vec<u64> = buffer.descriptors().map(|d| d.phys_start).collect();
+    assert_eq!(starts[20], 21 * 0x10_0000);
+}
+
+#[test]
+fn grows_only_when_too_small() {
+    let firmware = MockFirmware::new(20, 0);
+    let mut buffer = MemoryMapBuffer::new();
+    fetch(&mut buffer, &firmware).0.unwrap();
+    let capacity = buffer.capacity();
+
+    /* Growing within the slack needs nothing new */
+    firmware.descriptors.set(21 + MemoryMapBuffer::SLACK / DESCRIPTOR_SIZE);
+    let (result, _) = fetch(&mut buffer, &firmware);
+    assert!(result.is_ok());
+    assert_eq!((buffer.allocations, buffer.capacity()), (1, capacity));
+
+    /* Past it the buffer is replaced once */
+    firmware.descriptors.set(capacity / DESCRIPTOR_SIZE + 1);
+    let (result, calls) = fetch(&mut buffer, &firmware);
+    assert!(result.is_ok());
+    assert_eq!(calls.iter().filter(|c| c.too_small).count(), 1);
+    assert_eq!(buffer.allocations, 2);
+    assert!(buffer.capacity() > capacity);
+}
+
+#[test]
+fn presized_skips_probe() {
+    let firmware = MockFirmware::new(20, 0);
+    let mut buffer = MemoryMapBuffer::with_capacity(&firmware, 16384).unwrap();
+    assert_eq!(buffer.capacity(), 16384);
+
+    let (result, calls) = fetch(&mut buffer, &firmware);
+    assert!(result.is_ok());
+    assert_eq!(calls.len(), 1);
+    assert!(!calls[0].too_small);
+    assert_eq!((buffer.calls, buffer.allocations), (1, 1));
+}
+
+#[test]
+fn repeated_too_small() {
+    /* A few in a row are ridden out */
+    let firmware = MockFirmware::new(20, 3);
+    let mut buffer = MemoryMapBuffer::new();
+    let (result, calls) = fetch(&mut buffer, &firmware);
+    assert!(result.is_ok());
+    assert_eq!(calls.len(), 4);
+    assert_eq!(buffer.allocations, 3);
+
+    /* Firmware that never has enough is given up on */
+    let firmware = MockFirmware::new(20, 100);
+    let mut buffer = MemoryMapBuffer::new();
+    let (result, calls) = fetch(&mut buffer, &firmware);
+    assert_eq!(result, Err(Error::BufferTooSmall));
+    assert_eq!(calls.len(), 5);
+    assert_eq!(buffer.descriptors().count(), 0);
+}
+
+#[test]
+fn refetch_never_allocates() {
+    let firmware = MockFirmware::new(20, 0);
+    let mut buffer = MemoryMapBuffer::new();
+    fetch(&mut buffer, &firmware).0.unwrap();
+
+    let mut calls = 0;
+    assert!(buffer.refetch(&firmware, |_| calls += 1).is_ok());
+    firmware.descriptors.set(buffer.capacity() / DESCRIPTOR_SIZE + 1);
+    assert_eq!(buffer.refetch(&firmware, |_| calls += 1), Err(Error::BufferTooSmall));
+    assert_eq!(calls, 2);
+    assert_eq!(buffer.allocations, 1);
+}
@@ -0,0 +1,86 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use uefi::{poll_until, Clock, PolledInput, TimedOut};
+
+/* Every reading moves time 1us on */
+struct MockClock(Rc<Cell<u64>>);
+
+impl Clock for MockClock {
+    fn now_us(&self) -> u64 {
+        let now = self.0.get();
+        self.0.set(now + 1);
+        return now;
+    }
+    fn sleep_us(&self, _us: u64) {
+        unreachable!("polling must not sleep");
+    }
+}
+
+/* A byte that shows up on the line right after `at` */
+struct Line {
+    now: Rc<Cell<u64>>,
+    at: Option<u64>,
+    polls: usize,
+}
+
+impl PolledInput for Line {
+    fn poll(&mut self) -> Option<u8> {
+        self.polls += 1;
+        match self.at {
+            Some(at) if self.now.get() > at => {
+                self.at = None;
+                Some(b'x')
+            }
+            _ => None,
+        }
+    }
+}
+
+fn setup(at: Option<u64>) -> (MockClock, Line) {
+    let now = Rc::new(Cell::new(0));
+    let line = Line {
+        now: now.clone(),
+        at,
+        polls: 0,
+    };
+    return (MockClock(now), line);
+}
+
+#[test]
+fn arrives() {
+    let (clock, mut line) = setup(Some(10));
+    assert_eq!(poll_until(&mut line, &clock, 100), Ok(b'x'));
+    assert!(line.polls < 100);
+}
+
+#[test]
+fn times_out() {
+    let (clock, mut line) = setup(None);
+    assert_eq!(poll_until(&mut line, &clock, 100), Err(TimedOut));
+    assert_eq!(line.polls, 101);
+}
+
+#[test]
+fn just_in_time() {
+    /* Arrives right after the clock read the deadline */
+    let (clock, mut line) = setup(Some(100));
+    assert_eq!(poll_until(&mut line, &clock, 100), Ok(b'x'));
+
+    /* A microsecond too late */
+    let (clock, mut line) = setup(Some(101));
+    assert_eq!(poll_until(&mut line, &clock, 100), Err(TimedOut));
+}
+
+#[test]
+fn deadline_passed() {
+    let (clock, mut line) = setup(None);
+    clock.0.set(500);
+    assert_eq!(poll_until(&mut line, &clock, 100), Err(TimedOut));
+    assert_eq!(line.polls, 1);
+}
+
+#[test]
+fn forever() {
+    let (clock, mut line) = setup(Some(1_000_000));
+    assert_eq!(poll_until(&mut line, &clock, u64::MAX), Ok(b'x'));
+}
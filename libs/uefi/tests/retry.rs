@@ -0,0 +1,113 @@
+use std::cell::{Cell, RefCell};
+use uefi::{retry_with, Backoff, Clock, Error, RetryError, RetryPolicy};
+
+/* Time only moves when someone sleeps */
+struct MockClock {
+    now: Cell<u64>,
+    sleeps: RefCell<Vec<u64>>,
+}
+
+impl MockClock {
+    fn new() -> Self {
+        Self {
+            now: Cell::new(1_000_000),
+            sleeps: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Clock for MockClock {
+    fn now_us(&self) -> u64 {
+        self.now.get()
+    }
+    fn sleep_us(&self, us: u64) {
+        self.now.set(self.now.get() + us);
+        self.sleeps.borrow_mut().push(us);
+    }
+}
+
+fn device_error(e: Error) -> bool {
+    e == Error::DeviceError
+}
+
+/* Fails with `err` `failures` times, then succeeds */
+fn flaky(
+    failures: usize,
+    err: Error,
+) -> (Cell<usize>, impl Fn(&Cell<usize>) -> Result<usize, Error>) {
+    let f = move |calls: &Cell<usize>| {
+        calls.set(calls.get() + 1);
+        if calls.get() <= failures {
+            return Err(err);
+        }
+        return Ok(calls.get());
+    };
+    (Cell::new(0), f)
+}
+
+#[test]
+fn succeeds_after_retries() {
+    let clock = MockClock::new();
+    let policy = RetryPolicy {
+        deadline_us: 10_000,
+        backoff: Backoff::Fixed(1000),
+    };
+    let (calls, f) = flaky(2, Error::DeviceError);
+    let mut retries = Vec::new();
+
+    let result = retry_with(
+        &clock,
+        policy,
+        device_error,
+        |attempt, e| retries.push((attempt, e)),
+        || f(&calls),
+    );
+
+    assert_eq!(result, Ok(3));
+    assert_eq!(retries, [(1, Error::DeviceError), (2, Error::DeviceError)]);
+    assert_eq!(*clock.sleeps.borrow(), [1000, 1000]);
+}
+
+#[test]
+fn non_retryable_status() {
+    let clock = MockClock::new();
+    let policy = RetryPolicy {
+        deadline_us: 10_000,
+        backoff: Backoff::None,
+    };
+    let (calls, f) = flaky(5, Error::Unsupported);
+
+    let result = retry_with(&clock, policy, device_error, |_, _| panic!(), || f(&calls));
+
+    assert_eq!(result, Err(RetryError::NonRetryable(Error::Unsupported)));
+    assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn deadline() {
+    let clock = MockClock::new();
+    let policy = RetryPolicy {
+        deadline_us: 10_000,
+        backoff: Backoff::Doubling(1000),
+    };
+    let (calls, f) = flaky(100, Error::DeviceError);
+
+    let result = retry_with(&clock, policy, device_error, |_, _| {}, || f(&calls));
+
+    /* 1 + 2 + 4 = 7ms, next 8ms delay would end past the deadline */
+    assert_eq!(result, Err(RetryError::Deadline(Error::DeviceError)));
+    assert_eq!(result.unwrap_err().status(), Error::DeviceError);
+    assert_eq!(*clock.sleeps.borrow(), [1000, 2000, 4000]);
+    assert_eq!(calls.get(), 4);
+}
+
+#[test]
+fn doubling_saturates() {
+    assert_eq!(Backoff::Doubling(3).delay_us(1), 3);
+    assert_eq!(Backoff::Doubling(3).delay_us(4), 24);
+    assert_eq!(Backoff::Doubling(3).delay_us(63), 3 << 62);
+    assert_eq!(Backoff::Doubling(3).delay_us(64), u64::MAX);
+    assert_eq!(Backoff::Doubling(3).delay_us(1000), u64::MAX);
+    assert_eq!(Backoff::Fixed(5).delay_us(1000), 5);
+    assert_eq!(Backoff::None.delay_us(1), 0);
+}
@@ -0,0 +1,61 @@
+use uefi::{requires_revision, Config, Error, Revision, RtPropertiesTable, SystemTable};
+
+fn system_table(major: u16, minor: u16, config: &[Config]) -> SystemTable {
+    let mut st: SystemTable = unsafe { core::mem::zeroed() };
+    st.header.revision = Revision::new(major, minor);
+    st.number_of_table_entries = config.len() as u64;
+    st.config_table = config.as_ptr();
+    return st;
+}
+
+fn needs_2_50(st: &SystemTable) -> Result<(), Error> {
+    requires_revision!(st, 2, 50);
+    return Ok(());
+}
+
+#[test]
+fn revision_tuple() {
+    let st = system_table(2, 31, &[]);
+    assert_eq!(st.uefi_revision(), (2, 31));
+}
+
+#[test]
+fn gating() {
+    assert_eq!(
+        needs_2_50(&system_table(2, 31, &[])),
+        Err(Error::Unsupported)
+    );
+    assert_eq!(
+        needs_2_50(&system_table(1, 10, &[])),
+        Err(Error::Unsupported)
+    );
+    assert_eq!(needs_2_50(&system_table(2, 50, &[])), Ok(()));
+    assert_eq!(needs_2_50(&system_table(2, 70, &[])), Ok(()));
+    assert_eq!(needs_2_50(&system_table(3, 0, &[])), Ok(()));
+}
+
+#[test]
+fn gated_config_tables() {
+    let props = RtPropertiesTable {
+        version: 1,
+        length: 8,
+        runtime_services_supported: 0xffff,
+    };
+    let config = [Config {
+        guid: uefi::Guid::EFI_RT_PROPERTIES_TABLE,
+        table: &props as *const RtPropertiesTable as usize,
+    }];
+
+    let old = system_table(2, 70, &config);
+    assert_eq!(old.rt_properties().err(), Some(Error::Unsupported));
+    assert_eq!(old.memory_attributes_table().err(), Some(Error::NotFound));
+
+    let new = system_table(2, 80, &config);
+    assert_eq!(
+        new.rt_properties().unwrap().runtime_services_supported,
+        0xffff
+    );
+
+    let empty = system_table(2, 80, &[]);
+    assert_eq!(empty.rt_properties().err(), Some(Error::NotFound));
+}
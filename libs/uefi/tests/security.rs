@@ -0,0 +1,87 @@
+#![feature(abi_efiapi)]
+
+use std::cell::RefCell;
+use uefi::*;
+
+const SECURITY_VIOLATION: usize = 0x8000_0000_0000_001a;
+const ACCESS_DENIED: usize = 0x8000_0000_0000_000f;
+
+#[derive(Default)]
+struct Seen {
+    path: usize,
+    file: Vec<u8>,
+    boot_policy: bool,
+}
+
+thread_local! {
+    static SEEN: RefCell<Seen> = RefCell::new(Seen::default());
+}
+
+/* Accepts files starting with "MZ" */
+extern "efiapi" fn mock_file_authentication(
+    _this: &Security2Protocol,
+    path: *const DevicePath,
+    file: *const u8,
+    size: usize,
+    boot_policy: bool,
+) -> usize {
+    let file = unsafe { std::slice::from_raw_parts(file, size) };
+    SEEN.with(|s| {
+        *s.borrow_mut() = Seen {
+            path: path as usize,
+            file: file.to_vec(),
+            boot_policy,
+        }
+    });
+    return if file.starts_with(b"MZ") {
+        0
+    } else {
+        SECURITY_VIOLATION
+    };
+}
+
+extern "sysv64" fn mock_verify(file: *const u8, size: u32) -> usize {
+    let file = unsafe { std::slice::from_raw_parts(file, size as usize) };
+    return if file.starts_with(b"MZ") {
+        0
+    } else {
+        ACCESS_DENIED
+    };
+}
+
+#[test]
+fn file_authentication() {
+    let functions = [mock_file_authentication as usize];
+    let security = unsafe { &*(&functions as *const usize as *const Security2Protocol) };
+    let path = DevicePath {
+        typ: 0x7f,
+        subtype: 0xff,
+        length: [4, 0],
+    };
+
+    assert_eq!(
+        security.file_authentication(Some(&path), b"MZ\x90\0", false),
+        Ok(())
+    );
+    SEEN.with(|s| {
+        let s = s.borrow();
+        assert_eq!(s.path, &path as *const _ as usize);
+        assert_eq!(s.file, b"MZ\x90\0");
+        assert!(!s.boot_policy);
+    });
+
+    assert_eq!(
+        security.file_authentication(None, b"\x7fELF", true),
+        Err(Error::SecurityViolation)
+    );
+    SEEN.with(|s| assert_eq!(s.borrow().path, 0));
+}
+
+#[test]
+fn shim_verify() {
+    let functions = [mock_verify as usize, 0, 0];
+    let shim = unsafe { &*(&functions as *const usize as *const ShimLock) };
+
+    assert_eq!(shim.verify(b"MZ"), Ok(()));
+    assert_eq!(shim.verify(b"\x7fELF"), Err(Error::AccessDenied));
+}
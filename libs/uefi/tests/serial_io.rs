@@ -0,0 +1,148 @@
+#![feature(abi_efiapi)]
+
+use std::cell::RefCell;
+use uefi::*;
+
+const TIMEOUT: usize = 0x8000_0000_0000_0012;
+const DEVICE_ERROR: usize = 0x8000_0000_0000_0007;
+
+#[derive(Default)]
+struct Device {
+    /* baud, parity, data bits, stop bits */
+    attributes: Option<(u64, u32, u8, u32)>,
+    written: Vec<u8>,
+    /// Bytes accepted per write before timing out
+    write_limit: Option<usize>,
+    input: Vec<u8>,
+    broken: bool,
+}
+
+thread_local! {
+    static DEVICE: RefCell<Device> = RefCell::new(Device::default());
+}
+
+extern "efiapi" fn mock_set_attributes(
+    _this: &SerialIoProtocol,
+    baud: u64,
+    _fifo_depth: u32,
+    _timeout: u32,
+    parity: u32,
+    data_bits: u8,
+    stop_bits: u32,
+) -> usize {
+    DEVICE.with(|d| d.borrow_mut().attributes = Some((baud, parity, data_bits, stop_bits)));
+    return 0;
+}
+
+extern "efiapi" fn mock_get_control(_this: &SerialIoProtocol, control: &mut u32) -> usize {
+    /* DTR, CTS, input buffer empty */
+    *control = 1 | 1 << 4 | 1 << 8;
+    return 0;
+}
+
+extern "efiapi" fn mock_write(_this: &SerialIoProtocol, size: &mut usize, buf: *const u8) -> usize {
+    DEVICE.with(|d| {
+        let mut d = d.borrow_mut();
+        if d.broken {
+            *size = 0;
+            return DEVICE_ERROR;
+        }
+        let len = d.write_limit.map_or(*size, |limit| limit.min(*size));
+        let bytes = unsafe { std::slice::from_raw_parts(buf, len) };
+        d.written.extend_from_slice(bytes);
+        let timed_out = len < *size;
+        *size = len;
+        return if timed_out { TIMEOUT } else { 0 };
+    })
+}
+
+extern "efiapi" fn mock_read(_this: &SerialIoProtocol, size: &mut usize, buf: *mut u8) -> usize {
+    DEVICE.with(|d| {
+        let mut d = d.borrow_mut();
+        let len = d.input.len().min(*size);
+        unsafe { buf.copy_from_nonoverlapping(d.input.as_ptr(), len) };
+        d.input.drain(..len);
+        let timed_out = len < *size;
+        *size = len;
+        return if timed_out { TIMEOUT } else { 0 };
+    })
+}
+
+/* Same layout as SerialIoProtocol */
+#[repr(C)]
+struct MockSerialIo {
+    revision: u32,
+    functions: [usize; 6],
+    mode: usize,
+}
+
+fn with_serial_io(f: impl FnOnce(&SerialIoProtocol, &RefCell<Device>)) {
+    let mut mock = MockSerialIo {
+        revision: 0x0001_0000,
+        functions: [0; 6],
+        mode: 0,
+    };
+    mock.functions[1] = mock_set_attributes as usize;
+    mock.functions[3] = mock_get_control as usize;
+    mock.functions[4] = mock_write as usize;
+    mock.functions[5] = mock_read as usize;
+
+    DEVICE.with(|d| {
+        *d.borrow_mut() = Device::default();
+        f(
+            unsafe { &*(&mock as *const MockSerialIo as *const SerialIoProtocol) },
+            d,
+        );
+    });
+}
+
+#[test]
+fn attributes() {
+    with_serial_io(|io, device| {
+        assert_eq!(
+            io.set_attributes(115_200, Parity::No, 8, StopBits::One),
+            Ok(())
+        );
+        assert_eq!(device.borrow().attributes, Some((115_200, 1, 8, 1)));
+
+        let control = io.get_control().unwrap();
+        assert!(control.data_terminal_ready());
+        assert!(control.clear_to_send());
+        assert!(control.input_buffer_empty());
+        assert!(!control.carrier_detect());
+    });
+}
+
+#[test]
+fn write() {
+    with_serial_io(|io, device| {
+        assert_eq!(io.write(b"hello\n"), Ok(6));
+        device.borrow_mut().write_limit = Some(2);
+        assert_eq!(io.write(b"world"), Ok(2));
+        assert_eq!(device.borrow().written, b"hello\nwo");
+
+        device.borrow_mut().broken = true;
+        assert_eq!(io.write(b"x"), Err(Error::DeviceError));
+    });
+}
+
+#[test]
+fn read() {
+    with_serial_io(|io, device| {
+        let mut buf = [0u8; 4];
+        assert_eq!(io.read(&mut buf), Ok(0));
+
+        device.borrow_mut().input = b"ab".to_vec();
+        assert_eq!(io.read(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"ab");
+        assert_eq!(io.read(&mut buf), Ok(0));
+    });
+}
+
+#[test]
+fn guid() {
+    assert_eq!(
+        format!("{}", Guid::EFI_SERIAL_IO_PROTOCOL),
+        "bb25cf6f-f1d4-11d2-9a0c-0090273fc1fd"
+    );
+}
@@ -0,0 +1,193 @@
+#![feature(abi_efiapi)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use uefi::*;
+
+const NOT_FOUND: usize = 0x8000_0000_0000_000e;
+const BUFFER_TOO_SMALL: usize = 0x8000_0000_0000_0005;
+
+thread_local! {
+    /* name -> (attributes, data) */
+    static STORE: RefCell<HashMap<Vec<u16>, (u32, Vec<u8>)>> = RefCell::new(HashMap::new());
+    static WRITES: RefCell<usize> = RefCell::new(0);
+}
+
+unsafe fn read_name(mut ptr: *const u16) -> Vec<u16> {
+    let mut name = Vec::new();
+    while *ptr != 0 {
+        name.push(*ptr);
+        ptr = ptr.add(1);
+    }
+    return name;
+}
+
+extern "efiapi" fn mock_get_variable(
+    name: *const u16,
+    vendor: *const Guid,
+    attributes: *mut u32,
+    size: *mut usize,
+    data: *mut u8,
+) -> usize {
+    unsafe {
+        assert!(*vendor == Guid::EFI_GLOBAL_VARIABLE);
+        let name = read_name(name);
+        STORE.with(|store| {
+            let store = store.borrow();
+            let (attr, value) = match store.get(&name) {
+                Some(x) => x,
+                None => return NOT_FOUND,
+            };
+            if *size < value.len() {
+                *size = value.len();
+                return BUFFER_TOO_SMALL;
+            }
+            if !attributes.is_null() {
+                *attributes = *attr;
+            }
+            *size = value.len();
+            data.copy_from_nonoverlapping(value.as_ptr(), value.len());
+            return 0;
+        })
+    }
+}
+
+extern "efiapi" fn mock_set_variable(
+    name: *const u16,
+    vendor: *const Guid,
+    attributes: u32,
+    size: usize,
+    data: *const u8,
+) -> usize {
+    unsafe {
+        assert!(*vendor == Guid::EFI_GLOBAL_VARIABLE);
+        let name = read_name(name);
+        let value = std::slice::from_raw_parts(data, size).to_vec();
+        STORE.with(|store| store.borrow_mut().insert(name, (attributes, value)));
+        WRITES.with(|w| *w.borrow_mut() += 1);
+        return 0;
+    }
+}
+
+/* Same layout as RuntimeServices */
+#[repr(C)]
+struct MockRuntimeServices {
+    header: [u64; 3],
+    services: [usize; 14],
+}
+
+fn with_runtime_services(f: impl FnOnce(&RuntimeServices)) {
+    let mut mock = MockRuntimeServices {
+        header: [0; 3],
+        services: [0; 14],
+    };
+    mock.services[6] = mock_get_variable as usize;
+    mock.services[8] = mock_set_variable as usize;
+
+    STORE.with(|store| store.borrow_mut().clear());
+    WRITES.with(|w| *w.borrow_mut() = 0);
+    f(unsafe { &*(&mock as *const MockRuntimeServices as *const RuntimeServices) });
+}
+
+fn put(name: &[u16], data: &[u8]) {
+    let name = name[..name.len() - 1].to_vec();
+    STORE.with(|store| store.borrow_mut().insert(name, (0x7, data.to_vec())));
+}
+
+fn get(name: &[u16]) -> Option<(u32, Vec<u8>)> {
+    let name = name[..name.len() - 1].to_vec();
+    STORE.with(|store| store.borrow().get(&name).cloned())
+}
+
+fn writes() -> usize {
+    WRITES.with(|w| *w.borrow())
+}
+
+#[test]
+fn names() {
+    let expected: Vec<u16> = "OsIndications\0".encode_utf16().collect();
+    assert_eq!(&OS_INDICATIONS[..], &expected[..]);
+    let expected: Vec<u16> = "OsIndicationsSupported\0".encode_utf16().collect();
+    assert_eq!(&OS_INDICATIONS_SUPPORTED[..], &expected[..]);
+}
+
+#[test]
+fn preserves_other_bits() {
+    with_runtime_services(|rt| {
+        put(&OS_INDICATIONS_SUPPORTED, &0x1fu64.to_le_bytes());
+        put(&OS_INDICATIONS, &0x0000_0001_0000_0004u64.to_le_bytes());
+
+        assert!(rt.os_indications_supported().unwrap().boot_to_fw_ui());
+        assert_eq!(rt.request_boot_to_fw_ui(), Ok(()));
+
+        let (attributes, data) = get(&OS_INDICATIONS).unwrap();
+        assert_eq!(attributes, 0x7);
+        assert_eq!(data, 0x0000_0001_0000_0005u64.to_le_bytes().to_vec());
+    });
+}
+
+#[test]
+fn creates_missing_variable() {
+    with_runtime_services(|rt| {
+        put(&OS_INDICATIONS_SUPPORTED, &0x1u64.to_le_bytes());
+
+        assert_eq!(rt.request_boot_to_fw_ui(), Ok(()));
+
+        let (attributes, data) = get(&OS_INDICATIONS).unwrap();
+        assert_eq!(attributes, 0x7);
+        assert_eq!(data.len(), 8);
+        assert_eq!(data, 1u64.to_le_bytes().to_vec());
+    });
+}
+
+#[test]
+fn unsupported() {
+    with_runtime_services(|rt| {
+        put(&OS_INDICATIONS_SUPPORTED, &0x1eu64.to_le_bytes());
+        assert_eq!(rt.request_boot_to_fw_ui(), Err(Error::Unsupported));
+        assert_eq!(writes(), 0);
+    });
+
+    with_runtime_services(|rt| {
+        assert_eq!(rt.request_boot_to_fw_ui(), Err(Error::NotFound));
+        assert_eq!(writes(), 0);
+    });
+}
+
+#[test]
+fn exact_sizes() {
+    with_runtime_services(|rt| {
+        put(&OS_INDICATIONS_SUPPORTED, &0x1u64.to_le_bytes());
+
+        /* A truncated variable is not silently extended */
+        put(&OS_INDICATIONS, &[4, 0, 0, 0]);
+        let result = rt.get_variable_u64(&OS_INDICATIONS, &Guid::EFI_GLOBAL_VARIABLE);
+        assert_eq!(result.err(), Some(Error::BadBufferSize));
+        assert_eq!(rt.request_boot_to_fw_ui(), Err(Error::BadBufferSize));
+        assert_eq!(writes(), 0);
+
+        put(&OS_INDICATIONS, &[0; 16]);
+        let result = rt.get_variable_u64(&OS_INDICATIONS, &Guid::EFI_GLOBAL_VARIABLE);
+        assert_eq!(result.err(), Some(Error::BufferTooSmall));
+    });
+}
+
+#[test]
+fn secure_boot_state() {
+    with_runtime_services(|rt| {
+        /* Pre-2.3.1 firmware has neither variable */
+        assert_eq!(rt.secure_boot_state(), Ok(SecureBootState::default()));
+
+        put(&SECURE_BOOT, &[1]);
+        put(&SETUP_MODE, &[0]);
+        let state = rt.secure_boot_state().unwrap();
+        assert!(state.secure_boot && !state.setup_mode);
+        assert!(state.is_enforcing());
+
+        put(&SETUP_MODE, &[1]);
+        assert!(!rt.secure_boot_state().unwrap().is_enforcing());
+
+        put(&SECURE_BOOT, &[1, 0]);
+        assert_eq!(rt.secure_boot_state(), Err(Error::BufferTooSmall));
+    });
+}
@@ -4,29 +4,60 @@
 #![feature(abi_efiapi)]
 #![feature(abi_x86_interrupt)]
 #![feature(asm)]
-#![feature(array_chunks)]
 #![feature(const_maybe_uninit_assume_init)]
 #![feature(panic_info_message)]
 #![feature(slice_ptr_len)]
 #![feature(naked_functions)]
 
-use uart_16550::SerialPort;
 
 use elf::{Elf, self};
-use cpu::{self, acpi};
-use bootinfo::Bootinfo;
+use cpu::{self, acpi, PhysAddr, PhysRange, PhysSlice};
+use bootinfo::{AbiContract, AbiNote, AllocPurpose, BootCapabilities, BootStage, Bootinfo, BootinfoBuilder, Config, EfiSerial, EntropyPool, EntropySource, KernelPermPolicy, Module, PinnedBootinfo, SerialSinks, TableSnapshot};
+use bootinfo::{parse_u64, MapGranularity, Trampoline, HANDOFF_FIXED_VIRT, HANDOFF_SECTION};
+use bootinfo::{BootLineage, BootServicesFrames, FrameAllocator, KernelImage, KernelImageError};
+use bootinfo::Condition;
+use bootinfo::{KernelFeatures, ABI_NOTE_NAME, ABI_NOTE_TYPE, STAGE_WATCHDOG_S};
+use bootinfo::{KernelRequirements, REQUIREMENTS_NOTE_TYPE};
+use bootinfo::{LowMemWhitelist, DEFAULT_POISON, LOW_MEMORY_END, NULL_GUARD_SIZE};
+use bootinfo::{Mitigation, MitigationError, Mitigations, Outcome, Setting};
+use bootinfo::{Verifier, VerifyError, VerifyOutcome, VerifyPolicy};
+use bootinfo::{TxRing, TX_RING_SIZE};
+#[cfg(feature = "load-stats")]
+use bootinfo::TimelineEvent;
+use bootinfo::{CpuInterruptFlag, DisarmFn, InterruptSources};
+use bootinfo::MicrocodeStatus;
+use bootinfo::{ReservedKind, Slot, SlotState};
+use bootinfo::{PreviousTrace, TraceConfig, TraceEvent, TraceWriter};
+use bootinfo::{CrashDumpConfig, CrashDumpHeader, CrashDumpRegion, CrashDumpState, CRASHDUMP_HEADER_SIZE, DEFAULT_CRASHDUMP_SIZE};
+#[cfg(feature = "load-stats")]
+use cpu::perf::{PerfCounters, PerfSnapshot};
+use cpu::phys::{IdentityMapping, PhysWrite, PhysWriter};
+use impl_bits::fmt::{Addr, Size};
 use uefi::{self, Verify};
 
+use core::convert::TryInto;
 use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
 //use core::ptr;
-use core::mem::MaybeUninit;
 
 #[repr(align(2097152))]
 struct PageAligned<T: ?Sized>(T);
 
 static KERNEL: &PageAligned<[u8]> = &PageAligned(*include_bytes!(env!("SOVOS_KERNEL_PATH")));
 static mut BOOTINFO: Bootinfo = Bootinfo::new();
-const KERNEL_VIRT_ADDR: u64 = 0xffff_ffff_c000_0000;
+static mut TABLE_SNAPSHOT: TableSnapshot = TableSnapshot::new();
+/// Started by `start_trace` if the config asks for one
+static mut TRACE: Option<TraceWriter<'static>> = None;
+/// Console output not yet sent, see `SerialSinks::use_tx_ring`
+static mut TX_RING: TxRing<TX_RING_SIZE> = TxRing::new();
+/// Where the page fault handler resumes a probing read, 0 when not probing,
+/// see `null_selftest`
+static NULL_PROBE: AtomicU64 = AtomicU64::new(0);
+/// CR2 of the fault `NULL_PROBE` caught
+static NULL_FAULT: AtomicU64 = AtomicU64::new(u64::MAX);
+/// Bytes of per-CPU data the kernel gets for every CPU
+const PERCPU_SIZE: u64 = 16 * 1024;
+const DEFAULT_INPUT_TIMEOUT_MS: u64 = 60_000;
 
 macro_rules! brint {
     ($($arg:tt)*) => {{
@@ -34,14 +65,38 @@ macro_rules! brint {
     }}
 }
 
+/// Logs a warning about a condition tolerated in the field. With `strict=1`
+/// it fails the boot instead, see `Bootinfo::tolerate`.
+macro_rules! warn_or_fail {
+    ($out:expr, $bootinfo:expr, $condition:expr, $($arg:tt)*) => {{
+        brint!($out, "WARNING: ");
+        brint!($out, $($arg)*);
+        if let Err(e) = $bootinfo.tolerate($condition) {
+            panic!("strict=1: {:?}", e);
+        }
+    }}
+}
+
+/// Console of the panic and fault handlers, ports as configured, on every
+/// port since we don't know which one works. What was queued goes out
+/// first, nothing else drains the ring anymore, then output is synchronous.
+fn crash_console() -> SerialSinks {
+    let mut out = unsafe { BOOTINFO.serial_sinks };
+    let _ = unsafe { out.init() };
+    unsafe { out.use_tx_ring(&TX_RING) };
+    out.flush();
+    return out.unbuffered();
+}
+
 #[panic_handler]
 fn panic_handler(info: &core::panic::PanicInfo) -> ! {
-    let mut out = unsafe { SerialPort::new(0x3F8) };
+    let mut out = crash_console();
 
     brint!(out, "\n\n!!! PANIK !!!\n");
     if let Some(location) = info.location() {
         brint!(out, "file: {:?}, line: {}\n", location.file(), location.line());
     }
+    trace(TraceEvent::Panic, [info.location().map_or(0, |x| x.line() as u64), 0]);
     if let Some(msg) = info.message() {
         let _ = out.write_fmt(*msg);
         let _ = out.write_char('\n');
@@ -52,52 +107,303 @@ fn panic_handler(info: &core::panic::PanicInfo) -> ! {
     }
 }
 
+extern "sysv64" fn page_fault(ii: &mut cpu::interrupt::Stack) {
+    let recover = NULL_PROBE.swap(0, Ordering::SeqCst);
+    if recover != 0 {
+        NULL_FAULT.store(cpu::Cr2::get().0.as_u64(), Ordering::SeqCst);
+        ii.instruction_pointer = recover;
+        return;
+    }
+
+    let mut out = crash_console();
+    brint!(out, "\nPAGE FAULT at {:#x}\n\n{:?}\n", cpu::Cr2::get().0.as_u64(), ii);
+    loop { cpu::halt() };
+}
+
+/// Reads virtual address 0 with the page fault handler set to skip the
+/// read. True if it faulted there, like it should with the null guard.
+fn null_selftest() -> bool {
+    NULL_FAULT.store(u64::MAX, Ordering::SeqCst);
+    unsafe {
+        asm!(
+            "lea {tmp}, [rip + 2f]",
+            "mov [{probe}], {tmp}",
+            "mov {tmp}, qword ptr [{null}]",
+            "2:",
+            probe = in(reg) &NULL_PROBE as *const AtomicU64,
+            null = in(reg) 0u64,
+            tmp = out(reg) _,
+            options(nostack),
+        );
+    }
+    /* Still armed, the read went through */
+    let armed = NULL_PROBE.swap(0, Ordering::SeqCst) != 0;
+    return !armed && NULL_FAULT.load(Ordering::SeqCst) == 0;
+}
+
 #[no_mangle]
 extern "efiapi" fn efi_main(handle: uefi::ImageHandle, st: *const uefi::SystemTable) -> uefi::RawStatus {
     cpu::disable_interrupts();
+    /* Where firmware's FPDT timestamps are counted back from */
+    let entry = bootinfo::timestamp();
 
     let st = unsafe { &*st };
-    let bootinfo = unsafe { &mut BOOTINFO };
-    let mut out = unsafe { SerialPort::new(0x3F8) };
-    static mut buf: [MaybeUninit<u64>; 1024] = unsafe { MaybeUninit::uninit().assume_init() };
-    out.init();
+    /* SAFETY: UEFI identity maps memory */
+    let mut pinned = unsafe { BootinfoBuilder::new(&mut BOOTINFO) };
+    /* SAFETY: the unpinned API below only touches plain fields */
+    let bootinfo = unsafe { pinned.get_mut() };
+    /* Only ttyS0 until the config is found. Without legacy serial
+     * nothing is printed, instead of hanging on a dead port */
+    let mut out = SerialSinks::new();
+    let _ = unsafe { out.init() };
 
     assert_eq!(st.verify(), Ok(()));
 
+    let (major, minor) = st.uefi_revision();
+    brint!(out, "sovos uefi_wrapper, UEFI revision {}.{}\n", major, minor);
+    bootinfo.uefi_revision = st.header.revision;
+    bootinfo.uefi_systable = st as *const _ as *mut _;
+    brint!(out, "Boot time: {:?}\n", bootinfo.boot_time());
+
+    /* Whether `boot_delay` can offer "reboot into firmware setup" */
+    let runtime_services = unsafe { &*st.runtime_services };
+    match runtime_services.os_indications_supported() {
+        Ok(x) if x.boot_to_fw_ui() => brint!(out, "Reboot into firmware setup: available\n"),
+        Ok(_) => brint!(out, "Reboot into firmware setup: unavailable, BOOT_TO_FW_UI not supported\n"),
+        Err(e) => brint!(out, "Reboot into firmware setup: unavailable, OsIndicationsSupported: {:?}\n", e),
+    }
+
     let boot_services = unsafe { &*st.boot_services.get() };
     //assert_eq!(boot_services.verify(), Ok(()));
 
+    /* Only deadlines of firmware retries depend on it, so a wild guess
+     * of a 1GHz TSC is fine if Stall doesn't work */
+    let clock = match uefi::TscClock::calibrate(boot_services) {
+        Ok(clock) => {
+            bootinfo.record(BootCapabilities::set_tsc_calibrated);
+            clock
+        }
+        Err(e) => {
+            brint!(out, "WARNING: can't calibrate TSC: {:?}\n", e);
+            uefi::TscClock::new(1000)
+        }
+    };
+    brint!(out, "TSC: {} MHz\n", clock.ticks_per_us());
+
     for cfg in st.config_slice() {
-        use uefi::Guid;
+        brint!(out, "{:?}\n", cfg);
+    }
 
-        if cfg.guid == Guid::EFI_ACPI_20_TABLE {
-            let rsdp: *const acpi::Rsdp = cfg.table as *const _;
-            unsafe {
-                let rsdp = &*rsdp;
-                assert!(rsdp.verify_checksum());
-
-                let xsdt: &acpi::Xsdt = acpi::Xsdt::from_raw(rsdp.xsdt);
-                let sdt_iter = xsdt.other_sdts
-                    .array_chunks::<8>()
-                    .map(|x| usize::from_ne_bytes(*x) as *const acpi::SdtHeader);
-
-                for sdt in sdt_iter {
-                    let sig = &(*sdt).signature;
-                    let sig = core::str::from_utf8_unchecked(sig);
-                    let oid = &(*sdt).oem_id;
-                    let oid = core::str::from_utf8_unchecked(oid);
-                    brint!(out, "\tsignature: {:?} oem_id: {:?}\n", sig, oid);
+    let mut kernel: &'static [u8] = &KERNEL.0;
+    let mut image_path = None;
+    let mut archive = None;
+    match boot_services.loaded_image(&handle) {
+        Ok(image) => {
+            image_path = image.file_path();
+            archive = archive_from_load_options(image);
+            if archive.is_none() {
+                archive = archive_from_path(&mut out, boot_services, image);
+            }
+            if let Some(archive) = archive {
+                if let Some(k) = modules_from_archive(&mut out, bootinfo, archive) {
+                    kernel = k;
                 }
             }
         }
-        brint!(out, "{:?}\n", cfg);
+        Err(e) => brint!(out, "Can't get EFI_LOADED_IMAGE_PROTOCOL: {:?}\n", e),
+    }
+
+    let config = config(bootinfo);
+    match SerialSinks::from_config(&config) {
+        Ok(mut sinks) => {
+            if let Err(e) = unsafe { sinks.init() } {
+                brint!(sinks, "WARNING: serial port disabled: {:?}\n", e);
+            }
+            out = sinks;
+        }
+        Err(e) => brint!(out, "WARNING: bad serial config: {:?}, staying on ttyS0\n", e),
+    }
+    let ports = out.ports;
+    for (i, port) in ports.iter().enumerate() {
+        if port.is_enabled() {
+            brint!(out, "ttyS{} at {:#x}: {:?}\n", i, port.base(), port.probe());
+        }
+    }
+    if out.uart_found() {
+        bootinfo.record(BootCapabilities::set_uart);
+    }
+    bootinfo.serial = out.legacy_port();
+    /* A legacy UART survives ExitBootServices, so it's preferred */
+    if out.needs_fallback() {
+        efi_serial_fallback(&mut out, boot_services);
+    }
+    brint!(out, "Serial console: ttyS{}\n", out.console_index());
+    if !out.needs_fallback() {
+        bootinfo.record(BootCapabilities::set_legacy_serial);
+        /* SAFETY: `out` is the only writer, copies of it are unbuffered */
+        unsafe { out.use_tx_ring(&TX_RING) };
+    }
+    boot_delay(&mut out, st, boot_services, &clock, &config);
+    /* Set at build time for CI images, which can't pass a config */
+    if config.flag("strict") || option_env!("SOVOS_STRICT") == Some("1") {
+        bootinfo.record(BootCapabilities::set_strict);
+        brint!(out, "Strict mode: warnings about tolerated conditions fail the boot\n");
+    }
+    read_acpi(&mut out, st, bootinfo);
+    bootinfo.prepend_firmware_boot(clock.ticks_per_us(), entry);
+    /* A typo must not silently drop `nx=require` */
+    let mitigations = match Mitigations::from_config(&config) {
+        Ok(x) => x,
+        Err(e) => panic!("bad mitigations config: {:?}", e),
+    };
+    let verify_policy = match VerifyPolicy::from_config(&config) {
+        Ok(x) => x,
+        Err(e) => panic!("bad verify config: {:?}", e),
+    };
+    bootinfo.dma_ceiling = match bootinfo::dma_ceiling(&config) {
+        Ok(x) => x.unwrap_or(0),
+        Err(e) => panic!("bad dma_ceiling: {:?}", e),
+    };
+    select_page_flags(&mut out, bootinfo, &mitigations);
+    start_trace(&mut out, boot_services, bootinfo, &config);
+    reserve_crash_dump(&mut out, boot_services, bootinfo, &config);
+    place_dma_modules(&mut out, boot_services, bootinfo);
+    let mut pinned = pinned.console(out.unbuffered());
+    let bootinfo = unsafe { pinned.get_mut() };
+    boot_stage(&mut out, bootinfo, Some(boot_services), BootStage::ConsoleReady);
+    load_microcode(&mut out, boot_services, bootinfo);
+    if config.flag("verbose") {
+        list_volumes(&mut out, boot_services);
+    }
+    let mut irq_sources = interrupt_sources(&mut out, boot_services);
+    if let Some(k) = kernel_slot(&mut out, runtime_services, bootinfo, &config, archive) {
+        kernel = k;
+    }
+    verify(&mut out, st, bootinfo, verify_policy, image_path, kernel);
+    let kernel_image = prepare_kernel_elf(&mut out, kernel);
+    let kernelelf = kernel_image.elf();
+    check_section_map(&mut out, bootinfo, kernelelf, config.flag("verbose"));
+    if let Some(id) = kernelelf.build_id() {
+        brint!(out, "Kernel build ID: {:02x?}\n", id);
+    }
+    let abi = negotiate_abi(&mut out, kernelelf, kernel);
+    bootinfo.abi = abi;
+    if abi.bootinfo_version != 0 {
+        bootinfo.record(BootCapabilities::set_abi_note);
+    }
+    let requirements = kernel_requirements(&mut out, kernelelf, kernel);
+    let kernel_pslice = load_kernel(&mut out, boot_services, clock, &mut pinned, &kernel_image);
+    let mut pinned = pinned.kernel(kernel_pslice);
+    let stack = requirements.alloc_stack(&mut BootServicesFrames::new(boot_services))
+        .expect("can't allocate the kernel stack");
+    trace_alloc(stack.start(), stack.len());
+    brint!(out, "Kernel stack: {:?}\n", stack);
+    unsafe { pinned.get_mut() }.kernel_stack = stack;
+    boot_stage(&mut out, unsafe { pinned.get_mut() }, Some(boot_services), BootStage::KernelLoaded);
+
+    /* The MADT knows better than the config, `cpus=` is for firmware without one */
+    let cpus = pinned.acpi.madt_cpus()
+        .or_else(|| config.get("cpus").and_then(parse_u64).map(|x| x as usize))
+        .unwrap_or(1);
+    if abi.features.percpu() {
+        match unsafe { pinned.as_mut().reserve_percpu(boot_services, cpus, PERCPU_SIZE) } {
+            Ok(slice) => {
+                brint!(out, "Per-CPU areas for {} CPUs: {:?}\n", cpus, slice);
+                trace_alloc(slice.addr().as_u64(), slice.len() as u64);
+                let bootinfo = unsafe { pinned.get_mut() };
+                bootinfo.record(BootCapabilities::set_percpu);
+                let range = PhysRange::new(slice.addr().as_u64(), slice.len() as u64).unwrap();
+                if !bootinfo.tag_dma(range, ReservedKind::PerCpu) {
+                    warn_or_fail!(out, bootinfo, Condition::DmaCeiling,
+                        "per-CPU areas at {:?} are above dma_ceiling, low memory is full\n", range);
+                }
+            }
+            Err(e) => {
+                brint!(out, "WARNING: can't reserve per-CPU areas: {:?}\n", e);
+                trace(TraceEvent::Error, [line!() as u64, 0]);
+            }
+        }
+    }
+    let handoff = prepare_handoff(&mut out, &pinned, kernelelf, kernel);
+    if let Some(trampoline) = &handoff {
+        unsafe { pinned.get_mut() }.record(BootCapabilities::set_handoff_trampoline);
+        /* Firmware's 1:1 page, nothing allocated it, unlike the kernel's `.handoff` */
+        if trampoline.virt() == HANDOFF_FIXED_VIRT {
+            let page = PhysRange::new(HANDOFF_FIXED_VIRT & !0xFFF, 0x1000).unwrap();
+            unsafe { pinned.get_mut() }.reserve_region(page, ReservedKind::Handoff);
+        }
+    }
+
+    /* Firmware was seen modifying our page tables before kernel entry */
+    let verify_tables = config.flag("verify_tables");
+    if verify_tables {
+        pinned.snapshot_tables(unsafe { &mut TABLE_SNAPSHOT });
     }
 
-    let (memkey, memmap) = boot_services.get_memory_map(unsafe { &mut buf }).unwrap();
-    let ok = unsafe { boot_services.exit_boot_services(handle, memkey) };
-    assert_eq!(ok, Ok(()));
+    boot_entropy(&mut out, boot_services, unsafe { pinned.get_mut() });
+    open_crash_dump(&mut out, st, boot_services, &clock, &handle, unsafe { pinned.get_mut() }, &config);
+    boot_stage(&mut out, unsafe { pinned.get_mut() }, Some(boot_services), BootStage::HandoffPrepared);
 
-    for map in memmap {
+    /* Splitting firmware's large page over the null guard needs a PD and a PT */
+    let null_spare = BootServicesFrames::new(boot_services).alloc_frames(2 * 0x1000, 0x1000);
+    if let Some(spare) = null_spare {
+        trace_alloc(spare.start(), spare.len());
+    }
+
+    #[cfg(feature = "limine-compat")]
+    let limine = limine_prepare(&mut out, boot_services, st);
+
+    let mut memmap = memory_map_buffer(&mut out, boot_services, &config);
+    if out.has_efi_serial() {
+        brint!(out, "Exiting boot services, EFI Serial I/O console output stops here\n");
+    }
+    let pinned = unsafe {
+        pinned.exit_boot_services(st, &handle, &clock, &mut memmap, &mut irq_sources, &mut CpuInterruptFlag)
+    };
+    out.exit_boot_services();
+    let mut pinned = pinned.unwrap();
+    brint!(out, "Memory map: {} GetMemoryMap calls, {} buffers\n", memmap.calls, memmap.allocations);
+    if pinned.pre_exit.interrupts_disabled {
+        brint!(out, "WARNING: interrupts were enabled before ExitBootServices, disabled them\n");
+    }
+    brint!(out, "Interrupt sources disarmed before exit: {}\n", pinned.pre_exit.sources_disarmed);
+    boot_stage(&mut out, unsafe { pinned.get_mut() }, None, BootStage::ExitedBootServices);
+
+    let phys_bits = cpu::phys_addr_bits();
+    let clamp = unsafe { pinned.get_mut() }.clamp_physical_memory(phys_bits);
+    if clamp.regions != 0 {
+        warn_or_fail!(out, unsafe { pinned.get_mut() }, Condition::PhysMemClamped,
+            "{} of usable memory is above MAXPHYADDR={} and can't be used\n", Size(clamp.lost_bytes), phys_bits);
+    }
+    if pinned.capabilities.memory_map_truncated() {
+        warn_or_fail!(out, unsafe { pinned.get_mut() }, Condition::MemMapTruncated,
+            "memory map didn't fit into {} descriptors, the rest was dropped\n", pinned.uefi_meminfo.capacity());
+    }
+    let overlaps = bootinfo::overlapping_descriptors(&pinned.uefi_meminfo);
+    if overlaps != 0 {
+        warn_or_fail!(out, unsafe { pinned.get_mut() }, Condition::MemMapOverlap,
+            "{} memory map descriptors overlap others\n", overlaps);
+    }
+    brint!(out, "Usable memory ends at {}\n", Addr(clamp.usable_end));
+    if let Some(addr) = st.find_config(uefi::Guid::EFI_SYSTEM_RESOURCE_TABLE) {
+        esrt(&mut out, unsafe { pinned.get_mut() }, addr as u64);
+    }
+    low_memory(&mut out, &mut pinned, &config, handoff.as_ref(), null_spare);
+    #[cfg(feature = "limine-compat")]
+    if let Some(limine) = &limine {
+        limine_answer(&mut out, &pinned, limine);
+    }
+    let memory = pinned.memory;
+    brint!(out, "Free memory: {} in {} regions, {} below 1M, {} below 4G, {} above 4G\n",
+        Size(memory.total()), memory.regions, Size(memory.below_1m), Size(memory.below_4g), Size(memory.above_4g));
+    brint!(out, "Largest free region: {:?}\n", memory.largest);
+    /* Nothing is allocated past this point */
+    if let Err(e) = unsafe { pinned.get_mut() }.check_requirements(&requirements) {
+        brint!(out, "Kernel requirement not met: {:?}\n", e);
+        panic!("kernel requirement not met: {:?}", e);
+    }
+
+    for map in &pinned.uefi_meminfo {
         use uefi::memory::Type;
 
         let mtyp = Type::from_int(map.typ);
@@ -114,13 +420,16 @@ extern "efiapi" fn efi_main(handle: uefi::ImageHandle, st: *const uefi::SystemTa
     brint!(out, "CR4: {:?}\n", cr4);
     brint!(out, "CR0: {:?}\n", cr0);
 
-    use cpu::segmentation::GDTR;
-    let gdtr = GDTR::new(&bootinfo.gdt);
+    use cpu::segmentation::{GlobalDescriptorTable, GDTR};
+    const _: () = assert!(core::mem::size_of::<GlobalDescriptorTable>() <= bootinfo::budgeted(AllocPurpose::Gdt));
+    let gdt = pinned.arena().reserve_pinned(AllocPurpose::Gdt, GlobalDescriptorTable::new()).unwrap();
+    debug_assert!(pinned.arena().is_pinned(gdt));
+    let gdtr = GDTR::new(gdt);
     unsafe { gdtr.apply(); }
 
     use cpu::interrupt;
     extern "sysv64" fn _dummy_handler(ii: &mut interrupt::Stack) {
-        let mut out = unsafe { SerialPort::new(0x3F8) };
+        let mut out = crash_console();
         brint!(out, "\nHANDLER\n\n{:?}\n", ii);
         loop { cpu::halt() };
     }
@@ -129,42 +438,1467 @@ extern "efiapi" fn efi_main(handle: uefi::ImageHandle, st: *const uefi::SystemTa
         .disable_interrupts()
         .set_present();
     let idt_entry = interrupt::Entry::with_handler_and_flags(dummy_handler, idt_flags);
-    bootinfo.idt = [idt_entry; 256];
-    let idtr = interrupt::TableRegister::new(&bootinfo.idt);
+    let mut idt_entries = [idt_entry; 256];
+    idt_entries[14] = interrupt::Entry::with_handler_and_flags(interrupt::make_handler!(page_fault), idt_flags);
+    const _: () = assert!(core::mem::size_of::<[interrupt::Entry; 256]>() <= bootinfo::budgeted(AllocPurpose::Idt));
+    let idt = pinned.arena().reserve_pinned(AllocPurpose::Idt, idt_entries).unwrap();
+    debug_assert!(pinned.arena().is_pinned(idt));
+    let idtr = interrupt::TableRegister::new(idt);
     unsafe { idtr.apply(); }
+    if null_selftest() {
+        brint!(out, "Null guard selftest: reading 0 faults\n");
+    } else {
+        brint!(out, "WARNING: null guard selftest: reading 0 doesn't fault\n");
+    }
+
+    let mut pinned = pinned.finish();
+    boot_stage(&mut out, unsafe { pinned.get_mut() }, None, BootStage::TablesReady);
+    brint!(out, "Reserved ranges: {}{}\n", pinned.reserved.len(),
+        if pinned.reserved.widened() { ", widened to fit" } else { "" });
+    if config.flag("verbose") {
+        for (range, kind) in pinned.reserved_ranges() {
+            brint!(out, "\t{:?} {:?}\n", range, kind);
+        }
+    }
+
+    #[cfg(feature = "inspector")]
+    if config.flag("inspector") {
+        brint!(out, "Memory inspector, q to continue\n");
+        unsafe { bootinfo::inspector::run(&mut out, &pinned, &clock, input_timeout_us(&config)) };
+    }
+
+    /* Last moment before the kernel would get control */
+    if verify_tables {
+        if let Err(e) = pinned.verify_tables(unsafe { &TABLE_SNAPSHOT }) {
+            brint!(out, "Page tables modified behind our back: {:?} entry {} was {:#x}, is {:#x}\n",
+                e.table, e.index, e.expected, e.found);
+            panic!("page table verification failed");
+        }
+        unsafe { pinned.get_mut() }.record(BootCapabilities::set_tables_verified);
+    }
+
+    /* Firmware may leave SSE off, but the kernel is compiled code that uses it */
+    unsafe { cpu::simd::enable_sse() };
+    match unsafe { cpu::simd::enable_avx() } {
+        Ok(()) => {
+            brint!(out, "SSE and AVX enabled\n");
+            unsafe { pinned.get_mut() }.record(BootCapabilities::set_avx);
+        }
+        Err(e) => brint!(out, "SSE enabled, AVX not: {:?}\n", e),
+    }
 
-    prepare_kernel_elf(&mut out);
+    apply_mitigations(&mut out, unsafe { pinned.get_mut() }, &mitigations);
+
+    /* There is no jump yet, this is where the kernel entry would go */
+    if let Some(stage) = pinned.stages.missing_before(BootStage::JumpingToKernel) {
+        panic!("about to enter the kernel without {:?}", stage);
+    }
+    brint!(out, "Tolerated conditions: {:?}\n", pinned.conditions);
+    brint!(out, "Capabilities: {:#x} ({:?})\n", pinned.capabilities.as_u64(), pinned.capabilities);
+    if let Some(ring) = out.tx_ring() {
+        brint!(out, "Serial log: {} bytes, {} us waiting for the UART\n",
+            ring.queued(), ring.blocked() / clock.ticks_per_us().max(1));
+    }
+    /* What's left to send shows up between this and the last stage */
+    unsafe { pinned.get_mut() }.mark("serial flush");
+    boot_stage(&mut out, unsafe { pinned.get_mut() }, None, BootStage::JumpingToKernel);
 
     loop { cpu::halt() };
 }
 
-fn prepare_kernel_elf(out: &mut SerialPort) {
-    let kernel = &KERNEL.0;
+/// Copies the ESRT into `bootinfo`. Needs the final memory map, the
+/// region containing the table is all it may span.
+fn esrt(out: &mut SerialSinks, bootinfo: &mut Bootinfo, addr: u64) {
+    /* SAFETY: firmware tables are identity mapped and untouched so far */
+    let memory = match unsafe { bootinfo::table_memory(&bootinfo.uefi_meminfo, addr) } {
+        Some(x) => x,
+        None => {
+            brint!(out, "WARNING: ESRT at {} is outside the memory map\n", Addr(addr));
+            return;
+        }
+    };
+    let table = match uefi::Esrt::parse(memory) {
+        Ok(x) => x,
+        Err(e) => {
+            brint!(out, "WARNING: bad ESRT at {}: {:?}\n", Addr(addr), e);
+            return;
+        }
+    };
+
+    let dropped = bootinfo.record_esrt(&table);
+    bootinfo.record(BootCapabilities::set_esrt);
+    for resource in &bootinfo.esrt {
+        brint!(out, "ESRT: {} version {:#x}, lowest supported {:#x}, last attempt status {}\n",
+            resource.fw_class, resource.version, resource.lowest_supported_version, resource.last_attempt_status);
+    }
+    if dropped != 0 {
+        brint!(out, "WARNING: {} ESRT entries didn't fit and were dropped\n", dropped);
+    }
+}
+
+/// What answering the kernel's Limine requests needs from boot services
+#[cfg(feature = "limine-compat")]
+struct LimineCompat {
+    responses: PhysRange,
+    rsdp: Option<u64>,
+    boot_time: Option<i64>,
+}
+
+#[cfg(feature = "limine-compat")]
+const LIMINE_RESPONSES_SIZE: u64 = 4 * 0x1000;
+
+#[cfg(feature = "limine-compat")]
+fn limine_prepare(out: &mut SerialSinks, boot_services: &uefi::BootServices, st: &uefi::SystemTable) -> Option<LimineCompat> {
+    let responses = match BootServicesFrames::new(boot_services).alloc_frames(LIMINE_RESPONSES_SIZE, 0x1000) {
+        Some(x) => x,
+        None => {
+            brint!(out, "WARNING: can't allocate memory for Limine responses\n");
+            return None;
+        }
+    };
+    trace_alloc(responses.start(), responses.len());
+
+    let rsdp = st.find_config(uefi::Guid::EFI_ACPI_20_TABLE).or_else(|| st.find_config(uefi::Guid::ACPI_TABLE));
+    let runtime_services = unsafe { &*st.runtime_services };
+    let boot_time = runtime_services.get_time().map(|t| bootinfo::limine::unix_time(&t));
+    return Some(LimineCompat { responses, rsdp: rsdp.map(|x| x as u64), boot_time });
+}
+
+/// Points the Limine requests in the kernel at their responses. Needs the
+/// final memory map, unsupported requests are logged and left null.
+#[cfg(feature = "limine-compat")]
+fn limine_answer(out: &mut SerialSinks, bootinfo: &Bootinfo, limine: &LimineCompat) {
+    use bootinfo::limine::{answer_requests, RequestKind, Responses};
+
+    let kernel = bootinfo.kernel_pslice;
+    let kernel_range = PhysRange::new(kernel.addr().as_u64(), kernel.len() as u64).unwrap();
+    /* SAFETY: both are ours and identity mapped, the kernel hasn't run yet */
+    let image = unsafe { core::slice::from_raw_parts_mut(kernel.addr().as_u64() as *mut u8, kernel.len()) };
+    let buf = unsafe {
+        core::slice::from_raw_parts_mut(limine.responses.start() as *mut u8, limine.responses.len() as usize)
+    };
+    let mut responses = Responses::new(buf, limine.responses.start());
+
+    let answered = answer_requests(image, |request| {
+        let response = match request.kind() {
+            Some(RequestKind::Memmap) => responses.memmap(&bootinfo.uefi_meminfo, kernel_range),
+            Some(RequestKind::Rsdp) => limine.rsdp.and_then(|x| responses.rsdp(x)),
+            Some(RequestKind::Module) => responses.modules(&bootinfo.modules),
+            /* Everything is identity mapped */
+            Some(RequestKind::Hhdm) => responses.hhdm(0),
+            Some(RequestKind::BootTime) => limine.boot_time.and_then(|x| responses.boot_time(x)),
+            Some(RequestKind::Framebuffer) | None => None,
+        };
+        if response.is_none() {
+            brint!(out, "Limine: unsupported request {:#x}:{:#x} revision {} at {:#x}, left null\n",
+                request.id[0], request.id[1], request.revision, request.offset);
+        }
+        return response;
+    });
+    brint!(out, "Limine: answered {} requests with {} of responses\n", answered, Size(responses.used() as u64));
+}
+
+/// Checks the kernel's signature as `policy` says, before anything of it
+/// is parsed. `path` is the loader's own file, the kernel is embedded in
+/// it or came with its load options.
+fn verify(out: &mut SerialSinks, st: &uefi::SystemTable, bootinfo: &mut Bootinfo, policy: VerifyPolicy, path: Option<&uefi::DevicePath>, kernel: &[u8]) {
+    let runtime_services = unsafe { &*st.runtime_services };
+    let boot_services = unsafe { &*st.boot_services.get() };
+
+    let secure_boot = match runtime_services.secure_boot_state() {
+        Ok(x) => x,
+        Err(e) => {
+            brint!(out, "WARNING: can't read SecureBoot/SetupMode: {:?}\n", e);
+            uefi::SecureBootState::default()
+        }
+    };
+    brint!(out, "Secure Boot: {}, setup mode: {}, verify={}\n", secure_boot.secure_boot, secure_boot.setup_mode, policy.name());
+    if secure_boot.is_enforcing() {
+        bootinfo.record(BootCapabilities::set_secure_boot);
+    }
+
+    let verifier = match policy {
+        /* Not looked up, `verify_kernel` skips it anyway */
+        VerifyPolicy::Off => Err(uefi::Error::NotFound),
+        VerifyPolicy::Firmware => boot_services.security2().map(|protocol| Verifier::Firmware { protocol, path }),
+        VerifyPolicy::Shim => boot_services.shim_lock().map(Verifier::Shim),
+    };
+
+    match bootinfo::verify_kernel(policy, secure_boot, verifier, kernel) {
+        Ok(VerifyOutcome::Skipped) if secure_boot.is_enforcing() => {
+            brint!(out, "WARNING: ********************************************************\n");
+            brint!(out, "WARNING: Secure Boot is enforcing, but verify=off: the kernel\n");
+            brint!(out, "WARNING: is NOT verified, anything signed can boot anything\n");
+            brint!(out, "WARNING: ********************************************************\n");
+            warn_or_fail!(out, bootinfo, Condition::Unverified, "booting unverified\n");
+        }
+        Ok(VerifyOutcome::Skipped) => {}
+        Ok(VerifyOutcome::Accepted) => {
+            brint!(out, "Kernel signature accepted by verify={}\n", policy.name());
+            bootinfo.record(BootCapabilities::set_kernel_verified);
+        }
+        Ok(VerifyOutcome::NoVerifier(e)) => {
+            warn_or_fail!(out, bootinfo, Condition::Unverified,
+                "no verifier for verify={}: {:?}, Secure Boot is off, booting unverified\n", policy.name(), e)
+        }
+        Err(VerifyError::Rejected(e)) => {
+            panic!("kernel rejected by verify={}: {:?} (EFI status {:#x}), refusing to boot", policy.name(), e, (1 << 63) | e as u64)
+        }
+        Err(e) => panic!("can't verify the kernel with verify={}: {:?}, refusing to boot", policy.name(), e),
+    }
+}
+
+/// Applies `mitigations` and records the outcomes. A required mitigation
+/// the processor lacks aborts the boot, e.g. W^X is meaningless without NX.
+fn apply_mitigations(out: &mut SerialSinks, bootinfo: &mut Bootinfo, mitigations: &Mitigations) {
+    let info = cpu::CpuInfo::detect();
+    brint!(out, "{:?}\n", info);
+
+    /* SAFETY: we are in ring 0, nothing runs in user pages */
+    let record = match unsafe { bootinfo::apply_mitigations(mitigations, &info) } {
+        Ok(x) => x,
+        Err(MitigationError::Required(m)) => {
+            panic!("{}=require, but the processor doesn't support it, refusing to boot", m.name())
+        }
+        Err(e) => panic!("can't apply mitigations: {:?}", e),
+    };
+    bootinfo.mitigations = record;
+
+    for &m in &Mitigation::ALL {
+        match (record.get(m), mitigations.get(m)) {
+            (Outcome::Skipped, _) => {}
+            (Outcome::NotSupported, Setting::On) => brint!(out, "WARNING: {}=on not supported\n", m.name()),
+            (outcome, setting) => brint!(out, "Mitigation {}: {:?}, {:?}\n", m.name(), setting, outcome),
+        }
+    }
+}
+
+/// Validates ACPI once and hands what the consumers found to the kernel.
+/// Missing or corrupt tables only disable the features that need them.
+fn read_acpi(out: &mut SerialSinks, st: &uefi::SystemTable, bootinfo: &mut Bootinfo) {
+    /* ACPI 1.0 firmware only has an RSDT */
+    let rsdp = st.find_config(uefi::Guid::EFI_ACPI_20_TABLE).or_else(|| st.find_config(uefi::Guid::ACPI_TABLE));
+    let rsdp = match rsdp {
+        Some(x) => unsafe { &*(x as *const acpi::Rsdp) },
+        None => {
+            brint!(out, "No ACPI tables\n");
+            bootinfo.read_acpi(None);
+            return;
+        }
+    };
+
+    /* Firmware tables are identity mapped */
+    let _ = unsafe { acpi::dump(rsdp, 0, out) };
+    let context = match unsafe { acpi::AcpiContext::discover(rsdp, 0) } {
+        Ok(x) => x,
+        Err(e) => {
+            brint!(out, "ACPI unusable: {:?}, treating it as missing\n", e);
+            bootinfo.read_acpi(None);
+            return;
+        }
+    };
+    for skipped in context.skipped() {
+        warn_or_fail!(out, bootinfo, Condition::AcpiCorrupt, "ACPI table {:?} at {:#x} skipped: {:?}\n",
+            core::str::from_utf8(&skipped.signature).unwrap_or("????"), skipped.phys, skipped.reason);
+    }
+    if context.dropped() != 0 {
+        warn_or_fail!(out, bootinfo, Condition::AcpiCorrupt, "{} more ACPI tables dropped\n", context.dropped());
+    }
+
+    bootinfo.read_acpi(Some(&context));
+    let caps = bootinfo.capabilities;
+    if !caps.madt() {
+        brint!(out, "No MADT, no SMP bring-up\n");
+    }
+    if !caps.hpet() {
+        brint!(out, "No HPET, timing is TSC only\n");
+    }
+    if !caps.acpi_reset() {
+        brint!(out, "No FADT reset register, reset is UEFI only\n");
+    }
+    match bootinfo.acpi.firmware_boot {
+        Some(perf) => brint!(out, "Firmware: reset end {}ms, loader started {}ms after reset\n",
+            perf.reset_end / 1_000_000, perf.loader_start_image / 1_000_000),
+        None => brint!(out, "No FPDT, the timeline starts at the loader\n"),
+    }
+}
+
+/// Decides once whether the kernel's mappings use NX, before anything is
+/// mapped. Without NX the boot goes on with data executable, unless it's
+/// `nx=require`.
+fn select_page_flags(out: &mut SerialSinks, bootinfo: &mut Bootinfo, mitigations: &Mitigations) {
+    let info = cpu::CpuInfo::detect();
+    /* SAFETY: we are in ring 0 */
+    bootinfo.page_flags = match unsafe { bootinfo::enable_nx(mitigations, &info) } {
+        Ok(x) => x,
+        Err(MitigationError::Required(m)) => {
+            panic!("{}=require, but the processor doesn't support it, refusing to boot", m.name())
+        }
+        Err(e) => panic!("can't decide on NX: {:?}", e),
+    };
+
+    if !bootinfo.page_flags.nx {
+        bootinfo.record(BootCapabilities::set_nx_unavailable);
+        let why = if info.nx() { "nx=off" } else { "the processor has no NX" };
+        warn_or_fail!(out, bootinfo, Condition::NxUnavailable,
+            "{}, the kernel is mapped without NX and all of it is executable\n", why);
+    }
+}
+
+/// `boot_delay=<ms>` gives time to interrupt the boot from the keyboard
+/// or the serial console, a key pauses it until the next one
+fn boot_delay(out: &mut SerialSinks, st: &uefi::SystemTable, boot_services: &uefi::BootServices, clock: &uefi::TscClock, config: &Config) {
+    use uefi::{Clock, MenuInput};
+
+    let delay_ms = match config.get("boot_delay").and_then(parse_u64) {
+        Some(x) if x > 0 => x,
+        _ => return,
+    };
+    brint!(out, "Press any key to interrupt boot ({} ms)\n", delay_ms);
+    let deadline = clock.now_us().saturating_add(delay_ms.saturating_mul(1000));
+    let wait = |out: &mut SerialSinks, deadline| {
+        match uefi::wait_key_or_timeout(boot_services, st.con_in(), Some(&mut *out), clock, deadline) {
+            Ok(x) => x,
+            Err(e) => {
+                brint!(out, "WARNING: can't wait for input: {:?}\n", e);
+                MenuInput::Timeout
+            }
+        }
+    };
+
+    if wait(out, deadline) == MenuInput::Timeout {
+        return;
+    }
+    /* Firmware resets the machine 5 minutes into an image otherwise,
+     * `boot_stage` arms it again */
+    let _ = boot_services.set_watchdog_timer(0);
+    brint!(out, "Boot paused, press f to reboot into firmware setup, any other key to continue\n");
+    let deadline = clock.now_us().saturating_add(input_timeout_us(config));
+    let fw_ui = match wait(out, deadline) {
+        MenuInput::Timeout => {
+            brint!(out, "No input, continuing boot\n");
+            return;
+        }
+        MenuInput::Key(key) => key.unicode_char == b'f' as u16,
+        MenuInput::Serial(byte) => byte == b'f',
+    };
+    if !fw_ui {
+        return;
+    }
+
+    let runtime_services = unsafe { &*st.runtime_services };
+    match runtime_services.request_boot_to_fw_ui() {
+        Ok(()) => {
+            brint!(out, "Rebooting into firmware setup\n");
+            runtime_services.reset_system(uefi::ResetType::Cold, uefi::RawStatus(0), &[]);
+        }
+        Err(e) => brint!(out, "WARNING: can't reboot into firmware setup: {:?}, continuing boot\n", e),
+    }
+}
+
+/// Buffer for the final memory map. `memmap_size=` allocates that many
+/// bytes up front, which saves asking firmware for the size first, one
+/// GetMemoryMap call less on firmware where those are slow.
+fn memory_map_buffer(out: &mut SerialSinks, boot_services: &uefi::BootServices, config: &Config) -> uefi::MemoryMapBuffer {
+    let bytes = match config.get("memmap_size").and_then(parse_u64) {
+        Some(x) => x,
+        None => return uefi::MemoryMapBuffer::new(),
+    };
+
+    match uefi::MemoryMapBuffer::with_capacity(boot_services, bytes as usize) {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            brint!(out, "WARNING: no {} bytes for the memory map ({:?}), asking for the size\n", bytes, e);
+            uefi::MemoryMapBuffer::new()
+        }
+    }
+}
+
+/// `input_timeout=<ms>` bounds every wait for the console after someone
+/// interrupted the boot, so a glitch on the line of an unattended machine
+/// can't hold it up forever. 0 waits forever.
+fn input_timeout_us(config: &Config) -> u64 {
+    match config.get("input_timeout").and_then(parse_u64) {
+        Some(0) => u64::MAX,
+        Some(ms) => ms.saturating_mul(1000),
+        None => DEFAULT_INPUT_TIMEOUT_MS * 1000,
+    }
+}
+
+/// Volumes and block devices firmware knows about, after connecting every
+/// driver so that USB sticks show up too
+fn list_volumes(out: &mut SerialSinks, boot_services: &uefi::BootServices) {
+    use uefi::{BlockDevice, FileSystem, HandleSet};
+
+    match uefi::reconnect_all(boot_services) {
+        Ok(n) => brint!(out, "Connected drivers to {} handles\n", n),
+        Err(e) => brint!(out, "WARNING: can't connect drivers: {:?}\n", e),
+    }
+
+    let mut volumes = HandleSet::<FileSystem>::new(boot_services);
+    match volumes.enumerate() {
+        Ok(n) => brint!(out, "File systems: {}\n", n),
+        Err(e) => brint!(out, "WARNING: can't enumerate file systems: {:?}\n", e),
+    }
+
+    let mut disks = HandleSet::<BlockDevice>::new(boot_services);
+    if let Err(e) = disks.enumerate() {
+        brint!(out, "WARNING: can't enumerate block devices: {:?}\n", e);
+        return;
+    }
+    for (i, disk) in disks.iter().enumerate() {
+        match disks.open(disk).map(|x| x.media()) {
+            Ok(Some(m)) => brint!(out, "Block device {}: {} blocks of {} bytes{}{}{}\n",
+                i, m.last_block.saturating_add(1), m.block_size,
+                if m.logical_partition { ", partition" } else { "" },
+                if m.removable_media { ", removable" } else { "" },
+                if m.media_present { "" } else { ", no media" }),
+            Ok(None) => brint!(out, "Block device {}: no media info\n", i),
+            Err(e) => brint!(out, "Block device {}: {:?}\n", i, e),
+        }
+    }
+}
+
+/// Loads the newest fitting update of `microcode.bin` on the BSP, before
+/// anything depends on the errata it fixes. Corrupt or foreign updates are
+/// logged and never reach the trigger MSR. APs are the kernel's job, it
+/// gets the module as `ModuleKind::Microcode`.
+fn load_microcode(out: &mut SerialSinks, boot_services: &uefi::BootServices, bootinfo: &mut Bootinfo) {
+    use cpu::microcode::{self, CpuSignature};
+
+    let module = match bootinfo.microcode_module() {
+        Some(x) => x.data,
+        None => return,
+    };
+    /* IA32_MISC_ENABLE and the update MSRs come with the same processors */
+    if !cpu::CpuInfo::detect().misc_enable() {
+        brint!(out, "microcode: not an Intel processor, leaving updates to the kernel\n");
+        return;
+    }
+
+    /* Modules are still where the firmware loaded them, identity mapped */
+    let file = unsafe { core::slice::from_raw_parts(module.addr().as_u64() as *const u8, module.len()) };
+    let cpu = CpuSignature::current();
+    let current = microcode::current_revision();
+    bootinfo.microcode = MicrocodeStatus::unchanged(current);
+    brint!(out, "microcode: signature {:#x}, platform {}, revision {:#x}\n", cpu.signature, cpu.platform_id, current);
+
+    let update = bootinfo::select_microcode(file, cpu, current, |offset, e| {
+        brint!(out, "microcode: skipping update at offset {}: {:?}\n", offset, e);
+    });
+    let mut update = match update {
+        Some(x) => x,
+        None => {
+            brint!(out, "microcode: no newer update for this processor\n");
+            return;
+        }
+    };
+
+    /* newc aligns data to 4 bytes, the trigger MSR wants 16 */
+    if update.data().as_ptr() as usize % 16 != 0 {
+        let bytes = update.as_bytes();
+        let pages = (bytes.len() + 4095) / 4096;
+        let copy = match boot_services.allocate_pages(uefi::AllocateType::AnyPages, uefi::memory::Type::LoaderData, pages, 0) {
+            Ok(x) => x,
+            Err(e) => {
+                brint!(out, "WARNING: microcode: can't allocate an aligned copy: {:?}\n", e);
+                trace(TraceEvent::Error, [line!() as u64, e as u64]);
+                return;
+            }
+        };
+        trace_alloc(copy, (pages * 4096) as u64);
+        let allowed = [PhysRange::new(copy, bytes.len() as u64).unwrap()];
+        let mut log = phys_log(out);
+        unsafe {
+            let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+            writer.copy_from_slice("microcode copy", copy, bytes);
+        }
+        let copy = unsafe { core::slice::from_raw_parts(copy as *const u8, bytes.len()) };
+        update = microcode::IntelMicrocode::parse(copy).unwrap();
+    }
+
+    /* SAFETY: Intel, identity mapped, and the APs are still waiting for SIPI */
+    match unsafe { microcode::apply(&update) } {
+        Ok((old, new)) => {
+            bootinfo.microcode = MicrocodeStatus::applied(old, &update);
+            bootinfo.record(BootCapabilities::set_microcode_applied);
+            brint!(out, "microcode: BSP updated from {:#x} to {:#x}, APs are left to the kernel\n", old, new);
+        }
+        Err(e) => brint!(out, "WARNING: microcode: update {:#x} failed: {:?}\n", update.header.revision, e),
+    }
+}
+
+/// Everything the loader armed that could interrupt after ExitBootServices.
+/// Anything new that arms an interrupt has to be registered here.
+fn interrupt_sources(out: &mut SerialSinks, boot_services: &uefi::BootServices) -> InterruptSources {
+    unsafe fn disarm_watchdog(boot_services: u64) {
+        let _ = (*(boot_services as *const uefi::BootServices)).set_watchdog_timer(0);
+    }
+
+    let ports = out.ports;
+    let mut sources = InterruptSources::new();
+    let mut register = |name: &'static str, disarm: DisarmFn, arg: u64| {
+        if let Err(e) = sources.register(name, disarm, arg) {
+            brint!(out, "WARNING: can't register interrupt source {}: {:?}\n", name, e);
+        }
+    };
+
+    /* Firmware disarms it on exit too, but it's ours since boot_stage */
+    register("uefi watchdog", disarm_watchdog, boot_services as *const _ as u64);
+    /* Polled now, switched back to polling in case anything enabled RX interrupts */
+    for (port, &name) in ports.iter().zip(&["ttyS0 interrupts", "ttyS1 interrupts"]) {
+        if port.is_ready() {
+            register(name, bootinfo::mask_uart_interrupts, port.base() as u64);
+        }
+    }
+
+    return sources;
+}
+
+/// Enters `stage` once queued output is out, re-arming the firmware
+/// watchdog while `boot_services` are still there. Release builds only
+/// warn about an illegal transition, it's recorded in `Bootinfo::stages`
+/// for the kernel to see.
+fn boot_stage(out: &mut SerialSinks, bootinfo: &mut Bootinfo, boot_services: Option<&uefi::BootServices>, stage: BootStage) {
+    /* Nothing logged in a stage is lost to a hang in the next one */
+    out.flush();
+    if let Err(e) = bootinfo.advance(stage) {
+        brint!(out, "WARNING: illegal boot stage transition {:?} -> {:?}\n", e.from, e.to);
+        trace(TraceEvent::Error, [line!() as u64, e.from as u64]);
+    }
+    trace(TraceEvent::Stage, [stage as u64, 0]);
+
+    if let (true, Some(boot_services)) = (stage.pets_watchdog(), boot_services) {
+        if let Err(e) = boot_services.set_watchdog_timer(STAGE_WATCHDOG_S) {
+            brint!(out, "WARNING: can't re-arm the watchdog: {:?}\n", e);
+        }
+    }
+}
+
+/// Mirrors the boot log to EFI_SERIAL_IO_PROTOCOL, with the console's
+/// baud rate, on machines without a working legacy UART
+fn efi_serial_fallback(out: &mut SerialSinks, boot_services: &uefi::BootServices) {
+    let protocol = match boot_services.serial_io() {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    /* SAFETY: dropped by exit_boot_services before the protocol goes away */
+    match unsafe { EfiSerial::init(protocol, out.console()) } {
+        Ok(efi) => {
+            out.use_efi_serial(efi);
+            brint!(out, "WARNING: no legacy UART, console is on EFI Serial I/O until ExitBootServices\n");
+        }
+        Err(e) => brint!(out, "WARNING: no legacy UART and EFI Serial I/O failed: {:?}\n", e),
+    }
+}
+
+/// PXE can deliver only a single file, so the kernel, config and initrd
+/// can come packed in a newc CPIO archive that is already in RAM.
+/// Its address and size are passed as load options - two native-endian u64s.
+fn archive_from_load_options(image: &uefi::LoadedImage) -> Option<cpio::Archive<'static>> {
+    let options = image.load_options();
+    if options.len() != 16 {
+        return None;
+    }
+
+    let addr = u64::from_ne_bytes(options[0..8].try_into().unwrap());
+    let size = u64::from_ne_bytes(options[8..16].try_into().unwrap());
+    if addr == 0 || size < cpio::HEADER_SIZE as u64 {
+        return None;
+    }
+
+    let data = unsafe { core::slice::from_raw_parts(addr as usize as *const u8, size as usize) };
+    if !data.starts_with(cpio::MAGIC) && !data.starts_with(cpio::MAGIC_CRC) {
+        return None;
+    }
+
+    return Some(cpio::Archive::new(data));
+}
+
+/// The archive can also be a file on the volume the loader was started
+/// from, its path in the load options, e.g. `\boot\sovos.cpio` as the
+/// optional data of the boot entry or on the shell's command line
+fn archive_from_path(
+    out: &mut SerialSinks,
+    boot_services: &uefi::BootServices,
+    image: &uefi::LoadedImage,
+) -> Option<cpio::Archive<'static>> {
+    let mut buf = [0u16; 256];
+    let path = image.last_load_option(&mut buf)?;
+    if path[0] != b'\\' as u16 {
+        return None;
+    }
+
+    let fs: &uefi::SimpleFileSystemProtocol = match unsafe {
+        boot_services.handle_protocol(&image.device_handle, &uefi::Guid::EFI_SIMPLE_FILE_SYSTEM_PROTOCOL)
+    } {
+        Ok(x) => x,
+        Err(e) => {
+            brint!(out, "archive: no file system on the boot device: {:?}\n", e);
+            return None;
+        }
+    };
+    let data = fs.open_volume().and_then(|root| {
+        let data = read_file(boot_services, root, path);
+        root.close();
+        data
+    });
+    let data = match data {
+        Ok(x) => x,
+        Err(e) => {
+            brint!(out, "archive: can't read it from the boot device: {:?}\n", e);
+            return None;
+        }
+    };
+
+    if !data.starts_with(cpio::MAGIC) && !data.starts_with(cpio::MAGIC_CRC) {
+        brint!(out, "archive: file from the load options isn't a newc CPIO archive\n");
+        return None;
+    }
+    return Some(cpio::Archive::new(data));
+}
+
+/// Reads the file at `path` in `dir` into pages of its own, which stay
+/// allocated as loader data so that modules can point into them
+fn read_file(
+    boot_services: &uefi::BootServices,
+    dir: &uefi::File,
+    path: &[u16],
+) -> Result<&'static [u8], uefi::Error> {
+    let file = dir.open(path)?;
+    let data = file.size().and_then(|size| {
+        let pages = (size as usize + 4095) / 4096;
+        let addr = boot_services.allocate_pages(uefi::AllocateType::AnyPages, uefi::memory::Type::LoaderData, pages.max(1), 0)?;
+        trace_alloc(addr, (pages * 4096) as u64);
+        let data = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, size as usize) };
+        file.read_exact(data)?;
+        Ok(&*data)
+    });
+    file.close();
+    return data;
+}
+
+/// Records files from the archive as Bootinfo modules (in place, no copies)
+/// and returns the kernel if it can be used directly
+fn modules_from_archive(
+    out: &mut SerialSinks,
+    bootinfo: &mut Bootinfo,
+    archive: cpio::Archive<'static>,
+) -> Option<&'static [u8]> {
+    let mut kernel = None;
+
+    for &name in &["kernel.elf", "sovos.cfg", "initrd.img", "microcode.bin"] {
+        let entry = match archive.find(name) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => {
+                brint!(out, "archive: {} not found\n", name);
+                continue;
+            }
+            Err(e) => {
+                brint!(out, "archive: malformed entry #{}: {:?}\n", e.index, e.kind);
+                return None;
+            }
+        };
+
+        brint!(out, "archive: {} at {:p}, size={}\n", name, entry.data, entry.data.len());
+
+        let addr = PhysAddr::new(entry.data.as_ptr() as u64).unwrap();
+        let data = PhysSlice::new(addr, entry.data.len() as u64);
+        if bootinfo.modules.try_push(Module::new(name, data)).is_err() {
+            brint!(out, "archive: too many modules, skipping {}\n", name);
+        }
+
+        /* newc only aligns data to 4 bytes, which the ELF parser is fine with */
+        if name == "kernel.elf" {
+            kernel = Some(entry.data);
+        }
+    }
+
+    return kernel;
+}
+
+/// The kernel of the A/B slot to boot, `None` if `kernel_a` and `kernel_b`
+/// aren't configured. The try is counted before anything is loaded, a
+/// slot whose kernel can't be found fails the boot so that it runs out
+/// of tries like any other failing kernel.
+fn kernel_slot(
+    out: &mut SerialSinks,
+    runtime_services: &uefi::RuntimeServices,
+    bootinfo: &mut Bootinfo,
+    config: &Config,
+    archive: Option<cpio::Archive<'static>>,
+) -> Option<&'static [u8]> {
+    let names = [config.get(Slot::A.config_key()), config.get(Slot::B.config_key())];
+    if names == [None, None] {
+        return None;
+    }
+
+    let plan = match bootinfo::begin_slot_boot(runtime_services) {
+        Ok(x) => x,
+        Err(e) => {
+            brint!(out, "WARNING: A/B state unusable: {:?}, booting slot A\n", e);
+            SlotState::initial().plan()
+        }
+    };
+
+    /* There is no boot menu, this is what it would show */
+    for (&slot, name) in [Slot::A, Slot::B].iter().zip(&names) {
+        let marker = if slot == plan.boot { '*' } else { ' ' };
+        brint!(out, "{} slot {:?}: {}\n", marker, slot, name.unwrap_or("<not configured>"));
+    }
+    brint!(out, "A/B state: {:?}, tries left {}, last result {:?}\n",
+        plan.state.slot, plan.state.tries_remaining, plan.state.last_result);
+    if plan.falls_back() {
+        brint!(out, "WARNING: slot {:?} never confirmed a boot, falling back to slot {:?}\n", plan.state.slot, plan.boot);
+        bootinfo.record(BootCapabilities::set_slot_fallback);
+    }
+
+    let name = match names[plan.boot as usize] {
+        Some(x) => x,
+        None => panic!("slot {:?} has no {}", plan.boot, plan.boot.config_key()),
+    };
+    let entry = match archive.map(|x| x.find(name)) {
+        Some(Ok(Some(entry))) => entry,
+        _ => panic!("kernel {} of slot {:?} isn't in the archive", name, plan.boot),
+    };
+    brint!(out, "Booting slot {:?}: {}, size={}\n", plan.boot, name, entry.data.len());
+    return Some(entry.data);
+}
+
+/// `sovos.cfg` from the archive, empty if there was none
+fn config(bootinfo: &Bootinfo) -> Config<'static> {
+    let module = bootinfo.modules.iter().find(|m| m.name() == b"sovos.cfg");
+    match module {
+        /* Modules are still where the firmware loaded them, identity mapped */
+        Some(m) => unsafe {
+            let text = core::slice::from_raw_parts(m.data.addr().as_u64() as *const u8, m.data.len());
+            Config::new(text)
+        },
+        None => Config::empty(),
+    }
+}
+
+fn prepare_kernel_elf<'a>(out: &mut SerialSinks, kernel: &'a [u8]) -> KernelImage<'a> {
     brint!(out, "kernel: {:p}, size={}\n", kernel, core::mem::size_of_val(kernel));
     //brint!(out, "bootinfo: {:p}, size={}\n", bootptr, core::mem::size_of::<Bootinfo>());
 
-    let kernelelf: Elf<elf::Amd64> = Elf::from_bytes(&KERNEL.0).unwrap();
-    let pheaders = kernelelf.program_headers().unwrap();
+    /* First, KernelImage::parse would only say it isn't an Amd64 image */
+    if let Ok(header) = elf::Header::parse(kernel) {
+        if let Err(e) = header.validate_for_target(elf::Machine::X64, elf::Class::Bits64) {
+            panic!("kernel can't run on this CPU: {}", e);
+        }
+    }
+    let image = match KernelImage::parse(kernel) {
+        Ok(x) => x,
+        Err(KernelImageError::Elf(e)) => panic!("kernel isn't a 64-bit x86 executable: {}", e),
+        Err(e) => panic!("kernel isn't text, rodata and data/bss at KERNEL_BASE: {:?}", e),
+    };
+    let kernelelf = image.elf();
+
+    let header = kernelelf.header();
+    if let (Some(machine), Some(os_abi)) = (header.machine(), header.e_ident.os_abi()) {
+        brint!(out, "\n{} {}\n", machine, os_abi);
+    }
+    match header.segment_counts(kernel) {
+        Ok(counts) => brint!(out, "{:?}\n", counts),
+        Err(e) => panic!("kernel program headers are broken: {:?}", e),
+    }
+    brint!(out, "Remaining headers: {:#?}\n", image.other_headers());
+    return image;
+}
+
+/// Checks that every kernel section is in its PT_LOAD segment, to catch
+/// linker script mistakes before they fault. `verbose` prints it as
+/// a readelf-like table.
+fn check_section_map(out: &mut SerialSinks, bootinfo: &mut Bootinfo, kernelelf: &Elf<elf::Amd64>, verbose: bool) {
+    let header = kernelelf.header();
+    let map = match header.section_segment_map(kernelelf.data) {
+        Ok(x) => x,
+        Err(e) => {
+            brint!(out, "Can't map kernel sections to segments: {:?}\n", e);
+            return;
+        }
+    };
+
+    if verbose {
+        brint!(out, "{}", elf::SECTION_MAP_HEADER);
+    }
+    let mut conflicts = 0;
+    for placement in map {
+        if verbose {
+            let name = header.section_name(kernelelf.data, &placement.section).unwrap_or(None);
+            let _ = placement.write_row(out, name);
+        }
+        if placement.conflict.is_some() {
+            conflicts += 1;
+        }
+    }
+    if conflicts != 0 {
+        warn_or_fail!(out, bootinfo, Condition::SectionConflict,
+            "{} kernel sections conflict with their segments\n", conflicts);
+    }
+}
+
+/// Agrees with the kernel on what it gets, from its `SOVOS` ELF note.
+/// Panics with the reason if the kernel needs something this loader can't do.
+fn negotiate_abi(out: &mut SerialSinks, kernelelf: &Elf<elf::Amd64>, kernel: &[u8]) -> AbiContract {
+    let note = match kernelelf.header().find_note(kernel, ABI_NOTE_NAME, ABI_NOTE_TYPE) {
+        Ok(Some(note)) => Some(AbiNote::from_desc(note.desc)),
+        Ok(None) => None,
+        Err(e) => panic!("kernel note segments are broken: {:?}", e),
+    };
+    let note = note.transpose().unwrap_or_else(|e| panic!("bad SOVOS note: {:?}", e));
+
+    match bootinfo::negotiate(note.as_ref(), KernelFeatures::supported(), <Bootinfo>::BUF_SIZE) {
+        Ok(contract) => {
+            match note {
+                Some(note) => brint!(out, "Kernel ABI: Bootinfo v{} with {} byte buf, {:?}\n", note.bootinfo_version, note.buf_size, contract.features),
+                None => brint!(out, "Kernel has no SOVOS note, using the legacy contract\n"),
+            }
+            if contract.base_ignored {
+                brint!(out, "WARNING: kernel prefers base {:#x}, mapping it at {:#x}\n",
+                    note.map_or(0, |n| n.kernel_base), contract.kernel_base);
+            }
+            return contract;
+        }
+        Err(e) => {
+            brint!(out, "Kernel can't be booted by this loader: {:?}\n", e);
+            panic!("kernel ABI negotiation failed: {:?}", e);
+        }
+    }
+}
+
+/// The kernel's requirements note, checked by `Bootinfo::check_requirements`
+/// once everything is laid out. Kernels without one get the defaults.
+fn kernel_requirements(out: &mut SerialSinks, kernelelf: &Elf<elf::Amd64>, kernel: &[u8]) -> KernelRequirements {
+    let note = match kernelelf.header().find_note(kernel, ABI_NOTE_NAME, REQUIREMENTS_NOTE_TYPE) {
+        Ok(Some(note)) => note,
+        Ok(None) => return KernelRequirements::DEFAULT,
+        Err(e) => panic!("kernel note segments are broken: {:?}", e),
+    };
+    let requirements = KernelRequirements::from_desc(note.desc)
+        .unwrap_or_else(|e| panic!("bad SOVOS requirements note: {:?}", e));
+    brint!(out, "Kernel requirements: {:?}\n", requirements);
+    return requirements;
+}
+
+/// Per PT_LOAD segment numbers collected with the `load-stats` feature
+#[cfg(feature = "load-stats")]
+#[derive(Clone, Copy, Default)]
+struct SegmentStats {
+    vaddr: u64,
+    copied: u64,
+    copy: PerfSnapshot,
+    zeroed: u64,
+    zero: PerfSnapshot,
+}
+
+#[cfg(feature = "load-stats")]
+struct Counter(Option<u64>);
+
+#[cfg(feature = "load-stats")]
+impl core::fmt::Display for Counter {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.0 {
+            Some(x) => write!(f, "{:>12}", x),
+            None => write!(f, "{:>12}", "-"),
+        }
+    }
+}
+
+#[cfg(feature = "load-stats")]
+fn print_load_stats(out: &mut SerialSinks, stats: &[SegmentStats]) {
+    brint!(out, "{:<21} {:>10} {:>12} {:>12} {:>12} {:>10} {:>12} {:>12} {:>12}\n",
+        "vaddr", "copied", "cycles", "instructions", "llc misses",
+        "zeroed", "cycles", "instructions", "llc misses");
+    for s in stats {
+        brint!(out, "{} {:>10} {:>12} {} {} {:>10} {:>12} {} {}\n",
+            Addr(s.vaddr),
+            s.copied, s.copy.tsc, Counter(s.copy.instructions), Counter(s.copy.llc_misses),
+            s.zeroed, s.zero.tsc, Counter(s.zero.instructions), Counter(s.zero.llc_misses));
+    }
+}
+
+/// Copies text, rodata and data/bss segments one after another into
+/// freshly allocated 2M pages and maps them at KERNEL_BASE
+fn load_kernel(
+    out: &mut SerialSinks,
+    boot_services: &uefi::BootServices,
+    clock: uefi::TscClock,
+    pinned: &mut PinnedBootinfo,
+    kernel: &KernelImage,
+) -> PhysSlice<u8> {
+    use cpu::paging::MEGAPAGE_SIZE;
+
+    let image = BootServicesFrames::new(boot_services)
+        .alloc_frames(kernel.size(), MEGAPAGE_SIZE)
+        .expect("can't allocate memory for the kernel");
+    trace_alloc(image.start(), image.len());
+    let kernel_pslice: PhysSlice<u8> = image.into();
+    let bootinfo = unsafe { pinned.get_mut() };
+    bootinfo.mark("kernel load start");
+
+    let allowed = [image];
+    let mut log = phys_log(out);
+    /* SAFETY: freshly allocated and identity mapped */
+    let mut writer = unsafe { PhysWriter::new(&allowed, IdentityMapping, &mut log) };
+
+    #[cfg(feature = "load-stats")]
+    let perf = unsafe { PerfCounters::detect() };
+    #[cfg(feature = "load-stats")]
+    let mut stats = [SegmentStats::default(); 3];
+
+    /* Keeps firmware timers, like USB keyboard polling, alive during big copies */
+    let mut yielder = uefi::FirmwareYield::new(boot_services, clock);
+
+    let loads = kernel.loads(image.start()).unwrap();
+    #[cfg_attr(not(feature = "load-stats"), allow(unused_variables))]
+    for (i, load) in loads.iter().enumerate() {
+        #[cfg(feature = "load-stats")]
+        let before = perf.snapshot();
+        unsafe { load.copy(&mut writer) };
+        #[cfg(feature = "load-stats")]
+        let copied = perf.snapshot();
+        unsafe { load.zero(&mut writer) };
+        #[cfg(feature = "load-stats")]
+        {
+            let zeroed = perf.snapshot();
+            stats[i] = SegmentStats {
+                vaddr: load.vaddr,
+                copied: load.data.len() as u64,
+                copy: copied.delta(&before),
+                zeroed: load.bss.len(),
+                zero: zeroed.delta(&copied),
+            };
+            let s = &stats[i];
+            let names = [["text copy", "text zero"], ["rodata copy", "rodata zero"], ["data copy", "data zero"]];
+            let events = [
+                TimelineEvent::counted(names[i][0], s.copied, s.copy.tsc, copied.tsc),
+                TimelineEvent::counted(names[i][1], s.zeroed, s.zero.tsc, zeroed.tsc),
+            ];
+            for &event in &events {
+                let _ = bootinfo.timeline.try_push(event);
+            }
+        }
+
+        yielder.maybe_yield();
+    }
+
+    bootinfo.mark("kernel load end");
+    bootinfo.mark_phys_counters();
+
+    #[cfg(feature = "load-stats")]
+    print_load_stats(out, &stats);
+
+    let policy = KernelPermPolicy::new();
+    /* Without NX W^X can't hold, but the kernel still boots */
+    let effective = policy.effective(&pinned.page_flags);
+    let segments = [
+        ("text", policy.text, effective.text),
+        ("rodata", policy.rodata, effective.rodata),
+        ("data", policy.data, effective.data),
+    ];
+    for &(name, asked, got) in &segments {
+        if got != asked {
+            warn_or_fail!(out, unsafe { pinned.get_mut() }, Condition::WxAudit,
+                "W^X: kernel {} is {:?} instead of {:?} without NX\n", name, got, asked);
+        }
+    }
+    /* Misaligned segments would only be split by map_kernel, say so upfront */
+    let kernelelf = kernel.elf();
+    let granularity = match kernelelf.header().all_segments_page_aligned(kernelelf.data, MEGAPAGE_SIZE) {
+        Ok(()) => MapGranularity::Megapage,
+        Err(e) => {
+            brint!(out, "Kernel segments not megapage aligned: {:?}, falling back to 4K pages\n", e);
+            MapGranularity::Page
+        }
+    };
+    let [text, rodata, data] = loads;
+    let mapped = unsafe {
+        pinned.as_mut().map_kernel(text.megapages(), rodata.megapages(), data.megapages(), &policy, granularity)
+    };
+    /* Jumping into a kernel that isn't mapped would only fault later */
+    let regions = match mapped {
+        Ok(regions) => regions,
+        Err(e) => {
+            trace(TraceEvent::Error, [line!() as u64, 0]);
+            /* The 4K fallback is the last resort, there is nothing smaller */
+            panic!("can't map the kernel with {:?} granularity: {:?}", granularity, e);
+        }
+    };
+    for region in &regions {
+        brint!(out, "{:?} -> {:?} with {:?}\n", region.virt, region.phys, region.granularity);
+        trace(TraceEvent::Map, [region.virt.start(), region.phys.start()]);
+    }
+
+    return kernel_pslice;
+}
 
-    brint!(out, "\n{:?} {:?}\n", kernelelf.header().machine(), kernelelf.header().e_ident.os_abi());
-    assert_eq!(pheaders[0].p_vaddr, KERNEL_VIRT_ADDR);
+/// Copies the trampoline for kernels that tear down our page tables into
+/// the kernel's `.handoff` section, or the fixed handoff page if it has none.
+/// `None` if the page isn't mapped the same way in both page tables.
+fn prepare_handoff(
+    out: &mut SerialSinks,
+    bootinfo: &Bootinfo,
+    kernelelf: &Elf<elf::Amd64>,
+    kernel: &[u8],
+) -> Option<Trampoline> {
+    use cpu::paging::{PML4Entry, Table};
 
-    let (text, pheaders) = pheaders.split_first().unwrap();
-    assert!(text.is_executable());
-    assert!(!text.is_writable());
-    assert_eq!(text.p_align, 1 << 21);
-    assert_eq!(text.p_vaddr, KERNEL_VIRT_ADDR);
+    let virt = match kernelelf.header().section_by_name(kernel, HANDOFF_SECTION) {
+        Ok(Some(section)) => section.sh_addr,
+        _ => HANDOFF_FIXED_VIRT,
+    };
 
-    let (rodata, pheaders) = pheaders.split_first().unwrap();
-    assert!(!rodata.is_executable());
-    assert!(!rodata.is_writable());
-    assert_eq!(rodata.p_align, 1 << 21);
+    /* Firmware tables are identity mapped */
+    let old_root = cpu::Cr3::get().addr().as_u64() as usize as *const Table<PML4Entry>;
+    /* The kernel's `.handoff` section, or the fixed page firmware maps 1:1 */
+    let kernel = &bootinfo.kernel_pslice;
+    let allowed = [
+        PhysRange::new(kernel.addr().as_u64(), kernel.len() as u64).unwrap(),
+        PhysRange::new(HANDOFF_FIXED_VIRT & !0xFFF, 0x1000).unwrap(),
+    ];
+    let mut log = phys_log(out);
+    let mut writer = unsafe { PhysWriter::new(&allowed, IdentityMapping, &mut log) };
+    match unsafe { Trampoline::install(&*old_root, &bootinfo.paging_root, virt, &mut writer) } {
+        Ok(trampoline) => {
+            brint!(out, "Handoff trampoline at {:#x}\n", virt);
+            Some(trampoline)
+        }
+        Err(e) => {
+            brint!(out, "WARNING: can't use handoff trampoline at {:#x}: {:?}, falling back to the normal shim\n", virt, e);
+            trace(TraceEvent::Error, [line!() as u64, 0]);
+            None
+        }
+    }
+}
 
-    let (data_bss, pheaders) = pheaders.split_first().unwrap();
-    assert!(!data_bss.is_executable());
-    assert!(data_bss.is_writable());
-    assert_eq!(data_bss.p_align, 1 << 21);
+/// Unmaps the null guard from the kernel's tables and the live ones, using
+/// `spare` to split a large page, and poisons free memory below 1MiB,
+/// keeping a low handoff trampoline intact.
+/// `null_guard=<bytes>` widens the guard, `lowmem_poison=<u16>|off`.
+fn low_memory(
+    out: &mut SerialSinks,
+    pinned: &mut PinnedBootinfo,
+    config: &Config,
+    handoff: Option<&Trampoline>,
+    spare: Option<PhysRange>,
+) {
+    let mut whitelist = LowMemWhitelist::new();
+    if let Some(virt) = handoff.map(Trampoline::virt).filter(|&virt| virt < LOW_MEMORY_END) {
+        whitelist.keep(virt).unwrap();
+    }
 
-    brint!(out, "Remaining headers: {:#?}\n", pheaders);
+    let guard = config.get("null_guard").and_then(parse_u64).unwrap_or(NULL_GUARD_SIZE);
+    match unsafe { pinned.as_mut().unmap_null_guard(guard, &whitelist) } {
+        Ok(pages) => brint!(out, "Null guard {}: {} pages unmapped\n", Size(guard), pages),
+        Err(e) => brint!(out, "WARNING: can't unmap null guard {}: {:?}\n", Size(guard), e),
+    }
+    /* We are still on firmware's tables, the kernel is entered from them */
+    let live = match spare {
+        Some(spare) => unsafe {
+            let root = cpu::Cr3::get().addr().cast();
+            /* Firmware may keep its tables read-only */
+            cpu::Cr0::set(cpu::Cr0::get().clear_write_protect());
+            let live = bootinfo::unmap_live_null_guard(root, guard, &whitelist, spare, &IdentityMapping);
+            cpu::Cr0::set(cpu::Cr0::get().set_write_protect());
+            cpu::Cr3::set(cpu::Cr3::get());
+            live
+        },
+        None => Err(bootinfo::LowMemError::NoSpareTables),
+    };
+    match live {
+        Ok(pages) => brint!(out, "Null guard {} in live tables: {} pages unmapped\n", Size(guard), pages),
+        Err(e) => brint!(out, "WARNING: can't unmap null guard {} in live tables: {:?}\n", Size(guard), e),
+    }
+
+    let pattern = match config.get("lowmem_poison") {
+        Some("off") => return,
+        Some(x) => match parse_u64(x).and_then(|x| x.try_into().ok()) {
+            Some(x) => x,
+            None => {
+                brint!(out, "WARNING: bad lowmem_poison {:?}, using {:#x}\n", x, DEFAULT_POISON);
+                DEFAULT_POISON
+            }
+        },
+        None => DEFAULT_POISON,
+    };
+    let allowed = match PhysRange::from_start_end(guard, LOW_MEMORY_END) {
+        Some(x) => [x],
+        None => return,
+    };
+    let mut log = phys_log(out);
+    /* SAFETY: boot services are gone, so conventional memory is free and still identity mapped */
+    let poisoned = unsafe {
+        let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+        bootinfo::poison_low_memory(&pinned.uefi_meminfo, guard, &whitelist, pattern, &mut writer)
+    };
+    brint!(out, "Low memory poisoned with {:#x}: {}\n", pattern, Size(poisoned));
 }
 
+/// Moves the initrd below `dma_ceiling` if it isn't already, and tags it.
+/// The archive it's in was loaded before the config was known.
+fn place_dma_modules(out: &mut SerialSinks, boot_services: &uefi::BootServices, bootinfo: &mut Bootinfo) {
+    for i in 0..bootinfo.modules.len() {
+        let module = bootinfo.modules[i];
+        if module.kind != bootinfo::ModuleKind::Initrd || module.data.len() == 0 {
+            continue;
+        }
+        let mut range = PhysRange::new(module.data.addr().as_u64(), module.data.len() as u64).unwrap();
+
+        let ceiling = bootinfo.dma_ceiling().unwrap_or(u64::MAX);
+        let moved = if range.end() > ceiling {
+            BootServicesFrames::new(boot_services).alloc_frames_below(range.len(), 0x1000, ceiling)
+        } else {
+            None
+        };
+        if let Some(copy) = moved {
+            trace_alloc(copy.start(), copy.len());
+            let allowed = [copy];
+            let mut log = phys_log(out);
+            /* SAFETY: freshly allocated, the module is identity mapped */
+            unsafe {
+                let data = core::slice::from_raw_parts(range.start() as *const u8, range.len() as usize);
+                let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+                writer.copy_from_slice("initrd below dma_ceiling", copy.start(), data);
+            }
+            brint!(out, "Moved {} from {} to {} for dma_ceiling\n",
+                core::str::from_utf8(module.name()).unwrap_or("initrd"), Addr(range.start()), Addr(copy.start()));
+            range = PhysRange::new(copy.start(), range.len()).unwrap();
+            bootinfo.modules[i].data = PhysSlice::new(PhysAddr::new(range.start()).unwrap(), module.data.len());
+        }
+
+        if !bootinfo.tag_dma(range, ReservedKind::Module) {
+            warn_or_fail!(out, bootinfo, Condition::DmaCeiling,
+                "initrd at {:?} is above dma_ceiling, low memory is full\n", range);
+        }
+    }
+}
+
+/// Reserves the trace buffer at its fixed address, summarizes what an
+/// earlier boot left in it and starts this boot's trace over it. There is
+/// no file system access to keep the old trace on the ESP, only the summary.
+fn start_trace(out: &mut SerialSinks, boot_services: &uefi::BootServices, bootinfo: &mut Bootinfo, config: &Config) {
+    let settings = match TraceConfig::from_config(config) {
+        Ok(Some(x)) => x,
+        Ok(None) => return,
+        Err(e) => {
+            brint!(out, "WARNING: bad trace config: {:?}, not tracing\n", e);
+            return;
+        }
+    };
+
+    /* Reserved, so the kernel doesn't reuse it either */
+    let reserved = boot_services.allocate_pages(uefi::AllocateType::Address, uefi::memory::Type::Reserved, settings.pages(), settings.addr);
+    if let Err(e) = reserved {
+        brint!(out, "WARNING: can't reserve the trace buffer at {}: {:?}\n", Addr(settings.addr), e);
+        return;
+    }
+    let range = PhysRange::new(settings.addr, settings.size).unwrap();
+    bootinfo.reserve_region(range, ReservedKind::Trace);
+    /* Its address is configured, it can only be tagged */
+    if !bootinfo.tag_dma(range, ReservedKind::Trace) {
+        warn_or_fail!(out, bootinfo, Condition::DmaCeiling,
+            "trace buffer at {:?} is above dma_ceiling\n", range);
+    }
+
+    /* SAFETY: reserved above and identity mapped */
+    let previous = unsafe { core::slice::from_raw_parts(settings.addr as *const u8, settings.size as usize) };
+    let boot = match PreviousTrace::find(previous) {
+        Ok(previous) => {
+            let summary = previous.summary();
+            brint!(out, "Previous trace: boot {}, {} records, {} dropped, last stage {:?}, {} errors{}\n",
+                summary.boot, summary.records, summary.dropped, summary.last_stage, summary.errors,
+                if summary.panicked { ", panicked" } else { "" });
+            if let Some(last) = summary.last {
+                brint!(out, "Previous trace: last record {:?} {:#x} {:#x} at {}\n",
+                    last.event(), last.args[0], last.args[1], last.tsc);
+            }
+            summary.boot.wrapping_add(1)
+        }
+        Err(e) => {
+            brint!(out, "No previous trace at {}: {:?}\n", Addr(settings.addr), e);
+            0
+        }
+    };
+
+    let allowed = [PhysRange::new(settings.addr, settings.size).unwrap()];
+    let mut log = phys_log(out);
+    /* SAFETY: as above, nothing else uses the buffer */
+    let buf = unsafe {
+        let mut writer = PhysWriter::new(&allowed, IdentityMapping, &mut log);
+        writer.write_bytes("trace buffer", settings.addr, 0, settings.size);
+        core::slice::from_raw_parts_mut(settings.addr as *mut u8, settings.size as usize)
+    };
+    match TraceWriter::new(buf, boot) {
+        Ok(writer) => {
+            brint!(out, "Tracing boot {} to {:?}\n", boot, allowed[0]);
+            unsafe { TRACE = Some(writer.with_alloc_threshold(settings.alloc_threshold)) };
+            trace(TraceEvent::Start, [boot as u64, 0]);
+        }
+        Err(e) => brint!(out, "WARNING: can't start the trace: {:?}\n", e),
+    }
+}
+
+/// Reserves the region from `crashdump=` before anything else could be
+/// allocated there, `open_crash_dump` looks at it once the boot ID is known
+fn reserve_crash_dump(out: &mut SerialSinks, boot_services: &uefi::BootServices, bootinfo: &mut Bootinfo, config: &Config) {
+    let settings = match CrashDumpConfig::from_config(config) {
+        Ok(Some(x)) => x,
+        /* `xtask crashdump` boots without a config */
+        Ok(None) => match option_env!("SOVOS_CRASHDUMP_TEST").and_then(parse_u64) {
+            Some(addr) => CrashDumpConfig { addr, size: DEFAULT_CRASHDUMP_SIZE },
+            None => return,
+        },
+        Err(e) => {
+            brint!(out, "WARNING: bad crash dump config: {:?}, no crash dumps\n", e);
+            return;
+        }
+    };
+
+    /* Reserved, so the kernel doesn't reuse it either, and never zeroed */
+    let reserved = boot_services.allocate_pages(uefi::AllocateType::Address, uefi::memory::Type::Reserved, settings.pages(), settings.addr);
+    if let Err(e) = reserved {
+        brint!(out, "WARNING: can't reserve the crash dump region at {}: {:?}\n", Addr(settings.addr), e);
+        return;
+    }
+    let range = PhysRange::new(settings.addr, settings.size).unwrap();
+    bootinfo.reserve_region(range, ReservedKind::CrashDump);
+    bootinfo.crash_dump.range = range;
+    bootinfo.record(BootCapabilities::set_crash_dump);
+}
+
+/// Keeps a dump an earlier boot left in the crash dump region, or gives
+/// the region a fresh header. With `boot_delay=` a dump can be saved to
+/// the boot volume or discarded from the console, otherwise it's only
+/// summarized and kept for the kernel.
+fn open_crash_dump(
+    out: &mut SerialSinks,
+    st: &uefi::SystemTable,
+    boot_services: &uefi::BootServices,
+    clock: &uefi::TscClock,
+    handle: &uefi::ImageHandle,
+    bootinfo: &mut Bootinfo,
+    config: &Config,
+) {
+    let range = bootinfo.crash_dump.range;
+    if range.is_empty() {
+        return;
+    }
+    let boot_id = bootinfo.lineage.boot_id;
+    /* SAFETY: reserved by `reserve_crash_dump` and identity mapped */
+    let buf = unsafe { core::slice::from_raw_parts_mut(range.start() as *mut u8, range.len() as usize) };
+    let mut region = match CrashDumpRegion::open(range, buf, boot_id) {
+        Ok(x) => x,
+        Err(e) => {
+            brint!(out, "WARNING: can't use the crash dump region {:?}: {:?}\n", range, e);
+            return;
+        }
+    };
+
+    if region.state == CrashDumpState::Fresh {
+        brint!(out, "Crash dump region {:?}, no dump\n", range);
+        bootinfo.crash_dump = region;
+        crash_dump_test(out, st, &region);
+        return;
+    }
+
+    brint!(out, "Crash dump from boot {:02x?}: {} at {:?}\n",
+        region.header.boot_id, Size(region.header.length), range);
+    let clear = match crash_dump_choice(out, st, boot_services, clock, config) {
+        Some(b's') => match save_crash_dump(boot_services, handle, &region, buf) {
+            Ok(()) => {
+                brint!(out, "Crash dump saved\n");
+                true
+            }
+            Err(e) => {
+                brint!(out, "WARNING: can't save the crash dump: {:?}, keeping it\n", e);
+                false
+            }
+        },
+        Some(b'd') => true,
+        _ => false,
+    };
+    if clear {
+        /* Only fails for a region `open` refused */
+        let _ = region.clear(buf, boot_id);
+        brint!(out, "Crash dump cleared\n");
+    }
+    bootinfo.crash_dump = region;
+}
+
+/// Key pressed at the crash dump prompt, `None` without `boot_delay=` or
+/// if it passed without one
+fn crash_dump_choice(out: &mut SerialSinks, st: &uefi::SystemTable, boot_services: &uefi::BootServices, clock: &uefi::TscClock, config: &Config) -> Option<u8> {
+    use uefi::{Clock, MenuInput};
+
+    let delay_ms = match config.get("boot_delay").and_then(parse_u64) {
+        Some(x) if x > 0 => x,
+        _ => return None,
+    };
+    brint!(out, "Press s to save the crash dump to the boot volume, d to discard it, any other key to keep it ({} ms)\n", delay_ms);
+    let deadline = clock.now_us().saturating_add(delay_ms.saturating_mul(1000));
+    match uefi::wait_key_or_timeout(boot_services, st.con_in(), Some(&mut *out), clock, deadline) {
+        Ok(MenuInput::Key(key)) => key.unicode_char.try_into().ok(),
+        Ok(MenuInput::Serial(byte)) => Some(byte),
+        Ok(MenuInput::Timeout) => None,
+        Err(e) => {
+            brint!(out, "WARNING: can't wait for input: {:?}\n", e);
+            None
+        }
+    }
+}
+
+/// Writes the dump in `buf`, the region, header included, to
+/// `\crashdump-<boot ID>.bin` on the volume the loader was started from
+fn save_crash_dump(boot_services: &uefi::BootServices, handle: &uefi::ImageHandle, region: &CrashDumpRegion, buf: &[u8]) -> Result<(), uefi::Error> {
+    let dump = region.dump(buf).ok_or(uefi::Error::NotFound)?;
+
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let mut path = [0u16; 48];
+    let name = b"\\crashdump-";
+    for (i, &c) in name.iter().enumerate() {
+        path[i] = c as u16;
+    }
+    for (i, &byte) in region.header.boot_id.iter().enumerate() {
+        path[name.len() + 2 * i] = HEX[byte as usize >> 4] as u16;
+        path[name.len() + 2 * i + 1] = HEX[byte as usize & 15] as u16;
+    }
+    for (i, &c) in b".bin".iter().enumerate() {
+        path[name.len() + 32 + i] = c as u16;
+    }
+    /* The last one stays NUL */
+
+    let image = boot_services.loaded_image(handle)?;
+    let fs: &uefi::SimpleFileSystemProtocol = unsafe {
+        boot_services.handle_protocol(&image.device_handle, &uefi::Guid::EFI_SIMPLE_FILE_SYSTEM_PROTOCOL)?
+    };
+    let root = fs.open_volume()?;
+    let result = root.create(&path).and_then(|file| {
+        let result = file.write_all(dump).and_then(|()| file.flush());
+        file.close();
+        result
+    });
+    root.close();
+    return result;
+}
+
+/// A loader built with `SOVOS_CRASHDUMP_TEST` does what a crashing kernel
+/// would: writes a dump to the fresh region, then resets without clearing
+/// memory, so the next boot finds it. For `xtask crashdump`.
+fn crash_dump_test(out: &mut SerialSinks, st: &uefi::SystemTable, region: &CrashDumpRegion) {
+    if option_env!("SOVOS_CRASHDUMP_TEST").is_none() {
+        return;
+    }
+
+    let message = b"sovos crash dump test";
+    let header = region.header.with_dump(message.len() as u64, region.header.boot_id);
+    /* SAFETY: the region is reserved and at least a page, dump first, like a kernel would */
+    unsafe {
+        let dump = (region.range.start() as usize + CRASHDUMP_HEADER_SIZE) as *mut u8;
+        dump.copy_from_nonoverlapping(message.as_ptr(), message.len());
+        *(region.range.start() as *mut CrashDumpHeader) = header;
+    }
+    brint!(out, "Crash dump test: wrote a dump as boot {:02x?}, resetting\n", header.boot_id);
+    let runtime_services = unsafe { &*st.runtime_services };
+    runtime_services.reset_system(uefi::ResetType::Warm, uefi::RawStatus(0), &[]);
+}
+
+/// Appends to the trace, if there is one
+fn trace(event: TraceEvent, args: [u64; 2]) {
+    if let Some(trace) = unsafe { TRACE.as_mut() } {
+        trace.push(event, args);
+    }
+}
+
+/// Traces an allocation of at least `trace_alloc_threshold` bytes
+fn trace_alloc(addr: u64, bytes: u64) {
+    if let Some(trace) = unsafe { TRACE.as_mut() } {
+        trace.alloc(addr, bytes);
+    }
+}
+
+/// Logs `PhysWriter` writes to a copy of `out`, so the writer doesn't
+/// keep `out` borrowed
+fn phys_log(out: &SerialSinks) -> impl FnMut(&PhysWrite) {
+    let mut out = *out;
+    move |w| brint!(out, "phys write: {} {:?}\n", w.purpose, w.range)
+}
+
+/// Reads 8 bytes at a time from `read`, which may transiently fail
+fn fill_retrying(buf: &mut [u8], mut read: impl FnMut() -> Option<u64>) -> bool {
+    const RETRIES: usize = 64;
+
+    for chunk in buf.chunks_mut(8) {
+        let value = match (0..RETRIES).find_map(|_| read()) {
+            Some(x) => x,
+            None => return false,
+        };
+        chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+    }
+
+    return true;
+}
+
+/// Low byte of TSC deltas of a short busy loop, varies with cache,
+/// pipeline and interrupt timing. Weak on its own, but always present.
+fn tsc_jitter(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        let mut acc = 0u8;
+        for _ in 0..8 {
+            let start = cpu::rdtsc();
+            for _ in 0..16 {
+                cpu::nop();
+            }
+            acc = acc.rotate_left(1) ^ cpu::rdtsc().wrapping_sub(start) as u8;
+        }
+        *byte = acc;
+    }
+}
+
+/// Gathers entropy from every available source into `bootinfo.seed`.
+/// Sources failing health checks contribute nothing.
+fn boot_entropy(out: &mut SerialSinks, boot_services: &uefi::BootServices, bootinfo: &mut Bootinfo) {
+    let mut pool = EntropyPool::new();
+    let mut sample = [0u8; 32];
+
+    let mut add = |out: &mut SerialSinks, source: EntropySource, sample: &[u8]| {
+        if let Err(e) = pool.add(source, sample) {
+            brint!(out, "WARNING: entropy source {:?} failed health check: {:?}\n", source, e);
+        }
+    };
+
+    if let Ok(rng) = boot_services.rng() {
+        match rng.get_rng(&mut sample) {
+            Ok(()) => add(out, EntropySource::EfiRng, &sample),
+            Err(e) => brint!(out, "WARNING: EFI_RNG_PROTOCOL failed: {:?}\n", e),
+        }
+    }
+    if cpu::has_rdseed() {
+        if fill_retrying(&mut sample, || unsafe { cpu::rdseed() }) {
+            add(out, EntropySource::Rdseed, &sample);
+        } else {
+            brint!(out, "WARNING: rdseed kept failing\n");
+        }
+    }
+    if cpu::has_rdrand() {
+        if fill_retrying(&mut sample, || unsafe { cpu::rdrand() }) {
+            add(out, EntropySource::Rdrand, &sample);
+        } else {
+            brint!(out, "WARNING: rdrand kept failing\n");
+        }
+    }
+    tsc_jitter(&mut sample);
+    add(out, EntropySource::TscJitter, &sample);
+
+    let (seed, status) = pool.finish();
+    bootinfo.seed = seed;
+    bootinfo.entropy = status;
+    bootinfo.lineage = BootLineage::from_seed(&seed);
+    if status.healthy_sources() != 0 {
+        bootinfo.record(BootCapabilities::set_entropy_seeded);
+    }
+    brint!(out, "Entropy: {:?}\n", status);
+    brint!(out, "Boot ID: {:02x?}\n", bootinfo.lineage.boot_id);
+}
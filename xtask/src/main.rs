@@ -4,6 +4,7 @@ use std::process::Command;
 use std::path::PathBuf;
 use std::fs;
 use std::os::unix::process::CommandExt;
+use elf::{Elf, Amd64, SECTION_MAP_HEADER};
 
 type Return = std::result::Result<(), Box<dyn Error>>;
 
@@ -18,10 +19,19 @@ fn print_help() -> Return {
     println!("build");
     println!("run");
     println!("clean [all, kernel, uefi_wrapper]");
+    println!("lint [kernel ELF, the release build by default]");
+    println!("compat-nonx");
+    println!("strict");
+    println!("crashdump");
     Ok(())
 }
 
-fn build(mut current_dir: PathBuf) -> Return {
+fn build(current_dir: PathBuf) -> Return {
+    return build_with_env(current_dir, &[]);
+}
+
+/// `build` with extra environment for the uefi_wrapper build
+fn build_with_env(mut current_dir: PathBuf, env: &[(&str, &str)]) -> Return {
 
     assert!(current_dir.is_absolute());
     current_dir.push("kernel");
@@ -48,6 +58,7 @@ fn build(mut current_dir: PathBuf) -> Return {
         .current_dir(&current_dir)
         .args(&["build", "--release"])
         .env("SOVOS_KERNEL_PATH", &kernel_path)
+        .envs(env.iter().copied())
         .status()?;
 
     brint!("Cargo finished with {}\n", status);
@@ -105,6 +116,143 @@ fn run(current_dir: PathBuf) -> Return {
     return Err(qemu.exec().into());
 }
 
+/// Boots headless without KVM on `cpu` until the loader prints its
+/// capabilities, which are returned. A panic, a reset or two minutes
+/// without getting there are errors.
+fn boot_headless(cpu: &str) -> Result<String, Box<dyn Error>> {
+    return boot_headless_until(cpu, false, |line| line.contains("Capabilities:"));
+}
+
+/// `boot_headless` until `done` says so about a line, which is returned.
+/// With `reboot` a reset boots again instead of being an error.
+fn boot_headless_until(
+    cpu: &str,
+    reboot: bool,
+    mut done: impl FnMut(&str) -> bool,
+) -> Result<String, Box<dyn Error>> {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    let qemu_args = [
+        "-drive", "if=pflash,format=raw,read-only,file=/usr/share/edk2/ovmf/OVMF_CODE.fd",
+        "-drive", "format=raw,file=fat:rw:fat/",
+        "-cpu", cpu,
+        "-m", "1G",
+        "-nographic",
+    ];
+    let mut qemu = Command::new("qemu-system-x86_64");
+    qemu.args(&qemu_args).stdin(Stdio::null()).stdout(Stdio::piped());
+    if !reboot {
+        qemu.arg("-no-reboot");
+    }
+    brint!("{:?}\n", qemu);
+    let mut child = qemu.spawn()?;
+
+    let (tx, rx) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(120);
+    let result = loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let line = match rx.recv_timeout(left) {
+            Ok(Ok(line)) => line,
+            Ok(Err(e)) => break Err(e.into()),
+            Err(mpsc::RecvTimeoutError::Timeout) => break Err("timed out waiting for the loader".into()),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break Err("QEMU exited before the loader was done".into()),
+        };
+        println!("{}", line);
+        if line.contains("PANIK") {
+            break Err("the loader panicked".into());
+        }
+        if done(&line) {
+            break Ok(line);
+        }
+    };
+
+    let _ = child.kill();
+    let _ = child.wait();
+    return result;
+}
+
+/// Boots headless on a processor without NX, the loader has to fall back
+/// to mapping without it and tell the kernel so
+fn compat_nonx(current_dir: PathBuf) -> Return {
+    build(current_dir.clone())?;
+    build_run_directory(current_dir)?;
+
+    /* No KVM, -cpu host would bring NX back */
+    let result = match boot_headless("qemu64,-nx") {
+        Ok(caps) if caps.contains("nx_unavailable") => Ok(()),
+        Ok(_) => Err("booted without NX, but nx_unavailable isn't set".into()),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => brint!("Booted without NX\n"),
+        Err(_) => brint!("compat-nonx failed\n"),
+    }
+    return result;
+}
+
+/// Boots a loader built with `SOVOS_STRICT=1`, where every tolerated
+/// condition fails the boot, on a processor that has everything it needs.
+/// This is what CI should run.
+fn strict(current_dir: PathBuf) -> Return {
+    build_with_env(current_dir.clone(), &[("SOVOS_STRICT", "1")])?;
+    build_run_directory(current_dir)?;
+
+    let result = match boot_headless("qemu64,+nx") {
+        Ok(caps) if caps.contains("strict") => Ok(()),
+        Ok(_) => Err("booted, but strict isn't set, was the loader rebuilt?".into()),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => brint!("Booted cleanly in strict mode\n"),
+        Err(_) => brint!("strict failed\n"),
+    }
+    return result;
+}
+
+/// Boots twice without clearing memory in between. A loader built with
+/// `SOVOS_CRASHDUMP_TEST` writes a dump to the crash dump region on the
+/// first boot and resets, the second has to find it, with the first
+/// boot's ID as the writer.
+fn crashdump(current_dir: PathBuf) -> Return {
+    /* Well below where OVMF puts its own allocations in 1G */
+    build_with_env(current_dir.clone(), &[("SOVOS_CRASHDUMP_TEST", "0x10000000")])?;
+    build_run_directory(current_dir)?;
+
+    let mut boot_ids = Vec::new();
+    let found = boot_headless_until("qemu64,+nx", true, |line| {
+        if let Some(id) = line.split("Boot ID: ").nth(1) {
+            boot_ids.push(id.trim().to_string());
+        }
+        line.contains("Crash dump from boot") || line.contains("Capabilities:")
+    });
+    let result = match found {
+        Ok(line) if !line.contains("Crash dump from boot") => {
+            Err("booted without finding a crash dump, did the first boot reset?".into())
+        }
+        /* The second boot's ID is printed before the dump is looked at */
+        Ok(line) if boot_ids.len() >= 2 && line.contains(&boot_ids[boot_ids.len() - 2]) => Ok(()),
+        Ok(line) => Err(format!("found a dump, but not the first boot's: {}", line).into()),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => brint!("The second boot found the first one's crash dump\n"),
+        Err(_) => brint!("crashdump failed\n"),
+    }
+    return result;
+}
+
 fn clean(mut current_dir: PathBuf, clean_target: &str) -> Return {
     if clean_target.len() == 0 {
         return print_help();
@@ -156,6 +304,41 @@ fn clean(mut current_dir: PathBuf, clean_target: &str) -> Return {
     Ok(())
 }
 
+/// Checks that every section of the kernel sits in a PT_LOAD segment with
+/// matching permissions, fails if one doesn't
+fn lint(mut current_dir: PathBuf, path: Option<&str>) -> Return {
+    let path = match path {
+        Some(x) => PathBuf::from(x),
+        None => {
+            current_dir.push("kernel/target/amd64-kernel-none/release/kernel");
+            current_dir
+        }
+    };
+
+    brint!("Checking section placement of {}\n", path.display());
+    let file = fs::read(&path)?;
+    let kernel: Elf<Amd64> = Elf::from_bytes(&file).map_err(|e| format!("not a kernel ELF: {:?}", e))?;
+    let header = kernel.header();
+    let map = header.section_segment_map(&file).map_err(|e| format!("bad section or program headers: {:?}", e))?;
+
+    let mut table = String::from(SECTION_MAP_HEADER);
+    let mut conflicts = 0;
+    for placement in map {
+        let name = header.section_name(&file, &placement.section).unwrap_or(None);
+        placement.write_row(&mut table, name)?;
+        if placement.conflict.is_some() {
+            conflicts += 1;
+        }
+    }
+    print!("{}", table);
+
+    if conflicts != 0 {
+        return Err(format!("{} sections conflict with their segments, check the linker script", conflicts).into());
+    }
+    brint!("No conflicts\n");
+    Ok(())
+}
+
 fn main() -> Return {
     let current_dir = env::current_dir().unwrap();
     print!("Current directory: {:?}\n", current_dir.display());
@@ -172,6 +355,10 @@ fn main() -> Return {
         "build" => build(current_dir),
         "run" => run(current_dir),
         "clean" => clean(current_dir, rest.get(0).map(|s| s.as_str()).unwrap_or("")),
+        "lint" => lint(current_dir, rest.first().map(|s| s.as_str())),
+        "compat-nonx" => compat_nonx(current_dir),
+        "strict" => strict(current_dir),
+        "crashdump" => crashdump(current_dir),
         _ => print_help(),
     };
 }